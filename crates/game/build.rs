@@ -8,10 +8,11 @@ use spirv_cross::{
 	hlsl, spirv,
 	spirv::{Decoration, Type},
 };
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,6 +23,22 @@ enum BuildError {
 	ShaderCompilation(PathBuf, HassleError),
 	#[error("An unknown filesystem error occurred: {0}")]
 	Filesystem(std::io::Error),
+	#[error("Unsupported type for member {2} of {1} in {0}: {3}")]
+	UnsupportedMemberType(PathBuf, String, String, String),
+	#[error("Unsupported descriptor {1} in {0}: {2}")]
+	UnsupportedDescriptor(PathBuf, String, String),
+	#[error("Shader include cycle: {0}")]
+	IncludeCycle(String),
+	#[error("Shaders {1} and {2} would both generate the module {0}")]
+	DuplicateShaderModule(String, PathBuf, PathBuf),
+	#[error("Invalid specialization constant in {0}: {1}")]
+	InvalidSpecializationConstant(PathBuf, String),
+	#[error("Invalid permutations in {0}: {1}")]
+	InvalidPermutations(PathBuf, String),
+	#[error("The {1} permutation of {0} doesn't match the one with every define: {2}")]
+	InconsistentPermutation(PathBuf, String, String),
+	#[error("Couldn't find the declaration of struct type {2} used by {1} in {0}")]
+	MissingStructDeclaration(PathBuf, String, u32),
 	#[error("Unknown error: {0}")]
 	Unknown(String),
 }
@@ -29,28 +46,139 @@ enum BuildError {
 const SHADERS_DIR: &'static str = "shaders/";
 const SHADER_EXT: &'static str = "hlsl";
 const SHADER_INC: &'static str = "hlsli";
+// Shared includes that aren't meant to be next to the shaders including them, also an include root by default.
+const SHADER_LIB_DIR: &'static str = "lib/";
+// Overrides the include roots, in the same format as PATH. Includes relative to the file including them always win.
+const SHADER_INCLUDE_DIRS_VAR: &'static str = "GOLDFISH_SHADER_INCLUDE_DIRS";
 
 const VS_MAIN: &'static str = "vs_main";
 const PS_MAIN: &'static str = "ps_main";
+const GS_MAIN: &'static str = "gs_main";
 const CS_MAIN: &'static str = "cs_main";
 
+// See PERMUTE_PRAGMA in the editor's shader_compiler.rs, both permute shaders the same way.
+const PERMUTE_PRAGMA: &'static str = "// #pragma goldfish permute";
+const MAX_PERMUTATIONS: usize = 32;
+
+fn normalize(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+				normalized.pop();
+			}
+			component => normalized.push(component),
+		}
+	}
+
+	normalized
+}
+
+// A copy of the editor's IncludeResolver in shader_compiler.rs, changes to one have to be made to the other. Resolved includes are kept for cargo's rerun-if-changed.
+struct IncludeResolver {
+	roots: Vec<PathBuf>,
+	dependencies: RefCell<HashMap<PathBuf, Vec<PathBuf>>>,
+}
+
+impl IncludeResolver {
+	fn new(roots: Vec<PathBuf>) -> Self {
+		Self {
+			roots,
+			dependencies: Default::default(),
+		}
+	}
+
+	fn from_env(shaders_dir: &Path) -> Self {
+		let roots = match env::var_os(SHADER_INCLUDE_DIRS_VAR) {
+			Some(dirs) => env::split_paths(&dirs).collect(),
+			None => vec![shaders_dir.to_path_buf(), shaders_dir.join(SHADER_LIB_DIR)],
+		};
+
+		Self::new(roots)
+	}
+
+	fn resolve(&self, dir: &Path, include: &str) -> Option<PathBuf> {
+		std::iter::once(dir)
+			.chain(self.roots.iter().map(PathBuf::as_path))
+			.map(|root| normalize(&root.join(include)))
+			.find(|path| path.is_file())
+	}
+
+	fn resolve_dxc(&self, dir: &Path, filename: &str) -> Option<PathBuf> {
+		let components = Path::new(filename).components().filter(|component| *component != Component::CurDir).collect::<Vec<_>>();
+		let suffixes = (0..components.len()).map(|skip| components[skip..].iter().collect::<PathBuf>()).collect::<Vec<_>>();
+
+		std::iter::once(dir.join(filename))
+			.chain(self.roots.iter().flat_map(|root| suffixes.iter().map(move |suffix| root.join(suffix))))
+			.map(|path| normalize(&path))
+			.find(|path| path.is_file())
+	}
+
+	fn find_includes_recursive(&self, path: &Path, stack: &mut Vec<PathBuf>, includes: &mut Vec<PathBuf>) -> Result<(), BuildError> {
+		let src = fs::read_to_string(path).map_err(move |err| BuildError::Filesystem(err))?;
+		let dir = path.parent().unwrap_or(Path::new("./"));
+
+		stack.push(path.to_path_buf());
+		for include in parse_includes(&src) {
+			let Some(include_path) = self.resolve(dir, include) else {
+				println!("cargo:warning=Failed to find included file {} from {}", include, path.to_str().unwrap());
+				continue;
+			};
+
+			if let Some(start) = stack.iter().position(|visiting| *visiting == include_path) {
+				let cycle = stack[start..].iter().chain(std::iter::once(&include_path)).map(|path| path.to_string_lossy()).collect::<Vec<_>>();
+				return Err(BuildError::IncludeCycle(cycle.join(" -> ")));
+			}
+
+			if includes.contains(&include_path) {
+				continue;
+			}
+
+			includes.push(include_path.clone());
+			self.find_includes_recursive(&include_path, stack, includes)?;
+		}
+		stack.pop();
+
+		Ok(())
+	}
+
+	fn find_includes(&self, path: &Path) -> Result<Vec<PathBuf>, BuildError> {
+		let path = normalize(path);
+		if let Some(includes) = self.dependencies.borrow().get(&path) {
+			return Ok(includes.clone());
+		}
+
+		let mut includes = Vec::new();
+		self.find_includes_recursive(&path, &mut Vec::new(), &mut includes)?;
+		self.dependencies.borrow_mut().insert(path, includes.clone());
+
+		Ok(includes)
+	}
+
+	fn dependencies(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+		self.dependencies.borrow().clone()
+	}
+}
+
 struct ShaderIncludeHandler<'a> {
 	path: &'a Path,
+	resolver: &'a IncludeResolver,
 }
 
 impl<'a> DxcIncludeHandler for ShaderIncludeHandler<'a> {
 	fn load_source(&mut self, filename: String) -> Option<String> {
-		let full_path = self.path.join(filename);
-
-		use std::io::Read;
-		match std::fs::File::open(&full_path) {
-			Ok(mut f) => {
-				let mut content = String::new();
-				f.read_to_string(&mut content).ok()?;
-				Some(content)
+		match self.resolver.resolve_dxc(self.path, &filename) {
+			Some(full_path) => {
+				println!("cargo:warning=Resolved include {} to {}", filename, full_path.to_str().unwrap());
+				fs::read_to_string(&full_path).ok()
 			}
-			Err(_) => {
-				println!("cargo:warning=Compiling Failed to find included file {}", full_path.to_str().unwrap());
+			None => {
+				println!(
+					"cargo:warning=Compiling Failed to find included file {} in {} or any of the include roots",
+					filename,
+					self.path.to_str().unwrap()
+				);
 				None
 			}
 		}
@@ -60,10 +188,55 @@ impl<'a> DxcIncludeHandler for ShaderIncludeHandler<'a> {
 struct CompiledShaders {
 	vs: Option<Vec<u32>>,
 	ps: Option<Vec<u32>>,
+	gs: Option<Vec<u32>>,
 	cs: Option<Vec<u32>>,
+	cs_local_size: Option<[u32; 3]>,
+}
+
+struct ReflectedStage {
+	ast: spirv::Ast<hlsl::Target>,
+	// From the module's header, every id in the module is below it.
+	id_bound: u32,
+	// Variable ids of every TextureCube, which SPIRV-Cross reports like any other image.
+	cube_images: HashSet<u32>,
 }
 
-fn compile_hlsl(path: &Path, src: &str, disable_optimizations: bool) -> Result<(Vec<spirv::Ast<hlsl::Target>>, CompiledShaders), BuildError> {
+// Walks the module for OpVariables pointing at an OpTypeImage with Dim Cube, arrays of cubes aren't supported.
+fn cube_image_variables(ir: &[u32]) -> HashSet<u32> {
+	const OP_TYPE_IMAGE: u32 = 25;
+	const OP_TYPE_POINTER: u32 = 32;
+	const OP_VARIABLE: u32 = 59;
+	const DIM_CUBE: u32 = 3;
+
+	let mut cube_types = HashSet::new();
+	let mut cube_pointers = HashSet::new();
+	let mut cube_images = HashSet::new();
+
+	let mut offset = 5;
+	while offset < ir.len() {
+		let word_count = (ir[offset] >> 16) as usize;
+		let operands = &ir[offset + 1..(offset + word_count).min(ir.len())];
+
+		match (ir[offset] & 0xffff, operands) {
+			(OP_TYPE_IMAGE, [result, _, dim, ..]) if *dim == DIM_CUBE => {
+				cube_types.insert(*result);
+			}
+			(OP_TYPE_POINTER, [result, _, ty]) if cube_types.contains(ty) => {
+				cube_pointers.insert(*result);
+			}
+			(OP_VARIABLE, [ty, result, ..]) if cube_pointers.contains(ty) => {
+				cube_images.insert(*result);
+			}
+			_ => {}
+		}
+
+		offset += word_count.max(1);
+	}
+
+	cube_images
+}
+
+fn compile_hlsl(path: &Path, src: &str, resolver: &IncludeResolver, disable_optimizations: bool, defines: &[&str]) -> Result<(Vec<ReflectedStage>, CompiledShaders), BuildError> {
 	let dxc = Dxc::new(None).map_err(move |err| BuildError::ShaderCompilation(path.to_path_buf(), err))?;
 
 	let compiler = dxc.create_compiler().map_err(move |err| BuildError::ShaderCompilation(path.to_path_buf(), err))?;
@@ -82,6 +255,7 @@ fn compile_hlsl(path: &Path, src: &str, disable_optimizations: bool) -> Result<(
 			args,
 			Some(&mut ShaderIncludeHandler {
 				path: path.parent().unwrap_or(Path::new("./")),
+				resolver,
 			}),
 			defines,
 		);
@@ -106,47 +280,81 @@ fn compile_hlsl(path: &Path, src: &str, disable_optimizations: bool) -> Result<(
 		}
 	};
 
-	let mut asts: Vec<spirv::Ast<hlsl::Target>> = Default::default();
+	let mut stages: Vec<ReflectedStage> = Default::default();
 
 	let spirv_default = ["-spirv"];
 	let spirv_no_optimize = ["-spirv", "-Od"];
 
 	let config: &[&str] = if disable_optimizations { &spirv_no_optimize } else { &spirv_default };
+	let defines = defines.iter().map(|define| (*define, Some("1"))).collect::<Vec<_>>();
 
 	let vs = if src.contains(VS_MAIN) {
-		let vs_ir = compile(VS_MAIN, "vs_6_0", config, &[])?;
+		let vs_ir = compile(VS_MAIN, "vs_6_0", config, &defines)?;
 
 		let module = spirv::Module::from_words(&vs_ir);
 		let ast = spirv::Ast::<hlsl::Target>::parse(&module).map_err(move |err| BuildError::ShaderReflection(path.to_path_buf(), err))?;
-		asts.push(ast);
+		stages.push(ReflectedStage {
+			ast,
+			id_bound: vs_ir[3],
+			cube_images: cube_image_variables(&vs_ir),
+		});
 		Some(vs_ir)
 	} else {
 		None
 	};
 
 	let ps = if src.contains(PS_MAIN) {
-		let ps_ir = compile(PS_MAIN, "ps_6_0", config, &[])?;
+		let ps_ir = compile(PS_MAIN, "ps_6_0", config, &defines)?;
 
 		let module = spirv::Module::from_words(&ps_ir);
 		let ast = spirv::Ast::<hlsl::Target>::parse(&module).map_err(move |err| BuildError::ShaderReflection(path.to_path_buf(), err))?;
-		asts.push(ast);
+		stages.push(ReflectedStage {
+			ast,
+			id_bound: ps_ir[3],
+			cube_images: cube_image_variables(&ps_ir),
+		});
 		Some(ps_ir)
 	} else {
 		None
 	};
 
-	let cs = if src.contains(CS_MAIN) {
-		let cs_ir = compile(CS_MAIN, "cs_6_0", config, &[])?;
+	let gs = if src.contains(GS_MAIN) {
+		let gs_ir = compile(GS_MAIN, "gs_6_0", config, &defines)?;
 
-		let module = spirv::Module::from_words(&cs_ir);
+		let module = spirv::Module::from_words(&gs_ir);
 		let ast = spirv::Ast::<hlsl::Target>::parse(&module).map_err(move |err| BuildError::ShaderReflection(path.to_path_buf(), err))?;
-		asts.push(ast);
-		Some(cs_ir)
+		stages.push(ReflectedStage {
+			ast,
+			id_bound: gs_ir[3],
+			cube_images: cube_image_variables(&gs_ir),
+		});
+		Some(gs_ir)
 	} else {
 		None
 	};
 
-	Ok((asts, CompiledShaders { vs, ps, cs }))
+	let (cs, cs_local_size) = if src.contains(CS_MAIN) {
+		let cs_ir = compile(CS_MAIN, "cs_6_0", config, &defines)?;
+
+		let module = spirv::Module::from_words(&cs_ir);
+		let mut ast = spirv::Ast::<hlsl::Target>::parse(&module).map_err(move |err| BuildError::ShaderReflection(path.to_path_buf(), err))?;
+		let entry_points = ast.get_entry_points().map_err(move |err| BuildError::ShaderReflection(path.to_path_buf(), err))?;
+		let work_group_size = &entry_points
+			.first()
+			.ok_or_else(|| BuildError::Unknown(format!("No compute entry point in {}", path.display())))?
+			.work_group_size;
+		let local_size = [work_group_size.x, work_group_size.y, work_group_size.z];
+		stages.push(ReflectedStage {
+			ast,
+			id_bound: cs_ir[3],
+			cube_images: cube_image_variables(&cs_ir),
+		});
+		(Some(cs_ir), Some(local_size))
+	} else {
+		(None, None)
+	};
+
+	Ok((stages, CompiledShaders { vs, ps, gs, cs, cs_local_size }))
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -161,25 +369,193 @@ enum MemberType {
 	UVec2,
 	UVec3,
 	UVec4,
+	F16,
+	F16Vec2,
+	F16Vec3,
+	F16Vec4,
+	Bool32,
+	Struct(Struct),
+	Array { ty: Box<MemberType>, count: u32, stride: u32 },
 }
-impl From<Type> for MemberType {
-	fn from(ty: Type) -> Self {
-		match ty {
-			Type::Float { vecsize: 1, columns: 1, .. } => MemberType::F32,
-			Type::Float { vecsize: 2, columns: 1, .. } => MemberType::Vec2,
-			Type::Float { vecsize: 3, columns: 1, .. } => MemberType::Vec3,
-			Type::Float { vecsize: 4, columns: 1, .. } => MemberType::Vec4,
-			Type::Float { vecsize: 3, columns: 3, .. } => MemberType::Mat3,
-			Type::Float { vecsize: 4, columns: 4, .. } => MemberType::Mat4,
-			Type::UInt { vecsize: 1, columns: 1, .. } => MemberType::U32,
-			Type::UInt { vecsize: 2, columns: 1, .. } => MemberType::UVec2,
-			Type::UInt { vecsize: 3, columns: 1, .. } => MemberType::UVec3,
-			Type::UInt { vecsize: 4, columns: 1, .. } => MemberType::UVec4,
-			_ => unimplemented!("Unimplemented type {:?}", ty),
+
+impl MemberType {
+	fn rust_type(&self) -> String {
+		match self {
+			MemberType::F32 => "f32".to_owned(),
+			MemberType::Vec2 => "glam::Vec2".to_owned(),
+			MemberType::Vec3 => "glam::Vec3".to_owned(),
+			MemberType::Vec4 => "glam::Vec4".to_owned(),
+			MemberType::Mat3 => "glam::Mat3".to_owned(),
+			MemberType::Mat4 => "glam::Mat4".to_owned(),
+			MemberType::U32 => "u32".to_owned(),
+			MemberType::UVec2 => "glam::UVec2".to_owned(),
+			MemberType::UVec3 => "glam::UVec3".to_owned(),
+			MemberType::UVec4 => "glam::UVec4".to_owned(),
+			MemberType::F16 => "half::f16".to_owned(),
+			MemberType::F16Vec2 => "[half::f16; 2]".to_owned(),
+			MemberType::F16Vec3 => "[half::f16; 3]".to_owned(),
+			MemberType::F16Vec4 => "[half::f16; 4]".to_owned(),
+			// HLSL bools are 4 bytes wide in constant buffers, so just expose them as a u32.
+			MemberType::Bool32 => "u32".to_owned(),
+			MemberType::Struct(struct_info) => struct_info.ty_name.clone(),
+			MemberType::Array { ty, count, .. } => format!("[{}; {}]", ty.rust_type(), count),
+		}
+	}
+
+	fn repr_rust(&self) -> String {
+		match self {
+			MemberType::F32 => "goldfish::build::MemberTypeRepr::F32".to_owned(),
+			MemberType::Vec2 => "goldfish::build::MemberTypeRepr::Vec2".to_owned(),
+			MemberType::Vec3 => "goldfish::build::MemberTypeRepr::Vec3".to_owned(),
+			MemberType::Vec4 => "goldfish::build::MemberTypeRepr::Vec4".to_owned(),
+			MemberType::Mat3 => "goldfish::build::MemberTypeRepr::Mat3".to_owned(),
+			MemberType::Mat4 => "goldfish::build::MemberTypeRepr::Mat4".to_owned(),
+			MemberType::U32 => "goldfish::build::MemberTypeRepr::U32".to_owned(),
+			MemberType::UVec2 => "goldfish::build::MemberTypeRepr::UVec2".to_owned(),
+			MemberType::UVec3 => "goldfish::build::MemberTypeRepr::UVec3".to_owned(),
+			MemberType::UVec4 => "goldfish::build::MemberTypeRepr::UVec4".to_owned(),
+			MemberType::F16 => "goldfish::build::MemberTypeRepr::F16".to_owned(),
+			MemberType::F16Vec2 => "goldfish::build::MemberTypeRepr::F16Vec2".to_owned(),
+			MemberType::F16Vec3 => "goldfish::build::MemberTypeRepr::F16Vec3".to_owned(),
+			MemberType::F16Vec4 => "goldfish::build::MemberTypeRepr::F16Vec4".to_owned(),
+			MemberType::Bool32 => "goldfish::build::MemberTypeRepr::Bool32".to_owned(),
+			MemberType::Struct(struct_info) => format!(
+				"goldfish::build::MemberTypeRepr::Struct {{ name: \"{0}\", size: {1}, members: {0}::MEMBERS }}",
+				struct_info.ty_name, struct_info.size
+			),
+			MemberType::Array { ty, count, stride } => format!("goldfish::build::MemberTypeRepr::Array {{ ty: &{}, count: {}, stride: {} }}", ty.repr_rust(), count, stride),
+		}
+	}
+
+	// Serde only implements arrays up to 32 elements, so anything longer has to go through goldfish::build::serde_array.
+	fn serde_attribute_rust(&self) -> &'static str {
+		match self {
+			MemberType::Array { count, .. } if *count > 32 => "#[cfg_attr(feature = \"serde\", serde(with = \"goldfish::build::serde_array\"))]\n",
+			_ => "",
+		}
+	}
+
+	fn default_rust(&self) -> String {
+		match self {
+			// Default is only derived for arrays up to 32 elements, so build them out of their element's default instead.
+			MemberType::Array { ty, count, .. } => format!("[{}; {}]", ty.default_rust(), count),
+			_ => "Default::default()".to_owned(),
+		}
+	}
+
+	fn nested_structs(&self, structs: &mut Vec<Struct>) {
+		match self {
+			MemberType::Struct(struct_info) => {
+				for member in &struct_info.members {
+					member.ty.nested_structs(structs);
+				}
+
+				if !structs.contains(struct_info) {
+					structs.push(struct_info.clone());
+				}
+			}
+			MemberType::Array { ty, .. } => ty.nested_structs(structs),
+			_ => {}
 		}
 	}
 }
 
+fn strip_type_prefix(ty_name: String) -> String {
+	if let Some(last) = ty_name.rfind(".") {
+		ty_name[last + 1..].to_owned()
+	} else {
+		ty_name
+	}
+}
+
+// SPIRV-Cross only keeps member names and offsets on the struct declaration itself, so for arrays of structs we have to go find it.
+fn find_struct_declaration(ast: &mut spirv::Ast<hlsl::Target>, id_bound: u32, path: &Path, buffer: &str, id: u32, member_types: &[u32]) -> Result<u32, BuildError> {
+	for base_id in std::iter::once(id).chain(0..id_bound) {
+		if let Ok(Type::Struct {
+			member_types: ref base_member_types,
+			ref array,
+			..
+		}) = ast.get_type(base_id)
+		{
+			if array.is_empty() && base_member_types == member_types && !ast.get_member_name(base_id, 0).unwrap().is_empty() {
+				return Ok(base_id);
+			}
+		}
+	}
+
+	Err(BuildError::MissingStructDeclaration(path.to_path_buf(), buffer.to_owned(), id))
+}
+
+fn struct_members(ast: &mut spirv::Ast<hlsl::Target>, id_bound: u32, path: &Path, buffer: &str, base_type_id: u32, member_types: &[u32]) -> Result<Vec<StructMember>, BuildError> {
+	member_types
+		.iter()
+		.enumerate()
+		.map(|(i, id)| {
+			let name = ast.get_member_name(base_type_id, i as u32).unwrap();
+
+			Ok(StructMember {
+				ty: member_type(ast, id_bound, path, buffer, &name, *id)?,
+				offset: ast.get_member_decoration(base_type_id, i as u32, Decoration::Offset).unwrap(),
+				name,
+			})
+		})
+		.collect()
+}
+
+fn element_type(ast: &mut spirv::Ast<hlsl::Target>, id_bound: u32, path: &Path, buffer: &str, member: &str, id: u32, ty: Type) -> Result<MemberType, BuildError> {
+	Ok(match ty {
+		Type::Float { vecsize: 1, columns: 1, .. } => MemberType::F32,
+		Type::Float { vecsize: 2, columns: 1, .. } => MemberType::Vec2,
+		Type::Float { vecsize: 3, columns: 1, .. } => MemberType::Vec3,
+		Type::Float { vecsize: 4, columns: 1, .. } => MemberType::Vec4,
+		Type::Float { vecsize: 3, columns: 3, .. } => MemberType::Mat3,
+		Type::Float { vecsize: 4, columns: 4, .. } => MemberType::Mat4,
+		Type::UInt { vecsize: 1, columns: 1, .. } => MemberType::U32,
+		Type::UInt { vecsize: 2, columns: 1, .. } => MemberType::UVec2,
+		Type::UInt { vecsize: 3, columns: 1, .. } => MemberType::UVec3,
+		Type::UInt { vecsize: 4, columns: 1, .. } => MemberType::UVec4,
+		Type::Half { vecsize: 1, columns: 1, .. } => MemberType::F16,
+		Type::Half { vecsize: 2, columns: 1, .. } => MemberType::F16Vec2,
+		Type::Half { vecsize: 3, columns: 1, .. } => MemberType::F16Vec3,
+		Type::Half { vecsize: 4, columns: 1, .. } => MemberType::F16Vec4,
+		Type::Boolean { vecsize: 1, columns: 1, .. } => MemberType::Bool32,
+		Type::Struct { ref member_types, .. } => {
+			let base_type_id = find_struct_declaration(ast, id_bound, path, buffer, id, member_types)?;
+
+			MemberType::Struct(Struct {
+				ty_name: strip_type_prefix(ast.get_name(base_type_id).unwrap()),
+				members: struct_members(ast, id_bound, path, buffer, base_type_id, member_types)?,
+				size: ast.get_declared_struct_size(base_type_id).unwrap(),
+			})
+		}
+		_ => return Err(BuildError::UnsupportedMemberType(path.to_path_buf(), buffer.to_owned(), member.to_owned(), format!("{:?}", ty))),
+	})
+}
+
+fn member_type(ast: &mut spirv::Ast<hlsl::Target>, id_bound: u32, path: &Path, buffer: &str, member: &str, id: u32) -> Result<MemberType, BuildError> {
+	let ty = ast.get_type(id).unwrap();
+
+	let array = match ty {
+		Type::Float { ref array, .. } | Type::UInt { ref array, .. } | Type::Half { ref array, .. } | Type::Boolean { ref array, .. } | Type::Struct { ref array, .. } => array.clone(),
+		_ => Default::default(),
+	};
+
+	match array[..] {
+		[] => element_type(ast, id_bound, path, buffer, member, id, ty),
+		[count] => Ok(MemberType::Array {
+			ty: Box::new(element_type(ast, id_bound, path, buffer, member, id, ty)?),
+			count,
+			stride: ast.get_decoration(id, Decoration::ArrayStride).unwrap(),
+		}),
+		_ => Err(BuildError::UnsupportedMemberType(
+			path.to_path_buf(),
+			buffer.to_owned(),
+			member.to_owned(),
+			format!("multidimensional array {:?}", ty),
+		)),
+	}
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct StructMember {
 	name: String,
@@ -201,20 +577,48 @@ enum DescriptorBinding {
 	RWStructuredBuffer { name: String, struct_info: Struct },
 	SamplerState { name: String },
 	Texture2D { name: String },
+	Texture2DArray { name: String, count: u32 },
+	Texture2DRuntimeArray { name: String },
+	RWTexture2D { name: String },
+	TextureCube { name: String },
+}
+
+impl DescriptorBinding {
+	fn binding_type(&self) -> String {
+		match self {
+			DescriptorBinding::CBuffer { .. } => "CBuffer".to_owned(),
+			DescriptorBinding::StructuredBuffer { .. } => "StructuredBuffer".to_owned(),
+			DescriptorBinding::RWStructuredBuffer { .. } => "RWStructuredBuffer".to_owned(),
+			DescriptorBinding::SamplerState { .. } => "SamplerState".to_owned(),
+			DescriptorBinding::Texture2D { .. } => "Texture2D".to_owned(),
+			DescriptorBinding::Texture2DArray { count, .. } => format!("Texture2DArray {{ count: {} }}", count),
+			DescriptorBinding::Texture2DRuntimeArray { .. } => "Texture2DRuntimeArray".to_owned(),
+			DescriptorBinding::RWTexture2D { .. } => "RWTexture2D".to_owned(),
+			DescriptorBinding::TextureCube { .. } => "TextureCube".to_owned(),
+		}
+	}
+
+	fn struct_info(&self) -> Option<&Struct> {
+		match self {
+			DescriptorBinding::CBuffer { struct_info, .. } | DescriptorBinding::StructuredBuffer { struct_info, .. } | DescriptorBinding::RWStructuredBuffer { struct_info, .. } => Some(struct_info),
+			_ => None,
+		}
+	}
 }
 
 type DescriptorBindings = HashMap<u32, DescriptorBinding>;
 type DescriptorSets = HashMap<u32, DescriptorBindings>;
 
-fn generate_descriptors(asts: &mut [spirv::Ast<hlsl::Target>]) -> DescriptorSets {
+fn generate_descriptors(path: &Path, stages: &mut [ReflectedStage]) -> Result<DescriptorSets, BuildError> {
 	let mut descriptors: DescriptorSets = Default::default();
-	for ast in asts {
+	for stage in stages {
+		let ast = &mut stage.ast;
 		let resources = ast.get_shader_resources().unwrap();
 		for resource in resources.uniform_buffers {
 			let ty_name = ast.get_name(resource.base_type_id).unwrap();
 			let name = ast.get_name(resource.id).unwrap();
 
-			let ty_name = if let Some(last) = ty_name.rfind(".") { ty_name[last + 1..].to_owned() } else { ty_name };
+			let ty_name = strip_type_prefix(ty_name);
 
 			let resource_type = ast.get_type(resource.base_type_id).unwrap();
 			let size = ast.get_declared_struct_size(resource.base_type_id).unwrap();
@@ -226,15 +630,7 @@ fn generate_descriptors(asts: &mut [spirv::Ast<hlsl::Target>]) -> DescriptorSets
                 );
             };
 
-			let members = member_types
-				.iter()
-				.enumerate()
-				.map(|(i, id)| StructMember {
-					name: ast.get_member_name(resource.base_type_id, i as u32).unwrap(),
-					ty: ast.get_type(*id).unwrap().into(),
-					offset: ast.get_member_decoration(resource.base_type_id, i as u32, Decoration::Offset).unwrap(),
-				})
-				.collect::<Vec<_>>();
+			let members = struct_members(ast, stage.id_bound, path, &name, resource.base_type_id, &member_types)?;
 
 			let set = ast.get_decoration(resource.id, Decoration::DescriptorSet).unwrap();
 
@@ -281,16 +677,8 @@ fn generate_descriptors(asts: &mut [spirv::Ast<hlsl::Target>]) -> DescriptorSets
 			let base_type_id = base_type_id.expect("Could not find matching base_type_id through our hacky method :/");
 			let ty_name = ast.get_name(base_type_id).unwrap();
 
-			let members = member_types
-				.iter()
-				.enumerate()
-				.map(|(i, id)| StructMember {
-					// TODO(Brandon): This cannot POSSIBLY be correct, but for some reason it's working :/
-					name: ast.get_member_name(base_type_id, i as u32).unwrap(),
-					ty: ast.get_type(*id).unwrap().into(),
-					offset: ast.get_member_decoration(base_type_id, i as u32, Decoration::Offset).unwrap(),
-				})
-				.collect::<Vec<_>>();
+			// TODO(Brandon): This cannot POSSIBLY be correct, but for some reason it's working :/
+			let members = struct_members(ast, stage.id_bound, path, &name, base_type_id, member_types)?;
 
 			let set = ast.get_decoration(resource.id, Decoration::DescriptorSet).unwrap();
 
@@ -328,36 +716,158 @@ fn generate_descriptors(asts: &mut [spirv::Ast<hlsl::Target>]) -> DescriptorSets
 
 			let binding = ast.get_decoration(resource.id, Decoration::Binding).unwrap();
 
-			descriptors.entry(set).or_default().entry(binding).or_insert(DescriptorBinding::Texture2D { name });
+			let array = match ast.get_type(resource.type_id).unwrap() {
+				Type::Image { array, .. } => array,
+				_ => Default::default(),
+			};
+
+			// SPIRV-Cross reports unbounded arrays (Texture2D textures[]) as having a size of 0.
+			let descriptor_binding = match array[..] {
+				[] if stage.cube_images.contains(&resource.id) => DescriptorBinding::TextureCube { name },
+				[] => DescriptorBinding::Texture2D { name },
+				[0] => DescriptorBinding::Texture2DRuntimeArray { name },
+				[count] => DescriptorBinding::Texture2DArray { name, count },
+				_ => return Err(BuildError::UnsupportedDescriptor(path.to_path_buf(), name, format!("multidimensional texture array {:?}", array))),
+			};
+
+			descriptors.entry(set).or_default().entry(binding).or_insert(descriptor_binding);
+		}
+
+		for resource in resources.storage_images {
+			let name = resource.name;
+
+			let set = ast.get_decoration(resource.id, Decoration::DescriptorSet).unwrap();
+
+			let binding = ast.get_decoration(resource.id, Decoration::Binding).unwrap();
+
+			descriptors.entry(set).or_default().entry(binding).or_insert(DescriptorBinding::RWTexture2D { name });
 		}
 	}
-	return descriptors;
+	Ok(descriptors)
 }
 
-fn parse_shader_includes(asset_dir: &Path) -> Result<HashMap<String, DescriptorSets>, BuildError> {
-	let mut descriptor_layouts: HashMap<String, DescriptorSets> = Default::default();
+// A shader has at most one push constant block, every stage that uses it has to see it the same way.
+fn reflect_push_constants(path: &Path, stages: &mut [ReflectedStage]) -> Result<Option<Struct>, BuildError> {
+	let mut push_constants: Option<Struct> = None;
+	for stage in stages {
+		let ast = &mut stage.ast;
+		let resources = ast.get_shader_resources().unwrap();
+		for resource in resources.push_constant_buffers {
+			let name = ast.get_name(resource.id).unwrap();
+			let ty_name = strip_type_prefix(ast.get_name(resource.base_type_id).unwrap());
+			let size = ast.get_declared_struct_size(resource.base_type_id).unwrap();
+
+			let member_types = match ast.get_type(resource.base_type_id).unwrap() {
+				Type::Struct { member_types, .. } => member_types,
+				ty => return Err(BuildError::UnsupportedDescriptor(path.to_path_buf(), name, format!("push constants that aren't a struct {:?}", ty))),
+			};
+
+			let struct_info = Struct {
+				members: struct_members(ast, stage.id_bound, path, &name, resource.base_type_id, &member_types)?,
+				ty_name,
+				size,
+			};
+
+			match push_constants {
+				Some(ref existing) if *existing != struct_info => {
+					let message = "push constant block that's declared differently between stages".to_owned();
+					return Err(BuildError::UnsupportedDescriptor(path.to_path_buf(), name, message));
+				}
+				Some(_) => {}
+				None => push_constants = Some(struct_info),
+			}
+		}
+	}
+
+	Ok(push_constants)
+}
+
+fn parse_includes(src: &str) -> impl Iterator<Item = &str> {
+	src.lines().filter_map(|line| {
+		let include = line.trim_start().strip_prefix("#include")?.trim_start().strip_prefix('"')?;
+		Some(&include[..include.find('"')?])
+	})
+}
 
-	for asset in fs::read_dir(asset_dir).map_err(move |err| BuildError::Filesystem(err))? {
-		let asset = asset.map_err(move |err| BuildError::Filesystem(err))?;
-		let asset_path = asset.path();
+// Every shader with the given extension anywhere under `dir`, each one generates a module named after its file so those need to be unique.
+fn collect_shaders(dir: &Path, extension: &str, shaders: &mut HashMap<String, PathBuf>) -> Result<(), BuildError> {
+	for asset in fs::read_dir(dir).map_err(move |err| BuildError::Filesystem(err))? {
+		let asset_path = asset.map_err(move |err| BuildError::Filesystem(err))?.path();
 
 		if asset_path.is_dir() {
-			unimplemented!("Cannot handle nested directories for shaders");
-		} else if let Some(extension) = asset_path.extension() {
-			if extension != SHADER_INC {
-				continue;
+			collect_shaders(&asset_path, extension, shaders)?;
+		} else if asset_path.extension().map_or(false, |ext| ext == extension) {
+			let module = asset_path.file_stem().unwrap().to_str().unwrap().to_owned();
+			if let Some(existing) = shaders.get(&module) {
+				return Err(BuildError::DuplicateShaderModule(module, existing.clone(), asset_path));
 			}
 
-			println!("cargo:warning=Parsing shader include {} ...", asset_path.to_str().unwrap());
+			shaders.insert(module, normalize(&asset_path));
+		}
+	}
+
+	Ok(())
+}
+
+fn include_module(include: &Path) -> Option<&str> {
+	if include.extension()? != SHADER_INC {
+		return None;
+	}
+
+	include.file_stem()?.to_str()
+}
+
+// Maps each descriptor set that comes from one of `includes` to the generated module of the include that actually declares it.
+fn included_sets(includes: &[PathBuf], descriptor_layouts: &HashMap<String, DescriptorSets>, resolver: &IncludeResolver) -> Result<HashMap<u32, String>, BuildError> {
+	let mut included_sets: HashMap<u32, String> = Default::default();
 
-			let mut src = fs::read_to_string(&asset_path).map_err(move |err| BuildError::Filesystem(err))?;
+	for include in includes {
+		let Some(module) = include_module(include) else {
+			continue;
+		};
 
-			if src.contains("#include") {
-				println!("cargo:warning=WARNING: Nested include detected! This is not fully tested yet...");
+		let Some(sets) = descriptor_layouts.get(module) else {
+			continue;
+		};
+
+		// DXC hands us every set the include can see, so skip the ones it just pulled in from its own nested includes.
+		let nested_sets = resolver
+			.find_includes(include)?
+			.iter()
+			.filter_map(|nested| descriptor_layouts.get(include_module(nested)?))
+			.flat_map(|nested_sets| nested_sets.keys().copied())
+			.collect::<HashSet<u32>>();
+
+		for (set, _) in sets {
+			if !nested_sets.contains(set) {
+				included_sets.insert(*set, format!("super::{}_inc", module));
 			}
+		}
+	}
+
+	Ok(included_sets)
+}
+
+fn generate_included_descriptor_rust(set: u32, include_module: &str) -> String {
+	// Included sets alias the include's Descriptor type, so every shader that shares an include can be handed the same descriptor struct.
+	format!(
+		"\npub type Descriptor{0} = {1}::Descriptor{0};\npub const DESCRIPTOR_SET_{0}_INFO: &'static goldfish::renderer::DescriptorSetInfo = {1}::DESCRIPTOR_SET_{0}_INFO;\n",
+		set, include_module
+	)
+}
 
-			if !src.contains(VS_MAIN) {
-				src += "
+// Compiles every include on its own to find out which descriptor sets it declares, whatever it includes in turn gets pulled in by DXC.
+fn parse_shader_includes(includes: &HashMap<String, PathBuf>, resolver: &IncludeResolver) -> Result<HashMap<String, DescriptorSets>, BuildError> {
+	let mut descriptor_layouts: HashMap<String, DescriptorSets> = Default::default();
+
+	for (module, asset_path) in includes {
+		println!("cargo:warning=Parsing shader include {} ...", asset_path.to_str().unwrap());
+		resolver.find_includes(asset_path)?;
+
+		let mut src = fs::read_to_string(&asset_path).map_err(move |err| BuildError::Filesystem(err))?;
+
+		if !src.contains(VS_MAIN) {
+			src += "
 struct __VS_OUTPUT__
 {
     float4 position : SV_POSITION;
@@ -370,16 +880,189 @@ __VS_OUTPUT__ vs_main(float3 pos : POSITION)
     return result;
 }
 ";
-				let (mut asts, _) = compile_hlsl(&asset_path, &src, true)?;
-				let descriptors = generate_descriptors(&mut asts);
+			let (mut stages, _) = compile_hlsl(&asset_path, &src, resolver, true, &[])?;
+			let descriptors = generate_descriptors(&asset_path, &mut stages)?;
 
-				descriptor_layouts.insert(asset_path.file_stem().unwrap().to_str().unwrap().to_owned(), descriptors);
-			}
+			descriptor_layouts.insert(module.clone(), descriptors);
 		}
 	}
 	Ok(descriptor_layouts)
 }
 
+struct SpecializationConstant {
+	name: String,
+	constant_id: u32,
+	default: String,
+}
+
+// SPIRV-Cross doesn't give us an easy way to get at the default values, so we just pull the declarations out of the source.
+// We expect them to look like `[[vk::constant_id(0)]] const uint TILE_SIZE = 16;`
+fn parse_specialization_constants(path: &Path, src: &str) -> Result<Vec<SpecializationConstant>, BuildError> {
+	const CONSTANT_ID: &'static str = "[[vk::constant_id(";
+
+	let mut constants: Vec<SpecializationConstant> = Default::default();
+	for (start, _) in src.match_indices(CONSTANT_ID) {
+		let decl = &src[start + CONSTANT_ID.len()..];
+		let decl = &decl[..decl.find(';').ok_or_else(|| BuildError::InvalidSpecializationConstant(path.to_path_buf(), "missing ';'".to_owned()))?];
+
+		let invalid = || BuildError::InvalidSpecializationConstant(path.to_path_buf(), decl.to_owned());
+
+		let (constant_id, decl) = decl.split_once(")]]").ok_or_else(invalid)?;
+		let constant_id = constant_id.trim().parse::<u32>().map_err(move |_| invalid())?;
+
+		let (decl, value) = decl.split_once('=').ok_or_else(invalid)?;
+		let value = value.trim();
+
+		let [ty, name] = decl.split_whitespace().filter(|token| *token != "const" && *token != "static").collect::<Vec<_>>()[..] else {
+			return Err(invalid());
+		};
+
+		let default = match ty {
+			"uint" => format!("U32({})", value.trim_end_matches(['u', 'U']).parse::<u32>().map_err(move |_| invalid())?),
+			"int" => format!("I32({})", value.parse::<i32>().map_err(move |_| invalid())?),
+			"float" => format!("F32({:?})", value.trim_end_matches(['f', 'F']).parse::<f32>().map_err(move |_| invalid())?),
+			"bool" => format!("Bool({})", value.parse::<bool>().map_err(move |_| invalid())?),
+			_ => return Err(invalid()),
+		};
+
+		constants.push(SpecializationConstant {
+			name: name.to_owned(),
+			constant_id,
+			default: format!("goldfish::renderer::SpecValue::{}", default),
+		});
+	}
+
+	Ok(constants)
+}
+
+// Same rules as parse_permutations in the editor's shader_compiler.rs.
+fn parse_permutations(path: &Path, src: &str) -> Result<Vec<String>, BuildError> {
+	let invalid = |message: String| BuildError::InvalidPermutations(path.to_path_buf(), message);
+
+	let mut permutations: Vec<String> = Default::default();
+	for line in src.lines() {
+		let Some(defines) = line.trim_start().strip_prefix(PERMUTE_PRAGMA) else {
+			continue;
+		};
+
+		for define in defines.split_whitespace() {
+			if define.starts_with(|c: char| c.is_ascii_digit()) || !define.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+				return Err(invalid(format!("{} isn't a valid define", define)));
+			}
+
+			if permutations.iter().any(|existing| existing == define) {
+				return Err(invalid(format!("{} is listed more than once", define)));
+			}
+
+			permutations.push(define.to_owned());
+		}
+	}
+
+	let max_defines = MAX_PERMUTATIONS.trailing_zeros() as usize;
+	if permutations.len() > max_defines {
+		return Err(invalid(format!(
+			"{} defines would be 2^{} permutations, at most {} defines ({} permutations) are allowed",
+			permutations.len(),
+			permutations.len(),
+			max_defines,
+			MAX_PERMUTATIONS
+		)));
+	}
+
+	Ok(permutations)
+}
+
+fn permutation_defines(permutations: &[String], mask: usize) -> Vec<&str> {
+	permutations.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0).map(|(_, define)| define.as_str()).collect()
+}
+
+// Everything gets generated from the permutation with every define on, so every other permutation has to fit what it declares.
+fn check_permutation(path: &Path, defines: &[&str], superset: (&DescriptorSets, &CompiledShaders), permutation: (&DescriptorSets, &CompiledShaders)) -> Result<(), BuildError> {
+	let name = if defines.is_empty() { "no defines".to_owned() } else { defines.join(" ") };
+	let inconsistent = |message: String| BuildError::InconsistentPermutation(path.to_path_buf(), name.clone(), message);
+
+	for (set, bindings) in permutation.0 {
+		for (binding, info) in bindings {
+			match superset.0.get(set).and_then(|bindings| bindings.get(binding)) {
+				None => return Err(inconsistent(format!("set {} binding {} isn't declared with every define on", set, binding))),
+				Some(superset_info) if superset_info.binding_type() != info.binding_type() || superset_info.struct_info() != info.struct_info() => {
+					return Err(inconsistent(format!("set {} binding {} is declared differently with every define on", set, binding)));
+				}
+				_ => {}
+			}
+		}
+	}
+
+	if permutation.1.cs_local_size != superset.1.cs_local_size {
+		return Err(inconsistent(format!("numthreads is {:?} instead of {:?}", permutation.1.cs_local_size, superset.1.cs_local_size)));
+	}
+
+	Ok(())
+}
+
+fn generate_permutation_flags_rust(permutations: &[String]) -> String {
+	format!(
+		"
+goldfish::bitflags::bitflags! {{
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct PermutationFlags: u32 {{
+{}
+    }}
+}}
+",
+		permutations.iter().enumerate().map(|(i, define)| format!("const {} = 1 << {};\n", define, i)).collect::<String>(),
+	)
+}
+
+// Writes one stage of one permutation next to the generated code, and returns the include_bytes! for it.
+fn write_shader_ir(out: &Path, ir: &[u32]) -> Result<String, BuildError> {
+	let bytes = ir.iter().flat_map(|code| code.to_ne_bytes()).collect::<Vec<_>>();
+	std::fs::write(out, bytes).map_err(move |err| BuildError::Filesystem(err))?;
+
+	Ok(format!("include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\"))", out.file_name().unwrap().to_str().unwrap()))
+}
+
+// A shader without permutations just gets VS_BYTES and friends, otherwise there's one blob per permutation indexed by its mask.
+fn generate_shader_bytes_rust(stage: &str, blobs: &[String], permuted: bool) -> String {
+	if !permuted {
+		return format!("pub const {}_BYTES: &[u8] = {};\n", stage.to_uppercase(), blobs[0]);
+	}
+
+	format!(
+		"
+const {0}_PERMUTATIONS: [&[u8]; {1}] = [
+{2}
+];
+
+pub fn {3}_bytes(flags: PermutationFlags) -> &'static [u8] {{
+    {0}_PERMUTATIONS[flags.bits() as usize]
+}}
+",
+		stage.to_uppercase(),
+		blobs.len(),
+		blobs.iter().map(|blob| format!("{},\n", blob)).collect::<String>(),
+		stage,
+	)
+}
+
+fn generate_specialization_rust(constants: &[SpecializationConstant]) -> String {
+	let constant_rust = |constant: &SpecializationConstant| format!("goldfish::renderer::SpecializationConstant {{ constant_id: {}, default: {} }}", constant.constant_id, constant.default);
+
+	format!(
+		"
+{}
+pub const SPECIALIZATION_CONSTANTS: phf::Map<&'static str, goldfish::renderer::SpecializationConstant> = phf::phf_map! {{
+{}
+}};
+",
+		constants
+			.iter()
+			.map(|constant| format!("pub const {}: goldfish::renderer::SpecializationConstant = {};\n", constant.name, constant_rust(constant)))
+			.collect::<String>(),
+		constants.iter().map(|constant| format!("\"{}\" => {},\n", constant.name, constant_rust(constant))).collect::<String>(),
+	)
+}
+
 fn generate_descriptor_rust(set: u32, bindings: &DescriptorBindings) -> String {
 	format!(
 		"
@@ -387,6 +1070,18 @@ fn generate_descriptor_rust(set: u32, bindings: &DescriptorBindings) -> String {
 pub struct Descriptor{0} {{
 {1}
 }}
+
+pub const DESCRIPTOR_SET_{0}_INFO: &'static goldfish::renderer::DescriptorSetInfo = &goldfish::renderer::DescriptorSetInfo {{
+    bindings: phf::phf_map! {{
+{2}
+    }},
+}};
+
+impl goldfish::build::Descriptor for Descriptor{0} {{
+    fn info() -> &'static goldfish::renderer::DescriptorSetInfo {{
+        DESCRIPTOR_SET_{0}_INFO
+    }}
+}}
 ",
 		set,
 		bindings
@@ -399,6 +1094,10 @@ pub struct Descriptor{0} {{
 					DescriptorBinding::RWStructuredBuffer { name, .. } => name,
 					DescriptorBinding::SamplerState { name } => name,
 					DescriptorBinding::Texture2D { name } => name,
+					DescriptorBinding::Texture2DArray { name, .. } => name,
+					DescriptorBinding::Texture2DRuntimeArray { name } => name,
+					DescriptorBinding::RWTexture2D { name } => name,
+					DescriptorBinding::TextureCube { name } => name,
 				},
 				match info {
 					DescriptorBinding::CBuffer {
@@ -414,50 +1113,140 @@ pub struct Descriptor{0} {{
 				},
 			))
 			.collect::<String>(),
+		bindings
+			.iter()
+			.map(|(binding, info)| format!("{}u32 => goldfish::renderer::DescriptorBindingType::{},\n", binding, info.binding_type()))
+			.collect::<String>(),
 	)
 }
+// Serialize and Deserialize only get derived with the game's serde feature.
 fn generate_struct_rust(struct_info: &Struct) -> String {
 	format!(
 		"
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]
 pub struct {0} {{
 {1}
 }}
 
+impl {0} {{
+    pub const MEMBERS: &'static [goldfish::build::MemberInfo] = &[
+{3}
+    ];
+}}
+
+impl Default for {0} {{
+    fn default() -> Self {{
+        Self {{
+{2}
+        }}
+    }}
+}}
+
 unsafe impl bytemuck::Pod for {0} {{}}
 unsafe impl bytemuck::Zeroable for {0} {{}}
 
 ",
 		struct_info.ty_name,
+		struct_info
+			.members
+			.iter()
+			.map(|member| format!("{}pub {}: {},\n", member.ty.serde_attribute_rust(), member.name, member.ty.rust_type()))
+			.collect::<String>(),
+		struct_info.members.iter().map(|member| format!("{}: {},\n", member.name, member.ty.default_rust())).collect::<String>(),
 		struct_info
 			.members
 			.iter()
 			.map(|member| format!(
-				"pub {}: {},\n",
+				"goldfish::build::MemberInfo {{ name: \"{}\", offset: {}, ty: {} }},\n",
 				member.name,
-				match member.ty {
-					MemberType::F32 => "f32",
-					MemberType::Vec2 => "glam::Vec2",
-					MemberType::Vec3 => "glam::Vec3",
-					MemberType::Vec4 => "glam::Vec4",
-					MemberType::Mat3 => "glam::Mat3",
-					MemberType::Mat4 => "glam::Mat4",
-					MemberType::U32 => "u32",
-					MemberType::UVec2 => "glam::UVec2",
-					MemberType::UVec3 => "glam::UVec3",
-					MemberType::UVec4 => "glam::UVec4",
-				}
+				member.offset,
+				member.ty.repr_rust()
 			))
 			.collect::<String>(),
 	)
 }
 
+// Every struct used as a member (at any depth) of `structs`, inner structs first so each one is only declared once per module.
+fn generate_nested_structs_rust<'a>(structs: impl Iterator<Item = &'a Struct>) -> String {
+	let mut nested_structs: Vec<Struct> = Default::default();
+	for struct_info in structs {
+		for member in &struct_info.members {
+			member.ty.nested_structs(&mut nested_structs);
+		}
+	}
+
+	nested_structs.iter().map(|struct_info| generate_struct_rust(struct_info)).collect::<String>()
+}
+
+fn offset_rust(base: &str, offset: u32) -> String {
+	if offset == 0 {
+		base.to_owned()
+	} else {
+		format!("{} + {}", base, offset)
+	}
+}
+
+// Array elements live at `offset + i * stride`, and since `dst` starts zeroed anything between the element size and the stride is left as padding.
+fn generate_member_write_rust(ty: &MemberType, value: &str, offset: &str, dst: &str, depth: usize) -> String {
+	match ty {
+		MemberType::F32 | MemberType::U32 | MemberType::Bool32 => format!(
+			"
+let slice = &{0}.to_ne_bytes();
+{1}[{2}..{2} + slice.len()].clone_from_slice(slice);
+",
+			value, dst, offset
+		),
+		MemberType::Vec2 | MemberType::Vec3 | MemberType::Vec4 | MemberType::Mat3 | MemberType::Mat4 | MemberType::UVec2 | MemberType::UVec3 | MemberType::UVec4 => format!(
+			"
+let slice = bytemuck::cast_slice::<_, u8>({0}.as_ref());
+{1}[{2}..{2} + slice.len()].clone_from_slice(slice);
+",
+			value, dst, offset
+		),
+		MemberType::F16 => format!(
+			"
+let slice = &{0}.to_bits().to_ne_bytes();
+{1}[{2}..{2} + slice.len()].clone_from_slice(slice);
+",
+			value, dst, offset
+		),
+		MemberType::F16Vec2 | MemberType::F16Vec3 | MemberType::F16Vec4 => format!(
+			"
+let slice = &{0}.iter().flat_map(|x| x.to_bits().to_ne_bytes()).collect::<Vec<u8>>();
+{1}[{2}..{2} + slice.len()].clone_from_slice(slice);
+",
+			value, dst, offset
+		),
+		MemberType::Struct(struct_info) => struct_info
+			.members
+			.iter()
+			.map(|member| generate_member_write_rust(&member.ty, &format!("{}.{}", value, member.name), &offset_rust(offset, member.offset), dst, depth))
+			.collect::<String>(),
+		MemberType::Array { ty, stride, .. } => format!(
+			"
+for (i{0}, element{0}) in {1}.iter().enumerate() {{
+    let offset{0} = {2} + i{0} * {3};
+    {4}
+}}
+",
+			depth,
+			value,
+			offset,
+			stride,
+			generate_member_write_rust(ty, &format!("element{}", depth), &format!("offset{}", depth), dst, depth + 1)
+		),
+	}
+}
+
 fn generate_cbuffer_rust(struct_info: &Struct) -> String {
 	format!(
 		"
 {1}
 
 impl goldfish::build::CBuffer<{2}> for {0} {{
+    const MEMBERS: &'static [goldfish::build::MemberInfo] = {0}::MEMBERS;
+
     fn size() -> usize {{
         {2}
     }}
@@ -475,18 +1264,7 @@ impl goldfish::build::CBuffer<{2}> for {0} {{
 		struct_info
 			.members
 			.iter()
-			.map(|member| format!(
-				"
-let slice = {0};
-output[{1}..{1} + slice.len()].clone_from_slice(slice);
-",
-				match member.ty {
-					MemberType::F32 | MemberType::U32 => format!("&self.{0}.to_ne_bytes()", member.name),
-					MemberType::Vec2 | MemberType::Vec3 | MemberType::Vec4 | MemberType::Mat3 | MemberType::Mat4 | MemberType::UVec2 | MemberType::UVec3 | MemberType::UVec4 =>
-						format!("bytemuck::cast_slice::<_, u8>(self.{0}.as_ref())", member.name),
-				},
-				member.offset,
-			))
+			.map(|member| generate_member_write_rust(&member.ty, &format!("self.{}", member.name), &member.offset.to_string(), "output", 0))
 			.collect::<String>(),
 	)
 }
@@ -517,145 +1295,184 @@ impl goldfish::build::StructuredBuffer<{2}> for {0} {{
 		struct_info
 			.members
 			.iter()
-			.map(|member| format!(
-				"
-let slice = {0};
-dst[{1}..{1} + slice.len()].clone_from_slice(slice);
+			.map(|member| generate_member_write_rust(&member.ty, &format!("buf.{}", member.name), &member.offset.to_string(), "dst", 0))
+			.collect::<String>(),
+	)
+}
+
+// PUSH_CONSTANT_BYTES is what RasterPipelineDesc::push_constant_bytes should be.
+fn generate_push_constant_rust(struct_info: &Struct, declared: bool) -> String {
+	format!(
+		"
+pub const PUSH_CONSTANT_BYTES: usize = {2};
+
+{1}
+
+impl goldfish::build::PushConstant<{2}> for {0} {{
+    const MEMBERS: &'static [goldfish::build::MemberInfo] = {0}::MEMBERS;
+
+    fn size() -> usize {{
+        {2}
+    }}
+
+	fn as_buffer(&self) -> [u8; {2}] {{
+        let mut output: [u8; {2}] = [0; {2}];
+        {3}
+        output
+    }}
+}}
 ",
-				match member.ty {
-					MemberType::F32 | MemberType::U32 => format!("&buf.{0}.to_ne_bytes()", member.name),
-					MemberType::Vec2 | MemberType::Vec3 | MemberType::Vec4 | MemberType::Mat3 | MemberType::Mat4 | MemberType::UVec2 | MemberType::UVec3 | MemberType::UVec4 =>
-						format!("bytemuck::cast_slice::<_, u8>(buf.{0}.as_ref())", member.name),
-				},
-				member.offset,
-			))
+		struct_info.ty_name,
+		if declared { String::default() } else { generate_struct_rust(struct_info) },
+		struct_info.size,
+		struct_info
+			.members
+			.iter()
+			.map(|member| generate_member_write_rust(&member.ty, &format!("self.{}", member.name), &member.offset.to_string(), "output", 0))
 			.collect::<String>(),
 	)
 }
 
-fn compile_shaders(out_dir: &Path, asset_dir: &Path, descriptor_layouts: &HashMap<String, DescriptorSets>) -> Result<String, BuildError> {
+fn compile_shaders(out_dir: &Path, shaders: &HashMap<String, PathBuf>, descriptor_layouts: &HashMap<String, DescriptorSets>, resolver: &IncludeResolver) -> Result<String, BuildError> {
 	let mut generated = String::default();
-	for asset in fs::read_dir(asset_dir).map_err(move |err| BuildError::Filesystem(err))? {
-		let asset = asset.map_err(move |err| BuildError::Filesystem(err))?;
-		let asset_path = asset.path();
+	for (module, asset_path) in shaders {
+		println!("cargo:warning=Compiling {} ...", asset_path.to_str().unwrap());
 
-		if asset_path.is_dir() {
-			unimplemented!("Cannot handle nested directories for shaders");
-		} else if let Some(extension) = asset_path.extension() {
-			if extension != SHADER_EXT {
-				continue;
-			}
+		let includes = resolver.find_includes(&asset_path)?;
 
-			println!("cargo:warning=Compiling {} ...", asset_path.to_str().unwrap());
+		let src = fs::read_to_string(&asset_path).map_err(move |err| BuildError::Filesystem(err))?;
 
-			let src = fs::read_to_string(&asset_path).map_err(move |err| BuildError::Filesystem(err))?;
+		let permutations = parse_permutations(&asset_path, &src)?;
+		let superset = (1usize << permutations.len()) - 1;
 
-			let (mut asts, compiled_shaders) = compile_hlsl(&asset_path, &src, false)?;
+		let mut compiled = (0..=superset)
+			.map(|mask| compile_hlsl(&asset_path, &src, resolver, false, &permutation_defines(&permutations, mask)))
+			.collect::<Result<Vec<_>, BuildError>>()?;
 
-			let mut shader_ir_consts = String::default();
-			if let Some(ref vs) = compiled_shaders.vs {
-				let bytes = vs.iter().flat_map(|code| code.to_ne_bytes()).collect::<Vec<_>>();
+		// The descriptors are reflected from the permutation with every define on, the rest only get checked against it.
+		let mut permutation_descriptors = compiled
+			.iter_mut()
+			.map(|(stages, _)| generate_descriptors(&asset_path, stages))
+			.collect::<Result<Vec<_>, BuildError>>()?;
+		for (mask, (descriptors, (_, compiled_shaders))) in permutation_descriptors.iter().zip(compiled.iter()).enumerate() {
+			let reference = (&permutation_descriptors[superset], &compiled[superset].1);
+			check_permutation(&asset_path, &permutation_defines(&permutations, mask), reference, (descriptors, compiled_shaders))?;
+		}
+		let descriptors = permutation_descriptors.swap_remove(superset);
+
+		// A permutation that doesn't read any of its push constants won't have the block at all, which is fine.
+		let mut permutation_push_constants = compiled
+			.iter_mut()
+			.map(|(stages, _)| reflect_push_constants(&asset_path, stages))
+			.collect::<Result<Vec<_>, BuildError>>()?;
+		for (mask, push_constants) in permutation_push_constants.iter().enumerate() {
+			if push_constants.is_some() && *push_constants != permutation_push_constants[superset] {
+				let defines = permutation_defines(&permutations, mask);
+				let name = if defines.is_empty() { "no defines".to_owned() } else { defines.join(" ") };
+				let message = "the push constant block is declared differently".to_owned();
+				return Err(BuildError::InconsistentPermutation(asset_path.clone(), name, message));
+			}
+		}
+		let push_constants = permutation_push_constants.swap_remove(superset);
 
-				let out = out_dir.join(asset_path.file_name().unwrap()).with_extension("vs");
+		let mut shader_ir_consts = String::default();
+		if !permutations.is_empty() {
+			shader_ir_consts += &generate_permutation_flags_rust(&permutations);
+		}
 
-				std::fs::write(&out, bytes).map_err(move |err| BuildError::Filesystem(err))?;
+		let stages: [(&str, fn(&CompiledShaders) -> &Option<Vec<u32>>); 4] = [
+			("vs", |compiled_shaders| &compiled_shaders.vs),
+			("ps", |compiled_shaders| &compiled_shaders.ps),
+			("gs", |compiled_shaders| &compiled_shaders.gs),
+			("cs", |compiled_shaders| &compiled_shaders.cs),
+		];
 
-				shader_ir_consts += &format!(
-					"pub const VS_BYTES: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\"));\n",
-					out.file_name().unwrap().to_str().unwrap()
-				);
+		for (stage, stage_ir) in stages {
+			if stage_ir(&compiled[superset].1).is_none() {
+				continue;
 			}
 
-			if let Some(ref ps) = compiled_shaders.ps {
-				let bytes = ps.iter().flat_map(|code| code.to_ne_bytes()).collect::<Vec<_>>();
+			let blobs = compiled
+				.iter()
+				.enumerate()
+				.map(|(mask, (_, compiled_shaders))| {
+					let extension = if permutations.is_empty() { stage.to_owned() } else { format!("{}.{}", mask, stage) };
+					write_shader_ir(&out_dir.join(asset_path.file_name().unwrap()).with_extension(extension), stage_ir(compiled_shaders).as_ref().unwrap())
+				})
+				.collect::<Result<Vec<_>, BuildError>>()?;
 
-				let out = out_dir.join(asset_path.file_name().unwrap()).with_extension("ps");
-				std::fs::write(&out, bytes).map_err(move |err| BuildError::Filesystem(err))?;
+			shader_ir_consts += &generate_shader_bytes_rust(stage, &blobs, !permutations.is_empty());
+		}
 
-				shader_ir_consts += &format!(
-					"pub const PS_BYTES: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\"));\n",
-					out.file_name().unwrap().to_str().unwrap()
-				);
-			}
+		if let Some([x, y, z]) = compiled[superset].1.cs_local_size {
+			shader_ir_consts += &format!("pub const CS_LOCAL_SIZE: [u32; 3] = [{}, {}, {}];\n", x, y, z);
+		}
 
-			if let Some(ref cs) = compiled_shaders.cs {
-				let bytes = cs.iter().flat_map(|code| code.to_ne_bytes()).collect::<Vec<_>>();
+		let specialization_constants = parse_specialization_constants(&asset_path, &src)?;
 
-				let out = out_dir.join(asset_path.file_name().unwrap()).with_extension("cs");
-				std::fs::write(&out, bytes).map_err(move |err| BuildError::Filesystem(err))?;
+		let included_sets = included_sets(&includes, descriptor_layouts, resolver)?;
 
-				shader_ir_consts += &format!(
-					"pub const CS_BYTES: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\"));\n",
-					out.file_name().unwrap().to_str().unwrap()
+		let mut descriptor_decls: Vec<String> = Default::default();
+		let mut cbuffer_decls: Vec<Struct> = Default::default();
+		let mut structured_buffer_decls: Vec<Struct> = Default::default();
+
+		for (set, bindings) in descriptors {
+			if let Some(include_module) = included_sets.get(&set) {
+				descriptor_decls.push(generate_included_descriptor_rust(set, include_module));
+			} else {
+				cbuffer_decls.append(
+					&mut bindings
+						.iter()
+						.flat_map(|(_, info)| match info {
+							DescriptorBinding::CBuffer { struct_info, .. } => Some(struct_info.clone()),
+							_ => None,
+						})
+						.collect(),
 				);
+				structured_buffer_decls.append(
+					&mut bindings
+						.iter()
+						.flat_map(|(_, info)| match info {
+							DescriptorBinding::RWStructuredBuffer { struct_info, .. } => Some(struct_info.clone()),
+							DescriptorBinding::StructuredBuffer { struct_info, .. } => Some(struct_info.clone()),
+							_ => None,
+						})
+						.collect(),
+				);
+				descriptor_decls.push(generate_descriptor_rust(set, &bindings));
 			}
+		}
 
-			let descriptors = generate_descriptors(&mut asts);
+		use itertools::Itertools;
+		let cbuffer_decls = cbuffer_decls.into_iter().unique().collect::<Vec<_>>();
 
-			let included_sets = descriptor_layouts
-				.iter()
-				.flat_map(|(include, sets)| {
-					if src.contains(&format!("#include \"{}.hlsli\"", include)) {
-						sets.iter()
-							.map(|(set, _)| (*set, format!("super::{}_inc::Descriptor{}", include, *set)))
-							.collect::<Vec<(u32, String)>>()
-					} else {
-						Default::default()
-					}
-				})
-				.collect::<HashMap<u32, String>>();
-
-			let mut descriptor_decls: Vec<String> = Default::default();
-			let mut cbuffer_decls: Vec<Struct> = Default::default();
-			let mut structured_buffer_decls: Vec<Struct> = Default::default();
-
-			for (set, bindings) in descriptors {
-				if let Some(descriptor_type) = included_sets.get(&set) {
-					descriptor_decls.push(format!("\npub type Descriptor{} = {};\n", set, descriptor_type));
-				} else {
-					cbuffer_decls.append(
-						&mut bindings
-							.iter()
-							.flat_map(|(_, info)| match info {
-								DescriptorBinding::CBuffer { struct_info, .. } => Some(struct_info.clone()),
-								_ => None,
-							})
-							.collect(),
-					);
-					structured_buffer_decls.append(
-						&mut bindings
-							.iter()
-							.flat_map(|(_, info)| match info {
-								DescriptorBinding::RWStructuredBuffer { struct_info, .. } => Some(struct_info.clone()),
-								DescriptorBinding::StructuredBuffer { struct_info, .. } => Some(struct_info.clone()),
-								_ => None,
-							})
-							.collect(),
-					);
-					descriptor_decls.push(generate_descriptor_rust(set, &bindings));
-				}
-			}
-
-			use itertools::Itertools;
-			let cbuffer_decls = cbuffer_decls.into_iter().unique().collect::<Vec<_>>();
+		let push_constant_decl = match push_constants {
+			Some(ref struct_info) => generate_push_constant_rust(struct_info, cbuffer_decls.contains(struct_info) || structured_buffer_decls.contains(struct_info)),
+			None => String::default(),
+		};
 
-			generated += &format!(
-				"
+		generated += &format!(
+			"
 pub mod {} {{
 {}
 {}
+{}
 
+{}
+{}
 {}
 {}
 }}
 ",
-				asset_path.file_stem().unwrap().to_str().unwrap(),
-				&shader_ir_consts,
-				descriptor_decls.join(""),
-				cbuffer_decls.iter().map(|struct_info| generate_cbuffer_rust(struct_info)).collect::<String>(),
-				structured_buffer_decls.iter().map(|struct_info| generate_structured_buffer_rust(struct_info)).collect::<String>(),
-			);
-		}
+			module,
+			&shader_ir_consts,
+			generate_specialization_rust(&specialization_constants),
+			descriptor_decls.join(""),
+			generate_nested_structs_rust(cbuffer_decls.iter().chain(structured_buffer_decls.iter()).chain(push_constants.iter())),
+			cbuffer_decls.iter().map(|struct_info| generate_cbuffer_rust(struct_info)).collect::<String>(),
+			structured_buffer_decls.iter().map(|struct_info| generate_structured_buffer_rust(struct_info)).collect::<String>(),
+			push_constant_decl,
+		);
 	}
 	Ok(generated)
 }
@@ -663,8 +1480,19 @@ pub mod {} {{
 fn main() {
 	let out_dir = &env::var_os("OUT_DIR").unwrap();
 	println!("cargo:warning=Running build script, output dir {}", out_dir.to_str().unwrap());
+	println!("cargo:rerun-if-changed=build.rs");
+	println!("cargo:rerun-if-changed={}", SHADERS_DIR);
+	println!("cargo:rerun-if-env-changed={}", SHADER_INCLUDE_DIRS_VAR);
+
+	let resolver = IncludeResolver::from_env(Path::new(SHADERS_DIR));
 
-	match parse_shader_includes(&Path::new(SHADERS_DIR)) {
+	let mut include_paths = HashMap::new();
+	let mut shader_paths = HashMap::new();
+	if let Err(err) = collect_shaders(Path::new(SHADERS_DIR), SHADER_INC, &mut include_paths).and_then(|_| collect_shaders(Path::new(SHADERS_DIR), SHADER_EXT, &mut shader_paths)) {
+		panic!("Failed to find shaders! {}", err);
+	}
+
+	match parse_shader_includes(&include_paths, &resolver) {
 		Err(err) => panic!("Failed to parse shader includes! {}", err),
 		Ok(descriptor_layouts) => {
 			let cbuffer_decls = descriptor_layouts
@@ -696,23 +1524,38 @@ fn main() {
 
 			let includes_generated = descriptor_layouts
 				.iter()
-				.map(|(module, sets)| {
-					format!(
+				.map(|(module, sets)| -> Result<String, BuildError> {
+					let includes = resolver.find_includes(&include_paths[module])?;
+					let included_sets = included_sets(&includes, &descriptor_layouts, &resolver)?;
+
+					Ok(format!(
 						"
 pub mod {}_inc {{
 {}
 {}
 {}
+{}
 }}",
 						module,
-						sets.iter().map(|(set, bindings)| generate_descriptor_rust(*set, bindings)).collect::<String>(),
+						sets.iter()
+							.map(|(set, bindings)| match included_sets.get(set) {
+								Some(include_module) => generate_included_descriptor_rust(*set, include_module),
+								None => generate_descriptor_rust(*set, bindings),
+							})
+							.collect::<String>(),
+						generate_nested_structs_rust(cbuffer_decls.iter().chain(structured_buffer_decls.iter()).copied()),
 						cbuffer_decls.iter().map(|struct_info| generate_cbuffer_rust(struct_info)).collect::<String>(),
 						structured_buffer_decls.iter().map(|struct_info| generate_structured_buffer_rust(struct_info)).collect::<String>(),
-					)
+					))
 				})
-				.collect::<String>();
+				.collect::<Result<String, BuildError>>();
+
+			let includes_generated = match includes_generated {
+				Err(err) => panic!("Failed to resolve shader includes! {}", err),
+				Ok(includes_generated) => includes_generated,
+			};
 
-			match compile_shaders(Path::new(&out_dir), Path::new(SHADERS_DIR), &descriptor_layouts) {
+			match compile_shaders(Path::new(&out_dir), &shader_paths, &descriptor_layouts, &resolver) {
 				Err(err) => panic!("Failed to compile shaders! {}", err),
 				Ok(generated) => {
 					println!("cargo:warning=Successfully compiled shaders!");
@@ -723,4 +1566,12 @@ pub mod {}_inc {{
 			}
 		}
 	}
+
+	// Every shader and everything it includes went through find_includes.
+	for (path, includes) in resolver.dependencies() {
+		println!("cargo:rerun-if-changed={}", path.to_str().unwrap());
+		for include in includes {
+			println!("cargo:rerun-if-changed={}", include.to_str().unwrap());
+		}
+	}
 }
@@ -0,0 +1,43 @@
+#include "common.hlsli"
+
+#define MATERIAL_GRID_SIZE 8
+#define MATERIAL_SPACING 0.25
+#define MATERIAL_SCALE 0.1
+#define MATERIAL_DISTANCE 3.0
+
+struct MaterialConstants
+{
+	uint material_index;
+};
+
+[[vk::push_constant]] MaterialConstants c_material;
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 uv : TEXCOORD0;
+};
+
+// Unbounded, so this only works on devices with descriptor indexing. Every material's texture lives in this one set.
+[[vk::binding(0,1)]] Texture2D<float4> t_materials[] : register(t0, space1);
+[[vk::binding(1,1)]] SamplerState s_materials : register(s0, space1);
+
+// Each material gets its own cell in a grid in front of where the camera starts.
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	float2 cell = float2(c_material.material_index % MATERIAL_GRID_SIZE, c_material.material_index / MATERIAL_GRID_SIZE);
+	float2 offset = (cell - (MATERIAL_GRID_SIZE - 1) * 0.5) * MATERIAL_SPACING;
+	float3 position = input.position * MATERIAL_SCALE + float3(offset, MATERIAL_DISTANCE);
+
+	result.position = mul(c_camera.view_proj, float4(position, 1.0));
+	result.uv = input.uv;
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	return t_materials[c_material.material_index].Sample(s_materials, input.uv);
+}
@@ -0,0 +1,35 @@
+// Never dispatched, only here so the tests in lib.rs can check the generated as_buffer against a layout worked out by hand.
+
+struct Light
+{
+	float3 pos;
+	float radius;
+};
+
+struct LayoutTestConstants
+{
+	// Each element takes up a whole 16 byte register.
+	float weights[4];
+	Light lights[8];
+	uint count;
+};
+
+[[vk::binding(0,0)]] ConstantBuffer<LayoutTestConstants> c_layout_test : register(b0);
+[[vk::binding(1,0)]] RWTexture2D<float4> rw_t_output : register(u0);
+
+[numthreads(1, 1, 1)]
+void cs_main(uint3 id : SV_DispatchThreadID)
+{
+	float4 sum = 0;
+	for (uint i = 0; i < 4; i++)
+	{
+		sum.x += c_layout_test.weights[i];
+	}
+
+	for (uint j = 0; j < c_layout_test.count; j++)
+	{
+		sum.xyz += c_layout_test.lights[j].pos * c_layout_test.lights[j].radius;
+	}
+
+	rw_t_output[id.xy] = sum;
+}
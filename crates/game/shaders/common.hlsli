@@ -14,6 +14,18 @@ struct VSInput
     float3 bitangent: BINORMAL0;
 };
 
+// Matches ExtendedVertex, for meshes with vertex colors or a second uv set. The first five inputs are the same as VSInput.
+struct ExtendedVSInput
+{
+    float3 position : POSITION0;
+    float3 normal : NORMAL0;
+    float2 uv : TEXCOORD0;
+    float3 tangent: TANGENT0;
+    float3 bitangent: BINORMAL0;
+    float4 color : COLOR0;
+    float2 uv1 : TEXCOORD1;
+};
+
 struct Camera
 {
 	float3 position;
@@ -0,0 +1,31 @@
+#include "common.hlsli"
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float3 normal : NORMAL0;
+};
+
+struct Instance
+{
+	float4x4 matrix;
+};
+
+// One per cube in the registry, relative to the main cube.
+[[vk::binding(0,1)]] StructuredBuffer<Instance> s_instances : register(t0, space1);
+
+PSInput vs_main(VSInput input, uint instance_id : SV_InstanceID)
+{
+	PSInput result;
+
+	float4x4 model = mul(c_model.matrix, s_instances[instance_id].matrix);
+	result.position = mul(c_camera.view_proj, mul(model, float4(input.position, 1.0)));
+	result.normal = normalize(mul((float3x3)model, input.normal));
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	return float4(input.normal * 0.5 + 0.5, 1.0);
+}
@@ -0,0 +1,46 @@
+#include "common.hlsli"
+
+#define NORMAL_LENGTH 0.1
+
+struct GSInput
+{
+	float3 position : POSITION;
+	float3 normal : NORMAL;
+};
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+};
+
+GSInput vs_main(VSInput input)
+{
+	GSInput result;
+
+	result.position = mul(c_model.matrix, float4(input.position, 1.0)).xyz;
+	result.normal = normalize(mul((float3x3)c_model.matrix, input.normal));
+
+	return result;
+}
+
+[maxvertexcount(6)]
+void gs_main(triangle GSInput input[3], inout LineStream<PSInput> output)
+{
+	for (uint i = 0; i < 3; i++)
+	{
+		PSInput start;
+		start.position = mul(c_camera.view_proj, float4(input[i].position, 1.0));
+		output.Append(start);
+
+		PSInput end;
+		end.position = mul(c_camera.view_proj, float4(input[i].position + input[i].normal * NORMAL_LENGTH, 1.0));
+		output.Append(end);
+
+		output.RestartStrip();
+	}
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	return float4(1.0, 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,34 @@
+[[vk::binding(0,0)]] Texture2D<float4> t_font : register(t0);
+[[vk::binding(1,0)]] SamplerState s_font : register(s0);
+
+struct VSInput
+{
+	[[vk::location(0)]] float2 position : POSITION0;
+	[[vk::location(1)]] float2 uv : TEXCOORD0;
+	[[vk::location(2)]] float4 color : COLOR0;
+};
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 uv : TEXCOORD0;
+	float4 color : COLOR0;
+};
+
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	result.position = float4(input.position, 0.0f, 1.0f);
+	result.uv = input.uv;
+	result.color = input.color;
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	// The font atlas is single channel coverage.
+	float coverage = t_font.Sample(s_font, input.uv).r;
+	return float4(input.color.rgb, input.color.a * coverage);
+}
@@ -0,0 +1,65 @@
+#include "common.hlsli"
+
+// Reference forward+ lighting. Only walks the lights the cull put in the pixel's tile, everything is lit in view space since that's what
+// the LightManager uploads the lights in.
+#define AMBIENT 0.05f
+#define ALBEDO 0.8f
+
+// Has to match the tile size the lights were culled with.
+[[vk::constant_id(0)]] const uint TILE_SIZE = 16;
+
+[[vk::binding(2,0)]] StructuredBuffer<TileLights> s_tile_lights : register(t0);
+[[vk::binding(3,0)]] StructuredBuffer<PointLight> s_point_lights : register(t1);
+
+// Same as the fullscreen pass', the lights were culled at render_size but this might be drawn at the output size.
+struct ForwardPlusConstants
+{
+	float2 render_scale;
+	uint2 render_size;
+	float2 render_offset;
+};
+
+[[vk::push_constant]] ForwardPlusConstants c_forward_plus;
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float3 view_position : POSITION0;
+	float3 view_normal : NORMAL0;
+};
+
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	float4 world_position = mul(c_model.matrix, float4(input.position, 1.0));
+	result.position = mul(c_camera.view_proj, world_position);
+	result.view_position = mul(c_camera.view, world_position).xyz;
+	result.view_normal = mul((float3x3)c_camera.view, mul((float3x3)c_model.matrix, input.normal));
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	uint tiles_x = (c_forward_plus.render_size.x + TILE_SIZE - 1) / TILE_SIZE;
+	uint2 render_position = min(uint2((input.position.xy - c_forward_plus.render_offset) * c_forward_plus.render_scale), c_forward_plus.render_size - 1);
+	uint2 tile = render_position / TILE_SIZE;
+	TileLights tile_lights = s_tile_lights[tile.y * tiles_x + tile.x];
+
+	float3 normal = normalize(input.view_normal);
+	float3 color = AMBIENT;
+	uint light_count = min(tile_lights.light_count, MAX_LIGHTS_PER_TILE);
+	for (uint i = 0; i < light_count; i++)
+	{
+		PointLight light = s_point_lights[tile_lights.light_indices[i]];
+		float3 to_light = light.position - input.view_position;
+		float distance = length(to_light);
+
+		// Falls off to exactly nothing at the radius, otherwise the tile edges would show wherever a light got culled.
+		float falloff = saturate(1.0f - distance / light.radius);
+		color += light.color.rgb * saturate(dot(normal, to_light / distance)) * falloff * falloff;
+	}
+
+	return float4(color * ALBEDO, 1.0);
+}
@@ -1,5 +1,24 @@
+#include "utils.hlsli"
+
+// Has to match the tile size the lights were culled with.
+[[vk::constant_id(0)]] const uint TILE_SIZE = 16;
+
 [[vk::binding(0,0)]] Texture2D<float4> t_input : register(t0);
 [[vk::binding(1,0)]] SamplerState s_input : register(s0);
+[[vk::binding(2,0)]] StructuredBuffer<TileLights> s_tile_lights : register(t1);
+[[vk::binding(3,0)]] StructuredBuffer<PointLight> s_point_lights : register(t2);
+
+// The scene is rendered at a lower resolution than the output when the GPU is over budget, the input is always exactly render_size.
+struct FullscreenConstants
+{
+	// Render size over output size, takes output pixels to the render pixels the lights were culled for.
+	float2 render_scale;
+	uint2 render_size;
+	// Where the output policy's viewport starts, SV_Position is relative to the whole output rather than the viewport.
+	float2 render_offset;
+};
+
+[[vk::push_constant]] FullscreenConstants c_fullscreen;
 
 struct PSInput
 {
@@ -19,6 +38,21 @@ PSInput vs_main(uint vert_id : SV_VertexID)
 
 float4 ps_main (PSInput input) : SV_TARGET
 {
-	return t_input.Sample(s_input, input.uv);
+	float4 color = t_input.Sample(s_input, input.uv);
+
+	uint tiles_x = (c_fullscreen.render_size.x + TILE_SIZE - 1) / TILE_SIZE;
+	uint2 render_position = min(uint2((input.position.xy - c_fullscreen.render_offset) * c_fullscreen.render_scale), c_fullscreen.render_size - 1);
+	uint2 tile = render_position / TILE_SIZE;
+	TileLights tile_lights = s_tile_lights[tile.y * tiles_x + tile.x];
+
+	// Tint each tile by the lights that were culled into it, so it's easy to see which lights made it into which tiles.
+	float3 light_tint = float3(0.0f, 0.0f, 0.0f);
+	uint light_count = min(tile_lights.light_count, MAX_LIGHTS_PER_TILE);
+	for (uint i = 0; i < light_count; i++)
+	{
+		light_tint += s_point_lights[tile_lights.light_indices[i]].color.rgb;
+	}
+
+	return float4(color.rgb + light_tint * 0.05f, color.a);
 }
 
@@ -0,0 +1,35 @@
+// For the engine's FullscreenBlit, which picks the filter with this.
+[[vk::constant_id(0)]] const bool LINEAR_FILTER = true;
+
+[[vk::binding(0,0)]] Texture2D<float4> t_source : register(t0);
+[[vk::binding(1,0)]] SamplerState s_source : register(s0);
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 uv : TEXCOORD0;
+};
+
+PSInput vs_main(uint vert_id : SV_VertexID)
+{
+	PSInput result;
+
+	result.uv = float2((vert_id << 1) & 2, vert_id & 2);
+	result.position = float4(result.uv.x * 2.0f - 1.0f, result.uv.y * -2.0f + 1.0f, 0.0f, 1.0f);
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	if (LINEAR_FILTER)
+	{
+		return t_source.Sample(s_source, input.uv);
+	}
+
+	// Attachment samplers always filter linearly, so nearest loads whichever texel the pixel's center lands in instead.
+	uint width, height;
+	t_source.GetDimensions(width, height);
+	uint2 texel = min(uint2(input.uv * float2(width, height)), uint2(width, height) - 1);
+	return t_source.Load(int3(texel, 0));
+}
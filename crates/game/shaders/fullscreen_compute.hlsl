@@ -0,0 +1,53 @@
+// Does exactly what the pixel shader in fullscreen.hlsl does, but writes straight into the swapchain image instead of drawing a triangle.
+#include "utils.hlsli"
+
+#define LOCAL_SIZE 8
+
+// Has to match the tile size the lights were culled with.
+[[vk::constant_id(0)]] const uint TILE_SIZE = 16;
+
+// Same as the push constants in fullscreen.hlsl, compute pipelines don't have any so these go in a constant buffer.
+struct FullscreenConstants
+{
+	// Render size over output size, takes output pixels to the render pixels the lights were culled for.
+	float2 render_scale;
+	uint2 render_size;
+};
+
+[[vk::binding(0,0)]] Texture2D<float4> t_input : register(t0);
+[[vk::binding(1,0)]] SamplerState s_input : register(s0);
+[[vk::binding(2,0)]] StructuredBuffer<TileLights> s_tile_lights : register(t1);
+[[vk::binding(3,0)]] StructuredBuffer<PointLight> s_point_lights : register(t2);
+[[vk::binding(4,0)]] ConstantBuffer<FullscreenConstants> c_fullscreen : register(b0);
+// The swapchain image, its format is only known at runtime.
+[[vk::binding(5,0)]] RWTexture2D<float4> rw_t_output : register(u0);
+
+[numthreads(LOCAL_SIZE, LOCAL_SIZE, 1)]
+void cs_main(uint3 id : SV_DispatchThreadID)
+{
+	uint2 output_size;
+	rw_t_output.GetDimensions(output_size.x, output_size.y);
+	if (any(id.xy >= output_size))
+	{
+		return;
+	}
+
+	// The pixel shader runs at pixel centers, so sample there to get the exact same image. There's only the one mip to sample.
+	float2 position = float2(id.xy) + 0.5f;
+	float4 color = t_input.SampleLevel(s_input, position / float2(output_size), 0);
+
+	uint tiles_x = (c_fullscreen.render_size.x + TILE_SIZE - 1) / TILE_SIZE;
+	uint2 render_position = min(uint2(position * c_fullscreen.render_scale), c_fullscreen.render_size - 1);
+	uint2 tile = render_position / TILE_SIZE;
+	TileLights tile_lights = s_tile_lights[tile.y * tiles_x + tile.x];
+
+	// Tint each tile by the lights that were culled into it, so it's easy to see which lights made it into which tiles.
+	float3 light_tint = float3(0.0f, 0.0f, 0.0f);
+	uint light_count = min(tile_lights.light_count, MAX_LIGHTS_PER_TILE);
+	for (uint i = 0; i < light_count; i++)
+	{
+		light_tint += s_point_lights[tile_lights.light_indices[i]].color.rgb;
+	}
+
+	rw_t_output[id.xy] = float4(color.rgb + light_tint * 0.05f, color.a);
+}
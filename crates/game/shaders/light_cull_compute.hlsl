@@ -1,17 +1,12 @@
 #include "utils.hlsli"
-#ifndef TILE_SIZE
-#define TILE_SIZE 16
-#endif
 
-struct VisibleLightIndex
-{
-	int index;
-};
+// numthreads can't be specialized, so we always launch MAX_TILE_SIZE x MAX_TILE_SIZE threads and the ones outside of the tile just sit idle.
+#define MAX_TILE_SIZE 16
+[[vk::constant_id(0)]] const uint TILE_SIZE = 16;
 
 struct CullInfo
 {
 	float4x4 inverse_proj;
-	float4x4 view;
 	uint2 screen_size;
 	float z_near;
 	uint light_count;
@@ -23,11 +18,13 @@ struct Frustum
 	float3 planes[4];
 };
 
+// Already in view space, see LightManager.
 [[vk::binding(0,0)]] StructuredBuffer<PointLight> s_point_lights : register(t0);
 [[vk::binding(1,0)]] ConstantBuffer<CullInfo> c_cull_info : register(b0);
 [[vk::binding(2,0)]] Texture2D<float> t_depth_prepass : register(t1);
 [[vk::binding(3,0)]] RWTexture2D<float4> rw_t_heatmap : register(u0);
-// [[vk::binding(3,0)]] RWStructuredBuffer<VisibleLightIndex> rw_sb_visible_light_indices : register(u1);
+// The counters in here are expected to be zeroed before the dispatch.
+[[vk::binding(4,0)]] RWStructuredBuffer<TileLights> rw_sb_tile_lights : register(u1);
 
 // Atomic min and max depths that are computed in parallel.
 groupshared uint gs_min_depth;
@@ -130,11 +127,8 @@ Frustum compute_tile_frustum(uint2 global_invocation_id)
 	return result;
 }
 
-bool sphere_inside_frustum(float3 center, float radius, float z_nearest, float z_furthest)
+bool sphere_inside_frustum(float3 position, float radius, float z_nearest, float z_furthest)
 {
-		float4 view_space = mul(c_cull_info.view, float4(center, 1.0f));
-		float3 position = view_space.xyz / view_space.w;
-
 		if (position.z - radius > z_furthest || position.z + radius < z_nearest)
 		{
 			return false;
@@ -154,9 +148,11 @@ bool sphere_inside_frustum(float3 center, float radius, float z_nearest, float z
 		return true;
 }
 
-[numthreads(TILE_SIZE, TILE_SIZE, 1)]
-void cs_main(uint3 global_invocation_id : SV_DispatchThreadID, uint3 local_invocation_id : SV_GroupThreadID, uint local_invocation_index : SV_GroupIndex)
+[numthreads(MAX_TILE_SIZE, MAX_TILE_SIZE, 1)]
+void cs_main(uint3 group_id : SV_GroupID, uint3 local_invocation_id : SV_GroupThreadID, uint local_invocation_index : SV_GroupIndex)
 {
+	bool in_tile = all(local_invocation_id.xy < TILE_SIZE);
+
 	if (local_invocation_index == 0)
 	{
 		gs_min_depth = 0xFFFFFFFF;
@@ -165,14 +161,17 @@ void cs_main(uint3 global_invocation_id : SV_DispatchThreadID, uint3 local_invoc
 	}
 
 	GroupMemoryBarrierWithGroupSync();
-	uint2 location = uint2(global_invocation_id.xy);
+	uint2 location = group_id.xy * TILE_SIZE + local_invocation_id.xy;
 
-	float clip_depth = t_depth_prepass[location];
-	float view_depth = c_cull_info.z_near / clip_depth;
-	uint depth_int = asuint(view_depth);
+	if (in_tile)
+	{
+		float clip_depth = t_depth_prepass[location];
+		float view_depth = c_cull_info.z_near / clip_depth;
+		uint depth_int = asuint(view_depth);
 
-	InterlockedMin(gs_min_depth, depth_int);
-	InterlockedMax(gs_max_depth, depth_int);
+		InterlockedMin(gs_min_depth, depth_int);
+		InterlockedMax(gs_max_depth, depth_int);
+	}
 
 	GroupMemoryBarrierWithGroupSync();
 
@@ -186,6 +185,9 @@ void cs_main(uint3 global_invocation_id : SV_DispatchThreadID, uint3 local_invoc
 	float z_nearest = asfloat(gs_min_depth);
 	float z_furthest = asfloat(gs_max_depth);
 
+	uint tiles_x = (c_cull_info.screen_size.x + TILE_SIZE - 1) / TILE_SIZE;
+	uint tile_index = group_id.y * tiles_x + group_id.x;
+
 	const uint THREAD_COUNT = TILE_SIZE * TILE_SIZE;
 	uint light_index = local_invocation_index;
 	if (light_index < c_cull_info.light_count)
@@ -193,12 +195,24 @@ void cs_main(uint3 global_invocation_id : SV_DispatchThreadID, uint3 local_invoc
 		if (sphere_inside_frustum(s_point_lights[light_index].position, s_point_lights[light_index].radius, z_nearest, z_furthest))
 		{
 			InterlockedAdd(gs_visible_light_count, 1);
+
+			uint slot;
+			InterlockedAdd(rw_sb_tile_lights[tile_index].light_count, 1, slot);
+			if (slot < MAX_LIGHTS_PER_TILE)
+			{
+				rw_sb_tile_lights[tile_index].light_indices[slot] = light_index;
+			}
 		}
 	}
 
 	GroupMemoryBarrierWithGroupSync();
 
 
+	if (!in_tile)
+	{
+		return;
+	}
+
 	rw_t_heatmap[location] = float4(0.0f, 1.0f - z_furthest * 0.01f, float(gs_visible_light_count) / float(c_cull_info.light_count), 1.0f);
 
 }
@@ -0,0 +1,49 @@
+#ifndef PARTICLES
+#define PARTICLES
+
+// Everything in here has to match the layouts in goldfish's renderer/particles.rs, which writes them from the CPU.
+
+struct Particle
+{
+	float3 position;
+	float age;
+	float3 velocity;
+	// Dead once age reaches this, which is also what the zeroed buffer starts out as.
+	float lifetime;
+	float4 color;
+	float size;
+	float3 padding;
+};
+
+struct ParticleFrame
+{
+	float4x4 view;
+	float4x4 proj;
+	// xyz is the gravity, w the time step.
+	float4 gravity_dt;
+	// Emitter count, particles to emit, max particles and a random seed.
+	uint4 counts;
+};
+
+struct Emitter
+{
+	// xyz is the position, w how long its particles live.
+	float4 position_lifetime;
+	// xyz is the velocity, w how far off of it each particle's velocity is randomly pushed.
+	float4 velocity_spread;
+	float4 color;
+	float size;
+	uint count;
+	// How many particles the emitters before this one are emitting.
+	uint first;
+	uint padding;
+};
+
+// Indices into the counter buffer, the indirect draw arguments start at COUNTER_VERTEX_COUNT.
+#define COUNTER_EMIT_CURSOR 0
+#define COUNTER_VERTEX_COUNT 4
+#define COUNTER_INSTANCE_COUNT 5
+
+[[vk::binding(0,0)]] ConstantBuffer<ParticleFrame> c_frame : register(b0);
+
+#endif
@@ -0,0 +1,44 @@
+#include "particles.hlsli"
+
+[[vk::binding(1,0)]] StructuredBuffer<Particle> s_particles : register(t0);
+[[vk::binding(2,0)]] StructuredBuffer<uint> s_draw_list : register(t1);
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 uv : TEXCOORD0;
+	float4 color : COLOR0;
+};
+
+static const float2 CORNERS[6] = {
+	float2(-1.0, -1.0),
+	float2(-1.0, 1.0),
+	float2(1.0, -1.0),
+	float2(1.0, -1.0),
+	float2(-1.0, 1.0),
+	float2(1.0, 1.0),
+};
+
+// One camera facing quad per instance, expanded in view space.
+PSInput vs_main(uint vertex_id : SV_VertexID, uint instance_id : SV_InstanceID)
+{
+	Particle particle = s_particles[s_draw_list[instance_id]];
+	float2 corner = CORNERS[vertex_id];
+
+	float4 view_position = mul(c_frame.view, float4(particle.position, 1.0));
+	view_position.xy += corner * particle.size;
+
+	PSInput result;
+	result.position = mul(c_frame.proj, view_position);
+	result.uv = corner;
+	result.color = particle.color;
+	result.color.a *= 1.0 - particle.age / particle.lifetime;
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	float falloff = saturate(1.0 - length(input.uv));
+	return float4(input.color.rgb, input.color.a * falloff);
+}
@@ -0,0 +1,60 @@
+#include "particles.hlsli"
+
+[[vk::binding(1,0)]] StructuredBuffer<Emitter> s_emitters : register(t0);
+[[vk::binding(2,0)]] RWStructuredBuffer<Particle> rw_sb_particles : register(u0);
+[[vk::binding(3,0)]] RWStructuredBuffer<uint> rw_sb_counters : register(u1);
+
+uint hash(uint x)
+{
+	// PCG
+	uint state = x * 747796405u + 2891336453u;
+	uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+	return (word >> 22u) ^ word;
+}
+
+float random(inout uint seed)
+{
+	seed = hash(seed);
+	return float(seed) / 4294967295.0;
+}
+
+[numthreads(64, 1, 1)]
+void cs_main(uint3 id : SV_DispatchThreadID)
+{
+	// The update counts the living particles into the draw arguments from scratch every frame.
+	if (id.x == 0)
+	{
+		rw_sb_counters[COUNTER_INSTANCE_COUNT] = 0;
+	}
+
+	if (id.x >= c_frame.counts.y)
+	{
+		return;
+	}
+
+	uint emitter_index = 0;
+	while (emitter_index + 1 < c_frame.counts.x && id.x >= s_emitters[emitter_index].first + s_emitters[emitter_index].count)
+	{
+		emitter_index++;
+	}
+	Emitter emitter = s_emitters[emitter_index];
+
+	// Slots are handed out round robin, so once the buffer is full the oldest particles get replaced whether they're dead or not.
+	uint cursor;
+	InterlockedAdd(rw_sb_counters[COUNTER_EMIT_CURSOR], 1, cursor);
+	uint index = cursor % c_frame.counts.z;
+
+	uint seed = hash(id.x ^ c_frame.counts.w);
+	float3 jitter = float3(random(seed), random(seed), random(seed)) * 2.0 - 1.0;
+
+	Particle particle;
+	particle.position = emitter.position_lifetime.xyz;
+	particle.age = 0.0;
+	particle.velocity = emitter.velocity_spread.xyz + jitter * emitter.velocity_spread.w;
+	particle.lifetime = emitter.position_lifetime.w * (0.5 + random(seed) * 0.5);
+	particle.color = emitter.color;
+	particle.size = emitter.size;
+	particle.padding = float3(0.0, 0.0, 0.0);
+
+	rw_sb_particles[index] = particle;
+}
@@ -0,0 +1,36 @@
+#include "particles.hlsli"
+
+[[vk::binding(1,0)]] RWStructuredBuffer<Particle> rw_sb_particles : register(u0);
+// Indices of the particles that are still alive after this frame's update, one per instance of the indirect draw.
+[[vk::binding(2,0)]] RWStructuredBuffer<uint> rw_sb_draw_list : register(u1);
+[[vk::binding(3,0)]] RWStructuredBuffer<uint> rw_sb_counters : register(u2);
+
+[numthreads(64, 1, 1)]
+void cs_main(uint3 id : SV_DispatchThreadID)
+{
+	if (id.x >= c_frame.counts.z)
+	{
+		return;
+	}
+
+	Particle particle = rw_sb_particles[id.x];
+	if (particle.age >= particle.lifetime)
+	{
+		return;
+	}
+
+	float dt = c_frame.gravity_dt.w;
+	particle.age += dt;
+	particle.velocity += c_frame.gravity_dt.xyz * dt;
+	particle.position += particle.velocity * dt;
+	rw_sb_particles[id.x] = particle;
+
+	if (particle.age >= particle.lifetime)
+	{
+		return;
+	}
+
+	uint slot;
+	InterlockedAdd(rw_sb_counters[COUNTER_INSTANCE_COUNT], 1, slot);
+	rw_sb_draw_list[slot] = id.x;
+}
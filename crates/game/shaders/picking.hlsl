@@ -0,0 +1,28 @@
+#include "common.hlsli"
+
+struct PickingConstants
+{
+	uint object_id;
+};
+
+[[vk::push_constant]] PickingConstants c_picking;
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+};
+
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	result.position = mul(c_camera.view_proj, mul(c_model.matrix, float4(input.position, 1.0)));
+
+	return result;
+}
+
+// Written to an R32UInt attachment that's cleared to 0, so 0 means nothing was hit.
+uint ps_main(PSInput input) : SV_TARGET
+{
+	return c_picking.object_id;
+}
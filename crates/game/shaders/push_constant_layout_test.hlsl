@@ -0,0 +1,17 @@
+// Never dispatched, only here so the tests in lib.rs can check the generated push constant struct against a layout worked out by hand.
+
+struct PushLayoutTestConstants
+{
+	float4x4 model;
+	// Packed right after the matrix, push constants don't round the block up to 16 bytes.
+	uint index;
+};
+
+[[vk::push_constant]] PushLayoutTestConstants c_push_layout_test;
+[[vk::binding(0,0)]] RWTexture2D<float4> rw_t_output : register(u0);
+
+[numthreads(1, 1, 1)]
+void cs_main(uint3 id : SV_DispatchThreadID)
+{
+	rw_t_output[id.xy] = mul(c_push_layout_test.model, float4(id.xy, c_push_layout_test.index, 1.0));
+}
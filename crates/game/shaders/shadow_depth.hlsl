@@ -0,0 +1,26 @@
+#include "common.hlsli"
+#include "shadows.hlsli"
+
+struct ShadowConstants
+{
+	uint light_index;
+};
+
+[[vk::push_constant]] ShadowConstants c_shadow;
+
+[[vk::binding(0,1)]] StructuredBuffer<ShadowLight> s_shadow_lights : register(t0, space1);
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+};
+
+// Depth only, rendered once per light with the viewport set to that light's region of the atlas.
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	result.position = mul(s_shadow_lights[c_shadow.light_index].view_proj, mul(c_model.matrix, float4(input.position, 1.0)));
+
+	return result;
+}
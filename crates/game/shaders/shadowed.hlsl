@@ -0,0 +1,56 @@
+#include "common.hlsli"
+#include "shadows.hlsli"
+
+#define AMBIENT 0.1
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float3 world_position : POSITION0;
+	float3 normal : NORMAL0;
+};
+
+struct Instance
+{
+	float4x4 matrix;
+};
+
+// Same instances as cube_field.hlsl, relative to the main cube.
+[[vk::binding(0,1)]] StructuredBuffer<Instance> s_instances : register(t0, space1);
+
+[[vk::binding(0,2)]] StructuredBuffer<ShadowLight> s_shadow_lights : register(t1, space2);
+[[vk::binding(1,2)]] Texture2D<float> t_shadow_atlas : register(t2, space2);
+[[vk::binding(2,2)]] SamplerState s_shadow_atlas : register(s0, space2);
+
+PSInput vs_main(VSInput input, uint instance_id : SV_InstanceID)
+{
+	PSInput result;
+
+	float4x4 model = mul(c_model.matrix, s_instances[instance_id].matrix);
+	float4 world_position = mul(model, float4(input.position, 1.0));
+	result.position = mul(c_camera.view_proj, world_position);
+	result.world_position = world_position.xyz;
+	result.normal = normalize(mul((float3x3)model, input.normal));
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	uint light_count;
+	uint stride;
+	s_shadow_lights.GetDimensions(light_count, stride);
+
+	float3 normal = normalize(input.normal);
+	float3 color = AMBIENT;
+	for (uint i = 0; i < light_count; i++)
+	{
+		ShadowLight light = s_shadow_lights[i];
+		float3 to_light = normalize(light.position.xyz - input.world_position);
+		float lit = shadow_factor(light, input.world_position, t_shadow_atlas, s_shadow_atlas);
+
+		color += light.color.rgb * saturate(dot(normal, to_light)) * lit;
+	}
+
+	return float4(color, 1.0);
+}
@@ -0,0 +1,40 @@
+#ifndef SHADOWS
+#define SHADOWS
+
+// Keeps surfaces from shadowing themselves, in reverse Z depth.
+#define SHADOW_BIAS 0.0005
+
+// One per light with a region in the shadow atlas, written every frame.
+struct ShadowLight
+{
+	float4x4 view_proj;
+	// xy scales a UV within the light's shadow map and zw offsets it into the light's region of the atlas.
+	float4 uv_scale_offset;
+	float4 position;
+	float4 color;
+};
+
+// 1.0 when lit, 0.0 when something closer to the light is in the way. Anything outside of the light's frustum counts as lit.
+float shadow_factor(ShadowLight light, float3 world_position, Texture2D<float> atlas, SamplerState atlas_sampler)
+{
+	float4 clip = mul(light.view_proj, float4(world_position, 1.0));
+	if (clip.w <= 0.0)
+	{
+		return 1.0;
+	}
+
+	float3 ndc = clip.xyz / clip.w;
+	if (any(abs(ndc.xy) > 1.0))
+	{
+		return 1.0;
+	}
+
+	// The viewport is flipped, so +y in clip space is the top of the light's region.
+	float2 uv = float2(ndc.x, -ndc.y) * 0.5 + 0.5;
+	uv = uv * light.uv_scale_offset.xy + light.uv_scale_offset.zw;
+
+	// Reverse Z, so whatever is closest to the light has the greatest depth.
+	float occluder_depth = atlas.SampleLevel(atlas_sampler, uv, 0).r;
+	return ndc.z + SHADOW_BIAS >= occluder_depth ? 1.0 : 0.0;
+}
+#endif
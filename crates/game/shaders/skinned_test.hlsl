@@ -0,0 +1,23 @@
+#include "skinning.hlsli"
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float3 normal : NORMAL0;
+};
+
+PSInput vs_main(SkinnedVSInput input)
+{
+	PSInput result;
+
+	float4x4 model = mul(c_model.matrix, skinning_matrix(input));
+	result.position = mul(c_camera.view_proj, mul(model, float4(input.position, 1.0)));
+	result.normal = normalize(mul((float3x3)model, input.normal));
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	return float4(input.normal * 0.5 + 0.5, 1.0);
+}
@@ -0,0 +1,37 @@
+#ifndef SKINNING
+#define SKINNING
+#include "common.hlsli"
+
+#define MAX_BONE_INFLUENCES 4
+
+// Matches SkinnedVertex, the first five inputs are the same as VSInput.
+struct SkinnedVSInput
+{
+    float3 position : POSITION0;
+    float3 normal : NORMAL0;
+    float2 uv : TEXCOORD0;
+    float3 tangent: TANGENT0;
+    float3 bitangent: BINORMAL0;
+    uint4 bone_indices : BLENDINDICES0;
+    float4 bone_weights : BLENDWEIGHT0;
+};
+
+struct Bone
+{
+	float4x4 matrix;
+};
+
+// Written by SkinPalette every frame.
+[[vk::binding(0,1)]] StructuredBuffer<Bone> s_bones : register(t0, space1);
+
+float4x4 skinning_matrix(SkinnedVSInput input)
+{
+	float4x4 result = (float4x4)0;
+	for (uint i = 0; i < MAX_BONE_INFLUENCES; i++)
+	{
+		result += s_bones[input.bone_indices[i]].matrix * input.bone_weights[i];
+	}
+
+	return result;
+}
+#endif
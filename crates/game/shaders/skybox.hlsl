@@ -0,0 +1,32 @@
+#include "common.hlsli"
+
+[[vk::binding(2,0)]] TextureCube<float4> t_skybox : register(t0);
+[[vk::binding(3,0)]] SamplerState s_skybox : register(s0);
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 ndc : TEXCOORD0;
+};
+
+// A fullscreen triangle on the far plane, which is a depth of 0 since depth is reversed. Depth tested with GreaterOrEqual it only shows
+// up wherever nothing else was drawn.
+PSInput vs_main(uint vert_id : SV_VertexID)
+{
+	PSInput result;
+
+	float2 uv = float2((vert_id << 1) & 2, vert_id & 2);
+	result.ndc = float2(uv.x * 2.0f - 1.0f, uv.y * -2.0f + 1.0f);
+	result.position = float4(result.ndc, 0.0f, 1.0f);
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	// Back through the projection to a view space direction, then into world space with the transposed (inverse) view rotation.
+	float3 view_dir = float3(input.ndc.x / c_camera.proj[0][0], input.ndc.y / c_camera.proj[1][1], 1.0f);
+	float3 world_dir = mul(transpose((float3x3)c_camera.view), view_dir);
+
+	return t_skybox.Sample(s_skybox, world_dir);
+}
@@ -0,0 +1,30 @@
+#include "common.hlsli"
+
+// Every cube is its own draw, so its model matrix is pushed right before it.
+struct CubeConstants
+{
+	float4x4 model;
+};
+
+[[vk::push_constant]] CubeConstants c_cube;
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float3 normal : NORMAL0;
+};
+
+PSInput vs_main(VSInput input)
+{
+	PSInput result;
+
+	result.position = mul(c_camera.view_proj, mul(c_cube.model, float4(input.position, 1.0)));
+	result.normal = normalize(mul((float3x3)c_cube.model, input.normal));
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+	return float4(input.normal * 0.5 + 0.5, 1.0);
+}
@@ -0,0 +1,28 @@
+[[vk::binding(0,0)]] Texture2D<float4> t_current : register(t0);
+[[vk::binding(1,0)]] SamplerState s_current : register(s0);
+[[vk::binding(2,0)]] Texture2D<float4> t_history : register(t1);
+[[vk::binding(3,0)]] SamplerState s_history : register(s1);
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float2 uv : TEXCOORD0;
+};
+
+PSInput vs_main(uint vert_id : SV_VertexID)
+{
+	PSInput result;
+
+	result.uv = float2((vert_id << 1) & 2, vert_id & 2);
+	result.position = float4(result.uv.x * 2.0f - 1.0f, result.uv.y * -2.0f + 1.0f, 0.0f, 1.0f);
+
+	return result;
+}
+
+// Keeps 90% of whatever was on screen last frame, so anything that moves leaves a trail behind it.
+float4 ps_main (PSInput input) : SV_TARGET
+{
+	float4 current = t_current.Sample(s_current, input.uv);
+	float4 history = t_history.Sample(s_history, input.uv);
+	return lerp(current, history, 0.9f);
+}
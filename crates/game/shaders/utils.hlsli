@@ -7,4 +7,14 @@ struct PointLight
 	float3 position;
 	float radius;
 };
+
+#define MAX_LIGHTS_PER_TILE 31
+
+// One of these per tile, written by the light cull and read by the lighting. light_count keeps counting past MAX_LIGHTS_PER_TILE,
+// so it always has to be clamped before walking light_indices.
+struct TileLights
+{
+	uint light_count;
+	uint light_indices[MAX_LIGHTS_PER_TILE];
+};
 #endif
@@ -0,0 +1,36 @@
+#include "common.hlsli"
+
+// #pragma goldfish permute ALPHA_TEST
+
+// Draws a mesh with nothing but its vertex colors, to check they make it all the way from the MeshPackage to the shader. With ALPHA_TEST
+// anything that's less than half opaque is thrown away.
+struct VertexColorsConstants
+{
+	float4x4 model;
+};
+
+[[vk::push_constant]] VertexColorsConstants c_vertex_colors;
+
+struct PSInput
+{
+	float4 position : SV_POSITION;
+	float4 color : COLOR0;
+};
+
+PSInput vs_main(ExtendedVSInput input)
+{
+	PSInput result;
+
+	result.position = mul(c_camera.view_proj, mul(c_vertex_colors.model, float4(input.position, 1.0)));
+	result.color = input.color;
+
+	return result;
+}
+
+float4 ps_main(PSInput input) : SV_TARGET
+{
+#ifdef ALPHA_TEST
+	clip(input.color.a - 0.5);
+#endif
+	return input.color;
+}
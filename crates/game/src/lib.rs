@@ -1,14 +1,19 @@
 include!(concat!(env!("OUT_DIR"), "/materials.rs"));
 
+use goldfish::audio::{AudioClipHandle, PlayParams};
 use goldfish::build::{CBuffer, StructuredBuffer};
-use goldfish::game::GameLib;
-use goldfish::package::{AssetType, Package};
+use goldfish::camera::{Camera, PerspectiveCamera};
+use goldfish::input::{Key, MouseButton};
+use goldfish::log::{debug, error, info, warn};
+use goldfish::package::{AssetEvent, AssetType, AudioPackage, JointPackage, MeshPackage, Package, SkinPackage};
+use goldfish::registry::Registry;
 use goldfish::renderer;
-use goldfish::GoldfishEngine;
-use goldfish::{Mat4, Quat, UVec2, Vec3, Vec4, Vec4Swizzles};
+use goldfish::skinning::SkinPalette;
+use goldfish::window::{CursorLock, EngineEvent};
+use goldfish::{Color, GoldfishEngine, PhysicalSize, RENDER_GRAPH_DUMP_PATH};
+use goldfish::{Mat4, Quat, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
 use renderer::*;
-use uuid::uuid;
-use winit::event::VirtualKeyCode;
+use std::time::{Duration, Instant};
 
 #[derive(Default, Clone, Copy)]
 struct Transform {
@@ -31,275 +36,639 @@ impl Transform {
 	}
 }
 
-const COMMON_DESC_INFO: &'static DescriptorSetInfo = &DescriptorSetInfo {
-	bindings: phf::phf_map! {
-		0u32 => DescriptorBindingType::CBuffer,
-		1u32 => DescriptorBindingType::CBuffer,
-	},
-};
+trait GpuCamera {
+	fn to_gpu(&self) -> common_inc::Camera;
+}
 
-const SAMPLER_DESC_INFO: &'static DescriptorSetInfo = &DescriptorSetInfo {
-	bindings: phf::phf_map! {
-		0u32 => DescriptorBindingType::Texture2D,
-		1u32 => DescriptorBindingType::SamplerState,
-	},
-};
+struct PlacedCamera<C: Camera> {
+	transform: Transform,
+	camera: C,
+}
 
-const FULLSCREEN_DESC_INFO: &'static DescriptorSetInfo = &DescriptorSetInfo {
-	bindings: phf::phf_map! {
-		0u32 => DescriptorBindingType::Texture2D,
-		1u32 => DescriptorBindingType::SamplerState,
-	},
-};
+impl<C: Camera> GpuCamera for PlacedCamera<C> {
+	fn to_gpu(&self) -> common_inc::Camera {
+		let view = Mat4::look_at_lh(self.transform.position, self.transform.position + self.transform.forward(), Vec3 { x: 0.0, y: 1.0, z: 0.0 });
+		let proj = self.camera.projection();
 
-const DEPTH_DESC_INFO: &'static DescriptorSetInfo = &DescriptorSetInfo {
-	bindings: phf::phf_map! {
-		0u32 => DescriptorBindingType::Texture2D,
-		1u32 => DescriptorBindingType::SamplerState,
-		2u32 => DescriptorBindingType::CBuffer,
-	},
-};
+		common_inc::Camera {
+			position: self.transform.position,
+			view,
+			proj,
+			view_proj: proj * view,
+		}
+	}
+}
 
-const LIGHT_CULL_DESC_INFO: &'static DescriptorSetInfo = &DescriptorSetInfo {
-	bindings: phf::phf_map! {
-		0u32 => DescriptorBindingType::StructuredBuffer,
-		1u32 => DescriptorBindingType::CBuffer,
-		2u32 => DescriptorBindingType::Texture2D,
-		3u32 => DescriptorBindingType::RWTexture2D,
-	},
+const Z_NEAR: f32 = 0.01;
+const TILE_SIZE: u32 = match light_cull_compute::TILE_SIZE.default {
+	SpecValue::U32(tile_size) => tile_size,
+	_ => panic!("Light cull tile size should be a uint!"),
 };
+const SMALL_TILE_SIZE: u32 = 8;
+// The light cull only looks at one light per thread in the tile, more than this would get skipped with the small tiles.
+const MAX_POINT_LIGHTS: usize = (SMALL_TILE_SIZE * SMALL_TILE_SIZE) as usize;
+const LIGHT_COLORS: [Color; 3] = [
+	Color { r: 1.0, g: 0.1, b: 0.1, a: 1.0 },
+	Color { r: 0.1, g: 1.0, b: 0.1, a: 1.0 },
+	Color { r: 0.1, g: 0.1, b: 1.0, a: 1.0 },
+];
+const LIGHT_ORBIT_RADIUS: f32 = 1.5;
+const LIGHT_ORBIT_SPEED: f32 = 1.0;
+const LIGHT_RADIUS: f32 = 3.0;
+
+const GEOMETRY_OCCLUSION_QUERY: QuerySlot = QuerySlot::occlusion(0);
+const GEOMETRY_STATISTICS_QUERY: QuerySlot = QuerySlot::pipeline_statistics(0);
+const OVERDRAW_STATISTICS_QUERY: QuerySlot = QuerySlot::pipeline_statistics(1);
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+const OVERLAY_COLOR: Color = Color { r: 1.0, g: 1.0, b: 1.0, a: 0.9 };
+// Only shows where nothing gets drawn over the output, sRGB encoded like a color picker would give it.
+const OUTPUT_CLEAR_COLOR: Color = Color::new(0.1, 0.1, 0.12, 1.0);
+const PICKING_ATTACHMENT: &str = "Picking object IDs";
+const CUBE_OBJECT_ID: u32 = 1;
+const CUBE_MESH: &str = "meshes/cube.obj";
+const FULLSCREEN_SHADER: &str = "shaders/fullscreen.hlsl";
+// The +X face, the other five are next to it. Importing it with a Cubemap format in its meta file is what makes it a cubemap.
+const SKYBOX_CUBEMAP: &str = "textures/skybox_px.png";
+const DEFAULT_MOUSE_SENSITIVITY: f64 = 0.001;
+const DEFAULT_MOVE_SPEED: f64 = 0.05;
+const CAMERA_PITCH_LIMIT: f64 = std::f64::consts::FRAC_PI_2 - 0.001;
+const SKINNED_TEST_POSITION: Vec3 = Vec3 { x: -1.5, y: -1.0, z: 0.0 };
+const CUBE_FIELD_SIZE: usize = 10;
+const CUBE_FIELD_COUNT: usize = CUBE_FIELD_SIZE * CUBE_FIELD_SIZE;
+const CUBE_FIELD_SPACING: f32 = 0.5;
+const CUBE_FIELD_SCALE: f32 = 0.1;
+const OVERDRAW_CUBE_COUNT: usize = 500;
+const OVERDRAW_CUBE_SCALE: f32 = 0.3;
+const OVERDRAW_ATTACHMENT: &str = "Overdraw test";
+// Hold P to spray particles out of the middle of the cube field, fast enough to keep the whole buffer alive.
+const MAX_PARTICLES: u32 = 100_000;
+const PARTICLE_LIFETIME: f32 = 2.0;
+const PARTICLE_EMIT_RATE: f32 = MAX_PARTICLES as f32 / (PARTICLE_LIFETIME * 0.75);
+const SLOW_MOTION_SCALE: f32 = 0.2;
+const SHADOW_ATLAS_SIZE: u32 = 4096;
+const SHADOW_FOV: f32 = 1.4;
+const SHADOW_TARGET: Vec3 = Vec3::new(0.0, -2.0, 0.0);
+const SHADOW_CASTERS: [ShadowCaster; 2] = [
+	ShadowCaster {
+		position: Vec3::new(1.5, 2.0, 0.5),
+		color: Vec3::new(1.0, 0.8, 0.6),
+		resolution: 1024,
+	},
+	ShadowCaster {
+		position: Vec3::new(-1.5, 2.0, -0.5),
+		color: Vec3::new(0.5, 0.6, 1.0),
+		resolution: 128,
+	},
+];
 
-const Z_NEAR: f32 = 0.01;
+const MATERIAL_COUNT: usize = 64;
+const MATERIAL_TEXTURE_SIZE: u32 = 4;
+
+struct Spin {
+	axis: Vec3,
+	speed: f32,
+}
+
+struct ShadowCaster {
+	position: Vec3,
+	color: Vec3,
+	resolution: u32,
+}
+
+struct BindlessMaterials {
+	vs: Shader,
+	ps: Shader,
+	textures: Vec<Texture>,
+}
+
+struct Skybox {
+	vs: Shader,
+	ps: Shader,
+	cubemap: Texture,
+}
 
 struct Game {
 	vs: Shader,
 	ps: Shader,
 	vs_textured: Shader,
 	ps_textured: Shader,
-	vs_fullscreen: Shader,
-	ps_fullscreen: Shader,
+	fullscreen_shader: ReloadableShader,
+	cs_fullscreen: Shader,
+	vs_temporal_blend: Shader,
+	ps_temporal_blend: Shader,
 	ps_depth_debug: Shader,
+	vs_debug_normals: Shader,
+	gs_debug_normals: Shader,
+	ps_debug_normals: Shader,
 	cs_light_cull: Shader,
-	point_lights: [light_cull_compute::PointLight; 3],
-	point_lights_sbuffer: GpuBuffer,
-	light_cull_cbuffer: GpuBuffer,
+	vs_picking: Shader,
+	ps_picking: Shader,
+	vs_skinned_test: Shader,
+	ps_skinned_test: Shader,
+	vs_cube_field: Shader,
+	ps_cube_field: Shader,
+	vs_shadow_depth: Shader,
+	vs_shadowed: Shader,
+	ps_shadowed: Shader,
+	vs_sorted_cubes: Shader,
+	ps_sorted_cubes: Shader,
+	vs_forward_plus: Shader,
+	ps_forward_plus: Shader,
+	vs_vertex_colors: Shader,
+	ps_vertex_colors: Shader,
+	ps_vertex_colors_alpha_test: Shader,
+	lights: LightManager,
+	light_ids: [LightId; 3],
+	light_cull_cbuffer: PerFrameBuffer,
+	fullscreen_cbuffer: PerFrameBuffer,
 	depth_debug_cbuffer: GpuBuffer,
 	cube: Mesh,
-	camera_uniform: GpuBuffer,
-	model_uniform: GpuBuffer,
+	skinned_test_mesh: Mesh,
+	vertex_color_test_mesh: Mesh,
+	skin_palette: SkinPalette,
+	camera_uniform: PerFrameBuffer,
+	model_uniform: PerFrameBuffer,
+	skinned_model_uniform: PerFrameBuffer,
+	cube_field_instances: PerFrameBuffer,
+	shadow_lights: PerFrameBuffer,
+	overdraw_cubes: Vec<Mat4>,
+	particles: ParticleSystem,
+	depth_prepass: DepthPrepass,
+	fullscreen_blit: FullscreenBlit,
+	shadow_atlas: ShadowAtlas,
+	bindless_materials: Option<BindlessMaterials>,
+	skybox: Option<Skybox>,
 	upload_context: UploadContext,
+	click_sound: AudioClipHandle,
 
 	camera_transform: Transform,
 	camera_heading: f64,
 	camera_pitch: f64,
 	cube_transform: Transform,
+	registry: Registry,
+	mouse_sensitivity: f64,
+	move_speed: f64,
 
 	render_graph_cache: RenderGraphCache,
+	pending_pick: Option<(ReadbackId, u32, u32)>,
+	last_stats_print: Instant,
+	skinning_start: Instant,
+	lights_start: Instant,
+	overlay: DebugOverlay,
+	console: Option<String>,
 }
 
 impl Game {
+	fn reload_cube(&mut self, engine: &mut GoldfishEngine) {
+		let mesh_package = match engine.read_package_by_path(CUBE_MESH, AssetType::Mesh) {
+			Ok(Package::Mesh(mesh_package)) => mesh_package,
+			Ok(_) => return error!("Failed to reload the cube, incorrect package type loaded?"),
+			Err(err) => return error!("Failed to reload the cube! {}", err),
+		};
+
+		match engine.geometry_pool.upload_mesh(&mut self.upload_context, "Cube", &mesh_package.vertices, &mesh_package.indices) {
+			Ok(cube) => {
+				let old_cube = std::mem::replace(&mut self.cube, cube);
+				engine.geometry_pool.free_mesh(&mut engine.graphics_device, old_cube);
+				info!("Reloaded the cube mesh");
+			}
+			Err(err) => error!("Failed to reload the cube! {}", err),
+		}
+	}
+
 	fn update(&mut self, engine: &mut GoldfishEngine) {
-		let graphics_device = &mut engine.graphics_device;
-		let graphics_context = &mut engine.graphics_context;
+		if self.last_stats_print.elapsed() >= STATS_INTERVAL {
+			let frame_timer = &engine.frame_timer;
+			info!(
+				"Frame time: avg {:.2}ms, 95p {:.2}ms, 99p {:.2}ms ({:.1} fps)",
+				frame_timer.avg_ms(),
+				frame_timer.percentile(95.0),
+				frame_timer.percentile(99.0),
+				frame_timer.fps()
+			);
 
-		let dz = if engine.keys[VirtualKeyCode::W as usize] {
-			1.0
-		} else if engine.keys[VirtualKeyCode::S as usize] {
-			-1.0
-		} else {
-			0.0
-		};
+			let query_results = engine.graphics_context.query_results();
+			if let Some(QueryResult::Occlusion { samples_passed }) = query_results.get(&GEOMETRY_OCCLUSION_QUERY) {
+				debug!("Geometry pass: {} samples passed", samples_passed);
+			}
 
-		let dx = if engine.keys[VirtualKeyCode::A as usize] {
-			-1.0
-		} else if engine.keys[VirtualKeyCode::D as usize] {
-			1.0
-		} else {
-			0.0
-		};
+			if let Some(QueryResult::PipelineStatistics { fragment_shader_invocations, .. }) = query_results.get(&GEOMETRY_STATISTICS_QUERY) {
+				debug!("Geometry pass: {} fragment shader invocations", fragment_shader_invocations);
+			}
 
-		let dy = if engine.keys[VirtualKeyCode::E as usize] {
-			1.0
-		} else if engine.keys[VirtualKeyCode::Q as usize] {
-			-1.0
-		} else {
-			0.0
-		};
+			if let Some(QueryResult::PipelineStatistics { fragment_shader_invocations, .. }) = query_results.get(&OVERDRAW_STATISTICS_QUERY) {
+				debug!("Overdraw pass: {} fragment shader invocations", fragment_shader_invocations);
+			}
 
-		let sensitivity = 0.001;
-		self.camera_pitch += sensitivity * engine.mouse_delta.y as f64;
-		self.camera_pitch = self.camera_pitch.clamp(-std::f64::consts::FRAC_PI_2 + 0.001, std::f64::consts::FRAC_PI_2 - 0.001);
-		self.camera_heading += sensitivity * engine.mouse_delta.x as f64;
+			let memory_stats = engine.memory_stats();
+			debug!(
+				"GPU memory: device local {}/{} MB, host visible {}/{} MB ({} allocations)",
+				memory_stats.device_local_used / (1024 * 1024),
+				memory_stats.device_local_budget / (1024 * 1024),
+				memory_stats.host_visible_used / (1024 * 1024),
+				memory_stats.host_visible_budget / (1024 * 1024),
+				memory_stats.allocation_count
+			);
+
+			self.last_stats_print = Instant::now();
+		}
+
+		// Swap the cube and fullscreen shader out whenever the editor reimports them.
+		for event in engine.drain_asset_events() {
+			if self.fullscreen_shader.handle_asset_event(engine, &mut self.render_graph_cache, &event) {
+				continue;
+			}
+
+			match event {
+				AssetEvent::AssetChanged { uuid, asset_type: AssetType::Mesh } if engine.uuid_for_path(CUBE_MESH, AssetType::Mesh).ok() == Some(uuid) => self.reload_cube(engine),
+				event => debug!("Asset event: {:?}", event),
+			}
+		}
+
+		// The console is typed into through the text events rather than the keys, so it works with any keyboard layout (and IMEs).
+		let toggle_console = engine.input.action_just_pressed("console");
+		for event in engine.drain_events() {
+			match event {
+				EngineEvent::TextInput(c) if !toggle_console => {
+					if let Some(line) = &mut self.console {
+						line.push(c);
+					}
+				}
+				EngineEvent::FileDropped(path) => info!("Dropped {}", path.display()),
+				EngineEvent::CloseRequested => info!("Closing"),
+				event => debug!("Window event: {:?}", event),
+			}
+		}
+
+		if toggle_console {
+			self.console = match self.console {
+				Some(_) => None,
+				None => Some(String::new()),
+			};
+		}
+
+		if let Some(line) = &mut self.console {
+			if engine.input.key_just_pressed(Key::Back) {
+				line.pop();
+			}
+
+			if engine.input.key_just_pressed(Key::Return) {
+				info!("> {}", line);
+				line.clear();
+			}
+		}
+
+		if engine.input.action_just_pressed("gpu_capture") {
+			if engine.is_capture_available() {
+				engine.trigger_gpu_capture(1);
+			} else {
+				warn!("Launch the game from RenderDoc to capture frames");
+			}
+		}
+
+		if engine.input.action_just_pressed("capture_frame") {
+			if let Err(err) = engine.graphics_context.capture_next_frame("frame.gfcap") {
+				warn!("Failed to capture the frame: {}", err);
+			}
+		}
+
+		if engine.input.action_just_pressed("click") {
+			engine.audio.play(self.click_sound, PlayParams { volume: 0.5, ..Default::default() });
+		}
+
+		let dz = engine.input.axis("move_z");
+		let dx = engine.input.axis("move_x");
+		let dy = engine.input.axis("move_y");
+
+		// Hold the right mouse button to look around.
+		if engine.input.mouse_button_just_pressed(MouseButton::Right) {
+			if let Err(err) = engine.lock_cursor() {
+				warn!("Failed to lock the cursor, mouse look won't work! {}", err);
+			}
+		} else if engine.input.mouse_button_just_released(MouseButton::Right) {
+			if let Err(err) = engine.unlock_cursor() {
+				warn!("Failed to unlock the cursor! {}", err);
+			}
+		}
+
+		if engine.cursor_lock() != CursorLock::Unlocked {
+			let sensitivity = self.mouse_sensitivity;
+			self.camera_pitch += sensitivity * engine.mouse_delta.y as f64;
+			self.camera_pitch = self.camera_pitch.clamp(-CAMERA_PITCH_LIMIT, CAMERA_PITCH_LIMIT);
+			self.camera_heading += sensitivity * engine.mouse_delta.x as f64;
+		}
 		let new_rot = Quat::from_euler(glam::EulerRot::YXZ, self.camera_heading as f32, self.camera_pitch as f32, 0.0);
 		self.camera_transform.rotation = self.camera_transform.rotation.slerp(new_rot, 0.3);
 
-		let speed = 0.05;
+		// Press G to toggle slow motion, which the cubes and particles go along with but the camera doesn't.
+		if engine.input.action_just_pressed("slow_motion") {
+			let time_scale = if engine.time.time_scale() == 1.0 { SLOW_MOTION_SCALE } else { 1.0 };
+			engine.time.set_time_scale(time_scale);
+		}
+
+		let dt = engine.time.scaled_delta.as_secs_f32();
+		for (_, (transform, spin)) in self.registry.query::<(&mut Transform, &Spin)>() {
+			let angle = spin.speed * engine.time.elapsed_seconds_f32_wrapped(std::f32::consts::TAU / spin.speed);
+			transform.rotation = Quat::from_axis_angle(spin.axis, angle);
+		}
+
+		let speed = self.move_speed as f32;
 		self.camera_transform.position += speed * (self.camera_transform.forward() * dz + self.camera_transform.right() * dx + Vec3 { x: 0.0, y: 1.0, z: 0.0 } * dy);
 
-		if let Ok(_) = graphics_context.begin_frame(&engine.window) {
+		if !engine.should_render() {
+			return;
+		}
+
+		let graphics_device = &mut engine.graphics_device;
+		let graphics_context = &mut engine.graphics_context;
+
+		let _ = graphics_context.frame(&engine.window, |frame| {
 			let model = common_inc::Model {
 				matrix: Mat4::from_scale_rotation_translation(self.cube_transform.scale, self.cube_transform.rotation, self.cube_transform.position),
 			};
 
-			let proj = Mat4::perspective_infinite_reverse_lh(1.6, engine.window.get_size().aspect() as f32, Z_NEAR);
-			let inverse_proj = proj.inverse();
-
-			let view = Mat4::look_at_lh(
-				self.camera_transform.position,
-				self.camera_transform.position + self.camera_transform.forward(),
-				Vec3 { x: 0.0, y: 1.0, z: 0.0 },
-			);
+			let viewport = engine.output_policy.viewport(frame.output_size());
 
-			let camera = common_inc::Camera {
-				position: self.camera_transform.position,
-				view,
-				proj,
-				view_proj: proj * view,
+			let perspective = PerspectiveCamera {
+				fov_y: 1.6,
+				aspect: viewport.size().aspect() as f32,
+				z_near: Z_NEAR,
 			};
+			let camera = PlacedCamera {
+				transform: self.camera_transform,
+				camera: perspective,
+			}
+			.to_gpu();
+			let view = camera.view;
+			let inverse_proj = camera.proj.inverse();
+
+			let frame_slot = frame.frame_slot();
+
+			let resolution_scale = engine.resolution_scaler.current_scale();
+			let output_size = viewport.size();
+			let output_clear = ClearValue::from_color(OUTPUT_CLEAR_COLOR, frame.output_is_srgb());
+			let render_size = relative_output_size(output_size, resolution_scale, resolution_scale);
+			let render_size = UVec2::new(render_size.width, render_size.height);
+
+			if engine.input.action_pressed("particles") {
+				self.particles.emit(EmitParams {
+					position: SHADOW_TARGET,
+					velocity: Vec3::new(0.0, 4.0, 0.0),
+					spread: 1.5,
+					count: (PARTICLE_EMIT_RATE * dt).ceil() as u32,
+					lifetime: PARTICLE_LIFETIME,
+					size: 0.02,
+					color: Color { r: 1.0, g: 0.6, b: 0.2, a: 1.0 },
+				});
+			}
+			self.particles.prepare(frame_slot, dt, camera.view, camera.proj);
+
+			self.camera_uniform.write(frame_slot, &camera.as_buffer());
+
+			// Whatever the mouse did since the update gets added on right before submit, only to the camera cbuffer. Hold X to turn it off.
+			if matches!(engine.cursor_lock(), CursorLock::Locked | CursorLock::Confined) && !engine.input.action_pressed("skip_late_latch") {
+				let input_state = engine.input_state();
+				let mouse_total = input_state.mouse_total();
+				let sensitivity = self.mouse_sensitivity;
+				let (heading, pitch) = (self.camera_heading, self.camera_pitch);
+				let transform = self.camera_transform;
+
+				frame.register_late_update(
+					self.camera_uniform.current(frame_slot),
+					Box::new(move || {
+						let delta = sensitivity * (input_state.mouse_total() - mouse_total);
+						let late_pitch = (pitch + delta.y).clamp(-CAMERA_PITCH_LIMIT, CAMERA_PITCH_LIMIT);
+						let late_heading = heading + delta.x;
+
+						let update_rot = Quat::from_euler(glam::EulerRot::YXZ, heading as f32, pitch as f32, 0.0);
+						let late_rot = Quat::from_euler(glam::EulerRot::YXZ, late_heading as f32, late_pitch as f32, 0.0);
+						let mut transform = transform;
+						transform.rotation = (late_rot * update_rot.inverse() * transform.rotation).normalize();
+
+						PlacedCamera { transform, camera: perspective }.to_gpu().as_buffer().to_vec()
+					}),
+				);
+			}
+			self.model_uniform.write(frame_slot, &model.as_buffer());
+
+			let t = self.lights_start.elapsed().as_secs_f32();
+			for (i, (&id, color)) in self.light_ids.iter().zip(LIGHT_COLORS).enumerate() {
+				let angle = t * LIGHT_ORBIT_SPEED + i as f32 * std::f32::consts::TAU / 3.0;
+				let offset = Vec3::new(angle.cos() * LIGHT_ORBIT_RADIUS, (t + i as f32).sin() * 0.5, angle.sin() * LIGHT_ORBIT_RADIUS);
+				self.lights.update(
+					id,
+					PointLight {
+						position: self.cube_transform.position + offset,
+						radius: LIGHT_RADIUS,
+						color,
+					},
+				);
+			}
+			self.lights.prepare(frame_slot, view);
 
-			graphics_device.update_buffer(&mut self.camera_uniform, &camera.as_buffer());
-			graphics_device.update_buffer(&mut self.model_uniform, &model.as_buffer());
-			graphics_device.update_buffer(
-				&mut self.light_cull_cbuffer,
+			self.light_cull_cbuffer.write(
+				frame_slot,
 				&light_cull_compute::CullInfo {
-					screen_size: UVec2::new(engine.window.get_size().width, engine.window.get_size().height),
-					view,
+					screen_size: render_size,
 					z_near: Z_NEAR,
 					inverse_proj,
-					light_count: 1,
+					light_count: self.lights.light_count() as u32,
 				}
 				.as_buffer(),
 			);
 
-			let deltas = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
-			for (i, point_light) in self.point_lights.iter_mut().enumerate() {
-				point_light.position = Vec3::new(2.0, 0.0, 0.0);
-				point_light.radius = 2.0;
-			}
+			let cube_field = self
+				.registry
+				.query::<(&Transform, &Spin)>()
+				.take(CUBE_FIELD_COUNT)
+				.map(|(_, (transform, _))| cube_field::Instance {
+					matrix: Mat4::from_scale_rotation_translation(transform.scale, transform.rotation, transform.position),
+				})
+				.collect::<Vec<_>>();
 
 			{
-				let dst = graphics_device.get_buffer_dst(&mut self.point_lights_sbuffer);
-				light_cull_compute::PointLight::copy_to_raw(&self.point_lights, dst);
+				let mut dst = graphics_device.map_buffer(self.cube_field_instances.current_mut(frame_slot));
+				cube_field::Instance::copy_to_raw(&cube_field, &mut dst);
 			}
 
-			let mut render_graph = RenderGraph::new(&mut self.render_graph_cache);
-			let depth_prepass_attachment = {
-				let mut geometry_pass = render_graph.add_pass("geometry");
-
-				// let mut output = geometry_pass.add_attachment(AttachmentDesc {
-				// 	name: "Geometry output",
-				// 	format: TextureFormat::RGBA8,
-				// 	width: engine.window.get_size().width,
-				// 	height: engine.window.get_size().height,
-				// 	load_op: LoadOp::Clear,
-				// 	store_op: StoreOp::Store,
-				// 	usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT,
-				// });
-
-				let mut depth = geometry_pass.add_attachment(AttachmentDesc {
-					name: "Geometry depth",
-					format: TextureFormat::Depth,
-					width: engine.window.get_size().width,
-					height: engine.window.get_size().height,
-					load_op: LoadOp::Clear,
-					store_op: StoreOp::Store,
-					usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT,
-				});
+			let debug_shadows = engine.input.action_pressed("debug_shadows");
+			let shadow_regions = if debug_shadows {
+				self.shadow_atlas.clear_frame();
+				let regions = SHADOW_CASTERS.iter().map(|caster| self.shadow_atlas.allocate_transient(caster.resolution)).collect::<Vec<_>>();
 
-				let descriptor = geometry_pass.add_graphics_descriptor_set(DescriptorDesc {
-					name: "Geometry descriptor",
-					descriptor_layout: COMMON_DESC_INFO,
-					bindings: &mut [
-						(0, DescriptorBindingDesc::ImportedBuffer(&self.camera_uniform)),
-						(1, DescriptorBindingDesc::ImportedBuffer(&self.model_uniform)),
-					],
-				});
-
-				let render_pass = geometry_pass.add_render_pass(RenderPassDesc {
-					name: "Geometry render pass",
-					color_attachments: &mut [],
-					depth_attachment: Some(&mut depth),
-				});
+				let proj = PerspectiveCamera {
+					fov_y: SHADOW_FOV,
+					aspect: 1.0,
+					z_near: Z_NEAR,
+				}
+				.projection();
+
+				let shadow_lights = SHADOW_CASTERS
+					.iter()
+					.zip(regions.iter())
+					.map(|(caster, region)| shadowed::ShadowLight {
+						view_proj: proj * Mat4::look_at_lh(caster.position, SHADOW_TARGET, Vec3::Z),
+						uv_scale_offset: region.map_or(Vec4::ZERO, |region| region.uv_scale_offset(SHADOW_ATLAS_SIZE)),
+						position: caster.position.extend(1.0),
+						color: region.map_or(Vec4::ZERO, |_| caster.color.extend(1.0)),
+					})
+					.collect::<Vec<_>>();
+
+				let mut dst = graphics_device.map_buffer(self.shadow_lights.current_mut(frame_slot));
+				shadowed::ShadowLight::copy_to_raw(&shadow_lights, &mut dst);
+
+				regions
+			} else {
+				Vec::new()
+			};
 
-				let pipeline = geometry_pass.add_raster_pipeline(RasterPipelineDesc {
-					name: "Cube Pipeline",
-					vs: &self.vs,
-					ps: None,
-					descriptor_layouts: &[COMMON_DESC_INFO],
-					render_pass,
-					depth_compare_op: Some(DepthCompareOp::Greater),
-					depth_write: true,
-					face_cull: FaceCullMode::Back,
-					push_constant_bytes: 0,
-					vertex_input_info: Vertex::VERTEX_INFO,
-					polygon_mode: PolygonMode::Fill,
-				});
+			let debug_skinning = engine.input.action_pressed("debug_skinning");
+			if debug_skinning {
+				let bend = self.skinning_start.elapsed().as_secs_f32().sin() * 1.2;
+				let mut pose = self.skin_palette.rest_pose().to_vec();
+				pose[1] *= Mat4::from_rotation_z(bend);
+
+				self.skin_palette.set_pose(&pose);
+				self.skin_palette.write(frame_slot, |matrix| skinning_inc::Bone { matrix });
+				self.skinned_model_uniform.write(
+					frame_slot,
+					&common_inc::Model {
+						matrix: Mat4::from_translation(SKINNED_TEST_POSITION),
+					}
+					.as_buffer(),
+				);
+			}
 
-				geometry_pass.cmd_begin_render_pass(render_pass, &[ClearValue::DepthStencil { depth: 0.0, stencil: 0 }]);
+			self.overlay.begin(
+				PhysicalSize {
+					width: output_size.width,
+					height: output_size.height,
+				},
+				engine.window.scale_factor(),
+			);
+			{
+				let frame_timer = &engine.frame_timer;
+				let mut stats = format!("{:.1} fps ({:.2}ms, 99p {:.2}ms)\n", frame_timer.fps(), frame_timer.avg_ms(), frame_timer.percentile(99.0));
+				if let Some(gpu_frame_time) = frame.gpu_frame_time() {
+					stats += &format!("gpu {:.2}ms\n", gpu_frame_time.as_secs_f64() * 1000.0);
+				}
+				stats += &format!("{} redundant binds dropped\n", frame.redundant_cmd_count());
+
+				// Whichever of these is biggest is what the frame is bound by, the fence for the GPU and acquire/present for vsync.
+				let swapchain = frame.swapchain_info();
+				let pacing = frame.frame_pacing();
+				stats += &format!(
+					"{} images {:?} {:?} {}x{}\n",
+					swapchain.image_count, swapchain.present_mode, swapchain.format, swapchain.extent.width, swapchain.extent.height
+				);
+				stats += &format!(
+					"fence {:.2}ms acquire {:.2}ms present {:.2}ms (image {}{})\n",
+					pacing.fence_wait.as_secs_f64() * 1000.0,
+					pacing.acquire_wait.as_secs_f64() * 1000.0,
+					pacing.present_wait.as_secs_f64() * 1000.0,
+					pacing.image_index,
+					if pacing.suboptimal { ", suboptimal" } else { "" }
+				);
+				stats += &format!("{}x{} ({:.0}%)\n", render_size.x, render_size.y, resolution_scale * 100.0);
+				for timing in self.render_graph_cache.pass_timings() {
+					stats += &format!("{:<12} {:>7.3}ms cpu\n", timing.name, timing.cpu_time.as_secs_f64() * 1000.0);
+				}
 
-				geometry_pass.cmd_bind_raster_pipeline(pipeline);
-				geometry_pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+				self.overlay.text(8, 8, OVERLAY_COLOR, &stats);
 
-				geometry_pass.cmd_draw_mesh(&self.cube);
+				if let Some(line) = &self.console {
+					let y = engine.window.logical_size().height as i32 - 8 - self.overlay.line_height() as i32;
+					self.overlay.text(8, y, OVERLAY_COLOR, &format!("> {}_", line));
+				}
+			}
 
-				geometry_pass.cmd_end_render_pass();
+			let debug_materials = engine.input.action_pressed("debug_materials");
+			let material_textures = self.bindless_materials.as_ref().map_or(Vec::new(), |materials| materials.textures.iter().collect::<Vec<_>>());
+
+			let mut render_graph = RenderGraph::new(&mut self.render_graph_cache, &engine.default_resources);
+			render_graph.set_resolution_scale(resolution_scale);
+			render_graph.set_output_policy(engine.output_policy);
+			let depth_prepass_attachment = self.depth_prepass.record(
+				&mut render_graph,
+				&DepthPrepassParams {
+					camera_buffer: &self.camera_uniform,
+					draws: &[DrawItem {
+						mesh: &self.cube,
+						model: &self.model_uniform,
+						push_constants: None,
+					}],
+					size: AttachmentSize::OUTPUT,
+					queries: &[GEOMETRY_OCCLUSION_QUERY, GEOMETRY_STATISTICS_QUERY],
+				},
+			);
 
-				depth
-			};
+			// Hold T to cull with smaller tiles.
+			let tile_size = if engine.input.action_pressed("small_tiles") { SMALL_TILE_SIZE } else { TILE_SIZE };
+			let tiles = dispatch_group_count(render_size, UVec2::splat(tile_size));
 
-			let cull_attachment = {
+			let (cull_attachment, tile_lights) = {
 				let mut cull_pass = render_graph.add_pass("cull");
 
 				let mut max_depth = cull_pass.add_attachment(AttachmentDesc {
 					name: "Max Depth",
 					format: TextureFormat::RGBA8UNorm,
-					width: engine.window.get_size().width,
-					height: engine.window.get_size().height,
+					size: AttachmentSize::OUTPUT,
 					load_op: LoadOp::Clear,
 					store_op: StoreOp::Store,
-					usage: TextureUsage::SAMPLED | TextureUsage::STORAGE,
+					usage: TextureUsage::SAMPLED | TextureUsage::STORAGE | TextureUsage::TRANSFER_SRC,
 				});
 
+				let mut tile_lights = cull_pass.add_buffer(BufferDesc {
+					name: "Tile Lights",
+					size: (tiles.x * tiles.y) as usize * light_cull_compute::TileLights::size(),
+					usage: BufferUsage::StorageBuffer | BufferUsage::TransferDst,
+					location: MemoryLocation::GpuOnly,
+				});
+
+				cull_pass.cmd_fill_buffer(&mut tile_lights, 0);
+
 				let descriptor = cull_pass.add_compute_descriptor_set(DescriptorDesc {
 					name: "Cull Descriptor",
-					descriptor_layout: LIGHT_CULL_DESC_INFO,
+					descriptor_layout: light_cull_compute::DESCRIPTOR_SET_0_INFO,
 					bindings: &mut [
-						(0, DescriptorBindingDesc::ImportedBuffer(&self.point_lights_sbuffer)),
-						(1, DescriptorBindingDesc::ImportedBuffer(&self.light_cull_cbuffer)),
-						(2, DescriptorBindingDesc::Attachment(depth_prepass_attachment.read())),
+						(0, DescriptorBindingDesc::PerFrameBuffer(self.lights.buffer())),
+						(1, DescriptorBindingDesc::PerFrameBuffer(&self.light_cull_cbuffer)),
+						(2, DescriptorBindingDesc::Attachment(depth_prepass_attachment)),
 						(3, DescriptorBindingDesc::MutableAttachment(&mut max_depth)),
+						(4, DescriptorBindingDesc::MutableBuffer(&mut tile_lights)),
 					],
 				});
 
 				let pipeline = cull_pass.add_compute_pipeline(ComputePipelineDesc {
 					name: "Cull Pipeline",
 					cs: &self.cs_light_cull,
-					descriptor_layouts: &[LIGHT_CULL_DESC_INFO],
+					descriptor_layouts: &[light_cull_compute::DESCRIPTOR_SET_0_INFO],
+					specialization: &[(light_cull_compute::TILE_SIZE.constant_id, SpecValue::U32(tile_size))],
+					local_size: light_cull_compute::CS_LOCAL_SIZE,
 				});
 
 				cull_pass.cmd_bind_compute_pipeline(pipeline);
 				cull_pass.cmd_bind_compute_descriptor(descriptor, 0, pipeline);
-				let work_groups_x = (engine.window.get_size().width + (engine.window.get_size().width % 16)) / 16;
-				let work_groups_y = (engine.window.get_size().height + (engine.window.get_size().height % 16)) / 16;
-				cull_pass.cmd_dispatch(work_groups_x, work_groups_y, 1);
+				// Each group only covers a tile_size tile, not the MAX_TILE_SIZE threads it launches.
+				cull_pass.cmd_dispatch_for_size(render_size, UVec2::splat(tile_size));
 
-				max_depth
+				(max_depth, tile_lights)
 			};
 			// {
 			// 	let mut sampler_pass = render_graph.add_pass("sampler pass");
 
 			// 	let descriptor0 = sampler_pass.add_descriptor_set(DescriptorDesc {
 			// 		name: "Geometry descriptor",
-			// 		descriptor_layout: COMMON_DESC_INFO,
+			// 		descriptor_layout: test_shader::DESCRIPTOR_SET_0_INFO,
 			// 		bindings: &mut [
-			// 			(0, DescriptorBindingDesc::ImportedBuffer(&self.camera_uniform)),
-			// 			(1, DescriptorBindingDesc::ImportedBuffer(&self.model_uniform)),
+			// 			(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+			// 			(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
 			// 		],
 			// 	});
 
 			// 	let descriptor1 = sampler_pass.add_descriptor_set(DescriptorDesc {
 			// 		name: "Sampler descriptor",
-			// 		descriptor_layout: SAMPLER_DESC_INFO,
+			// 		descriptor_layout: test_sampler::DESCRIPTOR_SET_1_INFO,
 			// 		bindings: &mut [
 			// 			(0, DescriptorBindingDesc::Attachment(geometry_output_attachment.read())),
 			// 			(1, DescriptorBindingDesc::Attachment(geometry_output_attachment.read())),
@@ -312,7 +681,7 @@ impl Game {
 			// 		name: "Sampler Cube Pipeline",
 			// 		vs: &self.vs_textured,
 			// 		ps: &self.ps_textured,
-			// 		descriptor_layouts: &[COMMON_DESC_INFO, SAMPLER_DESC_INFO],
+			// 		descriptor_layouts: &[test_shader::DESCRIPTOR_SET_0_INFO, test_sampler::DESCRIPTOR_SET_1_INFO],
 			// 		render_pass,
 			// 		depth_write: true,
 			// 		face_cull: FaceCullMode::Back,
@@ -331,134 +700,1457 @@ impl Game {
 
 			// 	sampler_pass.cmd_end_render_pass();
 			// }
-			{
-				let mut fullscreen = render_graph.add_pass("fullscreen");
+			// Hold B to blend each frame with the last one, anything that moves leaves a trail while it's held.
+			let output_attachment = if engine.input.action_pressed("smear") {
+				let mut smear_pass = render_graph.add_pass("smear");
 
-				let render_pass = fullscreen.add_output_render_pass();
+				let (history, mut smeared) = smear_pass.add_history_attachment(AttachmentDesc {
+					name: "Smear History",
+					format: TextureFormat::RGBA8UNorm,
+					size: AttachmentSize::Absolute(output_size.width, output_size.height),
+					load_op: LoadOp::DontCare,
+					store_op: StoreOp::Store,
+					usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC,
+				});
+
+				let descriptor = smear_pass.add_graphics_descriptor_set(DescriptorDesc {
+					name: "Smear Descriptor",
+					descriptor_layout: temporal_blend::DESCRIPTOR_SET_0_INFO,
+					bindings: &mut [
+						(0, DescriptorBindingDesc::Attachment(cull_attachment.read())),
+						(1, DescriptorBindingDesc::Attachment(cull_attachment.read())),
+						(2, DescriptorBindingDesc::Attachment(history)),
+						(3, DescriptorBindingDesc::Attachment(history)),
+					],
+				});
+
+				let render_pass = smear_pass.add_render_pass(RenderPassDesc {
+					name: "Smear render pass",
+					color_attachments: &mut [&mut smeared],
+					depth_attachment: None,
+				});
 
-				let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
-					name: "Fullscreen Pipeline",
-					vs: &self.vs_fullscreen,
-					ps: Some(&self.ps_fullscreen),
-					descriptor_layouts: &[FULLSCREEN_DESC_INFO],
+				let pipeline = smear_pass.add_raster_pipeline(RasterPipelineDesc {
+					name: "Smear Pipeline",
+					vs: &self.vs_temporal_blend,
+					ps: Some(&self.ps_temporal_blend),
+					gs: None,
+					descriptor_layouts: &[temporal_blend::DESCRIPTOR_SET_0_INFO],
 					render_pass,
 					depth_compare_op: None,
 					depth_write: false,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
 					face_cull: FaceCullMode::Front,
 					push_constant_bytes: 0,
 					vertex_input_info: EMPTY_VERTEX_INFO,
 					polygon_mode: PolygonMode::Fill,
+					specialization: &[],
 				});
 
-				let descriptor0 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
-					name: "Fullscreen Descriptor",
-					descriptor_layout: FULLSCREEN_DESC_INFO,
+				smear_pass.cmd_begin_render_pass(render_pass, &[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+
+				smear_pass.cmd_bind_raster_pipeline(pipeline);
+				smear_pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+				smear_pass.cmd_draw(3, 1, 0, 0);
+
+				smear_pass.cmd_end_render_pass();
+
+				smeared
+			} else {
+				cull_attachment
+			};
+
+			// Hold V to add the output into a cleared attachment from two passes that load what was there before, it should come out twice as bright.
+			let output_attachment = if engine.input.action_pressed("accumulate") {
+				let mut accumulation = None;
+				for pass_name in ["accumulate 0", "accumulate 1"] {
+					let mut accumulate_pass = render_graph.add_pass(pass_name);
+
+					let mut accumulated = accumulation.unwrap_or_else(|| {
+						let mut accumulated = accumulate_pass.add_attachment(AttachmentDesc {
+							name: "Accumulation",
+							format: TextureFormat::RGBA8UNorm,
+							size: AttachmentSize::OUTPUT,
+							load_op: LoadOp::Load,
+							store_op: StoreOp::Store,
+							usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_DST,
+						});
+
+						accumulate_pass.cmd_clear_attachment(&mut accumulated, ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 });
+						accumulated
+					});
+
+					let descriptor = accumulate_pass.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Accumulate Descriptor",
+						descriptor_layout: temporal_blend::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(1, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(2, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(3, DescriptorBindingDesc::Attachment(output_attachment.read())),
+						],
+					});
+
+					let render_pass = accumulate_pass.add_render_pass(RenderPassDesc {
+						name: "Accumulate render pass",
+						color_attachments: &mut [&mut accumulated],
+						depth_attachment: None,
+					});
+
+					let pipeline = accumulate_pass.add_raster_pipeline(RasterPipelineDesc {
+						name: "Accumulate Pipeline",
+						vs: &self.vs_temporal_blend,
+						ps: Some(&self.ps_temporal_blend),
+						gs: None,
+						descriptor_layouts: &[temporal_blend::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Additive,
+						face_cull: FaceCullMode::Front,
+						push_constant_bytes: 0,
+						vertex_input_info: EMPTY_VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[],
+					});
+
+					accumulate_pass.cmd_begin_render_pass(render_pass, &[]);
+
+					accumulate_pass.cmd_bind_raster_pipeline(pipeline);
+					accumulate_pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+					accumulate_pass.cmd_draw(3, 1, 0, 0);
+
+					accumulate_pass.cmd_end_render_pass();
+
+					accumulation = Some(accumulated);
+				}
+
+				accumulation.unwrap()
+			} else {
+				output_attachment
+			};
+
+			let shadow_atlas = if debug_shadows {
+				let mut shadow_pass = render_graph.add_pass("shadows");
+
+				let mut atlas = shadow_pass.add_attachment(self.shadow_atlas.attachment_desc("Shadow atlas"));
+
+				let descriptor0 = shadow_pass.add_graphics_descriptor_set(DescriptorDesc {
+					name: "Shadow Descriptor",
+					descriptor_layout: shadow_depth::DESCRIPTOR_SET_0_INFO,
 					bindings: &mut [
-						(0, DescriptorBindingDesc::Attachment(cull_attachment.read())),
-						(1, DescriptorBindingDesc::Attachment(cull_attachment.read())),
+						(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+						(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
 					],
 				});
 
-				fullscreen.cmd_begin_render_pass(render_pass, &[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+				let descriptor1 = shadow_pass.add_graphics_descriptor_set(DescriptorDesc {
+					name: "Shadow Lights Descriptor",
+					descriptor_layout: shadow_depth::DESCRIPTOR_SET_1_INFO,
+					bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(&self.shadow_lights))],
+				});
 
-				fullscreen.cmd_bind_raster_pipeline(pipeline);
-				fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
-				fullscreen.cmd_draw(3, 1, 0, 0);
+				let render_pass = shadow_pass.add_render_pass(RenderPassDesc {
+					name: "Shadow render pass",
+					color_attachments: &mut [],
+					depth_attachment: Some(&mut atlas),
+				});
+
+				let pipeline = shadow_pass.add_raster_pipeline(RasterPipelineDesc {
+					name: "Shadow Pipeline",
+					vs: &self.vs_shadow_depth,
+					ps: None,
+					gs: None,
+					descriptor_layouts: &[shadow_depth::DESCRIPTOR_SET_0_INFO, shadow_depth::DESCRIPTOR_SET_1_INFO],
+					render_pass,
+					depth_compare_op: Some(DepthCompareOp::Greater),
+					depth_write: true,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
+					face_cull: FaceCullMode::Back,
+					push_constant_bytes: shadow_depth::PUSH_CONSTANT_BYTES,
+					vertex_input_info: Vertex::VERTEX_INFO,
+					polygon_mode: PolygonMode::Fill,
+					specialization: &[],
+				});
+
+				shadow_pass.cmd_begin_render_pass(render_pass, &[ClearValue::DepthStencil { depth: 0.0, stencil: 0 }]);
+
+				shadow_pass.cmd_bind_raster_pipeline(pipeline);
+				shadow_pass.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+				shadow_pass.cmd_bind_graphics_descriptor(descriptor1, 1, pipeline);
+
+				for (i, region) in shadow_regions.iter().enumerate() {
+					if let Some(region) = region {
+						shadow_pass.cmd_set_viewport(region.offset, region.extent);
+						shadow_pass.cmd_push_constant_struct(pipeline, &shadow_depth::ShadowConstants { light_index: i as u32 });
+						shadow_pass.cmd_draw_mesh(&self.cube);
+					}
+				}
+
+				shadow_pass.cmd_end_render_pass();
+
+				Some(atlas.read())
+			} else {
+				None
+			};
+
+			self.particles.record_simulation(&mut render_graph);
+
+			{
+				let mut fullscreen = render_graph.add_pass("fullscreen");
+
+				let render_pass = fullscreen.add_output_render_pass();
+
+				// Hold O to copy the output straight into the swapchain image instead of drawing it with a fullscreen triangle, it should look exactly the same.
+				if engine.input.action_pressed("blit_output") {
+					fullscreen.cmd_blit_attachment_to_output(output_attachment.read_as(ImageLayout::TransferSrcOptimal), Filter::Nearest);
+					fullscreen.cmd_begin_render_pass(render_pass, &[output_clear]);
+				} else if engine.input.action_pressed("shader_blit_output") {
+					// Hold I to draw it with the engine's fullscreen blit instead, which should look exactly the same as holding O.
+					self.fullscreen_blit.record(&mut fullscreen, output_attachment.read(), OutputOrAttachment::Output, Filter::Nearest);
+				} else if frame.supports_output_storage() && engine.output_policy == OutputPolicy::Stretch && !engine.input.action_pressed("raster_output") {
+					// Hold R to draw with the fullscreen triangle even when the swapchain can be written from compute, it should look the same.
+					let render_scale = render_size.as_vec2() / Vec2::new(output_size.width as f32, output_size.height as f32);
+					self.fullscreen_cbuffer
+						.write(frame_slot, &fullscreen_compute::FullscreenConstants { render_scale, render_size }.as_buffer());
+
+					let output = fullscreen.add_output_storage_binding();
+
+					let descriptor = fullscreen.add_compute_descriptor_set(DescriptorDesc {
+						name: "Fullscreen Compute Descriptor",
+						descriptor_layout: fullscreen_compute::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(1, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(2, DescriptorBindingDesc::Buffer(tile_lights.read())),
+							(3, DescriptorBindingDesc::PerFrameBuffer(self.lights.buffer())),
+							(4, DescriptorBindingDesc::PerFrameBuffer(&self.fullscreen_cbuffer)),
+							(5, DescriptorBindingDesc::OutputStorage(output)),
+						],
+					});
+
+					let pipeline = fullscreen.add_compute_pipeline(ComputePipelineDesc {
+						name: "Fullscreen Compute Pipeline",
+						cs: &self.cs_fullscreen,
+						descriptor_layouts: &[fullscreen_compute::DESCRIPTOR_SET_0_INFO],
+						specialization: &[(fullscreen_compute::TILE_SIZE.constant_id, SpecValue::U32(tile_size))],
+						local_size: fullscreen_compute::CS_LOCAL_SIZE,
+					});
+
+					fullscreen.cmd_bind_compute_pipeline(pipeline);
+					fullscreen.cmd_bind_compute_descriptor(descriptor, 0, pipeline);
+					fullscreen.cmd_dispatch_for_size_auto(UVec2::new(output_size.width, output_size.height));
+
+					fullscreen.cmd_begin_render_pass(render_pass, &[output_clear]);
+				} else {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Fullscreen Pipeline",
+						vs: self.fullscreen_shader.vs(&engine.default_resources),
+						ps: self.fullscreen_shader.ps(&engine.default_resources),
+						gs: None,
+						descriptor_layouts: &[fullscreen::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::Front,
+						push_constant_bytes: fullscreen::PUSH_CONSTANT_BYTES,
+						vertex_input_info: EMPTY_VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[(fullscreen::TILE_SIZE.constant_id, SpecValue::U32(tile_size))],
+					});
+
+					let descriptor0 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Fullscreen Descriptor",
+						descriptor_layout: fullscreen::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(1, DescriptorBindingDesc::Attachment(output_attachment.read())),
+							(2, DescriptorBindingDesc::Buffer(tile_lights.read())),
+							(3, DescriptorBindingDesc::PerFrameBuffer(self.lights.buffer())),
+						],
+					});
+
+					fullscreen.cmd_begin_render_pass(render_pass, &[output_clear]);
+
+					let constants = fullscreen::FullscreenConstants {
+						render_scale: render_size.as_vec2() / Vec2::new(output_size.width as f32, output_size.height as f32),
+						render_size,
+						render_offset: viewport.offset.as_vec2(),
+					};
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+					fullscreen.cmd_push_constant_struct(pipeline, &constants);
+					fullscreen.cmd_draw(3, 1, 0, 0);
+				}
+
+				// Hold Z to draw the skybox over the tile tint, behind the cube.
+				if let Some(skybox) = self.skybox.as_ref().filter(|_| engine.input.action_pressed("skybox")) {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Skybox Pipeline",
+						vs: &skybox.vs,
+						ps: Some(&skybox.ps),
+						gs: None,
+						descriptor_layouts: &[skybox::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::NoCull,
+						push_constant_bytes: 0,
+						vertex_input_info: EMPTY_VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[],
+					});
+
+					let descriptor = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Skybox Descriptor",
+						descriptor_layout: skybox::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(2, DescriptorBindingDesc::ImportedTexture(&skybox.cubemap)),
+							(3, DescriptorBindingDesc::ImportedTexture(&skybox.cubemap)),
+						],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+					fullscreen.cmd_draw(3, 1, 0, 0);
+				}
+
+				{
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Forward+ Pipeline",
+						vs: &self.vs_forward_plus,
+						ps: Some(&self.ps_forward_plus),
+						gs: None,
+						descriptor_layouts: &[forward_plus::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::Back,
+						push_constant_bytes: forward_plus::PUSH_CONSTANT_BYTES,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[(forward_plus::TILE_SIZE.constant_id, SpecValue::U32(tile_size))],
+					});
+
+					let descriptor = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Forward+ Descriptor",
+						descriptor_layout: forward_plus::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+							(2, DescriptorBindingDesc::Buffer(tile_lights.read())),
+							(3, DescriptorBindingDesc::PerFrameBuffer(self.lights.buffer())),
+						],
+					});
+
+					let constants = forward_plus::ForwardPlusConstants {
+						render_scale: render_size.as_vec2() / Vec2::new(output_size.width as f32, output_size.height as f32),
+						render_size,
+						render_offset: viewport.offset.as_vec2(),
+					};
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+					fullscreen.cmd_push_constant_struct(pipeline, &constants);
+					fullscreen.cmd_draw_mesh(&self.cube);
+				}
+
+				// Hold N to draw the cube's vertex normals on top of everything.
+				if engine.input.action_pressed("debug_normals") {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Debug Normals Pipeline",
+						vs: &self.vs_debug_normals,
+						ps: Some(&self.ps_debug_normals),
+						gs: Some(&self.gs_debug_normals),
+						descriptor_layouts: &[debug_normals::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::NoCull,
+						push_constant_bytes: 0,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[],
+					});
+
+					let descriptor = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Debug Normals Descriptor",
+						descriptor_layout: debug_normals::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+						],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+					fullscreen.cmd_draw_mesh(&self.cube);
+				}
+
+				// Hold Y to draw the vertex color quad twice, as is and with the ALPHA_TEST permutation cutting away its transparent corners.
+				if engine.input.action_pressed("debug_vertex_colors") {
+					let descriptor = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Vertex Colors Descriptor",
+						descriptor_layout: vertex_colors::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+						],
+					});
+
+					let permutations = [
+						("Vertex Colors Pipeline", &self.ps_vertex_colors, -0.6),
+						("Vertex Colors Alpha Test Pipeline", &self.ps_vertex_colors_alpha_test, 0.6),
+					];
+					for (name, ps, x) in permutations {
+						let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+							name,
+							vs: &self.vs_vertex_colors,
+							ps: Some(ps),
+							gs: None,
+							descriptor_layouts: &[vertex_colors::DESCRIPTOR_SET_0_INFO],
+							render_pass,
+							depth_compare_op: None,
+							depth_write: false,
+							depth_bias: None,
+							blend_mode: BlendMode::Opaque,
+							face_cull: FaceCullMode::NoCull,
+							push_constant_bytes: vertex_colors::PUSH_CONSTANT_BYTES,
+							vertex_input_info: ExtendedVertex::VERTEX_INFO,
+							polygon_mode: PolygonMode::Fill,
+							specialization: &[],
+						});
+
+						let constants = vertex_colors::VertexColorsConstants {
+							model: Mat4::from_translation(Vec3::new(x, 0.0, 1.5)),
+						};
+						fullscreen.cmd_bind_raster_pipeline(pipeline);
+						fullscreen.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+						fullscreen.cmd_push_constant_struct(pipeline, &constants);
+						fullscreen.cmd_draw_mesh(&self.vertex_color_test_mesh);
+					}
+				}
+
+				// Hold L to draw the cube's wireframe on top of everything, the pipeline is precompiled in on_load so this doesn't hitch the first time.
+				if engine.input.action_pressed("wireframe") {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Wireframe Pipeline",
+						vs: &self.vs,
+						ps: Some(&self.ps),
+						gs: None,
+						descriptor_layouts: &[test_shader::DESCRIPTOR_SET_0_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::NoCull,
+						push_constant_bytes: 0,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Line,
+						specialization: &[],
+					});
+
+					let descriptor = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Wireframe Descriptor",
+						descriptor_layout: test_shader::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+						],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+					fullscreen.cmd_draw_mesh(&self.cube);
+				}
+
+				if let Some(shadow_atlas) = shadow_atlas.filter(|_| !cube_field.is_empty()) {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Shadowed Cube Field Pipeline",
+						vs: &self.vs_shadowed,
+						ps: Some(&self.ps_shadowed),
+						gs: None,
+						descriptor_layouts: &[shadowed::DESCRIPTOR_SET_0_INFO, shadowed::DESCRIPTOR_SET_1_INFO, shadowed::DESCRIPTOR_SET_2_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::Back,
+						push_constant_bytes: 0,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[],
+					});
+
+					let descriptor0 = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Shadowed Cube Field Descriptor",
+						descriptor_layout: shadowed::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+						],
+					});
+
+					let descriptor1 = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Shadowed Cube Field Instances Descriptor",
+						descriptor_layout: shadowed::DESCRIPTOR_SET_1_INFO,
+						bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(&self.cube_field_instances))],
+					});
+
+					let descriptor2 = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Shadowed Cube Field Lights Descriptor",
+						descriptor_layout: shadowed::DESCRIPTOR_SET_2_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.shadow_lights)),
+							(1, DescriptorBindingDesc::Attachment(shadow_atlas)),
+							(2, DescriptorBindingDesc::Attachment(shadow_atlas)),
+						],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor1, 1, pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor2, 2, pipeline);
+					fullscreen.cmd_bind_vertex_buffers(&[(BufferBindingDesc::ImportedBuffer(&self.cube.vertex_buffer), 0)]);
+					fullscreen.cmd_bind_index_buffer(BufferBindingDesc::ImportedBuffer(&self.cube.index_buffer), 0, IndexType::U16);
+					fullscreen.cmd_draw_indexed(self.cube.index_count, cube_field.len() as u32, self.cube.first_index, self.cube.vertex_offset, 0);
+				} else if !cube_field.is_empty() {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Cube Field Pipeline",
+						vs: &self.vs_cube_field,
+						ps: Some(&self.ps_cube_field),
+						gs: None,
+						descriptor_layouts: &[cube_field::DESCRIPTOR_SET_0_INFO, cube_field::DESCRIPTOR_SET_1_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::NoCull,
+						push_constant_bytes: 0,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Line,
+						specialization: &[],
+					});
+
+					let descriptor0 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Cube Field Descriptor",
+						descriptor_layout: cube_field::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+						],
+					});
+
+					let descriptor1 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Cube Field Instances Descriptor",
+						descriptor_layout: cube_field::DESCRIPTOR_SET_1_INFO,
+						bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(&self.cube_field_instances))],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor1, 1, pipeline);
+					fullscreen.cmd_bind_vertex_buffers(&[(BufferBindingDesc::ImportedBuffer(&self.cube.vertex_buffer), 0)]);
+					fullscreen.cmd_bind_index_buffer(BufferBindingDesc::ImportedBuffer(&self.cube.index_buffer), 0, IndexType::U16);
+					fullscreen.cmd_draw_indexed(self.cube.index_count, cube_field.len() as u32, self.cube.first_index, self.cube.vertex_offset, 0);
+				}
+
+				// Hold K to draw a two joint column next to the cube that bends as its palette is animated.
+				if debug_skinning {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Skinned Test Pipeline",
+						vs: &self.vs_skinned_test,
+						ps: Some(&self.ps_skinned_test),
+						gs: None,
+						descriptor_layouts: &[skinned_test::DESCRIPTOR_SET_0_INFO, skinned_test::DESCRIPTOR_SET_1_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::NoCull,
+						push_constant_bytes: 0,
+						vertex_input_info: SkinnedVertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Line,
+						specialization: &[],
+					});
+
+					let descriptor0 = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Skinned Test Descriptor",
+						descriptor_layout: skinned_test::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+							(1, DescriptorBindingDesc::PerFrameBuffer(&self.skinned_model_uniform)),
+						],
+					});
+
+					let descriptor1 = fullscreen.add_transient_graphics_descriptor_set(DescriptorDesc {
+						name: "Skinned Test Bones Descriptor",
+						descriptor_layout: skinned_test::DESCRIPTOR_SET_1_INFO,
+						bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(self.skin_palette.buffer()))],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor1, 1, pipeline);
+					fullscreen.cmd_draw_mesh(&self.skinned_test_mesh);
+				}
+
+				// Hold M to draw a grid of cubes in front of the camera, each one picking its own texture out of the same descriptor set with a push constant.
+				if let Some(materials) = self.bindless_materials.as_ref().filter(|_| debug_materials) {
+					let pipeline = fullscreen.add_raster_pipeline(RasterPipelineDesc {
+						name: "Bindless Materials Pipeline",
+						vs: &materials.vs,
+						ps: Some(&materials.ps),
+						gs: None,
+						descriptor_layouts: &[bindless_materials::DESCRIPTOR_SET_0_INFO, bindless_materials::DESCRIPTOR_SET_1_INFO],
+						render_pass,
+						depth_compare_op: None,
+						depth_write: false,
+						depth_bias: None,
+						blend_mode: BlendMode::Opaque,
+						face_cull: FaceCullMode::Back,
+						push_constant_bytes: bindless_materials::PUSH_CONSTANT_BYTES,
+						vertex_input_info: Vertex::VERTEX_INFO,
+						polygon_mode: PolygonMode::Fill,
+						specialization: &[],
+					});
+
+					let descriptor0 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Bindless Materials Camera Descriptor",
+						descriptor_layout: bindless_materials::DESCRIPTOR_SET_0_INFO,
+						bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform))],
+					});
+
+					let descriptor1 = fullscreen.add_graphics_descriptor_set(DescriptorDesc {
+						name: "Bindless Materials Descriptor",
+						descriptor_layout: bindless_materials::DESCRIPTOR_SET_1_INFO,
+						bindings: &mut [
+							(0, DescriptorBindingDesc::TextureArray(&material_textures)),
+							(1, DescriptorBindingDesc::ImportedTexture(material_textures[0])),
+						],
+					});
+
+					fullscreen.cmd_bind_raster_pipeline(pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor0, 0, pipeline);
+					fullscreen.cmd_bind_graphics_descriptor(descriptor1, 1, pipeline);
+					for i in 0..MATERIAL_COUNT {
+						fullscreen.cmd_push_constant_struct(pipeline, &bindless_materials::MaterialConstants { material_index: i as u32 });
+						fullscreen.cmd_draw_mesh(&self.cube);
+					}
+				}
+
+				self.particles.record_draw(&mut fullscreen, render_pass);
+				self.overlay.record(&mut fullscreen, render_pass);
 
 				fullscreen.cmd_end_render_pass();
 			}
 
-			render_graph.execute(graphics_context, graphics_device);
+			// Only rendered on the frame the mouse was clicked, with the cursor remapped into the viewport the picking attachment covers.
+			let pick_position = engine
+				.output_policy
+				.output_to_viewport(frame.output_size(), engine.cursor_position)
+				.filter(|_| engine.input.mouse_button_just_pressed(MouseButton::Left));
+			if pick_position.is_some() {
+				let mut picking_pass = render_graph.add_pass("picking");
+
+				let (object_ids, depth) = picking_attachments(output_size.width, output_size.height);
+				let mut object_ids = picking_pass.add_attachment(object_ids);
+				let mut depth = picking_pass.add_attachment(depth);
+
+				let descriptor = picking_pass.add_graphics_descriptor_set(DescriptorDesc {
+					name: "Picking Descriptor",
+					descriptor_layout: picking::DESCRIPTOR_SET_0_INFO,
+					bindings: &mut [
+						(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+						(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+					],
+				});
+
+				let render_pass = picking_pass.add_render_pass(RenderPassDesc {
+					name: "Picking render pass",
+					color_attachments: &mut [&mut object_ids],
+					depth_attachment: Some(&mut depth),
+				});
+
+				let pipeline = picking_pass.add_raster_pipeline(RasterPipelineDesc {
+					name: "Picking Pipeline",
+					vs: &self.vs_picking,
+					ps: Some(&self.ps_picking),
+					gs: None,
+					descriptor_layouts: &[picking::DESCRIPTOR_SET_0_INFO],
+					render_pass,
+					depth_compare_op: Some(DepthCompareOp::Greater),
+					depth_write: true,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
+					face_cull: FaceCullMode::Back,
+					push_constant_bytes: picking::PUSH_CONSTANT_BYTES,
+					vertex_input_info: Vertex::VERTEX_INFO,
+					polygon_mode: PolygonMode::Fill,
+					specialization: &[],
+				});
+
+				picking_pass.cmd_begin_render_pass(
+					render_pass,
+					&[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 }, ClearValue::DepthStencil { depth: 0.0, stencil: 0 }],
+				);
+
+				picking_pass.cmd_bind_raster_pipeline(pipeline);
+				picking_pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+				picking_pass.cmd_push_constant_struct(pipeline, &picking::PickingConstants { object_id: CUBE_OBJECT_ID });
+				picking_pass.cmd_draw_mesh(&self.cube);
+
+				picking_pass.cmd_end_render_pass();
+
+				drop(picking_pass);
+				render_graph.export_attachment(object_ids);
+			}
+
+			// Hold J to draw a pile of cubes sorted front to back, or U for the unsorted order. The sorted ones should shade far fewer fragments.
+			let sort_overdraw = engine.input.action_pressed("overdraw_sorted");
+			if sort_overdraw || engine.input.action_pressed("overdraw_unsorted") {
+				let mut overdraw_pass = render_graph.add_pass("overdraw");
 
-			graphics_context.end_frame(&engine.window);
+				let mut color = overdraw_pass.add_attachment(AttachmentDesc {
+					name: OVERDRAW_ATTACHMENT,
+					format: TextureFormat::RGBA8UNorm,
+					size: AttachmentSize::OUTPUT,
+					load_op: LoadOp::Clear,
+					store_op: StoreOp::Store,
+					usage: TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC,
+				});
+
+				let mut depth = overdraw_pass.add_attachment(AttachmentDesc {
+					name: "Overdraw depth",
+					format: TextureFormat::Depth,
+					size: AttachmentSize::OUTPUT,
+					load_op: LoadOp::Clear,
+					store_op: StoreOp::DontCare,
+					usage: TextureUsage::ATTACHMENT,
+				});
+
+				let descriptor = overdraw_pass.add_graphics_descriptor_set(DescriptorDesc {
+					name: "Overdraw Descriptor",
+					descriptor_layout: sorted_cubes::DESCRIPTOR_SET_0_INFO,
+					bindings: &mut [
+						(0, DescriptorBindingDesc::PerFrameBuffer(&self.camera_uniform)),
+						(1, DescriptorBindingDesc::PerFrameBuffer(&self.model_uniform)),
+					],
+				});
+
+				let render_pass = overdraw_pass.add_render_pass(RenderPassDesc {
+					name: "Overdraw render pass",
+					color_attachments: &mut [&mut color],
+					depth_attachment: Some(&mut depth),
+				});
+
+				let pipeline = overdraw_pass.add_raster_pipeline(RasterPipelineDesc {
+					name: "Sorted Cubes Pipeline",
+					vs: &self.vs_sorted_cubes,
+					ps: Some(&self.ps_sorted_cubes),
+					gs: None,
+					descriptor_layouts: &[sorted_cubes::DESCRIPTOR_SET_0_INFO],
+					render_pass,
+					depth_compare_op: Some(DepthCompareOp::Greater),
+					depth_write: true,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
+					face_cull: FaceCullMode::Back,
+					push_constant_bytes: sorted_cubes::PUSH_CONSTANT_BYTES,
+					vertex_input_info: Vertex::VERTEX_INFO,
+					polygon_mode: PolygonMode::Fill,
+					specialization: &[],
+				});
+
+				overdraw_pass.cmd_begin_render_pass(
+					render_pass,
+					&[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }, ClearValue::DepthStencil { depth: 0.0, stencil: 0 }],
+				);
+
+				overdraw_pass.cmd_bind_raster_pipeline(pipeline);
+				overdraw_pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+				overdraw_pass.cmd_begin_query(OVERDRAW_STATISTICS_QUERY);
+
+				let camera_position = self.camera_transform.position;
+				let camera_forward = self.camera_transform.forward();
+				for model in self.overdraw_cubes.iter() {
+					overdraw_pass.cmd_push_constant_struct(pipeline, &sorted_cubes::CubeConstants { model: *model });
+
+					if sort_overdraw {
+						let depth = (model.w_axis.xyz() - camera_position).dot(camera_forward).max(0.0);
+						overdraw_pass.cmd_draw_mesh_sorted(&self.cube, (depth * 1024.0) as u64);
+					} else {
+						overdraw_pass.cmd_draw_mesh(&self.cube);
+					}
+				}
+
+				// The query has to end after the sorted draws are recorded, so flush them here rather than leaving it to cmd_end_render_pass.
+				overdraw_pass.cmd_flush_sorted(true);
+				overdraw_pass.cmd_end_query(OVERDRAW_STATISTICS_QUERY);
+
+				overdraw_pass.cmd_end_render_pass();
+
+				drop(overdraw_pass);
+				render_graph.export_attachment(color);
+			}
+
+			if std::mem::take(&mut engine.dump_render_graph) {
+				match std::fs::write(RENDER_GRAPH_DUMP_PATH, render_graph.dump_dot()) {
+					Ok(_) => info!("Wrote render graph to {}", RENDER_GRAPH_DUMP_PATH),
+					Err(err) => error!("Failed to write render graph to {}: {}", RENDER_GRAPH_DUMP_PATH, err),
+				}
+			}
+
+			if let Err(err) = frame.execute_graph(render_graph, graphics_device) {
+				error!("Failed to execute render graph: {}", err);
+			}
+
+			if let Some(position) = pick_position.filter(|_| !frame.is_discarded()) {
+				let (x, y) = (position.x as u32, position.y as u32);
+				if let Some(object_ids) = self.render_graph_cache.exported_attachment(PICKING_ATTACHMENT) {
+					match frame.request_readback(ReadbackSource::Texture {
+						texture: object_ids,
+						x,
+						y,
+						width: 1,
+						height: 1,
+					}) {
+						Ok(id) => self.pending_pick = Some((id, x, y)),
+						Err(err) => error!("Failed to read picking attachment: {}", err),
+					}
+				}
+			}
+		});
+
+		// The object ID only comes back once the GPU is done with the frame that picked it, usually a couple of frames later.
+		if let Some((id, x, y)) = self.pending_pick {
+			if let Some(texel) = graphics_context.poll_readback(id) {
+				info!("Picked object {} at ({}, {})", u32::from_ne_bytes(texel[..4].try_into().unwrap()), x, y);
+				self.pending_pick = None;
+			}
 		}
 	}
 
+	// The reverse of the order on_load creates everything in. The render graph cache goes first since its pipelines and framebuffers are
+	// made out of the shaders and textures below, and it waits on any pipeline still compiling on a job. The upload context goes once
+	// nothing is left that could upload through it, and the shaders go last since everything above was drawing with them.
 	fn destroy(self, engine: &mut GoldfishEngine) {
 		let graphics_device = &mut engine.graphics_device;
 		self.render_graph_cache.destroy(graphics_device);
 
-		graphics_device.destroy_buffer(self.light_cull_cbuffer);
-		graphics_device.destroy_buffer(self.camera_uniform);
-		graphics_device.destroy_buffer(self.model_uniform);
+		if let Some(skybox) = self.skybox {
+			graphics_device.destroy_texture(skybox.cubemap);
+			graphics_device.destroy_shader(skybox.vs);
+			graphics_device.destroy_shader(skybox.ps);
+		}
+		if let Some(materials) = self.bindless_materials {
+			for texture in materials.textures {
+				graphics_device.destroy_texture(texture);
+			}
+			graphics_device.destroy_shader(materials.vs);
+			graphics_device.destroy_shader(materials.ps);
+		}
+
+		self.shadow_lights.destroy(graphics_device);
+		self.cube_field_instances.destroy(graphics_device);
+		self.skinned_model_uniform.destroy(graphics_device);
+		self.skin_palette.destroy(graphics_device);
+		engine.geometry_pool.free_mesh(graphics_device, self.vertex_color_test_mesh);
+		engine.geometry_pool.free_mesh(graphics_device, self.skinned_test_mesh);
+		engine.geometry_pool.free_mesh(graphics_device, self.cube);
+		self.lights.destroy(graphics_device);
+		self.fullscreen_cbuffer.destroy(graphics_device);
+		self.light_cull_cbuffer.destroy(graphics_device);
 		graphics_device.destroy_buffer(self.depth_debug_cbuffer);
-		graphics_device.destroy_mesh(self.cube);
+		self.model_uniform.destroy(graphics_device);
+		self.camera_uniform.destroy(graphics_device);
+
+		self.fullscreen_blit.destroy(graphics_device);
+		self.depth_prepass.destroy(graphics_device);
+		self.overlay.destroy(graphics_device);
+		self.particles.destroy(graphics_device);
 		graphics_device.destroy_upload_context(self.upload_context);
-		graphics_device.destroy_shader(self.vs);
-		graphics_device.destroy_shader(self.ps);
-		graphics_device.destroy_shader(self.vs_textured);
-		graphics_device.destroy_shader(self.ps_textured);
-		graphics_device.destroy_shader(self.vs_fullscreen);
-		graphics_device.destroy_shader(self.ps_fullscreen);
-		graphics_device.destroy_shader(self.ps_depth_debug);
+
+		graphics_device.destroy_shader(self.ps_vertex_colors_alpha_test);
+		graphics_device.destroy_shader(self.ps_vertex_colors);
+		graphics_device.destroy_shader(self.vs_vertex_colors);
+		graphics_device.destroy_shader(self.ps_forward_plus);
+		graphics_device.destroy_shader(self.vs_forward_plus);
+		graphics_device.destroy_shader(self.ps_sorted_cubes);
+		graphics_device.destroy_shader(self.vs_sorted_cubes);
+		graphics_device.destroy_shader(self.ps_shadowed);
+		graphics_device.destroy_shader(self.vs_shadowed);
+		graphics_device.destroy_shader(self.vs_shadow_depth);
+		graphics_device.destroy_shader(self.ps_cube_field);
+		graphics_device.destroy_shader(self.vs_cube_field);
+		graphics_device.destroy_shader(self.ps_skinned_test);
+		graphics_device.destroy_shader(self.vs_skinned_test);
+		graphics_device.destroy_shader(self.ps_picking);
+		graphics_device.destroy_shader(self.vs_picking);
 		graphics_device.destroy_shader(self.cs_light_cull);
+		graphics_device.destroy_shader(self.ps_debug_normals);
+		graphics_device.destroy_shader(self.gs_debug_normals);
+		graphics_device.destroy_shader(self.vs_debug_normals);
+		graphics_device.destroy_shader(self.ps_depth_debug);
+		graphics_device.destroy_shader(self.ps_temporal_blend);
+		graphics_device.destroy_shader(self.vs_temporal_blend);
+		graphics_device.destroy_shader(self.cs_fullscreen);
+		self.fullscreen_shader.destroy(graphics_device);
+		graphics_device.destroy_shader(self.ps_textured);
+		graphics_device.destroy_shader(self.vs_textured);
+		graphics_device.destroy_shader(self.ps);
+		graphics_device.destroy_shader(self.vs);
+	}
+}
+
+// A square column with a joint at its base and another halfway up, the vertices around the middle blend between the two.
+fn skinned_test_package() -> MeshPackage {
+	const RINGS: usize = 9;
+	const HEIGHT: f32 = 2.0;
+	const HALF_WIDTH: f32 = 0.1;
+	let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+	let mut vertices = Vec::new();
+	let mut bone_indices = Vec::new();
+	let mut bone_weights = Vec::new();
+	for ring in 0..RINGS {
+		let y = HEIGHT * ring as f32 / (RINGS - 1) as f32;
+		let upper_weight = ((y - 0.75) / 0.5).clamp(0.0, 1.0);
+
+		for &(x, z) in corners.iter() {
+			vertices.push(Vertex {
+				position: Vec3::new(x * HALF_WIDTH, y, z * HALF_WIDTH),
+				normal: Vec3::new(x, 0.0, z).normalize(),
+				uv: Vec2::new(x * 0.5 + 0.5, y / HEIGHT),
+				tangent: Vec3::Y,
+				bitangent: Vec3::new(-z, 0.0, x).normalize(),
+			});
+			bone_indices.push([0, 1, 0, 0]);
+			bone_weights.push([1.0 - upper_weight, upper_weight, 0.0, 0.0]);
+		}
+	}
+
+	let mut indices = Vec::new();
+	for ring in 0..RINGS - 1 {
+		for side in 0..corners.len() {
+			let a = (ring * corners.len() + side) as u16;
+			let b = (ring * corners.len() + (side + 1) % corners.len()) as u16;
+			let (c, d) = (a + corners.len() as u16, b + corners.len() as u16);
+			indices.extend_from_slice(&[a, c, b, b, c, d]);
+		}
+	}
+
+	let joints = vec![
+		JointPackage {
+			name: "Lower".to_string(),
+			parent: None,
+			inverse_bind_matrix: Mat4::IDENTITY.to_cols_array(),
+			rest_transform: Mat4::IDENTITY.to_cols_array(),
+		},
+		JointPackage {
+			name: "Upper".to_string(),
+			parent: Some(0),
+			inverse_bind_matrix: Mat4::from_translation(Vec3::new(0.0, -HEIGHT / 2.0, 0.0)).to_cols_array(),
+			rest_transform: Mat4::from_translation(Vec3::new(0.0, HEIGHT / 2.0, 0.0)).to_cols_array(),
+		},
+	];
+
+	MeshPackage {
+		vertices,
+		indices,
+		skin: Some(SkinPackage { bone_indices, bone_weights, joints }),
+		colors: None,
+		uv1: None,
+	}
+}
+
+// A quad with a different color in each corner and a flipped second uv set, like an imported mesh with COLOR_0 and TEXCOORD_1.
+fn vertex_color_test_package() -> MeshPackage {
+	let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+	let vertices = corners
+		.iter()
+		.map(|&(x, y)| Vertex {
+			position: Vec3::new(x * 0.5, y * 0.5, 0.0),
+			normal: Vec3::Z,
+			uv: Vec2::new(x * 0.5 + 0.5, y * 0.5 + 0.5),
+			tangent: Vec3::X,
+			bitangent: Vec3::Y,
+		})
+		.collect::<Vec<_>>();
+	let uv1 = vertices.iter().map(|vertex| [1.0 - vertex.uv.x, 1.0 - vertex.uv.y]).collect();
+
+	MeshPackage {
+		vertices,
+		indices: vec![0, 1, 2, 0, 2, 3],
+		skin: None,
+		// The green and white corners are mostly transparent, so they're what the ALPHA_TEST permutation cuts away.
+		colors: Some(vec![[255, 0, 0, 255], [0, 255, 0, 64], [0, 0, 255, 255], [255, 255, 255, 64]]),
+		uv1: Some(uv1),
+	}
+}
+
+// The picking pass' object ID and depth attachments, on_load describes the same render pass to precompile the picking pipeline.
+fn picking_attachments(width: u32, height: u32) -> (AttachmentDesc, AttachmentDesc) {
+	let object_ids = AttachmentDesc {
+		name: PICKING_ATTACHMENT,
+		format: TextureFormat::R32UInt,
+		size: AttachmentSize::Absolute(width, height),
+		load_op: LoadOp::Clear,
+		store_op: StoreOp::Store,
+		usage: TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC,
+	};
+
+	let depth = AttachmentDesc {
+		name: "Picking depth",
+		format: TextureFormat::Depth,
+		size: AttachmentSize::Absolute(width, height),
+		load_op: LoadOp::Clear,
+		store_op: StoreOp::DontCare,
+		usage: TextureUsage::ATTACHMENT,
+	};
+
+	(object_ids, depth)
+}
+
+fn spawn_cube_field(registry: &mut Registry) {
+	let half_extent = (CUBE_FIELD_SIZE - 1) as f32 * CUBE_FIELD_SPACING * 0.5;
+
+	for i in 0..CUBE_FIELD_COUNT {
+		let (x, z) = ((i % CUBE_FIELD_SIZE) as f32, (i / CUBE_FIELD_SIZE) as f32);
+
+		let entity = registry.spawn();
+		registry.insert(
+			entity,
+			Transform {
+				position: Vec3::new(x * CUBE_FIELD_SPACING - half_extent, -2.0, z * CUBE_FIELD_SPACING - half_extent),
+				rotation: Quat::IDENTITY,
+				scale: Vec3::splat(CUBE_FIELD_SCALE),
+			},
+		);
+		registry.insert(
+			entity,
+			Spin {
+				axis: Vec3::new(x + 1.0, z + 1.0, 1.0).normalize(),
+				speed: 0.5 + (i % 7) as f32 * 0.25,
+			},
+		);
+	}
+}
+
+// Random positions from a fixed seed, so that the sorted and unsorted draws always get the same cubes.
+fn overdraw_cube_transforms() -> Vec<Mat4> {
+	let mut state = 0x2545_f491_u32;
+	let mut random = move || {
+		// xorshift32
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state as f32 / u32::MAX as f32
+	};
+
+	(0..OVERDRAW_CUBE_COUNT)
+		.map(|_| {
+			let position = Vec3::new(random() * 4.0 - 2.0, random() * 3.0 - 1.5, random() * 6.0 + 2.0);
+			Mat4::from_scale_rotation_translation(Vec3::splat(OVERDRAW_CUBE_SCALE), Quat::IDENTITY, position)
+		})
+		.collect()
+}
+
+// A checkerboard of white and a color that's different for every material.
+fn material_texture_data(material: usize) -> Vec<u8> {
+	let color = [(material % 4 * 85) as u8, (material / 4 % 4 * 85) as u8, (material / 16 * 85) as u8, 255];
+
+	(0..MATERIAL_TEXTURE_SIZE * MATERIAL_TEXTURE_SIZE)
+		.flat_map(|i| if (i % MATERIAL_TEXTURE_SIZE + i / MATERIAL_TEXTURE_SIZE) % 2 == 0 { color } else { [255; 4] })
+		.collect()
+}
+
+// A short decaying tone, generated here so the sample game doesn't need an audio file in the asset directory.
+fn click_sound_package() -> AudioPackage {
+	const SAMPLE_RATE: u32 = 44100;
+	const LENGTH: f32 = 0.03;
+	const FREQUENCY: f32 = 2000.0;
+
+	let sample_count = (SAMPLE_RATE as f32 * LENGTH) as usize;
+	let samples = (0..sample_count)
+		.map(|i| {
+			let time = i as f32 / SAMPLE_RATE as f32;
+			let envelope = (1.0 - i as f32 / sample_count as f32).powi(3);
+			let tone = (time * FREQUENCY * std::f32::consts::TAU).sin();
+			(tone * envelope * i16::MAX as f32) as i16
+		})
+		.collect();
+
+	AudioPackage {
+		sample_rate: SAMPLE_RATE,
+		channels: 1,
+		samples,
 	}
 }
 
 extern "C" fn on_load(engine: &mut GoldfishEngine) {
+	goldfish::logging::attach(engine);
+
+	let game_config = engine.game_config();
+	let mouse_sensitivity = game_config.get("mouse_sensitivity").and_then(|v| v.as_float()).unwrap_or(DEFAULT_MOUSE_SENSITIVITY);
+	let move_speed = game_config.get("move_speed").and_then(|v| v.as_float()).unwrap_or(DEFAULT_MOVE_SPEED);
+	// Keeps the scene at this width over height with bars around it, otherwise it's stretched over the whole window.
+	if let Some(aspect) = game_config.get("letterbox_aspect").and_then(|v| v.as_float()) {
+		engine.output_policy = OutputPolicy::Letterbox {
+			aspect: aspect as f32,
+			bar_color: Color::BLACK,
+		};
+	}
+
+	// Anything bound in the config file takes priority over these.
+	let input_map = &mut engine.input.map;
+	input_map.bind_default_axis("move_z", Key::W, Key::S);
+	input_map.bind_default_axis("move_x", Key::D, Key::A);
+	input_map.bind_default_axis("move_y", Key::E, Key::Q);
+	input_map.bind_default_action("small_tiles", &[Key::T]);
+	input_map.bind_default_action("debug_normals", &[Key::N]);
+	input_map.bind_default_action("wireframe", &[Key::L]);
+	input_map.bind_default_action("smear", &[Key::B]);
+	input_map.bind_default_action("accumulate", &[Key::V]);
+	input_map.bind_default_action("blit_output", &[Key::O]);
+	input_map.bind_default_action("shader_blit_output", &[Key::I]);
+	input_map.bind_default_action("raster_output", &[Key::R]);
+	input_map.bind_default_action("skip_late_latch", &[Key::X]);
+	input_map.bind_default_action("debug_skinning", &[Key::K]);
+	input_map.bind_default_action("click", &[Key::C]);
+	input_map.bind_default_action("debug_shadows", &[Key::H]);
+	input_map.bind_default_action("debug_materials", &[Key::M]);
+	input_map.bind_default_action("debug_vertex_colors", &[Key::Y]);
+	input_map.bind_default_action("console", &[Key::Grave]);
+	input_map.bind_default_action("overdraw_sorted", &[Key::J]);
+	input_map.bind_default_action("overdraw_unsorted", &[Key::U]);
+	input_map.bind_default_action("particles", &[Key::P]);
+	input_map.bind_default_action("slow_motion", &[Key::G]);
+	input_map.bind_default_action("skybox", &[Key::Z]);
+	input_map.bind_default_action("capture_frame", &[Key::F10]);
+	input_map.bind_default_action("gpu_capture", &[Key::F11]);
+
+	let click_sound = engine.audio.create_clip(&click_sound_package());
+
 	let graphics_device = &mut engine.graphics_device;
 
-	let vs = graphics_device.create_shader(&test_shader::VS_BYTES);
-	let ps = graphics_device.create_shader(&test_shader::PS_BYTES);
+	let vs = graphics_device.create_shader(&test_shader::VS_BYTES).expect("Failed to create shader!");
+	let ps = graphics_device.create_shader(&test_shader::PS_BYTES).expect("Failed to create shader!");
+
+	let vs_textured = graphics_device.create_shader(&test_sampler::VS_BYTES).expect("Failed to create shader!");
+	let ps_textured = graphics_device.create_shader(&test_sampler::PS_BYTES).expect("Failed to create shader!");
+
+	let vs_fullscreen = graphics_device.create_shader(&fullscreen::VS_BYTES).expect("Failed to create shader!");
+	let ps_fullscreen = graphics_device.create_shader(&fullscreen::PS_BYTES).expect("Failed to create shader!");
+	let fullscreen_shader = ReloadableShader::new(FULLSCREEN_SHADER, vs_fullscreen, Some(ps_fullscreen));
+	let cs_fullscreen = graphics_device.create_shader(&fullscreen_compute::CS_BYTES).expect("Failed to create shader!");
+	let vs_temporal_blend = graphics_device.create_shader(&temporal_blend::VS_BYTES).expect("Failed to create shader!");
+	let ps_temporal_blend = graphics_device.create_shader(&temporal_blend::PS_BYTES).expect("Failed to create shader!");
+
+	let ps_depth_debug = graphics_device.create_shader(&debug_depth::PS_BYTES).expect("Failed to create shader!");
 
-	let vs_textured = graphics_device.create_shader(&test_sampler::VS_BYTES);
-	let ps_textured = graphics_device.create_shader(&test_sampler::PS_BYTES);
+	let vs_debug_normals = graphics_device.create_shader(&debug_normals::VS_BYTES).expect("Failed to create shader!");
+	let gs_debug_normals = graphics_device.create_shader(&debug_normals::GS_BYTES).expect("Failed to create shader!");
+	let ps_debug_normals = graphics_device.create_shader(&debug_normals::PS_BYTES).expect("Failed to create shader!");
 
-	let vs_fullscreen = graphics_device.create_shader(&fullscreen::VS_BYTES);
-	let ps_fullscreen = graphics_device.create_shader(&fullscreen::PS_BYTES);
+	let cs_light_cull = graphics_device.create_shader(&light_cull_compute::CS_BYTES).expect("Failed to create shader!");
 
-	let ps_depth_debug = graphics_device.create_shader(&debug_depth::PS_BYTES);
+	let vs_picking = graphics_device.create_shader(&picking::VS_BYTES).expect("Failed to create shader!");
+	let ps_picking = graphics_device.create_shader(&picking::PS_BYTES).expect("Failed to create shader!");
 
-	let cs_light_cull = graphics_device.create_shader(&light_cull_compute::CS_BYTES);
+	let vs_skinned_test = graphics_device.create_shader(&skinned_test::VS_BYTES).expect("Failed to create shader!");
+	let ps_skinned_test = graphics_device.create_shader(&skinned_test::PS_BYTES).expect("Failed to create shader!");
+
+	let vs_cube_field = graphics_device.create_shader(&cube_field::VS_BYTES).expect("Failed to create shader!");
+	let ps_cube_field = graphics_device.create_shader(&cube_field::PS_BYTES).expect("Failed to create shader!");
+
+	let vs_shadow_depth = graphics_device.create_shader(&shadow_depth::VS_BYTES).expect("Failed to create shader!");
+	let vs_shadowed = graphics_device.create_shader(&shadowed::VS_BYTES).expect("Failed to create shader!");
+	let ps_shadowed = graphics_device.create_shader(&shadowed::PS_BYTES).expect("Failed to create shader!");
+
+	let vs_sorted_cubes = graphics_device.create_shader(&sorted_cubes::VS_BYTES).expect("Failed to create shader!");
+	let ps_sorted_cubes = graphics_device.create_shader(&sorted_cubes::PS_BYTES).expect("Failed to create shader!");
+	let vs_forward_plus = graphics_device.create_shader(&forward_plus::VS_BYTES).expect("Failed to create shader!");
+	let ps_forward_plus = graphics_device.create_shader(&forward_plus::PS_BYTES).expect("Failed to create shader!");
+	let vs_vertex_colors = graphics_device
+		.create_shader(vertex_colors::vs_bytes(vertex_colors::PermutationFlags::empty()))
+		.expect("Failed to create shader!");
+	let ps_vertex_colors = graphics_device
+		.create_shader(vertex_colors::ps_bytes(vertex_colors::PermutationFlags::empty()))
+		.expect("Failed to create shader!");
+	let ps_vertex_colors_alpha_test = graphics_device
+		.create_shader(vertex_colors::ps_bytes(vertex_colors::PermutationFlags::ALPHA_TEST))
+		.expect("Failed to create shader!");
+
+	let vs_overlay = graphics_device.create_shader(&debug_overlay::VS_BYTES).expect("Failed to create shader!");
+	let ps_overlay = graphics_device.create_shader(&debug_overlay::PS_BYTES).expect("Failed to create shader!");
 
 	let mut upload_context = graphics_device.create_upload_context();
 
-	let camera_uniform = upload_context.create_buffer(common_inc::Camera::size(), MemoryLocation::CpuToGpu, BufferUsage::UniformBuffer, None, None);
+	let particles = ParticleSystem::new(
+		&mut upload_context,
+		MAX_PARTICLES,
+		ParticleShaders {
+			cs_emit: graphics_device.create_shader(&particles_emit::CS_BYTES).expect("Failed to create shader!"),
+			emit_layout: particles_emit::DESCRIPTOR_SET_0_INFO,
+			emit_local_size: particles_emit::CS_LOCAL_SIZE,
+			cs_update: graphics_device.create_shader(&particles_update::CS_BYTES).expect("Failed to create shader!"),
+			update_layout: particles_update::DESCRIPTOR_SET_0_INFO,
+			update_local_size: particles_update::CS_LOCAL_SIZE,
+			vs: graphics_device.create_shader(&particles_draw::VS_BYTES).expect("Failed to create shader!"),
+			ps: graphics_device.create_shader(&particles_draw::PS_BYTES).expect("Failed to create shader!"),
+			draw_layout: particles_draw::DESCRIPTOR_SET_0_INFO,
+		},
+	)
+	.expect("Failed to create particle system!");
 
-	let model_uniform = upload_context.create_buffer(common_inc::Model::size(), MemoryLocation::CpuToGpu, BufferUsage::UniformBuffer, None, None);
+	let overlay = DebugOverlay::new(&mut upload_context, vs_overlay, ps_overlay, debug_overlay::DESCRIPTOR_SET_0_INFO).expect("Failed to create debug overlay!");
 
-	let depth_debug_cbuffer = upload_context.create_buffer(
-		debug_depth::NearPlane::size(),
-		MemoryLocation::CpuToGpu,
-		BufferUsage::UniformBuffer,
-		None,
-		Some(&debug_depth::NearPlane { z_near: Z_NEAR, z_scale: 0.02 }.as_buffer()),
+	let depth_prepass = DepthPrepass::new(
+		graphics_device.create_shader(&test_shader::VS_BYTES).expect("Failed to create shader!"),
+		test_shader::DESCRIPTOR_SET_0_INFO,
+		Vertex::VERTEX_INFO,
 	);
+	let fullscreen_blit = FullscreenBlit::new(
+		graphics_device.create_shader(&fullscreen_blit::VS_BYTES).expect("Failed to create shader!"),
+		graphics_device.create_shader(&fullscreen_blit::PS_BYTES).expect("Failed to create shader!"),
+		fullscreen_blit::DESCRIPTOR_SET_0_INFO,
+	);
+
+	let camera_uniform = PerFrameBuffer::new(&mut upload_context, "Camera Uniform", common_inc::Camera::size(), BufferUsage::UniformBuffer).expect("Failed to create buffer!");
+	let model_uniform = PerFrameBuffer::new(&mut upload_context, "Model Uniform", common_inc::Model::size(), BufferUsage::UniformBuffer).expect("Failed to create buffer!");
+
+	let depth_debug_cbuffer = upload_context
+		.create_buffer(
+			"Depth Debug Near Plane",
+			debug_depth::NearPlane::size(),
+			MemoryLocation::CpuToGpu,
+			BufferUsage::UniformBuffer,
+			None,
+			Some(&debug_depth::NearPlane { z_near: Z_NEAR, z_scale: 0.02 }.as_buffer()),
+		)
+		.expect("Failed to create buffer!");
+
+	let light_cull_cbuffer = PerFrameBuffer::new(&mut upload_context, "Light Cull Info", light_cull_compute::CullInfo::size(), BufferUsage::UniformBuffer).expect("Failed to create buffer!");
+	let fullscreen_cbuffer =
+		PerFrameBuffer::new(&mut upload_context, "Fullscreen Constants", fullscreen_compute::FullscreenConstants::size(), BufferUsage::UniformBuffer).expect("Failed to create buffer!");
+	let mut lights = LightManager::new(&mut upload_context, MAX_POINT_LIGHTS).expect("Failed to create light manager!");
+	let light_ids = LIGHT_COLORS.map(|color| {
+		lights.add_point_light(PointLight {
+			position: Vec3::ZERO,
+			radius: LIGHT_RADIUS,
+			color,
+		})
+	});
 
-	let light_cull_cbuffer = upload_context.create_buffer(light_cull_compute::CullInfo::size(), MemoryLocation::CpuToGpu, BufferUsage::UniformBuffer, None, None);
-	let point_lights_sbuffer = upload_context.create_buffer(light_cull_compute::PointLight::size() * 3, MemoryLocation::CpuToGpu, BufferUsage::StorageBuffer, None, None);
+	// The cubemap is the biggest package the game reads, so it's read on a job while the cube gets uploaded.
+	let skybox_read = engine.uuid_for_path(SKYBOX_CUBEMAP, AssetType::Texture).map(|uuid| engine.read_package_async(uuid, AssetType::Texture));
 
-	let Package::Mesh(mesh_package) = engine.read_package(
-			uuid!("471cb8ab-2bd0-4e91-9ea9-0d0573cb9e0a"),
+	let Package::Mesh(mesh_package) = engine.read_package_by_path(
+			CUBE_MESH,
 			AssetType::Mesh,
 	      ).expect("Failed to load mesh package!") else
 	      {
 	          panic!("Incorrect package type loaded?");
 	      };
 
-	let cube = upload_context.create_mesh(&mesh_package.vertices, &mesh_package.indices);
-
-	let render_graph_cache = RenderGraphCache::default();
+	let cube = engine
+		.geometry_pool
+		.upload_mesh(&mut upload_context, "Cube", &mesh_package.vertices, &mesh_package.indices)
+		.expect("Failed to create mesh!");
+
+	// Unlike the cube the skybox isn't needed for anything else, so the game still runs without it.
+	let skybox_package = match skybox_read.and_then(|read| read.wait()) {
+		Ok(Package::Texture(package)) if package.format.is_cubemap() => Some(package),
+		Ok(_) => {
+			warn!("The skybox is disabled, {} wasn't imported as a cubemap!", SKYBOX_CUBEMAP);
+			None
+		}
+		Err(err) => {
+			warn!("The skybox is disabled! {}", err);
+			None
+		}
+	};
+
+	let skinned_test_package = skinned_test_package();
+	let skinned_test_mesh = engine
+		.geometry_pool
+		.upload_skinned_mesh(&mut upload_context, "Skinned Test", &skinned_test_package.skinned_vertices().unwrap(), &skinned_test_package.indices)
+		.expect("Failed to create mesh!");
+	let vertex_color_test_mesh = engine
+		.geometry_pool
+		.upload_mesh_package(&mut upload_context, "Vertex Color Test", &vertex_color_test_package())
+		.expect("Failed to create mesh!");
+	let skin_palette = SkinPalette::new(&mut upload_context, "Skinned Test Bones", skinned_test_package.skin.as_ref().unwrap(), skinning_inc::Bone::size()).expect("Failed to create skin palette!");
+	let skinned_model_uniform = PerFrameBuffer::new(&mut upload_context, "Skinned Test Model Uniform", common_inc::Model::size(), BufferUsage::UniformBuffer).expect("Failed to create buffer!");
+
+	let mut registry = Registry::new();
+	spawn_cube_field(&mut registry);
+	let cube_field_instances =
+		PerFrameBuffer::new(&mut upload_context, "Cube Field Instances", cube_field::Instance::size() * CUBE_FIELD_COUNT, BufferUsage::StorageBuffer).expect("Failed to create buffer!");
+	let shadow_lights = PerFrameBuffer::new(&mut upload_context, "Shadow Lights", shadowed::ShadowLight::size() * SHADOW_CASTERS.len(), BufferUsage::StorageBuffer).expect("Failed to create buffer!");
+
+	let bindless_materials = match graphics_device.check_descriptor_set_support(&bindless_materials::DESCRIPTOR_SET_1_INFO.to_bindings()) {
+		Ok(_) => Some(BindlessMaterials {
+			vs: graphics_device.create_shader(&bindless_materials::VS_BYTES).expect("Failed to create shader!"),
+			ps: graphics_device.create_shader(&bindless_materials::PS_BYTES).expect("Failed to create shader!"),
+			textures: (0..MATERIAL_COUNT)
+				.map(|i| {
+					upload_context
+						.create_texture(
+							"Material",
+							MATERIAL_TEXTURE_SIZE,
+							MATERIAL_TEXTURE_SIZE,
+							TextureFormat::RGBA8UNorm,
+							TextureUsage::SAMPLED,
+							Some(&material_texture_data(i)),
+						)
+						.expect("Failed to create texture!")
+				})
+				.collect(),
+		}),
+		Err(err) => {
+			warn!("Bindless materials are disabled! {}", err);
+			None
+		}
+	};
+
+	let skybox_cubemap = skybox_package.map(|package| upload_context.create_texture("Skybox", package.width, package.height, package.format, TextureUsage::SAMPLED, Some(&package.data)));
+	let skybox = match skybox_cubemap {
+		Some(Ok(cubemap)) => Some(Skybox {
+			vs: graphics_device.create_shader(&skybox::VS_BYTES).expect("Failed to create shader!"),
+			ps: graphics_device.create_shader(&skybox::PS_BYTES).expect("Failed to create shader!"),
+			cubemap,
+		}),
+		Some(Err(err)) => {
+			warn!("The skybox is disabled, failed to create its cubemap! {}", err);
+			None
+		}
+		None => None,
+	};
+
+	let mut render_graph_cache = RenderGraphCache::default();
+	render_graph_cache.set_async_pipeline_compilation(true);
+	render_graph_cache.set_submit_batching(SubmitBatching::Hints);
+
+	// Both of these only show up on demand, so compile them now rather than on the frame they're first needed.
+	let (picking_ids, picking_depth) = picking_attachments(0, 0);
+	render_graph_cache
+		.precompile(
+			&mut engine.graphics_context,
+			graphics_device,
+			&[
+				RasterPipelinePrecompileDesc {
+					vs: &vs,
+					ps: Some(&ps),
+					gs: None,
+					descriptor_layouts: &[test_shader::DESCRIPTOR_SET_0_INFO],
+					render_pass: PrecompileRenderPass::Output,
+					depth_compare_op: None,
+					depth_write: false,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
+					face_cull: FaceCullMode::NoCull,
+					push_constant_bytes: 0,
+					vertex_input_info: Vertex::VERTEX_INFO,
+					polygon_mode: PolygonMode::Line,
+					specialization: &[],
+				},
+				RasterPipelinePrecompileDesc {
+					vs: &vs_picking,
+					ps: Some(&ps_picking),
+					gs: None,
+					descriptor_layouts: &[picking::DESCRIPTOR_SET_0_INFO],
+					render_pass: PrecompileRenderPass::Attachments {
+						color_attachments: &[picking_ids],
+						depth_attachment: Some(picking_depth),
+					},
+					depth_compare_op: Some(DepthCompareOp::Greater),
+					depth_write: true,
+					depth_bias: None,
+					blend_mode: BlendMode::Opaque,
+					face_cull: FaceCullMode::Back,
+					push_constant_bytes: picking::PUSH_CONSTANT_BYTES,
+					vertex_input_info: Vertex::VERTEX_INFO,
+					polygon_mode: PolygonMode::Fill,
+					specialization: &[],
+				},
+			],
+		)
+		.expect("Failed to precompile pipelines!");
 
 	let game = Box::new(Game {
 		vs,
 		ps,
 		vs_textured,
 		ps_textured,
-		vs_fullscreen,
-		ps_fullscreen,
+		fullscreen_shader,
+		cs_fullscreen,
+		vs_temporal_blend,
+		ps_temporal_blend,
 		ps_depth_debug,
+		vs_debug_normals,
+		gs_debug_normals,
+		ps_debug_normals,
 		cs_light_cull,
+		vs_picking,
+		ps_picking,
+		vs_skinned_test,
+		ps_skinned_test,
+		vs_cube_field,
+		ps_cube_field,
+		vs_shadow_depth,
+		vs_shadowed,
+		ps_shadowed,
+		vs_sorted_cubes,
+		ps_sorted_cubes,
+		vs_forward_plus,
+		ps_forward_plus,
+		vs_vertex_colors,
+		ps_vertex_colors,
+		ps_vertex_colors_alpha_test,
 
 		light_cull_cbuffer,
-		point_lights: Default::default(),
-		point_lights_sbuffer,
+		fullscreen_cbuffer,
+		lights,
+		light_ids,
 
 		depth_debug_cbuffer,
 		cube,
+		skinned_test_mesh,
+		vertex_color_test_mesh,
+		skin_palette,
 		upload_context,
+		click_sound,
 		camera_uniform,
 		model_uniform,
+		skinned_model_uniform,
+		cube_field_instances,
+		shadow_lights,
+		overdraw_cubes: overdraw_cube_transforms(),
+		particles,
+		depth_prepass,
+		fullscreen_blit,
+		shadow_atlas: ShadowAtlas::new(SHADOW_ATLAS_SIZE),
+		bindless_materials,
+		skybox,
 		camera_transform: Transform {
 			position: Vec3 { x: 0.0, y: 0.0, z: -1.0 },
 			..Default::default()
@@ -470,13 +2162,27 @@ extern "C" fn on_load(engine: &mut GoldfishEngine) {
 			scale: Vec3 { x: 1.0, y: 1.0, z: 1.0 },
 			..Default::default()
 		},
+		registry,
+		mouse_sensitivity,
+		move_speed,
 		render_graph_cache,
+		pending_pick: None,
+		last_stats_print: Instant::now(),
+		skinning_start: Instant::now(),
+		lights_start: Instant::now(),
+		overlay,
+		console: None,
 	});
 
 	engine.game_state = Box::into_raw(game) as *mut ();
 }
 
 extern "C" fn on_unload(engine: &mut GoldfishEngine) {
+	// The release of the right mouse button would go to the next version of the library, which doesn't know it locked anything.
+	if let Err(err) = engine.unlock_cursor() {
+		warn!("Failed to unlock the cursor! {}", err);
+	}
+
 	let game = unsafe { Box::from_raw(engine.game_state as *mut Game) };
 	game.destroy(engine);
 
@@ -488,7 +2194,113 @@ extern "C" fn on_update(engine: &mut GoldfishEngine) {
 	game.update(engine);
 }
 
-#[no_mangle]
-extern "C" fn _goldfish_create_game_lib() -> GameLib {
-	GameLib { on_load, on_unload, on_update }
+goldfish::game::declare_game!(on_load, on_unload, on_update);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn write_f32(buffer: &mut [u8], offset: usize, value: f32) {
+		buffer[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+	}
+
+	#[test]
+	fn cbuffer_arrays_and_nested_structs_match_hand_layout() {
+		let mut constants = cbuffer_layout_test::LayoutTestConstants {
+			weights: [1.0, 2.0, 3.0, 4.0],
+			count: 8,
+			..Default::default()
+		};
+		for (i, light) in constants.lights.iter_mut().enumerate() {
+			light.pos = Vec3::new(i as f32, i as f32 + 0.25, i as f32 + 0.5);
+			light.radius = 10.0 + i as f32;
+		}
+
+		// weights is 4 elements with a 16 byte stride, lights is 8 elements of 16 bytes each right after it and count comes last.
+		let mut expected = vec![0u8; 196];
+		for (i, weight) in constants.weights.iter().enumerate() {
+			write_f32(&mut expected, i * 16, *weight);
+		}
+		for (i, light) in constants.lights.iter().enumerate() {
+			let offset = 64 + i * 16;
+			write_f32(&mut expected, offset, light.pos.x);
+			write_f32(&mut expected, offset + 4, light.pos.y);
+			write_f32(&mut expected, offset + 8, light.pos.z);
+			write_f32(&mut expected, offset + 12, light.radius);
+		}
+		expected[192..196].copy_from_slice(&8u32.to_ne_bytes());
+
+		assert_eq!(cbuffer_layout_test::LayoutTestConstants::size(), expected.len());
+		assert_eq!(constants.as_buffer().as_slice(), expected.as_slice());
+	}
+
+	#[test]
+	fn reflected_members_match_hand_layout() {
+		use goldfish::build::{MemberInfo, MemberTypeRepr};
+
+		let light = [
+			MemberInfo {
+				name: "pos",
+				offset: 0,
+				ty: MemberTypeRepr::Vec3,
+			},
+			MemberInfo {
+				name: "radius",
+				offset: 12,
+				ty: MemberTypeRepr::F32,
+			},
+		];
+		assert_eq!(cbuffer_layout_test::Light::MEMBERS, light);
+
+		let members = cbuffer_layout_test::LayoutTestConstants::members();
+		assert_eq!(members.iter().map(|member| member.name).collect::<Vec<_>>(), ["weights", "lights", "count"]);
+		assert_eq!(members.iter().map(|member| member.offset).collect::<Vec<_>>(), [0, 64, 192]);
+
+		assert_eq!(
+			members[0].ty,
+			MemberTypeRepr::Array {
+				ty: &MemberTypeRepr::F32,
+				count: 4,
+				stride: 16
+			}
+		);
+		match members[1].ty {
+			MemberTypeRepr::Array {
+				ty: MemberTypeRepr::Struct { name, size, members },
+				count,
+				stride,
+			} => {
+				assert_eq!((*name, *size, count, stride), ("Light", 16, 8, 16));
+				assert_eq!(*members, light);
+			}
+			ty => panic!("lights should be an array of structs, not {:?}", ty),
+		}
+		assert_eq!(members[2].ty, MemberTypeRepr::U32);
+	}
+
+	// A pipeline created with the wrong push_constant_bytes would only show up as a validation error, so catch the reflection drifting at compile time.
+	const _: () = assert!(push_constant_layout_test::PUSH_CONSTANT_BYTES == std::mem::size_of::<Mat4>() + std::mem::size_of::<u32>());
+
+	#[test]
+	fn push_constants_match_hand_layout() {
+		use goldfish::build::PushConstant;
+
+		let constants = push_constant_layout_test::PushLayoutTestConstants {
+			model: Mat4::from_cols_array(&std::array::from_fn(|i| i as f32)),
+			index: 7,
+		};
+
+		// The matrix's 16 floats column by column, then the index with no padding after it.
+		let mut expected = vec![0u8; 68];
+		for (i, value) in constants.model.to_cols_array().iter().enumerate() {
+			write_f32(&mut expected, i * 4, *value);
+		}
+		expected[64..68].copy_from_slice(&7u32.to_ne_bytes());
+
+		assert_eq!(push_constant_layout_test::PushLayoutTestConstants::size(), push_constant_layout_test::PUSH_CONSTANT_BYTES);
+		assert_eq!(constants.as_buffer().as_slice(), expected.as_slice());
+
+		let members = push_constant_layout_test::PushLayoutTestConstants::members();
+		assert_eq!(members.iter().map(|member| (member.name, member.offset)).collect::<Vec<_>>(), [("model", 0), ("index", 64)]);
+	}
 }
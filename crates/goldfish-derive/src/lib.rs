@@ -0,0 +1,129 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use std::collections::HashMap;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta, Type};
+
+// Generates `VERTEX_INFO` for a #[repr(C)] vertex struct, locations are assigned in field order unless overridden with #[location = N].
+// Anything after an overridden field keeps counting up from there.
+#[proc_macro_derive(VertexInput, attributes(location))]
+pub fn derive_vertex_input(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+
+	match vertex_input(input) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
+
+fn vertex_input(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+	let name = &input.ident;
+
+	if !input.generics.params.is_empty() {
+		return Err(Error::new_spanned(&input.generics, "VertexInput cannot be derived for generic types"));
+	}
+
+	let is_repr_c = input
+		.attrs
+		.iter()
+		.filter(|attr| attr.path.is_ident("repr"))
+		.filter_map(|attr| attr.parse_meta().ok())
+		.any(|meta| match meta {
+			Meta::List(list) => list.nested.iter().any(|nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C"))),
+			_ => false,
+		});
+	if !is_repr_c {
+		return Err(Error::new(Span::call_site(), "VertexInput types must be #[repr(C)] so the field offsets are stable"));
+	}
+
+	let fields = match &input.data {
+		Data::Struct(data) => match &data.fields {
+			Fields::Named(fields) => &fields.named,
+			_ => return Err(Error::new(Span::call_site(), "VertexInput can only be derived for structs with named fields")),
+		},
+		_ => return Err(Error::new(Span::call_site(), "VertexInput can only be derived for structs")),
+	};
+
+	let mut location = 0u32;
+	let mut used_locations = HashMap::<u32, &syn::Ident>::new();
+	let mut bindings = Vec::new();
+	let mut size_checks = Vec::new();
+
+	for field in fields.iter() {
+		let field_name = field.ident.as_ref().unwrap();
+		let field_ty = &field.ty;
+
+		for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("location")) {
+			location = match attr.parse_meta()? {
+				Meta::NameValue(meta) => match meta.lit {
+					Lit::Int(lit) => lit.base10_parse::<u32>()?,
+					lit => return Err(Error::new_spanned(lit, "Expected an integer location")),
+				},
+				meta => return Err(Error::new_spanned(meta, "Expected #[location = N]")),
+			};
+		}
+
+		if let Some(other) = used_locations.insert(location, field_name) {
+			return Err(Error::new_spanned(field_name, format!("Location {} is already used by `{}`", location, other)));
+		}
+
+		let (format, format_size) = attribute_format(field_ty)?;
+		let format = syn::Ident::new(format, Span::call_site());
+
+		bindings.push(quote! {
+			::goldfish::renderer::VertexAttributeDescriptionBinding {
+				location: #location,
+				format: ::goldfish::renderer::VertexAttributeFormat::#format,
+				offset: ::goldfish::memoffset::offset_of!(#name, #field_name) as u32,
+			}
+		});
+
+		let message = format!("`{}::{}` isn't the size its vertex format expects", name, field_name);
+		size_checks.push(quote! {
+			const _: () = assert!(::core::mem::size_of::<#field_ty>() == #format_size, #message);
+		});
+
+		location += 1;
+	}
+
+	let field_types = fields.iter().map(|field| &field.ty);
+	let padding_message = format!("`{}` has padding bytes, vertex types have to be tightly packed to be uploaded as raw bytes", name);
+
+	Ok(quote! {
+		impl #name {
+			pub const VERTEX_INFO: ::goldfish::renderer::VertexInputInfo = ::goldfish::renderer::VertexInputInfo {
+				bindings: &[#(#bindings),*],
+				stride: ::core::mem::size_of::<Self>() as u32,
+			};
+		}
+
+		#(#size_checks)*
+		const _: () = assert!(::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#field_types>())*, #padding_message);
+	})
+}
+
+fn attribute_format(ty: &Type) -> Result<(&'static str, usize), Error> {
+	let unsupported = || Error::new_spanned(ty, "Unsupported vertex attribute type, expected one of f32, u32, Vec2, Vec3, Vec4, [u8; 4] or [u16; 4]");
+
+	match ty {
+		Type::Path(path) if path.qself.is_none() => {
+			let segment = path.path.segments.last().ok_or_else(unsupported)?;
+			match segment.ident.to_string().as_str() {
+				"f32" => Ok(("F32", 4)),
+				"u32" => Ok(("U32", 4)),
+				"Vec2" => Ok(("F32Vec2", 8)),
+				"Vec3" => Ok(("F32Vec3", 12)),
+				"Vec4" => Ok(("F32Vec4", 16)),
+				_ => Err(unsupported()),
+			}
+		}
+		Type::Array(array) => match (&*array.elem, &array.len) {
+			// Packed colors, these get normalized to [0.0, 1.0] in the shader.
+			(Type::Path(elem), syn::Expr::Lit(syn::ExprLit { lit: Lit::Int(len), .. })) if elem.path.is_ident("u8") && len.base10_parse::<usize>()? == 4 => Ok(("U8x4Norm", 4)),
+			// Bone indices, these stay integers.
+			(Type::Path(elem), syn::Expr::Lit(syn::ExprLit { lit: Lit::Int(len), .. })) if elem.path.is_ident("u16") && len.base10_parse::<usize>()? == 4 => Ok(("U16x4", 8)),
+			_ => Err(unsupported()),
+		},
+		_ => Err(unsupported()),
+	}
+}
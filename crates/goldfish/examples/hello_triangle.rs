@@ -0,0 +1,97 @@
+use goldfish::app::{AppConfig, TRIANGLE_MESH, UNLIT_SHADER};
+use goldfish::log::error;
+use goldfish::package::{AssetType, Package};
+use goldfish::renderer::*;
+use goldfish::{Color, GoldfishEngine};
+
+// The built in triangle drawn with the built in unlit shader, no asset directory or game library needed.
+struct HelloTriangle {
+	triangle: Mesh,
+	vs: Shader,
+	ps: Shader,
+	render_graph_cache: RenderGraphCache,
+}
+
+fn init(engine: &mut GoldfishEngine) -> HelloTriangle {
+	let mesh_package = match engine.read_package(TRIANGLE_MESH, AssetType::Mesh).expect("Failed to read the triangle!") {
+		Package::Mesh(package) => package,
+		_ => unreachable!(),
+	};
+	let shader_package = match engine.read_package(UNLIT_SHADER, AssetType::Shader).expect("Failed to read the unlit shader!") {
+		Package::Shader(package) => package,
+		_ => unreachable!(),
+	};
+
+	let mut upload_context = engine.graphics_device.create_upload_context();
+	let triangle = engine
+		.geometry_pool
+		.upload_mesh_package(&mut upload_context, "Triangle", &mesh_package)
+		.expect("Failed to upload the triangle!");
+	engine.graphics_device.destroy_upload_context(upload_context);
+
+	let permutation = shader_package.permutation(0).unwrap();
+	HelloTriangle {
+		triangle,
+		vs: engine.graphics_device.create_shader_with_code(permutation.vs_ir.as_ref().unwrap()).unwrap(),
+		ps: engine.graphics_device.create_shader_with_code(permutation.ps_ir.as_ref().unwrap()).unwrap(),
+		render_graph_cache: RenderGraphCache::default(),
+	}
+}
+
+fn update(engine: &mut GoldfishEngine, app: &mut HelloTriangle) {
+	if !engine.should_render() {
+		return;
+	}
+
+	let graphics_device = &mut engine.graphics_device;
+	let _ = engine.graphics_context.frame(&engine.window, |frame| {
+		let mut render_graph = RenderGraph::new(&mut app.render_graph_cache, &engine.default_resources);
+
+		{
+			let mut pass = render_graph.add_pass("triangle");
+			let render_pass = pass.add_output_render_pass();
+			let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+				name: "Triangle Pipeline",
+				vs: &app.vs,
+				ps: Some(&app.ps),
+				gs: None,
+				descriptor_layouts: &[],
+				render_pass,
+				depth_compare_op: None,
+				depth_write: false,
+				depth_bias: None,
+				blend_mode: BlendMode::Opaque,
+				face_cull: FaceCullMode::NoCull,
+				push_constant_bytes: 0,
+				vertex_input_info: ExtendedVertex::VERTEX_INFO,
+				polygon_mode: PolygonMode::Fill,
+				specialization: &[],
+			});
+
+			pass.cmd_begin_render_pass(render_pass, &[ClearValue::from_color(Color::from_srgb_u8(25, 25, 30, 255), frame.output_is_srgb())]);
+			pass.cmd_bind_raster_pipeline(pipeline);
+			pass.cmd_draw_mesh(&app.triangle);
+			pass.cmd_end_render_pass();
+		}
+
+		if let Err(err) = frame.execute_graph(render_graph, graphics_device) {
+			error!("Failed to execute render graph: {}", err);
+		}
+	});
+}
+
+fn main() {
+	let config = AppConfig {
+		title: "Goldfish Hello Triangle",
+		engine: None,
+		package_reader: None,
+	};
+
+	let (mut engine, app) = GoldfishEngine::run_app(config, init, |engine, app, _| update(engine, app));
+
+	let graphics_device = &mut engine.graphics_device;
+	app.render_graph_cache.destroy(graphics_device);
+	graphics_device.destroy_shader(app.vs);
+	graphics_device.destroy_shader(app.ps);
+	engine.geometry_pool.free_mesh(graphics_device, app.triangle);
+}
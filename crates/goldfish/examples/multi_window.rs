@@ -0,0 +1,64 @@
+use goldfish::package::{AssetType, Package};
+use goldfish::renderer::{ClearValue, SurfacePreference};
+use goldfish::window::EngineEvent;
+use goldfish::{Color, GoldfishEngine, GoldfishError, GoldfishResult};
+use uuid::Uuid;
+
+// Two windows sharing one graphics device, each clearing to its own color. The colors come out the same whether or not the surfaces
+// ended up sRGB typed.
+fn read_asset(uuid: Uuid, _asset_type: AssetType) -> GoldfishResult<Package> {
+	Err(GoldfishError::Unknown(format!("No asset {} in the multi window example", uuid)))
+}
+
+fn main() {
+	let mut engine = GoldfishEngine::new("Goldfish Multi Window", Box::new(read_asset), None);
+
+	let game_window = engine.window.new_secondary("Goldfish Game View").expect("Failed to create game view window!");
+	let game_context = engine
+		.create_secondary_context(&game_window, SurfacePreference::default())
+		.expect("Failed to create game view graphics context!");
+	// Gone once the game view is closed, the main window keeps going on its own.
+	let mut game_view = Some((game_window, game_context));
+
+	engine.run(|engine, _| {
+		for event in engine.drain_events() {
+			match event {
+				EngineEvent::SecondaryResized { window, size } => {
+					if let Some((_, game_context)) = game_view.as_mut().filter(|(game_window, _)| game_window.id() == window) {
+						game_context.on_resize(size.into());
+					}
+				}
+				EngineEvent::SecondaryCloseRequested(window) if game_view.as_ref().map_or(false, |(game_window, _)| game_window.id() == window) => {
+					let (game_window, mut game_context) = game_view.take().unwrap();
+					game_context.destroy();
+					drop(game_window);
+				}
+				_ => {}
+			}
+		}
+
+		if !engine.should_render() {
+			return;
+		}
+
+		// A frame that couldn't be started is just skipped, the swapchain gets recreated for the next one.
+		let _ = engine.graphics_context.frame(&engine.window, |frame| {
+			let clear = ClearValue::from_color(Color::from_srgb_u8(204, 51, 51, 255), frame.output_is_srgb());
+			frame.begin_output_render_pass(&[clear]);
+			frame.end_render_pass();
+		});
+
+		if let Some((game_window, game_context)) = game_view.as_mut() {
+			let _ = game_context.frame(game_window, |frame| {
+				let clear = ClearValue::from_color(Color::from_srgb_u8(51, 51, 204, 255), frame.output_is_srgb());
+				frame.begin_output_render_pass(&[clear]);
+				frame.end_render_pass();
+			});
+		}
+	});
+
+	if let Some((game_window, mut game_context)) = game_view {
+		game_context.destroy();
+		drop(game_window);
+	}
+}
@@ -0,0 +1,134 @@
+use goldfish::app::{AppConfig, TRIANGLE_MESH, UNLIT_SHADER};
+use goldfish::log::{error, info};
+use goldfish::package::{AssetType, Package};
+use goldfish::renderer::*;
+use goldfish::{Color, GoldfishEngine};
+
+const RENDER_GRAPH_DUMP_PATH: &str = "render_graph_basics.dot";
+
+// Two passes, one drawing the built in triangle into a half resolution attachment and one blitting that attachment to the output. The graph
+// orders the passes and puts the barrier between them from what each of them reads and writes. Press F9 to dump the graph.
+struct RenderGraphBasics {
+	triangle: Mesh,
+	vs: Shader,
+	ps: Shader,
+	render_graph_cache: RenderGraphCache,
+}
+
+fn init(engine: &mut GoldfishEngine) -> RenderGraphBasics {
+	let mesh_package = match engine.read_package(TRIANGLE_MESH, AssetType::Mesh).expect("Failed to read the triangle!") {
+		Package::Mesh(package) => package,
+		_ => unreachable!(),
+	};
+	let shader_package = match engine.read_package(UNLIT_SHADER, AssetType::Shader).expect("Failed to read the unlit shader!") {
+		Package::Shader(package) => package,
+		_ => unreachable!(),
+	};
+
+	let mut upload_context = engine.graphics_device.create_upload_context();
+	let triangle = engine
+		.geometry_pool
+		.upload_mesh_package(&mut upload_context, "Triangle", &mesh_package)
+		.expect("Failed to upload the triangle!");
+	engine.graphics_device.destroy_upload_context(upload_context);
+
+	let permutation = shader_package.permutation(0).unwrap();
+	RenderGraphBasics {
+		triangle,
+		vs: engine.graphics_device.create_shader_with_code(permutation.vs_ir.as_ref().unwrap()).unwrap(),
+		ps: engine.graphics_device.create_shader_with_code(permutation.ps_ir.as_ref().unwrap()).unwrap(),
+		render_graph_cache: RenderGraphCache::default(),
+	}
+}
+
+fn update(engine: &mut GoldfishEngine, app: &mut RenderGraphBasics) {
+	if !engine.should_render() {
+		return;
+	}
+
+	let graphics_device = &mut engine.graphics_device;
+	let dump_render_graph = std::mem::take(&mut engine.dump_render_graph);
+	let _ = engine.graphics_context.frame(&engine.window, |frame| {
+		let mut render_graph = RenderGraph::new(&mut app.render_graph_cache, &engine.default_resources);
+
+		let scene = {
+			let mut pass = render_graph.add_pass("scene");
+
+			// Sized relative to the output so it follows the window around, the graph recreates it when that changes.
+			let mut color = pass.add_attachment(AttachmentDesc {
+				name: "Scene",
+				format: TextureFormat::RGBA8UNorm,
+				size: AttachmentSize::OutputRelative { scale_x: 0.5, scale_y: 0.5 },
+				load_op: LoadOp::Clear,
+				store_op: StoreOp::Store,
+				usage: TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC,
+			});
+
+			let render_pass = pass.add_render_pass(RenderPassDesc {
+				name: "Scene render pass",
+				color_attachments: &mut [&mut color],
+				depth_attachment: None,
+			});
+
+			let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+				name: "Triangle Pipeline",
+				vs: &app.vs,
+				ps: Some(&app.ps),
+				gs: None,
+				descriptor_layouts: &[],
+				render_pass,
+				depth_compare_op: None,
+				depth_write: false,
+				depth_bias: None,
+				blend_mode: BlendMode::Opaque,
+				face_cull: FaceCullMode::NoCull,
+				push_constant_bytes: 0,
+				vertex_input_info: ExtendedVertex::VERTEX_INFO,
+				polygon_mode: PolygonMode::Fill,
+				specialization: &[],
+			});
+
+			// The attachment is RGBA8UNorm, so the clear color is given as is rather than converted for an sRGB output.
+			pass.cmd_begin_render_pass(render_pass, &[ClearValue::from_color(Color::from_srgb_u8(25, 25, 30, 255), false)]);
+			pass.cmd_bind_raster_pipeline(pipeline);
+			pass.cmd_draw_mesh(&app.triangle);
+			pass.cmd_end_render_pass();
+
+			color
+		};
+
+		{
+			let mut pass = render_graph.add_pass("present");
+
+			// Reading the attachment is what makes this pass run after the scene pass, it's scaled up to the size of the output.
+			pass.cmd_blit_attachment_to_output(scene.read_as(ImageLayout::TransferSrcOptimal), Filter::Linear);
+		}
+
+		if dump_render_graph {
+			match std::fs::write(RENDER_GRAPH_DUMP_PATH, render_graph.dump_dot()) {
+				Ok(_) => info!("Wrote render graph to {}", RENDER_GRAPH_DUMP_PATH),
+				Err(err) => error!("Failed to write render graph to {}: {}", RENDER_GRAPH_DUMP_PATH, err),
+			}
+		}
+
+		if let Err(err) = frame.execute_graph(render_graph, graphics_device) {
+			error!("Failed to execute render graph: {}", err);
+		}
+	});
+}
+
+fn main() {
+	let config = AppConfig {
+		title: "Goldfish Render Graph Basics",
+		engine: None,
+		package_reader: None,
+	};
+
+	let (mut engine, app) = GoldfishEngine::run_app(config, init, |engine, app, _| update(engine, app));
+
+	let graphics_device = &mut engine.graphics_device;
+	app.render_graph_cache.destroy(graphics_device);
+	graphics_device.destroy_shader(app.vs);
+	graphics_device.destroy_shader(app.ps);
+	engine.geometry_pool.free_mesh(graphics_device, app.triangle);
+}
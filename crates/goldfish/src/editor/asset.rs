@@ -1,20 +1,39 @@
-use super::shader_compiler;
+use super::audio_importer;
+use super::mesh_importer::ImportedScene;
+use super::shader_compiler::{self, IncludeResolver};
+use super::texture_importer;
 use super::{EditorError, BUILD_ASSET_DIR};
 use bincode::serialize;
 use filetime::FileTime;
-use goldfish::package::{AssetType, MeshPackage, Package, ShaderPackage};
+use goldfish::jobs;
+use goldfish::package::{AssetRegistry, AssetType, Bundle, Package, ASSET_REGISTRY_PATH};
 use goldfish::renderer::TextureFormat;
 use goldfish::{GoldfishError, GoldfishResult};
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
 use uuid::Uuid;
 
 const ASSET_META_EXTENSION: &'static str = "meta";
 const BUILD_ASSET_EXTENSION: &'static str = "asset";
+const MANIFEST_FILE: &'static str = "manifest.bin";
 
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Eq)]
+// Bump this whenever an importer changes its output so every asset gets rebuilt.
+const IMPORTER_VERSION: Version = Version::new(1, 5);
+
+// Every .asset file starts with the magic and format version, anything else gets reimported.
+const BUILD_ASSET_MAGIC: [u8; 4] = *b"GFAS";
+const BUILD_ASSET_HEADER_SIZE: usize = BUILD_ASSET_MAGIC.len() + std::mem::size_of::<u32>();
+
+// Bump this whenever the serialized layout of a package changes, including enum variants added anywhere but the end.
+const BUILD_ASSET_FORMAT_VERSION: Version = Version::new(1, 4);
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Eq, Clone, Copy)]
 pub struct Version {
 	version: u32,
 }
@@ -57,6 +76,7 @@ pub enum AdditionalAssetData {
 	Mesh,
 	Texture(TextureAsset),
 	Shader,
+	Audio,
 	Other,
 }
 
@@ -66,15 +86,21 @@ pub struct Asset {
 	pub version: Version,
 	pub asset_type: AssetType,
 	pub additional_data: AdditionalAssetData,
+	// Mesh files can also carry animations, those come after the meshes in uuids order and get read back as AssetType::Animation.
+	#[serde(default)]
+	pub animation_uuids: Vec<Uuid>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TextureAsset {
+	// RGBA8UNorm and SRGBA8 are imported uncompressed, BC7UNorm and BC7SRGB get compressed to BC7 by the importer. CubemapRGBA8UNorm and
+	// CubemapSRGBA8 take either the +X face of six name_px.png, name_nx.png, ... files or a single equirectangular image.
 	pub format: TextureFormat,
 }
 
 impl Asset {
-	const CURRENT_ASSET_VERSION: Version = Version::new(1, 0);
+	// 1.1: TextureFormat variants were renamed (RGBA8 -> RGBA8UNorm and so on).
+	const CURRENT_ASSET_VERSION: Version = Version::new(1, 1);
 
 	pub fn new(asset_type: AssetType, count: u32) -> Self {
 		// let uuid = Uuid::new_v4();
@@ -83,7 +109,8 @@ impl Asset {
 			AssetType::Mesh => AdditionalAssetData::Mesh,
 			AssetType::Texture => AdditionalAssetData::Texture(TextureAsset { format: TextureFormat::RGBA8UNorm }),
 			AssetType::Shader => AdditionalAssetData::Shader,
-			AssetType::Other => AdditionalAssetData::Other,
+			AssetType::Audio => AdditionalAssetData::Audio,
+			AssetType::Animation | AssetType::Other => AdditionalAssetData::Other,
 		};
 
 		Self {
@@ -91,164 +118,620 @@ impl Asset {
 			version: Self::CURRENT_ASSET_VERSION,
 			asset_type,
 			additional_data,
+			animation_uuids: Vec::new(),
 		}
 	}
+
+	// Upgrades metadata written by an older editor, returns whether anything changed so the caller knows to write the meta file back out.
+	fn migrate(&mut self) -> bool {
+		if self.version >= Self::CURRENT_ASSET_VERSION {
+			return false;
+		}
+
+		// The old names already deserialize through serde aliases on TextureFormat.
+		self.version = Self::CURRENT_ASSET_VERSION;
+
+		true
+	}
 }
 
-pub fn import_assets(asset_dir: &Path) -> Result<(), EditorError> {
-	if !Path::new(BUILD_ASSET_DIR).is_dir() {
-		fs::create_dir(BUILD_ASSET_DIR).map_err(move |err| EditorError::Filesystem(err))?;
+#[derive(Serialize, Deserialize, Clone)]
+struct ManifestEntry {
+	source_path: PathBuf,
+	source_modified: (i64, u32),
+	meta_modified: (i64, u32),
+	importer_version: Version,
+}
+
+impl ManifestEntry {
+	// A moved asset keeps its uuid through the meta file, but gets reimported since shader includes are resolved relative to it.
+	fn matches(&self, previous: &ManifestEntry) -> bool {
+		previous.source_path == self.source_path
+			&& previous.source_modified == self.source_modified
+			&& previous.meta_modified == self.meta_modified
+			&& previous.importer_version == self.importer_version
 	}
+}
 
-	for asset in fs::read_dir(asset_dir).map_err(move |err| EditorError::Filesystem(err))? {
-		let asset = asset.map_err(move |err| EditorError::Filesystem(err))?;
-		let asset_path = asset.path();
+type Manifest = HashMap<Uuid, ManifestEntry>;
+
+#[derive(Default)]
+pub struct ImportSummary {
+	pub imported: usize,
+	pub up_to_date: usize,
+	pub removed: usize,
+	pub imported_assets: Vec<(Uuid, AssetType)>,
+	pub removed_assets: Vec<Uuid>,
+	// Everything else still gets imported when an asset fails, these are already logged by the time import_assets returns.
+	pub failures: Vec<ImportFailure>,
+}
 
-		if asset_path.is_dir() {
-			import_assets(asset_path.as_path())?;
-		} else if asset_path.extension().unwrap_or_default() != ASSET_META_EXTENSION {
-			let meta_extension = if let Some(extension) = asset_path.extension() {
-				extension.to_str().unwrap().to_owned() + "." + ASSET_META_EXTENSION
-			} else {
-				ASSET_META_EXTENSION.to_owned()
-			};
+#[derive(Debug, Error)]
+#[error("{} ({}): {error}", .source_path.display(), .uuid.map_or("no uuid".to_string(), |uuid| uuid.to_string()))]
+pub struct ImportFailure {
+	pub source_path: PathBuf,
+	// None when the meta file couldn't be read, so nothing from the source was built.
+	pub uuid: Option<Uuid>,
+	pub error: EditorError,
+}
+
+fn modified_time(metadata: &fs::Metadata) -> (i64, u32) {
+	let time = FileTime::from_last_modification_time(metadata);
+	(time.unix_seconds(), time.nanoseconds())
+}
 
-			let meta_path = asset_path.with_extension(&meta_extension);
+fn read_manifest() -> Manifest {
+	let manifest_path = Path::new(BUILD_ASSET_DIR).join(MANIFEST_FILE);
 
-			let asset_type = AssetType::from_extension(asset_path.extension().unwrap_or_default().to_str().unwrap());
+	match fs::read(&manifest_path) {
+		Ok(contents) => bincode::deserialize::<Manifest>(&contents).unwrap_or_else(|err| {
+			warn!("Failed to deserialize asset manifest, reimporting all assets! {}", err);
+			Default::default()
+		}),
+		Err(_) => Default::default(),
+	}
+}
 
-			let mut meta_file_was_created = false;
+fn write_manifest(manifest: &Manifest) -> Result<(), EditorError> {
+	let serialized = bincode::serialize(manifest).map_err(move |_| EditorError::Serialize)?;
+	fs::write(Path::new(BUILD_ASSET_DIR).join(MANIFEST_FILE), serialized).map_err(move |err| EditorError::Filesystem(err))
+}
 
-			let mut mesh_packages: Option<Vec<MeshPackage>> = None;
+fn build_asset_header() -> [u8; BUILD_ASSET_HEADER_SIZE] {
+	let mut header = [0; BUILD_ASSET_HEADER_SIZE];
+	header[..BUILD_ASSET_MAGIC.len()].copy_from_slice(&BUILD_ASSET_MAGIC);
+	header[BUILD_ASSET_MAGIC.len()..].copy_from_slice(&BUILD_ASSET_FORMAT_VERSION.version.to_le_bytes());
+	header
+}
 
-			let import_mesh_packages = || -> Result<Option<Vec<MeshPackage>>, EditorError> {
-				let extension = asset_path.extension().unwrap().to_str().unwrap();
-				let data = fs::read(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
-				Ok(Some(super::mesh_importer::import_mesh(&data, &extension)?))
-			};
+// Only reads the header, so this is cheap enough to run on every build file when importing.
+fn has_current_build_asset_header(build_path: &Path) -> bool {
+	let mut header = [0; BUILD_ASSET_HEADER_SIZE];
+
+	match fs::File::open(build_path) {
+		Ok(mut file) => file.read_exact(&mut header).is_ok() && header == build_asset_header(),
+		Err(_) => false,
+	}
+}
+
+fn write_build_asset(build_path: &Path, payload: &[u8]) -> Result<(), EditorError> {
+	let mut output = fs::File::create(build_path).map_err(move |err| EditorError::Filesystem(err))?;
+	output.write_all(&build_asset_header()).map_err(move |err| EditorError::Filesystem(err))?;
+	output.write_all(payload).map_err(move |err| EditorError::Filesystem(err))
+}
+
+fn read_build_asset(build_path: &Path) -> GoldfishResult<Vec<u8>> {
+	let mut contents = fs::read(build_path).map_err(move |err| GoldfishError::Filesystem(err))?;
+
+	if contents.len() < BUILD_ASSET_HEADER_SIZE || contents[..BUILD_ASSET_HEADER_SIZE] != build_asset_header() {
+		return Err(GoldfishError::Unknown(format!(
+			"{} was built by a different version of the editor and needs to be reimported.",
+			build_path.to_str().unwrap_or("UNKNOWN_BUILD_PATH")
+		)));
+	}
+
+	contents.drain(..BUILD_ASSET_HEADER_SIZE);
 
-			let asset = if meta_path.exists() {
-				match fs::read_to_string(&meta_path) {
-					Ok(contents) => match serde_json::from_str::<Asset>(contents.as_str()) {
-						Ok(asset) => asset,
-						Err(err) => {
-							println!("Failed to deserialize metadata for asset! {}", err);
-							continue;
-						}
-					},
-					Err(err) => {
-						println!("Failed to load metadata for asset! {}", err);
-						continue;
+	Ok(contents)
+}
+
+// Records the modified time of every file under the asset directory so the importers don't have to stat them individually.
+fn collect_asset_files(dir: &Path, files: &mut HashMap<PathBuf, (i64, u32)>) -> Result<(), EditorError> {
+	for entry in fs::read_dir(dir).map_err(move |err| EditorError::Filesystem(err))? {
+		let entry = entry.map_err(move |err| EditorError::Filesystem(err))?;
+		let metadata = entry.metadata().map_err(move |err| EditorError::Filesystem(err))?;
+
+		if metadata.is_dir() {
+			collect_asset_files(&entry.path(), files)?;
+		} else {
+			files.insert(entry.path(), modified_time(&metadata));
+		}
+	}
+
+	Ok(())
+}
+
+// Only fails outright when the build directory can't be written, failed assets end up in ImportSummary::failures.
+pub fn import_assets(asset_dir: &Path, shader_include_dirs: &[PathBuf], force: bool) -> Result<ImportSummary, EditorError> {
+	if !Path::new(BUILD_ASSET_DIR).is_dir() {
+		fs::create_dir_all(BUILD_ASSET_DIR).map_err(move |err| EditorError::Filesystem(err))?;
+	}
+
+	let mut files = HashMap::new();
+	collect_asset_files(asset_dir, &mut files)?;
+
+	let mut built_assets = HashSet::new();
+	// Built with an older format, these get rebuilt like they were missing but still need cleaning up if their source is gone.
+	let mut outdated_assets = HashSet::new();
+	for entry in fs::read_dir(BUILD_ASSET_DIR).map_err(move |err| EditorError::Filesystem(err))? {
+		let path = entry.map_err(move |err| EditorError::Filesystem(err))?.path();
+
+		if path.extension().unwrap_or_default() != BUILD_ASSET_EXTENSION {
+			continue;
+		}
+
+		if let Some(uuid) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| Uuid::parse_str(stem).ok()) {
+			if has_current_build_asset_header(&path) {
+				built_assets.insert(uuid);
+			} else {
+				outdated_assets.insert(uuid);
+			}
+		}
+	}
+
+	let manifest = if force { Manifest::default() } else { read_manifest() };
+	let include_resolver = IncludeResolver::new(shader_include_dirs.iter().map(|dir| asset_dir.join(dir)).collect());
+
+	// Shader compilation is by far the slowest part of importing, each import creates its own Dxc instance so this is safe to run in parallel.
+	let imported = jobs::install(|| {
+		files
+			.par_iter()
+			.filter(|(path, _)| path.extension().unwrap_or_default() != ASSET_META_EXTENSION)
+			.map(|(path, &source_modified)| (path, import_asset(path, source_modified, &files, &manifest, &built_assets, &include_resolver)))
+			.collect::<Vec<_>>()
+	});
+
+	let mut summary = ImportSummary::default();
+	let mut new_manifest = Manifest::with_capacity(manifest.len());
+
+	let previous_registry = AssetRegistry::load(Path::new(ASSET_REGISTRY_PATH)).unwrap_or_default();
+	let mut registry = previous_registry.clone();
+	let mut registry_paths = HashSet::with_capacity(imported.len());
+
+	for (source_path, results) in imported {
+		let results = results.unwrap_or_else(|error| {
+			summary.failures.push(ImportFailure {
+				source_path: source_path.clone(),
+				uuid: None,
+				error,
+			});
+			Vec::new()
+		});
+		let failed = results.iter().any(|(_, _, _, result)| result.is_err());
+
+		if let Some(registry_path) = AssetRegistry::registry_path(asset_dir, source_path) {
+			// A failed source keeps whatever it was registered as last time, so it can still be found once it's fixed.
+			if !results.is_empty() && !failed {
+				registry.insert(registry_path.clone(), results.iter().map(|(uuid, asset_type, _, _)| (*uuid, *asset_type)).collect());
+			}
+
+			registry_paths.insert(registry_path);
+		}
+
+		for (uuid, asset_type, entry, result) in results {
+			match result {
+				Ok(true) => {
+					summary.imported += 1;
+					summary.imported_assets.push((uuid, asset_type));
+					new_manifest.insert(uuid, entry);
+				}
+				Ok(false) => {
+					summary.up_to_date += 1;
+					new_manifest.insert(uuid, entry);
+				}
+				Err(error) => {
+					// Keeps the last good build from being cleaned up below.
+					if let Some(previous) = manifest.get(&uuid) {
+						new_manifest.insert(uuid, previous.clone());
 					}
+
+					summary.failures.push(ImportFailure {
+						source_path: source_path.clone(),
+						uuid: Some(uuid),
+						error,
+					});
 				}
-			} else {
-				println!("Failed to find meta file {}! Creating...", meta_path.as_path().to_str().unwrap());
+			}
+		}
+	}
 
-				mesh_packages = match asset_type {
-					AssetType::Mesh => import_mesh_packages()?,
-					_ => None,
-				};
+	for failure in &summary.failures {
+		error!("Failed to import {}", failure);
+	}
 
-				let metadata = match mesh_packages {
-					Some(ref mesh_packages) => Asset::new(asset_type, mesh_packages.len() as u32),
-					None => Asset::new(asset_type, 1),
-				};
+	let removed_paths = registry.paths().filter(|path| !registry_paths.contains(*path)).map(str::to_string).collect::<Vec<_>>();
+	for path in removed_paths {
+		registry.remove(&path);
+	}
+
+	for uuid in built_assets.iter().chain(outdated_assets.iter()).filter(|uuid| !new_manifest.contains_key(uuid)) {
+		let build_path = Path::new(BUILD_ASSET_DIR).join(uuid.to_string()).with_extension(BUILD_ASSET_EXTENSION);
+
+		match fs::remove_file(&build_path) {
+			Ok(_) => {
+				summary.removed += 1;
+				summary.removed_assets.push(*uuid);
+			}
+			Err(err) => warn!("Failed to remove orphaned build file {}! {}", build_path.to_str().unwrap_or("UNKNOWN_BUILD_PATH"), err),
+		}
+	}
+
+	write_manifest(&new_manifest)?;
+
+	// The engine reloads the registry whenever it hears about a reimport, so only write it when something actually moved.
+	if registry != previous_registry || !Path::new(ASSET_REGISTRY_PATH).is_file() {
+		registry.save(Path::new(ASSET_REGISTRY_PATH)).map_err(|err| match err {
+			GoldfishError::Filesystem(err) => EditorError::Filesystem(err),
+			_ => EditorError::Serialize,
+		})?;
+	}
+
+	info!(
+		"Imported {} assets, {} up to date, {} removed, {} failed",
+		summary.imported,
+		summary.up_to_date,
+		summary.removed,
+		summary.failures.len()
+	);
+
+	Ok(summary)
+}
 
-				let serialized = serde_json::to_string_pretty(&metadata).map_err(move |_| EditorError::Serialize)?;
+// Meta files written by an older editor get migrated and written back out, the bool is whether that happened.
+fn read_meta(meta_path: &Path) -> Result<(Asset, bool), EditorError> {
+	let contents = fs::read_to_string(meta_path).map_err(move |err| EditorError::Filesystem(err))?;
+
+	match serde_json::from_str::<Asset>(contents.as_str()) {
+		Ok(asset) if asset.version > Asset::CURRENT_ASSET_VERSION => Err(EditorError::Metadata(format!(
+			"{} was written by a newer version of the editor",
+			meta_path.to_str().unwrap_or("UNKNOWN_META_PATH")
+		))),
+		Ok(mut asset) => {
+			let migrated = asset.migrate();
+			if migrated {
+				info!("Migrating metadata for asset {} to the current version", meta_path.to_str().unwrap_or("UNKNOWN_META_PATH"));
+
+				let serialized = serde_json::to_string_pretty(&asset).map_err(move |_| EditorError::Serialize)?;
+				fs::write(meta_path, serialized).map_err(move |err| EditorError::Filesystem(err))?;
+			}
 
-				fs::write(&meta_path, serialized).map_err(move |err| EditorError::Filesystem(err))?;
-				meta_file_was_created = true;
+			Ok((asset, migrated))
+		}
+		Err(err) => Err(EditorError::Metadata(format!("{}: {}", meta_path.to_str().unwrap_or("UNKNOWN_META_PATH"), err))),
+	}
+}
 
-				metadata
+// Shaders need to be rebuilt whenever anything they include changes, so treat the newest include as the shader's modified time.
+fn shader_source_modified(asset_path: &Path, source_modified: (i64, u32), files: &HashMap<PathBuf, (i64, u32)>, include_resolver: &IncludeResolver) -> Result<(i64, u32), EditorError> {
+	include_resolver
+		.find_includes(asset_path)?
+		.iter()
+		.try_fold(source_modified, |latest, include| -> Result<(i64, u32), EditorError> {
+			let include_modified = match files.get(include) {
+				Some(&include_modified) => include_modified,
+				None => modified_time(&fs::metadata(include).map_err(move |err| EditorError::Filesystem(err))?),
 			};
 
-			for (i, uuid) in asset.uuids.iter().enumerate() {
-				let build_path = Path::new(BUILD_ASSET_DIR).join(uuid.to_string()).with_extension(BUILD_ASSET_EXTENSION);
+			Ok(latest.max(include_modified))
+		})
+}
+
+fn import_asset(
+	asset_path: &Path,
+	source_modified: (i64, u32),
+	files: &HashMap<PathBuf, (i64, u32)>,
+	manifest: &Manifest,
+	built_assets: &HashSet<Uuid>,
+	include_resolver: &IncludeResolver,
+) -> Result<Vec<(Uuid, AssetType, ManifestEntry, Result<bool, EditorError>)>, EditorError> {
+	let meta_extension = if let Some(extension) = asset_path.extension() {
+		extension.to_str().unwrap().to_owned() + "." + ASSET_META_EXTENSION
+	} else {
+		ASSET_META_EXTENSION.to_owned()
+	};
+
+	let meta_path = asset_path.with_extension(&meta_extension);
+
+	let asset_type = AssetType::from_extension(asset_path.extension().unwrap_or_default().to_str().unwrap());
+
+	let mut imported_scene: Option<ImportedScene> = None;
+
+	let import_scene = || -> Result<Option<ImportedScene>, EditorError> {
+		let extension = asset_path.extension().unwrap().to_str().unwrap();
+		let data = fs::read(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
+		Ok(Some(super::mesh_importer::import_mesh(&data, &extension)?))
+	};
+
+	let (asset, meta_migrated) = if files.contains_key(&meta_path) {
+		read_meta(&meta_path)?
+	} else {
+		debug!("Failed to find meta file {}! Creating...", meta_path.as_path().to_str().unwrap());
+
+		imported_scene = match asset_type {
+			AssetType::Mesh => import_scene()?,
+			_ => None,
+		};
+
+		// Delete the meta file of a mesh imported before animations were supported to pick them up.
+		let metadata = match imported_scene {
+			Some(ref scene) => Asset {
+				animation_uuids: scene.animations.iter().map(|_| Uuid::new_v4()).collect(),
+				..Asset::new(asset_type, scene.meshes.len() as u32)
+			},
+			None => Asset::new(asset_type, 1),
+		};
+
+		let serialized = serde_json::to_string_pretty(&metadata).map_err(move |_| EditorError::Serialize)?;
+
+		fs::write(&meta_path, serialized).map_err(move |err| EditorError::Filesystem(err))?;
+
+		(metadata, false)
+	};
+
+	let source_modified = match asset.asset_type {
+		AssetType::Shader => shader_source_modified(asset_path, source_modified, files, include_resolver)?,
+		// The other faces of a cubemap don't have meta files pointing at the cubemap, so the newest face counts as the source.
+		AssetType::Texture => match asset.additional_data {
+			AdditionalAssetData::Texture(ref texture_asset) if texture_asset.format.is_cubemap() => texture_importer::cubemap_face_paths(asset_path)
+				.iter()
+				.flatten()
+				.filter_map(|face| files.get(face))
+				.fold(source_modified, |latest, &face_modified| latest.max(face_modified)),
+			_ => source_modified,
+		},
+		_ => source_modified,
+	};
+
+	// A migrated meta file was just rewritten, so the time from when the asset directory was scanned is already stale.
+	let meta_modified = match files.get(&meta_path) {
+		Some(&meta_modified) if !meta_migrated => meta_modified,
+		_ => modified_time(&fs::metadata(&meta_path).map_err(move |err| EditorError::Filesystem(err))?),
+	};
+
+	let mut results = Vec::with_capacity(asset.uuids.len() + asset.animation_uuids.len());
+
+	let sub_assets = asset
+		.uuids
+		.iter()
+		.enumerate()
+		.map(|(i, uuid)| (i, uuid, asset.asset_type))
+		.chain(asset.animation_uuids.iter().enumerate().map(|(i, uuid)| (i, uuid, AssetType::Animation)));
+
+	for (i, uuid, asset_type) in sub_assets {
+		let build_path = Path::new(BUILD_ASSET_DIR).join(uuid.to_string()).with_extension(BUILD_ASSET_EXTENSION);
+
+		let entry = ManifestEntry {
+			source_path: asset_path.to_path_buf(),
+			source_modified,
+			meta_modified,
+			importer_version: IMPORTER_VERSION,
+		};
 
-				let mut needs_reimport = asset.version != Asset::CURRENT_ASSET_VERSION || meta_file_was_created || !build_path.is_file();
+		let up_to_date = asset.version == Asset::CURRENT_ASSET_VERSION && built_assets.contains(uuid) && manifest.get(uuid).map_or(false, |previous| entry.matches(previous));
 
-				if !needs_reimport {
-					let build_meta = fs::metadata(&build_path).map_err(move |err| EditorError::Filesystem(err))?;
-					let asset_meta = fs::metadata(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
-					let meta_meta = fs::metadata(&meta_path).map_err(move |err| EditorError::Filesystem(err))?;
+		if up_to_date {
+			results.push((*uuid, asset_type, entry, Ok(false)));
+			continue;
+		}
 
-					let asset_modified_time = FileTime::from_last_modification_time(&asset_meta);
-					let build_modified_time = FileTime::from_last_modification_time(&build_meta);
-					let meta_modified_time = FileTime::from_last_modification_time(&meta_meta);
+		// Anything that goes wrong from here on only fails this one uuid, the rest of the source still gets built.
+		let mut build = || -> Result<Option<Vec<u8>>, EditorError> {
+			let serialized = match asset_type {
+				AssetType::Shader => {
+					let shader_data = fs::read_to_string(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
+					match shader_compiler::compile_shader(&asset_path, &shader_data, include_resolver)? {
+						Some(shader_asset) => Some(bincode::serialize(&shader_asset).map_err(move |_| EditorError::Serialize)?),
+						None => None,
+					}
+				}
+				AssetType::Mesh => {
+					if imported_scene.is_none() {
+						imported_scene = import_scene()?;
+					}
+
+					let Some(ref imported_scene) = imported_scene else
+					{
+						panic!("??");
+					};
 
-					needs_reimport = asset_modified_time > build_modified_time || meta_modified_time > build_modified_time;
+					Some(bincode::serialize(&imported_scene.meshes[i]).map_err(move |_| EditorError::Serialize)?)
 				}
+				AssetType::Animation => {
+					if imported_scene.is_none() {
+						imported_scene = import_scene()?;
+					}
 
-				if needs_reimport {
-					let serialized = match asset.asset_type {
-						AssetType::Shader => {
-							let shader_data = fs::read_to_string(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
-							let shader_asset = shader_compiler::compile_hlsl(&asset_path, &shader_data)?;
-
-							Some(bincode::serialize(&shader_asset).map_err(move |_| EditorError::Serialize)?)
-						}
-						AssetType::Mesh => {
-							if mesh_packages.is_none() {
-								mesh_packages = import_mesh_packages()?;
-							}
-
-							let Some(ref mesh_packages) = mesh_packages else
-							{
-								panic!("??");
-							};
-
-							Some(bincode::serialize(&mesh_packages[i]).map_err(move |_| EditorError::Serialize)?)
-						}
-						_ => None,
+					match imported_scene.as_ref().and_then(|scene| scene.animations.get(i)) {
+						Some(animation) => Some(bincode::serialize(animation).map_err(move |_| EditorError::Serialize)?),
+						None => None,
+					}
+				}
+				AssetType::Texture => {
+					let format = match asset.additional_data {
+						AdditionalAssetData::Texture(ref texture_asset) => texture_asset.format,
+						_ => TextureFormat::RGBA8UNorm,
 					};
 
-					if let Some(serialized) = serialized {
-						let mut output = fs::File::create(&build_path).map_err(move |err| EditorError::Filesystem(err))?;
-						output.write_all(&serialized).map_err(move |err| EditorError::Filesystem(err))?;
-
-						// Touch asset files
-						let now = FileTime::now();
-						if let Err(err) = filetime::set_file_mtime(&build_path, now) {
-							println!(
-								"WARNING: Failed to update date modified for build file {}! Maybe it wasn't created properly? {}",
-								build_path.as_path().to_str().unwrap_or("UNKNOWN_BUILD_PATH"),
-								err
-							);
-						}
-
-						if let Err(err) = filetime::set_file_mtime(&meta_path, now) {
-							println!("WARNING: Failed to update date modified for metadata file! Maybe it wasn't created properly? {}", err);
-						}
+					let texture_package = if format.is_cubemap() {
+						texture_importer::import_cubemap(&asset_path, format)?
 					} else {
-						println!("No output was created for asset {}!", uuid);
-					}
+						let data = fs::read(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
+						texture_importer::import_texture(&data, format)?
+					};
+
+					Some(bincode::serialize(&texture_package).map_err(move |_| EditorError::Serialize)?)
 				}
-			}
+				AssetType::Audio => {
+					let extension = asset_path.extension().unwrap().to_str().unwrap();
+					let data = fs::read(&asset_path).map_err(move |err| EditorError::Filesystem(err))?;
+					let audio_package = audio_importer::import_audio(&data, extension)?;
+
+					Some(bincode::serialize(&audio_package).map_err(move |_| EditorError::Serialize)?)
+				}
+				_ => None,
+			};
+
+			Ok(serialized)
+		};
+
+		match build().and_then(|serialized| serialized.map(|serialized| write_build_asset(&build_path, &serialized)).transpose()) {
+			Ok(Some(())) => results.push((*uuid, asset_type, entry, Ok(true))),
+			Ok(None) => warn!("No output was created for asset {}!", uuid),
+			Err(error) => results.push((*uuid, asset_type, entry, Err(error))),
 		}
 	}
-	Ok(())
+
+	Ok(results)
 }
 
 pub fn read_asset(uuid: Uuid, asset_type: AssetType) -> GoldfishResult<Package> {
 	let build_path = Path::new(BUILD_ASSET_DIR).join(uuid.to_string()).with_extension(BUILD_ASSET_EXTENSION);
+	let contents = read_build_asset(&build_path)?;
+
+	Package::deserialize(asset_type, &contents).map_err(|err| GoldfishError::Unknown(format!("{}. Try cleaning '.build' and reimporting all assets.", err)))
+}
+
+// An asset in the registry whose build doesn't load the way the engine would load it, see validate_assets.
+#[derive(Debug, Error)]
+#[error("{path} ({uuid}): {error}")]
+pub struct ValidationFailure {
+	// Relative to the asset directory, like the registry.
+	pub path: String,
+	pub uuid: Uuid,
+	pub error: String,
+}
 
-	match asset_type {
-		AssetType::Shader => {
-			let contents = fs::read(&build_path).map_err(move |err| GoldfishError::Filesystem(err))?;
+// Reads back every built asset in the registry and makes sure it deserializes, validating shaders' SPIR-V too.
+pub fn validate_assets() -> GoldfishResult<(usize, Vec<ValidationFailure>)> {
+	let registry = AssetRegistry::load(Path::new(ASSET_REGISTRY_PATH))?;
+
+	let mut assets = registry
+		.paths()
+		.flat_map(|path| registry.get(path).unwrap().iter().map(move |&(uuid, asset_type)| (path, uuid, asset_type)))
+		.collect::<Vec<_>>();
+	assets.sort_by_key(|&(path, uuid, _)| (path, uuid));
+
+	let failures = jobs::install(|| {
+		assets
+			.par_iter()
+			.filter_map(|&(path, uuid, asset_type)| {
+				let result = match read_asset(uuid, asset_type) {
+					Ok(Package::Shader(shader_package)) => shader_compiler::validate_spirv(&shader_package),
+					Ok(_) => Ok(()),
+					Err(err) => Err(err.to_string()),
+				};
+
+				result.err().map(|error| ValidationFailure { path: path.to_string(), uuid, error })
+			})
+			.collect::<Vec<_>>()
+	});
 
-			let package = bincode::deserialize::<ShaderPackage>(&contents)
-				.map_err(move |err| GoldfishError::Unknown("Failed to deserialize shader package: ".to_string() + &err.to_string() + ". Try cleaning '.build' and reimporting all assets."))?;
+	Ok((assets.len(), failures))
+}
 
-			Ok(Package::Shader(package))
+// Packs every built asset in the registry into a bundle, see goldfish::package::Bundle. Assets have to be imported first.
+pub fn cook_bundle(out: &Path) -> GoldfishResult<()> {
+	let registry = AssetRegistry::load(Path::new(ASSET_REGISTRY_PATH))?;
+
+	let mut payloads = Vec::new();
+	for path in registry.paths() {
+		for &(uuid, asset_type) in registry.get(path).unwrap() {
+			let build_path = Path::new(BUILD_ASSET_DIR).join(uuid.to_string()).with_extension(BUILD_ASSET_EXTENSION);
+			payloads.push((uuid, asset_type, read_build_asset(&build_path)?));
 		}
-		AssetType::Mesh => {
-			let contents = fs::read(&build_path).map_err(move |err| GoldfishError::Filesystem(err))?;
+	}
 
-			let package = bincode::deserialize::<MeshPackage>(&contents)
-				.map_err(move |err| GoldfishError::Unknown("Failed to deserialize mesh package: ".to_string() + &err.to_string() + ". Try cleaning '.build' and reimporting all assets."))?;
+	info!("Cooking {} assets into {}", payloads.len(), out.display());
+	Bundle::write(out, &registry, payloads)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fixtures_dir() -> PathBuf {
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+	}
 
-			Ok(Package::Mesh(package))
+	struct TempMeta(PathBuf);
+
+	impl TempMeta {
+		// A copy of the fixture, since reading an old meta file writes the migrated one back over it.
+		fn copy(name: &str, fixture: &str) -> Self {
+			let path = std::env::temp_dir().join(format!("goldfish_{}_{}.meta", name, std::process::id()));
+			fs::copy(fixtures_dir().join(fixture), &path).unwrap();
+			Self(path)
 		}
-		_ => unimplemented!(),
+	}
+
+	impl Drop for TempMeta {
+		fn drop(&mut self) {
+			let _ = fs::remove_file(&self.0);
+		}
+	}
+
+	#[test]
+	fn old_meta_files_migrate_and_get_saved_at_the_current_version() {
+		let meta = TempMeta::copy("migrated", "texture_v1_0.png.meta");
+
+		let (asset, migrated) = read_meta(&meta.0).unwrap();
+		assert!(migrated);
+		assert!(asset.version == Asset::CURRENT_ASSET_VERSION);
+		assert_eq!(asset.uuids, [Uuid::from_u128(0x6c2a1f38_5d0e_4b8e_9a57_3f1c2d4e5f60)]);
+		assert!(asset.animation_uuids.is_empty());
+		assert!(matches!(asset.additional_data, AdditionalAssetData::Texture(TextureAsset { format: TextureFormat::RGBA8UNorm })));
+
+		let saved = serde_json::from_str::<Asset>(&fs::read_to_string(&meta.0).unwrap()).unwrap();
+		assert!(saved.version == Asset::CURRENT_ASSET_VERSION);
+		assert_eq!(saved.uuids, asset.uuids);
+
+		let (_, migrated_again) = read_meta(&meta.0).unwrap();
+		assert!(!migrated_again);
+	}
+
+	#[test]
+	fn meta_files_from_newer_editors_are_refused() {
+		let meta = TempMeta::copy("newer", "texture_v1_0.png.meta");
+		let newer = fs::read_to_string(&meta.0).unwrap().replace("65536", &Version::new(2, 0).version.to_string());
+		fs::write(&meta.0, newer).unwrap();
+
+		assert!(matches!(read_meta(&meta.0), Err(EditorError::Metadata(message)) if message.contains("newer version")));
+	}
+
+	#[test]
+	fn touching_a_nested_include_rebuilds_the_shader() {
+		let dir = fixtures_dir().join("shader_includes");
+		let (a, b, c) = (dir.join("a.hlsl"), dir.join("b.hlsli"), dir.join("c.hlsli"));
+		let include_resolver = IncludeResolver::default();
+
+		assert_eq!(include_resolver.find_includes(&a).unwrap(), vec![b.clone(), c.clone()]);
+		assert_eq!(include_resolver.find_includes(&b).unwrap(), vec![c.clone()]);
+		assert!(include_resolver.find_includes(&c).unwrap().is_empty());
+
+		let mut files = [&a, &b, &c].into_iter().map(|path| (path.clone(), (100, 0))).collect::<HashMap<_, _>>();
+		let entry = |files: &HashMap<PathBuf, (i64, u32)>| ManifestEntry {
+			source_path: a.clone(),
+			source_modified: shader_source_modified(&a, files[&a], files, &include_resolver).unwrap(),
+			meta_modified: (100, 0),
+			importer_version: IMPORTER_VERSION,
+		};
+
+		let built = entry(&files);
+		assert!(entry(&files).matches(&built));
+
+		files.insert(c.clone(), (200, 0));
+		let touched = entry(&files);
+		assert_eq!(touched.source_modified, (200, 0));
+		assert!(!touched.matches(&built));
 	}
 }
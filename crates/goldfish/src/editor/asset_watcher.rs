@@ -0,0 +1,121 @@
+use super::asset;
+use super::EditorError;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use goldfish::package::AssetEvent;
+use log::{error, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Editors tend to save in more than one write (or write a temporary file and rename it), so wait for things to settle before reimporting.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+// Files that are still being written fail to import, those get retried a few times before giving up until the next change.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRIES: u32 = 5;
+
+// Reimports on a background thread whenever anything under the asset directory changes, and tells the engine what was rebuilt.
+// Stops watching once it's dropped.
+pub struct AssetWatcher {
+	watcher: Option<RecommendedWatcher>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl AssetWatcher {
+	pub fn new(asset_dir: &Path, shader_include_dirs: &[PathBuf], asset_events: Sender<AssetEvent>) -> Result<Self, EditorError> {
+		let (raw_sender, raw_receiver) = crossbeam_channel::unbounded();
+
+		let mut watcher = notify::recommended_watcher(move |event| {
+			let _ = raw_sender.send(event);
+		})
+		.map_err(EditorError::Watch)?;
+		watcher.watch(asset_dir, RecursiveMode::Recursive).map_err(EditorError::Watch)?;
+
+		let asset_dir = asset_dir.to_path_buf();
+		let shader_include_dirs = shader_include_dirs.to_vec();
+		let thread = std::thread::Builder::new()
+			.name("Asset Watcher".to_string())
+			.spawn(move || Self::run(asset_dir, shader_include_dirs, raw_receiver, asset_events))
+			.map_err(EditorError::Filesystem)?;
+
+		Ok(Self {
+			watcher: Some(watcher),
+			thread: Some(thread),
+		})
+	}
+
+	fn run(asset_dir: PathBuf, shader_include_dirs: Vec<PathBuf>, raw_events: Receiver<notify::Result<notify::Event>>, asset_events: Sender<AssetEvent>) {
+		// Pushed back every time something else changes, so a burst of writes only causes a single import.
+		let mut import_at: Option<Instant> = None;
+		let mut retries = 0;
+
+		loop {
+			let event = match import_at {
+				Some(deadline) => raw_events.recv_deadline(deadline),
+				None => raw_events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+			};
+
+			match event {
+				Ok(Ok(event)) => {
+					if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+						import_at = Some(Instant::now() + DEBOUNCE);
+						retries = 0;
+					}
+					continue;
+				}
+				Ok(Err(err)) => {
+					warn!("Asset watcher error! {}", err);
+					continue;
+				}
+				Err(RecvTimeoutError::Disconnected) => return,
+				Err(RecvTimeoutError::Timeout) => import_at = None,
+			}
+
+			// import_assets only rebuilds what changed according to the manifest, and takes care of generating
+			// meta files for new assets and cleaning up after deleted ones.
+			match asset::import_assets(&asset_dir, &shader_include_dirs, false) {
+				Ok(summary) => {
+					// The failures were already logged, whatever did import still gets sent along.
+					if !summary.failures.is_empty() && retries < MAX_RETRIES {
+						retries += 1;
+						warn!("{} assets failed to reimport, retrying ({}/{})!", summary.failures.len(), retries, MAX_RETRIES);
+						import_at = Some(Instant::now() + RETRY_DELAY);
+					}
+
+					let failed = summary.failures.iter().filter_map(|failure| {
+						failure.uuid.map(|uuid| AssetEvent::ImportFailed {
+							uuid,
+							message: failure.error.to_string(),
+						})
+					});
+					let changed = summary.imported_assets.into_iter().map(|(uuid, asset_type)| AssetEvent::AssetChanged { uuid, asset_type });
+					let removed = summary.removed_assets.into_iter().map(|uuid| AssetEvent::AssetRemoved { uuid });
+
+					for event in failed.chain(changed).chain(removed) {
+						// The engine is gone, nobody is left to reload anything.
+						if asset_events.send(event).is_err() {
+							return;
+						}
+					}
+				}
+				Err(err) if retries < MAX_RETRIES => {
+					retries += 1;
+					warn!("Failed to reimport assets, retrying ({}/{})! {}", retries, MAX_RETRIES, err);
+					import_at = Some(Instant::now() + RETRY_DELAY);
+				}
+				Err(err) => error!("Failed to reimport assets, giving up until something changes again! {}", err),
+			}
+		}
+	}
+}
+
+impl Drop for AssetWatcher {
+	fn drop(&mut self) {
+		// Dropping the watcher closes the channel the thread is waiting on.
+		std::mem::drop(self.watcher.take());
+
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
@@ -0,0 +1,58 @@
+use super::EditorError;
+use goldfish::package::AudioPackage;
+use hound::{SampleFormat, WavReader};
+use lewton::inside_ogg::OggStreamReader;
+use std::io::Cursor;
+
+// Everything gets converted to 16 bit samples, the engine resamples to the output rate itself so the sample rate is kept as is.
+pub fn import_audio(data: &[u8], extension: &str) -> Result<AudioPackage, EditorError> {
+	match extension.to_ascii_lowercase().as_str() {
+		"wav" => import_wav(data),
+		"ogg" => import_ogg(data),
+		_ => panic!("Unsupported audio extension {}!", extension),
+	}
+}
+
+fn import_wav(data: &[u8]) -> Result<AudioPackage, EditorError> {
+	let mut reader = WavReader::new(Cursor::new(data)).map_err(move |err| EditorError::WavImport(err))?;
+	let spec = reader.spec();
+
+	let samples = match spec.sample_format {
+		SampleFormat::Float => reader
+			.samples::<f32>()
+			.map(|sample| sample.map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+			.collect::<Result<Vec<_>, _>>(),
+		// hound hands back integer samples at their original bit depth (8 bit samples are already made signed), so shift them into 16 bits.
+		SampleFormat::Int => reader
+			.samples::<i32>()
+			.map(|sample| {
+				sample.map(|sample| match spec.bits_per_sample {
+					bits if bits > 16 => (sample >> (bits - 16)) as i16,
+					bits => (sample << (16 - bits)) as i16,
+				})
+			})
+			.collect::<Result<Vec<_>, _>>(),
+	}
+	.map_err(move |err| EditorError::WavImport(err))?;
+
+	Ok(AudioPackage {
+		sample_rate: spec.sample_rate,
+		channels: spec.channels,
+		samples,
+	})
+}
+
+fn import_ogg(data: &[u8]) -> Result<AudioPackage, EditorError> {
+	let mut reader = OggStreamReader::new(Cursor::new(data)).map_err(move |err| EditorError::OggImport(err))?;
+
+	let mut samples = Vec::new();
+	while let Some(packet) = reader.read_dec_packet_itl().map_err(move |err| EditorError::OggImport(err))? {
+		samples.extend(packet);
+	}
+
+	Ok(AudioPackage {
+		sample_rate: reader.ident_hdr.audio_sample_rate,
+		channels: reader.ident_hdr.audio_channels as u16,
+		samples,
+	})
+}
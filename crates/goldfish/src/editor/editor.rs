@@ -2,28 +2,61 @@
 #![allow(unused_imports)]
 
 mod asset;
+mod asset_watcher;
+mod audio_importer;
 mod mesh_importer;
 mod shader_compiler;
-use goldfish::game::{CreateGamelibApi, GameLib};
+mod texture_importer;
+use clap::{Parser, Subcommand};
+use goldfish::config::GoldfishConfig;
+use goldfish::game::{GameLib, FIXED_UPDATE_INTERVAL};
+use goldfish::package::AssetEvent;
+use goldfish::renderer;
 use goldfish::GoldfishEngine;
-use libloading::{Library, Symbol};
-use std::path::Path;
+use libloading::Library;
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 use thiserror::Error;
 
 use asset::read_asset;
 
-const ASSET_DIR: &'static str = "assets/";
 const BUILD_DIR: &'static str = ".build/";
 const BUILD_ASSET_DIR: &'static str = ".build/assets/";
+const GAME_LIB_PATH: &'static str = "target/debug/libgame.so";
+// How far any channel of a replayed pixel can be from the golden image, drivers don't all round the same way.
+const REPLAY_TOLERANCE: u8 = 2;
+const MAX_FIXED_UPDATES_PER_FRAME: u32 = 4;
 
 #[derive(Error, Debug)]
 pub enum EditorError {
 	#[error("Failed to import mesh: {0}")]
 	MeshImport(russimp::RussimpError),
+	#[error("Failed to import texture: {0}")]
+	TextureImport(image::ImageError),
+	#[error("Textures can't be imported as {0:?}")]
+	UnsupportedTextureFormat(goldfish::renderer::TextureFormat),
+	#[error("Failed to import cubemap: {0}")]
+	CubemapImport(String),
+	#[error("Failed to import wav: {0}")]
+	WavImport(hound::Error),
+	#[error("Failed to import ogg: {0}")]
+	OggImport(lewton::VorbisError),
 	#[error("Failed to compile shader: {0}")]
 	ShaderCompilation(hassle_rs::HassleError),
+	#[error("Failed to compile shader {0}: {1}")]
+	ShaderTranslation(std::path::PathBuf, String),
+	#[error("Shader include cycle: {0}")]
+	ShaderIncludeCycle(String),
+	#[error("Invalid permutations in {0}: {1}")]
+	ShaderPermutations(std::path::PathBuf, String),
 	#[error("Failed to reflect spirv: {0}")]
 	ShaderReflection(rspirv_reflect::ReflectError),
+	#[error("Invalid metadata: {0}")]
+	Metadata(String),
+	#[error("Failed to watch the asset directory: {0}")]
+	Watch(notify::Error),
 	#[error("Failed to serialize")]
 	Serialize,
 	#[error("Failed to deserialize")]
@@ -34,29 +67,308 @@ pub enum EditorError {
 	Unknown,
 }
 
-fn main() {
+// Without a subcommand the editor imports everything and opens the game.
+#[derive(Parser)]
+#[command(name = "goldfish_editor")]
+struct Cli {
+	/// Use this asset directory instead of the one in the config
+	#[arg(long, global = true)]
+	asset_dir: Option<PathBuf>,
+	#[command(subcommand)]
+	command: Option<EditorCommand>,
+}
+
+// The doc comments here are what --help prints. Only replay and reload_stress touch the GPU.
+#[derive(Subcommand)]
+enum EditorCommand {
+	/// Import everything under the asset directory, carrying on past assets that fail
+	Import {
+		/// Rebuild every asset, even the ones that are up to date
+		#[arg(long)]
+		force: bool,
+	},
+	/// Import and then pack every asset into a bundle the game can run from
+	Cook {
+		#[arg(long)]
+		out: PathBuf,
+	},
+	/// Check that every built asset loads, and that shaders pass SPIR-V validation
+	Validate,
+	/// Reimport whenever anything under the asset directory changes, until the process is killed
+	Watch,
+	/// Replay a frame captured with frame_capture and write what it rendered to a PNG
+	Replay {
+		capture: PathBuf,
+		#[arg(long)]
+		out: PathBuf,
+		/// Fail if the replay doesn't match this PNG
+		#[arg(long)]
+		golden: Option<PathBuf>,
+	},
+	/// Load and unload the game lib over and over, failing on validation errors or GPU allocations that outlive an unload
+	ReloadStress {
+		#[arg(long, default_value_t = 50)]
+		cycles: u32,
+	},
+}
+
+fn main() -> ExitCode {
+	let cli = Cli::parse();
+
+	let mut config = GoldfishConfig::load_or_default();
+	goldfish::logging::init_from_config(&config.log).unwrap_or_else(|err| panic!("Failed to set up logging: {}", err));
+
+	if let Some(asset_dir) = cli.asset_dir {
+		config.asset_dir = asset_dir;
+	}
+
+	if !config.asset_dir.is_dir() {
+		error!("Failed to find the asset directory {}!", config.asset_dir.display());
+		return ExitCode::FAILURE;
+	}
+
+	let succeeded = match cli.command {
+		Some(EditorCommand::Import { force }) => import(&config, force),
+		// A bundle with stale assets in it is worse than no bundle at all.
+		Some(EditorCommand::Cook { out }) => import(&config, false) && cook(&out),
+		Some(EditorCommand::Validate) => validate(),
+		Some(EditorCommand::Watch) => watch(&config),
+		Some(EditorCommand::Replay { capture, out, golden }) => replay(&config, &capture, &out, golden.as_deref()),
+		Some(EditorCommand::ReloadStress { cycles }) => import(&config, false) && reload_stress(config, cycles),
+		None => {
+			run(config);
+			true
+		}
+	};
+
+	if succeeded {
+		ExitCode::SUCCESS
+	} else {
+		ExitCode::FAILURE
+	}
+}
+
+fn import(config: &GoldfishConfig, force: bool) -> bool {
+	match asset::import_assets(&config.asset_dir, &config.shader_include_dirs, force) {
+		Ok(summary) if summary.failures.is_empty() => true,
+		Ok(summary) => {
+			error!("{} assets failed to import", summary.failures.len());
+			false
+		}
+		Err(err) => {
+			error!("Failed to import assets: {}", err);
+			false
+		}
+	}
+}
+
+fn cook(out: &Path) -> bool {
+	match asset::cook_bundle(out) {
+		Ok(()) => true,
+		Err(err) => {
+			error!("Failed to cook bundle: {}", err);
+			false
+		}
+	}
+}
+
+fn validate() -> bool {
+	match asset::validate_assets() {
+		Ok((checked, failures)) => {
+			for failure in &failures {
+				error!("Invalid asset {}", failure);
+			}
+
+			info!("Validated {} assets, {} failed", checked, failures.len());
+			failures.is_empty()
+		}
+		Err(err) => {
+			error!("Failed to read the asset registry, assets have to be imported first! {}", err);
+			false
+		}
+	}
+}
+
+fn watch(config: &GoldfishConfig) -> bool {
+	// Failures are logged and retried once their source changes, same as in the editor.
+	import(config, false);
+
+	let (asset_event_sender, asset_event_receiver) = crossbeam_channel::unbounded();
+	let _asset_watcher = match asset_watcher::AssetWatcher::new(&config.asset_dir, &config.shader_include_dirs, asset_event_sender) {
+		Ok(asset_watcher) => asset_watcher,
+		Err(err) => {
+			error!("Failed to start the asset watcher: {}", err);
+			return false;
+		}
+	};
+
+	info!("Watching {} for changes", config.asset_dir.display());
+
+	// The watcher only hangs up when its thread is gone, which means something went badly wrong.
+	for event in asset_event_receiver {
+		match event {
+			AssetEvent::AssetChanged { uuid, asset_type } => info!("Reimported {:?} {}", asset_type, uuid),
+			AssetEvent::AssetRemoved { uuid } => info!("Removed {}", uuid),
+			AssetEvent::ImportFailed { .. } => (),
+		}
+	}
+
+	error!("The asset watcher stopped unexpectedly!");
+	false
+}
+
+fn replay(config: &GoldfishConfig, capture: &Path, out: &Path, golden: Option<&Path>) -> bool {
+	let capture = match renderer::FrameCapture::load(capture) {
+		Ok(capture) => capture,
+		Err(err) => {
+			error!("Failed to load {}: {}", capture.display(), err);
+			return false;
+		}
+	};
+
+	let window = match goldfish::window::Window::new_hidden("Goldfish Replay") {
+		Ok(window) => window,
+		Err(err) => {
+			error!("Failed to create a window to replay in: {}", err);
+			return false;
+		}
+	};
+
+	let mut device = renderer::GraphicsDevice::new(&window, config.frames_in_flight, config.validation_desc().as_ref(), config.device_selection(), false);
+	let result = device.replay_capture(&capture);
+	device.destroy();
+
+	let output = match result {
+		Ok(output) => output,
+		Err(err) => {
+			error!("Failed to replay the capture: {}", err);
+			return false;
+		}
+	};
+
+	info!("Replayed {} commands at {}x{}", capture.cmd_count(), output.width, output.height);
+
+	let Some(image) = image::RgbaImage::from_raw(output.width, output.height, output.pixels.clone()) else {
+		error!("The replay didn't read back a whole {}x{} image!", output.width, output.height);
+		return false;
+	};
+
+	if let Err(err) = image.save(out) {
+		error!("Failed to write {}: {}", out.display(), err);
+		return false;
+	}
+
+	let Some(golden) = golden else {
+		return true;
+	};
+
+	let expected = match image::open(golden) {
+		Ok(expected) => expected.into_rgba8(),
+		Err(err) => {
+			error!("Failed to read the golden image {}: {}", golden.display(), err);
+			return false;
+		}
+	};
+
+	if expected.dimensions() != image.dimensions() {
+		error!("The golden image is {:?} but the replay is {:?}", expected.dimensions(), image.dimensions());
+		return false;
+	}
+
+	let mismatched = output.mismatched_pixels(expected.as_raw(), REPLAY_TOLERANCE);
+
+	if mismatched > 0 {
+		error!("{} pixels differ from {}", mismatched, golden.display());
+		return false;
+	}
+
+	info!("Matches {}", golden.display());
+	true
+}
+
+// Each cycle tears down the way the editor does on exit, so a missed destroy shows up on the cycle that did it.
+fn reload_stress(config: GoldfishConfig, cycles: u32) -> bool {
+	let mut engine = GoldfishEngine::new("Goldfish Reload Stress", Box::new(read_asset), Some(config));
+	engine.graphics_device.take_validation_error();
+	let allocation_count = engine.graphics_device.memory_stats().allocation_count;
+
+	for cycle in 0..cycles {
+		let lib = match unsafe { Library::new(Path::new(GAME_LIB_PATH)) } {
+			Ok(lib) => lib,
+			Err(err) => {
+				error!("Failed to load libgame: {}", err);
+				return false;
+			}
+		};
+
+		let game_lib = match unsafe { GameLib::load(&lib) } {
+			Ok(game_lib) => game_lib,
+			Err(err) => {
+				error!("{}", err);
+				return false;
+			}
+		};
+
+		(game_lib.on_load)(&mut engine);
+		(game_lib.on_unload)(&mut engine);
+		engine.graphics_device.flush_destruction_queues();
+		drop(lib);
+
+		if engine.graphics_device.take_validation_error() {
+			error!("Cycle {} raised a validation error", cycle);
+			return false;
+		}
+
+		let leaked = engine.graphics_device.memory_stats().allocation_count.saturating_sub(allocation_count);
+		if leaked > 0 {
+			error!("Cycle {} left {} GPU allocations behind", cycle, leaked);
+			return false;
+		}
+	}
+
+	info!("Loaded and unloaded the game {} times", cycles);
+	true
+}
+
+fn run(mut config: GoldfishConfig) {
 	if !Path::new(BUILD_DIR).is_dir() {
 		panic!("Failed to find build directory!");
 	}
 
-	if !Path::new(ASSET_DIR).is_dir() {
-		panic!("Failed to find resource directory!");
-	}
+	let lib = unsafe { Library::new(Path::new(GAME_LIB_PATH)).expect("Failed to load libgame!") };
 
-	let lib = unsafe { Library::new(Path::new("target/debug/libgame.so")).expect("Failed to load libgame!") };
+	// A stale game lib built against a different GameLib layout is refused here rather than called into.
+	let game_lib = unsafe { GameLib::load_or_noop(&lib) };
 
-	let game_lib = unsafe { lib.get::<Symbol<CreateGamelibApi>>(b"_goldfish_create_game_lib").expect("No gamelib constructor found!")() };
+	// Whatever failed was already logged, the game can still run with everything else.
+	if let Err(err) = asset::import_assets(&config.asset_dir, &config.shader_include_dirs, false) {
+		panic!("Failed to import assets: {}", err);
+	}
 
-	match asset::import_assets(Path::new(ASSET_DIR)) {
-		Err(err) => panic!("Failed to import assets: {}", err),
-		_ => (),
+	// Handy for latency testing without touching the config, anything the device can't do just gets clamped.
+	if let Some(frames_in_flight) = std::env::var("GOLDFISH_FRAMES_IN_FLIGHT").ok().and_then(|v| v.parse().ok()) {
+		config.frames_in_flight = frames_in_flight;
 	}
 
-	let mut engine = GoldfishEngine::new("Goldfish Editor", read_asset);
+	let mut engine = GoldfishEngine::new("Goldfish Editor", Box::new(read_asset), Some(config));
+
+	// Keeps reimporting in the background for as long as the editor is open, the game hears about it through engine.drain_asset_events.
+	let _asset_watcher = asset_watcher::AssetWatcher::new(&engine.config().asset_dir, &engine.config().shader_include_dirs, engine.asset_event_sender())
+		.unwrap_or_else(|err| panic!("Failed to start the asset watcher: {}", err));
 
 	(game_lib.on_load)(&mut engine);
 
-	engine.run(|engine, _| {
+	// Fixed updates catch up on however much time has passed, but only so far so a long hitch doesn't turn into a long stall.
+	let mut fixed_update_time = Duration::ZERO;
+	engine.run(|engine, dt| {
+		if let Some(on_fixed_update) = game_lib.on_fixed_update {
+			fixed_update_time = (fixed_update_time + dt).min(FIXED_UPDATE_INTERVAL * MAX_FIXED_UPDATES_PER_FRAME);
+			while fixed_update_time >= FIXED_UPDATE_INTERVAL {
+				fixed_update_time -= FIXED_UPDATE_INTERVAL;
+				on_fixed_update(engine);
+			}
+		}
+
 		(game_lib.on_update)(engine);
 	});
 
@@ -1,9 +1,32 @@
 use super::EditorError;
-use glam::{vec2, vec3};
-use goldfish::{package::MeshPackage, renderer::Vertex};
+use glam::{quat, vec2, vec3, Mat4};
+use goldfish::animation::{AnimationClip, Keyframes, Track};
+use goldfish::package::{JointPackage, MeshPackage, SkinPackage};
+use goldfish::renderer::Vertex;
+use russimp::animation::Animation;
+use russimp::node::Node;
 use russimp::scene::{PostProcess, Scene};
+use russimp::Matrix4x4;
+use std::collections::HashMap;
 
-pub fn import_mesh(data: &[u8], extension: &str) -> Result<Vec<MeshPackage>, EditorError> {
+// Matches SkinnedVertex, LimitBoneWeights keeps assimp from giving us any more than this.
+const MAX_BONE_INFLUENCES: usize = 4;
+// What assimp assumes when a file doesn't say how fast its animations tick.
+const DEFAULT_TICKS_PER_SECOND: f64 = 25.0;
+
+struct SceneNode {
+	transform: Mat4,
+	parent: Option<String>,
+	depth: usize,
+}
+
+// Everything that comes out of a single mesh file, each mesh and each animation gets its own uuid in the meta file.
+pub struct ImportedScene {
+	pub meshes: Vec<MeshPackage>,
+	pub animations: Vec<AnimationClip>,
+}
+
+pub fn import_mesh(data: &[u8], extension: &str) -> Result<ImportedScene, EditorError> {
 	let scene = Scene::from_buffer(
 		data,
 		vec![
@@ -12,11 +35,18 @@ pub fn import_mesh(data: &[u8], extension: &str) -> Result<Vec<MeshPackage>, Edi
 			PostProcess::JoinIdenticalVertices,
 			PostProcess::SortByPrimitiveType,
 			PostProcess::MakeLeftHanded,
+			PostProcess::LimitBoneWeights,
 		],
 		extension,
 	)
 	.map_err(move |err| EditorError::MeshImport(err))?;
-	Ok(scene
+
+	let mut nodes = HashMap::new();
+	if let Some(ref root) = scene.root {
+		collect_nodes(root, None, 0, &mut nodes);
+	}
+
+	let meshes = scene
 		.meshes
 		.iter()
 		.map(|mesh| {
@@ -39,7 +69,138 @@ pub fn import_mesh(data: &[u8], extension: &str) -> Result<Vec<MeshPackage>, Edi
 				})
 				.collect::<Vec<u16>>();
 
-			MeshPackage { vertices, indices }
+			let skin = import_skin(mesh, &nodes);
+
+			// Assimp puts glTF's COLOR_0 and TEXCOORD_1 (and the colors some OBJ exporters put after the positions) in
+			// the first color set and the second uv set.
+			let colors = mesh.colors.first().and_then(Option::as_ref).map(|colors| {
+				colors
+					.iter()
+					.map(|color| [color.r, color.g, color.b, color.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+					.collect::<Vec<_>>()
+			});
+			let uv1 = mesh.texture_coords.get(1).and_then(Option::as_ref).map(|uv| uv.iter().map(|uv| [uv.x, uv.y]).collect::<Vec<_>>());
+
+			MeshPackage { vertices, indices, skin, colors, uv1 }
+		})
+		.collect::<Vec<_>>();
+
+	let animations = scene.animations.iter().map(import_animation).collect::<Vec<_>>();
+
+	Ok(ImportedScene { meshes, animations })
+}
+
+fn import_skin(mesh: &russimp::mesh::Mesh, nodes: &HashMap<String, SceneNode>) -> Option<SkinPackage> {
+	if mesh.bones.is_empty() {
+		return None;
+	}
+
+	assert!(mesh.bones.len() <= u16::MAX as usize, "Too many bones!");
+
+	// Parents have to come before their children, which sorting by depth in the scene takes care of.
+	let mut bones = mesh.bones.iter().collect::<Vec<_>>();
+	bones.sort_by_key(|bone| nodes.get(&bone.name).map_or(0, |node| node.depth));
+
+	let joint_indices = bones.iter().enumerate().map(|(i, bone)| (bone.name.as_str(), i as u16)).collect::<HashMap<_, _>>();
+
+	let joints = bones
+		.iter()
+		.map(|bone| {
+			let node = nodes.get(&bone.name);
+			let mut rest_transform = node.map_or(Mat4::IDENTITY, |node| node.transform);
+			let mut parent = None;
+
+			// Not every node between two joints has to be a joint itself, anything in between gets folded into the rest transform.
+			let mut ancestor = node.and_then(|node| node.parent.as_deref());
+			while let Some(name) = ancestor {
+				if let Some(&index) = joint_indices.get(name) {
+					parent = Some(index);
+					break;
+				}
+
+				let node = &nodes[name];
+				rest_transform = node.transform * rest_transform;
+				ancestor = node.parent.as_deref();
+			}
+
+			JointPackage {
+				name: bone.name.clone(),
+				parent,
+				inverse_bind_matrix: to_mat4(&bone.offset_matrix).to_cols_array(),
+				rest_transform: rest_transform.to_cols_array(),
+			}
 		})
-		.collect::<Vec<_>>())
+		.collect::<Vec<_>>();
+
+	let mut bone_indices = vec![[0u16; MAX_BONE_INFLUENCES]; mesh.vertices.len()];
+	let mut bone_weights = vec![[0.0f32; MAX_BONE_INFLUENCES]; mesh.vertices.len()];
+	let mut influence_counts = vec![0usize; mesh.vertices.len()];
+
+	for (joint, bone) in bones.iter().enumerate() {
+		for weight in bone.weights.iter() {
+			let vertex = weight.vertex_id as usize;
+			let slot = influence_counts[vertex];
+			if slot >= MAX_BONE_INFLUENCES {
+				continue;
+			}
+
+			bone_indices[vertex][slot] = joint as u16;
+			bone_weights[vertex][slot] = weight.weight;
+			influence_counts[vertex] += 1;
+		}
+	}
+
+	for weights in bone_weights.iter_mut() {
+		let total = weights.iter().sum::<f32>();
+		if total > 0.0 {
+			weights.iter_mut().for_each(|weight| *weight /= total);
+		}
+	}
+
+	Some(SkinPackage { bone_indices, bone_weights, joints })
+}
+
+// Tracks are matched up with joints by node name, see AnimationClip::sample_skin_pose.
+fn import_animation(animation: &Animation) -> AnimationClip {
+	let ticks_per_second = if animation.ticks_per_second > 0.0 { animation.ticks_per_second } else { DEFAULT_TICKS_PER_SECOND };
+	let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+
+	let tracks = animation
+		.channels
+		.iter()
+		.map(|channel| Track {
+			node: channel.name.clone(),
+			translation: Keyframes::new(channel.position_keys.iter().map(|key| (seconds(key.time), vec3(key.value.x, key.value.y, key.value.z))).collect()),
+			rotation: Keyframes::new(
+				channel
+					.rotation_keys
+					.iter()
+					.map(|key| (seconds(key.time), quat(key.value.x, key.value.y, key.value.z, key.value.w).normalize()))
+					.collect(),
+			),
+			scale: Keyframes::new(channel.scaling_keys.iter().map(|key| (seconds(key.time), vec3(key.value.x, key.value.y, key.value.z))).collect()),
+		})
+		.collect();
+
+	AnimationClip::new(animation.name.clone(), tracks)
+}
+
+fn collect_nodes(node: &Node, parent: Option<&str>, depth: usize, nodes: &mut HashMap<String, SceneNode>) {
+	nodes.insert(
+		node.name.clone(),
+		SceneNode {
+			transform: to_mat4(&node.transformation),
+			parent: parent.map(str::to_owned),
+			depth,
+		},
+	);
+
+	for child in node.children.borrow().iter() {
+		collect_nodes(child, Some(&node.name), depth + 1, nodes);
+	}
+}
+
+// Assimp matrices are row major.
+fn to_mat4(m: &Matrix4x4) -> Mat4 {
+	Mat4::from_cols_array(&[m.a1, m.b1, m.c1, m.d1, m.a2, m.b2, m.c2, m.d2, m.a3, m.b3, m.c3, m.d3, m.a4, m.b4, m.c4, m.d4])
 }
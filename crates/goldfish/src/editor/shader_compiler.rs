@@ -1,35 +1,180 @@
 use super::EditorError;
 use goldfish::{
-	package::ShaderPackage,
-	renderer::{CS_MAIN, PS_MAIN, VS_MAIN},
+	package::{ShaderPackage, ShaderPermutationPackage},
+	renderer::{CS_MAIN, GS_MAIN, PS_MAIN, VS_MAIN},
 };
 use hassle_rs::{Dxc, DxcIncludeHandler, HassleError};
-use std::path::Path;
+use log::{debug, error, warn};
+use naga::{
+	back::spv,
+	front::{glsl, wgsl},
+	valid::{Capabilities, ValidationFlags, Validator},
+	Module, ShaderStage,
+};
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Once;
+
+// `// #pragma goldfish permute ALPHA_TEST SKINNED` compiles the shader once for every combination of the listed defines. The game's
+// build.rs has its own copy of the permutation and include handling below, keep the two in step.
+const PERMUTE_PRAGMA: &'static str = "// #pragma goldfish permute";
+const MAX_PERMUTATIONS: usize = 32;
+
+// Where includes are looked for when they aren't next to the file including them, tried in order.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeResolver {
+	roots: Vec<PathBuf>,
+}
+
+// Lexically drops . and .. so the same file always ends up with the same path, canonicalize would make everything absolute.
+fn normalize(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+				normalized.pop();
+			}
+			component => normalized.push(component),
+		}
+	}
+
+	normalized
+}
+
+impl IncludeResolver {
+	pub fn new(roots: Vec<PathBuf>) -> Self {
+		Self { roots }
+	}
+
+	// Relative to the directory of the file including it first, then each root in order.
+	pub fn resolve(&self, dir: &Path, include: &str) -> Option<PathBuf> {
+		std::iter::once(dir)
+			.chain(self.roots.iter().map(PathBuf::as_path))
+			.map(|root| normalize(&root.join(include)))
+			.find(|path| path.is_file())
+	}
+
+	// DXC has already joined the include onto the directory of the file including it by the time it asks for it, so the
+	// include as it was written is some suffix of `filename`. The longest suffix that exists under a root wins.
+	fn resolve_dxc(&self, dir: &Path, filename: &str) -> Option<PathBuf> {
+		let components = Path::new(filename).components().filter(|component| *component != Component::CurDir).collect::<Vec<_>>();
+		let suffixes = (0..components.len()).map(|skip| components[skip..].iter().collect::<PathBuf>()).collect::<Vec<_>>();
+
+		std::iter::once(dir.join(filename))
+			.chain(self.roots.iter().flat_map(|root| suffixes.iter().map(move |suffix| root.join(suffix))))
+			.map(|path| normalize(&path))
+			.find(|path| path.is_file())
+	}
+
+	fn find_includes_recursive(&self, path: &Path, stack: &mut Vec<PathBuf>, includes: &mut Vec<PathBuf>) -> Result<(), EditorError> {
+		let src = std::fs::read_to_string(path).map_err(move |err| EditorError::Filesystem(err))?;
+		let dir = path.parent().unwrap_or(Path::new("./"));
+
+		stack.push(path.to_path_buf());
+		for include in parse_includes(&src) {
+			let Some(include_path) = self.resolve(dir, include) else {
+				error!("Failed to find included file {} from {}", include, path.to_str().unwrap());
+				continue;
+			};
+
+			if let Some(start) = stack.iter().position(|visiting| *visiting == include_path) {
+				let cycle = stack[start..].iter().chain(std::iter::once(&include_path)).map(|path| path.to_string_lossy()).collect::<Vec<_>>();
+				return Err(EditorError::ShaderIncludeCycle(cycle.join(" -> ")));
+			}
+
+			// Diamonds are fine, each file only needs to be visited once.
+			if includes.contains(&include_path) {
+				continue;
+			}
+
+			includes.push(include_path.clone());
+			self.find_includes_recursive(&include_path, stack, includes)?;
+		}
+		stack.pop();
+
+		Ok(())
+	}
+
+	// Every file the shader at `path` transitively includes, an include cycle is an error even with include guards.
+	pub fn find_includes(&self, path: &Path) -> Result<Vec<PathBuf>, EditorError> {
+		let mut includes = Vec::new();
+		self.find_includes_recursive(&normalize(path), &mut Vec::new(), &mut includes)?;
+
+		Ok(includes)
+	}
+}
 
 struct ShaderIncludeHandler<'a> {
 	path: &'a Path,
+	resolver: &'a IncludeResolver,
 }
 
 impl<'a> DxcIncludeHandler for ShaderIncludeHandler<'a> {
 	fn load_source(&mut self, filename: String) -> Option<String> {
-		let full_path = self.path.join(filename);
-
-		use std::io::Read;
-		match std::fs::File::open(&full_path) {
-			Ok(mut f) => {
-				let mut content = String::new();
-				f.read_to_string(&mut content).ok()?;
-				Some(content)
+		match self.resolver.resolve_dxc(self.path, &filename) {
+			Some(full_path) => {
+				debug!("Resolved include {} to {}", filename, full_path.to_str().unwrap());
+				std::fs::read_to_string(&full_path).ok()
 			}
-			Err(_) => {
-				println!("Failed to find included file {}", full_path.to_str().unwrap());
+			None => {
+				error!("Failed to find included file {} in {} or any of the include roots", filename, self.path.to_str().unwrap());
 				None
 			}
 		}
 	}
 }
 
-pub fn compile_hlsl(path: &Path, src: &str) -> Result<ShaderPackage, EditorError> {
+fn parse_includes(src: &str) -> impl Iterator<Item = &str> {
+	src.lines().filter_map(|line| {
+		let include = line.trim_start().strip_prefix("#include")?.trim_start().strip_prefix('"')?;
+		Some(&include[..include.find('"')?])
+	})
+}
+
+// Bit i of a permutation's mask is the i-th define listed, in the order they're written in the source.
+fn parse_permutations(path: &Path, src: &str) -> Result<Vec<String>, EditorError> {
+	let invalid = |message: String| EditorError::ShaderPermutations(path.to_path_buf(), message);
+
+	let mut defines: Vec<String> = Vec::new();
+	for line in src.lines() {
+		let Some(permute) = line.trim_start().strip_prefix(PERMUTE_PRAGMA) else {
+			continue;
+		};
+
+		for define in permute.split_whitespace() {
+			if define.starts_with(|c: char| c.is_ascii_digit()) || !define.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+				return Err(invalid(format!("{} isn't a valid define", define)));
+			}
+
+			if defines.iter().any(|existing| existing == define) {
+				return Err(invalid(format!("{} is listed more than once", define)));
+			}
+
+			defines.push(define.to_owned());
+		}
+	}
+
+	let max_defines = MAX_PERMUTATIONS.trailing_zeros() as usize;
+	if defines.len() > max_defines {
+		return Err(invalid(format!(
+			"{} defines would be 2^{} permutations, at most {} defines ({} permutations) are allowed",
+			defines.len(),
+			defines.len(),
+			max_defines,
+			MAX_PERMUTATIONS
+		)));
+	}
+
+	Ok(defines)
+}
+
+fn permutation_defines(defines: &[String], mask: usize) -> Vec<&str> {
+	defines.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0).map(|(_, define)| define.as_str()).collect()
+}
+
+pub fn compile_hlsl(path: &Path, src: &str, resolver: &IncludeResolver) -> Result<ShaderPackage, EditorError> {
 	let dxc = Dxc::new(None).map_err(move |err| EditorError::ShaderCompilation(err))?;
 
 	let compiler = dxc.create_compiler().map_err(move |err| EditorError::ShaderCompilation(err))?;
@@ -46,6 +191,7 @@ pub fn compile_hlsl(path: &Path, src: &str) -> Result<ShaderPackage, EditorError
 			args,
 			Some(&mut ShaderIncludeHandler {
 				path: path.parent().unwrap_or(Path::new("./")),
+				resolver,
 			}),
 			defines,
 		);
@@ -65,9 +211,173 @@ pub fn compile_hlsl(path: &Path, src: &str) -> Result<ShaderPackage, EditorError
 		}
 	};
 
-	let vs_ir = if src.contains(VS_MAIN) { Some(compile(VS_MAIN, "vs_6_0", &["-spirv"], &[])?) } else { None };
+	let defines = parse_permutations(path, src)?;
+	let permutations = (0..1usize << defines.len())
+		.map(|mask| {
+			let permutation_defines = permutation_defines(&defines, mask).into_iter().map(|define| (define, Some("1"))).collect::<Vec<_>>();
+			let compile_stage = |entry_point: &str, target_profile: &str| -> Result<Option<Vec<u32>>, EditorError> {
+				if src.contains(entry_point) {
+					compile(entry_point, target_profile, &["-spirv"], &permutation_defines).map(Some)
+				} else {
+					Ok(None)
+				}
+			};
+
+			Ok(ShaderPermutationPackage {
+				vs_ir: compile_stage(VS_MAIN, "vs_6_0")?,
+				ps_ir: compile_stage(PS_MAIN, "ps_6_0")?,
+				gs_ir: compile_stage(GS_MAIN, "gs_6_0")?,
+				cs_ir: compile_stage(CS_MAIN, "cs_6_0")?,
+			})
+		})
+		.collect::<Result<Vec<_>, EditorError>>()?;
+
+	Ok(ShaderPackage { defines, permutations })
+}
+
+// GLSL has no way of putting more than one stage in a file, so the stage comes from the extension, either directly (lights.comp) or
+// just before .glsl (lights.comp.glsl).
+fn glsl_stage(path: &Path) -> Option<ShaderStage> {
+	let extension = match path.extension()?.to_str()? {
+		"glsl" => Path::new(path.file_stem()?).extension()?.to_str()?,
+		extension => extension,
+	};
+
+	match extension {
+		"vert" => Some(ShaderStage::Vertex),
+		"frag" => Some(ShaderStage::Fragment),
+		"comp" => Some(ShaderStage::Compute),
+		_ => None,
+	}
+}
+
+// Everything gets compiled through naga, the entry points are renamed to the same ones the HLSL shaders use so that the pipelines
+// don't need to care what language a shader was written in.
+fn write_spirv(path: &Path, module: &mut Module) -> Result<ShaderPackage, EditorError> {
+	let translation_error = |err: String| EditorError::ShaderTranslation(path.to_path_buf(), err);
+
+	let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+		.validate(module)
+		.map_err(|err| translation_error(err.to_string()))?;
+
+	let mut package = ShaderPermutationPackage::default();
+
+	// Naga flips y by default to match wgpu, our shaders are all written against Vulkan's coordinate space.
+	let options = spv::Options {
+		flags: spv::WriterFlags::DEBUG,
+		..Default::default()
+	};
+
+	for i in 0..module.entry_points.len() {
+		let (ir, entry_point) = match module.entry_points[i].stage {
+			ShaderStage::Vertex => (&mut package.vs_ir, VS_MAIN),
+			ShaderStage::Fragment => (&mut package.ps_ir, PS_MAIN),
+			ShaderStage::Compute => (&mut package.cs_ir, CS_MAIN),
+		};
+
+		if ir.is_some() {
+			return Err(translation_error(format!("more than one {:?} entry point", module.entry_points[i].stage)));
+		}
+
+		module.entry_points[i].name = entry_point.to_owned();
+
+		let pipeline_options = spv::PipelineOptions {
+			shader_stage: module.entry_points[i].stage,
+			entry_point: entry_point.to_owned(),
+		};
+
+		*ir = Some(spv::write_vec(module, &info, &options, Some(&pipeline_options)).map_err(|err| translation_error(err.to_string()))?);
+	}
+
+	Ok(ShaderPackage {
+		defines: Vec::new(),
+		permutations: vec![package],
+	})
+}
+
+pub fn compile_glsl(path: &Path, src: &str, stage: ShaderStage) -> Result<ShaderPackage, EditorError> {
+	let mut module = glsl::Parser::default()
+		.parse(&glsl::Options::from(stage), src)
+		.map_err(|errors| EditorError::ShaderTranslation(path.to_path_buf(), errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")))?;
+
+	write_spirv(path, &mut module)
+}
+
+pub fn compile_wgsl(path: &Path, src: &str) -> Result<ShaderPackage, EditorError> {
+	let mut module = wgsl::parse_str(src).map_err(|err| EditorError::ShaderTranslation(path.to_path_buf(), err.emit_to_string(src)))?;
+
+	write_spirv(path, &mut module)
+}
+
+// Picks the compiler from the extension, anything that isn't a shader we know how to build is skipped rather than failing the import.
+pub fn compile_shader(path: &Path, src: &str, resolver: &IncludeResolver) -> Result<Option<ShaderPackage>, EditorError> {
+	match path.extension().and_then(|extension| extension.to_str()) {
+		Some("hlsl") => compile_hlsl(path, src, resolver).map(Some),
+		Some("wgsl") => compile_wgsl(path, src).map(Some),
+		_ => match glsl_stage(path) {
+			Some(stage) => compile_glsl(path, src, stage).map(Some),
+			None => {
+				warn!("Skipping shader {}, can't tell which stage it is from the extension", path.to_str().unwrap());
+				Ok(None)
+			}
+		},
+	}
+}
 
-	let ps_ir = if src.contains(PS_MAIN) { Some(compile(PS_MAIN, "ps_6_0", &["-spirv"], &[])?) } else { None };
+// Runs every stage of every permutation through spirv-val from the Vulkan SDK, the error names the stage's entry point and the
+// permutation's defines. Without spirv-val on the PATH this only checks that each stage parses.
+pub fn validate_spirv(package: &ShaderPackage) -> Result<(), String> {
+	for (mask, permutation) in package.permutations.iter().enumerate() {
+		let stages = [
+			(VS_MAIN, &permutation.vs_ir),
+			(PS_MAIN, &permutation.ps_ir),
+			(GS_MAIN, &permutation.gs_ir),
+			(CS_MAIN, &permutation.cs_ir),
+		];
 
-	Ok(ShaderPackage { vs_ir, ps_ir })
+		for (entry_point, ir) in stages {
+			if let Some(ir) = ir {
+				validate_module(ir).map_err(|err| {
+					if package.defines.is_empty() {
+						format!("{}: {}", entry_point, err)
+					} else {
+						format!("{} ({}): {}", entry_point, permutation_defines(&package.defines, mask).join(" "), err)
+					}
+				})?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn validate_module(ir: &[u32]) -> Result<(), String> {
+	static MISSING_SPIRV_VAL: Once = Once::new();
+
+	let spawned = Command::new("spirv-val")
+		.args(["--target-env", "vulkan1.2", "-"])
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn();
+
+	match spawned {
+		Ok(mut child) => {
+			// spirv-val reads everything before it starts, so stdin is dropped right after writing to close it.
+			let write_result = child.stdin.take().unwrap().write_all(bytemuck::cast_slice(ir));
+			let output = child.wait_with_output().map_err(|err| err.to_string())?;
+			write_result.map_err(|err| err.to_string())?;
+
+			if output.status.success() {
+				Ok(())
+			} else {
+				Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+			}
+		}
+		Err(err) if err.kind() == ErrorKind::NotFound => {
+			MISSING_SPIRV_VAL.call_once(|| warn!("spirv-val wasn't found, shaders are only checked for whether they parse. It comes with the Vulkan SDK."));
+			rspirv::dr::load_words(ir).map(|_| ()).map_err(|err| format!("{:?}", err))
+		}
+		Err(err) => Err(format!("Failed to run spirv-val: {}", err)),
+	}
 }
@@ -0,0 +1,153 @@
+use super::EditorError;
+use goldfish::{package::TexturePackage, renderer::TextureFormat};
+use image::RgbaImage;
+use intel_tex_2::{bc7, RgbaSurface};
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// In the order the faces are laid out in the package, which is the order of the image's array layers.
+const CUBEMAP_FACE_SUFFIXES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+// The meta file's format decides what ends up in the package, BC7 formats get compressed here so the engine can upload the blocks as is.
+pub fn import_texture(data: &[u8], format: TextureFormat) -> Result<TexturePackage, EditorError> {
+	let image = image::load_from_memory(data).map_err(move |err| EditorError::TextureImport(err))?.to_rgba8();
+	let (width, height) = image.dimensions();
+
+	let data = match format {
+		TextureFormat::RGBA8UNorm | TextureFormat::SRGBA8 => image.into_raw(),
+		TextureFormat::BC7UNorm | TextureFormat::BC7SRGB => compress_bc7(width, height, &image.into_raw()),
+		_ => return Err(EditorError::UnsupportedTextureFormat(format)),
+	};
+
+	Ok(TexturePackage {
+		width,
+		height,
+		format,
+		faces: 1,
+		data,
+	})
+}
+
+// The sibling files of a name_px.png face, in package order. None when the path isn't the +X face, it's treated as an equirectangular
+// image instead.
+pub fn cubemap_face_paths(path: &Path) -> Option<[PathBuf; 6]> {
+	let name = path.file_stem()?.to_str()?.strip_suffix("_px")?;
+	let extension = path.extension().and_then(|extension| extension.to_str()).map_or(String::new(), |extension| format!(".{}", extension));
+
+	Some(CUBEMAP_FACE_SUFFIXES.map(|suffix| path.with_file_name(format!("{}_{}{}", name, suffix, extension))))
+}
+
+pub fn import_cubemap(path: &Path, format: TextureFormat) -> Result<TexturePackage, EditorError> {
+	if !matches!(format, TextureFormat::CubemapRGBA8UNorm | TextureFormat::CubemapSRGBA8) {
+		return Err(EditorError::UnsupportedTextureFormat(format));
+	}
+
+	let load = |path: &Path| -> Result<RgbaImage, EditorError> {
+		let data = fs::read(path).map_err(move |err| EditorError::Filesystem(err))?;
+		Ok(image::load_from_memory(&data).map_err(move |err| EditorError::TextureImport(err))?.to_rgba8())
+	};
+
+	let faces = match cubemap_face_paths(path) {
+		Some(face_paths) => face_paths.iter().map(|face_path| load(face_path)).collect::<Result<Vec<_>, _>>()?,
+		None => equirectangular_to_faces(&load(path)?),
+	};
+
+	let (size, _) = faces[0].dimensions();
+	for (face, suffix) in faces.iter().zip(CUBEMAP_FACE_SUFFIXES) {
+		if face.dimensions() != (size, size) {
+			return Err(EditorError::CubemapImport(format!(
+				"the {} face is {}x{}, every face has to be square and the same size as the +X face ({}x{})",
+				suffix,
+				face.width(),
+				face.height(),
+				size,
+				size
+			)));
+		}
+	}
+
+	Ok(TexturePackage {
+		width: size,
+		height: size,
+		format,
+		faces: 6,
+		data: faces.into_iter().flat_map(RgbaImage::into_raw).collect(),
+	})
+}
+
+// The direction through a point on a face, with s and t from -1 to 1 across it. The inverse of how Vulkan picks a face and texel for a
+// direction, see "Cube Map Face Selection" in the spec.
+fn cubemap_direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+	match face {
+		0 => [1.0, -t, -s],
+		1 => [-1.0, -t, s],
+		2 => [s, 1.0, t],
+		3 => [s, -1.0, -t],
+		4 => [s, -t, 1.0],
+		_ => [-s, -t, -1.0],
+	}
+}
+
+// A single bilinear tap per texel, which is fine as long as the faces aren't much smaller than a quarter of the image's
+// width. -Z ends up in the middle of the image and +Y along the top.
+fn equirectangular_to_faces(image: &RgbaImage) -> Vec<RgbaImage> {
+	let (width, height) = image.dimensions();
+	let size = (width / 4).max(1);
+
+	let texel = |x: i64, y: i64| image.get_pixel(x.rem_euclid(width as i64) as u32, y.clamp(0, height as i64 - 1) as u32).0;
+	let sample = |u: f32, v: f32| {
+		let x = u * width as f32 - 0.5;
+		let y = v * height as f32 - 0.5;
+		let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+		let (fx, fy) = (x - x.floor(), y - y.floor());
+
+		let mut texel_out = [0u8; 4];
+		for (channel, out) in texel_out.iter_mut().enumerate() {
+			let top = texel(x0, y0)[channel] as f32 * (1.0 - fx) + texel(x0 + 1, y0)[channel] as f32 * fx;
+			let bottom = texel(x0, y0 + 1)[channel] as f32 * (1.0 - fx) + texel(x0 + 1, y0 + 1)[channel] as f32 * fx;
+			*out = (top * (1.0 - fy) + bottom * fy).round() as u8;
+		}
+		texel_out
+	};
+
+	(0..CUBEMAP_FACE_SUFFIXES.len())
+		.map(|face| {
+			RgbaImage::from_fn(size, size, |x, y| {
+				let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+				let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+				let [dx, dy, dz] = cubemap_direction(face, s, t);
+				let length = (dx * dx + dy * dy + dz * dz).sqrt();
+
+				let u = 0.5 + dx.atan2(-dz) / (2.0 * PI);
+				let v = (dy / length).clamp(-1.0, 1.0).acos() / PI;
+				image::Rgba(sample(u, v))
+			})
+		})
+		.collect()
+}
+
+fn compress_bc7(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+	// The compressor only works on whole blocks, so pad out to a multiple of 4 by repeating the last row and column.
+	// The engine only ever samples the real width and height out of the padded blocks.
+	let padded_width = (width + 3) / 4 * 4;
+	let padded_height = (height + 3) / 4 * 4;
+
+	let mut padded = vec![0u8; (padded_width * padded_height * 4) as usize];
+	for y in 0..padded_height {
+		for x in 0..padded_width {
+			let src = ((y.min(height - 1) * width + x.min(width - 1)) * 4) as usize;
+			let dst = ((y * padded_width + x) * 4) as usize;
+			padded[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+		}
+	}
+
+	let surface = RgbaSurface {
+		data: &padded,
+		width: padded_width,
+		height: padded_height,
+		stride: padded_width * 4,
+	};
+
+	bc7::compress_blocks(&bc7::alpha_basic_settings(), &surface)
+}
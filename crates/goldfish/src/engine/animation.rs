@@ -0,0 +1,391 @@
+use crate::skinning::SkinPalette;
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Transform {
+	pub translation: Vec3,
+	pub rotation: Quat,
+	pub scale: Vec3,
+}
+
+impl Transform {
+	pub const IDENTITY: Self = Self {
+		translation: Vec3::ZERO,
+		rotation: Quat::IDENTITY,
+		scale: Vec3::ONE,
+	};
+
+	pub fn from_matrix(matrix: Mat4) -> Self {
+		let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+		Self { translation, rotation, scale }
+	}
+
+	pub fn to_matrix(&self) -> Mat4 {
+		Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+	}
+}
+
+impl Default for Transform {
+	fn default() -> Self {
+		Self::IDENTITY
+	}
+}
+
+// What happens once playback goes past either end of the clip.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaybackMode {
+	#[default]
+	Loop,
+	Clamp,
+}
+
+// Times are in seconds and always sorted, each value lines up with the time at the same index.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Keyframes<T> {
+	times: Vec<f32>,
+	values: Vec<T>,
+}
+
+impl<T> Default for Keyframes<T> {
+	fn default() -> Self {
+		Self {
+			times: Vec::new(),
+			values: Vec::new(),
+		}
+	}
+}
+
+impl<T: Copy> Keyframes<T> {
+	// Keyframes can come in any order, they get sorted here. If two land on the same time the last one wins.
+	pub fn new(mut keyframes: Vec<(f32, T)>) -> Self {
+		keyframes.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+		keyframes.dedup_by(|(next_time, next_value), (time, value)| {
+			if next_time == time {
+				*value = *next_value;
+				true
+			} else {
+				false
+			}
+		});
+
+		let (times, values) = keyframes.into_iter().unzip();
+		Self { times, values }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.times.is_empty()
+	}
+
+	pub fn times(&self) -> &[f32] {
+		&self.times
+	}
+
+	pub fn values(&self) -> &[T] {
+		&self.values
+	}
+
+	pub fn duration(&self) -> f32 {
+		self.times.last().copied().unwrap_or(0.0)
+	}
+
+	// Anything before the first or after the last keyframe gets that keyframe's value, None if there aren't any keyframes.
+	fn sample_with(&self, time: f32, interpolate: impl Fn(T, T, f32) -> T) -> Option<T> {
+		let last = self.times.len().checked_sub(1)?;
+
+		if time <= self.times[0] {
+			return Some(self.values[0]);
+		}
+
+		if time >= self.times[last] {
+			return Some(self.values[last]);
+		}
+
+		let next = self.times.partition_point(|&keyframe_time| keyframe_time <= time);
+		let previous = next - 1;
+
+		let t = (time - self.times[previous]) / (self.times[next] - self.times[previous]);
+		Some(interpolate(self.values[previous], self.values[next], t))
+	}
+}
+
+impl Keyframes<Vec3> {
+	pub fn sample(&self, time: f32) -> Option<Vec3> {
+		self.sample_with(time, |a, b, t| a.lerp(b, t))
+	}
+}
+
+impl Keyframes<Quat> {
+	pub fn sample(&self, time: f32) -> Option<Quat> {
+		self.sample_with(time, |a, b, t| a.slerp(b, t).normalize())
+	}
+}
+
+// Animates a single node, or a joint when driving a skin. Channels without any keyframes are left alone.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Track {
+	pub node: String,
+	pub translation: Keyframes<Vec3>,
+	pub rotation: Keyframes<Quat>,
+	pub scale: Keyframes<Vec3>,
+}
+
+impl Track {
+	pub fn duration(&self) -> f32 {
+		self.translation.duration().max(self.rotation.duration()).max(self.scale.duration())
+	}
+
+	pub fn sample(&self, time: f32) -> Transform {
+		self.sample_over(time, &Transform::IDENTITY)
+	}
+
+	pub fn sample_over(&self, time: f32, base: &Transform) -> Transform {
+		Transform {
+			translation: self.translation.sample(time).unwrap_or(base.translation),
+			rotation: self.rotation.sample(time).unwrap_or(base.rotation),
+			scale: self.scale.sample(time).unwrap_or(base.scale),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnimationClip {
+	pub name: String,
+	pub tracks: Vec<Track>,
+	pub duration: f32,
+}
+
+impl AnimationClip {
+	pub fn new(name: String, tracks: Vec<Track>) -> Self {
+		let duration = tracks.iter().map(Track::duration).fold(0.0, f32::max);
+		Self { name, tracks, duration }
+	}
+
+	pub fn track(&self, node: &str) -> Option<&Track> {
+		self.tracks.iter().find(|track| track.node == node)
+	}
+
+	// Maps a time that can be anywhere (including negative) onto the clip.
+	pub fn clip_time(&self, time: f32, mode: PlaybackMode) -> f32 {
+		if self.duration <= 0.0 {
+			return 0.0;
+		}
+
+		match mode {
+			PlaybackMode::Clamp => time.clamp(0.0, self.duration),
+			PlaybackMode::Loop => {
+				// Landing exactly on the end gives the last keyframe, so stepping by the exact duration ends on the final pose.
+				let wrapped = time.rem_euclid(self.duration);
+				if wrapped == 0.0 && time != 0.0 {
+					self.duration
+				} else {
+					wrapped
+				}
+			}
+		}
+	}
+
+	pub fn sample(&self, time: f32, mode: PlaybackMode) -> Vec<(&str, Transform)> {
+		let time = self.clip_time(time, mode);
+		self.tracks.iter().map(|track| (track.node.as_str(), track.sample(time))).collect()
+	}
+
+	// Builds a pose for SkinPalette::set_pose, joints without a track (and channels that aren't animated) stay at their rest transform.
+	pub fn sample_skin_pose(&self, time: f32, mode: PlaybackMode, palette: &SkinPalette) -> Vec<Mat4> {
+		let time = self.clip_time(time, mode);
+
+		palette
+			.joint_names()
+			.iter()
+			.zip(palette.rest_pose())
+			.map(|(name, &rest)| match self.track(name) {
+				Some(track) => track.sample_over(time, &Transform::from_matrix(rest)).to_matrix(),
+				None => rest,
+			})
+			.collect()
+	}
+}
+
+pub struct AnimationPlayer {
+	pub clip: AnimationClip,
+	pub time: f32,
+	// Negative plays the clip backwards.
+	pub speed: f32,
+	pub playing: bool,
+	pub mode: PlaybackMode,
+}
+
+impl AnimationPlayer {
+	pub fn new(clip: AnimationClip) -> Self {
+		Self {
+			clip,
+			time: 0.0,
+			speed: 1.0,
+			playing: true,
+			mode: PlaybackMode::Loop,
+		}
+	}
+
+	// Call once per frame with GoldfishEngine::delta_time. Clamped clips stop playing once they hit either end.
+	pub fn update(&mut self, dt: Duration) {
+		if !self.playing {
+			return;
+		}
+
+		let time = self.time + dt.as_secs_f32() * self.speed;
+		self.time = self.clip.clip_time(time, self.mode);
+
+		let finished = (self.speed > 0.0 && time >= self.clip.duration) || (self.speed < 0.0 && time <= 0.0);
+		if self.mode == PlaybackMode::Clamp && finished {
+			self.playing = false;
+		}
+	}
+
+	pub fn restart(&mut self) {
+		self.time = if self.speed < 0.0 { self.clip.duration } else { 0.0 };
+		self.playing = true;
+	}
+
+	pub fn sample(&self) -> Vec<(&str, Transform)> {
+		self.clip.sample(self.time, self.mode)
+	}
+
+	pub fn sample_skin_pose(&self, palette: &SkinPalette) -> Vec<Mat4> {
+		self.clip.sample_skin_pose(self.time, self.mode, palette)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_vec3_eq(a: Vec3, b: Vec3) {
+		assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+	}
+
+	fn assert_quat_eq(a: Quat, b: Quat) {
+		// q and -q are the same rotation.
+		assert!(a.abs_diff_eq(b, 1e-5) || a.abs_diff_eq(-b, 1e-5), "{} != {}", a, b);
+	}
+
+	fn translations() -> Keyframes<Vec3> {
+		Keyframes::new(vec![(2.0, Vec3::new(4.0, 0.0, 0.0)), (0.0, Vec3::ZERO), (1.0, Vec3::new(2.0, 2.0, 0.0))])
+	}
+
+	#[test]
+	fn clamps_outside_the_keyframes() {
+		let keyframes = translations();
+		assert_eq!(keyframes.times(), &[0.0, 1.0, 2.0]);
+
+		assert_vec3_eq(keyframes.sample(-1.0).unwrap(), Vec3::ZERO);
+		assert_vec3_eq(keyframes.sample(2.5).unwrap(), Vec3::new(4.0, 0.0, 0.0));
+		assert_vec3_eq(keyframes.sample(100.0).unwrap(), Vec3::new(4.0, 0.0, 0.0));
+
+		assert_eq!(Keyframes::<Vec3>::default().sample(1.0), None);
+
+		let single = Keyframes::new(vec![(0.5, Vec3::ONE)]);
+		assert_vec3_eq(single.sample(0.0).unwrap(), Vec3::ONE);
+		assert_vec3_eq(single.sample(0.5).unwrap(), Vec3::ONE);
+		assert_vec3_eq(single.sample(1.0).unwrap(), Vec3::ONE);
+	}
+
+	#[test]
+	fn exact_keyframe_hits_and_lerp_between() {
+		let keyframes = translations();
+		assert_vec3_eq(keyframes.sample(0.0).unwrap(), Vec3::ZERO);
+		assert_vec3_eq(keyframes.sample(1.0).unwrap(), Vec3::new(2.0, 2.0, 0.0));
+		assert_vec3_eq(keyframes.sample(2.0).unwrap(), Vec3::new(4.0, 0.0, 0.0));
+
+		assert_vec3_eq(keyframes.sample(0.5).unwrap(), Vec3::new(1.0, 1.0, 0.0));
+		assert_vec3_eq(keyframes.sample(1.25).unwrap(), Vec3::new(2.5, 1.5, 0.0));
+	}
+
+	#[test]
+	fn duplicate_times_keep_the_last_value() {
+		let keyframes = Keyframes::new(vec![(0.0, Vec3::ZERO), (1.0, Vec3::X), (1.0, Vec3::Y)]);
+		assert_eq!(keyframes.times(), &[0.0, 1.0]);
+		assert_vec3_eq(keyframes.sample(1.0).unwrap(), Vec3::Y);
+	}
+
+	#[test]
+	fn rotations_slerp() {
+		let quarter = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+		let keyframes = Keyframes::new(vec![(0.0, Quat::IDENTITY), (1.0, quarter)]);
+
+		assert_quat_eq(keyframes.sample(0.0).unwrap(), Quat::IDENTITY);
+		assert_quat_eq(keyframes.sample(1.0).unwrap(), quarter);
+
+		// Constant angular speed, a lerp would be off in the middle.
+		let halfway = keyframes.sample(0.5).unwrap();
+		assert_quat_eq(halfway, Quat::from_rotation_y(std::f32::consts::FRAC_PI_4));
+		assert!((halfway.length() - 1.0).abs() < 1e-5);
+
+		let third = keyframes.sample(1.0 / 3.0).unwrap();
+		assert_quat_eq(third, Quat::from_rotation_y(std::f32::consts::FRAC_PI_6));
+	}
+
+	#[test]
+	fn looping_wraps_and_clamping_holds() {
+		let clip = AnimationClip::new(
+			"walk".to_string(),
+			vec![Track {
+				node: "root".to_string(),
+				translation: translations(),
+				rotation: Keyframes::default(),
+				scale: Keyframes::default(),
+			}],
+		);
+		assert_eq!(clip.duration, 2.0);
+
+		assert_eq!(clip.clip_time(0.0, PlaybackMode::Loop), 0.0);
+		assert_eq!(clip.clip_time(0.5, PlaybackMode::Loop), 0.5);
+		assert_eq!(clip.clip_time(2.5, PlaybackMode::Loop), 0.5);
+		assert_eq!(clip.clip_time(-0.5, PlaybackMode::Loop), 1.5);
+		// Exactly on the end (or a whole number of loops past it) stays on the last keyframe.
+		assert_eq!(clip.clip_time(2.0, PlaybackMode::Loop), 2.0);
+		assert_eq!(clip.clip_time(4.0, PlaybackMode::Loop), 2.0);
+
+		assert_eq!(clip.clip_time(-0.5, PlaybackMode::Clamp), 0.0);
+		assert_eq!(clip.clip_time(2.5, PlaybackMode::Clamp), 2.0);
+
+		let pose = clip.sample(2.5, PlaybackMode::Loop);
+		assert_eq!(pose[0].0, "root");
+		assert_vec3_eq(pose[0].1.translation, Vec3::new(1.0, 1.0, 0.0));
+		assert_quat_eq(pose[0].1.rotation, Quat::IDENTITY);
+		assert_vec3_eq(pose[0].1.scale, Vec3::ONE);
+
+		let pose = clip.sample(2.0, PlaybackMode::Loop);
+		assert_vec3_eq(pose[0].1.translation, Vec3::new(4.0, 0.0, 0.0));
+	}
+
+	#[test]
+	fn player_stops_at_the_end_when_clamped() {
+		let clip = AnimationClip::new(
+			"wave".to_string(),
+			vec![Track {
+				node: "hand".to_string(),
+				translation: translations(),
+				rotation: Keyframes::default(),
+				scale: Keyframes::default(),
+			}],
+		);
+
+		let mut player = AnimationPlayer::new(clip);
+		player.update(Duration::from_secs_f32(2.5));
+		assert!(player.playing);
+		assert!((player.time - 0.5).abs() < 1e-5);
+
+		player.mode = PlaybackMode::Clamp;
+		player.update(Duration::from_secs(2));
+		assert!(!player.playing);
+		assert_eq!(player.time, 2.0);
+
+		player.speed = -1.0;
+		player.restart();
+		assert_eq!(player.time, 2.0);
+		player.update(Duration::from_secs(3));
+		assert!(!player.playing);
+		assert_eq!(player.time, 0.0);
+	}
+}
@@ -0,0 +1,108 @@
+use crate::config::GoldfishConfig;
+use crate::package::{AssetType, MeshPackage, Package, ReadAssetFn, ShaderPackage, ShaderPermutationPackage};
+use crate::renderer::{compile_wgsl, Vertex, PS_MAIN, VS_MAIN};
+use crate::{GoldfishEngine, GoldfishError, GoldfishResult};
+use glam::{Vec2, Vec3};
+use naga::ShaderStage;
+use std::time::Duration;
+use uuid::{uuid, Uuid};
+
+// Assets that are built into the engine, so a small program like the examples can draw something without an asset directory. run_app
+// always serves these, ahead of whatever package reader it was given.
+
+// A triangle with a red, green and blue corner, already in clip space.
+pub const TRIANGLE_MESH: Uuid = uuid!("5f0c3a8e-2d4b-4c1e-9a57-6b1e0f3d8c21");
+// Draws the vertex colors as they are with the positions taken as clip space. It has no descriptors and takes ExtendedVertex::VERTEX_INFO.
+pub const UNLIT_SHADER: Uuid = uuid!("a3e1d6b2-7c4f-4e8a-b0d9-2f5c8e1a7b34");
+
+const UNLIT_SHADER_WGSL: &str = r#"
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(5) color: vec4<f32>) -> VertexOutput {
+	var output: VertexOutput;
+	output.position = vec4<f32>(position, 1.0);
+	output.color = color;
+	return output;
+}
+
+@fragment
+fn ps_main(input: VertexOutput) -> @location(0) vec4<f32> {
+	return input.color;
+}
+"#;
+
+// A ReadAssetFn that only knows about the built in assets.
+pub fn read_builtin_asset(uuid: Uuid, asset_type: AssetType) -> GoldfishResult<Package> {
+	match (uuid, asset_type) {
+		(TRIANGLE_MESH, AssetType::Mesh) => Ok(Package::Mesh(triangle_mesh())),
+		(UNLIT_SHADER, AssetType::Shader) => Ok(Package::Shader(ShaderPackage {
+			defines: Vec::new(),
+			permutations: vec![ShaderPermutationPackage {
+				vs_ir: Some(compile_wgsl(UNLIT_SHADER_WGSL, ShaderStage::Vertex, VS_MAIN)?),
+				ps_ir: Some(compile_wgsl(UNLIT_SHADER_WGSL, ShaderStage::Fragment, PS_MAIN)?),
+				..Default::default()
+			}],
+		})),
+		_ => Err(GoldfishError::AssetNotFound(format!("{} isn't a built in {:?} asset", uuid, asset_type))),
+	}
+}
+
+fn triangle_mesh() -> MeshPackage {
+	// Clip space y points down in Vulkan, so the first corner is the top one.
+	let corners = [
+		(Vec2::new(0.0, -0.5), [255, 0, 0, 255]),
+		(Vec2::new(0.5, 0.5), [0, 255, 0, 255]),
+		(Vec2::new(-0.5, 0.5), [0, 0, 255, 255]),
+	];
+
+	MeshPackage {
+		vertices: corners
+			.iter()
+			.map(|&(position, _)| Vertex {
+				position: position.extend(0.0),
+				normal: Vec3::NEG_Z,
+				uv: position + 0.5,
+				tangent: Vec3::X,
+				bitangent: Vec3::Y,
+			})
+			.collect(),
+		indices: vec![0, 1, 2],
+		skin: None,
+		colors: Some(corners.iter().map(|&(_, color)| color).collect()),
+		uv1: None,
+	}
+}
+
+// Everything run_app needs to get going.
+pub struct AppConfig {
+	pub title: &'static str,
+	// Loaded from disk like GoldfishEngine::new does when None.
+	pub engine: Option<GoldfishConfig>,
+	// Reads anything that isn't built in, without one only the built in assets can be read.
+	pub package_reader: Option<ReadAssetFn>,
+}
+
+impl GoldfishEngine {
+	// For programs that don't go through the editor and a game library. init creates the app's state once the engine is up and update is
+	// called with it every frame, like the closure given to run. The engine and the state are handed back once the engine exits, so anything
+	// in the state that has to be destroyed through the graphics device can be before the engine is dropped.
+	pub fn run_app<S>(config: AppConfig, init: impl FnOnce(&mut GoldfishEngine) -> S, mut update: impl FnMut(&mut GoldfishEngine, &mut S, Duration)) -> (GoldfishEngine, S) {
+		let package_reader: ReadAssetFn = match config.package_reader {
+			Some(package_reader) => Box::new(move |uuid, asset_type| match read_builtin_asset(uuid, asset_type) {
+				Err(GoldfishError::AssetNotFound(_)) => package_reader(uuid, asset_type),
+				result => result,
+			}),
+			None => Box::new(read_builtin_asset),
+		};
+
+		let mut engine = GoldfishEngine::new(config.title, package_reader, config.engine);
+		let mut state = init(&mut engine);
+		engine.run(|engine, dt| update(engine, &mut state, dt));
+
+		(engine, state)
+	}
+}
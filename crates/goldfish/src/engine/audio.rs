@@ -0,0 +1,267 @@
+use super::package::AudioPackage;
+use super::{GoldfishError, GoldfishResult};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Sample, SampleFormat, Stream, StreamConfig};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::warn;
+use std::sync::Arc;
+
+// Anything past this gets dropped, the mixer never allocates so the voices have to fit in what's reserved up front.
+const MAX_VOICES: usize = 64;
+// Commands sent faster than the audio thread picks them up get dropped once this fills up.
+const COMMAND_QUEUE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AudioClipHandle(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId(u64);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayParams {
+	pub volume: f32,
+	pub looping: bool,
+	// Playback rate, 2.0 is an octave up and twice as fast.
+	pub pitch: f32,
+}
+
+impl Default for PlayParams {
+	fn default() -> Self {
+		Self {
+			volume: 1.0,
+			looping: false,
+			pitch: 1.0,
+		}
+	}
+}
+
+struct AudioClip {
+	sample_rate: u32,
+	channels: u16,
+	// Interleaved.
+	samples: Vec<i16>,
+}
+
+impl AudioClip {
+	fn frame_count(&self) -> usize {
+		self.samples.len() / self.channels as usize
+	}
+
+	fn sample(&self, frame: usize, channel: usize) -> f32 {
+		self.samples[frame * self.channels as usize + channel] as f32 / i16::MAX as f32
+	}
+}
+
+enum AudioCommand {
+	Play { voice: VoiceId, clip: Arc<AudioClip>, params: PlayParams },
+	Stop(VoiceId),
+	SetVolume(VoiceId, f32),
+}
+
+struct Voice {
+	id: VoiceId,
+	clip: Arc<AudioClip>,
+	// In frames of the clip, fractional since the clip gets resampled to the output rate.
+	position: f64,
+	volume: f32,
+	looping: bool,
+	pitch: f32,
+}
+
+// Lives on the audio thread, everything it needs comes in through the command queue.
+struct Mixer {
+	commands: Receiver<AudioCommand>,
+	voices: Vec<Voice>,
+	sample_rate: u32,
+	channels: usize,
+}
+
+impl Mixer {
+	fn process_commands(&mut self) {
+		for command in self.commands.try_iter() {
+			match command {
+				AudioCommand::Play { voice, clip, params } => {
+					if self.voices.len() < MAX_VOICES && clip.frame_count() > 0 {
+						self.voices.push(Voice {
+							id: voice,
+							clip,
+							position: 0.0,
+							volume: params.volume,
+							looping: params.looping,
+							pitch: params.pitch,
+						});
+					}
+				}
+				AudioCommand::Stop(id) => self.voices.retain(|voice| voice.id != id),
+				AudioCommand::SetVolume(id, volume) => {
+					if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+						voice.volume = volume;
+					}
+				}
+			}
+		}
+	}
+
+	fn mix<T: Sample>(&mut self, output: &mut [T]) {
+		self.process_commands();
+
+		for frame in output.chunks_mut(self.channels) {
+			for (channel, sample) in frame.iter_mut().enumerate() {
+				let mixed = self.voices.iter().map(|voice| voice.sample(channel) * voice.volume).sum::<f32>();
+				*sample = T::from(&mixed.clamp(-1.0, 1.0));
+			}
+
+			let sample_rate = self.sample_rate;
+			self.voices.retain_mut(|voice| voice.advance(sample_rate));
+		}
+	}
+}
+
+impl Voice {
+	// Clips get linearly resampled to the output rate on the fly. Outputs with more channels than the clip reuse the clip's
+	// last channel, so mono clips play on every speaker.
+	fn sample(&self, channel: usize) -> f32 {
+		let frame_count = self.clip.frame_count();
+		let channel = channel.min(self.clip.channels as usize - 1);
+
+		let frame = self.position as usize;
+		let next_frame = if frame + 1 < frame_count {
+			frame + 1
+		} else if self.looping {
+			0
+		} else {
+			frame
+		};
+
+		let t = self.position.fract() as f32;
+		let current = self.clip.sample(frame, channel);
+		current + (self.clip.sample(next_frame, channel) - current) * t
+	}
+
+	// Returns false once a voice that isn't looping has played all of its clip.
+	fn advance(&mut self, output_sample_rate: u32) -> bool {
+		let frame_count = self.clip.frame_count() as f64;
+		self.position += self.clip.sample_rate as f64 / output_sample_rate as f64 * self.pitch.max(0.0) as f64;
+
+		if self.position < frame_count {
+			true
+		} else if self.looping {
+			self.position %= frame_count;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+// Plays clips on the default output device. Mixing happens on the device's audio thread, the game thread only ever talks to it
+// through a queue so it never waits on the audio thread (or the other way around).
+pub struct AudioEngine {
+	// None when there's no output device, everything still works but nothing gets heard.
+	stream: Option<Stream>,
+	commands: Sender<AudioCommand>,
+	// Voices only hold onto clips, this keeps them alive so the audio thread is never the one that ends up freeing them.
+	clips: Vec<Arc<AudioClip>>,
+	next_voice: u64,
+}
+
+impl AudioEngine {
+	pub fn new() -> Self {
+		let (commands, command_receiver) = crossbeam_channel::bounded(COMMAND_QUEUE_SIZE);
+
+		let stream = match Self::create_stream(command_receiver) {
+			Ok(stream) => Some(stream),
+			Err(err) => {
+				warn!("Failed to open an audio output stream, audio is disabled! {}", err);
+				None
+			}
+		};
+
+		Self {
+			stream,
+			commands,
+			clips: Vec::new(),
+			next_voice: 0,
+		}
+	}
+
+	fn create_stream(commands: Receiver<AudioCommand>) -> GoldfishResult<Stream> {
+		let device = cpal::default_host().default_output_device().ok_or_else(|| GoldfishError::Audio("No output device".to_string()))?;
+		let supported_config = device.default_output_config().map_err(|err| GoldfishError::Audio(err.to_string()))?;
+
+		let sample_format = supported_config.sample_format();
+		let config: StreamConfig = supported_config.into();
+
+		let mixer = Mixer {
+			commands,
+			voices: Vec::with_capacity(MAX_VOICES),
+			sample_rate: config.sample_rate.0,
+			channels: config.channels as usize,
+		};
+
+		let stream = match sample_format {
+			SampleFormat::F32 => Self::build_stream::<f32>(&device, &config, mixer),
+			SampleFormat::I16 => Self::build_stream::<i16>(&device, &config, mixer),
+			SampleFormat::U16 => Self::build_stream::<u16>(&device, &config, mixer),
+		}?;
+
+		stream.play().map_err(|err| GoldfishError::Audio(err.to_string()))?;
+
+		Ok(stream)
+	}
+
+	fn build_stream<T: Sample>(device: &cpal::Device, config: &StreamConfig, mut mixer: Mixer) -> GoldfishResult<Stream> {
+		device
+			.build_output_stream(config, move |output: &mut [T], _| mixer.mix(output), |err| warn!("Audio stream error! {}", err))
+			.map_err(|err| GoldfishError::Audio(err.to_string()))
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.stream.is_some()
+	}
+
+	// Clips stay loaded for as long as the audio engine is around.
+	pub fn create_clip(&mut self, package: &AudioPackage) -> AudioClipHandle {
+		assert!(package.channels > 0, "Audio clips need at least one channel!");
+		assert_eq!(package.samples.len() % package.channels as usize, 0, "Audio clip samples don't line up with its channels!");
+
+		self.clips.push(Arc::new(AudioClip {
+			sample_rate: package.sample_rate,
+			channels: package.channels,
+			samples: package.samples.clone(),
+		}));
+
+		AudioClipHandle(self.clips.len() as u32 - 1)
+	}
+
+	pub fn play(&mut self, clip: AudioClipHandle, params: PlayParams) -> VoiceId {
+		let voice = VoiceId(self.next_voice);
+		self.next_voice += 1;
+
+		let clip = self.clips[clip.0 as usize].clone();
+		self.send(AudioCommand::Play { voice, clip, params });
+
+		voice
+	}
+
+	// Does nothing if the voice already finished.
+	pub fn stop(&mut self, voice: VoiceId) {
+		self.send(AudioCommand::Stop(voice));
+	}
+
+	pub fn set_volume(&mut self, voice: VoiceId, volume: f32) {
+		self.send(AudioCommand::SetVolume(voice, volume));
+	}
+
+	fn send(&self, command: AudioCommand) {
+		if self.stream.is_none() {
+			return;
+		}
+
+		match self.commands.try_send(command) {
+			Ok(_) => (),
+			Err(TrySendError::Full(_)) => warn!("Audio command queue is full, dropping command!"),
+			Err(TrySendError::Disconnected(_)) => warn!("Audio thread is gone, dropping command!"),
+		}
+	}
+}
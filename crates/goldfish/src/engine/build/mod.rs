@@ -1,7 +1,26 @@
 use crate::renderer::DescriptorSetInfo;
 pub trait CBuffer<const S: usize> {
+	// Generated along with the struct, see MemberInfo.
+	const MEMBERS: &'static [MemberInfo] = &[];
+
 	fn size() -> usize;
 	fn as_buffer(&self) -> [u8; S];
+
+	fn members() -> &'static [MemberInfo] {
+		Self::MEMBERS
+	}
+}
+
+// A shader's push constant block, laid out the same way as a CBuffer. The generated module's PUSH_CONSTANT_BYTES is S.
+pub trait PushConstant<const S: usize> {
+	const MEMBERS: &'static [MemberInfo] = &[];
+
+	fn size() -> usize;
+	fn as_buffer(&self) -> [u8; S];
+
+	fn members() -> &'static [MemberInfo] {
+		Self::MEMBERS
+	}
 }
 
 pub trait StructuredBuffer<const S: usize>: Sized {
@@ -10,5 +29,53 @@ pub trait StructuredBuffer<const S: usize>: Sized {
 }
 
 pub trait Descriptor {
-	fn info() -> DescriptorSetInfo;
+	fn info() -> &'static DescriptorSetInfo;
+}
+
+// Describes one member of a struct generated from a shader, so things like an editor's property grid can walk it without knowing the
+// type. The offset is where the member lives in the GPU buffer, which isn't where it is in the Rust struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemberInfo {
+	pub name: &'static str,
+	pub offset: u32,
+	pub ty: MemberTypeRepr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberTypeRepr {
+	F32,
+	Vec2,
+	Vec3,
+	Vec4,
+	Mat3,
+	Mat4,
+	U32,
+	UVec2,
+	UVec3,
+	UVec4,
+	F16,
+	F16Vec2,
+	F16Vec3,
+	F16Vec4,
+	// Exposed as a u32 on the Rust side.
+	Bool32,
+	Struct { name: &'static str, size: u32, members: &'static [MemberInfo] },
+	Array { ty: &'static MemberTypeRepr, count: u32, stride: u32 },
+}
+
+// Serde only implements arrays of up to 32 elements, the generated structs go through this for anything longer.
+pub mod serde_array {
+	use serde::de::Error;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	pub fn serialize<S: Serializer, T: Serialize, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error> {
+		array.as_slice().serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(deserializer: D) -> Result<[T; N], D::Error> {
+		let elements = Vec::<T>::deserialize(deserializer)?;
+		let len = elements.len();
+
+		elements.try_into().map_err(|_| D::Error::invalid_length(len, &format!("an array of {} elements", N).as_str()))
+	}
 }
@@ -0,0 +1,120 @@
+use glam::Mat4;
+
+// Everything here is left handed and reverse Z, the near plane maps to a depth of 1.0 and the far plane to 0.0,
+// which is what the DepthCompareOp::Greater pipelines expect. The backend flips the viewport so +y in clip space is the top of the screen.
+pub trait Camera {
+	fn projection(&self) -> Mat4;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PerspectiveCamera {
+	pub fov_y: f32,
+	pub aspect: f32,
+	// There's no far plane, depth approaches 0.0 at infinity.
+	pub z_near: f32,
+}
+
+impl Camera for PerspectiveCamera {
+	fn projection(&self) -> Mat4 {
+		Mat4::perspective_infinite_reverse_lh(self.fov_y, self.aspect, self.z_near)
+	}
+}
+
+// Mostly for directional shadow maps, the volume is in view space.
+#[derive(Debug, Clone, Copy)]
+pub struct OrthographicCamera {
+	pub left: f32,
+	pub right: f32,
+	pub bottom: f32,
+	pub top: f32,
+	pub near: f32,
+	pub far: f32,
+}
+
+impl Camera for OrthographicCamera {
+	fn projection(&self) -> Mat4 {
+		// Swapping near and far is all it takes to reverse the depth range of a regular orthographic projection.
+		Mat4::orthographic_lh(self.left, self.right, self.bottom, self.top, self.far, self.near)
+	}
+}
+
+// Maps pixel coordinates with the origin at the top left of the screen to clip space, z in [0.0, 1.0] ends up at depth [1.0, 0.0].
+pub fn pixel_space_projection(width: u32, height: u32) -> Mat4 {
+	OrthographicCamera {
+		left: 0.0,
+		right: width as f32,
+		bottom: height as f32,
+		top: 0.0,
+		near: 0.0,
+		far: 1.0,
+	}
+	.projection()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::{Vec3, Vec4};
+
+	const EPSILON: f32 = 1e-5;
+
+	fn to_clip(projection: Mat4, point: Vec3) -> Vec3 {
+		let clip = projection * Vec4::new(point.x, point.y, point.z, 1.0);
+		clip.truncate() / clip.w
+	}
+
+	fn assert_close(actual: Vec3, expected: Vec3) {
+		assert!(actual.abs_diff_eq(expected, EPSILON), "Expected {}, got {}", expected, actual);
+	}
+
+	#[test]
+	fn orthographic_corners_map_to_clip_corners() {
+		let camera = OrthographicCamera {
+			left: -4.0,
+			right: 6.0,
+			bottom: -2.0,
+			top: 3.0,
+			near: 0.5,
+			far: 20.0,
+		};
+		let projection = camera.projection();
+
+		assert_close(to_clip(projection, Vec3::new(-4.0, -2.0, 0.5)), Vec3::new(-1.0, -1.0, 1.0));
+		assert_close(to_clip(projection, Vec3::new(6.0, 3.0, 0.5)), Vec3::new(1.0, 1.0, 1.0));
+		assert_close(to_clip(projection, Vec3::new(-4.0, 3.0, 20.0)), Vec3::new(-1.0, 1.0, 0.0));
+		assert_close(to_clip(projection, Vec3::new(6.0, -2.0, 20.0)), Vec3::new(1.0, -1.0, 0.0));
+	}
+
+	#[test]
+	fn nearer_points_pass_the_greater_depth_test() {
+		let orthographic = OrthographicCamera {
+			left: -1.0,
+			right: 1.0,
+			bottom: -1.0,
+			top: 1.0,
+			near: 0.1,
+			far: 100.0,
+		};
+		let perspective = PerspectiveCamera {
+			fov_y: std::f32::consts::FRAC_PI_2,
+			aspect: 16.0 / 9.0,
+			z_near: 0.1,
+		};
+
+		for projection in [orthographic.projection(), perspective.projection()] {
+			let near = to_clip(projection, Vec3::new(0.0, 0.0, 1.0)).z;
+			let far = to_clip(projection, Vec3::new(0.0, 0.0, 50.0)).z;
+			assert!(near > far, "{} should be in front of {}", near, far);
+			assert!((0.0..=1.0).contains(&near) && (0.0..=1.0).contains(&far));
+		}
+	}
+
+	#[test]
+	fn pixel_space_origin_is_the_top_left() {
+		let projection = pixel_space_projection(1280, 720);
+
+		assert_close(to_clip(projection, Vec3::new(0.0, 0.0, 0.0)), Vec3::new(-1.0, 1.0, 1.0));
+		assert_close(to_clip(projection, Vec3::new(1280.0, 720.0, 1.0)), Vec3::new(1.0, -1.0, 0.0));
+		assert_close(to_clip(projection, Vec3::new(640.0, 360.0, 0.5)), Vec3::new(0.0, 0.0, 0.5));
+	}
+}
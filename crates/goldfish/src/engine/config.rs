@@ -0,0 +1,251 @@
+use crate::input::InputMap;
+use crate::logging::LogConfig;
+use crate::renderer::{DeviceSelection, OnValidationError, PresentMode, SurfacePreference, ValidationDesc, DEFAULT_FRAMES_IN_FLIGHT};
+use crate::window::{BackgroundBehavior, WindowDesc};
+use crate::{GoldfishError, GoldfishResult};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Overrides where the config is loaded from, the extension picks the format.
+pub const CONFIG_PATH_VAR: &str = "GOLDFISH_CONFIG";
+pub const CONFIG_FILE_NAMES: [&str; 2] = ["goldfish.toml", "goldfish.json"];
+
+// Overrides the gpu in the config, either an adapter index or auto, integrated or discrete.
+pub const GPU_VAR: &str = "GOLDFISH_GPU";
+
+// Startup parameters for the engine, anything missing from the file keeps its default so partial configs are fine.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GoldfishConfig {
+	// TOML can't have plain values after a table, so the tables need to stay at the bottom of the struct.
+	pub present_mode: PresentMode,
+	pub surface_preference: SurfacePreference,
+	// Clamped to whatever the device can actually do.
+	pub frames_in_flight: usize,
+	pub validation: bool,
+	// Captures the next frame with RenderDoc the first time validation reports an error, does nothing when not running under RenderDoc.
+	pub capture_on_validation_error: bool,
+	// Set to Panic in CI so any validation error fails the run.
+	pub on_validation_error: OnValidationError,
+	// The message_id_numbers of validation messages that are never logged.
+	pub suppressed_validation_messages: Vec<i32>,
+	// Tracks every resource so frames can be captured with GraphicsContext::capture_next_frame. Off by default, it costs memory.
+	pub frame_capture: bool,
+	// Leak reports also say where the resource was created, at the cost of a backtrace per resource.
+	pub resource_backtraces: bool,
+	// Only applies when the present mode isn't vsynced.
+	pub target_fps: Option<u32>,
+	// In milliseconds, the scene passes render at a lower resolution while the GPU takes longer than this.
+	pub gpu_frame_budget_ms: Option<f32>,
+	pub asset_dir: PathBuf,
+	// Relative to the asset directory, HLSL includes that aren't next to the file including them are looked for in each of these in order.
+	pub shader_include_dirs: Vec<PathBuf>,
+	// Use device_selection to also pick up GPU_VAR.
+	pub gpu: DeviceSelection,
+	// While the window is unfocused or can't be seen.
+	pub background: BackgroundBehavior,
+	pub log: LogConfig,
+	pub window: WindowDesc,
+	pub input: InputMap,
+	// Whatever the game wants, the engine never looks at it.
+	pub game: toml::Value,
+}
+
+impl Default for GoldfishConfig {
+	fn default() -> Self {
+		Self {
+			present_mode: Default::default(),
+			surface_preference: Default::default(),
+			frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+			validation: true,
+			capture_on_validation_error: false,
+			on_validation_error: Default::default(),
+			// The shader not consuming a vertex input, plenty of our shaders ignore some of the vertex.
+			suppressed_validation_messages: vec![101294395],
+			frame_capture: false,
+			resource_backtraces: false,
+			target_fps: None,
+			gpu_frame_budget_ms: None,
+			asset_dir: PathBuf::from("assets/"),
+			shader_include_dirs: vec![PathBuf::from("shaders/"), PathBuf::from("shaders/lib/")],
+			gpu: Default::default(),
+			background: Default::default(),
+			log: Default::default(),
+			window: Default::default(),
+			input: Default::default(),
+			game: toml::Value::Table(Default::default()),
+		}
+	}
+}
+
+impl GoldfishConfig {
+	// Falls back to the defaults if there's no config file, or if the one that's there is broken.
+	pub fn load_or_default() -> Self {
+		match Self::load() {
+			Ok(Some(config)) => config,
+			Ok(None) => Self::default(),
+			Err(err) => {
+				// Logging is set up from the config, so this happens before there's a logger and has to go straight to the console.
+				println!("Failed to load config, using the defaults instead: {}", err);
+				Self::default()
+			}
+		}
+	}
+
+	pub fn load() -> GoldfishResult<Option<Self>> {
+		match Self::find() {
+			Some(path) => Self::from_path(&path).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	// CONFIG_PATH_VAR wins if it's set, otherwise it's looked for next to the executable and then in the working directory (which is where cargo run leaves us).
+	pub fn find() -> Option<PathBuf> {
+		if let Some(path) = std::env::var_os(CONFIG_PATH_VAR) {
+			return Some(PathBuf::from(path));
+		}
+
+		let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+		let working_dir = std::env::current_dir().ok();
+
+		exe_dir
+			.into_iter()
+			.chain(working_dir)
+			.flat_map(|dir| CONFIG_FILE_NAMES.map(|name| dir.join(name)))
+			.find(|path| path.is_file())
+	}
+
+	pub fn device_selection(&self) -> DeviceSelection {
+		match std::env::var(GPU_VAR) {
+			Ok(selection) => selection.parse().unwrap_or_else(|err| {
+				warn!("Ignoring {}: {}", GPU_VAR, err);
+				self.gpu
+			}),
+			Err(_) => self.gpu,
+		}
+	}
+
+	pub fn validation_desc(&self) -> Option<ValidationDesc> {
+		self.validation.then(|| ValidationDesc {
+			on_error: self.on_validation_error,
+			suppressed_messages: self.suppressed_validation_messages.clone(),
+		})
+	}
+
+	pub fn gpu_frame_budget(&self) -> Option<Duration> {
+		self.gpu_frame_budget_ms.map(|ms| Duration::from_secs_f32(ms / 1000.0))
+	}
+
+	pub fn from_path(path: &Path) -> GoldfishResult<Self> {
+		let contents = std::fs::read_to_string(path).map_err(GoldfishError::Filesystem)?;
+
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some("json") => Self::from_json(&contents),
+			_ => Self::from_toml(&contents),
+		}
+	}
+
+	pub fn from_toml(contents: &str) -> GoldfishResult<Self> {
+		toml::from_str(contents).map_err(|err| GoldfishError::Config(err.to_string()))
+	}
+
+	pub fn from_json(contents: &str) -> GoldfishResult<Self> {
+		serde_json::from_str(contents).map_err(|err| GoldfishError::Config(err.to_string()))
+	}
+
+	pub fn to_toml(&self) -> GoldfishResult<String> {
+		// Going through a Value first sorts the tables in the input map after the plain values.
+		let value = toml::Value::try_from(self).map_err(|err| GoldfishError::Config(err.to_string()))?;
+		toml::to_string_pretty(&value).map_err(|err| GoldfishError::Config(err.to_string()))
+	}
+
+	pub fn to_json(&self) -> GoldfishResult<String> {
+		serde_json::to_string_pretty(self).map_err(|err| GoldfishError::Config(err.to_string()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tweaked() -> GoldfishConfig {
+		let mut config = GoldfishConfig::default();
+		config.present_mode = PresentMode::Mailbox;
+		config.frames_in_flight = 3;
+		config.validation = false;
+		config.target_fps = Some(144);
+		config.asset_dir = PathBuf::from("other_assets/");
+		config.gpu = DeviceSelection::Index(1);
+		config.background = BackgroundBehavior::ThrottleTo(10);
+		config.window.resizable = false;
+		config.log.filter = "debug".to_string();
+		config
+	}
+
+	fn assert_tweaked(config: &GoldfishConfig) {
+		assert_eq!(config.present_mode, PresentMode::Mailbox);
+		assert_eq!(config.frames_in_flight, 3);
+		assert!(!config.validation);
+		assert_eq!(config.target_fps, Some(144));
+		assert_eq!(config.gpu_frame_budget_ms, None);
+		assert_eq!(config.asset_dir, PathBuf::from("other_assets/"));
+		assert_eq!(config.gpu, DeviceSelection::Index(1));
+		assert_eq!(config.background, BackgroundBehavior::ThrottleTo(10));
+		assert!(!config.window.resizable);
+		assert_eq!(config.log.filter, "debug");
+	}
+
+	#[test]
+	fn toml_round_trip() {
+		let toml = tweaked().to_toml().unwrap();
+		let config = GoldfishConfig::from_toml(&toml).unwrap();
+		assert_tweaked(&config);
+		assert_eq!(config.to_toml().unwrap(), toml);
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let json = tweaked().to_json().unwrap();
+		let config = GoldfishConfig::from_json(&json).unwrap();
+		assert_tweaked(&config);
+		assert_eq!(config.to_json().unwrap(), json);
+	}
+
+	#[test]
+	fn partial_files_keep_the_defaults() {
+		let defaults = GoldfishConfig::default();
+
+		let config = GoldfishConfig::from_toml("frames_in_flight = 3\n\n[window]\nmaximized = true\n").unwrap();
+		assert_eq!(config.frames_in_flight, 3);
+		assert!(config.window.maximized);
+		assert_eq!(config.window.resizable, defaults.window.resizable);
+		assert_eq!(config.validation, defaults.validation);
+		assert_eq!(config.asset_dir, defaults.asset_dir);
+		assert_eq!(config.shader_include_dirs, defaults.shader_include_dirs);
+		assert_eq!(config.suppressed_validation_messages, defaults.suppressed_validation_messages);
+
+		let config = GoldfishConfig::from_json(r#"{ "validation": false, "log": { "file": "goldfish.log" } }"#).unwrap();
+		assert!(!config.validation);
+		assert_eq!(config.log.file, Some(PathBuf::from("goldfish.log")));
+		assert_eq!(config.log.filter, defaults.log.filter);
+		assert_eq!(config.frames_in_flight, defaults.frames_in_flight);
+
+		let config = GoldfishConfig::from_toml("").unwrap();
+		assert_eq!(config.to_toml().unwrap(), defaults.to_toml().unwrap());
+	}
+
+	#[test]
+	fn checked_in_config_parses() {
+		let config = GoldfishConfig::from_toml(include_str!("../../../../goldfish.toml")).unwrap();
+		assert_eq!(config.present_mode, PresentMode::Mailbox);
+		assert_eq!(config.background, BackgroundBehavior::ThrottleTo(10));
+	}
+
+	#[test]
+	fn unknown_values_are_errors() {
+		assert!(matches!(GoldfishConfig::from_toml("frames_in_flight = \"two\""), Err(GoldfishError::Config(_))));
+		assert!(matches!(GoldfishConfig::from_json("{ \"present_mode\": \"Sometimes\" }"), Err(GoldfishError::Config(_))));
+	}
+}
@@ -1,13 +1,211 @@
-use crate::GoldfishEngine;
+use crate::{GoldfishEngine, GoldfishError, GoldfishResult};
+use libloading::Library;
+use log::error;
 use std::time::Duration;
 
+// Bump this whenever a required hook changes. New hooks go on the end as an Option<GameHook> without bumping it.
+pub const GAME_LIB_ABI_VERSION: u32 = 1;
+
+pub const CREATE_GAME_LIB_SYMBOL: &[u8] = b"_goldfish_create_game_lib";
+
+pub type GameHook = extern "C" fn(&mut GoldfishEngine);
+
+// How often GameLib::on_fixed_update runs, 60 times a second.
+pub const FIXED_UPDATE_INTERVAL: Duration = Duration::from_micros(16_667);
+
+// Games don't fill this out by hand, see declare_game!.
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct GameLib {
-	pub on_load: extern "C" fn(&mut GoldfishEngine),
-	pub on_unload: extern "C" fn(&mut GoldfishEngine),
-	pub on_update: extern "C" fn(&mut GoldfishEngine),
-	// setup: fn(),
-	// destroy: fn(),
+	// These two always have to come first, they're all the engine reads before it knows the rest of the struct matches.
+	pub abi_version: u32,
+	// size_of::<GameLib>() as the game lib saw it when it was built.
+	pub size: u32,
+	pub on_load: GameHook,
+	pub on_unload: GameHook,
+	pub on_update: GameHook,
+	// Called at FIXED_UPDATE_INTERVAL no matter the frame rate, before on_update.
+	pub on_fixed_update: Option<GameHook>,
+}
+
+// Hands back a GameLib that lives for as long as the library stays loaded.
+pub type CreateGamelibApi = unsafe extern "C" fn() -> *const GameLib;
+
+// Anything past the required hooks has to be optional, older game libs won't have it.
+const REQUIRED_GAME_LIB_SIZE: usize = memoffset::offset_of!(GameLib, on_update) + std::mem::size_of::<GameHook>();
+
+extern "C" fn noop_hook(_: &mut GoldfishEngine) {}
+
+impl GameLib {
+	// Stands in for a game lib that couldn't be loaded, so the editor still comes up (with nothing in it) instead of crashing.
+	pub const NOOP: Self = Self {
+		abi_version: GAME_LIB_ABI_VERSION,
+		size: std::mem::size_of::<Self>() as u32,
+		on_load: noop_hook,
+		on_unload: noop_hook,
+		on_update: noop_hook,
+		on_fixed_update: None,
+	};
+
+	/// The version and size are checked before anything else is read, then only as much of the struct as the game lib knows about is copied.
+	///
+	/// # Safety
+	/// `raw` has to be null or point to something that starts with a GameLib's abi_version and size, followed by `size` readable bytes.
+	pub unsafe fn from_raw(raw: *const GameLib) -> GoldfishResult<Self> {
+		if raw.is_null() {
+			return Err(GoldfishError::GameLib("the game lib constructor returned null".to_owned()));
+		}
+
+		let header = raw as *const u32;
+		let abi_version = header.read();
+		let size = header.add(1).read() as usize;
+
+		if abi_version != GAME_LIB_ABI_VERSION {
+			return Err(GoldfishError::GameLib(format!(
+				"the game lib was built for ABI version {}, but the engine is on version {}. Rebuild the game!",
+				abi_version, GAME_LIB_ABI_VERSION
+			)));
+		}
+
+		if size < REQUIRED_GAME_LIB_SIZE {
+			return Err(GoldfishError::GameLib(format!(
+				"the game lib's GameLib is {} bytes, but the required hooks alone need {}",
+				size, REQUIRED_GAME_LIB_SIZE
+			)));
+		}
+
+		// Hooks the game lib doesn't know about are left zeroed, which is None for an Option<GameHook>.
+		let mut game_lib = std::mem::MaybeUninit::<Self>::zeroed();
+		std::ptr::copy_nonoverlapping(raw as *const u8, game_lib.as_mut_ptr() as *mut u8, size.min(std::mem::size_of::<Self>()));
+
+		Ok(game_lib.assume_init())
+	}
+
+	/// # Safety
+	/// Calls the library's constructor, whatever the library exports under CREATE_GAME_LIB_SYMBOL has to be a CreateGamelibApi.
+	pub unsafe fn load(lib: &Library) -> GoldfishResult<Self> {
+		let create = lib.get::<CreateGamelibApi>(CREATE_GAME_LIB_SYMBOL).map_err(|err| GoldfishError::GameLib(err.to_string()))?;
+
+		Self::from_raw(create())
+	}
+
+	/// Same as load, except anything wrong with the game lib is logged and a game that does nothing is used in its place.
+	///
+	/// # Safety
+	/// See load.
+	pub unsafe fn load_or_noop(lib: &Library) -> Self {
+		Self::load(lib).unwrap_or_else(|err| {
+			error!("Running without a game! {}", err);
+			Self::NOOP
+		})
+	}
 }
 
-pub type CreateGamelibApi = unsafe fn() -> GameLib;
+// Declares the constructor the editor looks for, with the ABI version and size filled in. Takes the required hooks in order, any
+// optional ones go after them as `hook = function`.
+#[macro_export]
+macro_rules! declare_game {
+	($on_load:expr, $on_unload:expr, $on_update:expr $(, $hook:ident = $function:expr)* $(,)?) => {
+		#[no_mangle]
+		pub extern "C" fn _goldfish_create_game_lib() -> *const $crate::game::GameLib {
+			// Starts from NOOP for the version, the size and every optional hook that isn't given.
+			static GAME_LIB: $crate::game::GameLib = {
+				let mut game_lib = $crate::game::GameLib::NOOP;
+				game_lib.on_load = $on_load;
+				game_lib.on_unload = $on_unload;
+				game_lib.on_update = $on_update;
+				$(game_lib.$hook = Some($function);)*
+				game_lib
+			};
+
+			&GAME_LIB
+		}
+	};
+}
+
+pub use crate::declare_game;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	extern "C" fn marker_hook(_: &mut GoldfishEngine) {}
+
+	fn load_error(raw: *const GameLib) -> String {
+		match unsafe { GameLib::from_raw(raw) } {
+			Ok(_) => panic!("Loaded a game lib that should have been refused!"),
+			Err(GoldfishError::GameLib(err)) => err,
+			Err(err) => panic!("Unexpected error {}", err),
+		}
+	}
+
+	#[test]
+	fn refuses_mismatched_abi_version() {
+		let stale = GameLib {
+			abi_version: GAME_LIB_ABI_VERSION + 1,
+			..GameLib::NOOP
+		};
+
+		assert!(load_error(&stale).contains("ABI version"));
+		assert!(load_error(std::ptr::null()).contains("null"));
+	}
+
+	#[test]
+	fn refuses_game_libs_missing_required_hooks() {
+		// Only the header and the first hook, like a game lib from before on_update existed.
+		#[repr(C)]
+		struct Truncated {
+			abi_version: u32,
+			size: u32,
+			on_load: GameHook,
+		}
+
+		let truncated = Truncated {
+			abi_version: GAME_LIB_ABI_VERSION,
+			size: std::mem::size_of::<Truncated>() as u32,
+			on_load: marker_hook,
+		};
+
+		assert!(load_error(&truncated as *const Truncated as *const GameLib).contains("required hooks"));
+	}
+
+	#[test]
+	fn older_game_libs_leave_optional_hooks_unset() {
+		// Built before on_fixed_update existed, the struct ends right after on_update.
+		let older = GameLib {
+			size: REQUIRED_GAME_LIB_SIZE as u32,
+			on_update: marker_hook,
+			on_fixed_update: Some(marker_hook),
+			..GameLib::NOOP
+		};
+
+		let game_lib = unsafe { GameLib::from_raw(&older) }.ok().unwrap();
+		assert_eq!(game_lib.on_update as usize, marker_hook as GameHook as usize);
+		assert!(game_lib.on_fixed_update.is_none());
+	}
+
+	#[test]
+	fn newer_game_libs_only_copy_what_the_engine_knows() {
+		// A game lib built against an engine with a hook this one doesn't know about yet.
+		#[repr(C)]
+		struct Newer {
+			game_lib: GameLib,
+			on_late_update: Option<GameHook>,
+		}
+
+		let newer = Newer {
+			game_lib: GameLib {
+				size: std::mem::size_of::<Newer>() as u32,
+				on_update: marker_hook,
+				on_fixed_update: Some(marker_hook),
+				..GameLib::NOOP
+			},
+			on_late_update: Some(marker_hook),
+		};
+
+		let game_lib = unsafe { GameLib::from_raw(&newer as *const Newer as *const GameLib) }.ok().unwrap();
+		assert_eq!(game_lib.on_update as usize, marker_hook as GameHook as usize);
+		assert_eq!(game_lib.on_load as usize, noop_hook as GameHook as usize);
+		assert_eq!(game_lib.on_fixed_update.map(|hook| hook as usize), Some(marker_hook as GameHook as usize));
+	}
+}
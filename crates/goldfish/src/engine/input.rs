@@ -0,0 +1,140 @@
+use glam::DVec2;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub use winit::event::MouseButton;
+pub use winit::event::VirtualKeyCode as Key;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct AxisBinding {
+	pub positive: Key,
+	pub negative: Key,
+}
+
+// Maps named actions and axes to keys, this is what projects ship as their keymap file.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct InputMap {
+	actions: HashMap<String, Vec<Key>>,
+	axes: HashMap<String, AxisBinding>,
+}
+
+impl InputMap {
+	pub fn bind_action(&mut self, action: &str, keys: &[Key]) {
+		self.actions.insert(action.to_string(), keys.to_vec());
+	}
+
+	pub fn bind_axis(&mut self, axis: &str, positive: Key, negative: Key) {
+		self.axes.insert(axis.to_string(), AxisBinding { positive, negative });
+	}
+
+	// Only binds the action if nothing (usually the config file) has bound it already, so user keymaps win over the game's defaults.
+	pub fn bind_default_action(&mut self, action: &str, keys: &[Key]) {
+		self.actions.entry(action.to_string()).or_insert_with(|| keys.to_vec());
+	}
+
+	pub fn bind_default_axis(&mut self, axis: &str, positive: Key, negative: Key) {
+		self.axes.entry(axis.to_string()).or_insert(AxisBinding { positive, negative });
+	}
+
+	pub fn action_keys(&self, action: &str) -> &[Key] {
+		self.actions.get(action).map_or(&[], |keys| keys.as_slice())
+	}
+
+	pub fn axis_binding(&self, axis: &str) -> Option<AxisBinding> {
+		self.axes.get(axis).copied()
+	}
+}
+
+#[derive(Default)]
+pub struct Input {
+	pub map: InputMap,
+	pressed: HashSet<Key>,
+	last_pressed: HashSet<Key>,
+	mouse_buttons: HashSet<MouseButton>,
+	last_mouse_buttons: HashSet<MouseButton>,
+}
+
+impl Input {
+	pub fn update(&mut self, pressed: &HashSet<Key>, mouse_buttons: &HashSet<MouseButton>) {
+		std::mem::swap(&mut self.last_pressed, &mut self.pressed);
+		self.pressed.clone_from(pressed);
+
+		std::mem::swap(&mut self.last_mouse_buttons, &mut self.mouse_buttons);
+		self.mouse_buttons.clone_from(mouse_buttons);
+	}
+
+	pub fn mouse_button_pressed(&self, button: MouseButton) -> bool {
+		self.mouse_buttons.contains(&button)
+	}
+
+	pub fn mouse_button_just_pressed(&self, button: MouseButton) -> bool {
+		self.mouse_buttons.contains(&button) && !self.last_mouse_buttons.contains(&button)
+	}
+
+	pub fn mouse_button_just_released(&self, button: MouseButton) -> bool {
+		!self.mouse_buttons.contains(&button) && self.last_mouse_buttons.contains(&button)
+	}
+
+	pub fn key_pressed(&self, key: Key) -> bool {
+		self.pressed.contains(&key)
+	}
+
+	pub fn key_just_pressed(&self, key: Key) -> bool {
+		self.pressed.contains(&key) && !self.last_pressed.contains(&key)
+	}
+
+	pub fn action_pressed(&self, action: &str) -> bool {
+		self.map.action_keys(action).iter().any(|&key| self.key_pressed(key))
+	}
+
+	// This is only true on the frame that the first bound key goes down, pressing a second bound key while the first is held doesn't count.
+	pub fn action_just_pressed(&self, action: &str) -> bool {
+		let keys = self.map.action_keys(action);
+		keys.iter().any(|&key| self.key_just_pressed(key)) && !keys.iter().any(|key| self.last_pressed.contains(key))
+	}
+
+	// Returns a value in the range [-1.0, 1.0], holding both directions cancels out.
+	pub fn axis(&self, axis: &str) -> f32 {
+		let Some(binding) = self.map.axis_binding(axis) else {
+			return 0.0;
+		};
+
+		let positive = if self.key_pressed(binding.positive) { 1.0 } else { 0.0 };
+		let negative = if self.key_pressed(binding.negative) { 1.0 } else { 0.0 };
+
+		positive - negative
+	}
+}
+
+// Input the window's event loop writes as each event comes in instead of once per frame, so it can be read at any point in the frame (or
+// from another thread). See GraphicsContext::register_late_update.
+// The event loop runs on the same thread as the frame for now, so this can only ever see events that were handled before
+// the frame started. It's still written per event so that nothing reading it has to change once that's no longer the case.
+#[derive(Default)]
+pub struct AtomicInputState {
+	// The f64 bits of every raw mouse motion summed up since the window was created, readers diff it against whatever they saw last.
+	mouse_total_x: AtomicU64,
+	mouse_total_y: AtomicU64,
+}
+
+impl AtomicInputState {
+	pub fn add_mouse_delta(&self, delta: DVec2) {
+		Self::add(&self.mouse_total_x, delta.x);
+		Self::add(&self.mouse_total_y, delta.y);
+	}
+
+	// Raw motion, it doesn't skip the warp when the cursor gets grabbed and keeps counting while unfocused, unlike GoldfishEngine::mouse_delta.
+	pub fn mouse_total(&self) -> DVec2 {
+		DVec2 {
+			x: f64::from_bits(self.mouse_total_x.load(Ordering::Acquire)),
+			y: f64::from_bits(self.mouse_total_y.load(Ordering::Acquire)),
+		}
+	}
+
+	fn add(total: &AtomicU64, delta: f64) {
+		// There's only ever the one writer, the update can't actually fail.
+		let _ = total.fetch_update(Ordering::AcqRel, Ordering::Acquire, |bits| Some((f64::from_bits(bits) + delta).to_bits()));
+	}
+}
@@ -0,0 +1,254 @@
+use crate::GoldfishEngine;
+use crossbeam_channel::{Receiver, Sender};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::panic::AssertUnwindSafe;
+use std::sync::OnceLock;
+use tracy_client as tracy;
+
+// One pool for the whole process, the editor imports assets without ever creating an engine.
+static POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+// A core is left over for the main thread, which is usually busy recording the frame while the jobs run.
+pub fn worker_count() -> usize {
+	num_cpus::get_physical().saturating_sub(1).max(1)
+}
+
+fn pool() -> &'static ThreadPool {
+	POOL.get_or_init(|| {
+		ThreadPoolBuilder::new()
+			.num_threads(worker_count())
+			.thread_name(|index| format!("Job Worker {}", index))
+			.start_handler(|index| {
+				if let Some(client) = tracy::Client::running() {
+					client.set_thread_name(&format!("Job Worker {}", index));
+				}
+			})
+			.build()
+			.expect("Failed to create the job pool!")
+	})
+}
+
+// Jobs also run in tools that never start a Tracy client, like the editor's importer, where span! would panic.
+fn job_span() -> Option<tracy::Span> {
+	tracy::Client::running().map(|client| client.span(tracy::span_location!("Job"), 0))
+}
+
+// Returned by spawn, the job keeps running if this is dropped but its result is thrown away.
+pub struct JobHandle<T> {
+	receiver: Receiver<std::thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+	pub fn is_finished(&self) -> bool {
+		!self.receiver.is_empty()
+	}
+
+	// Blocks until the job is done, Err holds whatever the job panicked with.
+	pub fn join(self) -> std::thread::Result<T> {
+		self.receiver.recv().expect("The job was dropped without running!")
+	}
+
+	// Same as join, except a panic in the job carries on in the caller. Don't call this from inside a job, use scope.
+	pub fn wait(self) -> T {
+		self.join().unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+	}
+}
+
+// Runs the job on one of the workers, it shows up as a "Job" zone on that worker's thread in Tracy.
+pub fn spawn<F, T>(job: F) -> JobHandle<T>
+where
+	F: FnOnce() -> T + Send + 'static,
+	T: Send + 'static,
+{
+	let (sender, receiver) = crossbeam_channel::bounded(1);
+	pool().spawn(move || {
+		let _span = job_span();
+		// The pool aborts the process when a job panics, so the panic is caught and handed to whoever waits on it instead.
+		let result = std::panic::catch_unwind(AssertUnwindSafe(job));
+		let _ = sender.send(result);
+	});
+
+	JobHandle { receiver }
+}
+
+pub struct Scope<'a, 'scope> {
+	inner: &'a rayon::Scope<'scope>,
+}
+
+impl<'a, 'scope> Scope<'a, 'scope> {
+	// The job gets handed the scope too, so it can spawn more jobs that the scope waits on.
+	pub fn spawn<F>(&self, job: F)
+	where
+		F: for<'b> FnOnce(&Scope<'b, 'scope>) + Send + 'scope,
+	{
+		self.inner.spawn(move |inner| {
+			let _span = job_span();
+			job(&Scope { inner })
+		})
+	}
+}
+
+// Only returns once every job spawned on the scope (and every job those spawned) is done. If any of them panicked the panic carries on
+// here after the rest have finished. Scopes can be nested, a worker waiting on a scope runs other jobs in the meantime.
+pub fn scope<'scope, F, R>(op: F) -> R
+where
+	F: for<'a> FnOnce(&Scope<'a, 'scope>) -> R + Send,
+	R: Send,
+{
+	pool().scope(move |inner| op(&Scope { inner }))
+}
+
+// Runs op on a worker and blocks until it's done, rayon's parallel iterators inside it use the job pool.
+pub fn install<F, R>(op: F) -> R
+where
+	F: FnOnce() -> R + Send,
+	R: Send,
+{
+	pool().install(op)
+}
+
+pub type MainThreadJob<T = GoldfishEngine> = Box<dyn FnOnce(&mut T) + Send>;
+
+// Work that has to happen on the main thread once a job is done with its part, like creating GPU objects out of something a job decoded.
+// The engine drains its queue once a frame right before the game's update, running everything in the order it was pushed.
+pub struct MainThreadQueue<T = GoldfishEngine> {
+	sender: Sender<MainThreadJob<T>>,
+	receiver: Receiver<MainThreadJob<T>>,
+}
+
+impl<T> MainThreadQueue<T> {
+	pub fn new() -> Self {
+		let (sender, receiver) = crossbeam_channel::unbounded();
+		Self { sender, receiver }
+	}
+
+	pub fn sender(&self) -> MainThreadSender<T> {
+		MainThreadSender { sender: self.sender.clone() }
+	}
+
+	// Only takes what was pushed before it was called, anything pushed by the jobs it returns waits for the next drain.
+	pub fn drain(&self) -> Vec<MainThreadJob<T>> {
+		self.receiver.try_iter().collect()
+	}
+}
+
+impl<T> Default for MainThreadQueue<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// Can be sent to jobs, see GoldfishEngine::main_thread_sender.
+pub struct MainThreadSender<T = GoldfishEngine> {
+	sender: Sender<MainThreadJob<T>>,
+}
+
+// Derived Clone would want T: Clone.
+impl<T> Clone for MainThreadSender<T> {
+	fn clone(&self) -> Self {
+		Self { sender: self.sender.clone() }
+	}
+}
+
+impl<T> MainThreadSender<T> {
+	pub fn push<F>(&self, job: F)
+	where
+		F: FnOnce(&mut T) + Send + 'static,
+	{
+		// Only fails once the engine is gone, when there's nothing left to run it on anyway.
+		let _ = self.sender.send(Box::new(job));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	#[test]
+	fn nested_scopes_wait_for_every_job() {
+		let mut values = vec![0u32; 64];
+		let finished = AtomicUsize::new(0);
+		let finished = &finished;
+
+		scope(|s| {
+			for chunk in values.chunks_mut(16) {
+				s.spawn(move |_| {
+					// The inner scope borrows what the outer job was handed, so it has to be done before that job is.
+					scope(|inner| {
+						for value in chunk.iter_mut() {
+							inner.spawn(move |_| *value += 1);
+						}
+					});
+					finished.fetch_add(1, Ordering::Relaxed);
+				});
+			}
+
+			// Spawned from inside a job, but the outer scope still waits on it.
+			s.spawn(move |s| {
+				s.spawn(move |_| {
+					finished.fetch_add(1, Ordering::Relaxed);
+				})
+			});
+		});
+
+		assert!(values.iter().all(|&value| value == 1));
+		assert_eq!(finished.load(Ordering::Relaxed), 5);
+	}
+
+	#[test]
+	fn job_panics_reach_the_waiter() {
+		let payload = spawn(|| -> u32 { panic!("Job failed") }).join().unwrap_err();
+		assert_eq!(payload.downcast_ref::<&str>(), Some(&"Job failed"));
+
+		assert!(std::panic::catch_unwind(|| spawn(|| -> u32 { panic!("Job failed") }).wait()).is_err());
+
+		// The scope lets its other jobs finish before carrying on with the panic.
+		let finished = AtomicUsize::new(0);
+		let scoped = std::panic::catch_unwind(AssertUnwindSafe(|| {
+			scope(|s| {
+				s.spawn(|_| panic!("Job failed"));
+				s.spawn(|_| {
+					finished.fetch_add(1, Ordering::Relaxed);
+				});
+			})
+		}));
+		assert!(scoped.is_err());
+		assert_eq!(finished.load(Ordering::Relaxed), 1);
+
+		// None of that took the workers down with it.
+		assert_eq!(spawn(|| 2 + 2).wait(), 4);
+	}
+
+	#[test]
+	fn main_thread_jobs_run_in_push_order() {
+		let queue = MainThreadQueue::<Vec<u32>>::new();
+		let sender = queue.sender();
+
+		let job_sender = sender.clone();
+		spawn(move || {
+			for i in 0..8 {
+				job_sender.push(move |order| order.push(i));
+			}
+		})
+		.wait();
+
+		let requeue = sender.clone();
+		sender.push(move |order| {
+			order.push(8);
+			requeue.push(|order| order.push(9));
+		});
+
+		let mut order = Vec::new();
+		for job in queue.drain() {
+			job(&mut order);
+		}
+		assert_eq!(order, (0..9).collect::<Vec<_>>());
+
+		// Pushed while the last drain was running, so it only shows up now.
+		for job in queue.drain() {
+			job(&mut order);
+		}
+		assert_eq!(order, (0..10).collect::<Vec<_>>());
+	}
+}
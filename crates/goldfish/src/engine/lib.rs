@@ -1,112 +1,521 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
+extern crate self as goldfish;
+
+pub mod animation;
+pub mod app;
+pub mod audio;
 pub mod build;
+pub mod camera;
+pub mod config;
 pub mod game;
+pub mod input;
+pub mod jobs;
+pub mod logging;
 pub mod package;
+pub mod registry;
 pub mod renderer;
+pub mod skinning;
+pub mod time;
 pub mod tracy_gpu;
 pub mod types;
 pub mod window;
 
+use audio::AudioEngine;
+// Shaders with permutations generate a bitflags type for them, which the game can't name the crate for on its own.
+#[doc(hidden)]
+pub use bitflags;
+use config::GoldfishConfig;
+use crossbeam_channel::{Receiver, Sender};
 pub use glam::*;
-use package::{AssetType, Package, ReadAssetFn};
-use renderer::{GraphicsContext, GraphicsDevice};
-use std::time::Duration;
+use input::{AtomicInputState, Input};
+use jobs::{JobHandle, MainThreadQueue, MainThreadSender};
+pub use log;
+#[doc(hidden)]
+pub use memoffset;
+use package::{AssetEvent, AssetRegistry, AssetType, Package, ReadAssetFn, ASSET_REGISTRY_PATH};
+use renderer::renderdoc;
+use renderer::{DefaultResources, FramePacingStats, GeometryPool, GraphicsContext, GraphicsDevice, MemoryStats, OutputPolicy, ResolutionScaler, SurfacePreference, SwapchainInfo};
+use std::mem::ManuallyDrop;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use time::{EngineTime, FrameTimer};
 use tracy_client as tracy;
 pub use types::*;
 use uuid::Uuid;
-use window::Window;
+use window::{BackgroundBehavior, CursorLock, EngineEvent, UpdateControl, Window};
 
 #[derive(Error, Debug)]
 pub enum GoldfishError {
 	#[error("A filesystem error occurred {0}")]
 	Filesystem(std::io::Error),
+	#[error("A rendering error occurred {0}")]
+	Render(renderer::RenderError),
+	#[error("An audio error occurred {0}")]
+	Audio(String),
+	#[error("Failed to parse config {0}")]
+	Config(String),
+	#[error("Failed to set up logging {0}")]
+	Logging(String),
+	#[error("A windowing error occurred {0}")]
+	Window(String),
+	#[error("Failed to load the game lib: {0}")]
+	GameLib(String),
+	#[error("Asset not found: {0}")]
+	AssetNotFound(String),
+	#[error("Asset bundle error: {0}")]
+	Bundle(String),
 	#[error("Unknown error {0}")]
 	Unknown(String),
 }
 
 pub type GoldfishResult<T> = Result<T, GoldfishError>;
 
+pub const RENDER_GRAPH_DUMP_PATH: &str = "framegraph.dot";
+
+// How often the game still gets updated with BackgroundBehavior::SkipRendering, there's nothing to pace it otherwise.
+const SKIP_RENDERING_UPDATE_FPS: u32 = 30;
+
 #[macro_use(defer)]
 extern crate scopeguard;
 
 pub struct GoldfishEngine {
 	pub window: Window,
-	package_reader: ReadAssetFn,
+	package_reader: Arc<ReadAssetFn>,
+	asset_registry: AssetRegistry,
 	pub graphics_device: GraphicsDevice,
 	pub graphics_context: GraphicsContext,
+	// This is only ManuallyDrop so that we can hand it back to the device in Drop, it's always valid otherwise.
+	pub default_resources: ManuallyDrop<DefaultResources>,
+	pub geometry_pool: ManuallyDrop<GeometryPool>,
+	pub audio: AudioEngine,
 	pub game_state: *mut (),
 	tracy: tracy::Client,
-	pub keys: [bool; 255],
+	pub input: Input,
+	input_state: Arc<AtomicInputState>,
 	pub mouse_delta: DVec2,
+	pub cursor_position: DVec2,
+	cursor_locked: bool,
+	cursor_lock: CursorLock,
+	// The warp that comes with grabbing the cursor would otherwise show up as one huge delta.
+	skip_mouse_delta: bool,
+	last_cursor_position: DVec2,
+	pub frame_timer: FrameTimer,
+	pub resolution_scaler: ResolutionScaler,
+	pub output_policy: OutputPolicy,
+	pub delta_time: Duration,
+	// Game time, which can be slowed down and paused unlike delta_time.
+	pub time: EngineTime,
+	pub dump_render_graph: bool,
+	captured_validation_error: bool,
+	config: GoldfishConfig,
+	asset_event_sender: Sender<AssetEvent>,
+	asset_event_receiver: Receiver<AssetEvent>,
+	asset_events: Vec<AssetEvent>,
+	main_thread_queue: MainThreadQueue,
+	events: Vec<EngineEvent>,
+	exit_requested: bool,
+	focused: bool,
+	occluded: bool,
+	logger: &'static dyn log::Log,
+	log_level: log::LevelFilter,
 }
 
 #[global_allocator]
 static GLOBAL: tracy::ProfiledAllocator<std::alloc::System> = tracy::ProfiledAllocator::new(std::alloc::System, 128);
 
 impl GoldfishEngine {
-	pub fn new(title: &'static str, package_reader: ReadAssetFn) -> Self {
+	pub fn new(title: &'static str, package_reader: ReadAssetFn, config: Option<GoldfishConfig>) -> Self {
+		let config = config.unwrap_or_else(GoldfishConfig::load_or_default);
+
 		let tracy = tracy::Client::start();
-		let window = Window::new(title).unwrap();
+		renderer::set_capture_destroy_backtraces(config.resource_backtraces);
+		let window = Window::new(title, &config.window).unwrap();
 		let game_state = std::ptr::null_mut();
-		let keys = [false; 255];
+		let mut input = Input::default();
+		input.map = config.input.clone();
 		let mouse_delta = Default::default();
+		let cursor_position = Default::default();
+		let mut frame_timer = FrameTimer::new();
+		frame_timer.set_target_fps(config.target_fps);
+		let resolution_scaler = ResolutionScaler::new(config.gpu_frame_budget());
+		let dump_render_graph = false;
+		let (asset_event_sender, asset_event_receiver) = crossbeam_channel::unbounded();
+
+		if let Some(renderdoc) = renderdoc::renderdoc() {
+			let (major, minor, patch) = renderdoc.version();
+			log::info!("Running under RenderDoc {}.{}.{}", major, minor, patch);
+		}
+
+		let (graphics_device, graphics_context) = GraphicsDevice::new_with_context(
+			&window,
+			config.frames_in_flight,
+			config.validation_desc().as_ref(),
+			config.device_selection(),
+			config.frame_capture,
+			config.surface_preference,
+			config.present_mode,
+		);
+
+		let mut upload_context = graphics_device.create_upload_context();
+		let default_resources = ManuallyDrop::new(upload_context.create_default_resources().expect("Failed to create default resources!"));
+		graphics_device.destroy_upload_context(upload_context);
+		let geometry_pool = ManuallyDrop::new(GeometryPool::new(graphics_device.frames_in_flight));
 
-		let (graphics_device, graphics_context) = GraphicsDevice::new_with_context(&window);
+		let audio = AudioEngine::new();
+		let asset_registry = Self::load_asset_registry();
 
 		Self {
 			window,
 			graphics_device,
 			graphics_context,
-			package_reader,
+			default_resources,
+			geometry_pool,
+			audio,
+			package_reader: Arc::new(package_reader),
+			asset_registry,
 			tracy,
 			game_state,
-			keys,
+			input,
+			input_state: Default::default(),
 			mouse_delta,
+			cursor_position,
+			cursor_locked: false,
+			cursor_lock: CursorLock::Unlocked,
+			skip_mouse_delta: false,
+			last_cursor_position: cursor_position,
+			frame_timer,
+			resolution_scaler,
+			output_policy: OutputPolicy::Stretch,
+			delta_time: Duration::ZERO,
+			time: EngineTime::new(),
+			dump_render_graph,
+			captured_validation_error: false,
+			config,
+			asset_event_sender,
+			asset_event_receiver,
+			asset_events: Vec::new(),
+			main_thread_queue: MainThreadQueue::new(),
+			events: Vec::new(),
+			exit_requested: false,
+			// Not every platform sends a focus event for a window that starts out focused.
+			focused: true,
+			occluded: false,
+			logger: log::logger(),
+			log_level: log::max_level(),
+		}
+	}
+
+	pub fn config(&self) -> &GoldfishConfig {
+		&self.config
+	}
+
+	pub fn game_config(&self) -> &toml::Value {
+		&self.config.game
+	}
+
+	pub fn asset_event_sender(&self) -> Sender<AssetEvent> {
+		self.asset_event_sender.clone()
+	}
+
+	pub fn main_thread_sender(&self) -> MainThreadSender {
+		self.main_thread_queue.sender()
+	}
+
+	pub fn input_state(&self) -> Arc<AtomicInputState> {
+		self.input_state.clone()
+	}
+
+	// Everything reimported or removed since the last call, re-read changed assets with read_package.
+	pub fn drain_asset_events(&mut self) -> Vec<AssetEvent> {
+		std::mem::take(&mut self.asset_events)
+	}
+
+	pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+		std::mem::take(&mut self.events)
+	}
+
+	pub fn request_exit(&mut self) {
+		self.exit_requested = true;
+	}
+
+	// Keeps the engine running after a request_exit or an EngineEvent::CloseRequested, e.g. to ask about unsaved changes first.
+	pub fn cancel_exit(&mut self) {
+		self.exit_requested = false;
+	}
+
+	pub fn exit_requested(&self) -> bool {
+		self.exit_requested
+	}
+
+	pub fn has_focus(&self) -> bool {
+		self.focused
+	}
+
+	pub fn is_occluded(&self) -> bool {
+		self.occluded
+	}
+
+	// False while in the background with BackgroundBehavior::SkipRendering, the game should still update but not begin a frame.
+	pub fn should_render(&self) -> bool {
+		!(self.in_background() && self.config.background == BackgroundBehavior::SkipRendering)
+	}
+
+	fn in_background(&self) -> bool {
+		!self.focused || self.occluded
+	}
+
+	fn background_frame_interval(&self) -> Option<Duration> {
+		if !self.in_background() {
+			return None;
+		}
+
+		match self.config.background {
+			BackgroundBehavior::RenderNormally => None,
+			BackgroundBehavior::ThrottleTo(fps) => Some(Duration::from_secs_f64(1.0 / fps.max(1) as f64)),
+			BackgroundBehavior::SkipRendering => Some(Duration::from_secs_f64(1.0 / SKIP_RENDERING_UPDATE_FPS as f64)),
+		}
+	}
+
+	pub fn frame_index(&self) -> u64 {
+		self.graphics_context.frame_index()
+	}
+
+	pub fn is_capture_available(&self) -> bool {
+		renderdoc::renderdoc().is_some()
+	}
+
+	// Each frame ends up in its own .rdc wherever RenderDoc is set to put them.
+	pub fn trigger_gpu_capture(&mut self, frames: u32) {
+		match renderdoc::renderdoc() {
+			Some(renderdoc) => {
+				log::info!("Capturing the next {} frame(s) with RenderDoc", frames);
+				renderdoc.trigger_capture(frames);
+			}
+			None => log::debug!("Not running under RenderDoc, ignoring the capture request"),
 		}
 	}
 
 	pub fn read_package(&self, uuid: Uuid, asset_type: AssetType) -> GoldfishResult<Package> {
-		let fn_ptr = self.package_reader;
-		fn_ptr(uuid, asset_type)
+		(self.package_reader)(uuid, asset_type)
+	}
+
+	pub fn uuid_for_path(&self, path: &str, asset_type: AssetType) -> GoldfishResult<Uuid> {
+		self.asset_registry.resolve(path, asset_type)
+	}
+
+	pub fn read_package_by_path(&self, path: &str, asset_type: AssetType) -> GoldfishResult<Package> {
+		self.read_package(self.uuid_for_path(path, asset_type)?, asset_type)
+	}
+
+	// Reads and decodes the package on a job. Whatever needs the engine afterwards, like creating GPU objects, goes through main_thread_sender.
+	pub fn read_package_async(&self, uuid: Uuid, asset_type: AssetType) -> JobHandle<GoldfishResult<Package>> {
+		let package_reader = Arc::clone(&self.package_reader);
+		jobs::spawn(move || package_reader(uuid, asset_type))
+	}
+
+	pub fn asset_registry(&self) -> &AssetRegistry {
+		&self.asset_registry
+	}
+
+	pub fn set_asset_registry(&mut self, asset_registry: AssetRegistry) {
+		self.asset_registry = asset_registry;
+	}
+
+	fn load_asset_registry() -> AssetRegistry {
+		AssetRegistry::load(Path::new(ASSET_REGISTRY_PATH)).unwrap_or_else(|err| {
+			log::warn!("Failed to load the asset registry, assets can only be read by uuid! {}", err);
+			AssetRegistry::default()
+		})
 	}
 
 	pub fn run<F>(&mut self, mut editor_update: F)
 	where
 		F: FnMut(&mut Self, Duration),
 	{
-		Window::run(self.window.get_run_context(), |dt, keys, mouse_delta, new_size| {
-			self.keys.copy_from_slice(keys);
-			self.mouse_delta = mouse_delta;
+		let main_window = self.window.winit_window.id();
+		let run_context = self.window.get_run_context();
+		let input_state = self.input_state.clone();
+		Window::run(run_context, main_window, input_state, |dt, keys, mouse_buttons, mouse_delta, cursor_position, new_size, events| {
+			let update_start = Instant::now();
+			self.input.update(keys, mouse_buttons);
+			for event in &events {
+				match *event {
+					EngineEvent::FocusChanged(focused) => {
+						self.focused = focused;
+						self.on_focus_changed(focused);
+					}
+					EngineEvent::OcclusionChanged(occluded) => self.occluded = occluded,
+					EngineEvent::CloseRequested => self.exit_requested = true,
+					_ => {}
+				}
+			}
+			self.events.extend(events);
+			self.mouse_delta = self.locked_mouse_delta(mouse_delta, cursor_position);
+			self.cursor_position = cursor_position;
+			self.dump_render_graph = self.input.key_just_pressed(input::Key::F9);
+			// This captures the frame after the one that raised the error, most errors come back every frame anyway.
+			if self.graphics_device.take_validation_error() && self.config.capture_on_validation_error && !self.captured_validation_error && self.is_capture_available() {
+				log::warn!("Validation error detected, capturing the next frame");
+				self.captured_validation_error = true;
+				self.trigger_gpu_capture(1);
+			}
+			let asset_event_count = self.asset_events.len();
+			self.asset_events.extend(self.asset_event_receiver.try_iter());
+			if self.asset_events.len() != asset_event_count {
+				self.asset_registry = Self::load_asset_registry();
+			}
 
 			tracy::span!();
 			// let renderer = self.renderer.as_mut().unwrap();
 
 			if let Some(size) = new_size {
-				self.graphics_context.on_resize(size);
+				self.graphics_context.on_resize(size.into());
 
 				// TODO(Brandon): This is really really really fucking stupid, but it's the
 				// only way I've been able to stop this ERROR_NATIVE_WINDOW_IN_USE_KHR
 				// nonsense. I need to find a better solution to this
-				return;
+				return UpdateControl::Continue;
 			}
 			// renderer.update(&self.window);
 
+			if let Some(gpu_frame_time) = self.graphics_context.gpu_frame_time() {
+				self.resolution_scaler.record(gpu_frame_time);
+			}
+
+			self.delta_time = dt;
+			self.time.advance(dt);
+			for job in self.main_thread_queue.drain() {
+				job(self);
+			}
 			editor_update(self, dt);
+			self.events.clear();
+			self.geometry_pool.end_frame();
+
+			self.frame_timer.record(dt);
 			tracy::frame_mark();
+
+			if self.exit_requested {
+				return UpdateControl::Exit;
+			}
+
+			match self.background_frame_interval() {
+				Some(interval) => UpdateControl::WaitUntil(update_start + interval),
+				None => {
+					self.frame_timer.limit(self.graphics_context.is_vsync());
+					UpdateControl::Continue
+				}
+			}
 		});
 	}
 
-	pub fn lock_cursor(&self) {
-		self.window.winit_window.set_cursor_grab(winit::window::CursorGrabMode::Locked).unwrap();
-		self.window.winit_window.set_cursor_visible(false);
+	// The caller has to destroy secondary contexts before the engine is dropped.
+	pub fn create_secondary_context(&self, window: &Window, surface_preference: SurfacePreference) -> GoldfishResult<GraphicsContext> {
+		GraphicsContext::new(&self.graphics_device, window, surface_preference, self.config.present_mode)
+	}
+
+	pub fn defaults(&self) -> &DefaultResources {
+		&self.default_resources
+	}
+
+	pub fn memory_stats(&self) -> MemoryStats {
+		self.graphics_device.memory_stats()
+	}
+
+	pub fn frame_pacing(&self) -> FramePacingStats {
+		self.graphics_context.frame_pacing()
 	}
 
-	pub fn unlock_cursor(&self) {
+	pub fn swapchain_info(&self) -> SwapchainInfo {
+		self.graphics_context.swapchain_info()
+	}
+
+	pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+		self.frame_timer.set_target_fps(target_fps);
+	}
+
+	// Falls back to confining the cursor and then to warping it back to the center every frame on platforms that can't lock it.
+	pub fn lock_cursor(&mut self) -> GoldfishResult<()> {
+		self.cursor_locked = true;
+		self.apply_cursor_lock()
+	}
+
+	pub fn unlock_cursor(&mut self) -> GoldfishResult<()> {
+		self.cursor_locked = false;
+		self.cursor_lock = CursorLock::Unlocked;
+
 		self.window.winit_window.set_cursor_visible(true);
-		self.window.winit_window.set_cursor_grab(winit::window::CursorGrabMode::None).unwrap();
+		self.window.release_cursor().map_err(|err| GoldfishError::Window(err.to_string()))
+	}
+
+	pub fn cursor_lock(&self) -> CursorLock {
+		self.cursor_lock
+	}
+
+	// The cursor in pixels from the top left of output_policy's viewport, None while it's over the bars or outside of the window.
+	pub fn cursor_render_position(&self) -> Option<DVec2> {
+		self.output_policy.output_to_viewport(self.graphics_context.output_size(), self.cursor_position)
+	}
+
+	fn apply_cursor_lock(&mut self) -> GoldfishResult<()> {
+		self.cursor_lock = self.window.grab_cursor();
+		self.skip_mouse_delta = true;
+
+		if self.cursor_lock == CursorLock::Emulated {
+			if let Err(err) = self.window.center_cursor() {
+				self.cursor_locked = false;
+				self.cursor_lock = CursorLock::Unlocked;
+				return Err(GoldfishError::Window(format!("Cannot grab or warp the cursor: {}", err)));
+			}
+		}
+
+		self.window.winit_window.set_cursor_visible(false);
+
+		Ok(())
+	}
+
+	fn on_focus_changed(&mut self, focused: bool) {
+		if !self.cursor_locked {
+			return;
+		}
+
+		if focused {
+			if let Err(err) = self.apply_cursor_lock() {
+				log::warn!("Failed to lock the cursor again after regaining focus! {}", err);
+			}
+		} else {
+			self.cursor_lock = CursorLock::Unlocked;
+			self.window.winit_window.set_cursor_visible(true);
+			let _ = self.window.release_cursor();
+		}
+	}
+
+	fn locked_mouse_delta(&mut self, raw_delta: DVec2, cursor_position: DVec2) -> DVec2 {
+		let moved = cursor_position != self.last_cursor_position;
+		self.last_cursor_position = cursor_position;
+
+		let delta = match self.cursor_lock {
+			CursorLock::Unlocked if self.cursor_locked => DVec2::ZERO,
+			CursorLock::Unlocked | CursorLock::Locked => raw_delta,
+			CursorLock::Confined => {
+				let _ = self.window.center_cursor();
+				raw_delta
+			}
+			CursorLock::Emulated => {
+				let delta = if moved { cursor_position - self.window.get_center() } else { DVec2::ZERO };
+				let _ = self.window.center_cursor();
+				delta
+			}
+		};
+
+		if std::mem::take(&mut self.skip_mouse_delta) {
+			DVec2::ZERO
+		} else {
+			delta
+		}
 	}
 }
 
@@ -114,7 +523,16 @@ impl Drop for GoldfishEngine {
 	fn drop(&mut self) {
 		// let renderer = self.renderer.take().unwrap();
 		// renderer.destroy();
+		// The game has been unloaded by now, whatever it queued for destruction has to go before the swapchain does.
+		self.graphics_device.flush_destruction_queues();
 		self.graphics_context.destroy();
+
+		let default_resources = unsafe { ManuallyDrop::take(&mut self.default_resources) };
+		self.graphics_device.destroy_default_resources(default_resources);
+
+		let geometry_pool = unsafe { ManuallyDrop::take(&mut self.geometry_pool) };
+		geometry_pool.destroy(&mut self.graphics_device);
+
 		self.graphics_device.destroy();
 	}
 }
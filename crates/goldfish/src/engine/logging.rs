@@ -0,0 +1,171 @@
+use crate::{GoldfishEngine, GoldfishError, GoldfishResult};
+pub use log::{Level, LevelFilter};
+use log::{Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Takes priority over the filter in the config when it's set, same syntax.
+pub const LOG_FILTER_VAR: &str = "RUST_LOG";
+
+// Set this to anything to get the console output without colors.
+pub const NO_COLOR_VAR: &str = "NO_COLOR";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+	// See LogFilter::parse, something like "info,goldfish::renderer=debug".
+	pub filter: String,
+	// Everything that gets past the filter is written here too, without the colors. Truncated on startup.
+	pub file: Option<PathBuf>,
+}
+
+impl Default for LogConfig {
+	fn default() -> Self {
+		Self {
+			filter: String::from("info"),
+			file: None,
+		}
+	}
+}
+
+// A default level plus per module overrides, the most specific module that matches a record's target wins.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+	default: LevelFilter,
+	modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+	pub fn new(default: LevelFilter) -> Self {
+		Self { default, modules: Vec::new() }
+	}
+
+	// RUST_LOG style comma separated directives, either a bare level ("warn") for the default, "module::path=level", or a bare module
+	// which turns on everything for it. Without a bare level anything not mentioned only logs errors.
+	pub fn parse(spec: &str) -> GoldfishResult<Self> {
+		let parse_level = |level: &str| {
+			level
+				.trim()
+				.parse::<LevelFilter>()
+				.map_err(|_| GoldfishError::Logging(format!("Unknown log level \"{}\" in \"{}\"", level.trim(), spec)))
+		};
+
+		let mut filter = Self::new(LevelFilter::Error);
+		for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+			match directive.split_once('=') {
+				Some((module, level)) => filter.modules.push((module.trim().to_string(), parse_level(level)?)),
+				None => match directive.parse::<LevelFilter>() {
+					Ok(level) => filter.default = level,
+					Err(_) => filter.modules.push((directive.to_string(), LevelFilter::Trace)),
+				},
+			}
+		}
+
+		// Longest first, so that the first match in level_for is always the most specific one.
+		filter.modules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+		Ok(filter)
+	}
+
+	pub fn level_for(&self, target: &str) -> LevelFilter {
+		self.modules
+			.iter()
+			.find(|(module, _)| matches!(target.strip_prefix(module.as_str()), Some(rest) if rest.is_empty() || rest.starts_with("::")))
+			.map_or(self.default, |&(_, level)| level)
+	}
+
+	// The most verbose level anything is allowed to log at, records above this are thrown away before they're even formatted.
+	pub fn max_level(&self) -> LevelFilter {
+		self.modules.iter().map(|&(_, level)| level).fold(self.default, LevelFilter::max)
+	}
+}
+
+pub struct GoldfishLogger {
+	filter: LogFilter,
+	file: Option<Mutex<File>>,
+	colors: bool,
+	start: Instant,
+}
+
+impl GoldfishLogger {
+	fn color(level: Level) -> &'static str {
+		match level {
+			Level::Error => "31",
+			Level::Warn => "33",
+			Level::Info => "32",
+			Level::Debug => "36",
+			Level::Trace => "90",
+		}
+	}
+}
+
+impl Log for GoldfishLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.filter.level_for(metadata.target())
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+
+		let line = format!("{:>9.3} {:<5} {}: {}", self.start.elapsed().as_secs_f32(), record.level(), record.target(), record.args());
+
+		if self.colors {
+			eprintln!("\x1b[{}m{}\x1b[0m", Self::color(record.level()), line);
+		} else {
+			eprintln!("{}", line);
+		}
+
+		// Not much we can do about a failed write from inside the logger, and the console still got it.
+		if let Some(file) = &self.file {
+			let _ = writeln!(file.lock().unwrap(), "{}", line);
+		}
+	}
+
+	fn flush(&self) {
+		if let Some(file) = &self.file {
+			let _ = file.lock().unwrap().flush();
+		}
+	}
+}
+
+// Can only be called once per process, the editor does it as soon as the config is loaded.
+pub fn init(level: LevelFilter, file: Option<PathBuf>) -> GoldfishResult<()> {
+	init_with_filter(LogFilter::new(level), file)
+}
+
+pub fn init_with_filter(filter: LogFilter, file: Option<PathBuf>) -> GoldfishResult<()> {
+	let file = file.map(File::create).transpose().map_err(GoldfishError::Filesystem)?;
+	let max_level = filter.max_level();
+
+	let logger = Box::leak(Box::new(GoldfishLogger {
+		filter,
+		file: file.map(Mutex::new),
+		colors: std::env::var_os(NO_COLOR_VAR).is_none(),
+		start: Instant::now(),
+	}));
+
+	log::set_logger(logger).map_err(|err| GoldfishError::Logging(err.to_string()))?;
+	log::set_max_level(max_level);
+
+	Ok(())
+}
+
+pub fn init_from_config(config: &LogConfig) -> GoldfishResult<()> {
+	let spec = std::env::var(LOG_FILTER_VAR).unwrap_or_else(|_| config.filter.clone());
+	init_with_filter(LogFilter::parse(&spec)?, config.file.clone())
+}
+
+// The game library statically links its own copy of log, which doesn't know about the logger the editor installed. Call this at the
+// top of on_load so that the game's logging (and anything it calls into the engine for) goes through the same logger.
+pub fn attach(engine: &GoldfishEngine) {
+	// This fails if the library wasn't actually unloaded since the last on_load, but then it's still attached from last time.
+	if log::set_logger(engine.logger).is_ok() {
+		log::set_max_level(engine.log_level);
+	}
+}
@@ -1,13 +1,25 @@
-use super::{renderer::Vertex, GoldfishError, GoldfishResult};
+use super::animation::AnimationClip;
+use super::renderer::{ExtendedVertex, SkinnedVertex, TextureFormat, Vertex, VertexChannels, VertexInputInfo};
+use super::{GoldfishError, GoldfishResult};
+use memmap2::Mmap;
 use serde::{Deserialize, Serialize};
-
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+// Written by the editor's importer alongside the built assets, loaded by the engine on startup.
+pub const ASSET_REGISTRY_PATH: &str = ".build/assets/registry.bin";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AssetType {
 	Mesh,
 	Texture,
 	Shader,
+	Audio,
+	// Never has a source file of its own, clips come out of mesh files alongside the meshes.
+	Animation,
 	Other,
 }
 
@@ -15,30 +27,561 @@ impl AssetType {
 	pub fn from_extension(extension: &str) -> Self {
 		match extension.to_ascii_lowercase().as_str() {
 			"png" | "jpg" | "jpeg" => Self::Texture,
-			"fbx" | "obj" => Self::Mesh,
-			"hlsl" => Self::Shader,
+			// Assets are imported from memory, so .gltf files only work if their buffers are embedded. Prefer .glb.
+			"fbx" | "obj" | "gltf" | "glb" => Self::Mesh,
+			// GLSL stages come from the extension, see shader_compiler::compile_shader.
+			"hlsl" | "wgsl" | "glsl" | "vert" | "frag" | "comp" => Self::Shader,
+			"wav" | "ogg" => Self::Audio,
 			_ => Self::Other,
 		}
 	}
 }
 
+// Maps every source asset path under the asset directory ("meshes/cube.obj") to the uuids built from it.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetRegistry {
+	assets: HashMap<String, Vec<(Uuid, AssetType)>>,
+}
+
+impl AssetRegistry {
+	pub fn load(path: &Path) -> GoldfishResult<Self> {
+		let contents = std::fs::read(path).map_err(GoldfishError::Filesystem)?;
+		bincode::deserialize(&contents).map_err(|err| GoldfishError::Unknown(format!("Failed to deserialize asset registry {}: {}", path.display(), err)))
+	}
+
+	pub fn save(&self, path: &Path) -> GoldfishResult<()> {
+		let serialized = bincode::serialize(self).map_err(|err| GoldfishError::Unknown(format!("Failed to serialize asset registry: {}", err)))?;
+		std::fs::write(path, serialized).map_err(GoldfishError::Filesystem)
+	}
+
+	pub fn registry_path(asset_dir: &Path, source_path: &Path) -> Option<String> {
+		let relative = source_path.strip_prefix(asset_dir).ok()?;
+		let components = relative.components().map(|component| component.as_os_str().to_str()).collect::<Option<Vec<_>>>()?;
+
+		Some(components.join("/"))
+	}
+
+	pub fn insert(&mut self, path: String, assets: Vec<(Uuid, AssetType)>) {
+		self.assets.insert(path, assets);
+	}
+
+	pub fn remove(&mut self, path: &str) -> Option<Vec<(Uuid, AssetType)>> {
+		self.assets.remove(path)
+	}
+
+	pub fn get(&self, path: &str) -> Option<&[(Uuid, AssetType)]> {
+		self.assets.get(path).map(Vec::as_slice)
+	}
+
+	pub fn paths(&self) -> impl Iterator<Item = &str> {
+		self.assets.keys().map(String::as_str)
+	}
+
+	// A plain path is the first asset of the type built from that file, "meshes/character.fbx#2" is the third.
+	pub fn resolve(&self, path: &str, asset_type: AssetType) -> GoldfishResult<Uuid> {
+		let (source_path, index) = match path.rsplit_once('#') {
+			Some((source_path, index)) => match index.parse::<usize>() {
+				Ok(index) => (source_path, index),
+				Err(_) => return Err(GoldfishError::AssetNotFound(format!("\"{}\" has an invalid sub asset index", path))),
+			},
+			None => (path, 0),
+		};
+
+		let assets = match self.assets.get(source_path) {
+			Some(assets) => assets,
+			None => return Err(GoldfishError::AssetNotFound(format!("No asset at \"{}\"{}", source_path, self.suggestions(source_path)))),
+		};
+
+		assets
+			.iter()
+			.filter(|&&(_, built_type)| built_type == asset_type)
+			.nth(index)
+			.map(|&(uuid, _)| uuid)
+			.ok_or_else(|| GoldfishError::AssetNotFound(format!("\"{}\" has no {:?} asset #{}", source_path, asset_type, index)))
+	}
+
+	fn suggestions(&self, path: &str) -> String {
+		const MAX_SUGGESTIONS: usize = 3;
+
+		let file_name = path.rsplit('/').next().unwrap_or(path);
+		let mut candidates = self
+			.paths()
+			.map(|candidate| {
+				let distance = if candidate.rsplit('/').next() == Some(file_name) { 0 } else { edit_distance(path, candidate) };
+				(distance, candidate)
+			})
+			.filter(|&(distance, _)| distance <= (path.len() / 3).max(3))
+			.collect::<Vec<_>>();
+		candidates.sort();
+
+		if candidates.is_empty() {
+			return String::new();
+		}
+
+		let names = candidates.iter().take(MAX_SUGGESTIONS).map(|(_, candidate)| format!("\"{}\"", candidate)).collect::<Vec<_>>();
+		format!(", did you mean {}?", names.join(" or "))
+	}
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b = b.chars().collect::<Vec<_>>();
+	let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+	for (i, a) in a.chars().enumerate() {
+		let mut current = vec![i + 1; b.len() + 1];
+		for (j, &b) in b.iter().enumerate() {
+			current[j + 1] = (previous[j] + (a != b) as usize).min(previous[j + 1] + 1).min(current[j] + 1);
+		}
+		previous = current;
+	}
+
+	previous[b.len()]
+}
+
+// Sent by the editor whenever the asset watcher reimports or removes something, see GoldfishEngine::drain_asset_events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetEvent {
+	AssetChanged { uuid: Uuid, asset_type: AssetType },
+	AssetRemoved { uuid: Uuid },
+	ImportFailed { uuid: Uuid, message: String },
+}
+
 pub enum Package {
 	Mesh(MeshPackage),
 	Shader(ShaderPackage),
+	Texture(TexturePackage),
+	Animation(AnimationClip),
+	Audio(AudioPackage),
 	Text(String),
 	Bin(Vec<u8>),
 }
 
+impl Package {
+	// Built assets are the bincode serialized package for their type, this is the same whether they came from a loose file or a bundle.
+	pub fn deserialize(asset_type: AssetType, data: &[u8]) -> GoldfishResult<Self> {
+		let deserialize_error = |name: &str, err: bincode::Error| GoldfishError::Unknown(format!("Failed to deserialize {}: {}", name, err));
+
+		match asset_type {
+			AssetType::Shader => bincode::deserialize(data).map(Self::Shader).map_err(|err| deserialize_error("shader package", err)),
+			AssetType::Mesh => bincode::deserialize(data).map(Self::Mesh).map_err(|err| deserialize_error("mesh package", err)),
+			AssetType::Texture => bincode::deserialize(data).map(Self::Texture).map_err(|err| deserialize_error("texture package", err)),
+			AssetType::Animation => bincode::deserialize(data).map(Self::Animation).map_err(|err| deserialize_error("animation clip", err)),
+			AssetType::Audio => bincode::deserialize(data).map(Self::Audio).map_err(|err| deserialize_error("audio package", err)),
+			AssetType::Other => Err(GoldfishError::Unknown(String::from("Assets of type Other don't have a package"))),
+		}
+	}
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ShaderPackage {
+	// From the shader's `// #pragma goldfish permute` line, bit i of a mask means defines[i] was set.
+	pub defines: Vec<String>,
+	pub permutations: Vec<ShaderPermutationPackage>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ShaderPermutationPackage {
 	pub vs_ir: Option<Vec<u32>>,
 	pub ps_ir: Option<Vec<u32>>,
+	pub gs_ir: Option<Vec<u32>>,
+	pub cs_ir: Option<Vec<u32>>,
+}
+
+impl ShaderPackage {
+	// The mask for the permutation with exactly these defines set, None if any of them isn't one of the shader's defines.
+	pub fn mask(&self, defines: &[&str]) -> Option<u32> {
+		defines
+			.iter()
+			.try_fold(0, |mask, define| Some(mask | 1 << self.defines.iter().position(|existing| existing == define)?))
+	}
+
+	pub fn permutation(&self, mask: u32) -> Option<&ShaderPermutationPackage> {
+		self.permutations.get(mask as usize)
+	}
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct MeshPackage {
 	pub vertices: Vec<Vertex>,
 	pub indices: Vec<u16>,
+	pub skin: Option<SkinPackage>,
+	// The optional channels line up with vertices and are left out entirely when the source mesh doesn't have them, see channels.
+	pub colors: Option<Vec<[u8; 4]>>,
+	pub uv1: Option<Vec<[f32; 2]>>,
+}
+
+impl MeshPackage {
+	pub fn channels(&self) -> VertexChannels {
+		let mut channels = VertexChannels::empty();
+		channels.set(VertexChannels::COLOR, self.colors.is_some());
+		channels.set(VertexChannels::UV1, self.uv1.is_some());
+		channels
+	}
+
+	pub fn vertex_info(&self) -> VertexInputInfo {
+		if self.channels().is_empty() {
+			Vertex::VERTEX_INFO
+		} else {
+			ExtendedVertex::VERTEX_INFO
+		}
+	}
+
+	// The vertices with the optional channels folded in, None when the mesh doesn't have any so it can stay plain Vertex.
+	pub fn extended_vertices(&self) -> Option<Vec<ExtendedVertex>> {
+		if self.channels().is_empty() {
+			return None;
+		}
+
+		Some(
+			self.vertices
+				.iter()
+				.enumerate()
+				.map(|(i, vertex)| ExtendedVertex {
+					position: vertex.position,
+					normal: vertex.normal,
+					uv: vertex.uv,
+					tangent: vertex.tangent,
+					bitangent: vertex.bitangent,
+					color: self.colors.as_ref().map_or([255; 4], |colors| colors[i]),
+					uv1: self.uv1.as_ref().map_or([0.0; 2], |uv1| uv1[i]).into(),
+				})
+				.collect(),
+		)
+	}
+
+	pub fn skinned_vertices(&self) -> Option<Vec<SkinnedVertex>> {
+		let skin = self.skin.as_ref()?;
+
+		Some(
+			self.vertices
+				.iter()
+				.zip(skin.bone_indices.iter().zip(skin.bone_weights.iter()))
+				.map(|(vertex, (&bone_indices, &bone_weights))| SkinnedVertex {
+					position: vertex.position,
+					normal: vertex.normal,
+					uv: vertex.uv,
+					tangent: vertex.tangent,
+					bitangent: vertex.bitangent,
+					bone_indices,
+					bone_weights: bone_weights.into(),
+				})
+				.collect(),
+		)
+	}
+}
+
+// The influences line up with MeshPackage::vertices. Joints are sorted so parents always come before their children.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SkinPackage {
+	pub bone_indices: Vec<[u16; 4]>,
+	pub bone_weights: Vec<[f32; 4]>,
+	pub joints: Vec<JointPackage>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JointPackage {
+	pub name: String,
+	pub parent: Option<u16>,
+	pub inverse_bind_matrix: [f32; 16],
+	// Relative to the parent joint (or the mesh for roots), used for joints that a pose doesn't move.
+	pub rest_transform: [f32; 16],
+}
+
+// Only the top mip for now, as tightly packed texels or rows of 4x4 blocks. Cubemaps have the faces back to back, see TextureFormat::faces.
+#[derive(Serialize, Deserialize)]
+pub struct TexturePackage {
+	pub width: u32,
+	pub height: u32,
+	pub format: TextureFormat,
+	pub faces: u32,
+	pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AudioPackage {
+	pub sample_rate: u32,
+	pub channels: u16,
+	pub samples: Vec<i16>,
+}
+
+// Send and Sync so packages can also be read on jobs, see GoldfishEngine::read_package_async.
+pub type ReadAssetFn = Box<dyn Fn(Uuid, AssetType) -> GoldfishResult<Package> + Send + Sync>;
+
+const BUNDLE_MAGIC: [u8; 4] = *b"GFBN";
+
+// Bump this whenever the layout of the header or the index changes. The payloads are versioned along with the editor's build assets.
+const BUNDLE_VERSION: u32 = 1;
+
+const BUNDLE_HEADER_SIZE: usize = BUNDLE_MAGIC.len() + std::mem::size_of::<u32>() + 2 * std::mem::size_of::<u64>() + std::mem::size_of::<u32>();
+
+const BUNDLE_COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+struct BundleEntry {
+	offset: u64,
+	length: u64,
+	asset_type: AssetType,
+	compressed: bool,
+	checksum: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundleIndex {
+	entries: HashMap<Uuid, BundleEntry>,
+	registry: AssetRegistry,
+}
+
+// Every built asset packed into one memory mapped file: a fixed size header, the payloads back to back, then the index.
+pub struct Bundle {
+	mmap: Mmap,
+	index: BundleIndex,
+}
+
+impl Bundle {
+	pub fn write<I>(out: &Path, registry: &AssetRegistry, payloads: I) -> GoldfishResult<()>
+	where
+		I: IntoIterator<Item = (Uuid, AssetType, Vec<u8>)>,
+	{
+		let mut file = File::create(out).map_err(GoldfishError::Filesystem)?;
+		file.write_all(&[0; BUNDLE_HEADER_SIZE]).map_err(GoldfishError::Filesystem)?;
+
+		let mut index = BundleIndex {
+			entries: HashMap::new(),
+			registry: registry.clone(),
+		};
+
+		let mut offset = BUNDLE_HEADER_SIZE as u64;
+		for (uuid, asset_type, payload) in payloads {
+			let compressed = zstd::encode_all(&payload[..], BUNDLE_COMPRESSION_LEVEL).map_err(GoldfishError::Filesystem)?;
+			let (data, compressed) = if compressed.len() < payload.len() { (compressed, true) } else { (payload, false) };
+
+			file.write_all(&data).map_err(GoldfishError::Filesystem)?;
+			index.entries.insert(
+				uuid,
+				BundleEntry {
+					offset,
+					length: data.len() as u64,
+					asset_type,
+					compressed,
+					checksum: crc32fast::hash(&data),
+				},
+			);
+			offset += data.len() as u64;
+		}
+
+		let serialized = bincode::serialize(&index).map_err(|err| GoldfishError::Bundle(format!("Failed to serialize the index of {}: {}", out.display(), err)))?;
+		file.write_all(&serialized).map_err(GoldfishError::Filesystem)?;
+
+		let mut header = Vec::with_capacity(BUNDLE_HEADER_SIZE);
+		header.extend_from_slice(&BUNDLE_MAGIC);
+		header.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+		header.extend_from_slice(&offset.to_le_bytes());
+		header.extend_from_slice(&(serialized.len() as u64).to_le_bytes());
+		header.extend_from_slice(&crc32fast::hash(&serialized).to_le_bytes());
+
+		file.seek(SeekFrom::Start(0)).map_err(GoldfishError::Filesystem)?;
+		file.write_all(&header).map_err(GoldfishError::Filesystem)
+	}
+
+	pub fn open(path: &Path) -> GoldfishResult<Self> {
+		let file = File::open(path).map_err(GoldfishError::Filesystem)?;
+		let mmap = unsafe { Mmap::map(&file) }.map_err(GoldfishError::Filesystem)?;
+
+		let corrupt = |reason: &str| GoldfishError::Bundle(format!("{} is not a valid bundle, {}", path.display(), reason));
+
+		if mmap.len() < BUNDLE_HEADER_SIZE || mmap[..BUNDLE_MAGIC.len()] != BUNDLE_MAGIC {
+			return Err(corrupt("the header is missing"));
+		}
+
+		let read_u32 = |at: usize| u32::from_le_bytes(mmap[at..at + 4].try_into().unwrap());
+		let read_u64 = |at: usize| u64::from_le_bytes(mmap[at..at + 8].try_into().unwrap());
+
+		let version = read_u32(4);
+		if version != BUNDLE_VERSION {
+			return Err(corrupt(&format!("it was written as version {} but version {} is expected, cook it again", version, BUNDLE_VERSION)));
+		}
+
+		let index_offset = read_u64(8);
+		let index_length = read_u64(16);
+		let index_checksum = read_u32(24);
+
+		let index = index_offset
+			.checked_add(index_length)
+			.filter(|&end| end <= mmap.len() as u64)
+			.map(|end| &mmap[index_offset as usize..end as usize])
+			.ok_or_else(|| corrupt("the index is past the end of the file"))?;
+
+		if crc32fast::hash(index) != index_checksum {
+			return Err(corrupt("the index checksum doesn't match"));
+		}
+
+		let index = bincode::deserialize::<BundleIndex>(index).map_err(|err| corrupt(&format!("the index failed to deserialize ({})", err)))?;
+
+		Ok(Self { mmap, index })
+	}
+
+	pub fn read(&self, uuid: Uuid, asset_type: AssetType) -> GoldfishResult<Package> {
+		let entry = self.index.entries.get(&uuid).ok_or_else(|| GoldfishError::AssetNotFound(format!("{} is not in the bundle", uuid)))?;
+
+		if entry.asset_type != asset_type {
+			return Err(GoldfishError::AssetNotFound(format!("{} is a {:?} asset, not a {:?} asset", uuid, entry.asset_type, asset_type)));
+		}
+
+		let data = entry
+			.offset
+			.checked_add(entry.length)
+			.filter(|&end| end <= self.mmap.len() as u64)
+			.map(|end| &self.mmap[entry.offset as usize..end as usize])
+			.ok_or_else(|| GoldfishError::Bundle(format!("{} points past the end of the bundle", uuid)))?;
+
+		let checksum = crc32fast::hash(data);
+		if checksum != entry.checksum {
+			return Err(GoldfishError::Bundle(format!(
+				"{} ({:?}) is corrupt, its checksum is {:08x} but {:08x} was expected",
+				uuid, asset_type, checksum, entry.checksum
+			)));
+		}
+
+		if entry.compressed {
+			let decompressed = zstd::decode_all(data).map_err(|err| GoldfishError::Bundle(format!("Failed to decompress {}: {}", uuid, err)))?;
+			Package::deserialize(asset_type, &decompressed)
+		} else {
+			Package::deserialize(asset_type, data)
+		}
+	}
+
+	pub fn contains(&self, uuid: Uuid) -> bool {
+		self.index.entries.contains_key(&uuid)
+	}
+
+	// The registry the bundle was cooked with, hand it to GoldfishEngine::set_asset_registry to read assets by path.
+	pub fn registry(&self) -> &AssetRegistry {
+		&self.index.registry
+	}
+
+	pub fn into_reader(self) -> ReadAssetFn {
+		Box::new(move |uuid, asset_type| self.read(uuid, asset_type))
+	}
 }
 
-pub type ReadAssetFn = fn(Uuid, AssetType) -> GoldfishResult<Package>;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::{Vec2, Vec3};
+	use std::path::PathBuf;
+
+	const SHADER: Uuid = Uuid::from_u128(1);
+	const MESH: Uuid = Uuid::from_u128(2);
+
+	struct TempBundle(PathBuf);
+
+	impl TempBundle {
+		fn new(name: &str) -> Self {
+			Self(std::env::temp_dir().join(format!("goldfish_{}_{}.bundle", name, std::process::id())))
+		}
+	}
+
+	impl Drop for TempBundle {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+
+	fn cook(path: &Path) {
+		let shader = ShaderPackage {
+			defines: vec!["SKINNED".to_string()],
+			permutations: vec![
+				ShaderPermutationPackage {
+					vs_ir: Some(vec![0x07230203; 64]),
+					ps_ir: Some(vec![1, 2, 3]),
+					..Default::default()
+				},
+				ShaderPermutationPackage {
+					cs_ir: Some(vec![4, 5, 6]),
+					..Default::default()
+				},
+			],
+		};
+
+		let vertex = |x: f32| Vertex {
+			position: Vec3::new(x, 0.0, 0.0),
+			normal: Vec3::Z,
+			uv: Vec2::new(x, 1.0),
+			tangent: Vec3::X,
+			bitangent: Vec3::Y,
+		};
+		let mesh = MeshPackage {
+			vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+			indices: vec![0, 1, 2],
+			skin: None,
+			colors: Some(vec![[255, 0, 0, 255]; 3]),
+			uv1: None,
+		};
+
+		let mut registry = AssetRegistry::default();
+		registry.insert("shaders/lit.hlsl".to_string(), vec![(SHADER, AssetType::Shader)]);
+		registry.insert("meshes/triangle.glb".to_string(), vec![(MESH, AssetType::Mesh)]);
+
+		let payloads = vec![
+			(SHADER, AssetType::Shader, bincode::serialize(&shader).unwrap()),
+			(MESH, AssetType::Mesh, bincode::serialize(&mesh).unwrap()),
+		];
+		Bundle::write(path, &registry, payloads).unwrap();
+	}
+
+	#[test]
+	fn round_trips_a_shader_and_a_mesh() {
+		let path = TempBundle::new("round_trip");
+		cook(&path.0);
+
+		let bundle = Bundle::open(&path.0).unwrap();
+		assert!(bundle.contains(SHADER) && bundle.contains(MESH));
+		assert_eq!(bundle.registry().resolve("meshes/triangle.glb", AssetType::Mesh).unwrap(), MESH);
+
+		assert!(bundle.index.entries[&SHADER].compressed);
+
+		let shader = match bundle.read(SHADER, AssetType::Shader).unwrap() {
+			Package::Shader(shader) => shader,
+			_ => panic!("Expected a shader package!"),
+		};
+		assert_eq!(shader.mask(&["SKINNED"]), Some(1));
+		assert_eq!(shader.permutation(0).unwrap().vs_ir, Some(vec![0x07230203; 64]));
+		assert_eq!(shader.permutation(0).unwrap().ps_ir, Some(vec![1, 2, 3]));
+		assert_eq!(shader.permutation(1).unwrap().cs_ir, Some(vec![4, 5, 6]));
+
+		let mesh = match bundle.read(MESH, AssetType::Mesh).unwrap() {
+			Package::Mesh(mesh) => mesh,
+			_ => panic!("Expected a mesh package!"),
+		};
+		assert_eq!(mesh.indices, [0, 1, 2]);
+		assert_eq!(mesh.vertices[2].position, Vec3::new(2.0, 0.0, 0.0));
+		assert_eq!(mesh.vertices[1].uv, Vec2::new(1.0, 1.0));
+		assert_eq!(mesh.channels(), VertexChannels::COLOR);
+
+		assert!(matches!(bundle.read(MESH, AssetType::Shader), Err(GoldfishError::AssetNotFound(_))));
+		assert!(matches!(bundle.read(Uuid::from_u128(3), AssetType::Mesh), Err(GoldfishError::AssetNotFound(_))));
+	}
+
+	#[test]
+	fn corrupt_payloads_and_headers_are_caught() {
+		let path = TempBundle::new("corrupt");
+		cook(&path.0);
+
+		let offset = Bundle::open(&path.0).unwrap().index.entries[&MESH].offset as usize;
+		let mut contents = std::fs::read(&path.0).unwrap();
+		contents[offset] ^= 0xff;
+		std::fs::write(&path.0, &contents).unwrap();
+
+		let bundle = Bundle::open(&path.0).unwrap();
+		match bundle.read(MESH, AssetType::Mesh) {
+			Err(GoldfishError::Bundle(err)) => assert!(err.contains("is corrupt"), "{}", err),
+			_ => panic!("Reading a corrupt payload should fail!"),
+		}
+		assert!(bundle.read(SHADER, AssetType::Shader).is_ok());
+		drop(bundle);
+
+		contents[0] = b'X';
+		std::fs::write(&path.0, &contents).unwrap();
+		assert!(matches!(Bundle::open(&path.0), Err(GoldfishError::Bundle(_))));
+	}
+}
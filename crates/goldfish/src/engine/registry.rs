@@ -0,0 +1,379 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+// Stale handles come back as None once their index gets reused, since the generation won't match anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+	index: u32,
+	generation: u32,
+}
+
+impl Entity {
+	pub fn index(&self) -> u32 {
+		self.index
+	}
+
+	pub fn generation(&self) -> u32 {
+		self.generation
+	}
+}
+
+// Components are kept densely packed in the order they were inserted, with a slot per entity index pointing into them.
+pub struct ComponentStorage<T> {
+	slots: Vec<Option<u32>>,
+	entities: Vec<Entity>,
+	components: Vec<T>,
+}
+
+impl<T> Default for ComponentStorage<T> {
+	fn default() -> Self {
+		Self {
+			slots: Vec::new(),
+			entities: Vec::new(),
+			components: Vec::new(),
+		}
+	}
+}
+
+impl<T> ComponentStorage<T> {
+	fn slot(&self, entity: Entity) -> Option<usize> {
+		let slot = (*self.slots.get(entity.index as usize)?)? as usize;
+		(self.entities[slot] == entity).then_some(slot)
+	}
+
+	fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+		if let Some(slot) = self.slot(entity) {
+			return Some(std::mem::replace(&mut self.components[slot], component));
+		}
+
+		let index = entity.index as usize;
+		if index >= self.slots.len() {
+			self.slots.resize(index + 1, None);
+		}
+
+		self.slots[index] = Some(self.components.len() as u32);
+		self.entities.push(entity);
+		self.components.push(component);
+
+		None
+	}
+
+	fn get(&self, entity: Entity) -> Option<&T> {
+		self.slot(entity).map(|slot| &self.components[slot])
+	}
+
+	fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+		self.slot(entity).map(|slot| &mut self.components[slot])
+	}
+
+	fn remove(&mut self, entity: Entity) -> Option<T> {
+		let slot = self.slot(entity)?;
+		self.slots[entity.index as usize] = None;
+
+		// Shifted down rather than swap removed so iteration order stays stable, O(n) is fine for the handful of objects this holds.
+		self.entities.remove(slot);
+		for moved in &self.entities[slot..] {
+			self.slots[moved.index as usize] = Some(self.slots[moved.index as usize].unwrap() - 1);
+		}
+
+		Some(self.components.remove(slot))
+	}
+
+	pub fn len(&self) -> usize {
+		self.components.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.components.is_empty()
+	}
+}
+
+trait ErasedStorage {
+	fn remove_entity(&mut self, entity: Entity);
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> ErasedStorage for ComponentStorage<T> {
+	fn remove_entity(&mut self, entity: Entity) {
+		self.remove(entity);
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+}
+
+// A tiny object store for game objects, deliberately not a full ECS.
+#[derive(Default)]
+pub struct Registry {
+	generations: Vec<u32>,
+	free_indices: Vec<u32>,
+	storages: HashMap<TypeId, Box<dyn ErasedStorage>>,
+}
+
+impl Registry {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn spawn(&mut self) -> Entity {
+		match self.free_indices.pop() {
+			Some(index) => Entity {
+				index,
+				generation: self.generations[index as usize],
+			},
+			None => {
+				self.generations.push(0);
+				Entity {
+					index: self.generations.len() as u32 - 1,
+					generation: 0,
+				}
+			}
+		}
+	}
+
+	pub fn despawn(&mut self, entity: Entity) -> bool {
+		if !self.is_alive(entity) {
+			return false;
+		}
+
+		for storage in self.storages.values_mut() {
+			storage.remove_entity(entity);
+		}
+
+		self.generations[entity.index as usize] = entity.generation.wrapping_add(1);
+		self.free_indices.push(entity.index);
+
+		true
+	}
+
+	pub fn is_alive(&self, entity: Entity) -> bool {
+		self.generations.get(entity.index as usize) == Some(&entity.generation)
+	}
+
+	pub fn len(&self) -> usize {
+		self.generations.len() - self.free_indices.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+		assert!(self.is_alive(entity), "Can't insert a component on a despawned entity!");
+
+		self.storages
+			.entry(TypeId::of::<T>())
+			.or_insert_with(|| Box::new(ComponentStorage::<T>::default()))
+			.as_any_mut()
+			.downcast_mut::<ComponentStorage<T>>()
+			.unwrap()
+			.insert(entity, component)
+	}
+
+	pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+		self.storage::<T>()?.get(entity)
+	}
+
+	pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+		self.storage_mut::<T>()?.get_mut(entity)
+	}
+
+	pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+		self.storage_mut::<T>()?.remove(entity)
+	}
+
+	pub fn storage<T: 'static>(&self) -> Option<&ComponentStorage<T>> {
+		self.storages.get(&TypeId::of::<T>()).map(|storage| storage.as_any().downcast_ref::<ComponentStorage<T>>().unwrap())
+	}
+
+	fn storage_mut<T: 'static>(&mut self) -> Option<&mut ComponentStorage<T>> {
+		self.storages
+			.get_mut(&TypeId::of::<T>())
+			.map(|storage| storage.as_any_mut().downcast_mut::<ComponentStorage<T>>().unwrap())
+	}
+
+	// Something like registry.query::<(&mut Transform, &Mesh)>(), the same component type can't be asked for twice.
+	pub fn query<Q: Query>(&mut self) -> std::vec::IntoIter<(Entity, Q::Item<'_>)> {
+		Q::fetch(self).into_iter()
+	}
+}
+
+pub trait QueryParam {
+	type Component: 'static;
+	type Item<'a>;
+
+	/// # Safety
+	/// The pointer has to be valid for 'a, and mutable components can only be fetched once per query.
+	unsafe fn fetch<'a>(component: *mut Self::Component) -> Self::Item<'a>;
+}
+
+impl<T: 'static> QueryParam for &T {
+	type Component = T;
+	type Item<'a> = &'a T;
+
+	unsafe fn fetch<'a>(component: *mut T) -> &'a T {
+		&*component
+	}
+}
+
+impl<T: 'static> QueryParam for &mut T {
+	type Component = T;
+	type Item<'a> = &'a mut T;
+
+	unsafe fn fetch<'a>(component: *mut T) -> &'a mut T {
+		&mut *component
+	}
+}
+
+pub trait Query {
+	type Item<'a>;
+
+	fn fetch(registry: &mut Registry) -> Vec<(Entity, Self::Item<'_>)>;
+}
+
+macro_rules! impl_query {
+	($first:ident $first_storage:ident, $($param:ident $storage:ident),*) => {
+		impl<$first: QueryParam, $($param: QueryParam),*> Query for ($first, $($param,)*) {
+			type Item<'a> = ($first::Item<'a>, $($param::Item<'a>,)*);
+
+			fn fetch(registry: &mut Registry) -> Vec<(Entity, Self::Item<'_>)> {
+				let type_ids = [TypeId::of::<$first::Component>(), $(TypeId::of::<$param::Component>()),*];
+				for (i, type_id) in type_ids.iter().enumerate() {
+					assert!(!type_ids[..i].contains(type_id), "Queries can't ask for the same component twice!");
+				}
+
+				// Every storage is a different type, so handing out references into all of them at once doesn't alias.
+				let $first_storage: *mut ComponentStorage<$first::Component> = match registry.storage_mut::<$first::Component>() {
+					Some(storage) => storage,
+					None => return Vec::new(),
+				};
+				$(
+					let $storage: *mut ComponentStorage<$param::Component> = match registry.storage_mut::<$param::Component>() {
+						Some(storage) => storage,
+						None => return Vec::new(),
+					};
+				)*
+
+				unsafe {
+					(*$first_storage)
+						.entities
+						.iter()
+						.enumerate()
+						.filter_map(|(slot, &entity)| {
+							$(let $storage = (*$storage).slot(entity).map(|slot| (*$storage).components.as_mut_ptr().add(slot))?;)*
+							let $first_storage = (*$first_storage).components.as_mut_ptr().add(slot);
+							Some((entity, ($first::fetch($first_storage), $($param::fetch($storage),)*)))
+						})
+						.collect()
+				}
+			}
+		}
+	};
+}
+
+impl_query!(A a,);
+impl_query!(A a, B b);
+impl_query!(A a, B b, C c);
+impl_query!(A a, B b, C c, D d);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, PartialEq)]
+	struct Position(i32);
+
+	#[derive(Debug, PartialEq)]
+	struct Velocity(i32);
+
+	#[test]
+	fn stale_entities_after_index_reuse() {
+		let mut registry = Registry::new();
+		let first = registry.spawn();
+		registry.insert(first, Position(1));
+
+		assert!(registry.despawn(first));
+		assert!(!registry.despawn(first));
+		assert!(!registry.is_alive(first));
+		assert!(registry.is_empty());
+
+		let second = registry.spawn();
+		assert_eq!(second.index(), first.index());
+		assert_ne!(second.generation(), first.generation());
+
+		// The old handle can't see (or touch) what's in the reused slot.
+		assert_eq!(registry.get::<Position>(second), None);
+		registry.insert(second, Position(2));
+		assert_eq!(registry.get::<Position>(first), None);
+		assert_eq!(registry.get_mut::<Position>(first), None);
+		assert_eq!(registry.remove::<Position>(first), None);
+		assert_eq!(registry.get::<Position>(second), Some(&Position(2)));
+		assert_eq!(registry.len(), 1);
+	}
+
+	#[test]
+	fn mutable_query_over_two_components() {
+		let mut registry = Registry::new();
+		let entities: Vec<_> = (0..4).map(|_| registry.spawn()).collect();
+		for (i, &entity) in entities.iter().enumerate() {
+			registry.insert(entity, Position(i as i32 * 10));
+			if i % 2 == 0 {
+				registry.insert(entity, Velocity(i as i32 + 1));
+			}
+		}
+
+		for (_, (position, velocity)) in registry.query::<(&mut Position, &Velocity)>() {
+			position.0 += velocity.0;
+		}
+
+		let positions: Vec<_> = entities.iter().map(|&entity| registry.get::<Position>(entity).unwrap().0).collect();
+		assert_eq!(positions, [1, 10, 23, 30]);
+
+		for (_, (velocity, position)) in registry.query::<(&mut Velocity, &mut Position)>() {
+			velocity.0 = 0;
+			position.0 = -1;
+		}
+		assert_eq!(registry.get::<Position>(entities[0]), Some(&Position(-1)));
+		assert_eq!(registry.get::<Position>(entities[1]), Some(&Position(10)));
+		assert_eq!(registry.get::<Velocity>(entities[2]), Some(&Velocity(0)));
+	}
+
+	#[test]
+	fn query_order_survives_removal() {
+		let mut registry = Registry::new();
+		let entities: Vec<_> = (0..5).map(|_| registry.spawn()).collect();
+		for (i, &entity) in entities.iter().enumerate() {
+			registry.insert(entity, Position(i as i32));
+		}
+
+		// Removing from the middle would move the last component into the hole with a swap remove.
+		registry.despawn(entities[1]);
+		assert_eq!(registry.remove::<Position>(entities[3]), Some(Position(3)));
+
+		let order: Vec<_> = registry.query::<(&Position,)>().map(|(entity, (position,))| (entity, position.0)).collect();
+		assert_eq!(order, [(entities[0], 0), (entities[2], 2), (entities[4], 4)]);
+
+		assert_eq!(registry.get::<Position>(entities[4]), Some(&Position(4)));
+		assert_eq!(registry.storage::<Position>().unwrap().len(), 3);
+
+		let reused = registry.spawn();
+		registry.insert(reused, Position(5));
+		let order: Vec<_> = registry.query::<(&Position,)>().map(|(_, (position,))| position.0).collect();
+		assert_eq!(order, [0, 2, 4, 5]);
+	}
+
+	#[test]
+	#[should_panic(expected = "same component twice")]
+	fn query_rejects_aliasing() {
+		let mut registry = Registry::new();
+		let entity = registry.spawn();
+		registry.insert(entity, Position(0));
+		let _ = registry.query::<(&mut Position, &Position)>();
+	}
+}
@@ -0,0 +1,169 @@
+use super::VulkanRasterCmd;
+use ash::vk;
+
+// What the commands queued so far leave bound, so binding the same thing again can be dropped before it's ever queued. Only the commands
+// that actually changed something are tracked, the ones that were dropped set exactly what's already here.
+// Nothing carries over from one command buffer to the next, so this has to be reset every time the queued commands get
+// recorded. Executing secondaries doesn't matter here since the primary sets everything again afterwards, see split_cmd_list.
+#[derive(Default)]
+pub struct VulkanBoundState {
+	pipelines: Vec<(vk::PipelineBindPoint, vk::Pipeline)>,
+	descriptors: Vec<(vk::PipelineBindPoint, u32, vk::PipelineLayout, vk::DescriptorSet)>,
+	// Indexed by binding.
+	vertex_buffers: Vec<Option<(vk::Buffer, vk::DeviceSize)>>,
+	index_buffer: Option<(vk::Buffer, vk::DeviceSize, vk::IndexType)>,
+	viewport: Option<[f32; 6]>,
+	scissor: Option<(i32, i32, u32, u32)>,
+}
+
+impl VulkanBoundState {
+	// False if cmd wouldn't change anything and can be dropped, otherwise whatever it binds is tracked from now on. Anything that doesn't
+	// bind state always has to be queued.
+	pub fn bind(&mut self, cmd: &VulkanRasterCmd) -> bool {
+		match *cmd {
+			VulkanRasterCmd::BindPipeline { bind_point, pipeline } => {
+				if self.pipelines.contains(&(bind_point, pipeline)) {
+					return false;
+				}
+
+				self.pipelines.retain(|&(existing, _)| existing != bind_point);
+				self.pipelines.push((bind_point, pipeline));
+				true
+			}
+			VulkanRasterCmd::BindDescriptor {
+				pipeline_bind_point,
+				pipeline_layout,
+				first_set,
+				descriptor_set,
+			} => {
+				let bound = (pipeline_bind_point, first_set, pipeline_layout, descriptor_set);
+				if self.descriptors.contains(&bound) {
+					return false;
+				}
+
+				// Binding a set with a different layout can disturb the other sets, so they can't be trusted to still be bound.
+				self.descriptors
+					.retain(|&(bind_point, set, layout, _)| bind_point != pipeline_bind_point || (set != first_set && layout == pipeline_layout));
+				self.descriptors.push(bound);
+				true
+			}
+			VulkanRasterCmd::BindVertexBuffer { first_binding, buffer, offset } => self.bind_vertex_buffers(first_binding, &[buffer], &[offset]),
+			VulkanRasterCmd::BindVertexBuffers {
+				first_binding,
+				ref buffers,
+				ref offsets,
+			} => self.bind_vertex_buffers(first_binding, buffers, offsets),
+			VulkanRasterCmd::BindIndexBuffer { buffer, offset, index_type } => {
+				let bound = Some((buffer, offset, index_type));
+				std::mem::replace(&mut self.index_buffer, bound) != bound
+			}
+			VulkanRasterCmd::SetViewport { viewport } => {
+				let bound = Some([viewport.x, viewport.y, viewport.width, viewport.height, viewport.min_depth, viewport.max_depth]);
+				std::mem::replace(&mut self.viewport, bound) != bound
+			}
+			VulkanRasterCmd::SetScissor { scissor } => {
+				let bound = Some((scissor.offset.x, scissor.offset.y, scissor.extent.width, scissor.extent.height));
+				std::mem::replace(&mut self.scissor, bound) != bound
+			}
+			_ => true,
+		}
+	}
+
+	fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[vk::Buffer], offsets: &[vk::DeviceSize]) -> bool {
+		let first_binding = first_binding as usize;
+		let end = first_binding + buffers.len();
+		if self.vertex_buffers.len() < end {
+			self.vertex_buffers.resize(end, None);
+		}
+
+		let bound = &mut self.vertex_buffers[first_binding..end];
+		let unchanged = bound.iter().zip(buffers.iter().zip(offsets)).all(|(bound, (&buffer, &offset))| *bound == Some((buffer, offset)));
+		if unchanged {
+			return false;
+		}
+
+		for (bound, (&buffer, &offset)) in bound.iter_mut().zip(buffers.iter().zip(offsets)) {
+			*bound = Some((buffer, offset));
+		}
+		true
+	}
+
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ash::vk::Handle;
+
+	// The same cmds the mesh pass queues per mesh, only the buffers change from one mesh to the next.
+	fn mesh_cmds(mesh: u64) -> [VulkanRasterCmd; 5] {
+		[
+			VulkanRasterCmd::BindPipeline {
+				bind_point: vk::PipelineBindPoint::GRAPHICS,
+				pipeline: vk::Pipeline::from_raw(1),
+			},
+			VulkanRasterCmd::BindDescriptor {
+				pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+				pipeline_layout: vk::PipelineLayout::from_raw(2),
+				first_set: 0,
+				descriptor_set: vk::DescriptorSet::from_raw(3),
+			},
+			VulkanRasterCmd::BindVertexBuffer {
+				first_binding: 0,
+				buffer: vk::Buffer::from_raw(100 + mesh),
+				offset: 0,
+			},
+			VulkanRasterCmd::BindIndexBuffer {
+				buffer: vk::Buffer::from_raw(1000 + mesh),
+				offset: 0,
+				index_type: vk::IndexType::UINT32,
+			},
+			VulkanRasterCmd::DrawIndexed {
+				index_count: 36,
+				instance_count: 1,
+				first_index: 0,
+				vertex_offset: 0,
+				first_instance: 0,
+			},
+		]
+	}
+
+	fn queued(state: &mut VulkanBoundState, cmds: impl IntoIterator<Item = VulkanRasterCmd>) -> Vec<VulkanRasterCmd> {
+		cmds.into_iter().filter(|cmd| state.bind(cmd)).collect()
+	}
+
+	#[test]
+	fn same_pipeline_is_bound_once() {
+		let mut state = VulkanBoundState::default();
+		let cmds = queued(&mut state, (0..100).flat_map(mesh_cmds));
+
+		let count = |f: fn(&VulkanRasterCmd) -> bool| cmds.iter().filter(|cmd| f(cmd)).count();
+		assert_eq!(count(|cmd| matches!(cmd, VulkanRasterCmd::BindPipeline { .. })), 1);
+		assert_eq!(count(|cmd| matches!(cmd, VulkanRasterCmd::BindDescriptor { .. })), 1);
+		assert_eq!(count(|cmd| matches!(cmd, VulkanRasterCmd::BindVertexBuffer { .. })), 100);
+		assert_eq!(count(|cmd| matches!(cmd, VulkanRasterCmd::BindIndexBuffer { .. })), 100);
+		assert_eq!(count(|cmd| matches!(cmd, VulkanRasterCmd::DrawIndexed { .. })), 100);
+	}
+
+	#[test]
+	fn reset_binds_again() {
+		let mut state = VulkanBoundState::default();
+		assert_eq!(queued(&mut state, mesh_cmds(0)).len(), 5);
+
+		state.reset();
+		assert_eq!(queued(&mut state, mesh_cmds(0)).len(), 5);
+	}
+
+	#[test]
+	fn switching_pipelines_binds_each_switch() {
+		let mut state = VulkanBoundState::default();
+		let bind = |pipeline| VulkanRasterCmd::BindPipeline {
+			bind_point: vk::PipelineBindPoint::GRAPHICS,
+			pipeline: vk::Pipeline::from_raw(pipeline),
+		};
+		assert_eq!(queued(&mut state, [bind(1), bind(1), bind(2), bind(1), bind(1)]).len(), 3);
+	}
+}
@@ -2,12 +2,16 @@ use super::{
 	device::{VulkanDestructor, VulkanDevice, VulkanUploadContext},
 	VulkanGraphicsContext, VulkanRasterCmd,
 };
-use crate::renderer::BufferUsage;
+use crate::renderer::{BufferUsage, DestroyGuard, IndexType, MemoryLocationPreference};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 use gpu_allocator::vulkan as vma;
 use gpu_allocator::MemoryLocation;
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut, Range};
 
 impl From<BufferUsage> for vk::BufferUsageFlags {
 	fn from(usage: BufferUsage) -> vk::BufferUsageFlags {
@@ -49,16 +53,33 @@ impl From<BufferUsage> for vk::BufferUsageFlags {
 			flags |= vk::BufferUsageFlags::VERTEX_BUFFER;
 		}
 
+		if usage.contains(BufferUsage::IndirectBuffer) {
+			flags |= vk::BufferUsageFlags::INDIRECT_BUFFER;
+		}
+
 		return flags;
 	}
 }
 
+impl From<IndexType> for vk::IndexType {
+	fn from(index_type: IndexType) -> vk::IndexType {
+		match index_type {
+			IndexType::U16 => vk::IndexType::UINT16,
+			IndexType::U32 => vk::IndexType::UINT32,
+		}
+	}
+}
+
 pub struct VulkanBuffer {
 	pub raw: vk::Buffer,
 	pub allocation: vma::Allocation,
 	pub location: MemoryLocation,
 	pub usage: BufferUsage,
 	pub size: usize,
+	pub owned: bool,
+	// Lets rewriting the same data be skipped without reading back the (uncached) mapped memory.
+	written_ranges: WrittenRanges,
+	pub(crate) guard: DestroyGuard,
 }
 
 impl Hash for VulkanBuffer {
@@ -75,25 +96,117 @@ impl PartialEq for VulkanBuffer {
 
 impl Eq for VulkanBuffer {}
 
+impl VulkanBuffer {
+	// Has to be called after writing mapped memory any way other than update_buffer_range or map_buffer.
+	pub(crate) fn forget_written(&mut self, written: Range<usize>) {
+		self.written_ranges.forget(written);
+	}
+}
+
+// What update_buffer_range last wrote into a buffer, keyed by offset as (length, hash of the data). The ranges never overlap.
+#[derive(Default)]
+struct WrittenRanges(BTreeMap<usize, (usize, u64)>);
+
+impl WrittenRanges {
+	fn hash(data: &[u8]) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		data.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	fn is_unchanged(&self, offset: usize, len: usize, hash: u64) -> bool {
+		self.0.get(&offset) == Some(&(len, hash))
+	}
+
+	// Ranges are sorted and never overlap, so the walk back can stop at the first one ending before the start.
+	fn forget(&mut self, range: Range<usize>) {
+		if range.is_empty() {
+			return;
+		}
+
+		let overlapping = self
+			.0
+			.range(..range.end)
+			.rev()
+			.take_while(|&(&start, &(len, _))| start + len > range.start)
+			.map(|(&start, _)| start)
+			.collect::<Vec<_>>();
+		for start in overlapping {
+			self.0.remove(&start);
+		}
+	}
+
+	fn record(&mut self, offset: usize, len: usize, hash: u64) {
+		self.forget(offset..offset + len);
+		self.0.insert(offset, (len, hash));
+	}
+}
+
+#[cfg(test)]
+impl VulkanBuffer {
+	// Doesn't point at anything, for tests that only need a buffer to exist.
+	pub(crate) fn null(size: usize, usage: BufferUsage) -> Self {
+		Self {
+			raw: vk::Buffer::null(),
+			allocation: vma::Allocation::default(),
+			location: MemoryLocation::Unknown,
+			usage,
+			size,
+			owned: false,
+			written_ranges: WrittenRanges::default(),
+			guard: DestroyGuard::untracked(),
+		}
+	}
+}
+
+const UNNAMED_BUFFER: &str = "Unnamed Buffer";
+
 impl VulkanUploadContext {
-	pub fn create_buffer(&mut self, size: usize, location: MemoryLocation, mut usage: BufferUsage, alignment: Option<u64>, data: Option<&[u8]>) -> VulkanBuffer {
+	pub fn create_buffer(
+		&mut self,
+		name: &str,
+		size: usize,
+		location: impl Into<MemoryLocationPreference>,
+		mut usage: BufferUsage,
+		alignment: Option<u64>,
+		data: Option<&[u8]>,
+	) -> GoldfishResult<VulkanBuffer> {
 		if data.is_some() {
 			usage |= BufferUsage::TransferDst;
 		}
 
-		let buffer = self.device.create_empty_buffer(size, location, usage, alignment);
+		let buffer = self.device.create_empty_buffer(name, size, location, usage, alignment)?;
 
 		if let Some(data) = data {
-			let mut copy_buffer = self.device.create_empty_buffer(size, MemoryLocation::CpuToGpu, BufferUsage::TransferSrc, None);
+			if let Err(err) = self.write_buffer(name, &buffer, 0, data) {
+				self.destroy_buffer(buffer);
+				return Err(err);
+			}
+		}
 
-			copy_buffer.allocation.mapped_slice_mut().unwrap()[0..data.len()].copy_from_slice(data);
+		Ok(buffer)
+	}
 
-			self.wait_submit(|device, cmd| unsafe { device.cmd_copy_buffer(cmd, copy_buffer.raw, buffer.raw, &[vk::BufferCopy::builder().size(size as u64).build()]) });
+	// Copies through a staging buffer and waits for it, the destination has to have been created with TransferDst usage.
+	pub fn write_buffer(&mut self, name: &str, buffer: &VulkanBuffer, offset: u64, data: &[u8]) -> GoldfishResult<()> {
+		assert!(buffer.usage.contains(BufferUsage::TransferDst), "Cannot write a buffer without TransferDst usage!");
+		assert!(offset + data.len() as u64 <= buffer.size as u64, "Cannot write past the end of the buffer!");
 
-			self.destroy_buffer(copy_buffer);
-		}
+		let mut copy_buffer = self
+			.device
+			.create_empty_buffer(&format!("{} (Staging)", name), data.len(), MemoryLocation::CpuToGpu, BufferUsage::TransferSrc, None)?;
+
+		copy_buffer.allocation.mapped_slice_mut().unwrap()[0..data.len()].copy_from_slice(data);
+
+		self.wait_submit(|device, cmd| unsafe { device.cmd_copy_buffer(cmd, copy_buffer.raw, buffer.raw, &[vk::BufferCopy::builder().dst_offset(offset).size(data.len() as u64).build()]) });
+
+		self.destroy_buffer(copy_buffer);
 
-		return buffer;
+		Ok(())
+	}
+
+	pub fn create_buffer_unnamed(&mut self, size: usize, location: MemoryLocation, usage: BufferUsage, alignment: Option<u64>, data: Option<&[u8]>) -> GoldfishResult<VulkanBuffer> {
+		self.create_buffer(UNNAMED_BUFFER, size, location, usage, alignment, data)
 	}
 
 	pub fn destroy_buffer(&mut self, buffer: VulkanBuffer) {
@@ -102,15 +215,22 @@ impl VulkanUploadContext {
 }
 
 impl VulkanDevice {
-	pub fn create_empty_buffer(&self, mut size: usize, location: MemoryLocation, usage: BufferUsage, alignment: Option<u64>) -> VulkanBuffer {
+	// A buffer created with MemoryLocationPreference::Auto might not be host visible, check its location before mapping it.
+	pub fn create_empty_buffer(&self, name: &str, mut size: usize, location: impl Into<MemoryLocationPreference>, mut usage: BufferUsage, alignment: Option<u64>) -> GoldfishResult<VulkanBuffer> {
+		let location = self.resolve_memory_location(location.into());
+
 		if usage.contains(BufferUsage::UniformBuffer) || usage.contains(BufferUsage::UniformTexelBuffer) {
 			size = self.pad_size(size as u64) as usize;
 		}
 
+		if self.frame_capture_enabled() {
+			usage |= BufferUsage::TransferSrc;
+		}
+
 		let raw = unsafe {
 			self.raw
 				.create_buffer(&vk::BufferCreateInfo::builder().size(size as u64).usage(usage.into()).sharing_mode(vk::SharingMode::EXCLUSIVE), None)
-				.expect("Failed to create buffer!")
+				.map_err(move |err| GoldfishError::Render(err.into()))?
 		};
 
 		let mut requirements = unsafe { self.raw.get_buffer_memory_requirements(raw) };
@@ -119,59 +239,277 @@ impl VulkanDevice {
 			requirements.alignment = requirements.alignment.max(alignment);
 		}
 
-		let mut guard = self.vma.lock().unwrap();
-		let vma = guard.as_mut().unwrap();
-		let allocation = vma
-			.allocate(&vma::AllocationCreateDesc {
-				name: "buffer",
-				requirements,
-				location,
-				linear: true,
-			})
-			.expect("Failed to allocate buffer!");
+		let allocation = match self.allocate(&vma::AllocationCreateDesc {
+			name,
+			requirements,
+			location,
+			linear: true,
+		}) {
+			Ok(allocation) => allocation,
+			Err(err) => {
+				unsafe { self.raw.destroy_buffer(raw, None) };
+				return Err(GoldfishError::Render(err));
+			}
+		};
 
-		unsafe {
-			self.raw.bind_buffer_memory(raw, allocation.memory(), allocation.offset()).expect("Failed to bind buffer memory!");
+		if let Err(err) = unsafe { self.raw.bind_buffer_memory(raw, allocation.memory(), allocation.offset()) } {
+			unsafe { self.raw.destroy_buffer(raw, None) };
+			self.free(allocation);
+			return Err(GoldfishError::Render(err.into()));
 		}
 
-		VulkanBuffer {
+		self.with_capture_registry(|registry| registry.register_buffer(raw, name, size, usage));
+
+		Ok(VulkanBuffer {
 			raw,
 			allocation,
 			location,
 			usage,
 			size,
+			owned: true,
+			written_ranges: WrittenRanges::default(),
+			guard: DestroyGuard::new("Buffer", name),
+		})
+	}
+
+	// Wraps a buffer created outside of the engine. Nothing is owned, the caller keeps it alive and destroys it.
+	pub fn import_buffer_raw(&self, buffer: vk::Buffer, size: usize, usage: BufferUsage) -> VulkanBuffer {
+		VulkanBuffer {
+			raw: buffer,
+			allocation: vma::Allocation::default(),
+			location: MemoryLocation::Unknown,
+			usage,
+			size,
+			owned: false,
+			written_ranges: WrittenRanges::default(),
+			guard: DestroyGuard::untracked(),
+		}
+	}
+
+	pub fn create_empty_buffer_unnamed(&self, size: usize, location: MemoryLocation, usage: BufferUsage, alignment: Option<u64>) -> GoldfishResult<VulkanBuffer> {
+		self.create_empty_buffer(UNNAMED_BUFFER, size, location, usage, alignment)
+	}
+
+	// The range vkFlushMappedMemoryRanges needs for bytes of the buffer, None when the memory is host coherent.
+	pub(super) fn non_coherent_range(&self, buffer: &VulkanBuffer, written: Range<usize>) -> Option<vk::MappedMemoryRange> {
+		if written.is_empty() || buffer.allocation.memory_properties().contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+			return None;
+		}
+
+		let (offset, size) = non_coherent_flush_range(
+			buffer.allocation.offset(),
+			buffer.allocation.size(),
+			written.start as u64..written.end as u64,
+			self.physical_device_properties.limits.non_coherent_atom_size,
+		);
+
+		Some(vk::MappedMemoryRange::builder().memory(unsafe { buffer.allocation.memory() }).offset(offset).size(size).build())
+	}
+
+	pub(super) fn flush_mapped_ranges(&self, ranges: &[vk::MappedMemoryRange]) {
+		if ranges.is_empty() {
+			return;
+		}
+
+		unsafe { self.raw.flush_mapped_memory_ranges(ranges) }.expect("Failed to flush mapped memory!");
+	}
+
+	pub fn flush_buffer_range(&self, buffer: &VulkanBuffer, written: Range<usize>) {
+		if let Some(range) = self.non_coherent_range(buffer, written) {
+			self.flush_mapped_ranges(&[range]);
 		}
 	}
 
+	// Hands out the buffer's mapped memory, flushed on drop. The buffer has to be host visible and unused by the frame being recorded.
+	pub fn map_buffer<'a>(&'a self, buffer: &'a mut VulkanBuffer) -> VulkanMappedBuffer<'a> {
+		assert!(buffer.allocation.mapped_ptr().is_some(), "Cannot map a buffer that isn't host visible!");
+		self.assert_not_recorded(buffer);
+
+		VulkanMappedBuffer { device: self, buffer, written: None }
+	}
+
+	// Same as update_buffer_range from the start of the buffer, returns whether anything was written.
 	pub fn update_buffer(&self, buffer: &mut VulkanBuffer, data: &[u8]) -> bool {
-		if buffer.location != MemoryLocation::CpuToGpu {
-			panic!("Cannot update buffer that is not CpuToGpu!");
+		self.update_buffer_range(buffer, 0, data).is_some()
+	}
+
+	// Writes data at offset into a CpuToGpu buffer, skipped (returning None) when the range last got the same data. See forget_written.
+	pub fn update_buffer_range(&self, buffer: &mut VulkanBuffer, offset: usize, data: &[u8]) -> Option<Range<usize>> {
+		assert!(buffer.location == MemoryLocation::CpuToGpu, "Cannot update buffer that is not CpuToGpu!");
+		assert_update_in_bounds(offset, data.len(), buffer.size);
+
+		if data.is_empty() {
+			return None;
 		}
 
-		if data.len() > buffer.size {
-			panic!("Cannot update buffer with data that is too long!");
+		let end = offset + data.len();
+		let hash = WrittenRanges::hash(data);
+		if buffer.written_ranges.is_unchanged(offset, data.len(), hash) {
+			return None;
 		}
 
-		let dst = &mut buffer.allocation.mapped_slice_mut().expect("Failed to map allocation!")[0..data.len()];
+		self.assert_not_recorded(buffer);
+		buffer.allocation.mapped_slice_mut().expect("Failed to map allocation!")[offset..end].copy_from_slice(data);
+		buffer.written_ranges.record(offset, data.len(), hash);
+		self.flush_buffer_range(buffer, offset..end);
+
+		Some(offset..end)
+	}
 
-		if dst != data {
-			dst.copy_from_slice(data);
-			return true;
+	// Writing a buffer the frame being recorded already uses changes what its earlier commands see too. Only tracked in debug builds.
+	pub(super) fn record_buffer_use(&self, buffer: vk::Buffer) {
+		if let Some(recorded_buffers) = &self.recorded_buffers {
+			recorded_buffers.lock().unwrap().insert(buffer);
 		}
+	}
 
-		return false;
+	// Called once a frame has been submitted.
+	pub(super) fn forget_recorded_buffers(&self) {
+		if let Some(recorded_buffers) = &self.recorded_buffers {
+			recorded_buffers.lock().unwrap().clear();
+		}
 	}
 
-	pub fn get_buffer_dst<'a>(&self, buffer: &'a mut VulkanBuffer) -> &'a mut [u8] {
-		buffer.allocation.mapped_slice_mut().expect("Failed to map allocation!")
+	fn assert_not_recorded(&self, buffer: &VulkanBuffer) {
+		if let Some(recorded_buffers) = &self.recorded_buffers {
+			assert!(
+				!recorded_buffers.lock().unwrap().contains(&buffer.raw),
+				"Cannot write a buffer that the frame being recorded already uses, give it a copy per frame in flight instead!"
+			);
+		}
 	}
 
-	pub fn destroy_buffer(&mut self, buffer: VulkanBuffer) {
+	pub fn destroy_buffer(&mut self, mut buffer: VulkanBuffer) {
+		buffer.guard.mark_destroyed();
+		if !buffer.owned {
+			return;
+		}
+
 		self.queue_destruction(&mut [VulkanDestructor::Buffer(buffer.raw), VulkanDestructor::Allocation(buffer.allocation)])
 	}
+
+	// Skips waiting on the frames in flight, only for buffers the GPU is known to be done with.
+	pub fn destroy_buffer_immediate(&self, mut buffer: VulkanBuffer) {
+		buffer.guard.mark_destroyed();
+		if !buffer.owned {
+			return;
+		}
+
+		self.run_destructor(VulkanDestructor::Buffer(buffer.raw));
+		self.run_destructor(VulkanDestructor::Allocation(buffer.allocation));
+	}
+}
+
+// A host visible buffer's memory, see VulkanDevice::map_buffer.
+pub struct VulkanMappedBuffer<'a> {
+	device: &'a VulkanDevice,
+	buffer: &'a mut VulkanBuffer,
+	written: Option<Range<usize>>,
+}
+
+impl VulkanMappedBuffer<'_> {
+	pub fn write(&mut self, offset: usize, data: &[u8]) {
+		let end = offset + data.len();
+		assert!(
+			end <= self.buffer.size,
+			"Cannot write bytes {}..{} of a buffer that is only {} bytes long!",
+			offset,
+			end,
+			self.buffer.size
+		);
+
+		self.buffer.allocation.mapped_slice_mut().expect("Failed to map allocation!")[offset..end].copy_from_slice(data);
+		self.written = merge_written(self.written.take(), offset..end);
+	}
+
+	// Flushes everything written so far, and forgets what update_buffer_range last wrote there. Dropping the mapping does this too.
+	pub fn flush(&mut self) {
+		if let Some(written) = self.written.take() {
+			self.buffer.written_ranges.forget(written.clone());
+			self.device.flush_buffer_range(self.buffer, written);
+		}
+	}
+}
+
+// Written so a huge offset can't overflow its way back into bounds.
+fn assert_update_in_bounds(offset: usize, len: usize, size: usize) {
+	assert!(
+		offset <= size && len <= size - offset,
+		"Cannot update bytes {}..{} of a buffer that is only {} bytes long!",
+		offset,
+		offset.saturating_add(len),
+		size
+	);
+}
+
+// The smallest range covering both, flushing the gap in between is cheaper than flushing twice.
+fn merge_written(written: Option<Range<usize>>, range: Range<usize>) -> Option<Range<usize>> {
+	match written {
+		_ if range.is_empty() => written,
+		Some(written) => Some(written.start.min(range.start)..written.end.max(range.end)),
+		None => Some(range),
+	}
+}
+
+impl Deref for VulkanMappedBuffer<'_> {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		&self.buffer.allocation.mapped_slice().expect("Failed to map allocation!")[..self.buffer.size]
+	}
+}
+
+impl DerefMut for VulkanMappedBuffer<'_> {
+	fn deref_mut(&mut self) -> &mut [u8] {
+		self.written = Some(0..self.buffer.size);
+		&mut self.buffer.allocation.mapped_slice_mut().expect("Failed to map allocation!")[..self.buffer.size]
+	}
+}
+
+impl Drop for VulkanMappedBuffer<'_> {
+	fn drop(&mut self) {
+		self.flush();
+	}
+}
+
+// Widens written bytes out to whole non-coherent atoms, running to vk::WHOLE_SIZE if that would pass the end of the allocation.
+fn non_coherent_flush_range(allocation_offset: u64, allocation_size: u64, written: Range<u64>, atom_size: u64) -> (u64, u64) {
+	let start = (allocation_offset + written.start) / atom_size * atom_size;
+	let end = (allocation_offset + written.end).div_ceil(atom_size) * atom_size;
+
+	if end > allocation_offset + allocation_size {
+		(start, vk::WHOLE_SIZE)
+	} else {
+		(start, end - start)
+	}
 }
 
 impl VulkanGraphicsContext {
+	// Can't be recorded inside of a render pass.
+	pub fn copy_buffer(&self, src: &VulkanBuffer, dst: &VulkanBuffer, src_offset: u64, dst_offset: u64, size: u64) {
+		assert!(src_offset + size <= src.size as u64, "Buffer copy reads past the end of the source buffer!");
+		assert!(dst_offset + size <= dst.size as u64, "Buffer copy writes past the end of the destination buffer!");
+
+		self.queue_raster_cmd(VulkanRasterCmd::CopyBuffer {
+			src: src.raw,
+			dst: dst.raw,
+			region: vk::BufferCopy { src_offset, dst_offset, size },
+		});
+	}
+
+	// Can't be recorded inside of a render pass either, offset and size have to be multiples of 4.
+	pub fn fill_buffer(&self, buffer: &VulkanBuffer, offset: u64, size: u64, data: u32) {
+		assert!(offset % 4 == 0 && size % 4 == 0, "Buffer fill offset and size must be multiples of 4!");
+		assert!(offset + size <= buffer.size as u64, "Buffer fill writes past the end of the buffer!");
+
+		self.queue_raster_cmd(VulkanRasterCmd::FillBuffer {
+			buffer: buffer.raw,
+			offset,
+			size,
+			data,
+		});
+	}
+
 	pub fn bind_vertex_buffer(&self, buffer: &VulkanBuffer) {
 		self.queue_raster_cmd(VulkanRasterCmd::BindVertexBuffer {
 			first_binding: 0,
@@ -180,12 +518,163 @@ impl VulkanGraphicsContext {
 		});
 	}
 
-	pub fn bind_index_buffer(&self, buffer: &VulkanBuffer) {
+	pub fn bind_vertex_buffers(&self, first_binding: u32, buffers: &[(&VulkanBuffer, u64)]) {
+		self.queue_raster_cmd(VulkanRasterCmd::BindVertexBuffers {
+			first_binding,
+			buffers: buffers.iter().map(|(buffer, _)| buffer.raw).collect(),
+			offsets: buffers.iter().map(|(_, offset)| *offset).collect(),
+		});
+	}
+
+	pub fn bind_index_buffer(&self, buffer: &VulkanBuffer, offset: u64, index_type: IndexType) {
 		self.queue_raster_cmd(VulkanRasterCmd::BindIndexBuffer {
 			buffer: buffer.raw,
-			offset: 0,
-			index_type: vk::IndexType::UINT16,
+			offset,
+			index_type: index_type.into(),
 		});
 	}
 }
 // impl Vulkan
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn written(ranges: &WrittenRanges) -> Vec<(usize, usize)> {
+		ranges.0.iter().map(|(&offset, &(len, _))| (offset, len)).collect()
+	}
+
+	#[test]
+	fn overlapping_writes_forget_what_they_overlap() {
+		let mut ranges = WrittenRanges::default();
+		ranges.record(0, 16, 1);
+		ranges.record(16, 16, 2);
+		ranges.record(32, 16, 3);
+		ranges.record(64, 16, 4);
+
+		ranges.record(8, 16, 5);
+		assert_eq!(written(&ranges), [(8, 16), (32, 16), (64, 16)]);
+		assert!(!ranges.is_unchanged(0, 16, 1));
+		assert!(!ranges.is_unchanged(16, 16, 2));
+
+		ranges.record(36, 4, 6);
+		assert_eq!(written(&ranges), [(8, 16), (36, 4), (64, 16)]);
+
+		ranges.record(0, 72, 7);
+		assert_eq!(written(&ranges), [(0, 72)]);
+		assert!(ranges.is_unchanged(0, 72, 7));
+	}
+
+	#[test]
+	fn adjacent_and_disjoint_writes_are_kept() {
+		let mut ranges = WrittenRanges::default();
+		ranges.record(16, 16, 1);
+		ranges.record(0, 16, 2);
+		ranges.record(32, 16, 3);
+		ranges.record(128, 4, 4);
+		assert_eq!(written(&ranges), [(0, 16), (16, 16), (32, 16), (128, 4)]);
+
+		assert!(ranges.is_unchanged(16, 16, 1));
+		assert!(ranges.is_unchanged(128, 4, 4));
+		// Only the exact range with the same hash counts.
+		assert!(!ranges.is_unchanged(16, 16, 2));
+		assert!(!ranges.is_unchanged(16, 8, 1));
+		assert!(!ranges.is_unchanged(20, 16, 1));
+
+		ranges.record(16, 16, 5);
+		assert!(ranges.is_unchanged(16, 16, 5));
+		assert_eq!(written(&ranges).len(), 4);
+
+		// Empty ranges don't touch anything, even strictly inside of another one.
+		ranges.forget(20..20);
+		assert_eq!(written(&ranges).len(), 4);
+	}
+
+	#[test]
+	fn hashes_tell_data_apart() {
+		assert_eq!(WrittenRanges::hash(&[1, 2, 3, 4]), WrittenRanges::hash(&[1, 2, 3, 4]));
+		assert_ne!(WrittenRanges::hash(&[1, 2, 3, 4]), WrittenRanges::hash(&[1, 2, 3, 5]));
+	}
+
+	#[test]
+	fn reused_frame_slots_forget_their_writes() {
+		// Writing a slot's copy again has to forget what update_buffer_range left in it.
+		let mut slots = [VulkanBuffer::null(64, BufferUsage::UniformBuffer), VulkanBuffer::null(64, BufferUsage::UniformBuffer)];
+		for buffer in &mut slots {
+			buffer.written_ranges.record(0, 16, 1);
+			buffer.written_ranges.record(16, 16, 2);
+			buffer.written_ranges.record(48, 16, 3);
+		}
+
+		slots[0].forget_written(0..24);
+		assert_eq!(written(&slots[0].written_ranges), [(48, 16)]);
+		assert!(!slots[0].written_ranges.is_unchanged(0, 16, 1));
+
+		// The other slot's copy is untouched.
+		assert_eq!(written(&slots[1].written_ranges), [(0, 16), (16, 16), (48, 16)]);
+
+		slots[1].forget_written(0..64);
+		assert!(written(&slots[1].written_ranges).is_empty());
+	}
+
+	#[test]
+	fn updates_up_to_the_end_are_in_bounds() {
+		assert_update_in_bounds(0, 64, 64);
+		assert_update_in_bounds(60, 4, 64);
+		// An empty update right at the end doesn't touch anything, so it's fine.
+		assert_update_in_bounds(64, 0, 64);
+	}
+
+	#[test]
+	#[should_panic(expected = "Cannot update bytes 60..68 of a buffer that is only 64 bytes long")]
+	fn updates_past_the_end_panic() {
+		assert_update_in_bounds(60, 8, 64);
+	}
+
+	#[test]
+	#[should_panic(expected = "Cannot update bytes 18446744073709551615..18446744073709551615 of a buffer that is only 64 bytes long")]
+	fn huge_offsets_dont_wrap_around() {
+		assert_update_in_bounds(usize::MAX, 2, 64);
+	}
+
+	#[test]
+	fn mapped_writes_merge_into_one_range() {
+		let written = merge_written(None, 8..16);
+		assert_eq!(written, Some(8..16));
+
+		// Disjoint writes flush the gap in between as well.
+		let written = merge_written(written, 40..48);
+		assert_eq!(written, Some(8..48));
+
+		let written = merge_written(written, 0..4);
+		assert_eq!(written, Some(0..48));
+
+		// Writes inside of what's already covered, and empty ones, don't change anything.
+		assert_eq!(merge_written(written.clone(), 16..24), Some(0..48));
+		assert_eq!(merge_written(written, 100..100), Some(0..48));
+		assert_eq!(merge_written(None, 4..4), None);
+	}
+
+	#[test]
+	fn flush_ranges_cover_whole_atoms() {
+		// Already aligned.
+		assert_eq!(non_coherent_flush_range(0, 1024, 0..64, 64), (0, 64));
+		assert_eq!(non_coherent_flush_range(256, 1024, 64..128, 64), (320, 64));
+
+		// Widened out to the atoms on either side, offsets are in the memory object rather than the allocation.
+		assert_eq!(non_coherent_flush_range(0, 1024, 1..2, 64), (0, 64));
+		assert_eq!(non_coherent_flush_range(0, 1024, 60..70, 64), (0, 128));
+		assert_eq!(non_coherent_flush_range(100, 1024, 0..10, 64), (64, 64));
+		assert_eq!(non_coherent_flush_range(100, 1024, 20..40, 64), (64, 128));
+
+		// An atom size of 1 flushes exactly what was written.
+		assert_eq!(non_coherent_flush_range(100, 1024, 3..17, 1), (103, 14));
+
+		// Rounding up past the end of the allocation runs to the end of the memory object instead.
+		assert_eq!(non_coherent_flush_range(0, 100, 90..100, 64), (64, vk::WHOLE_SIZE));
+		assert_eq!(non_coherent_flush_range(128, 64, 0..64, 256), (0, vk::WHOLE_SIZE));
+
+		// Ending exactly on the end of an atom-aligned allocation stays a plain size.
+		assert_eq!(non_coherent_flush_range(128, 128, 0..128, 64), (128, 128));
+	}
+}
@@ -0,0 +1,2135 @@
+use super::{
+	buffer::VulkanBuffer,
+	device::{VulkanDestructor, VulkanDevice, VulkanUploadContext},
+	framebuffer::VulkanFramebuffer,
+	pipeline::VulkanPipeline,
+	render_pass::VulkanRenderPass,
+	shader::VulkanShader,
+	swapchain::{FrameInfo, VulkanSwapchain},
+	texture::VulkanTexture,
+	VulkanBarrierArena, VulkanGraphicsContext, VulkanRasterCmd,
+};
+use crate::renderer::{
+	AttachmentDescription, BlendMode, BufferUsage, ClearValue, DepthBias, DepthCompareOp, DescriptorBindingType, FaceCullMode, ImageLayout, LoadOp, PolygonMode, RenderError, SpecValue, StoreOp,
+	TextureFormat, TextureUsage, VertexAttributeDescriptionBinding, VertexInputInfo,
+};
+use crate::{GoldfishError, GoldfishResult};
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+// Bump whenever anything serialized below changes, captures from other versions are refused.
+const CAPTURE_VERSION: u32 = 1;
+
+fn capture_error(message: String) -> GoldfishError {
+	GoldfishError::Render(RenderError::Capture(message))
+}
+
+fn unknown_handle(what: &str, handle: impl std::fmt::Debug) -> GoldfishError {
+	capture_error(format!(
+		"{} {:?} isn't known to the capture registry, it was either destroyed or created outside of the device",
+		what, handle
+	))
+}
+
+type CapturedDescriptorLayout = Vec<(u32, DescriptorBindingType)>;
+
+// Everything below mirrors the commands and resources of a frame. Vulkan handles mean nothing outside of the process that made them, so
+// resources are indices into the capture's tables instead, and vk flags and enums are stored as their raw values.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct CapturedAttachment {
+	format: TextureFormat,
+	usage: u16,
+	load_op: LoadOp,
+	store_op: StoreOp,
+	initial_layout: ImageLayout,
+	final_layout: ImageLayout,
+}
+
+impl From<AttachmentDescription> for CapturedAttachment {
+	fn from(desc: AttachmentDescription) -> Self {
+		Self {
+			format: desc.format,
+			usage: desc.usage.bits(),
+			load_op: desc.load_op,
+			store_op: desc.store_op,
+			initial_layout: desc.initial_layout,
+			final_layout: desc.final_layout,
+		}
+	}
+}
+
+impl From<CapturedAttachment> for AttachmentDescription {
+	fn from(attachment: CapturedAttachment) -> Self {
+		Self {
+			format: attachment.format,
+			usage: TextureUsage::from_bits_truncate(attachment.usage),
+			load_op: attachment.load_op,
+			store_op: attachment.store_op,
+			initial_layout: attachment.initial_layout,
+			final_layout: attachment.final_layout,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum CapturedRenderPass {
+	Output {
+		load: bool,
+	},
+	Offscreen {
+		color_attachments: Vec<CapturedAttachment>,
+		depth_attachment: Option<CapturedAttachment>,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum CapturedImage {
+	// Whichever swapchain image the frame was recorded for, an offscreen texture stands in for it when replaying.
+	Output,
+	Texture(usize),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CapturedBuffer {
+	name: String,
+	size: usize,
+	usage: u16,
+	data: Vec<u8>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CapturedTexture {
+	name: String,
+	width: u32,
+	height: u32,
+	format: TextureFormat,
+	usage: u16,
+	initial_layout: ImageLayout,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedFramebuffer {
+	width: u32,
+	height: u32,
+	render_pass: CapturedRenderPass,
+	attachments: Vec<CapturedImage>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct CapturedRasterState {
+	pub(super) color_attachments: usize,
+	pub(super) depth_compare_op: Option<DepthCompareOp>,
+	pub(super) depth_write: bool,
+	pub(super) depth_bias: Option<DepthBias>,
+	pub(super) blend_mode: BlendMode,
+	pub(super) face_cull: FaceCullMode,
+	pub(super) push_constant_bytes: usize,
+	pub(super) vertex_attributes: Vec<VertexAttributeDescriptionBinding>,
+	pub(super) vertex_stride: u32,
+	pub(super) polygon_mode: PolygonMode,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum CapturedPipeline {
+	Raster {
+		vs: Vec<u32>,
+		ps: Option<Vec<u32>>,
+		gs: Option<Vec<u32>>,
+		descriptor_layouts: Vec<CapturedDescriptorLayout>,
+		render_pass: CapturedRenderPass,
+		state: CapturedRasterState,
+		specialization: Vec<(u32, SpecValue)>,
+	},
+	Compute {
+		cs: Vec<u32>,
+		descriptor_layouts: Vec<CapturedDescriptorLayout>,
+		specialization: Vec<(u32, SpecValue)>,
+	},
+}
+
+impl CapturedPipeline {
+	fn descriptor_layouts(&self) -> &[CapturedDescriptorLayout] {
+		match self {
+			CapturedPipeline::Raster { descriptor_layouts, .. } | CapturedPipeline::Compute { descriptor_layouts, .. } => descriptor_layouts,
+		}
+	}
+
+	fn shaders(&self) -> Vec<&[u32]> {
+		match self {
+			CapturedPipeline::Raster { vs, ps, gs, .. } => [Some(vs), ps.as_ref(), gs.as_ref()].into_iter().flatten().map(|code| code.as_slice()).collect(),
+			CapturedPipeline::Compute { cs, .. } => vec![cs.as_slice()],
+		}
+	}
+
+	fn bind_point(&self) -> vk::PipelineBindPoint {
+		match self {
+			CapturedPipeline::Raster { .. } => vk::PipelineBindPoint::GRAPHICS,
+			CapturedPipeline::Compute { .. } => vk::PipelineBindPoint::COMPUTE,
+		}
+	}
+
+	fn stage_flags(&self) -> vk::ShaderStageFlags {
+		match self {
+			CapturedPipeline::Raster { .. } => vk::ShaderStageFlags::ALL_GRAPHICS,
+			CapturedPipeline::Compute { .. } => vk::ShaderStageFlags::COMPUTE,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+enum CapturedDescriptorWrite {
+	Buffer(usize),
+	Image(CapturedImage, ImageLayout),
+	ImageArray(Vec<CapturedImage>, ImageLayout),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedDescriptorSet {
+	pipeline: usize,
+	set: u32,
+	writes: Vec<(u32, CapturedDescriptorWrite)>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CapturedRect {
+	x: i32,
+	y: i32,
+	width: u32,
+	height: u32,
+}
+
+impl From<vk::Rect2D> for CapturedRect {
+	fn from(rect: vk::Rect2D) -> Self {
+		Self {
+			x: rect.offset.x,
+			y: rect.offset.y,
+			width: rect.extent.width,
+			height: rect.extent.height,
+		}
+	}
+}
+
+impl From<CapturedRect> for vk::Rect2D {
+	fn from(rect: CapturedRect) -> Self {
+		vk::Rect2D {
+			offset: vk::Offset2D { x: rect.x, y: rect.y },
+			extent: vk::Extent2D {
+				width: rect.width,
+				height: rect.height,
+			},
+		}
+	}
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CapturedSubresourceRange {
+	aspect_mask: u32,
+	base_mip_level: u32,
+	level_count: u32,
+	base_array_layer: u32,
+	layer_count: u32,
+}
+
+impl From<vk::ImageSubresourceRange> for CapturedSubresourceRange {
+	fn from(range: vk::ImageSubresourceRange) -> Self {
+		Self {
+			aspect_mask: range.aspect_mask.as_raw(),
+			base_mip_level: range.base_mip_level,
+			level_count: range.level_count,
+			base_array_layer: range.base_array_layer,
+			layer_count: range.layer_count,
+		}
+	}
+}
+
+impl From<CapturedSubresourceRange> for vk::ImageSubresourceRange {
+	fn from(range: CapturedSubresourceRange) -> Self {
+		vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::from_raw(range.aspect_mask),
+			base_mip_level: range.base_mip_level,
+			level_count: range.level_count,
+			base_array_layer: range.base_array_layer,
+			layer_count: range.layer_count,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CapturedSubresourceLayers {
+	aspect_mask: u32,
+	mip_level: u32,
+	base_array_layer: u32,
+	layer_count: u32,
+}
+
+impl From<vk::ImageSubresourceLayers> for CapturedSubresourceLayers {
+	fn from(layers: vk::ImageSubresourceLayers) -> Self {
+		Self {
+			aspect_mask: layers.aspect_mask.as_raw(),
+			mip_level: layers.mip_level,
+			base_array_layer: layers.base_array_layer,
+			layer_count: layers.layer_count,
+		}
+	}
+}
+
+impl From<CapturedSubresourceLayers> for vk::ImageSubresourceLayers {
+	fn from(layers: CapturedSubresourceLayers) -> Self {
+		vk::ImageSubresourceLayers {
+			aspect_mask: vk::ImageAspectFlags::from_raw(layers.aspect_mask),
+			mip_level: layers.mip_level,
+			base_array_layer: layers.base_array_layer,
+			layer_count: layers.layer_count,
+		}
+	}
+}
+
+fn offset_to_array(offset: vk::Offset3D) -> [i32; 3] {
+	[offset.x, offset.y, offset.z]
+}
+
+fn array_to_offset([x, y, z]: [i32; 3]) -> vk::Offset3D {
+	vk::Offset3D { x, y, z }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CapturedBufferImageCopy {
+	buffer_offset: u64,
+	buffer_row_length: u32,
+	buffer_image_height: u32,
+	image_subresource: CapturedSubresourceLayers,
+	image_offset: [i32; 3],
+	image_extent: [u32; 3],
+}
+
+impl From<vk::BufferImageCopy> for CapturedBufferImageCopy {
+	fn from(region: vk::BufferImageCopy) -> Self {
+		Self {
+			buffer_offset: region.buffer_offset,
+			buffer_row_length: region.buffer_row_length,
+			buffer_image_height: region.buffer_image_height,
+			image_subresource: region.image_subresource.into(),
+			image_offset: offset_to_array(region.image_offset),
+			image_extent: [region.image_extent.width, region.image_extent.height, region.image_extent.depth],
+		}
+	}
+}
+
+impl From<CapturedBufferImageCopy> for vk::BufferImageCopy {
+	fn from(region: CapturedBufferImageCopy) -> Self {
+		let [width, height, depth] = region.image_extent;
+		vk::BufferImageCopy {
+			buffer_offset: region.buffer_offset,
+			buffer_row_length: region.buffer_row_length,
+			buffer_image_height: region.buffer_image_height,
+			image_subresource: region.image_subresource.into(),
+			image_offset: array_to_offset(region.image_offset),
+			image_extent: vk::Extent3D { width, height, depth },
+		}
+	}
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CapturedImageBlit {
+	src_subresource: CapturedSubresourceLayers,
+	src_offsets: [[i32; 3]; 2],
+	dst_subresource: CapturedSubresourceLayers,
+	dst_offsets: [[i32; 3]; 2],
+}
+
+impl From<vk::ImageBlit> for CapturedImageBlit {
+	fn from(region: vk::ImageBlit) -> Self {
+		Self {
+			src_subresource: region.src_subresource.into(),
+			src_offsets: region.src_offsets.map(offset_to_array),
+			dst_subresource: region.dst_subresource.into(),
+			dst_offsets: region.dst_offsets.map(offset_to_array),
+		}
+	}
+}
+
+impl From<CapturedImageBlit> for vk::ImageBlit {
+	fn from(region: CapturedImageBlit) -> Self {
+		vk::ImageBlit {
+			src_subresource: region.src_subresource.into(),
+			src_offsets: region.src_offsets.map(array_to_offset),
+			dst_subresource: region.dst_subresource.into(),
+			dst_offsets: region.dst_offsets.map(array_to_offset),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedMemoryBarrier {
+	src_access_mask: u32,
+	dst_access_mask: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedBufferBarrier {
+	src_access_mask: u32,
+	dst_access_mask: u32,
+	buffer: usize,
+	offset: u64,
+	size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedImageBarrier {
+	src_access_mask: u32,
+	dst_access_mask: u32,
+	old_layout: i32,
+	new_layout: i32,
+	image: CapturedImage,
+	range: CapturedSubresourceRange,
+}
+
+// VulkanRasterCmd with every handle swapped for an index. Checkpoints, buffer markers and queries are dropped since the pools and buffers
+// they write belong to the swapchain's frames, and none of them change what ends up in the output.
+#[derive(Serialize, Deserialize)]
+enum CapturedCmd {
+	BindPipeline {
+		pipeline: usize,
+	},
+	BindVertexBuffers {
+		first_binding: u32,
+		buffers: Vec<usize>,
+		offsets: Vec<u64>,
+	},
+	BindIndexBuffer {
+		buffer: usize,
+		offset: u64,
+		index_type: i32,
+	},
+	SetViewport {
+		viewport: [f32; 6],
+	},
+	SetScissor {
+		scissor: CapturedRect,
+	},
+	BeginRenderPass {
+		render_pass: CapturedRenderPass,
+		framebuffer: Option<usize>,
+		render_area: CapturedRect,
+		clear_values: Vec<[u32; 4]>,
+	},
+	EndRenderPass,
+	DrawIndexed {
+		index_count: u32,
+		instance_count: u32,
+		first_index: u32,
+		vertex_offset: i32,
+		first_instance: u32,
+	},
+	Draw {
+		vertex_count: u32,
+		instance_count: u32,
+		first_vertex: u32,
+		first_instance: u32,
+	},
+	DrawIndirect {
+		buffer: usize,
+		offset: u64,
+		draw_count: u32,
+		stride: u32,
+	},
+	DrawIndexedIndirect {
+		buffer: usize,
+		offset: u64,
+		draw_count: u32,
+		stride: u32,
+	},
+	BindDescriptor {
+		pipeline: usize,
+		first_set: u32,
+		descriptor_set: usize,
+	},
+	PipelineBarrier {
+		src_stage_mask: u32,
+		dst_stage_mask: u32,
+		dependency_flags: u32,
+		memory_barriers: Vec<CapturedMemoryBarrier>,
+		buffer_memory_barriers: Vec<CapturedBufferBarrier>,
+		image_memory_barriers: Vec<CapturedImageBarrier>,
+	},
+	Dispatch {
+		group_count_x: u32,
+		group_count_y: u32,
+		group_count_z: u32,
+	},
+	CopyBuffer {
+		src: usize,
+		dst: usize,
+		src_offset: u64,
+		dst_offset: u64,
+		size: u64,
+	},
+	FillBuffer {
+		buffer: usize,
+		offset: u64,
+		size: u64,
+		data: u32,
+	},
+	ClearColorImage {
+		image: CapturedImage,
+		layout: i32,
+		value: [u32; 4],
+		range: CapturedSubresourceRange,
+	},
+	ClearDepthStencilImage {
+		image: CapturedImage,
+		layout: i32,
+		depth: f32,
+		stencil: u32,
+		range: CapturedSubresourceRange,
+	},
+	CopyImageToBuffer {
+		src: CapturedImage,
+		src_layout: i32,
+		dst: usize,
+		region: CapturedBufferImageCopy,
+	},
+	BlitImage {
+		src: CapturedImage,
+		src_layout: i32,
+		dst: CapturedImage,
+		dst_layout: i32,
+		region: CapturedImageBlit,
+		filter: i32,
+	},
+	PushConstants {
+		pipeline: usize,
+		stage_flags: u32,
+		offset: u32,
+		data: Vec<u8>,
+	},
+	BeginDebugLabel {
+		name: String,
+	},
+	EndDebugLabel,
+}
+
+// A single frame's commands and everything they reference, see GraphicsContext::capture_next_frame.
+#[derive(Serialize, Deserialize)]
+pub struct VulkanFrameCapture {
+	width: u32,
+	height: u32,
+	output_is_srgb: bool,
+	buffers: Vec<CapturedBuffer>,
+	textures: Vec<CapturedTexture>,
+	framebuffers: Vec<CapturedFramebuffer>,
+	pipelines: Vec<CapturedPipeline>,
+	descriptor_sets: Vec<CapturedDescriptorSet>,
+	cmds: Vec<CapturedCmd>,
+}
+
+impl VulkanFrameCapture {
+	pub fn load(path: &Path) -> GoldfishResult<Self> {
+		let mut reader = BufReader::new(File::open(path).map_err(GoldfishError::Filesystem)?);
+
+		let version: u32 = bincode::deserialize_from(&mut reader).map_err(|err| capture_error(format!("{} isn't a frame capture: {}", path.display(), err)))?;
+		if version != CAPTURE_VERSION {
+			return Err(capture_error(format!(
+				"{} is a version {} capture, only version {} can be replayed",
+				path.display(),
+				version,
+				CAPTURE_VERSION
+			)));
+		}
+
+		bincode::deserialize_from(&mut reader).map_err(|err| capture_error(format!("Failed to read {}: {}", path.display(), err)))
+	}
+
+	pub fn save(&self, path: &Path) -> GoldfishResult<()> {
+		let mut writer = BufWriter::new(File::create(path).map_err(GoldfishError::Filesystem)?);
+
+		bincode::serialize_into(&mut writer, &CAPTURE_VERSION)
+			.and_then(|_| bincode::serialize_into(&mut writer, self))
+			.map_err(|err| capture_error(format!("Failed to write {}: {}", path.display(), err)))
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+
+	pub fn cmd_count(&self) -> usize {
+		self.cmds.len()
+	}
+}
+
+struct RegisteredFramebuffer {
+	width: u32,
+	height: u32,
+	render_pass: CapturedRenderPass,
+	attachments: Vec<vk::ImageView>,
+}
+
+enum RegisteredDescriptorWrite {
+	Buffer(vk::Buffer),
+	Image(vk::ImageView, ImageLayout),
+	ImageArray(Vec<vk::ImageView>, ImageLayout),
+}
+
+// Everything alive on the device that a frame could reference, keyed by raw handle. Only kept while frame capture is enabled.
+#[derive(Default)]
+pub(super) struct VulkanCaptureRegistry {
+	buffers: HashMap<vk::Buffer, CapturedBuffer>,
+	images: HashMap<vk::Image, CapturedTexture>,
+	image_views: HashMap<vk::ImageView, vk::Image>,
+	render_passes: HashMap<vk::RenderPass, CapturedRenderPass>,
+	framebuffers: HashMap<vk::Framebuffer, RegisteredFramebuffer>,
+	shaders: HashMap<vk::ShaderModule, Vec<u32>>,
+	descriptor_layouts: HashMap<vk::DescriptorSetLayout, CapturedDescriptorLayout>,
+	pipelines: HashMap<vk::Pipeline, CapturedPipeline>,
+	pipeline_layouts: HashMap<vk::PipelineLayout, vk::Pipeline>,
+	descriptor_sets: HashMap<vk::DescriptorSet, BTreeMap<u32, RegisteredDescriptorWrite>>,
+}
+
+impl VulkanCaptureRegistry {
+	pub(super) fn register_buffer(&mut self, buffer: vk::Buffer, name: &str, size: usize, usage: BufferUsage) {
+		self.buffers.insert(
+			buffer,
+			CapturedBuffer {
+				name: name.to_string(),
+				size,
+				usage: usage.bits(),
+				data: Vec::new(),
+			},
+		);
+	}
+
+	pub(super) fn register_texture(&mut self, name: &str, texture: &VulkanTexture) {
+		self.images.insert(
+			texture.image,
+			CapturedTexture {
+				name: name.to_string(),
+				width: texture.width,
+				height: texture.height,
+				format: texture.format,
+				usage: texture.usage.bits(),
+				initial_layout: texture.sampled_layout,
+			},
+		);
+		self.image_views.insert(texture.image_view, texture.image);
+	}
+
+	pub(super) fn register_render_pass(&mut self, render_pass: &VulkanRenderPass) {
+		self.render_passes.insert(
+			render_pass.raw,
+			CapturedRenderPass::Offscreen {
+				color_attachments: render_pass.color_attachments.iter().map(|&desc| desc.into()).collect(),
+				depth_attachment: render_pass.depth_attachment.map(Into::into),
+			},
+		);
+	}
+
+	fn render_pass(&self, render_pass: vk::RenderPass) -> CapturedRenderPass {
+		self.render_passes.get(&render_pass).cloned().unwrap_or(CapturedRenderPass::Output { load: false })
+	}
+
+	pub(super) fn register_framebuffer(&mut self, framebuffer: &VulkanFramebuffer, render_pass: vk::RenderPass, attachments: &[vk::ImageView]) {
+		let render_pass = self.render_pass(render_pass);
+		self.framebuffers.insert(
+			framebuffer.raw,
+			RegisteredFramebuffer {
+				width: framebuffer.width,
+				height: framebuffer.height,
+				render_pass,
+				attachments: attachments.to_vec(),
+			},
+		);
+	}
+
+	pub(super) fn register_shader(&mut self, module: vk::ShaderModule, code: &[u32]) {
+		self.shaders.insert(module, code.to_vec());
+	}
+
+	pub(super) fn register_descriptor_layout(&mut self, layout: vk::DescriptorSetLayout, bindings: &[(u32, DescriptorBindingType)]) {
+		self.descriptor_layouts.insert(layout, bindings.to_vec());
+	}
+
+	// Pipelines made from shaders or layouts the registry doesn't know about are left out, so capturing a frame that uses one fails.
+	fn register_pipeline(
+		&mut self,
+		pipeline: &VulkanPipeline,
+		shaders: &[Option<vk::ShaderModule>],
+		descriptor_layouts: &[vk::DescriptorSetLayout],
+		f: impl FnOnce(Vec<Option<Vec<u32>>>, Vec<CapturedDescriptorLayout>) -> CapturedPipeline,
+	) {
+		let Some(code) = shaders
+			.iter()
+			.map(|shader| shader.map_or(Some(None), |shader| self.shaders.get(&shader).cloned().map(Some)))
+			.collect::<Option<Vec<_>>>()
+		else {
+			return;
+		};
+		let Some(layouts) = descriptor_layouts.iter().map(|layout| self.descriptor_layouts.get(layout).cloned()).collect::<Option<Vec<_>>>() else {
+			return;
+		};
+
+		self.pipelines.insert(pipeline.pipeline, f(code, layouts));
+		self.pipeline_layouts.insert(pipeline.pipeline_layout, pipeline.pipeline);
+	}
+
+	pub(super) fn register_raster_pipeline(
+		&mut self,
+		pipeline: &VulkanPipeline,
+		shaders: [Option<vk::ShaderModule>; 3],
+		descriptor_layouts: &[vk::DescriptorSetLayout],
+		render_pass: vk::RenderPass,
+		state: CapturedRasterState,
+		specialization: &[(u32, SpecValue)],
+	) {
+		let render_pass = self.render_pass(render_pass);
+		self.register_pipeline(pipeline, &shaders, descriptor_layouts, |mut code, descriptor_layouts| {
+			let gs = code.pop().flatten();
+			let ps = code.pop().flatten();
+			CapturedPipeline::Raster {
+				vs: code.pop().flatten().unwrap_or_default(),
+				ps,
+				gs,
+				descriptor_layouts,
+				render_pass,
+				state,
+				specialization: specialization.to_vec(),
+			}
+		});
+	}
+
+	pub(super) fn register_compute_pipeline(&mut self, pipeline: &VulkanPipeline, cs: vk::ShaderModule, descriptor_layouts: &[vk::DescriptorSetLayout], specialization: &[(u32, SpecValue)]) {
+		self.register_pipeline(pipeline, &[Some(cs)], descriptor_layouts, |mut code, descriptor_layouts| CapturedPipeline::Compute {
+			cs: code.pop().flatten().unwrap_or_default(),
+			descriptor_layouts,
+			specialization: specialization.to_vec(),
+		});
+	}
+
+	pub(super) fn reset_descriptor_set(&mut self, set: vk::DescriptorSet) {
+		self.descriptor_sets.remove(&set);
+	}
+
+	pub(super) fn write_descriptor_set(
+		&mut self,
+		set: vk::DescriptorSet,
+		buffers: &[(u32, &VulkanBuffer)],
+		images: &[(u32, &VulkanTexture, ImageLayout)],
+		image_arrays: &[(u32, &[&VulkanTexture], ImageLayout)],
+	) {
+		let writes = self.descriptor_sets.entry(set).or_default();
+
+		for &(binding, buffer) in buffers {
+			writes.insert(binding, RegisteredDescriptorWrite::Buffer(buffer.raw));
+		}
+
+		for &(binding, texture, layout) in images {
+			writes.insert(binding, RegisteredDescriptorWrite::Image(texture.image_view, layout));
+		}
+
+		for &(binding, textures, layout) in image_arrays {
+			let mut views = textures.iter().map(|texture| texture.image_view).collect::<Vec<_>>();
+			if let Some(RegisteredDescriptorWrite::ImageArray(old_views, _)) = writes.get(&binding) {
+				views.extend(old_views.iter().skip(views.len()));
+			}
+			writes.insert(binding, RegisteredDescriptorWrite::ImageArray(views, layout));
+		}
+	}
+
+	pub(super) fn forget(&mut self, destructor: &VulkanDestructor) {
+		match *destructor {
+			VulkanDestructor::Buffer(buffer) => {
+				self.buffers.remove(&buffer);
+			}
+			VulkanDestructor::Image(image) => {
+				self.images.remove(&image);
+			}
+			VulkanDestructor::ImageView(view) => {
+				self.image_views.remove(&view);
+			}
+			VulkanDestructor::RenderPass(render_pass) => {
+				self.render_passes.remove(&render_pass);
+			}
+			VulkanDestructor::Shader(shader) => {
+				self.shaders.remove(&shader);
+			}
+			VulkanDestructor::Pipeline(pipeline) => {
+				self.pipelines.remove(&pipeline);
+			}
+			VulkanDestructor::PipelineLayout(pipeline_layout) => {
+				self.pipeline_layouts.remove(&pipeline_layout);
+			}
+			VulkanDestructor::DescriptorSetLayout(layout) => {
+				self.descriptor_layouts.remove(&layout);
+			}
+			VulkanDestructor::Framebuffer(framebuffer) => {
+				self.framebuffers.remove(&framebuffer);
+			}
+			_ => {}
+		}
+	}
+}
+
+fn image_layout_from_vk(layout: vk::ImageLayout) -> Option<ImageLayout> {
+	[
+		ImageLayout::General,
+		ImageLayout::ColorAttachmentOptimal,
+		ImageLayout::DepthStencilAttachmentOptimal,
+		ImageLayout::DepthStencilReadOnlyOptimal,
+		ImageLayout::ShaderReadOnlyOptimal,
+		ImageLayout::TransferSrcOptimal,
+		ImageLayout::TransferDstOptimal,
+		ImageLayout::PresentSrc,
+	]
+	.into_iter()
+	.find(|&candidate| vk::ImageLayout::from(candidate) == layout)
+}
+
+// The swapchain objects of the frame being captured, all of which turn into the offscreen output when replaying.
+struct CaptureOutput {
+	image: vk::Image,
+	storage_view: Option<vk::ImageView>,
+	framebuffer: vk::Framebuffer,
+	render_pass: vk::RenderPass,
+	load_render_pass: vk::RenderPass,
+}
+
+// Builds up a capture one command at a time, resources get added to its tables the first time a command references them.
+struct VulkanCaptureBuilder<'a> {
+	registry: &'a VulkanCaptureRegistry,
+	output: CaptureOutput,
+	capture: VulkanFrameCapture,
+	buffer_handles: Vec<vk::Buffer>,
+	buffers: HashMap<vk::Buffer, usize>,
+	textures: HashMap<vk::Image, usize>,
+	transitioned: HashSet<usize>,
+	framebuffers: HashMap<vk::Framebuffer, usize>,
+	pipelines: HashMap<vk::Pipeline, usize>,
+	descriptor_sets: HashMap<vk::DescriptorSet, usize>,
+}
+
+impl<'a> VulkanCaptureBuilder<'a> {
+	fn buffer(&mut self, buffer: vk::Buffer) -> GoldfishResult<usize> {
+		if let Some(&index) = self.buffers.get(&buffer) {
+			return Ok(index);
+		}
+
+		let desc = self.registry.buffers.get(&buffer).ok_or_else(|| unknown_handle("Buffer", buffer))?;
+		self.capture.buffers.push(desc.clone());
+		self.buffer_handles.push(buffer);
+		self.buffers.insert(buffer, self.capture.buffers.len() - 1);
+
+		Ok(self.capture.buffers.len() - 1)
+	}
+
+	fn image(&mut self, image: vk::Image) -> GoldfishResult<CapturedImage> {
+		if image == self.output.image {
+			return Ok(CapturedImage::Output);
+		}
+
+		if let Some(&index) = self.textures.get(&image) {
+			return Ok(CapturedImage::Texture(index));
+		}
+
+		let desc = self.registry.images.get(&image).ok_or_else(|| unknown_handle("Image", image))?;
+		self.capture.textures.push(desc.clone());
+		self.textures.insert(image, self.capture.textures.len() - 1);
+
+		Ok(CapturedImage::Texture(self.capture.textures.len() - 1))
+	}
+
+	fn view(&mut self, view: vk::ImageView) -> GoldfishResult<CapturedImage> {
+		if Some(view) == self.output.storage_view {
+			return Ok(CapturedImage::Output);
+		}
+
+		let image = *self.registry.image_views.get(&view).ok_or_else(|| unknown_handle("Image view", view))?;
+		self.image(image)
+	}
+
+	fn render_pass(&self, render_pass: vk::RenderPass) -> GoldfishResult<CapturedRenderPass> {
+		if render_pass == self.output.render_pass || render_pass == self.output.load_render_pass {
+			return Ok(CapturedRenderPass::Output {
+				load: render_pass == self.output.load_render_pass,
+			});
+		}
+
+		self.registry.render_passes.get(&render_pass).cloned().ok_or_else(|| unknown_handle("Render pass", render_pass))
+	}
+
+	fn framebuffer(&mut self, framebuffer: vk::Framebuffer) -> GoldfishResult<Option<usize>> {
+		if framebuffer == self.output.framebuffer {
+			return Ok(None);
+		}
+
+		if let Some(&index) = self.framebuffers.get(&framebuffer) {
+			return Ok(Some(index));
+		}
+
+		let registry = self.registry;
+		let desc = registry.framebuffers.get(&framebuffer).ok_or_else(|| unknown_handle("Framebuffer", framebuffer))?;
+		let attachments = desc.attachments.iter().map(|&view| self.view(view)).collect::<GoldfishResult<Vec<_>>>()?;
+		self.capture.framebuffers.push(CapturedFramebuffer {
+			width: desc.width,
+			height: desc.height,
+			render_pass: desc.render_pass.clone(),
+			attachments,
+		});
+		self.framebuffers.insert(framebuffer, self.capture.framebuffers.len() - 1);
+
+		Ok(Some(self.capture.framebuffers.len() - 1))
+	}
+
+	fn pipeline(&mut self, pipeline: vk::Pipeline) -> GoldfishResult<usize> {
+		if let Some(&index) = self.pipelines.get(&pipeline) {
+			return Ok(index);
+		}
+
+		let desc = self.registry.pipelines.get(&pipeline).ok_or_else(|| unknown_handle("Pipeline", pipeline))?;
+		self.capture.pipelines.push(desc.clone());
+		self.pipelines.insert(pipeline, self.capture.pipelines.len() - 1);
+
+		Ok(self.capture.pipelines.len() - 1)
+	}
+
+	fn pipeline_for_layout(&mut self, pipeline_layout: vk::PipelineLayout) -> GoldfishResult<usize> {
+		let pipeline = *self.registry.pipeline_layouts.get(&pipeline_layout).ok_or_else(|| unknown_handle("Pipeline layout", pipeline_layout))?;
+		self.pipeline(pipeline)
+	}
+
+	fn descriptor_set(&mut self, descriptor_set: vk::DescriptorSet, pipeline: usize, set: u32) -> GoldfishResult<usize> {
+		if let Some(&index) = self.descriptor_sets.get(&descriptor_set) {
+			return Ok(index);
+		}
+
+		let registry = self.registry;
+		let writes = registry.descriptor_sets.get(&descriptor_set).ok_or_else(|| unknown_handle("Descriptor set", descriptor_set))?;
+		let writes = writes
+			.iter()
+			.map(|(&binding, write)| {
+				let write = match write {
+					RegisteredDescriptorWrite::Buffer(buffer) => CapturedDescriptorWrite::Buffer(self.buffer(*buffer)?),
+					RegisteredDescriptorWrite::Image(view, layout) => CapturedDescriptorWrite::Image(self.view(*view)?, *layout),
+					RegisteredDescriptorWrite::ImageArray(views, layout) => CapturedDescriptorWrite::ImageArray(views.iter().map(|&view| self.view(view)).collect::<GoldfishResult<_>>()?, *layout),
+				};
+				Ok((binding, write))
+			})
+			.collect::<GoldfishResult<Vec<_>>>()?;
+
+		self.capture.descriptor_sets.push(CapturedDescriptorSet { pipeline, set, writes });
+		self.descriptor_sets.insert(descriptor_set, self.capture.descriptor_sets.len() - 1);
+
+		Ok(self.capture.descriptor_sets.len() - 1)
+	}
+
+	fn image_barrier(&mut self, barrier: &vk::ImageMemoryBarrier) -> GoldfishResult<CapturedImageBarrier> {
+		let image = self.image(barrier.image)?;
+
+		if let CapturedImage::Texture(index) = image {
+			if self.transitioned.insert(index) {
+				if let Some(layout) = image_layout_from_vk(barrier.old_layout) {
+					self.capture.textures[index].initial_layout = layout;
+				}
+			}
+		}
+
+		Ok(CapturedImageBarrier {
+			src_access_mask: barrier.src_access_mask.as_raw(),
+			dst_access_mask: barrier.dst_access_mask.as_raw(),
+			old_layout: barrier.old_layout.as_raw(),
+			new_layout: barrier.new_layout.as_raw(),
+			image,
+			range: barrier.subresource_range.into(),
+		})
+	}
+
+	fn cmd(&mut self, cmd: &VulkanRasterCmd, barriers: &VulkanBarrierArena) -> GoldfishResult<Option<CapturedCmd>> {
+		Ok(Some(match *cmd {
+			VulkanRasterCmd::BindPipeline { pipeline, .. } => CapturedCmd::BindPipeline { pipeline: self.pipeline(pipeline)? },
+			VulkanRasterCmd::BindVertexBuffer { first_binding, buffer, offset } => CapturedCmd::BindVertexBuffers {
+				first_binding,
+				buffers: vec![self.buffer(buffer)?],
+				offsets: vec![offset],
+			},
+			VulkanRasterCmd::BindVertexBuffers {
+				first_binding,
+				ref buffers,
+				ref offsets,
+			} => CapturedCmd::BindVertexBuffers {
+				first_binding,
+				buffers: buffers.iter().map(|&buffer| self.buffer(buffer)).collect::<GoldfishResult<_>>()?,
+				offsets: offsets.clone(),
+			},
+			VulkanRasterCmd::BindIndexBuffer { buffer, offset, index_type } => CapturedCmd::BindIndexBuffer {
+				buffer: self.buffer(buffer)?,
+				offset,
+				index_type: index_type.as_raw(),
+			},
+			VulkanRasterCmd::SetViewport { viewport } => CapturedCmd::SetViewport {
+				viewport: [viewport.x, viewport.y, viewport.width, viewport.height, viewport.min_depth, viewport.max_depth],
+			},
+			VulkanRasterCmd::SetScissor { scissor } => CapturedCmd::SetScissor { scissor: scissor.into() },
+			VulkanRasterCmd::BeginRenderPass {
+				render_pass,
+				framebuffer,
+				render_area,
+				ref clear_values,
+				..
+			} => CapturedCmd::BeginRenderPass {
+				render_pass: self.render_pass(render_pass)?,
+				framebuffer: self.framebuffer(framebuffer)?,
+				render_area: render_area.into(),
+				clear_values: clear_values.iter().map(|value| unsafe { value.color.uint32 }).collect(),
+			},
+			VulkanRasterCmd::EndRenderPass {} => CapturedCmd::EndRenderPass,
+			VulkanRasterCmd::DrawIndexed {
+				index_count,
+				instance_count,
+				first_index,
+				vertex_offset,
+				first_instance,
+			} => CapturedCmd::DrawIndexed {
+				index_count,
+				instance_count,
+				first_index,
+				vertex_offset,
+				first_instance,
+			},
+			VulkanRasterCmd::Draw {
+				vertex_count,
+				instance_count,
+				first_vertex,
+				first_instance,
+			} => CapturedCmd::Draw {
+				vertex_count,
+				instance_count,
+				first_vertex,
+				first_instance,
+			},
+			VulkanRasterCmd::DrawIndirect { buffer, offset, draw_count, stride } => CapturedCmd::DrawIndirect {
+				buffer: self.buffer(buffer)?,
+				offset,
+				draw_count,
+				stride,
+			},
+			VulkanRasterCmd::DrawIndexedIndirect { buffer, offset, draw_count, stride } => CapturedCmd::DrawIndexedIndirect {
+				buffer: self.buffer(buffer)?,
+				offset,
+				draw_count,
+				stride,
+			},
+			VulkanRasterCmd::BindDescriptor {
+				pipeline_layout,
+				first_set,
+				descriptor_set,
+				..
+			} => {
+				let pipeline = self.pipeline_for_layout(pipeline_layout)?;
+				CapturedCmd::BindDescriptor {
+					pipeline,
+					first_set,
+					descriptor_set: self.descriptor_set(descriptor_set, pipeline, first_set)?,
+				}
+			}
+			VulkanRasterCmd::PipelineBarrier {
+				src_stage_mask,
+				dst_stage_mask,
+				dependency_flags,
+				ref memory_barriers,
+				ref buffer_memory_barriers,
+				ref image_memory_barriers,
+			} => CapturedCmd::PipelineBarrier {
+				src_stage_mask: src_stage_mask.as_raw(),
+				dst_stage_mask: dst_stage_mask.as_raw(),
+				dependency_flags: dependency_flags.as_raw(),
+				memory_barriers: barriers.memory_barriers[memory_barriers.clone()]
+					.iter()
+					.map(|barrier| CapturedMemoryBarrier {
+						src_access_mask: barrier.src_access_mask.as_raw(),
+						dst_access_mask: barrier.dst_access_mask.as_raw(),
+					})
+					.collect(),
+				buffer_memory_barriers: barriers.buffer_memory_barriers[buffer_memory_barriers.clone()]
+					.iter()
+					.map(|barrier| {
+						Ok(CapturedBufferBarrier {
+							src_access_mask: barrier.src_access_mask.as_raw(),
+							dst_access_mask: barrier.dst_access_mask.as_raw(),
+							buffer: self.buffer(barrier.buffer)?,
+							offset: barrier.offset,
+							size: barrier.size,
+						})
+					})
+					.collect::<GoldfishResult<_>>()?,
+				image_memory_barriers: barriers.image_memory_barriers[image_memory_barriers.clone()]
+					.iter()
+					.map(|barrier| self.image_barrier(barrier))
+					.collect::<GoldfishResult<_>>()?,
+			},
+			VulkanRasterCmd::Dispatch {
+				group_count_x,
+				group_count_y,
+				group_count_z,
+			} => CapturedCmd::Dispatch {
+				group_count_x,
+				group_count_y,
+				group_count_z,
+			},
+			VulkanRasterCmd::CopyBuffer { src, dst, region } => CapturedCmd::CopyBuffer {
+				src: self.buffer(src)?,
+				dst: self.buffer(dst)?,
+				src_offset: region.src_offset,
+				dst_offset: region.dst_offset,
+				size: region.size,
+			},
+			VulkanRasterCmd::FillBuffer { buffer, offset, size, data } => CapturedCmd::FillBuffer {
+				buffer: self.buffer(buffer)?,
+				offset,
+				size,
+				data,
+			},
+			VulkanRasterCmd::ClearColorImage { image, layout, value, range } => CapturedCmd::ClearColorImage {
+				image: self.image(image)?,
+				layout: layout.as_raw(),
+				value: unsafe { value.uint32 },
+				range: range.into(),
+			},
+			VulkanRasterCmd::ClearDepthStencilImage { image, layout, value, range } => CapturedCmd::ClearDepthStencilImage {
+				image: self.image(image)?,
+				layout: layout.as_raw(),
+				depth: value.depth,
+				stencil: value.stencil,
+				range: range.into(),
+			},
+			VulkanRasterCmd::CopyImageToBuffer { src, src_layout, dst, region } => CapturedCmd::CopyImageToBuffer {
+				src: self.image(src)?,
+				src_layout: src_layout.as_raw(),
+				dst: self.buffer(dst)?,
+				region: region.into(),
+			},
+			VulkanRasterCmd::BlitImage {
+				src,
+				src_layout,
+				dst,
+				dst_layout,
+				region,
+				filter,
+			} => CapturedCmd::BlitImage {
+				src: self.image(src)?,
+				src_layout: src_layout.as_raw(),
+				dst: self.image(dst)?,
+				dst_layout: dst_layout.as_raw(),
+				region: region.into(),
+				filter: filter.as_raw(),
+			},
+			VulkanRasterCmd::PushConstants {
+				pipeline_layout,
+				stage_flags,
+				offset,
+				ref data,
+			} => CapturedCmd::PushConstants {
+				pipeline: self.pipeline_for_layout(pipeline_layout)?,
+				stage_flags: stage_flags.as_raw(),
+				offset,
+				data: data.clone(),
+			},
+			VulkanRasterCmd::BeginDebugLabel { ref name } => CapturedCmd::BeginDebugLabel {
+				name: name.to_string_lossy().into_owned(),
+			},
+			VulkanRasterCmd::EndDebugLabel => CapturedCmd::EndDebugLabel,
+			VulkanRasterCmd::SetCheckpoint { .. }
+			| VulkanRasterCmd::WriteBufferMarker { .. }
+			| VulkanRasterCmd::ResetQueryPool { .. }
+			| VulkanRasterCmd::BeginQuery { .. }
+			| VulkanRasterCmd::EndQuery { .. }
+			| VulkanRasterCmd::WriteTimestamp { .. }
+			| VulkanRasterCmd::None => return Ok(None),
+		}))
+	}
+}
+
+pub(super) struct PendingCapture {
+	capture: VulkanFrameCapture,
+	buffers: Vec<vk::Buffer>,
+}
+
+impl VulkanDevice {
+	pub(super) fn with_capture_registry(&self, f: impl FnOnce(&mut VulkanCaptureRegistry)) {
+		if let Some(registry) = &self.capture_registry {
+			f(&mut registry.lock().unwrap());
+		}
+	}
+
+	pub fn frame_capture_enabled(&self) -> bool {
+		self.capture_registry.is_some()
+	}
+
+	fn read_capture_buffers(&self, capture: &mut VulkanFrameCapture, buffers: &[vk::Buffer]) -> GoldfishResult<()> {
+		let total_size = capture.buffers.iter().map(|buffer| buffer.size).sum::<usize>();
+		if total_size == 0 {
+			return Ok(());
+		}
+
+		self.wait_idle();
+
+		let mut staging = self.create_empty_buffer("Frame Capture Staging", total_size, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None)?;
+
+		let mut upload_context = self.create_upload_context();
+		upload_context.wait_submit(|device, cmd| unsafe {
+			let mut offset = 0;
+			for (desc, &buffer) in capture.buffers.iter().zip(buffers) {
+				if desc.size > 0 {
+					device.cmd_copy_buffer(
+						cmd,
+						buffer,
+						staging.raw,
+						&[vk::BufferCopy {
+							src_offset: 0,
+							dst_offset: offset,
+							size: desc.size as u64,
+						}],
+					);
+				}
+				offset += desc.size as u64;
+			}
+		});
+		self.destroy_upload_context(upload_context);
+
+		let mapped = staging.allocation.mapped_slice().expect("Failed to map allocation!");
+		let mut offset = 0;
+		for desc in capture.buffers.iter_mut() {
+			desc.data = mapped[offset..offset + desc.size].to_vec();
+			offset += desc.size;
+		}
+
+		staging.guard.mark_destroyed();
+		self.queue_destruction(&mut [VulkanDestructor::Buffer(staging.raw), VulkanDestructor::Allocation(staging.allocation)]);
+
+		Ok(())
+	}
+
+	// Everything the capture references is recreated, and every texture starts out zeroed since only descriptions are captured.
+	pub fn replay_capture(&self, capture: &VulkanFrameCapture) -> GoldfishResult<VulkanReplayOutput> {
+		let mut replay = VulkanReplay {
+			device: self.clone(),
+			capture,
+			upload_context: self.create_upload_context(),
+			output: None,
+			output_render_passes: Vec::new(),
+			output_framebuffer: None,
+			render_passes: HashMap::new(),
+			buffers: Vec::new(),
+			textures: Vec::new(),
+			framebuffers: Vec::new(),
+			pipelines: Vec::new(),
+			set_layouts: Vec::new(),
+			descriptor_pools: Vec::new(),
+			descriptor_sets: Vec::new(),
+		};
+
+		let result = replay.create_resources().and_then(|_| replay.run());
+		replay.destroy();
+
+		result
+	}
+}
+
+impl VulkanGraphicsContext {
+	// The file is written once the next frame ends, anything that goes wrong from then on is logged. Needs frame_capture in the config.
+	pub fn capture_next_frame(&mut self, path: impl Into<PathBuf>) -> GoldfishResult<()> {
+		if !self.swapchain.device.frame_capture_enabled() {
+			return Err(capture_error("frame_capture is turned off in the config".to_string()));
+		}
+
+		self.requested_capture = Some(path.into());
+		Ok(())
+	}
+
+	pub(super) fn begin_capture(&self, frame_info: &FrameInfo) -> GoldfishResult<PendingCapture> {
+		let registry = self.swapchain.device.capture_registry.as_ref().expect("Frame capture is not enabled!").lock().unwrap();
+
+		let mut builder = VulkanCaptureBuilder {
+			registry: &registry,
+			output: CaptureOutput {
+				image: frame_info.output_image,
+				storage_view: self.swapchain.storage_texture(frame_info.image_index).map(|texture| texture.image_view),
+				framebuffer: frame_info.output_framebuffer,
+				render_pass: self.swapchain.render_pass,
+				load_render_pass: self.swapchain.load_render_pass,
+			},
+			capture: VulkanFrameCapture {
+				width: self.swapchain.extent.width,
+				height: self.swapchain.extent.height,
+				output_is_srgb: self.output_is_srgb(),
+				buffers: Vec::new(),
+				textures: Vec::new(),
+				framebuffers: Vec::new(),
+				pipelines: Vec::new(),
+				descriptor_sets: Vec::new(),
+				cmds: Vec::new(),
+			},
+			buffer_handles: Vec::new(),
+			buffers: HashMap::new(),
+			textures: HashMap::new(),
+			transitioned: HashSet::new(),
+			framebuffers: HashMap::new(),
+			pipelines: HashMap::new(),
+			descriptor_sets: HashMap::new(),
+		};
+
+		let barriers = self.barriers.borrow();
+		for cmd in self.cmd_lists.borrow().iter().flatten() {
+			if let Some(cmd) = builder.cmd(cmd, &barriers)? {
+				builder.capture.cmds.push(cmd);
+			}
+		}
+
+		Ok(PendingCapture {
+			capture: builder.capture,
+			buffers: builder.buffer_handles,
+		})
+	}
+
+	// Run once the frame's late updates are in, right before it's submitted, so the buffers hold exactly what the frame is about to read.
+	pub(super) fn finish_capture(&self, path: &Path, pending: GoldfishResult<PendingCapture>) {
+		let result = pending.and_then(|mut pending| {
+			self.swapchain.device.read_capture_buffers(&mut pending.capture, &pending.buffers)?;
+			pending.capture.save(path)
+		});
+
+		match result {
+			Ok(()) => info!("Captured frame {} to {}", self.frame_index, path.display()),
+			Err(err) => error!("Failed to capture frame {} to {}: {}", self.frame_index, path.display(), err),
+		}
+	}
+}
+
+// What replay_capture rendered, tightly packed RGBA8 rows from the top left.
+pub struct VulkanReplayOutput {
+	pub width: u32,
+	pub height: u32,
+	pub pixels: Vec<u8>,
+}
+
+impl VulkanReplayOutput {
+	pub fn mismatched_pixels(&self, expected: &[u8], tolerance: u8) -> usize {
+		assert_eq!(expected.len(), self.pixels.len(), "The expected image has to be the same size as the replay!");
+
+		self.pixels
+			.chunks_exact(4)
+			.zip(expected.chunks_exact(4))
+			.filter(|(actual, expected)| actual.iter().zip(expected.iter()).any(|(a, e)| a.abs_diff(*e) > tolerance))
+			.count()
+	}
+}
+
+struct VulkanReplay<'a> {
+	device: VulkanDevice,
+	capture: &'a VulkanFrameCapture,
+	upload_context: VulkanUploadContext,
+	output: Option<VulkanTexture>,
+	output_render_passes: Vec<vk::RenderPass>,
+	output_framebuffer: Option<VulkanFramebuffer>,
+	render_passes: HashMap<CapturedRenderPass, VulkanRenderPass>,
+	buffers: Vec<VulkanBuffer>,
+	textures: Vec<VulkanTexture>,
+	framebuffers: Vec<VulkanFramebuffer>,
+	pipelines: Vec<VulkanPipeline>,
+	set_layouts: Vec<Vec<vk::DescriptorSetLayout>>,
+	descriptor_pools: Vec<vk::DescriptorPool>,
+	descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl VulkanReplay<'_> {
+	fn output(&self) -> &VulkanTexture {
+		self.output.as_ref().expect("The replay output has not been created!")
+	}
+
+	fn texture(&self, image: CapturedImage) -> &VulkanTexture {
+		match image {
+			CapturedImage::Output => self.output(),
+			CapturedImage::Texture(index) => &self.textures[index],
+		}
+	}
+
+	fn render_pass(&mut self, render_pass: &CapturedRenderPass) -> GoldfishResult<vk::RenderPass> {
+		match render_pass {
+			CapturedRenderPass::Output { load } => Ok(self.output_render_passes[*load as usize]),
+			CapturedRenderPass::Offscreen { color_attachments, depth_attachment } => {
+				if let Some(existing) = self.render_passes.get(render_pass) {
+					return Ok(existing.raw);
+				}
+
+				let color_attachments = color_attachments.iter().map(|&attachment| attachment.into()).collect::<Vec<AttachmentDescription>>();
+				let created = self.device.create_render_pass(&color_attachments, depth_attachment.map(Into::into))?;
+				let raw = created.raw;
+				self.render_passes.insert(render_pass.clone(), created);
+
+				Ok(raw)
+			}
+		}
+	}
+
+	fn create_resources(&mut self) -> GoldfishResult<()> {
+		let capture = self.capture;
+
+		let output_format = if capture.output_is_srgb { TextureFormat::SRGBA8 } else { TextureFormat::RGBA8UNorm };
+		let mut output_usage = TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC | TextureUsage::TRANSFER_DST;
+		if !capture.output_is_srgb {
+			output_usage |= TextureUsage::STORAGE;
+		}
+
+		self.output = Some(self.device.create_texture("Replay Output", capture.width, capture.height, output_format, output_usage)?);
+
+		let vk_format = output_format.to_vk(&self.device);
+		self.output_render_passes = vec![
+			VulkanSwapchain::create_output_render_pass(&self.device, vk_format, vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED),
+			VulkanSwapchain::create_output_render_pass(&self.device, vk_format, vk::AttachmentLoadOp::LOAD, vk::ImageLayout::PRESENT_SRC_KHR),
+		];
+
+		let output_render_pass = VulkanRenderPass {
+			raw: self.output_render_passes[0],
+			color_attachments: Vec::new(),
+			depth_attachment: None,
+		};
+		self.output_framebuffer = Some(self.device.create_framebuffer(capture.width, capture.height, &output_render_pass, &[self.output()]));
+
+		for buffer in &capture.buffers {
+			let usage = BufferUsage::from_bits_truncate(buffer.usage) | BufferUsage::TransferDst;
+			self.buffers.push(self.device.create_empty_buffer(&buffer.name, buffer.size, MemoryLocation::GpuOnly, usage, None)?);
+
+			if !buffer.data.is_empty() {
+				self.upload_context.write_buffer(&buffer.name, &self.buffers[self.buffers.len() - 1], 0, &buffer.data)?;
+			}
+		}
+
+		for texture in &capture.textures {
+			let usage = TextureUsage::from_bits_truncate(texture.usage) | TextureUsage::TRANSFER_DST;
+
+			if let Some((_, size)) = texture.format.compressed_mip_size(texture.width, texture.height, 0) {
+				let created = self
+					.upload_context
+					.create_texture(&texture.name, texture.width, texture.height, texture.format, usage, Some(&vec![0; size]))?;
+				self.textures.push(created);
+				continue;
+			}
+
+			self.textures.push(self.device.create_texture(&texture.name, texture.width, texture.height, texture.format, usage)?);
+
+			let clear_value = if texture.format == TextureFormat::Depth {
+				ClearValue::DepthStencil { depth: 0.0, stencil: 0 }
+			} else {
+				ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 }
+			};
+			self.upload_context.clear_texture(&self.textures[self.textures.len() - 1], clear_value, texture.initial_layout);
+		}
+
+		for framebuffer in &capture.framebuffers {
+			let render_pass = VulkanRenderPass {
+				raw: self.render_pass(&framebuffer.render_pass)?,
+				color_attachments: Vec::new(),
+				depth_attachment: None,
+			};
+			let attachments = framebuffer.attachments.iter().map(|&image| self.texture(image)).collect::<Vec<_>>();
+			let created = self.device.create_framebuffer(framebuffer.width, framebuffer.height, &render_pass, &attachments);
+			self.framebuffers.push(created);
+		}
+
+		for pipeline in &capture.pipelines {
+			let set_layouts = pipeline
+				.descriptor_layouts()
+				.iter()
+				.map(|bindings| self.device.create_descriptor_layout(bindings, pipeline.stage_flags()))
+				.collect::<Vec<_>>();
+			self.set_layouts.push(set_layouts.clone());
+
+			let mut shaders = Vec::new();
+			let mut result = Ok(());
+			for code in pipeline.shaders() {
+				match self.device.create_shader_with_code(code) {
+					Ok(shader) => shaders.push(shader),
+					Err(err) => {
+						result = Err(err);
+						break;
+					}
+				}
+			}
+
+			let created = result.and_then(|_| self.create_pipeline(pipeline, &shaders, &set_layouts));
+			for shader in shaders {
+				self.device.destroy_shader(shader);
+			}
+			self.pipelines.push(created?);
+		}
+
+		for set in &capture.descriptor_sets {
+			let bindings = &capture.pipelines[set.pipeline].descriptor_layouts()[set.set as usize];
+			let layout = self.set_layouts[set.pipeline][set.set as usize];
+
+			let mut pool_sizes = HashMap::<vk::DescriptorType, u32>::new();
+			for (_, ty) in bindings {
+				*pool_sizes.entry((*ty).into()).or_default() += ty.descriptor_count();
+			}
+
+			let flags = if bindings.iter().any(|(_, ty)| ty.is_bindless()) {
+				vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+			} else {
+				vk::DescriptorPoolCreateFlags::empty()
+			};
+
+			let pool = unsafe {
+				self.device.raw.create_descriptor_pool(
+					&vk::DescriptorPoolCreateInfo::builder()
+						.pool_sizes(&pool_sizes.into_iter().map(|(ty, descriptor_count)| vk::DescriptorPoolSize { ty, descriptor_count }).collect::<Vec<_>>())
+						.max_sets(1)
+						.flags(flags),
+					None,
+				)
+			}
+			.map_err(|err| GoldfishError::Render(err.into()))?;
+			self.descriptor_pools.push(pool);
+
+			let descriptor_set = unsafe {
+				self.device
+					.raw
+					.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder().set_layouts(&[layout]).descriptor_pool(pool))
+			}
+			.map_err(|err| GoldfishError::Render(err.into()))?[0];
+			self.descriptor_sets.push(descriptor_set);
+
+			self.write_descriptor_set(descriptor_set, bindings, &set.writes)?;
+		}
+
+		Ok(())
+	}
+
+	fn create_pipeline(&mut self, pipeline: &CapturedPipeline, shaders: &[VulkanShader], set_layouts: &[vk::DescriptorSetLayout]) -> GoldfishResult<VulkanPipeline> {
+		match pipeline {
+			CapturedPipeline::Raster {
+				ps,
+				gs,
+				render_pass,
+				state,
+				specialization,
+				..
+			} => {
+				let render_pass = self.render_pass(render_pass)?;
+
+				let vertex_input_info = VertexInputInfo {
+					bindings: Box::leak(state.vertex_attributes.clone().into_boxed_slice()),
+					stride: state.vertex_stride,
+				};
+
+				self.device.create_raster_pipeline_impl(
+					&shaders[0],
+					ps.as_ref().map(|_| &shaders[1]),
+					gs.as_ref().map(|_| &shaders[shaders.len() - 1]),
+					set_layouts,
+					render_pass,
+					state.color_attachments,
+					state.depth_compare_op,
+					state.depth_write,
+					state.depth_bias,
+					state.blend_mode,
+					state.face_cull,
+					state.push_constant_bytes,
+					vertex_input_info,
+					state.polygon_mode,
+					specialization,
+				)
+			}
+			CapturedPipeline::Compute { specialization, .. } => self.device.create_compute_pipeline(&shaders[0], set_layouts, specialization),
+		}
+	}
+
+	fn write_descriptor_set(&self, descriptor_set: vk::DescriptorSet, bindings: &CapturedDescriptorLayout, writes: &[(u32, CapturedDescriptorWrite)]) -> GoldfishResult<()> {
+		let image_info = |image: CapturedImage, layout: ImageLayout| {
+			let texture = self.texture(image);
+			vk::DescriptorImageInfo {
+				sampler: texture.sampler,
+				image_view: texture.image_view,
+				image_layout: layout.into(),
+			}
+		};
+
+		let infos = writes
+			.iter()
+			.map(|(_, write)| match write {
+				CapturedDescriptorWrite::Buffer(index) => {
+					let buffer = &self.buffers[*index];
+					(
+						vec![vk::DescriptorBufferInfo {
+							buffer: buffer.raw,
+							offset: 0,
+							range: buffer.size as u64,
+						}],
+						Vec::new(),
+					)
+				}
+				CapturedDescriptorWrite::Image(image, layout) => (Vec::new(), vec![image_info(*image, *layout)]),
+				CapturedDescriptorWrite::ImageArray(images, layout) => (Vec::new(), images.iter().map(|&image| image_info(image, *layout)).collect()),
+			})
+			.collect::<Vec<_>>();
+
+		let descriptor_writes = writes
+			.iter()
+			.zip(infos.iter())
+			.filter(|(_, (buffer_infos, image_infos))| !buffer_infos.is_empty() || !image_infos.is_empty())
+			.map(|((binding, _), (buffer_infos, image_infos))| {
+				let ty = bindings
+					.iter()
+					.find(|(other, _)| other == binding)
+					.map(|&(_, ty)| ty)
+					.ok_or_else(|| capture_error(format!("Binding {} was written but isn't in the descriptor set's layout", binding)))?;
+
+				let write = vk::WriteDescriptorSet::builder().dst_set(descriptor_set).dst_binding(*binding).descriptor_type(ty.into());
+				let write = if buffer_infos.is_empty() {
+					write.image_info(image_infos)
+				} else {
+					write.buffer_info(buffer_infos)
+				};
+
+				Ok(write.build())
+			})
+			.collect::<GoldfishResult<Vec<_>>>()?;
+
+		unsafe { self.device.raw.update_descriptor_sets(&descriptor_writes, &[]) };
+
+		Ok(())
+	}
+
+	fn image(&self, image: CapturedImage) -> vk::Image {
+		self.texture(image).image
+	}
+
+	fn raster_cmd(&mut self, cmd: &CapturedCmd, barriers: &mut VulkanBarrierArena) -> GoldfishResult<VulkanRasterCmd> {
+		Ok(match *cmd {
+			CapturedCmd::BindPipeline { pipeline } => VulkanRasterCmd::BindPipeline {
+				bind_point: self.capture.pipelines[pipeline].bind_point(),
+				pipeline: self.pipelines[pipeline].pipeline,
+			},
+			CapturedCmd::BindVertexBuffers {
+				first_binding,
+				ref buffers,
+				ref offsets,
+			} => VulkanRasterCmd::BindVertexBuffers {
+				first_binding,
+				buffers: buffers.iter().map(|&buffer| self.buffers[buffer].raw).collect(),
+				offsets: offsets.clone(),
+			},
+			CapturedCmd::BindIndexBuffer { buffer, offset, index_type } => VulkanRasterCmd::BindIndexBuffer {
+				buffer: self.buffers[buffer].raw,
+				offset,
+				index_type: vk::IndexType::from_raw(index_type),
+			},
+			CapturedCmd::SetViewport {
+				viewport: [x, y, width, height, min_depth, max_depth],
+			} => VulkanRasterCmd::SetViewport {
+				viewport: vk::Viewport {
+					x,
+					y,
+					width,
+					height,
+					min_depth,
+					max_depth,
+				},
+			},
+			CapturedCmd::SetScissor { scissor } => VulkanRasterCmd::SetScissor { scissor: scissor.into() },
+			CapturedCmd::BeginRenderPass {
+				ref render_pass,
+				framebuffer,
+				render_area,
+				ref clear_values,
+			} => VulkanRasterCmd::BeginRenderPass {
+				render_pass: self.render_pass(render_pass)?,
+				framebuffer: match framebuffer {
+					Some(index) => self.framebuffers[index].raw,
+					None => self.output_framebuffer.as_ref().expect("The replay output has not been created!").raw,
+				},
+				render_area: render_area.into(),
+				clear_values: clear_values
+					.iter()
+					.map(|&uint32| vk::ClearValue {
+						color: vk::ClearColorValue { uint32 },
+					})
+					.collect(),
+				subpass_contents: vk::SubpassContents::INLINE,
+			},
+			CapturedCmd::EndRenderPass => VulkanRasterCmd::EndRenderPass {},
+			CapturedCmd::DrawIndexed {
+				index_count,
+				instance_count,
+				first_index,
+				vertex_offset,
+				first_instance,
+			} => VulkanRasterCmd::DrawIndexed {
+				index_count,
+				instance_count,
+				first_index,
+				vertex_offset,
+				first_instance,
+			},
+			CapturedCmd::Draw {
+				vertex_count,
+				instance_count,
+				first_vertex,
+				first_instance,
+			} => VulkanRasterCmd::Draw {
+				vertex_count,
+				instance_count,
+				first_vertex,
+				first_instance,
+			},
+			CapturedCmd::DrawIndirect { buffer, offset, draw_count, stride } => VulkanRasterCmd::DrawIndirect {
+				buffer: self.buffers[buffer].raw,
+				offset,
+				draw_count,
+				stride,
+			},
+			CapturedCmd::DrawIndexedIndirect { buffer, offset, draw_count, stride } => VulkanRasterCmd::DrawIndexedIndirect {
+				buffer: self.buffers[buffer].raw,
+				offset,
+				draw_count,
+				stride,
+			},
+			CapturedCmd::BindDescriptor { pipeline, first_set, descriptor_set } => VulkanRasterCmd::BindDescriptor {
+				pipeline_bind_point: self.capture.pipelines[pipeline].bind_point(),
+				pipeline_layout: self.pipelines[pipeline].pipeline_layout,
+				first_set,
+				descriptor_set: self.descriptor_sets[descriptor_set],
+			},
+			CapturedCmd::PipelineBarrier {
+				src_stage_mask,
+				dst_stage_mask,
+				dependency_flags,
+				ref memory_barriers,
+				ref buffer_memory_barriers,
+				ref image_memory_barriers,
+			} => {
+				let memory_barriers = memory_barriers
+					.iter()
+					.map(|barrier| vk::MemoryBarrier {
+						src_access_mask: vk::AccessFlags::from_raw(barrier.src_access_mask),
+						dst_access_mask: vk::AccessFlags::from_raw(barrier.dst_access_mask),
+						..Default::default()
+					})
+					.collect::<Vec<_>>();
+				let buffer_memory_barriers = buffer_memory_barriers
+					.iter()
+					.map(|barrier| vk::BufferMemoryBarrier {
+						src_access_mask: vk::AccessFlags::from_raw(barrier.src_access_mask),
+						dst_access_mask: vk::AccessFlags::from_raw(barrier.dst_access_mask),
+						src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						buffer: self.buffers[barrier.buffer].raw,
+						offset: barrier.offset,
+						size: barrier.size,
+						..Default::default()
+					})
+					.collect::<Vec<_>>();
+				let image_memory_barriers = image_memory_barriers
+					.iter()
+					.map(|barrier| vk::ImageMemoryBarrier {
+						src_access_mask: vk::AccessFlags::from_raw(barrier.src_access_mask),
+						dst_access_mask: vk::AccessFlags::from_raw(barrier.dst_access_mask),
+						old_layout: vk::ImageLayout::from_raw(barrier.old_layout),
+						new_layout: vk::ImageLayout::from_raw(barrier.new_layout),
+						src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						image: self.image(barrier.image),
+						subresource_range: barrier.range.into(),
+						..Default::default()
+					})
+					.collect::<Vec<_>>();
+
+				VulkanRasterCmd::PipelineBarrier {
+					src_stage_mask: vk::PipelineStageFlags::from_raw(src_stage_mask),
+					dst_stage_mask: vk::PipelineStageFlags::from_raw(dst_stage_mask),
+					dependency_flags: vk::DependencyFlags::from_raw(dependency_flags),
+					memory_barriers: VulkanBarrierArena::push(&mut barriers.memory_barriers, &memory_barriers),
+					buffer_memory_barriers: VulkanBarrierArena::push(&mut barriers.buffer_memory_barriers, &buffer_memory_barriers),
+					image_memory_barriers: VulkanBarrierArena::push(&mut barriers.image_memory_barriers, &image_memory_barriers),
+				}
+			}
+			CapturedCmd::Dispatch {
+				group_count_x,
+				group_count_y,
+				group_count_z,
+			} => VulkanRasterCmd::Dispatch {
+				group_count_x,
+				group_count_y,
+				group_count_z,
+			},
+			CapturedCmd::CopyBuffer {
+				src,
+				dst,
+				src_offset,
+				dst_offset,
+				size,
+			} => VulkanRasterCmd::CopyBuffer {
+				src: self.buffers[src].raw,
+				dst: self.buffers[dst].raw,
+				region: vk::BufferCopy { src_offset, dst_offset, size },
+			},
+			CapturedCmd::FillBuffer { buffer, offset, size, data } => VulkanRasterCmd::FillBuffer {
+				buffer: self.buffers[buffer].raw,
+				offset,
+				size,
+				data,
+			},
+			CapturedCmd::ClearColorImage { image, layout, value, range } => VulkanRasterCmd::ClearColorImage {
+				image: self.image(image),
+				layout: vk::ImageLayout::from_raw(layout),
+				value: vk::ClearColorValue { uint32: value },
+				range: range.into(),
+			},
+			CapturedCmd::ClearDepthStencilImage { image, layout, depth, stencil, range } => VulkanRasterCmd::ClearDepthStencilImage {
+				image: self.image(image),
+				layout: vk::ImageLayout::from_raw(layout),
+				value: vk::ClearDepthStencilValue { depth, stencil },
+				range: range.into(),
+			},
+			CapturedCmd::CopyImageToBuffer { src, src_layout, dst, region } => VulkanRasterCmd::CopyImageToBuffer {
+				src: self.image(src),
+				src_layout: vk::ImageLayout::from_raw(src_layout),
+				dst: self.buffers[dst].raw,
+				region: region.into(),
+			},
+			CapturedCmd::BlitImage {
+				src,
+				src_layout,
+				dst,
+				dst_layout,
+				region,
+				filter,
+			} => VulkanRasterCmd::BlitImage {
+				src: self.image(src),
+				src_layout: vk::ImageLayout::from_raw(src_layout),
+				dst: self.image(dst),
+				dst_layout: vk::ImageLayout::from_raw(dst_layout),
+				region: region.into(),
+				filter: vk::Filter::from_raw(filter),
+			},
+			CapturedCmd::PushConstants {
+				pipeline,
+				stage_flags,
+				offset,
+				ref data,
+			} => VulkanRasterCmd::PushConstants {
+				pipeline_layout: self.pipelines[pipeline].pipeline_layout,
+				stage_flags: vk::ShaderStageFlags::from_raw(stage_flags),
+				offset,
+				data: data.clone(),
+			},
+			CapturedCmd::BeginDebugLabel { ref name } => VulkanRasterCmd::BeginDebugLabel {
+				name: std::ffi::CString::new(name.as_str()).unwrap_or_default(),
+			},
+			CapturedCmd::EndDebugLabel => VulkanRasterCmd::EndDebugLabel,
+		})
+	}
+
+	fn run(&mut self) -> GoldfishResult<VulkanReplayOutput> {
+		let capture = self.capture;
+		let mut barriers = VulkanBarrierArena::default();
+		let cmds = capture.cmds.iter().map(|cmd| self.raster_cmd(cmd, &mut barriers)).collect::<GoldfishResult<Vec<_>>>()?;
+
+		let (width, height) = (capture.width, capture.height);
+		let size = width as usize * height as usize * 4;
+		let readback = self.device.create_empty_buffer("Replay Readback", size, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None)?;
+
+		let output = self.output();
+		let (image, subresource_range) = (output.image, output.subresource_range);
+		let device = &self.device;
+		self.upload_context.wait_submit(|raw, cmd_buf| unsafe {
+			for cmd in &cmds {
+				device.record_raster_cmd(cmd_buf, cmd, &barriers);
+			}
+
+			raw.cmd_pipeline_barrier(
+				cmd_buf,
+				vk::PipelineStageFlags::ALL_COMMANDS,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[vk::ImageMemoryBarrier::builder()
+					.src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+					.dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+					.old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+					.new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+					.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.image(image)
+					.subresource_range(subresource_range)
+					.build()],
+			);
+
+			raw.cmd_copy_image_to_buffer(
+				cmd_buf,
+				image,
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				readback.raw,
+				&[vk::BufferImageCopy::builder()
+					.image_subresource(
+						vk::ImageSubresourceLayers::builder()
+							.aspect_mask(vk::ImageAspectFlags::COLOR)
+							.mip_level(0)
+							.base_array_layer(0)
+							.layer_count(1)
+							.build(),
+					)
+					.image_extent(vk::Extent3D { width, height, depth: 1 })
+					.build()],
+			);
+		});
+
+		let pixels = readback.allocation.mapped_slice().expect("Failed to map allocation!")[..size].to_vec();
+		self.device.destroy_buffer(readback);
+
+		Ok(VulkanReplayOutput { width, height, pixels })
+	}
+
+	fn destroy(self) {
+		let Self {
+			mut device,
+			upload_context,
+			output,
+			output_render_passes,
+			output_framebuffer,
+			render_passes,
+			buffers,
+			textures,
+			framebuffers,
+			pipelines,
+			set_layouts,
+			descriptor_pools,
+			..
+		} = self;
+
+		for framebuffer in framebuffers.into_iter().chain(output_framebuffer) {
+			device.destroy_framebuffer(framebuffer);
+		}
+
+		for pipeline in pipelines {
+			device.destroy_pipeline(pipeline);
+		}
+
+		for (_, render_pass) in render_passes {
+			device.destroy_render_pass(render_pass);
+		}
+
+		for buffer in buffers {
+			device.destroy_buffer(buffer);
+		}
+
+		for texture in textures.into_iter().chain(output) {
+			device.destroy_texture(texture);
+		}
+
+		device.queue_destruction(
+			&mut output_render_passes
+				.into_iter()
+				.map(VulkanDestructor::RenderPass)
+				.chain(set_layouts.into_iter().flatten().map(VulkanDestructor::DescriptorSetLayout))
+				.chain(descriptor_pools.into_iter().map(VulkanDestructor::DescriptorPool))
+				.collect::<Vec<_>>(),
+		);
+
+		device.destroy_upload_context(upload_context);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// tests/captures/blit.capture was written from this. It clears the output to red and blits a blue texture into the middle of it, which
+	// covers render passes, clears, barriers and blits without needing any shaders.
+	fn blit_capture() -> VulkanFrameCapture {
+		let color = |r: f32, g: f32, b: f32, a: f32| [r.to_bits(), g.to_bits(), b.to_bits(), a.to_bits()];
+		let range = CapturedSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR.as_raw(),
+			base_mip_level: 0,
+			level_count: 1,
+			base_array_layer: 0,
+			layer_count: 1,
+		};
+		let layers = CapturedSubresourceLayers {
+			aspect_mask: vk::ImageAspectFlags::COLOR.as_raw(),
+			mip_level: 0,
+			base_array_layer: 0,
+			layer_count: 1,
+		};
+		let barrier = |image, src_access_mask: vk::AccessFlags, dst_access_mask: vk::AccessFlags, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout| CapturedImageBarrier {
+			src_access_mask: src_access_mask.as_raw(),
+			dst_access_mask: dst_access_mask.as_raw(),
+			old_layout: old_layout.as_raw(),
+			new_layout: new_layout.as_raw(),
+			image,
+			range,
+		};
+		let source = CapturedImage::Texture(0);
+
+		VulkanFrameCapture {
+			width: 16,
+			height: 16,
+			output_is_srgb: false,
+			buffers: Vec::new(),
+			textures: vec![CapturedTexture {
+				name: "Blit Source".to_string(),
+				width: 8,
+				height: 8,
+				format: TextureFormat::RGBA8UNorm,
+				usage: (TextureUsage::TRANSFER_SRC | TextureUsage::TRANSFER_DST).bits(),
+				initial_layout: ImageLayout::TransferDstOptimal,
+			}],
+			framebuffers: Vec::new(),
+			pipelines: Vec::new(),
+			descriptor_sets: Vec::new(),
+			cmds: vec![
+				CapturedCmd::BeginRenderPass {
+					render_pass: CapturedRenderPass::Output { load: false },
+					framebuffer: None,
+					render_area: CapturedRect { x: 0, y: 0, width: 16, height: 16 },
+					clear_values: vec![color(1.0, 0.0, 0.0, 1.0)],
+				},
+				CapturedCmd::EndRenderPass,
+				CapturedCmd::ClearColorImage {
+					image: source,
+					layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL.as_raw(),
+					value: color(0.0, 0.0, 1.0, 1.0),
+					range,
+				},
+				CapturedCmd::PipelineBarrier {
+					src_stage_mask: (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER).as_raw(),
+					dst_stage_mask: vk::PipelineStageFlags::TRANSFER.as_raw(),
+					dependency_flags: 0,
+					memory_barriers: Vec::new(),
+					buffer_memory_barriers: Vec::new(),
+					image_memory_barriers: vec![
+						barrier(
+							source,
+							vk::AccessFlags::TRANSFER_WRITE,
+							vk::AccessFlags::TRANSFER_READ,
+							vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+							vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+						),
+						barrier(
+							CapturedImage::Output,
+							vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+							vk::AccessFlags::TRANSFER_WRITE,
+							vk::ImageLayout::PRESENT_SRC_KHR,
+							vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+						),
+					],
+				},
+				CapturedCmd::BlitImage {
+					src: source,
+					src_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL.as_raw(),
+					dst: CapturedImage::Output,
+					dst_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL.as_raw(),
+					region: CapturedImageBlit {
+						src_subresource: layers,
+						src_offsets: [[0, 0, 0], [8, 8, 1]],
+						dst_subresource: layers,
+						dst_offsets: [[4, 4, 0], [12, 12, 1]],
+					},
+					filter: vk::Filter::NEAREST.as_raw(),
+				},
+				CapturedCmd::PipelineBarrier {
+					src_stage_mask: vk::PipelineStageFlags::TRANSFER.as_raw(),
+					dst_stage_mask: vk::PipelineStageFlags::BOTTOM_OF_PIPE.as_raw(),
+					dependency_flags: 0,
+					memory_barriers: Vec::new(),
+					buffer_memory_barriers: Vec::new(),
+					image_memory_barriers: vec![barrier(
+						CapturedImage::Output,
+						vk::AccessFlags::TRANSFER_WRITE,
+						vk::AccessFlags::MEMORY_READ,
+						vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+						vk::ImageLayout::PRESENT_SRC_KHR,
+					)],
+				},
+			],
+		}
+	}
+
+	fn encode(capture: &VulkanFrameCapture) -> Vec<u8> {
+		let mut bytes = bincode::serialize(&CAPTURE_VERSION).unwrap();
+		bytes.extend(bincode::serialize(capture).unwrap());
+		bytes
+	}
+
+	struct TempCapture(PathBuf);
+
+	impl TempCapture {
+		fn new(name: &str) -> Self {
+			Self(std::env::temp_dir().join(format!("goldfish_{}_{}.capture", name, std::process::id())))
+		}
+	}
+
+	impl Drop for TempCapture {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+
+	#[test]
+	fn round_trips_through_a_file() {
+		let path = TempCapture::new("round_trip");
+		let mut capture = blit_capture();
+		capture.buffers.push(CapturedBuffer {
+			name: "Constants".to_string(),
+			size: 4,
+			usage: BufferUsage::UniformBuffer.bits(),
+			data: vec![1, 2, 3, 4],
+		});
+		capture.save(&path.0).unwrap();
+
+		let loaded = VulkanFrameCapture::load(&path.0).unwrap();
+		assert_eq!((loaded.width(), loaded.height(), loaded.cmd_count()), (16, 16, 6));
+		assert_eq!(loaded.buffers[0].data, [1, 2, 3, 4]);
+		assert_eq!(encode(&loaded), encode(&capture));
+	}
+
+	#[test]
+	fn checked_in_capture_is_current() {
+		// Failing here means the format changed, bump CAPTURE_VERSION and rewrite the file with encode(&blit_capture()).
+		assert_eq!(include_bytes!("../../../../../tests/captures/blit.capture").as_slice(), encode(&blit_capture()));
+	}
+
+	#[test]
+	fn refuses_other_versions_and_truncated_files() {
+		let path = TempCapture::new("refused");
+		let mut bytes = bincode::serialize(&(CAPTURE_VERSION + 1)).unwrap();
+		bytes.extend(bincode::serialize(&blit_capture()).unwrap());
+		std::fs::write(&path.0, bytes).unwrap();
+		let err = VulkanFrameCapture::load(&path.0).err().unwrap().to_string();
+		assert!(err.contains(&format!("version {} capture", CAPTURE_VERSION + 1)), "{}", err);
+
+		let bytes = encode(&blit_capture());
+		std::fs::write(&path.0, &bytes[..bytes.len() / 2]).unwrap();
+		assert!(VulkanFrameCapture::load(&path.0).is_err());
+	}
+
+	#[test]
+	fn mismatched_pixels_allows_the_tolerance() {
+		let output = VulkanReplayOutput {
+			width: 2,
+			height: 1,
+			pixels: vec![100, 100, 100, 255, 0, 0, 0, 255],
+		};
+		assert_eq!(output.mismatched_pixels(&[102, 98, 100, 255, 0, 0, 0, 255], 2), 0);
+		assert_eq!(output.mismatched_pixels(&[103, 100, 100, 255, 0, 0, 0, 255], 2), 1);
+		assert_eq!(output.mismatched_pixels(&[0, 0, 0, 0, 255, 255, 255, 255], 2), 2);
+	}
+}
@@ -1,4 +1,5 @@
 use super::device::VulkanDevice;
+use super::{VulkanBarrierArena, VulkanRasterCmd};
 use ash::vk;
 use tracy_client as tracy;
 
@@ -13,6 +14,9 @@ pub struct VulkanCommandPool {
 	raw: vk::CommandPool,
 	command_buffers: Vec<VulkanCommandBuffer>,
 	index: usize,
+	// A command buffer's level is fixed when it's allocated, so secondaries get their own list.
+	secondary_command_buffers: Vec<VulkanCommandBuffer>,
+	secondary_index: usize,
 }
 
 impl VulkanDevice {
@@ -28,6 +32,8 @@ impl VulkanDevice {
 			raw,
 			command_buffers: vec![],
 			index: 0,
+			secondary_command_buffers: vec![],
+			secondary_index: 0,
 		}
 	}
 
@@ -35,6 +41,46 @@ impl VulkanDevice {
 		tracy::span!();
 		unsafe { self.raw.destroy_command_pool(command_pool.raw, None) }
 	}
+
+	// Safe to call from any thread. Every thread records into its own pool out of thread_command_pools, and the pools are tied to the fence
+	// of the frame that submits them so they only get recycled once that frame is done on the GPU.
+	pub fn record_secondary_command_buffer(&self, owner: vk::Fence, render_pass: vk::RenderPass, framebuffer: vk::Framebuffer, cmds: &[&VulkanRasterCmd]) -> VulkanCommandBuffer {
+		tracy::span!();
+		let key = (owner, std::thread::current().id());
+
+		// The pool is taken out of the cache while recording so the lock is only held for the lookup.
+		let existing = self.thread_command_pools.lock().unwrap().remove(&key);
+		let mut command_pool = existing.unwrap_or_else(|| self.create_command_pool(QueueType::GRAPHICS));
+
+		// Barriers keep a render pass from being split up, so there's never one to record in here.
+		let barriers = VulkanBarrierArena::default();
+		let command_buffer = command_pool.begin_secondary_command_buffer(self, render_pass, framebuffer);
+		for cmd in cmds {
+			self.record_raster_cmd(command_buffer, cmd, &barriers);
+		}
+		command_pool.end_secondary_command_buffer(self, command_buffer);
+
+		self.thread_command_pools.lock().unwrap().insert(key, command_pool);
+
+		command_buffer
+	}
+
+	// Must only be called once the owner fence has been waited on.
+	pub fn recycle_thread_command_pools(&self, owner: vk::Fence) {
+		for (_, command_pool) in self.thread_command_pools.lock().unwrap().iter_mut().filter(|((pool_owner, _), _)| *pool_owner == owner) {
+			command_pool.recycle(self);
+		}
+	}
+
+	pub fn destroy_thread_command_pools(&self, owner: vk::Fence) {
+		let mut thread_command_pools = self.thread_command_pools.lock().unwrap();
+		let keys = thread_command_pools.keys().filter(|(pool_owner, _)| *pool_owner == owner).copied().collect::<Vec<_>>();
+
+		for key in keys {
+			let command_pool = thread_command_pools.remove(&key).unwrap();
+			self.destroy_command_pool(command_pool);
+		}
+	}
 }
 
 impl VulkanCommandPool {
@@ -43,7 +89,8 @@ impl VulkanCommandPool {
 		assert!(self.index <= self.command_buffers.len(), "Invalid command buffer index!");
 
 		if self.index == self.command_buffers.len() {
-			self.expand(device);
+			let command_buffer = Self::allocate(device, self.raw, vk::CommandBufferLevel::PRIMARY);
+			self.command_buffers.push(command_buffer);
 		}
 
 		let command_buffer = self.command_buffers[self.index];
@@ -67,6 +114,41 @@ impl VulkanCommandPool {
 		self.index += 1;
 	}
 
+	// Only valid inside the given render pass, in a primary that began it with SubpassContents::SECONDARY_COMMAND_BUFFERS.
+	pub fn begin_secondary_command_buffer(&mut self, device: &VulkanDevice, render_pass: vk::RenderPass, framebuffer: vk::Framebuffer) -> VulkanCommandBuffer {
+		assert!(self.secondary_index <= self.secondary_command_buffers.len(), "Invalid command buffer index!");
+
+		if self.secondary_index == self.secondary_command_buffers.len() {
+			let command_buffer = Self::allocate(device, self.raw, vk::CommandBufferLevel::SECONDARY);
+			self.secondary_command_buffers.push(command_buffer);
+		}
+
+		let command_buffer = self.secondary_command_buffers[self.secondary_index];
+		let inheritance_info = vk::CommandBufferInheritanceInfo::builder().render_pass(render_pass).subpass(0).framebuffer(framebuffer);
+
+		unsafe {
+			device
+				.raw
+				.begin_command_buffer(
+					command_buffer,
+					&vk::CommandBufferBeginInfo::builder()
+						.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+						.inheritance_info(&inheritance_info),
+				)
+				.expect("Failed to begin secondary command buffer!");
+		}
+
+		command_buffer
+	}
+
+	pub fn end_secondary_command_buffer(&mut self, device: &VulkanDevice, command_buffer: VulkanCommandBuffer) {
+		unsafe {
+			device.raw.end_command_buffer(command_buffer).expect("Failed to end secondary command buffer!");
+		}
+
+		self.secondary_index += 1;
+	}
+
 	pub fn recycle(&mut self, device: &VulkanDevice) {
 		tracy::span!();
 		unsafe {
@@ -76,24 +158,18 @@ impl VulkanCommandPool {
 				.expect("Failed to recycle command pool!");
 		}
 		self.index = 0;
+		self.secondary_index = 0;
 	}
 
-	fn expand(&mut self, device: &VulkanDevice) {
+	fn allocate(device: &VulkanDevice, command_pool: vk::CommandPool, level: vk::CommandBufferLevel) -> VulkanCommandBuffer {
 		tracy::span!();
-		let new_cmd_buffer = *unsafe {
+		*unsafe {
 			device
 				.raw
-				.allocate_command_buffers(
-					&vk::CommandBufferAllocateInfo::builder()
-						.command_pool(self.raw)
-						.level(vk::CommandBufferLevel::PRIMARY)
-						.command_buffer_count(1),
-				)
+				.allocate_command_buffers(&vk::CommandBufferAllocateInfo::builder().command_pool(command_pool).level(level).command_buffer_count(1))
 				.unwrap()
 		}
 		.first()
-		.unwrap();
-
-		self.command_buffers.push(new_cmd_buffer);
+		.unwrap()
 	}
 }
@@ -1,18 +1,35 @@
 use super::{
 	buffer::VulkanBuffer,
 	device::{VulkanDestructor, VulkanDevice},
-	VulkanSwapchain,
 };
-use crate::renderer::{DescriptorBindingType, DescriptorSetInfo};
+use crate::renderer::{DescriptorBindingType, DescriptorSetBindings, DestroyGuard, RenderError};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
+use log::debug;
 use std::collections::HashMap;
 
 pub type VulkanDescriptorLayout = vk::DescriptorSetLayout;
 
 #[derive(Default)]
 pub struct VulkanDescriptorLayoutCache {
-	pub graphics_layouts: HashMap<*const DescriptorSetInfo, vk::DescriptorSetLayout>,
-	pub compute_layouts: HashMap<*const DescriptorSetInfo, vk::DescriptorSetLayout>,
+	pub graphics_layouts: HashMap<DescriptorSetBindings, vk::DescriptorSetLayout>,
+	pub compute_layouts: HashMap<DescriptorSetBindings, vk::DescriptorSetLayout>,
+}
+
+impl VulkanDescriptorLayoutCache {
+	pub(crate) fn get_or_create(
+		layouts: &mut HashMap<DescriptorSetBindings, vk::DescriptorSetLayout>,
+		bindings: &DescriptorSetBindings,
+		create: impl FnOnce(&[(u32, DescriptorBindingType)]) -> vk::DescriptorSetLayout,
+	) -> vk::DescriptorSetLayout {
+		if let Some(layout) = layouts.get(bindings) {
+			return *layout;
+		}
+
+		let layout = create(bindings.as_slice());
+		layouts.insert(bindings.clone(), layout);
+		layout
+	}
 }
 
 impl From<DescriptorBindingType> for vk::DescriptorType {
@@ -26,6 +43,9 @@ impl From<DescriptorBindingType> for vk::DescriptorType {
 			DescriptorBindingType::CBuffer => vk::DescriptorType::UNIFORM_BUFFER,
 			DescriptorBindingType::StructuredBuffer => vk::DescriptorType::STORAGE_BUFFER,
 			DescriptorBindingType::RWStructuredBuffer => vk::DescriptorType::STORAGE_BUFFER,
+			DescriptorBindingType::Texture2DArray { .. } => vk::DescriptorType::SAMPLED_IMAGE,
+			DescriptorBindingType::Texture2DRuntimeArray => vk::DescriptorType::SAMPLED_IMAGE,
+			DescriptorBindingType::TextureCube => vk::DescriptorType::SAMPLED_IMAGE,
 		}
 	}
 }
@@ -38,15 +58,14 @@ impl VulkanDevice {
 		}
 	}
 
-	pub fn get_graphics_layout(&self, cache: &mut VulkanDescriptorLayoutCache, info: &'static DescriptorSetInfo) -> VulkanDescriptorLayout {
-		*cache
-			.graphics_layouts
-			.entry(info)
-			.or_insert_with(|| self.create_descriptor_layout(&info, vk::ShaderStageFlags::ALL_GRAPHICS))
+	pub fn get_graphics_layout(&self, cache: &mut VulkanDescriptorLayoutCache, bindings: &DescriptorSetBindings) -> VulkanDescriptorLayout {
+		VulkanDescriptorLayoutCache::get_or_create(&mut cache.graphics_layouts, bindings, |bindings| {
+			self.create_descriptor_layout(bindings, vk::ShaderStageFlags::ALL_GRAPHICS)
+		})
 	}
 
-	pub fn get_compute_layout(&self, cache: &mut VulkanDescriptorLayoutCache, info: &'static DescriptorSetInfo) -> VulkanDescriptorLayout {
-		*cache.compute_layouts.entry(info).or_insert_with(|| self.create_descriptor_layout(&info, vk::ShaderStageFlags::COMPUTE))
+	pub fn get_compute_layout(&self, cache: &mut VulkanDescriptorLayoutCache, bindings: &DescriptorSetBindings) -> VulkanDescriptorLayout {
+		VulkanDescriptorLayoutCache::get_or_create(&mut cache.compute_layouts, bindings, |bindings| self.create_descriptor_layout(bindings, vk::ShaderStageFlags::COMPUTE))
 	}
 
 	pub fn destroy_descriptor_layout_cache(&mut self, cache: VulkanDescriptorLayoutCache) {
@@ -60,38 +79,70 @@ impl VulkanDevice {
 		);
 	}
 
-	fn create_descriptor_layout(&self, info: &DescriptorSetInfo, stage_flags: vk::ShaderStageFlags) -> vk::DescriptorSetLayout {
-		unsafe {
-			self.raw
-				.create_descriptor_set_layout(
-					&vk::DescriptorSetLayoutCreateInfo::builder().bindings(
-						&info
-							.bindings
-							.entries()
-							.map(|(&binding, &ty)| {
-								vk::DescriptorSetLayoutBinding::builder()
-									.binding(binding)
-									.descriptor_type(ty.into())
-									.descriptor_count(1)
-									.stage_flags(stage_flags)
-									.build()
-							})
-							.collect::<Vec<_>>(),
-					),
-					None,
-				)
-				.unwrap()
+	// Anything that uses descriptor sets should check them with this first, so devices without descriptor indexing get an error instead of a broken layout.
+	pub fn check_descriptor_set_support(&self, bindings: &DescriptorSetBindings) -> GoldfishResult<()> {
+		if bindings.is_bindless() && !self.descriptor_indexing_supported {
+			return Err(GoldfishError::Render(RenderError::UnsupportedFeature("descriptor indexing")));
+		}
+
+		Ok(())
+	}
+
+	// Bindings are expected to be sorted, frame capture records and replays them in this order.
+	pub(super) fn create_descriptor_layout(&self, bindings: &[(u32, DescriptorBindingType)], stage_flags: vk::ShaderStageFlags) -> vk::DescriptorSetLayout {
+		let is_bindless = bindings.iter().any(|(_, ty)| ty.is_bindless());
+		assert!(
+			!is_bindless || self.descriptor_indexing_supported,
+			"Bindless descriptor sets need descriptor indexing, check them with check_descriptor_set_support first!"
+		);
+
+		let layout_bindings = bindings
+			.iter()
+			.map(|&(binding, ty)| {
+				vk::DescriptorSetLayoutBinding::builder()
+					.binding(binding)
+					.descriptor_type(ty.into())
+					.descriptor_count(ty.descriptor_count())
+					.stage_flags(stage_flags)
+					.build()
+			})
+			.collect::<Vec<_>>();
+
+		// Bindless arrays are written while the set is bound and are usually only partly filled in. Every other binding
+		// in the set keeps the usual rules.
+		let binding_flags = bindings
+			.iter()
+			.map(|(_, ty)| {
+				if ty.is_bindless() {
+					vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+				} else {
+					vk::DescriptorBindingFlags::empty()
+				}
+			})
+			.collect::<Vec<_>>();
+		let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+
+		let mut create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+		if is_bindless {
+			create_info = create_info.flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL).push_next(&mut binding_flags_info);
 		}
+
+		let layout = unsafe { self.raw.create_descriptor_set_layout(&create_info, None).unwrap() };
+		self.with_capture_registry(|registry| registry.register_descriptor_layout(layout, bindings));
+
+		layout
 	}
 }
 
 pub struct VulkanDescriptorHeap {
-	pub frame_pools: [vk::DescriptorPool; VulkanSwapchain::MAX_FRAMES_IN_FLIGHT],
+	// One pool per frame in flight, each descriptor has a set allocated out of every one of them.
+	pub frame_pools: Vec<vk::DescriptorPool>,
 
-	pub descriptors: Vec<[vk::DescriptorSet; VulkanSwapchain::MAX_FRAMES_IN_FLIGHT]>,
+	pub descriptors: Vec<Vec<vk::DescriptorSet>>,
 
 	pub free_descriptors: Vec<u32>,
 	pub allocated_descriptors: Vec<u32>,
+	pub(crate) guard: DestroyGuard,
 }
 
 #[derive(Clone, Copy)]
@@ -118,37 +169,66 @@ impl VulkanDescriptorHeap {
 	}
 }
 
+#[cfg(test)]
+impl VulkanDescriptorHeap {
+	// Doesn't have any pools, for tests that only need a heap to exist.
+	pub(crate) fn null() -> Self {
+		Self {
+			frame_pools: Vec::new(),
+			descriptors: Vec::new(),
+			free_descriptors: Vec::new(),
+			allocated_descriptors: Vec::new(),
+			guard: DestroyGuard::untracked(),
+		}
+	}
+}
+
 impl VulkanDevice {
-	pub fn create_descriptor_heap(&self, layout: VulkanDescriptorLayout) -> VulkanDescriptorHeap {
-		let max_sets = 128;
-		let pool_sizes = [
-			vk::DescriptorPoolSize {
-				ty: vk::DescriptorType::UNIFORM_BUFFER,
-				descriptor_count: max_sets * 2,
-			},
-			vk::DescriptorPoolSize {
-				ty: vk::DescriptorType::SAMPLER,
-				descriptor_count: max_sets * 4,
-			},
-			vk::DescriptorPoolSize {
-				ty: vk::DescriptorType::SAMPLED_IMAGE,
-				descriptor_count: max_sets * 4,
-			},
-		];
-
-		let frame_pools = core::array::from_fn(|_| unsafe {
-			self.raw
-				.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::builder().pool_sizes(&pool_sizes).max_sets(max_sets), None)
-				.expect("Failed to create descriptor pool!")
-		});
+	pub fn create_descriptor_heap(&self, layout: VulkanDescriptorLayout, bindings: &DescriptorSetBindings) -> VulkanDescriptorHeap {
+		// Bindless sets are huge and there's usually only a handful of them, so don't reserve nearly as many.
+		let max_sets = if bindings.is_bindless() { 8 } else { 128 };
+
+		let mut set_sizes = HashMap::<vk::DescriptorType, u32>::new();
+		for (_, ty) in bindings.iter() {
+			*set_sizes.entry(ty.into()).or_default() += ty.descriptor_count();
+		}
+
+		let pool_sizes = set_sizes
+			.into_iter()
+			.map(|(ty, count)| vk::DescriptorPoolSize {
+				ty,
+				descriptor_count: count * max_sets,
+			})
+			.collect::<Vec<_>>();
+
+		let flags = if bindings.is_bindless() {
+			vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+		} else {
+			vk::DescriptorPoolCreateFlags::empty()
+		};
+
+		let frame_pools = (0..self.frames_in_flight)
+			.map(|_| unsafe {
+				self.raw
+					.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::builder().pool_sizes(&pool_sizes).max_sets(max_sets).flags(flags), None)
+					.expect("Failed to create descriptor pool!")
+			})
+			.collect::<Vec<_>>();
 
 		let descriptors = (0..max_sets)
 			.map(|_| {
-				core::array::from_fn(|i| unsafe {
-					self.raw
-						.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder().set_layouts(&[layout]).descriptor_pool(frame_pools[i]))
-						.expect("Failed to allocate descriptor set")[0]
-				})
+				frame_pools
+					.iter()
+					.map(|&pool| {
+						let set = unsafe {
+							self.raw
+								.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder().set_layouts(&[layout]).descriptor_pool(pool))
+								.expect("Failed to allocate descriptor set")[0]
+						};
+						self.with_capture_registry(|registry| registry.reset_descriptor_set(set));
+						set
+					})
+					.collect()
 			})
 			.collect::<Vec<_>>();
 
@@ -159,10 +239,148 @@ impl VulkanDevice {
 			descriptors,
 			free_descriptors,
 			allocated_descriptors: Default::default(),
+			guard: DestroyGuard::new("Descriptor heap", ""),
 		}
 	}
 
-	pub fn destroy_descriptor_heap(&mut self, descriptor_heap: VulkanDescriptorHeap) {
-		self.queue_destruction(&mut descriptor_heap.frame_pools.map(|pool| VulkanDestructor::DescriptorPool(pool)));
+	pub fn destroy_descriptor_heap(&mut self, mut descriptor_heap: VulkanDescriptorHeap) {
+		descriptor_heap.guard.mark_destroyed();
+		self.queue_destruction(&mut descriptor_heap.frame_pools.into_iter().map(|pool| VulkanDestructor::DescriptorPool(pool)).collect::<Vec<_>>());
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct VulkanTransientDescriptor {
+	pub raw: vk::DescriptorSet,
+}
+
+// Transient descriptors only live for a single frame, the whole pool is reset once that frame comes back around
+// so they never need to be freed individually. If we ran out last frame we grow to fit instead of keeping the overflow pools around.
+pub struct VulkanTransientDescriptorPool {
+	pools: Vec<vk::DescriptorPool>,
+	max_sets: u32,
+	pool_sizes: HashMap<vk::DescriptorType, u32>,
+	used_sets: u32,
+	used_sizes: HashMap<vk::DescriptorType, u32>,
+}
+
+impl VulkanTransientDescriptorPool {
+	const DEFAULT_MAX_SETS: u32 = 64;
+
+	fn create_pool(device: &VulkanDevice, max_sets: u32, pool_sizes: &HashMap<vk::DescriptorType, u32>) -> vk::DescriptorPool {
+		unsafe {
+			device
+				.raw
+				.create_descriptor_pool(
+					&vk::DescriptorPoolCreateInfo::builder()
+						.pool_sizes(&pool_sizes.iter().map(|(&ty, &descriptor_count)| vk::DescriptorPoolSize { ty, descriptor_count }).collect::<Vec<_>>())
+						.max_sets(max_sets),
+					None,
+				)
+				.expect("Failed to create transient descriptor pool!")
+		}
+	}
+
+	pub fn alloc(&mut self, device: &VulkanDevice, layout: VulkanDescriptorLayout, bindings: &DescriptorSetBindings) -> VulkanTransientDescriptor {
+		assert!(!bindings.is_bindless(), "Bindless descriptor sets can't be transient!");
+
+		let mut needed_sizes = HashMap::<vk::DescriptorType, u32>::new();
+		for (_, ty) in bindings.iter() {
+			*needed_sizes.entry(ty.into()).or_default() += ty.descriptor_count();
+		}
+
+		self.used_sets += 1;
+		for (&ty, &count) in needed_sizes.iter() {
+			*self.used_sizes.entry(ty).or_default() += count;
+		}
+
+		let pool = *self.pools.last().unwrap();
+		let result = unsafe {
+			device
+				.raw
+				.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder().set_layouts(&[layout]).descriptor_pool(pool))
+		};
+
+		let raw = match result {
+			Ok(sets) => sets[0],
+			Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
+				// Make sure the overflow pool can actually fit this set, even if it uses a type we haven't seen yet.
+				let mut overflow_sizes = self.pool_sizes.clone();
+				for (&ty, &count) in needed_sizes.iter() {
+					let size = overflow_sizes.entry(ty).or_default();
+					*size = (*size).max(count);
+				}
+
+				let pool = Self::create_pool(device, self.max_sets, &overflow_sizes);
+				self.pools.push(pool);
+
+				unsafe {
+					device
+						.raw
+						.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder().set_layouts(&[layout]).descriptor_pool(pool))
+						.expect("Failed to allocate transient descriptor set!")[0]
+				}
+			}
+			Err(err) => panic!("Failed to allocate transient descriptor set! {}", err),
+		};
+		device.with_capture_registry(|registry| registry.reset_descriptor_set(raw));
+
+		VulkanTransientDescriptor { raw }
+	}
+
+	// Must only be called once the GPU is done with every descriptor allocated from this pool.
+	pub fn reset(&mut self, device: &VulkanDevice) {
+		if self.pools.len() > 1 {
+			self.max_sets = self.max_sets.max(self.used_sets.next_power_of_two());
+			for (&ty, &count) in self.used_sizes.iter() {
+				let size = self.pool_sizes.entry(ty).or_default();
+				*size = (*size).max(count.next_power_of_two());
+			}
+
+			for pool in self.pools.drain(..) {
+				unsafe { device.raw.destroy_descriptor_pool(pool, None) };
+			}
+
+			debug!("Grew transient descriptor pool to {} sets", self.max_sets);
+			self.pools.push(Self::create_pool(device, self.max_sets, &self.pool_sizes));
+		} else {
+			unsafe {
+				device
+					.raw
+					.reset_descriptor_pool(self.pools[0], vk::DescriptorPoolResetFlags::empty())
+					.expect("Failed to reset transient descriptor pool!");
+			}
+		}
+
+		self.used_sets = 0;
+		self.used_sizes.clear();
+	}
+}
+
+impl VulkanDevice {
+	pub fn create_transient_descriptor_pool(&self) -> VulkanTransientDescriptorPool {
+		let max_sets = VulkanTransientDescriptorPool::DEFAULT_MAX_SETS;
+		let pool_sizes = HashMap::from([
+			(vk::DescriptorType::UNIFORM_BUFFER, max_sets * 2),
+			(vk::DescriptorType::STORAGE_BUFFER, max_sets * 2),
+			(vk::DescriptorType::SAMPLER, max_sets * 4),
+			(vk::DescriptorType::SAMPLED_IMAGE, max_sets * 4),
+			(vk::DescriptorType::STORAGE_IMAGE, max_sets),
+		]);
+
+		VulkanTransientDescriptorPool {
+			pools: vec![VulkanTransientDescriptorPool::create_pool(self, max_sets, &pool_sizes)],
+			max_sets,
+			pool_sizes,
+			used_sets: 0,
+			used_sizes: Default::default(),
+		}
+	}
+
+	// Only the swapchain owns these and it waits for the device to go idle first, so there's no need to queue this.
+	pub fn destroy_transient_descriptor_pool(&self, transient_descriptor_pool: VulkanTransientDescriptorPool) {
+		for pool in transient_descriptor_pool.pools {
+			unsafe { self.raw.destroy_descriptor_pool(pool, None) };
+		}
 	}
 }
@@ -1,22 +1,29 @@
+use crate::renderer::{AdapterInfo, AdapterType, DeviceSelection, ValidationDesc};
 use crate::window::Window;
 
+use super::capture::VulkanCaptureRegistry;
 use super::command_pool::{QueueType, VulkanCommandBuffer, VulkanCommandPool};
 use super::fence::VulkanFence;
+use super::memory::VulkanMemoryTracker;
 use super::swapchain::VulkanSwapchain;
+use super::validation::{vulkan_debug_callback, ValidationState};
 
 use ash::{
 	extensions::{
 		ext::DebugUtils,
 		khr::{Surface, Swapchain},
+		nv::DeviceDiagnosticCheckpoints,
 	},
 	vk, Entry,
 };
 use gpu_allocator::vulkan as vma;
+use log::{info, warn};
 use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
 use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
 use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
 
 pub enum VulkanDestructor {
 	Allocation(vma::Allocation),
@@ -31,6 +38,9 @@ pub enum VulkanDestructor {
 	DescriptorSetLayout(vk::DescriptorSetLayout),
 	DescriptorPool(vk::DescriptorPool),
 	Framebuffer(vk::Framebuffer),
+	Fence(vk::Fence),
+	Semaphore(vk::Semaphore),
+	CommandPool(vk::CommandPool),
 	None,
 }
 
@@ -40,32 +50,82 @@ impl Default for VulkanDestructor {
 	}
 }
 
+// Both of these are stamped with the device frame they were queued on. Nothing from frame N can still be in flight once
+// frame N + frames_in_flight starts, which is when they get handled.
 pub struct VulkanPerFrameData {
-	pub destructors: [Vec<VulkanDestructor>; VulkanSwapchain::MAX_FRAMES_IN_FLIGHT],
-	pub frame: u32,
+	pub destructors: Vec<(u64, VulkanDestructor)>,
+	// Every context that submitted during a frame registers its fence here, so destructors only run once all of them are done with the frame.
+	pub fences: Vec<(u64, vk::Fence)>,
+	pub frame: u64,
+}
+
+impl VulkanPerFrameData {
+	pub fn is_retired(&self, frame: u64, frames_in_flight: usize) -> bool {
+		frame + frames_in_flight as u64 <= self.frame
+	}
+
+	pub fn queue(&mut self, destructor: VulkanDestructor) {
+		let frame = self.frame;
+		self.destructors.push((frame, destructor));
+	}
+
+	pub fn submitted(&mut self, fence: vk::Fence) {
+		let frame = self.frame;
+		self.fences.push((frame, fence));
+		self.frame += 1;
+	}
+
+	pub fn take_retired_fences(&mut self, frames_in_flight: usize) -> Vec<vk::Fence> {
+		let (retired, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.fences).into_iter().partition(|&(frame, _)| self.is_retired(frame, frames_in_flight));
+		self.fences = pending;
+		retired.into_iter().map(|(_, fence)| fence).collect()
+	}
+
+	pub fn take_retired_destructors(&mut self, frames_in_flight: usize) -> Vec<VulkanDestructor> {
+		let (retired, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.destructors).into_iter().partition(|&(frame, _)| self.is_retired(frame, frames_in_flight));
+		self.destructors = pending;
+		retired.into_iter().map(|(_, destructor)| destructor).collect()
+	}
+
+	pub fn forget_fence(&mut self, fence: vk::Fence) {
+		self.fences.retain(|&(_, pending)| pending != fence);
+	}
 }
 
 #[derive(Clone)]
 pub struct VulkanDevice {
+	entry: Entry,
 	pub instance: Arc<ash::Instance>,
 	pub physical_device: vk::PhysicalDevice,
 	pub physical_device_properties: vk::PhysicalDeviceProperties,
+	pub adapter: AdapterInfo,
+	pub enabled_features: vk::PhysicalDeviceFeatures,
 
 	pub raw: Arc<ash::Device>,
 
-	pub surface: vk::SurfaceKHR,
 	pub surface_loader: Surface,
 
-	debug_utils_loader: DebugUtils,
-	debug_callback: vk::DebugUtilsMessengerEXT,
+	pub(super) debug_utils_loader: DebugUtils,
+	debug_callback: Option<vk::DebugUtilsMessengerEXT>,
+	validation: Option<Arc<ValidationState>>,
 
 	pub vma: Arc<Mutex<Option<vma::Allocator>>>,
+	pub memory_tracker: Arc<VulkanMemoryTracker>,
+	pub memory_budget_supported: bool,
+	pub descriptor_indexing_supported: bool,
+	// Core since 1.2 but optional, frames are only split into several submits with it.
+	pub timeline_semaphore_supported: bool,
+	pub rebar_supported: bool,
+
+	pub checkpoints: Option<DeviceDiagnosticCheckpoints>,
+	pub buffer_marker: Option<vk::AmdBufferMarkerFn>,
 
 	pub graphics_queue: Arc<Mutex<vk::Queue>>,
 	pub compute_queue: Arc<Mutex<vk::Queue>>,
 	pub present_queue: Arc<Mutex<vk::Queue>>,
 
 	pub depth_format: vk::Format,
+	pub frames_in_flight: usize,
 
 	queue_family_indices: QueueFamilyIndices,
 
@@ -73,6 +133,10 @@ pub struct VulkanDevice {
 
 	pub frame: Arc<Mutex<VulkanPerFrameData>>,
 	pub descriptor_layouts: Arc<Mutex<HashMap<TypeId, vk::DescriptorSetLayout>>>,
+	// Secondary command buffers are recorded on worker threads, so each thread gets a pool per frame in flight, keyed by its fence.
+	pub thread_command_pools: Arc<Mutex<HashMap<(vk::Fence, ThreadId), VulkanCommandPool>>>,
+	pub(super) capture_registry: Option<Arc<Mutex<VulkanCaptureRegistry>>>,
+	pub(super) recorded_buffers: Option<Arc<Mutex<HashSet<vk::Buffer>>>>,
 }
 
 pub struct SwapchainDetails {
@@ -81,36 +145,50 @@ pub struct SwapchainDetails {
 	pub present_modes: Vec<vk::PresentModeKHR>,
 }
 
-unsafe extern "system" fn vulkan_debug_callback(
-	message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
-	message_type: vk::DebugUtilsMessageTypeFlagsEXT,
-	p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-	_user_data: *mut std::os::raw::c_void,
-) -> vk::Bool32 {
-	use std::borrow::Cow;
-	let callback_data = *p_callback_data;
-	let message_id_number: i32 = callback_data.message_id_number as i32;
-
-	// Ignore the shader not consuming input warning
-	if message_id_number == 101294395 {
-		return vk::FALSE;
+unsafe fn adapter_info(instance: &ash::Instance, index: usize, dev: vk::PhysicalDevice) -> AdapterInfo {
+	let properties = instance.get_physical_device_properties(dev);
+	let memory_properties = instance.get_physical_device_memory_properties(dev);
+
+	let vram_bytes = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+		.iter()
+		.filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+		.map(|heap| heap.size)
+		.sum();
+
+	AdapterInfo {
+		index,
+		name: CStr::from_ptr(properties.device_name.as_ptr()).to_string_lossy().into_owned(),
+		device_type: match properties.device_type {
+			vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::Discrete,
+			vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::Integrated,
+			vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::Virtual,
+			vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+			_ => AdapterType::Other,
+		},
+		vram_bytes,
+		driver_version: driver_version_string(&properties),
 	}
+}
 
-	let message_id_name = if callback_data.p_message_id_name.is_null() {
-		Cow::from("")
-	} else {
-		CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
-	};
+// Without resizable BAR the CPU only gets a 256 MiB window into VRAM. Integrated GPUs count too.
+fn rebar_supported(memory_properties: &vk::PhysicalDeviceMemoryProperties) -> bool {
+	const BAR_WINDOW_BYTES: u64 = 256 * 1024 * 1024;
 
-	let message = if callback_data.p_message.is_null() {
-		Cow::from("")
-	} else {
-		CStr::from_ptr(callback_data.p_message).to_string_lossy()
-	};
+	memory_properties.memory_types[..memory_properties.memory_type_count as usize].iter().any(|memory_type| {
+		memory_type.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE)
+			&& memory_properties.memory_heaps[memory_type.heap_index as usize].size > BAR_WINDOW_BYTES
+	})
+}
 
-	println!("{:?}:{:?} [{} ({})] : {}", message_severity, message_type, message_id_name, &message_id_number.to_string(), message,);
+fn driver_version_string(properties: &vk::PhysicalDeviceProperties) -> String {
+	const NVIDIA_VENDOR_ID: u32 = 0x10DE;
 
-	vk::FALSE
+	let version = properties.driver_version;
+	if properties.vendor_id == NVIDIA_VENDOR_ID {
+		format!("{}.{}.{}.{}", version >> 22, (version >> 14) & 0xFF, (version >> 6) & 0xFF, version & 0x3F)
+	} else {
+		format!("{}.{}.{}", vk::api_version_major(version), vk::api_version_minor(version), vk::api_version_patch(version))
+	}
 }
 
 #[derive(Clone)]
@@ -121,14 +199,61 @@ pub struct QueueFamilyIndices {
 }
 
 impl VulkanDevice {
-	pub fn new(window: &Window) -> Self {
+	pub fn new(window: &Window, frames_in_flight: usize, validation: Option<&ValidationDesc>, device_selection: DeviceSelection, frame_capture: bool) -> Self {
+		let (device, surface) = Self::new_with_surface(window, frames_in_flight, validation, device_selection, frame_capture);
+		device.destroy_surface(surface);
+
+		device
+	}
+
+	// Lists every GPU through a throwaway instance, so this works without creating a device.
+	pub fn enumerate_adapters() -> Vec<AdapterInfo> {
+		unsafe {
+			let entry = Entry::linked();
+
+			let app_info = vk::ApplicationInfo::builder().api_version(vk::make_api_version(0, 1, 2, 0));
+			let instance = match entry.create_instance(&vk::InstanceCreateInfo::builder().application_info(&app_info), None) {
+				Ok(instance) => instance,
+				Err(err) => {
+					warn!("Failed to create a Vulkan instance to list the GPUs! {}", err);
+					return Vec::new();
+				}
+			};
+
+			let adapters = instance
+				.enumerate_physical_devices()
+				.unwrap_or_default()
+				.into_iter()
+				.enumerate()
+				.map(|(index, dev)| adapter_info(&instance, index, dev))
+				.collect();
+
+			instance.destroy_instance(None);
+
+			adapters
+		}
+	}
+
+	pub(super) fn new_with_surface(window: &Window, frames_in_flight: usize, validation: Option<&ValidationDesc>, device_selection: DeviceSelection, frame_capture: bool) -> (Self, vk::SurfaceKHR) {
 		unsafe {
 			let entry = Entry::linked();
 
 			let mut extension_names = ash_window::enumerate_required_extensions(&window.winit_window).expect("Failed to get required extensions!").to_vec();
 			extension_names.push(DebugUtils::name().as_ptr());
 
-			let layer_names = [CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0")];
+			let supported_instance_extensions = entry.enumerate_instance_extension_properties(None).unwrap_or_default();
+			if supported_instance_extensions
+				.iter()
+				.any(|ext| CStr::from_ptr(ext.extension_name.as_ptr()) == vk::ExtSwapchainColorspaceFn::name())
+			{
+				extension_names.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+			}
+
+			let layer_names: &[&CStr] = if validation.is_some() {
+				&[CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0")]
+			} else {
+				&[]
+			};
 
 			let layer_names_raw: Vec<*const c_char> = layer_names.iter().map(|raw_name| raw_name.as_ptr()).collect();
 
@@ -148,6 +273,8 @@ impl VulkanDevice {
 
 			let instance = entry.create_instance(&create_info, None).expect("Failed to create Vulkan instance!");
 
+			let validation = validation.map(|desc| Arc::new(ValidationState::new(desc)));
+			let user_data = validation.as_ref().map_or(std::ptr::null(), Arc::as_ptr);
 			let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
 				.message_severity(
 					vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -156,10 +283,13 @@ impl VulkanDevice {
 						| vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
 				)
 				.message_type(vk::DebugUtilsMessageTypeFlagsEXT::GENERAL | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE)
-				.pfn_user_callback(Some(vulkan_debug_callback));
+				.pfn_user_callback(Some(vulkan_debug_callback))
+				.user_data(user_data as *mut c_void);
 
 			let debug_utils_loader = DebugUtils::new(&entry, &instance);
-			let debug_callback = debug_utils_loader.create_debug_utils_messenger(&debug_info, None).expect("Failed to create debug messenger!");
+			let debug_callback = validation
+				.is_some()
+				.then(|| debug_utils_loader.create_debug_utils_messenger(&debug_info, None).expect("Failed to create debug messenger!"));
 
 			let surface = ash_window::create_surface(&entry, &instance, &window.winit_window, None).expect("Failed to create surface!");
 
@@ -223,18 +353,72 @@ impl VulkanDevice {
 				panic!("No GPUs on this machine support Vulkan!");
 			}
 
-			let mut best_score = 0;
-			let mut best_dev: Option<vk::PhysicalDevice> = None;
-			for dev in physical_devices {
-				let score = rate_device_suitability(dev);
-				if score > best_score {
-					best_score = score;
-					best_dev = Some(dev);
+			let (adapter_index, physical_device) = match device_selection {
+				DeviceSelection::Index(index) => {
+					let dev = *physical_devices
+						.get(index)
+						.unwrap_or_else(|| panic!("GPU {} was selected, but there are only {} GPUs on this machine!", index, physical_devices.len()));
+
+					if rate_device_suitability(dev) == 0 {
+						panic!(
+							"GPU {} ({}) was selected, but it can't present to the window or doesn't have the queues we need!",
+							index,
+							adapter_info(&instance, index, dev).name
+						);
+					}
+
+					(index, dev)
 				}
-			}
+				_ => {
+					let preferred_type = match device_selection {
+						DeviceSelection::PreferIntegrated => Some(vk::PhysicalDeviceType::INTEGRATED_GPU),
+						DeviceSelection::PreferDiscrete => Some(vk::PhysicalDeviceType::DISCRETE_GPU),
+						_ => None,
+					};
+
+					let mut best_score = 0;
+					let mut best_dev: Option<(usize, vk::PhysicalDevice)> = None;
+					for (index, &dev) in physical_devices.iter().enumerate() {
+						let mut score = rate_device_suitability(dev);
+
+						if score > 0 && Some(instance.get_physical_device_properties(dev).device_type) == preferred_type {
+							score += 1_000_000;
+						}
+
+						if score > best_score {
+							best_score = score;
+							best_dev = Some((index, dev));
+						}
+					}
+
+					best_dev.expect("No GPUs on this machine are supported!")
+				}
+			};
 
-			let physical_device = best_dev.expect("No GPUs on this machine are supported!");
 			let physical_device_properties = instance.get_physical_device_properties(physical_device);
+			let adapter = adapter_info(&instance, adapter_index, physical_device);
+			info!(
+				"Using GPU {} {} ({:?}, {} MiB, driver {})",
+				adapter.index,
+				adapter.name,
+				adapter.device_type,
+				adapter.vram_bytes / (1024 * 1024),
+				adapter.driver_version
+			);
+
+			let frames_in_flight = {
+				let capabilities = surface_loader
+					.get_physical_device_surface_capabilities(physical_device, surface)
+					.expect("Failed to get surface capabilities!");
+
+				let max_frames_in_flight = if capabilities.max_image_count > 0 {
+					(capabilities.max_image_count as usize).clamp(VulkanSwapchain::MIN_FRAMES_IN_FLIGHT, VulkanSwapchain::MAX_FRAMES_IN_FLIGHT)
+				} else {
+					VulkanSwapchain::MAX_FRAMES_IN_FLIGHT
+				};
+
+				frames_in_flight.clamp(VulkanSwapchain::MIN_FRAMES_IN_FLIGHT, max_frames_in_flight)
+			};
 
 			let queue_family_indices = find_queue_families(physical_device).expect("Failed to get queue family indices from physical device chosen. This shouldn't ever happen!");
 
@@ -249,20 +433,89 @@ impl VulkanDevice {
 				.map(|index| vk::DeviceQueueCreateInfo::builder().queue_family_index(*index).queue_priorities(&queue_priorities).build())
 				.collect();
 
-			let device_extension_names_raw = [Swapchain::name().as_ptr()];
+			let supported_extensions = instance.enumerate_device_extension_properties(physical_device).unwrap_or_default();
+			let supports_extension = |name: &CStr| supported_extensions.iter().any(|ext| CStr::from_ptr(ext.extension_name.as_ptr()) == name);
+
+			let memory_budget_supported = supports_extension(vk::ExtMemoryBudgetFn::name());
+			let checkpoints_supported = supports_extension(DeviceDiagnosticCheckpoints::name());
+			let buffer_marker_supported = supports_extension(vk::AmdBufferMarkerFn::name());
+
+			let mut supported_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+			let descriptor_indexing_supported = supports_extension(vk::ExtDescriptorIndexingFn::name()) && {
+				instance.get_physical_device_features2(physical_device, &mut vk::PhysicalDeviceFeatures2::builder().push_next(&mut supported_indexing_features));
+
+				supported_indexing_features.runtime_descriptor_array != 0
+					&& supported_indexing_features.descriptor_binding_partially_bound != 0
+					&& supported_indexing_features.descriptor_binding_sampled_image_update_after_bind != 0
+					&& supported_indexing_features.shader_sampled_image_array_non_uniform_indexing != 0
+			};
+
+			let mut supported_timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+			instance.get_physical_device_features2(physical_device, &mut vk::PhysicalDeviceFeatures2::builder().push_next(&mut supported_timeline_features));
+			let timeline_semaphore_supported = supported_timeline_features.timeline_semaphore != 0;
+
+			let mut device_extension_names_raw = vec![Swapchain::name().as_ptr()];
+			if memory_budget_supported {
+				device_extension_names_raw.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+			}
+			if checkpoints_supported {
+				device_extension_names_raw.push(DeviceDiagnosticCheckpoints::name().as_ptr());
+			}
+			if buffer_marker_supported {
+				device_extension_names_raw.push(vk::AmdBufferMarkerFn::name().as_ptr());
+			}
+			if descriptor_indexing_supported {
+				device_extension_names_raw.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+			}
+			// Anything that needs one of these optional features checks enabled_features first.
+			let supported_features = instance.get_physical_device_features(physical_device);
 			let features = vk::PhysicalDeviceFeatures {
 				shader_clip_distance: 1,
+				geometry_shader: supported_features.geometry_shader,
+				depth_bias_clamp: supported_features.depth_bias_clamp,
+				fill_mode_non_solid: supported_features.fill_mode_non_solid,
+				pipeline_statistics_query: supported_features.pipeline_statistics_query,
+				occlusion_query_precise: supported_features.occlusion_query_precise,
+				texture_compression_bc: supported_features.texture_compression_bc,
+				multi_draw_indirect: supported_features.multi_draw_indirect,
+				shader_storage_image_write_without_format: supported_features.shader_storage_image_write_without_format,
+				shader_sampled_image_array_dynamic_indexing: supported_features.shader_sampled_image_array_dynamic_indexing,
 				..Default::default()
 			};
 
-			let device_create_info = vk::DeviceCreateInfo::builder()
+			let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+				.runtime_descriptor_array(true)
+				.descriptor_binding_partially_bound(true)
+				.descriptor_binding_sampled_image_update_after_bind(true)
+				.shader_sampled_image_array_non_uniform_indexing(true);
+
+			let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::builder().timeline_semaphore(true);
+
+			let mut device_create_info = vk::DeviceCreateInfo::builder()
 				.queue_create_infos(&queue_create_infos)
 				.enabled_layer_names(&layer_names_raw)
 				.enabled_extension_names(&device_extension_names_raw)
 				.enabled_features(&features);
+			if descriptor_indexing_supported {
+				device_create_info = device_create_info.push_next(&mut indexing_features);
+			}
+			if timeline_semaphore_supported {
+				device_create_info = device_create_info.push_next(&mut timeline_features);
+			}
 
 			let device = instance.create_device(physical_device, &device_create_info, None).expect("Failed to create logical device!");
 
+			let checkpoints = if checkpoints_supported {
+				Some(DeviceDiagnosticCheckpoints::new(&instance, &device))
+			} else {
+				None
+			};
+			let buffer_marker = if buffer_marker_supported {
+				Some(vk::AmdBufferMarkerFn::load(|name| std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))))
+			} else {
+				None
+			};
+
 			let graphics_queue = Arc::new(Mutex::new(device.get_device_queue(queue_family_indices.graphics_family, 0)));
 
 			let compute_queue = Arc::new(Mutex::new(device.get_device_queue(queue_family_indices.compute_family, 0)));
@@ -300,45 +553,92 @@ impl VulkanDevice {
 
 			let depth_format = depth_format.expect("No depth format found on this device!");
 
-			Self {
+			let rebar_supported = rebar_supported(&instance.get_physical_device_memory_properties(physical_device));
+			if rebar_supported {
+				info!("Resizable BAR is available, per frame buffers are written directly into VRAM");
+			} else {
+				info!("Resizable BAR isn't available, per frame buffers are copied into VRAM from staging buffers");
+			}
+
+			let device = Self {
+				entry,
 				instance: Arc::new(instance),
 				physical_device,
 				physical_device_properties,
+				adapter,
+				enabled_features: features,
 
 				raw: Arc::new(device),
 
 				surface_loader,
-				surface,
 
 				debug_callback,
+				validation,
 				debug_utils_loader,
 
 				vma,
+				memory_tracker: Default::default(),
+				memory_budget_supported,
+				descriptor_indexing_supported,
+				timeline_semaphore_supported,
+				rebar_supported,
+				checkpoints,
+				buffer_marker,
 
 				graphics_queue,
 				compute_queue,
 				present_queue,
 
 				depth_format,
+				frames_in_flight,
 
 				queue_family_indices,
 				scratch_fence: None,
 
 				frame: Arc::new(Mutex::new(VulkanPerFrameData {
-					destructors: Default::default(),
+					destructors: Vec::new(),
+					fences: Vec::new(),
 					frame: 0,
 				})),
 				descriptor_layouts: Default::default(),
-			}
+				thread_command_pools: Default::default(),
+				capture_registry: frame_capture.then(Default::default),
+				recorded_buffers: cfg!(debug_assertions).then(Default::default),
+			};
+
+			(device, surface)
 		}
 	}
 
+	pub fn create_surface(&self, window: &Window) -> vk::SurfaceKHR {
+		unsafe { ash_window::create_surface(&self.entry, &self.instance, &window.winit_window, None).expect("Failed to create surface!") }
+	}
+
+	pub fn destroy_surface(&self, surface: vk::SurfaceKHR) {
+		unsafe { self.surface_loader.destroy_surface(surface, None) };
+	}
+
+	// The present queue is picked against the first window's surface, any other surface has to be presentable from that same queue family.
+	pub fn supports_surface(&self, surface: vk::SurfaceKHR) -> bool {
+		let supports_present = unsafe {
+			self.surface_loader
+				.get_physical_device_surface_support(self.physical_device, self.queue_family_indices.present_family, surface)
+				.unwrap_or(false)
+		};
+
+		supports_present && Self::query_swapchain_support_physical_device(&self.surface_loader, surface, self.physical_device).is_some()
+	}
+
 	// pub fn setup_descriptor_layouts(&self, descriptor_layouts: &HashMap<TypeId, DescriptorInfo>) {}
 
 	pub fn wait_idle(&self) {
 		unsafe { self.raw.device_wait_idle().expect("Wait idle failed!") };
 	}
 
+	pub fn max_compute_work_group_count(&self) -> [u32; 3] {
+		self.physical_device_properties.limits.max_compute_work_group_count
+	}
+
 	pub fn pad_size(&self, size: u64) -> u64 {
 		let alignment = self.physical_device_properties.limits.min_uniform_buffer_offset_alignment;
 		if alignment <= 0 {
@@ -382,53 +682,73 @@ impl VulkanDevice {
 		}
 	}
 
-	pub fn query_swapchain_details(&self) -> SwapchainDetails {
-		Self::query_swapchain_support_physical_device(&self.surface_loader, self.surface, self.physical_device).expect("Failed to get physical device swapchain support details!")
+	pub fn query_swapchain_details(&self, surface: vk::SurfaceKHR) -> SwapchainDetails {
+		Self::query_swapchain_support_physical_device(&self.surface_loader, surface, self.physical_device).expect("Failed to get physical device swapchain support details!")
+	}
+
+	// Whether validation has reported an error since the last call. Panics instead with OnValidationError::Panic.
+	pub fn take_validation_error(&self) -> bool {
+		self.validation.as_ref().map_or(false, |validation| validation.take_error())
 	}
 
 	pub fn get_queue_family_indices(&self) -> &QueueFamilyIndices {
 		&self.queue_family_indices
 	}
 
+	// Waits for the GPU to go idle and runs everything queued for destruction right away.
+	pub fn flush_destruction_queues(&self) {
+		let destructors = std::mem::take(&mut self.frame.lock().unwrap().destructors);
+		if destructors.is_empty() {
+			return;
+		}
+
+		self.wait_idle();
+		for (_, destructor) in destructors {
+			self.run_destructor(destructor);
+		}
+	}
+
 	pub fn destroy(&mut self) {
 		self.wait_idle();
+		self.flush_destruction_queues();
 
-		let mut guard = self.frame.lock().unwrap();
-		for destructor_queue in guard.destructors.iter_mut() {
-			let destructors = std::mem::take(destructor_queue);
-			for destructor in destructors.into_iter() {
-				self.run_destructor(destructor);
-			}
+		for (_, command_pool) in std::mem::take(&mut *self.thread_command_pools.lock().unwrap()) {
+			self.destroy_command_pool(command_pool);
+		}
+
+		if cfg!(debug_assertions) {
+			self.report_live_allocations();
 		}
 
 		unsafe {
 			std::mem::drop(self.vma.lock().unwrap().take());
 
 			self.raw.destroy_device(None);
-			self.surface_loader.destroy_surface(self.surface, None);
-			self.debug_utils_loader.destroy_debug_utils_messenger(self.debug_callback, None);
+			if let Some(debug_callback) = self.debug_callback.take() {
+				self.debug_utils_loader.destroy_debug_utils_messenger(debug_callback, None);
+			}
+			if let Some(validation) = self.validation.take() {
+				validation.flush();
+			}
 			self.instance.destroy_instance(None);
 		}
 	}
 
-	pub fn queue_destruction(&mut self, destructors: &mut [VulkanDestructor]) {
+	pub fn queue_destruction(&self, destructors: &mut [VulkanDestructor]) {
 		let mut guard = self.frame.lock().unwrap();
 
-		let current_frame = guard.frame as usize;
-
-		for destructor in destructors.into_iter() {
-			guard.destructors[current_frame].push(std::mem::take(destructor));
+		for destructor in destructors.iter_mut() {
+			guard.queue(std::mem::take(destructor));
 		}
 	}
 
 	pub fn run_destructor(&self, destructor: VulkanDestructor) {
+		self.with_capture_registry(|registry| registry.forget(&destructor));
+
 		unsafe {
 			match destructor {
 				VulkanDestructor::Allocation(allocation) => {
-					let mut guard = self.vma.lock().unwrap();
-					let vma = guard.as_mut().unwrap();
-
-					vma.free(allocation).expect("Failed to free allocation!");
+					self.free(allocation);
 				}
 				VulkanDestructor::Buffer(buffer) => {
 					self.raw.destroy_buffer(buffer, None);
@@ -463,6 +783,15 @@ impl VulkanDevice {
 				VulkanDestructor::Framebuffer(framebuffer) => {
 					self.raw.destroy_framebuffer(framebuffer, None);
 				}
+				VulkanDestructor::Fence(fence) => {
+					self.raw.destroy_fence(fence, None);
+				}
+				VulkanDestructor::Semaphore(semaphore) => {
+					self.raw.destroy_semaphore(semaphore, None);
+				}
+				VulkanDestructor::CommandPool(command_pool) => {
+					self.raw.destroy_command_pool(command_pool, None);
+				}
 				VulkanDestructor::None => panic!("A None destructor was passed in the queue!"),
 			}
 		}
@@ -484,9 +813,9 @@ impl VulkanDevice {
 		}
 	}
 
+	// Goes through the queue like everything else so teardown only happens in one place.
 	pub fn destroy_upload_context(&self, upload_context: VulkanUploadContext) {
-		self.destroy_fence(upload_context.fence);
-		self.destroy_command_pool(upload_context.command_pool);
+		self.queue_destruction(&mut [VulkanDestructor::Fence(upload_context.fence.raw), VulkanDestructor::CommandPool(upload_context.command_pool.raw)]);
 	}
 }
 
@@ -524,3 +853,75 @@ impl VulkanUploadContext {
 		self.command_pool.recycle(&self.device);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ash::vk::Handle;
+
+	const FRAMES_IN_FLIGHT: usize = 2;
+
+	fn frame_data() -> VulkanPerFrameData {
+		VulkanPerFrameData {
+			destructors: Vec::new(),
+			fences: Vec::new(),
+			frame: 0,
+		}
+	}
+
+	fn buffers(destructors: Vec<VulkanDestructor>) -> Vec<u64> {
+		destructors
+			.into_iter()
+			.map(|destructor| match destructor {
+				VulkanDestructor::Buffer(buffer) => buffer.as_raw(),
+				_ => panic!("Only buffers were queued!"),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn destructors_retire_frames_in_flight_later() {
+		let mut data = frame_data();
+		data.queue(VulkanDestructor::Buffer(vk::Buffer::from_raw(1)));
+		data.submitted(vk::Fence::from_raw(100));
+		assert_eq!(data.frame, 1);
+
+		data.queue(VulkanDestructor::Buffer(vk::Buffer::from_raw(2)));
+		assert!(data.take_retired_fences(FRAMES_IN_FLIGHT).is_empty());
+		assert!(data.take_retired_destructors(FRAMES_IN_FLIGHT).is_empty());
+
+		data.submitted(vk::Fence::from_raw(101));
+		assert_eq!(data.frame, 2);
+		assert_eq!(data.take_retired_fences(FRAMES_IN_FLIGHT), [vk::Fence::from_raw(100)]);
+		assert_eq!(buffers(data.take_retired_destructors(FRAMES_IN_FLIGHT)), [1]);
+
+		data.submitted(vk::Fence::from_raw(102));
+		assert_eq!(data.take_retired_fences(FRAMES_IN_FLIGHT), [vk::Fence::from_raw(101)]);
+		assert_eq!(buffers(data.take_retired_destructors(FRAMES_IN_FLIGHT)), [2]);
+		assert_eq!(data.fences, [(2, vk::Fence::from_raw(102))]);
+	}
+
+	#[test]
+	fn frame_keeps_counting_across_swapchain_recreation() {
+		let mut data = frame_data();
+		for fence in 0..5 {
+			data.submitted(vk::Fence::from_raw(100 + fence));
+			data.take_retired_fences(FRAMES_IN_FLIGHT);
+		}
+		assert_eq!(data.frame, 5);
+
+		// The old swapchain's fences go away with it, while a destructor queued on its last frame still has to wait.
+		data.queue(VulkanDestructor::Buffer(vk::Buffer::from_raw(1)));
+		data.forget_fence(vk::Fence::from_raw(103));
+		data.forget_fence(vk::Fence::from_raw(104));
+		assert!(data.fences.is_empty());
+		assert_eq!(data.frame, 5);
+
+		data.submitted(vk::Fence::from_raw(200));
+		assert!(data.take_retired_destructors(FRAMES_IN_FLIGHT).is_empty());
+		data.submitted(vk::Fence::from_raw(201));
+		assert_eq!(data.frame, 7);
+		assert_eq!(buffers(data.take_retired_destructors(FRAMES_IN_FLIGHT)), [1]);
+		assert_eq!(data.fences, [(5, vk::Fence::from_raw(200)), (6, vk::Fence::from_raw(201))]);
+	}
+}
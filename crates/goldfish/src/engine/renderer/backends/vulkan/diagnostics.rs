@@ -0,0 +1,173 @@
+use super::{buffer::VulkanBuffer, device::VulkanDevice, VulkanGraphicsContext, VulkanRasterCmd};
+use crate::renderer::{renderdoc, BufferUsage, PassDiagnostics};
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::error;
+use std::ffi::CString;
+use std::fmt::Write;
+
+pub const CRASH_LOG_PATH: &str = "goldfish_crash.log";
+
+// Markers pack the frame slot, the pass index and whether it's the start or end of the pass into 32 bits.
+// 0 is never a valid marker, so a zeroed marker buffer means nothing has run yet.
+fn encode_marker(frame_slot: usize, pass: usize, end: bool) -> u32 {
+	((frame_slot as u32) << 24) | ((pass as u32 + 1) << 1) | end as u32
+}
+
+fn decode_marker(marker: u32) -> Option<(usize, usize, bool)> {
+	let pass = (marker & 0xffffff) >> 1;
+	if pass == 0 {
+		return None;
+	}
+
+	Some(((marker >> 24) as usize, pass as usize - 1, marker & 1 == 1))
+}
+
+impl VulkanDevice {
+	// Only exists if VK_AMD_buffer_marker is supported, the first u32 is the last pass that started and the second is the last one that finished.
+	pub fn create_marker_buffer(&self) -> Option<VulkanBuffer> {
+		self.buffer_marker.as_ref()?;
+
+		let mut buffer = self.create_empty_buffer("Buffer Markers", 8, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None).ok()?;
+		self.map_buffer(&mut buffer).fill(0);
+
+		Some(buffer)
+	}
+}
+
+impl VulkanGraphicsContext {
+	// The render graph calls these around every pass it executes, so a lost device can be traced back to whatever pass was running.
+	// They also wrap the pass in a debug label, which is what names the passes in a RenderDoc capture.
+	pub fn begin_pass_diagnostics(&mut self, name: &'static str, resources: Vec<&'static str>) {
+		let frame_slot = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
+		let frame = &mut self.swapchain.frames[frame_slot];
+
+		let marker = encode_marker(frame_slot, frame.passes.len(), false);
+		frame.passes.push(PassDiagnostics { name, resources });
+
+		self.queue_raster_cmd(VulkanRasterCmd::BeginDebugLabel {
+			name: CString::new(name).unwrap_or_default(),
+		});
+		self.queue_markers(frame_slot, marker, vk::PipelineStageFlags::TOP_OF_PIPE, 0);
+	}
+
+	pub fn end_pass_diagnostics(&mut self) {
+		let frame_slot = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
+		let pass = self.swapchain.frames[frame_slot].passes.len().checked_sub(1).expect("end_pass_diagnostics called without a pass!");
+
+		let marker = encode_marker(frame_slot, pass, true);
+		self.queue_markers(frame_slot, marker, vk::PipelineStageFlags::BOTTOM_OF_PIPE, 4);
+		self.queue_raster_cmd(VulkanRasterCmd::EndDebugLabel);
+	}
+
+	fn queue_markers(&self, frame_slot: usize, marker: u32, stage: vk::PipelineStageFlags, offset: u64) {
+		if self.swapchain.device.checkpoints.is_some() {
+			self.queue_raster_cmd(VulkanRasterCmd::SetCheckpoint { marker });
+		}
+
+		if let Some(buffer) = &self.swapchain.frames[frame_slot].marker_buffer {
+			self.queue_raster_cmd(VulkanRasterCmd::WriteBufferMarker {
+				stage,
+				buffer: buffer.raw,
+				offset,
+				marker,
+			});
+		}
+	}
+
+	// Must only be called once the frame's fence has been waited on.
+	pub(super) fn reset_pass_diagnostics(&mut self, frame_slot: usize) {
+		let frame = &mut self.swapchain.frames[frame_slot];
+		frame.passes.clear();
+
+		if let Some(buffer) = &mut frame.marker_buffer {
+			self.swapchain.device.map_buffer(buffer).fill(0);
+		}
+	}
+
+	// Pretty much every Vulkan call fails after the device is lost, including the ones in all of our destructors,
+	// so the best we can do is write down everything we know and bail out without unwinding.
+	pub(super) fn handle_device_lost(&self) -> ! {
+		let device = &self.swapchain.device;
+		let mut report = String::new();
+		writeln!(report, "The graphics device was lost on frame {}!", self.frame_index).unwrap();
+
+		let checkpoints = device.checkpoints.as_ref().map_or(Vec::new(), |checkpoints| unsafe {
+			let graphics_queue = *device.graphics_queue.lock().unwrap();
+			let mut data = vec![vk::CheckpointDataNV::default(); checkpoints.get_queue_checkpoint_data_len(graphics_queue)];
+			checkpoints.get_queue_checkpoint_data(graphics_queue, &mut data);
+
+			data.into_iter()
+				.filter_map(|checkpoint| decode_marker(checkpoint.p_checkpoint_marker as usize as u32).map(|marker| (marker, checkpoint.stage)))
+				.collect::<Vec<_>>()
+		});
+
+		for (frame_slot, frame) in self.swapchain.frames.iter().enumerate() {
+			writeln!(report, "Passes in flight for frame slot {}:", frame_slot).unwrap();
+			if frame.passes.is_empty() {
+				writeln!(report, "\t(none)").unwrap();
+			}
+
+			let (started, finished) = match &frame.marker_buffer {
+				Some(buffer) => {
+					let markers = buffer.allocation.mapped_slice().expect("Failed to map marker buffer!");
+					let read = |offset: usize| decode_marker(u32::from_ne_bytes(markers[offset..offset + 4].try_into().unwrap())).map(|(_, pass, _)| pass);
+					(read(0), read(4))
+				}
+				None => (None, None),
+			};
+
+			for (i, pass) in frame.passes.iter().enumerate() {
+				let mut notes = checkpoints
+					.iter()
+					.filter(|&&((f, p, _), _)| f == frame_slot && p == i)
+					.map(|&((_, _, end), stage)| format!("reached {} checkpoint at {:?}", if end { "end" } else { "begin" }, stage))
+					.collect::<Vec<_>>();
+
+				if started == Some(i) {
+					notes.push("last pass started".to_string());
+				}
+				if finished == Some(i) {
+					notes.push("last pass finished".to_string());
+				}
+
+				writeln!(report, "\t#{} {} [{}]", i, pass.name, pass.resources.join(", ")).unwrap();
+				for note in notes {
+					writeln!(report, "\t\t{}", note).unwrap();
+				}
+			}
+		}
+
+		if device.checkpoints.is_none() && device.buffer_marker.is_none() {
+			writeln!(
+				report,
+				"Neither VK_NV_device_diagnostic_checkpoints nor VK_AMD_buffer_marker are supported, so there's no telling which of these passes was running."
+			)
+			.unwrap();
+		}
+
+		// There's no next frame to capture once the device is gone, but a capture that was already running when it happened
+		// is exactly the one worth having, so it gets finished off instead of dying with the process.
+		if let Some(renderdoc) = renderdoc::renderdoc() {
+			if renderdoc.is_frame_capturing() {
+				if renderdoc.end_frame_capture() {
+					let capture = renderdoc.capture_count().checked_sub(1).and_then(|index| renderdoc.capture_path(index));
+					writeln!(report, "Finished the RenderDoc capture that was running: {:?}", capture).unwrap();
+				} else {
+					writeln!(report, "Failed to finish the RenderDoc capture that was running.").unwrap();
+				}
+			}
+		}
+
+		error!("{}", report);
+		match std::fs::write(CRASH_LOG_PATH, &report) {
+			Ok(_) => error!("Wrote crash log to {}", CRASH_LOG_PATH),
+			Err(err) => error!("Failed to write crash log to {}: {}", CRASH_LOG_PATH, err),
+		}
+
+		// exit doesn't give anything a chance to flush, so the log file would lose the report otherwise.
+		log::logger().flush();
+
+		std::process::exit(1);
+	}
+}
@@ -35,6 +35,17 @@ impl VulkanFence {
 		}
 	}
 
+	// Same as wait, but hands the error back so a lost device can be dealt with instead of panicking.
+	pub fn try_wait(&self, device: &VulkanDevice) -> Result<(), vk::Result> {
+		tracy::span!();
+		unsafe { device.raw.wait_for_fences(&[self.raw], true, std::u64::MAX) }
+	}
+
+	// Doesn't wait, just checks whether the fence has been signaled yet.
+	pub fn is_signaled(&self, device: &VulkanDevice) -> Result<bool, vk::Result> {
+		unsafe { device.raw.get_fence_status(self.raw) }
+	}
+
 	pub fn wait_multiple(device: &VulkanDevice, fences: &[&VulkanFence], wait_all: bool) {
 		tracy::span!();
 		unsafe {
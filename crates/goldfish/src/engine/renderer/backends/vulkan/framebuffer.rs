@@ -30,7 +30,10 @@ impl VulkanDevice {
 				.expect("Failed to create framebuffer!")
 		};
 
-		VulkanFramebuffer { width, height, raw }
+		let framebuffer = VulkanFramebuffer { width, height, raw };
+		self.with_capture_registry(|registry| registry.register_framebuffer(&framebuffer, render_pass.raw, &attachments));
+
+		framebuffer
 	}
 
 	pub fn destroy_framebuffer(&mut self, framebuffer: VulkanFramebuffer) {
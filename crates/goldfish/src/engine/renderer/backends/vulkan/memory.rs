@@ -0,0 +1,194 @@
+use super::device::VulkanDevice;
+use crate::renderer::{MemoryLocationPreference, MemoryStats, RenderError};
+use ash::vk;
+use gpu_allocator::vulkan as vma;
+use gpu_allocator::MemoryLocation;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// gpu_allocator doesn't tell us how much it has allocated, so we keep our own running totals.
+// These live behind an Arc on the device since devices get cloned all over the place.
+pub struct VulkanMemoryTracker {
+	device_local_allocated: AtomicU64,
+	host_visible_allocated: AtomicU64,
+	allocation_count: AtomicU64,
+	// Stored as the bits of an f32 since there's no AtomicF32.
+	warning_fraction: AtomicU32,
+	over_budget: AtomicBool,
+	// Keyed by the memory block and offset, which is unique for as long as the allocation is alive.
+	live_allocations: Mutex<HashMap<(vk::DeviceMemory, u64), LiveAllocation>>,
+}
+
+struct LiveAllocation {
+	name: String,
+	size: u64,
+}
+
+impl Default for VulkanMemoryTracker {
+	fn default() -> Self {
+		Self {
+			device_local_allocated: Default::default(),
+			host_visible_allocated: Default::default(),
+			allocation_count: Default::default(),
+			warning_fraction: AtomicU32::new(Self::DEFAULT_WARNING_FRACTION.to_bits()),
+			over_budget: Default::default(),
+			live_allocations: Default::default(),
+		}
+	}
+}
+
+impl VulkanMemoryTracker {
+	pub const DEFAULT_WARNING_FRACTION: f32 = 0.9;
+
+	fn counter(&self, allocation: &vma::Allocation) -> &AtomicU64 {
+		// Anything host visible is persistently mapped by gpu_allocator.
+		if allocation.mapped_ptr().is_some() {
+			&self.host_visible_allocated
+		} else {
+			&self.device_local_allocated
+		}
+	}
+
+	fn allocation_key(allocation: &vma::Allocation) -> (vk::DeviceMemory, u64) {
+		(unsafe { allocation.memory() }, allocation.offset())
+	}
+}
+
+impl VulkanDevice {
+	// TODO: The allocation name should also be given to the buffer/image it backs once we name Vulkan objects through debug utils.
+	pub fn allocate(&self, desc: &vma::AllocationCreateDesc) -> Result<vma::Allocation, RenderError> {
+		let allocation = {
+			let mut guard = self.vma.lock().unwrap();
+			let vma = guard.as_mut().unwrap();
+
+			vma.allocate(desc)?
+		};
+
+		self.memory_tracker.counter(&allocation).fetch_add(allocation.size(), Ordering::Relaxed);
+		self.memory_tracker.allocation_count.fetch_add(1, Ordering::Relaxed);
+		self.memory_tracker.live_allocations.lock().unwrap().insert(
+			VulkanMemoryTracker::allocation_key(&allocation),
+			LiveAllocation {
+				name: desc.name.to_string(),
+				size: allocation.size(),
+			},
+		);
+
+		self.check_memory_budget();
+
+		Ok(allocation)
+	}
+
+	pub fn free(&self, allocation: vma::Allocation) {
+		self.memory_tracker.counter(&allocation).fetch_sub(allocation.size(), Ordering::Relaxed);
+		self.memory_tracker.allocation_count.fetch_sub(1, Ordering::Relaxed);
+		self.memory_tracker.live_allocations.lock().unwrap().remove(&VulkanMemoryTracker::allocation_key(&allocation));
+
+		let mut guard = self.vma.lock().unwrap();
+		let vma = guard.as_mut().unwrap();
+
+		vma.free(allocation).expect("Failed to free allocation!");
+	}
+
+	// Logs everything that's still allocated, biggest first. Anything showing up here on shutdown is a leak.
+	pub fn report_live_allocations(&self) {
+		let live_allocations = self.memory_tracker.live_allocations.lock().unwrap();
+		if live_allocations.is_empty() {
+			info!("No live GPU allocations");
+			return;
+		}
+
+		let mut allocations = live_allocations.values().collect::<Vec<_>>();
+		allocations.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+		let total_size = allocations.iter().map(|allocation| allocation.size).sum::<u64>();
+		warn!("{} live GPU allocations ({} KB):", allocations.len(), total_size / 1024);
+		for allocation in allocations {
+			warn!("\t{}: {} KB", allocation.name, allocation.size / 1024);
+		}
+	}
+
+	// Decided once when the device is created, and logged then.
+	pub fn supports_rebar(&self) -> bool {
+		self.rebar_supported
+	}
+
+	pub fn resolve_memory_location(&self, preference: MemoryLocationPreference) -> MemoryLocation {
+		match preference {
+			MemoryLocationPreference::Exact(location) => location,
+			MemoryLocationPreference::Auto if self.rebar_supported => MemoryLocation::CpuToGpu,
+			MemoryLocationPreference::Auto => MemoryLocation::GpuOnly,
+		}
+	}
+
+	// Warn once whenever usage crosses the warning fraction of the budget, this is reset once we drop back under it.
+	pub fn set_memory_warning_fraction(&self, fraction: f32) {
+		self.memory_tracker.warning_fraction.store(fraction.to_bits(), Ordering::Relaxed);
+	}
+
+	fn check_memory_budget(&self) {
+		let stats = self.memory_stats();
+		let fraction = f32::from_bits(self.memory_tracker.warning_fraction.load(Ordering::Relaxed)) as f64;
+
+		let over_budget = stats.device_local_used as f64 > stats.device_local_budget as f64 * fraction || stats.host_visible_used as f64 > stats.host_visible_budget as f64 * fraction;
+
+		if over_budget && !self.memory_tracker.over_budget.swap(true, Ordering::Relaxed) {
+			warn!(
+				"GPU memory usage is above {:.0}% of the budget! Device local {}/{} MB, host visible {}/{} MB",
+				fraction * 100.0,
+				stats.device_local_used / (1024 * 1024),
+				stats.device_local_budget / (1024 * 1024),
+				stats.host_visible_used / (1024 * 1024),
+				stats.host_visible_budget / (1024 * 1024)
+			);
+		} else if !over_budget {
+			self.memory_tracker.over_budget.store(false, Ordering::Relaxed);
+		}
+	}
+
+	pub fn memory_stats(&self) -> MemoryStats {
+		let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+		let mut properties = vk::PhysicalDeviceMemoryProperties2::builder();
+		if self.memory_budget_supported {
+			properties = properties.push_next(&mut budget_properties);
+		}
+
+		unsafe { self.instance.get_physical_device_memory_properties2(self.physical_device, &mut properties) };
+
+		let memory_properties = properties.memory_properties;
+		let mut stats = MemoryStats {
+			device_local_used: self.memory_tracker.device_local_allocated.load(Ordering::Relaxed),
+			host_visible_used: self.memory_tracker.host_visible_allocated.load(Ordering::Relaxed),
+			allocation_count: self.memory_tracker.allocation_count.load(Ordering::Relaxed),
+			..Default::default()
+		};
+
+		let mut device_local_usage = 0;
+		let mut host_visible_usage = 0;
+		for (i, heap) in memory_properties.memory_heaps[0..memory_properties.memory_heap_count as usize].iter().enumerate() {
+			let (budget, usage) = if self.memory_budget_supported {
+				(budget_properties.heap_budget[i], budget_properties.heap_usage[i])
+			} else {
+				(heap.size, 0)
+			};
+
+			if heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+				stats.device_local_budget += budget;
+				device_local_usage += usage;
+			} else {
+				stats.host_visible_budget += budget;
+				host_visible_usage += usage;
+			}
+		}
+
+		// The driver's numbers include everything the process has allocated (swapchain images etc.), so prefer them when we have them.
+		if self.memory_budget_supported {
+			stats.device_local_used = device_local_usage;
+			stats.host_visible_used = host_visible_usage;
+		}
+
+		stats
+	}
+}
@@ -1,40 +1,85 @@
+mod bound_state;
 mod buffer;
+mod capture;
 mod command_pool;
 mod descriptor;
 mod device;
+mod diagnostics;
 mod fence;
 mod framebuffer;
+mod memory;
 mod pipeline;
+mod query;
+mod readback;
 mod render_pass;
 mod semaphore;
 mod shader;
 mod swapchain;
 mod texture;
+mod validation;
 
 use crate::window::Window;
+use bound_state::VulkanBoundState;
 use command_pool::VulkanCommandBuffer;
+use query::VulkanQueryPools;
 use swapchain::{FrameInfo, VulkanSwapchain};
 
-use crate::renderer::{ClearValue, DepthCompareOp, DescriptorSetInfo, FaceCullMode, FrameId, ImageLayout, PolygonMode, VertexInputInfo};
+use crate::renderer::{
+	BlendMode, ClearValue, DepthBias, DepthCompareOp, DescriptorSetBindings, DeviceSelection, FaceCullMode, Filter, FramePacingStats, ImageLayout, OutputViewport, PolygonMode, PresentMode, QueryKind,
+	QueryResult, QuerySlot, ReadbackId, RenderError, SpecValue, SurfaceFormat, SurfacePreference, SwapchainInfo, ValidationDesc, VertexInputInfo,
+};
 use crate::types::{Color, Size};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 use custom_error::custom_error;
-use std::cell::RefCell;
+use gpu_allocator::MemoryLocation;
+use rayon::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ops::{Deref, DerefMut, Range};
+use std::path::PathBuf;
+use std::ptr::NonNull;
+use std::time::Duration;
 use tracy_client as tracy;
 
 custom_error! {pub SwapchainError
 	SubmitSuboptimal = "Swapchain is suboptimal and needs to be recreated",
-	AcquireSuboptimal = "Swapchain is suboptimal and needs to be recreated"
+	AcquireSuboptimal = "Swapchain is suboptimal and needs to be recreated",
+	DeviceLost = "The graphics device was lost"
 }
 
-pub use buffer::VulkanBuffer;
-pub use descriptor::{VulkanDescriptorHandle, VulkanDescriptorHeap, VulkanDescriptorLayout, VulkanDescriptorLayoutCache};
+impl From<vk::Result> for RenderError {
+	fn from(result: vk::Result) -> Self {
+		match result {
+			vk::Result::ERROR_DEVICE_LOST => RenderError::DeviceLost,
+			vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_POOL_MEMORY => RenderError::OutOfMemory,
+			vk::Result::ERROR_INVALID_SHADER_NV => RenderError::InvalidShader { message: result.to_string() },
+			vk::Result::ERROR_FORMAT_NOT_SUPPORTED => RenderError::UnsupportedFormat,
+			_ => RenderError::Unknown(result.to_string()),
+		}
+	}
+}
+
+impl From<gpu_allocator::AllocationError> for RenderError {
+	fn from(err: gpu_allocator::AllocationError) -> Self {
+		match err {
+			gpu_allocator::AllocationError::OutOfMemory => RenderError::OutOfMemory,
+			_ => RenderError::Unknown(err.to_string()),
+		}
+	}
+}
+
+pub use buffer::{VulkanBuffer, VulkanMappedBuffer};
+pub use capture::{VulkanFrameCapture, VulkanReplayOutput};
+pub use descriptor::{VulkanDescriptorHandle, VulkanDescriptorHeap, VulkanDescriptorLayout, VulkanDescriptorLayoutCache, VulkanTransientDescriptor};
 pub use device::{VulkanDevice, VulkanUploadContext};
 pub use framebuffer::VulkanFramebuffer;
 pub use pipeline::VulkanPipeline;
 pub use render_pass::VulkanRenderPass;
 pub use shader::VulkanShader;
 pub use texture::VulkanTexture;
+pub use validation::{ValidationState, VALIDATION_LOG_TARGET};
 
 pub enum VulkanRasterCmd {
 	BindPipeline {
@@ -83,6 +128,18 @@ pub enum VulkanRasterCmd {
 		first_vertex: u32,
 		first_instance: u32,
 	},
+	DrawIndirect {
+		buffer: vk::Buffer,
+		offset: vk::DeviceSize,
+		draw_count: u32,
+		stride: u32,
+	},
+	DrawIndexedIndirect {
+		buffer: vk::Buffer,
+		offset: vk::DeviceSize,
+		draw_count: u32,
+		stride: u32,
+	},
 	BindDescriptor {
 		pipeline_bind_point: vk::PipelineBindPoint,
 		pipeline_layout: vk::PipelineLayout,
@@ -93,15 +150,90 @@ pub enum VulkanRasterCmd {
 		src_stage_mask: vk::PipelineStageFlags,
 		dst_stage_mask: vk::PipelineStageFlags,
 		dependency_flags: vk::DependencyFlags,
-		memory_barriers: Vec<vk::MemoryBarrier>,
-		buffer_memory_barriers: Vec<vk::BufferMemoryBarrier>,
-		image_memory_barriers: Vec<vk::ImageMemoryBarrier>,
+		memory_barriers: Range<usize>,
+		buffer_memory_barriers: Range<usize>,
+		image_memory_barriers: Range<usize>,
 	},
 	Dispatch {
 		group_count_x: u32,
 		group_count_y: u32,
 		group_count_z: u32,
 	},
+	CopyBuffer {
+		src: vk::Buffer,
+		dst: vk::Buffer,
+		region: vk::BufferCopy,
+	},
+	FillBuffer {
+		buffer: vk::Buffer,
+		offset: u64,
+		size: u64,
+		data: u32,
+	},
+	ClearColorImage {
+		image: vk::Image,
+		layout: vk::ImageLayout,
+		value: vk::ClearColorValue,
+		range: vk::ImageSubresourceRange,
+	},
+	ClearDepthStencilImage {
+		image: vk::Image,
+		layout: vk::ImageLayout,
+		value: vk::ClearDepthStencilValue,
+		range: vk::ImageSubresourceRange,
+	},
+	CopyImageToBuffer {
+		src: vk::Image,
+		src_layout: vk::ImageLayout,
+		dst: vk::Buffer,
+		region: vk::BufferImageCopy,
+	},
+	BlitImage {
+		src: vk::Image,
+		src_layout: vk::ImageLayout,
+		dst: vk::Image,
+		dst_layout: vk::ImageLayout,
+		region: vk::ImageBlit,
+		filter: vk::Filter,
+	},
+	PushConstants {
+		pipeline_layout: vk::PipelineLayout,
+		stage_flags: vk::ShaderStageFlags,
+		offset: u32,
+		data: Vec<u8>,
+	},
+	SetCheckpoint {
+		marker: u32,
+	},
+	BeginDebugLabel {
+		name: CString,
+	},
+	EndDebugLabel,
+	WriteBufferMarker {
+		stage: vk::PipelineStageFlags,
+		buffer: vk::Buffer,
+		offset: vk::DeviceSize,
+		marker: u32,
+	},
+	ResetQueryPool {
+		query_pool: vk::QueryPool,
+		first_query: u32,
+		query_count: u32,
+	},
+	BeginQuery {
+		query_pool: vk::QueryPool,
+		query: u32,
+		flags: vk::QueryControlFlags,
+	},
+	EndQuery {
+		query_pool: vk::QueryPool,
+		query: u32,
+	},
+	WriteTimestamp {
+		stage: vk::PipelineStageFlags,
+		query_pool: vk::QueryPool,
+		query: u32,
+	},
 	None,
 }
 
@@ -111,93 +243,43 @@ impl Default for VulkanRasterCmd {
 	}
 }
 
-pub struct VulkanUniformBufferUpdate {
-	pub buffer: vk::Buffer,
-	pub offset: usize,
-	pub range: usize,
+// PipelineBarrier commands hold ranges into here so queueing one doesn't allocate.
+#[derive(Default)]
+pub struct VulkanBarrierArena {
+	memory_barriers: Vec<vk::MemoryBarrier>,
+	buffer_memory_barriers: Vec<vk::BufferMemoryBarrier>,
+	image_memory_barriers: Vec<vk::ImageMemoryBarrier>,
 }
 
-impl VulkanDevice {
-	pub fn new_with_context(window: &Window) -> (Self, VulkanGraphicsContext) {
-		let device = VulkanDevice::new(window);
-		let swapchain = VulkanSwapchain::new(window.get_size(), device.clone());
-
-		(
-			device,
-			VulkanGraphicsContext {
-				swapchain,
-				current_frame_info: None,
-				raster_cmds: Default::default(),
-				frame_id: FrameId(0),
-			},
-		)
+impl VulkanBarrierArena {
+	fn push<T: Copy>(barriers: &mut Vec<T>, new: &[T]) -> Range<usize> {
+		let start = barriers.len();
+		barriers.extend_from_slice(new);
+		start..barriers.len()
 	}
-}
 
-pub struct VulkanGraphicsContext {
-	swapchain: VulkanSwapchain,
-	current_frame_info: Option<FrameInfo>,
-	raster_cmds: RefCell<Vec<VulkanRasterCmd>>,
-	frame_id: FrameId,
-}
-
-impl From<ClearValue> for vk::ClearValue {
-	fn from(c: ClearValue) -> Self {
-		match c {
-			ClearValue::Color { r, g, b, a } => vk::ClearValue {
-				color: vk::ClearColorValue { float32: [r, g, b, a] },
-			},
-			ClearValue::DepthStencil { depth, stencil } => vk::ClearValue {
-				depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
-			},
-		}
+	fn clear(&mut self) {
+		self.memory_barriers.clear();
+		self.buffer_memory_barriers.clear();
+		self.image_memory_barriers.clear();
 	}
 }
 
-impl VulkanGraphicsContext {
-	pub fn begin_frame(&mut self, window: &Window) -> Result<(), SwapchainError> {
-		assert!(self.current_frame_info.is_none(), "Did not call end_frame before starting another frame!");
-
-		self.frame_id.incr();
-		match self.swapchain.acquire() {
-			Ok(res) => {
-				self.current_frame_info = Some(res);
-
-				Ok(())
-			}
-			Err(err) => {
-				self.swapchain.invalidate(window.get_size());
-				Err(err)
-			}
-		}
-	}
-
-	pub fn end_frame(&mut self, window: &Window) {
-		if let Some(current_frame_info) = self.current_frame_info.take() {
-			self.fill_raster_cmds(current_frame_info.command_buffer);
-			if let Err(_) = self.swapchain.submit(current_frame_info.image_index, current_frame_info.command_buffer) {
-				self.swapchain.invalidate(window.get_size());
-			}
-		} else {
-			panic!("Did not call begin_frame first!");
-		}
-	}
-
-	pub fn queue_raster_cmd(&self, cmd: VulkanRasterCmd) {
-		self.raster_cmds.borrow_mut().push(cmd);
-	}
-
-	fn fill_raster_cmds(&self, cmd_buf: VulkanCommandBuffer) {
-		tracy::span!();
-		let raw = self.raw_device();
-		self.raster_cmds.take().into_iter().for_each(|cmd| unsafe {
-			match cmd {
+impl VulkanDevice {
+	pub fn record_raster_cmd(&self, cmd_buf: VulkanCommandBuffer, cmd: &VulkanRasterCmd, barriers: &VulkanBarrierArena) {
+		let raw = &self.raw;
+		unsafe {
+			match *cmd {
 				VulkanRasterCmd::BindPipeline { bind_point, pipeline } => raw.cmd_bind_pipeline(cmd_buf, bind_point, pipeline),
 				VulkanRasterCmd::BindVertexBuffer { first_binding, buffer, offset } => {
 					raw.cmd_bind_vertex_buffers(cmd_buf, first_binding, &[buffer], &[offset]);
 				}
-				VulkanRasterCmd::BindVertexBuffers { first_binding, buffers, offsets } => {
-					raw.cmd_bind_vertex_buffers(cmd_buf, first_binding, &buffers, &offsets);
+				VulkanRasterCmd::BindVertexBuffers {
+					first_binding,
+					ref buffers,
+					ref offsets,
+				} => {
+					raw.cmd_bind_vertex_buffers(cmd_buf, first_binding, buffers, offsets);
 				}
 				VulkanRasterCmd::BindIndexBuffer { buffer, offset, index_type } => {
 					raw.cmd_bind_index_buffer(cmd_buf, buffer, offset, index_type);
@@ -212,7 +294,7 @@ impl VulkanGraphicsContext {
 					render_pass,
 					framebuffer,
 					render_area,
-					clear_values,
+					ref clear_values,
 					subpass_contents,
 				} => {
 					raw.cmd_begin_render_pass(
@@ -221,7 +303,7 @@ impl VulkanGraphicsContext {
 							.render_pass(render_pass)
 							.framebuffer(framebuffer)
 							.render_area(render_area)
-							.clear_values(&clear_values),
+							.clear_values(clear_values),
 						subpass_contents,
 					);
 				}
@@ -245,17 +327,17 @@ impl VulkanGraphicsContext {
 					src_stage_mask,
 					dst_stage_mask,
 					dependency_flags,
-					memory_barriers,
-					buffer_memory_barriers,
-					image_memory_barriers,
+					ref memory_barriers,
+					ref buffer_memory_barriers,
+					ref image_memory_barriers,
 				} => raw.cmd_pipeline_barrier(
 					cmd_buf,
 					src_stage_mask,
 					dst_stage_mask,
 					dependency_flags,
-					&memory_barriers,
-					&buffer_memory_barriers,
-					&image_memory_barriers,
+					&barriers.memory_barriers[memory_barriers.clone()],
+					&barriers.buffer_memory_barriers[buffer_memory_barriers.clone()],
+					&barriers.image_memory_barriers[image_memory_barriers.clone()],
 				),
 				VulkanRasterCmd::Draw {
 					vertex_count,
@@ -263,18 +345,624 @@ impl VulkanGraphicsContext {
 					first_vertex,
 					first_instance,
 				} => raw.cmd_draw(cmd_buf, vertex_count, instance_count, first_vertex, first_instance),
+				VulkanRasterCmd::DrawIndirect { buffer, offset, draw_count, stride } => raw.cmd_draw_indirect(cmd_buf, buffer, offset, draw_count, stride),
+				VulkanRasterCmd::DrawIndexedIndirect { buffer, offset, draw_count, stride } => raw.cmd_draw_indexed_indirect(cmd_buf, buffer, offset, draw_count, stride),
 				VulkanRasterCmd::Dispatch {
 					group_count_x,
 					group_count_y,
 					group_count_z,
 				} => raw.cmd_dispatch(cmd_buf, group_count_x, group_count_y, group_count_z),
+				VulkanRasterCmd::CopyBuffer { src, dst, region } => raw.cmd_copy_buffer(cmd_buf, src, dst, &[region]),
+				VulkanRasterCmd::FillBuffer { buffer, offset, size, data } => raw.cmd_fill_buffer(cmd_buf, buffer, offset, size, data),
+				VulkanRasterCmd::ClearColorImage { image, layout, value, range } => raw.cmd_clear_color_image(cmd_buf, image, layout, &value, &[range]),
+				VulkanRasterCmd::ClearDepthStencilImage { image, layout, value, range } => raw.cmd_clear_depth_stencil_image(cmd_buf, image, layout, &value, &[range]),
+				VulkanRasterCmd::CopyImageToBuffer { src, src_layout, dst, region } => raw.cmd_copy_image_to_buffer(cmd_buf, src, src_layout, dst, &[region]),
+				VulkanRasterCmd::BlitImage {
+					src,
+					src_layout,
+					dst,
+					dst_layout,
+					region,
+					filter,
+				} => raw.cmd_blit_image(cmd_buf, src, src_layout, dst, dst_layout, &[region], filter),
+				VulkanRasterCmd::PushConstants {
+					pipeline_layout,
+					stage_flags,
+					offset,
+					ref data,
+				} => raw.cmd_push_constants(cmd_buf, pipeline_layout, stage_flags, offset, data),
+				VulkanRasterCmd::SetCheckpoint { marker } => {
+					let checkpoints = self.checkpoints.as_ref().expect("Checkpoints are not supported!");
+					checkpoints.cmd_set_checkpoint(cmd_buf, marker as usize as *const std::ffi::c_void);
+				}
+				VulkanRasterCmd::WriteBufferMarker { stage, buffer, offset, marker } => {
+					let buffer_marker = self.buffer_marker.as_ref().expect("Buffer markers are not supported!");
+					(buffer_marker.cmd_write_buffer_marker_amd)(cmd_buf, stage, buffer, offset, marker);
+				}
+				VulkanRasterCmd::BeginDebugLabel { ref name } => {
+					let label = vk::DebugUtilsLabelEXT::builder().label_name(name);
+					self.debug_utils_loader.cmd_begin_debug_utils_label(cmd_buf, &label);
+				}
+				VulkanRasterCmd::EndDebugLabel => self.debug_utils_loader.cmd_end_debug_utils_label(cmd_buf),
+				VulkanRasterCmd::ResetQueryPool { query_pool, first_query, query_count } => raw.cmd_reset_query_pool(cmd_buf, query_pool, first_query, query_count),
+				VulkanRasterCmd::BeginQuery { query_pool, query, flags } => raw.cmd_begin_query(cmd_buf, query_pool, query, flags),
+				VulkanRasterCmd::EndQuery { query_pool, query } => raw.cmd_end_query(cmd_buf, query_pool, query),
+				VulkanRasterCmd::WriteTimestamp { stage, query_pool, query } => raw.cmd_write_timestamp(cmd_buf, stage, query_pool, query),
 				VulkanRasterCmd::None => panic!("None raster command queued!"),
 			}
+		}
+	}
+}
+
+// Render passes with fewer commands than this are cheaper to record inline than to hand off to another thread.
+const SECONDARY_MIN_CMDS: usize = 256;
+const SECONDARY_CHUNK_CMDS: usize = 512;
+
+// State that has to be set again at the start of each secondary command buffer, since secondaries don't inherit any of it from the primary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StateKey {
+	Pipeline(vk::PipelineBindPoint),
+	Descriptor(vk::PipelineBindPoint, u32),
+	PushConstants(u32),
+	VertexBuffers(u32),
+	IndexBuffer,
+	Viewport,
+	Scissor,
+}
+
+impl VulkanRasterCmd {
+	fn state_key(&self) -> Option<StateKey> {
+		match *self {
+			Self::BindPipeline { bind_point, .. } => Some(StateKey::Pipeline(bind_point)),
+			Self::BindDescriptor { pipeline_bind_point, first_set, .. } => Some(StateKey::Descriptor(pipeline_bind_point, first_set)),
+			Self::PushConstants { offset, .. } => Some(StateKey::PushConstants(offset)),
+			Self::BindVertexBuffer { first_binding, .. } | Self::BindVertexBuffers { first_binding, .. } => Some(StateKey::VertexBuffers(first_binding)),
+			Self::BindIndexBuffer { .. } => Some(StateKey::IndexBuffer),
+			Self::SetViewport { .. } => Some(StateKey::Viewport),
+			Self::SetScissor { .. } => Some(StateKey::Scissor),
+			_ => None,
+		}
+	}
+
+	fn is_secondary_safe(&self) -> bool {
+		matches!(self, Self::Draw { .. } | Self::DrawIndexed { .. } | Self::DrawIndirect { .. } | Self::DrawIndexedIndirect { .. }) || self.state_key().is_some()
+	}
+
+	fn for_each_buffer(&self, mut f: impl FnMut(vk::Buffer)) {
+		match self {
+			Self::BindVertexBuffer { buffer, .. }
+			| Self::BindIndexBuffer { buffer, .. }
+			| Self::DrawIndirect { buffer, .. }
+			| Self::DrawIndexedIndirect { buffer, .. }
+			| Self::FillBuffer { buffer, .. }
+			| Self::WriteBufferMarker { buffer, .. }
+			| Self::CopyImageToBuffer { dst: buffer, .. } => f(*buffer),
+			Self::BindVertexBuffers { buffers, .. } => buffers.iter().copied().for_each(f),
+			Self::CopyBuffer { src, dst, .. } => {
+				f(*src);
+				f(*dst);
+			}
+			_ => {}
+		}
+	}
+}
+
+struct SecondaryChunk<'a>(Vec<&'a VulkanRasterCmd>);
+
+enum CmdSegment<'a> {
+	Inline(&'a [VulkanRasterCmd]),
+	// Executing secondaries leaves the state undefined, so it gets set again on the primary once the pass is over.
+	RenderPass {
+		render_pass: vk::RenderPass,
+		framebuffer: vk::Framebuffer,
+		render_area: vk::Rect2D,
+		clear_values: &'a [vk::ClearValue],
+		chunks: Vec<SecondaryChunk<'a>>,
+		restore: Vec<&'a VulkanRasterCmd>,
+	},
+}
+
+fn track_state<'a>(state: &mut Vec<(StateKey, &'a VulkanRasterCmd)>, cmd: &'a VulkanRasterCmd) {
+	if let Some(key) = cmd.state_key() {
+		state.retain(|(existing, _)| *existing != key);
+		state.push((key, cmd));
+	}
+}
+
+fn split_cmd_list<'a>(cmd_list: &'a [VulkanRasterCmd], state: &mut Vec<(StateKey, &'a VulkanRasterCmd)>, segments: &mut Vec<CmdSegment<'a>>) {
+	let mut inline_start = 0;
+	let mut i = 0;
+
+	while i < cmd_list.len() {
+		let (render_pass, framebuffer, render_area, clear_values) = match cmd_list[i] {
+			VulkanRasterCmd::BeginRenderPass {
+				render_pass,
+				framebuffer,
+				render_area,
+				ref clear_values,
+				..
+			} => (render_pass, framebuffer, render_area, clear_values),
+			ref cmd => {
+				track_state(state, cmd);
+				i += 1;
+				continue;
+			}
+		};
+
+		let end = i + cmd_list[i..]
+			.iter()
+			.position(|cmd| matches!(cmd, VulkanRasterCmd::EndRenderPass {}))
+			.expect("Render pass was never ended!");
+		let contents = &cmd_list[i + 1..end];
+
+		if contents.len() < SECONDARY_MIN_CMDS || !contents.iter().all(VulkanRasterCmd::is_secondary_safe) {
+			contents.iter().for_each(|cmd| track_state(state, cmd));
+			i = end + 1;
+			continue;
+		}
+
+		if inline_start < i {
+			segments.push(CmdSegment::Inline(&cmd_list[inline_start..i]));
+		}
+
+		let chunks = contents
+			.chunks(SECONDARY_CHUNK_CMDS)
+			.map(|chunk| {
+				let mut cmds = state.iter().map(|&(_, cmd)| cmd).collect::<Vec<_>>();
+				chunk.iter().for_each(|cmd| track_state(state, cmd));
+				cmds.extend(chunk);
+				SecondaryChunk(cmds)
+			})
+			.collect();
+
+		segments.push(CmdSegment::RenderPass {
+			render_pass,
+			framebuffer,
+			render_area,
+			clear_values,
+			chunks,
+			restore: state.iter().map(|&(_, cmd)| cmd).collect(),
+		});
+
+		i = end + 1;
+		inline_start = i;
+	}
+
+	if inline_start < cmd_list.len() {
+		segments.push(CmdSegment::Inline(&cmd_list[inline_start..]));
+	}
+}
+
+pub struct VulkanUniformBufferUpdate {
+	pub buffer: vk::Buffer,
+	pub offset: usize,
+	pub range: usize,
+}
+
+impl VulkanDevice {
+	pub fn new_with_context(
+		window: &Window,
+		frames_in_flight: usize,
+		validation: Option<&ValidationDesc>,
+		device_selection: DeviceSelection,
+		frame_capture: bool,
+		surface_preference: SurfacePreference,
+		present_mode: PresentMode,
+	) -> (Self, VulkanGraphicsContext) {
+		let (device, surface) = VulkanDevice::new_with_surface(window, frames_in_flight, validation, device_selection, frame_capture);
+		let context = VulkanGraphicsContext::new_with_surface(&device, window, surface, surface_preference, present_mode);
+
+		(device, context)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputState {
+	Untouched,
+	// Blitted or drawn to and in PRESENT_SRC, the output render pass loads it instead of clearing it.
+	Written,
+	Storage,
+}
+
+// A write into a CpuToGpu buffer that's held off until right before the frame is submitted, see register_late_update.
+struct LateUpdate {
+	mapped: NonNull<u8>,
+	size: usize,
+	flush: Option<vk::MappedMemoryRange>,
+	callback: Box<dyn Fn() -> Vec<u8>>,
+}
+
+pub struct VulkanGraphicsContext {
+	swapchain: VulkanSwapchain,
+	current_frame_info: Option<FrameInfo>,
+	output_state: Cell<OutputState>,
+	cmd_lists: RefCell<Vec<Vec<VulkanRasterCmd>>>,
+	barriers: RefCell<VulkanBarrierArena>,
+	bound_state: RefCell<VulkanBoundState>,
+	redundant_cmds: Cell<usize>,
+	last_redundant_cmds: usize,
+	late_updates: RefCell<Vec<LateUpdate>>,
+	parallel_recording: bool,
+	frame_index: u64,
+	query_results: HashMap<QuerySlot, QueryResult>,
+	gpu_frame_time: Option<Duration>,
+	next_readback_id: u64,
+	completed_readbacks: HashMap<ReadbackId, Vec<u8>>,
+	requested_capture: Option<PathBuf>,
+	capturing: Option<PathBuf>,
+}
+
+// Ends the frame when it's dropped. A discarded frame (or one dropped while panicking) submits nothing, the output just gets cleared.
+pub struct VulkanFrameGuard<'a> {
+	context: &'a mut VulkanGraphicsContext,
+	window: &'a Window,
+	discarded: bool,
+}
+
+impl VulkanFrameGuard<'_> {
+	// For when something went wrong halfway through recording, whatever was recorded might reference resources that are gone.
+	pub fn discard(&mut self) {
+		self.discarded = true;
+	}
+
+	pub fn is_discarded(&self) -> bool {
+		self.discarded
+	}
+}
+
+impl Deref for VulkanFrameGuard<'_> {
+	type Target = VulkanGraphicsContext;
+
+	fn deref(&self) -> &Self::Target {
+		self.context
+	}
+}
+
+impl DerefMut for VulkanFrameGuard<'_> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.context
+	}
+}
+
+impl Drop for VulkanFrameGuard<'_> {
+	fn drop(&mut self) {
+		if self.discarded || std::thread::panicking() {
+			self.context.discard_frame();
+		}
+
+		self.context.end_frame(self.window);
+	}
+}
+
+impl From<ClearValue> for vk::ClearValue {
+	fn from(c: ClearValue) -> Self {
+		match c {
+			ClearValue::Color { r, g, b, a } => vk::ClearValue {
+				color: vk::ClearColorValue { float32: [r, g, b, a] },
+			},
+			ClearValue::DepthStencil { depth, stencil } => vk::ClearValue {
+				depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+			},
+		}
+	}
+}
+
+impl VulkanGraphicsContext {
+	pub fn new(device: &VulkanDevice, window: &Window, surface_preference: SurfacePreference, present_mode: PresentMode) -> GoldfishResult<Self> {
+		let surface = device.create_surface(window);
+
+		if !device.supports_surface(surface) {
+			device.destroy_surface(surface);
+			return Err(GoldfishError::Render(RenderError::UnsupportedSurface));
+		}
+
+		Ok(Self::new_with_surface(device, window, surface, surface_preference, present_mode))
+	}
+
+	fn new_with_surface(device: &VulkanDevice, window: &Window, surface: vk::SurfaceKHR, surface_preference: SurfacePreference, present_mode: PresentMode) -> Self {
+		Self {
+			swapchain: VulkanSwapchain::new(window.get_size().into(), device.clone(), surface, surface_preference, present_mode),
+			current_frame_info: None,
+			output_state: Cell::new(OutputState::Untouched),
+			cmd_lists: Default::default(),
+			barriers: Default::default(),
+			bound_state: Default::default(),
+			redundant_cmds: Cell::new(0),
+			last_redundant_cmds: 0,
+			late_updates: Default::default(),
+			parallel_recording: false,
+			frame_index: 0,
+			query_results: Default::default(),
+			gpu_frame_time: None,
+			next_readback_id: 0,
+			completed_readbacks: Default::default(),
+			requested_capture: None,
+			capturing: None,
+		}
+	}
+
+	// An error means there's no frame to record this time around, usually because the swapchain is being recreated.
+	pub fn begin_frame<'a>(&'a mut self, window: &'a Window) -> Result<VulkanFrameGuard<'a>, SwapchainError> {
+		self.acquire_frame(window)?;
+
+		Ok(VulkanFrameGuard {
+			context: self,
+			window,
+			discarded: false,
+		})
+	}
+
+	pub fn frame<R>(&mut self, window: &Window, f: impl FnOnce(&mut VulkanFrameGuard) -> R) -> Result<R, SwapchainError> {
+		let mut frame = self.begin_frame(window)?;
+		Ok(f(&mut frame))
+	}
+
+	fn acquire_frame(&mut self, window: &Window) -> Result<(), SwapchainError> {
+		assert!(self.current_frame_info.is_none(), "Did not end the last frame before starting another one!");
+
+		match self.swapchain.acquire() {
+			Ok(res) => {
+				let queries = &mut self.swapchain.frames[res.frame_slot].queries;
+				queries.read_results(&self.swapchain.device, &mut self.query_results);
+
+				if let Some(gpu_frame_time) = queries.read_frame_time(&self.swapchain.device) {
+					self.gpu_frame_time = Some(gpu_frame_time);
+				}
+
+				self.queue_frame_start(res.frame_slot);
+				self.swapchain.frames[res.frame_slot].transient_descriptors.reset(&self.swapchain.device);
+				self.reset_pass_diagnostics(res.frame_slot);
+				self.recycle_readbacks(res.frame_slot);
+
+				self.current_frame_info = Some(res);
+				self.output_state.set(OutputState::Untouched);
+				self.capturing = self.requested_capture.take();
+
+				Ok(())
+			}
+			Err(SwapchainError::DeviceLost) => self.handle_device_lost(),
+			Err(err) => {
+				self.swapchain.invalidate(window.get_size().into());
+				Err(err)
+			}
+		}
+	}
+
+	fn queue_frame_start(&self, frame_slot: usize) {
+		let queries = &self.swapchain.frames[frame_slot].queries;
+		let timestamp_pool = queries.timestamp_pool();
+
+		for query_pool in queries.pools() {
+			self.queue_raster_cmd(VulkanRasterCmd::ResetQueryPool {
+				query_pool,
+				first_query: 0,
+				query_count: QuerySlot::MAX_QUERIES,
+			});
+		}
+
+		if let Some(query_pool) = timestamp_pool {
+			self.queue_raster_cmd(VulkanRasterCmd::ResetQueryPool {
+				query_pool,
+				first_query: 0,
+				query_count: 2,
+			});
+			self.queue_raster_cmd(VulkanRasterCmd::WriteTimestamp {
+				stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+				query_pool,
+				query: VulkanQueryPools::FRAME_START_TIMESTAMP,
+			});
+		}
+	}
+
+	// The image has been acquired by now, so the cleared frame still has to be presented or the acquire semaphore is left signaled.
+	fn discard_frame(&mut self) {
+		let frame_slot = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
+
+		self.cmd_lists.borrow_mut().clear();
+		self.barriers.borrow_mut().clear();
+		self.bound_state.borrow_mut().reset();
+		self.redundant_cmds.set(0);
+		self.late_updates.borrow_mut().clear();
+		self.swapchain.frames[frame_slot].queries.forget_used();
+		self.reset_pass_diagnostics(frame_slot);
+		self.discard_readbacks(frame_slot);
+
+		self.queue_frame_start(frame_slot);
+		self.output_state.set(OutputState::Untouched);
+		self.begin_output_render_pass(&[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+		self.end_render_pass();
+	}
+
+	fn end_frame(&mut self, window: &Window) {
+		if self.output_state.get() == OutputState::Storage {
+			self.begin_cmd_list();
+			self.end_output_storage();
+		}
+
+		if let Some(current_frame_info) = self.current_frame_info.take() {
+			let queries = &mut self.swapchain.frames[current_frame_info.frame_slot].queries;
+			if let Some(query_pool) = queries.timestamp_pool() {
+				queries.mark_timestamps_written();
+				self.cmd_lists.borrow_mut().push(vec![VulkanRasterCmd::WriteTimestamp {
+					stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+					query_pool,
+					query: VulkanQueryPools::FRAME_END_TIMESTAMP,
+				}]);
+			}
+
+			let capture = self.capturing.take().map(|path| (self.begin_capture(&current_frame_info), path));
+
+			let command_buffer = self.swapchain.begin_batch();
+			self.fill_raster_cmds(command_buffer, current_frame_info.frame_slot);
+			self.submit_readbacks(current_frame_info.frame_slot);
+			self.run_late_updates();
+			if let Some((pending, path)) = capture {
+				self.finish_capture(&path, pending);
+			}
+			self.frame_index += 1;
+			self.swapchain.device.forget_recorded_buffers();
+			self.last_redundant_cmds = self.redundant_cmds.replace(0);
+			match self.swapchain.submit(current_frame_info.image_index, command_buffer) {
+				Ok(_) if current_frame_info.suboptimal => self.swapchain.invalidate(window.get_size().into()),
+				Ok(_) => {}
+				Err(SwapchainError::DeviceLost) => self.handle_device_lost(),
+				Err(_) => self.swapchain.invalidate(window.get_size().into()),
+			}
+		} else {
+			panic!("Did not call begin_frame first!");
+		}
+	}
+
+	// Submits everything queued so far right away, so the GPU can get started while the rest is recorded. Needs timeline semaphores.
+	pub fn submit_batch(&mut self) {
+		tracy::span!();
+		let frame_slot = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
+		if !self.supports_submit_batches() || self.cmd_lists.borrow().iter().all(|cmd_list| cmd_list.is_empty()) {
+			return;
+		}
+
+		if self.capturing.is_some() {
+			return;
+		}
+
+		let command_buffer = self.swapchain.begin_batch();
+		self.fill_raster_cmds(command_buffer, frame_slot);
+		self.run_late_updates();
+
+		let writes_output = self.output_state.get() != OutputState::Untouched;
+		if let Err(SwapchainError::DeviceLost) = self.swapchain.submit_batch(command_buffer, writes_output) {
+			self.handle_device_lost();
+		}
+	}
+
+	pub fn supports_submit_batches(&self) -> bool {
+		self.swapchain.device.timeline_semaphore_supported
+	}
+
+	// As late as the CPU can write anything the frame reads. With the frame split into batches they run before the next batch instead.
+	fn run_late_updates(&self) {
+		tracy::span!();
+		let mut flushes = Vec::new();
+		for late_update in self.late_updates.take() {
+			let data = (late_update.callback)();
+			assert!(data.len() <= late_update.size, "Late update wrote more than fits in its buffer!");
+
+			unsafe {
+				std::ptr::copy_nonoverlapping(data.as_ptr(), late_update.mapped.as_ptr(), data.len());
+			}
+			flushes.extend(late_update.flush);
+		}
+
+		self.swapchain.device.flush_mapped_ranges(&flushes);
+	}
+
+	pub fn begin_cmd_list(&self) {
+		self.cmd_lists.borrow_mut().push(Vec::new());
+	}
+
+	pub fn queue_raster_cmd(&self, cmd: VulkanRasterCmd) {
+		cmd.for_each_buffer(|buffer| self.swapchain.device.record_buffer_use(buffer));
+
+		if !self.bound_state.borrow_mut().bind(&cmd) {
+			self.redundant_cmds.set(self.redundant_cmds.get() + 1);
+			return;
+		}
+
+		let mut cmd_lists = self.cmd_lists.borrow_mut();
+		match cmd_lists.last_mut() {
+			Some(cmd_list) => cmd_list.push(cmd),
+			None => cmd_lists.push(vec![cmd]),
+		}
+	}
+
+	fn fill_raster_cmds(&self, cmd_buf: VulkanCommandBuffer, frame_slot: usize) {
+		tracy::span!();
+		let device = &self.swapchain.device;
+		let cmd_lists = self.cmd_lists.take();
+		self.bound_state.borrow_mut().reset();
+		let mut barriers = self.barriers.borrow_mut();
+
+		if !self.parallel_recording {
+			for cmd in cmd_lists.iter().flatten() {
+				device.record_raster_cmd(cmd_buf, cmd, &barriers);
+			}
+			barriers.clear();
+			return;
+		}
+
+		let mut state = Vec::new();
+		let mut segments = Vec::new();
+		for cmd_list in &cmd_lists {
+			split_cmd_list(cmd_list, &mut state, &mut segments);
+		}
+
+		let owner = self.swapchain.frames[frame_slot].completed_fence.raw;
+		let jobs = segments
+			.iter()
+			.flat_map(|segment| match segment {
+				CmdSegment::Inline(_) => Vec::new(),
+				CmdSegment::RenderPass { render_pass, framebuffer, chunks, .. } => chunks.iter().map(|chunk| (*render_pass, *framebuffer, chunk)).collect(),
+			})
+			.collect::<Vec<_>>();
+
+		let secondaries = crate::jobs::install(|| {
+			jobs.par_iter()
+				.map(|&(render_pass, framebuffer, chunk)| device.record_secondary_command_buffer(owner, render_pass, framebuffer, &chunk.0))
+				.collect::<Vec<_>>()
 		});
+
+		let mut secondaries = secondaries.into_iter();
+		for segment in &segments {
+			match segment {
+				CmdSegment::Inline(cmds) => {
+					for cmd in cmds.iter() {
+						device.record_raster_cmd(cmd_buf, cmd, &barriers);
+					}
+				}
+				CmdSegment::RenderPass {
+					render_pass,
+					framebuffer,
+					render_area,
+					clear_values,
+					chunks,
+					restore,
+				} => unsafe {
+					let command_buffers = secondaries.by_ref().take(chunks.len()).collect::<Vec<_>>();
+
+					device.raw.cmd_begin_render_pass(
+						cmd_buf,
+						&vk::RenderPassBeginInfo::builder()
+							.render_pass(*render_pass)
+							.framebuffer(*framebuffer)
+							.render_area(*render_area)
+							.clear_values(clear_values),
+						vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+					);
+					device.raw.cmd_execute_commands(cmd_buf, &command_buffers);
+					device.raw.cmd_end_render_pass(cmd_buf);
+
+					for cmd in restore {
+						device.record_raster_cmd(cmd_buf, cmd, &barriers);
+					}
+				},
+			}
+		}
+
+		barriers.clear();
+	}
+
+	// Off by default. Big render passes get recorded into secondary command buffers across the job pool at the end of the frame.
+	pub fn set_parallel_recording(&mut self, enabled: bool) {
+		self.parallel_recording = enabled;
+	}
+
+	pub fn parallel_recording(&self) -> bool {
+		self.parallel_recording
 	}
 
 	pub fn begin_output_render_pass(&self, clear_values: &[ClearValue]) {
 		tracy::span!();
+		self.end_output_storage();
 
 		self.queue_raster_cmd(VulkanRasterCmd::SetViewport {
 			viewport: vk::Viewport::builder()
@@ -291,8 +979,13 @@ impl VulkanGraphicsContext {
 			scissor: vk::Rect2D::builder().offset(vk::Offset2D { x: 0, y: 0 }).extent(self.swapchain.extent).build(),
 		});
 
+		let render_pass = match self.output_state.replace(OutputState::Written) {
+			OutputState::Untouched => self.swapchain.render_pass,
+			_ => self.swapchain.load_render_pass,
+		};
+
 		self.queue_raster_cmd(VulkanRasterCmd::BeginRenderPass {
-			render_pass: self.swapchain.render_pass,
+			render_pass,
 			framebuffer: self.get_output_framebuffer(),
 			render_area: vk::Rect2D {
 				offset: vk::Offset2D { x: 0, y: 0 },
@@ -336,10 +1029,250 @@ impl VulkanGraphicsContext {
 		});
 	}
 
+	pub fn set_viewport(&self, x: u32, y: u32, width: u32, height: u32) {
+		self.queue_raster_cmd(VulkanRasterCmd::SetViewport {
+			viewport: vk::Viewport::builder()
+				.x(x as f32)
+				.y((y + height) as f32)
+				.width(width as f32)
+				.height(-(height as f32))
+				.min_depth(0.0)
+				.max_depth(1.0)
+				.build(),
+		});
+
+		self.queue_raster_cmd(VulkanRasterCmd::SetScissor {
+			scissor: vk::Rect2D::builder().offset(vk::Offset2D { x: x as i32, y: y as i32 }).extent(vk::Extent2D { width, height }).build(),
+		});
+	}
+
 	pub fn end_render_pass(&self) {
 		self.queue_raster_cmd(VulkanRasterCmd::EndRenderPass {});
 	}
 
+	// FIFO presentation already blocks on vblank, so there is no point in limiting the frame rate on top of it.
+	pub fn is_vsync(&self) -> bool {
+		self.swapchain.present_mode == vk::PresentModeKHR::FIFO
+	}
+
+	pub fn surface_format(&self) -> SurfaceFormat {
+		self.swapchain.surface_format
+	}
+
+	pub fn output_is_srgb(&self) -> bool {
+		self.swapchain.surface_format == SurfaceFormat::Srgb
+	}
+
+	pub fn output_size(&self) -> Size {
+		Size {
+			width: self.swapchain.extent.width,
+			height: self.swapchain.extent.height,
+		}
+	}
+
+	pub fn swapchain_info(&self) -> SwapchainInfo {
+		self.swapchain.info()
+	}
+
+	pub fn frame_pacing(&self) -> FramePacingStats {
+		self.swapchain.last_pacing()
+	}
+
+	pub fn get_output_generation(&self) -> u64 {
+		self.swapchain.generation
+	}
+
+	// The texture has to already be in src_layout. The rest of the image is cleared to bar_clear, or left undefined without one.
+	pub fn blit_to_output(&self, texture: &VulkanTexture, src_layout: ImageLayout, filter: Filter, viewport: OutputViewport, bar_clear: Option<ClearValue>) {
+		assert!(self.swapchain.supports_blit, "This surface doesn't support blitting to the swapchain!");
+		let output_image = self.current_frame_info.as_ref().expect("begin_frame was not called!").output_image;
+		self.end_output_storage();
+
+		let color_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1)
+			.build();
+
+		let layers = |aspect_mask| vk::ImageSubresourceLayers::builder().aspect_mask(aspect_mask).mip_level(0).base_array_layer(0).layer_count(1).build();
+
+		// The submit only waits on the acquire semaphore at COLOR_ATTACHMENT_OUTPUT, so the transition has to chain off of that stage.
+		self.pipeline_barrier(
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::DependencyFlags::empty(),
+			&[],
+			&[],
+			&[vk::ImageMemoryBarrier::builder()
+				.old_layout(vk::ImageLayout::UNDEFINED)
+				.new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+				.image(output_image)
+				.subresource_range(color_range)
+				.src_access_mask(vk::AccessFlags::empty())
+				.dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+				.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.build()],
+		);
+
+		if let Some(ClearValue::Color { r, g, b, a }) = bar_clear {
+			self.queue_raster_cmd(VulkanRasterCmd::ClearColorImage {
+				image: output_image,
+				layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				value: vk::ClearColorValue { float32: [r, g, b, a] },
+				range: color_range,
+			});
+
+			self.pipeline_barrier(
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[vk::ImageMemoryBarrier::builder()
+					.old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+					.new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+					.image(output_image)
+					.subresource_range(color_range)
+					.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+					.dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+					.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.build()],
+			);
+		}
+
+		self.queue_raster_cmd(VulkanRasterCmd::BlitImage {
+			src: texture.image,
+			src_layout: src_layout.into(),
+			dst: output_image,
+			dst_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			region: vk::ImageBlit::builder()
+				.src_subresource(layers(texture.subresource_range.aspect_mask))
+				.src_offsets([
+					vk::Offset3D { x: 0, y: 0, z: 0 },
+					vk::Offset3D {
+						x: texture.width as i32,
+						y: texture.height as i32,
+						z: 1,
+					},
+				])
+				.dst_subresource(layers(vk::ImageAspectFlags::COLOR))
+				.dst_offsets([
+					vk::Offset3D {
+						x: viewport.offset.x as i32,
+						y: viewport.offset.y as i32,
+						z: 0,
+					},
+					vk::Offset3D {
+						x: (viewport.offset.x + viewport.extent.x) as i32,
+						y: (viewport.offset.y + viewport.extent.y) as i32,
+						z: 1,
+					},
+				])
+				.build(),
+			filter: match filter {
+				Filter::Nearest => vk::Filter::NEAREST,
+				Filter::Linear => vk::Filter::LINEAR,
+			},
+		});
+
+		self.pipeline_barrier(
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			vk::DependencyFlags::empty(),
+			&[],
+			&[],
+			&[vk::ImageMemoryBarrier::builder()
+				.old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+				.new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+				.image(output_image)
+				.subresource_range(color_range)
+				.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+				.dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+				.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.build()],
+		);
+
+		self.output_state.set(OutputState::Written);
+	}
+
+	// Whether compute shaders can write straight into the swapchain image, otherwise the output has to be drawn (or blitted) to.
+	pub fn supports_output_storage(&self) -> bool {
+		self.swapchain.supports_storage
+	}
+
+	pub fn output_storage_texture(&self) -> &VulkanTexture {
+		let image_index = self.current_frame_info.as_ref().expect("begin_frame was not called!").image_index;
+		self.swapchain
+			.storage_texture(image_index)
+			.expect("This surface doesn't support writing to the swapchain from a shader!")
+	}
+
+	pub fn begin_output_storage(&self) {
+		let output_image = self.current_frame_info.as_ref().expect("begin_frame was not called!").output_image;
+		assert!(self.swapchain.supports_storage, "This surface doesn't support writing to the swapchain from a shader!");
+
+		// Chains off of the stage the acquire semaphore is waited on at, same as blit_to_output.
+		let (old_layout, src_stage, src_access) = match self.output_state.replace(OutputState::Storage) {
+			OutputState::Untouched => (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::empty()),
+			OutputState::Written => (
+				vk::ImageLayout::PRESENT_SRC_KHR,
+				vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+			),
+			OutputState::Storage => (vk::ImageLayout::GENERAL, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE),
+		};
+
+		self.pipeline_barrier(
+			src_stage,
+			vk::PipelineStageFlags::COMPUTE_SHADER,
+			vk::DependencyFlags::empty(),
+			&[],
+			&[],
+			&[vk::ImageMemoryBarrier::builder()
+				.old_layout(old_layout)
+				.new_layout(vk::ImageLayout::GENERAL)
+				.image(output_image)
+				.subresource_range(self.output_storage_texture().subresource_range)
+				.src_access_mask(src_access)
+				.dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+				.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.build()],
+		);
+	}
+
+	fn end_output_storage(&self) {
+		if self.output_state.get() != OutputState::Storage {
+			return;
+		}
+
+		let output_image = self.current_frame_info.as_ref().expect("begin_frame was not called!").output_image;
+		self.pipeline_barrier(
+			vk::PipelineStageFlags::COMPUTE_SHADER,
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			vk::DependencyFlags::empty(),
+			&[],
+			&[],
+			&[vk::ImageMemoryBarrier::builder()
+				.old_layout(vk::ImageLayout::GENERAL)
+				.new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+				.image(output_image)
+				.subresource_range(self.output_storage_texture().subresource_range)
+				.src_access_mask(vk::AccessFlags::SHADER_WRITE)
+				.dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+				.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+				.build()],
+		);
+
+		self.output_state.set(OutputState::Written);
+	}
+
 	fn get_output_framebuffer(&self) -> vk::Framebuffer {
 		self.current_frame_info.as_ref().expect("begin_frame was not called!").output_framebuffer
 	}
@@ -360,36 +1293,81 @@ impl VulkanGraphicsContext {
 		&mut self,
 		vs: &VulkanShader,
 		ps: Option<&VulkanShader>,
+		gs: Option<&VulkanShader>,
 		descriptor_layouts: &[VulkanDescriptorLayout],
 		depth_compare_op: Option<DepthCompareOp>,
 		depth_write: bool,
+		depth_bias: Option<DepthBias>,
+		blend_mode: BlendMode,
 		face_cull: FaceCullMode,
 		push_constant_bytes: usize,
 		vertex_input_info: VertexInputInfo,
 		polygon_mode: PolygonMode,
-	) -> VulkanPipeline {
+		specialization: &[(u32, SpecValue)],
+	) -> GoldfishResult<VulkanPipeline> {
 		self.swapchain.device.create_raster_pipeline_impl(
 			vs,
 			ps,
+			gs,
 			descriptor_layouts,
 			self.swapchain.render_pass,
 			1usize,
 			depth_compare_op,
 			depth_write,
+			depth_bias,
+			blend_mode,
 			face_cull,
 			push_constant_bytes,
 			vertex_input_info,
 			polygon_mode,
+			specialization,
 		)
 	}
 
-	pub fn draw_indexed(&self, index_count: u32) {
+	pub fn begin_query(&mut self, slot: QuerySlot) {
+		let frame_slot = self.current_frame_info.as_ref().expect("Queries can only be recorded during a frame!").frame_slot;
+		let queries = &mut self.swapchain.frames[frame_slot].queries;
+		queries.mark_used(slot);
+
+		if let Some(query_pool) = queries.pool(slot.kind) {
+			let flags = if slot.kind == QueryKind::Occlusion && self.swapchain.device.enabled_features.occlusion_query_precise != 0 {
+				vk::QueryControlFlags::PRECISE
+			} else {
+				vk::QueryControlFlags::default()
+			};
+
+			self.queue_raster_cmd(VulkanRasterCmd::BeginQuery { query_pool, query: slot.index, flags });
+		}
+	}
+
+	pub fn end_query(&mut self, slot: QuerySlot) {
+		let frame_slot = self.current_frame_info.as_ref().expect("Queries can only be recorded during a frame!").frame_slot;
+
+		if let Some(query_pool) = self.swapchain.frames[frame_slot].queries.pool(slot.kind) {
+			self.queue_raster_cmd(VulkanRasterCmd::EndQuery { query_pool, query: slot.index });
+		}
+	}
+
+	// Results lag a couple of frames behind, until the frame that ran them comes around again.
+	pub fn query_results(&self) -> &HashMap<QuerySlot, QueryResult> {
+		&self.query_results
+	}
+
+	pub fn gpu_frame_time(&self) -> Option<Duration> {
+		self.gpu_frame_time
+	}
+
+	pub fn redundant_cmd_count(&self) -> usize {
+		self.last_redundant_cmds
+	}
+
+	pub fn draw_indexed(&self, index_count: u32, instance_count: u32, first_index: u32, vertex_offset: i32, first_instance: u32) {
 		self.queue_raster_cmd(VulkanRasterCmd::DrawIndexed {
 			index_count,
-			instance_count: 1,
-			first_index: 0,
-			vertex_offset: 0,
-			first_instance: 0,
+			instance_count,
+			first_index,
+			vertex_offset,
+			first_instance,
 		});
 	}
 
@@ -402,8 +1380,36 @@ impl VulkanGraphicsContext {
 		});
 	}
 
+	pub fn draw_indirect(&self, buffer: &VulkanBuffer, offset: u64, draw_count: u32, stride: u32) {
+		assert!(
+			draw_count <= 1 || self.swapchain.device.enabled_features.multi_draw_indirect != 0,
+			"Multi draw indirect is not supported on this device!"
+		);
+
+		self.queue_raster_cmd(VulkanRasterCmd::DrawIndirect {
+			buffer: buffer.raw,
+			offset,
+			draw_count,
+			stride,
+		});
+	}
+
+	pub fn draw_indexed_indirect(&self, buffer: &VulkanBuffer, offset: u64, draw_count: u32, stride: u32) {
+		assert!(
+			draw_count <= 1 || self.swapchain.device.enabled_features.multi_draw_indirect != 0,
+			"Multi draw indirect is not supported on this device!"
+		);
+
+		self.queue_raster_cmd(VulkanRasterCmd::DrawIndexedIndirect {
+			buffer: buffer.raw,
+			offset,
+			draw_count,
+			stride,
+		});
+	}
+
 	pub fn bind_graphics_descriptor(&self, descriptor_heap: &VulkanDescriptorHeap, descriptor_set: &VulkanDescriptorHandle, set: u32, pipeline: &VulkanPipeline) {
-		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_index;
+		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
 
 		let descriptor = descriptor_heap.descriptors[descriptor_set.id as usize][frame];
 		self.queue_raster_cmd(VulkanRasterCmd::BindDescriptor {
@@ -415,7 +1421,7 @@ impl VulkanGraphicsContext {
 	}
 
 	pub fn bind_compute_descriptor(&self, descriptor_heap: &VulkanDescriptorHeap, descriptor_set: &VulkanDescriptorHandle, set: u32, pipeline: &VulkanPipeline) {
-		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_index;
+		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
 
 		let descriptor = descriptor_heap.descriptors[descriptor_set.id as usize][frame];
 		self.queue_raster_cmd(VulkanRasterCmd::BindDescriptor {
@@ -426,17 +1432,105 @@ impl VulkanGraphicsContext {
 		});
 	}
 
+	pub fn bind_graphics_transient_descriptor(&self, descriptor_set: &VulkanTransientDescriptor, set: u32, pipeline: &VulkanPipeline) {
+		self.queue_raster_cmd(VulkanRasterCmd::BindDescriptor {
+			pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+			pipeline_layout: pipeline.pipeline_layout,
+			first_set: set,
+			descriptor_set: descriptor_set.raw,
+		});
+	}
+
+	pub fn bind_compute_transient_descriptor(&self, descriptor_set: &VulkanTransientDescriptor, set: u32, pipeline: &VulkanPipeline) {
+		self.queue_raster_cmd(VulkanRasterCmd::BindDescriptor {
+			pipeline_bind_point: vk::PipelineBindPoint::COMPUTE,
+			pipeline_layout: pipeline.pipeline_layout,
+			first_set: set,
+			descriptor_set: descriptor_set.raw,
+		});
+	}
+
+	// Cycles through 0..frames_in_flight, see frame_index for a counter.
+	pub fn frame_slot(&self) -> usize {
+		self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot
+	}
+
+	pub fn frame_index(&self) -> u64 {
+		self.frame_index
+	}
+
+	// Writes whatever callback returns to the start of the CpuToGpu buffer right before the frame (or its next batch) is submitted.
+	pub fn register_late_update(&self, buffer: &VulkanBuffer, callback: Box<dyn Fn() -> Vec<u8>>) {
+		assert!(self.current_frame_info.is_some(), "register_late_update must be called between begin_frame and end_frame!");
+		assert!(buffer.location == MemoryLocation::CpuToGpu, "Cannot late update a buffer that is not CpuToGpu!");
+
+		let mapped = buffer.allocation.mapped_ptr().expect("Failed to map allocation!").cast::<u8>();
+		let flush = self.swapchain.device.non_coherent_range(buffer, 0..buffer.size);
+		self.late_updates.borrow_mut().push(LateUpdate {
+			mapped,
+			size: buffer.size,
+			flush,
+			callback,
+		});
+	}
+
+	pub fn alloc_transient_descriptor(&mut self, layout: VulkanDescriptorLayout, descriptor_layout: &DescriptorSetBindings) -> VulkanTransientDescriptor {
+		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
+
+		self.swapchain.frames[frame].transient_descriptors.alloc(&self.swapchain.device, layout, descriptor_layout)
+	}
+
 	pub fn update_descriptor(
-		&mut self,
+		&self,
 		buffers: &[(u32, &VulkanBuffer)],
 		images: &[(u32, &VulkanTexture, ImageLayout)],
-		descriptor_layout: &'static DescriptorSetInfo,
+		image_arrays: &[(u32, &[&VulkanTexture], ImageLayout)],
+		descriptor_layout: &DescriptorSetBindings,
 		descriptor_heap: &VulkanDescriptorHeap,
 		descriptor_set: &VulkanDescriptorHandle,
 	) {
-		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_index;
+		let frame = self.current_frame_info.as_ref().expect("begin_frame was not called!").frame_slot;
 		let descriptor = descriptor_heap.descriptors[descriptor_set.id as usize][frame];
 
+		debug_assert!(
+			self.swapchain.frame_fence(frame).is_signaled(&self.swapchain.device).unwrap_or(true),
+			"Cannot write a descriptor set that a frame in flight might still be using!"
+		);
+
+		self.write_descriptor(buffers, images, image_arrays, descriptor_layout, descriptor);
+	}
+
+	pub fn update_transient_descriptor(
+		&self,
+		buffers: &[(u32, &VulkanBuffer)],
+		images: &[(u32, &VulkanTexture, ImageLayout)],
+		image_arrays: &[(u32, &[&VulkanTexture], ImageLayout)],
+		descriptor_layout: &DescriptorSetBindings,
+		descriptor_set: &VulkanTransientDescriptor,
+	) {
+		self.write_descriptor(buffers, images, image_arrays, descriptor_layout, descriptor_set.raw);
+	}
+
+	fn write_descriptor(
+		&self,
+		buffers: &[(u32, &VulkanBuffer)],
+		images: &[(u32, &VulkanTexture, ImageLayout)],
+		image_arrays: &[(u32, &[&VulkanTexture], ImageLayout)],
+		descriptor_layout: &DescriptorSetBindings,
+		descriptor: vk::DescriptorSet,
+	) {
+		for (binding, textures, _) in image_arrays {
+			let count = descriptor_layout.get(*binding).expect("Image array written to a binding that isn't in the layout!").descriptor_count();
+			assert!(textures.len() as u32 <= count, "{} textures written to binding {} which only holds {}!", textures.len(), binding, count);
+		}
+
+		self.swapchain
+			.device
+			.with_capture_registry(|registry| registry.write_descriptor_set(descriptor, buffers, images, image_arrays));
+		for (_, buffer) in buffers {
+			self.swapchain.device.record_buffer_use(buffer.raw);
+		}
+
 		let buffer_infos = buffers
 			.iter()
 			.map(|(_, buffer)| vk::DescriptorBufferInfo::builder().buffer(buffer.raw).offset(0).range(buffer.size as u64).build())
@@ -453,6 +1547,22 @@ impl VulkanGraphicsContext {
 			})
 			.collect::<Vec<_>>();
 
+		let image_array_infos = image_arrays
+			.iter()
+			.map(|(_, textures, layout)| {
+				textures
+					.iter()
+					.map(|image| {
+						vk::DescriptorImageInfo::builder()
+							.image_view(image.image_view)
+							.sampler(image.sampler)
+							.image_layout((*layout).into())
+							.build()
+					})
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+
 		unsafe {
 			self.raw_device().update_descriptor_sets(
 				&buffers
@@ -462,7 +1572,7 @@ impl VulkanGraphicsContext {
 						vk::WriteDescriptorSet::builder()
 							.dst_set(descriptor)
 							.dst_binding(*binding)
-							.descriptor_type((*descriptor_layout.bindings.get(&binding).unwrap()).into())
+							.descriptor_type(descriptor_layout.get(*binding).unwrap().into())
 							.buffer_info(&buffer_infos[i..=i])
 							.build()
 					})
@@ -470,10 +1580,25 @@ impl VulkanGraphicsContext {
 						vk::WriteDescriptorSet::builder()
 							.dst_set(descriptor)
 							.dst_binding(*binding)
-							.descriptor_type((*descriptor_layout.bindings.get(&binding).unwrap()).into())
+							.descriptor_type(descriptor_layout.get(*binding).unwrap().into())
 							.image_info(&image_infos[i..=i])
 							.build()
 					}))
+					.chain(
+						image_arrays
+							.iter()
+							.zip(image_array_infos.iter())
+							.filter(|(_, infos)| !infos.is_empty())
+							.map(|((binding, _, _), infos)| {
+								vk::WriteDescriptorSet::builder()
+									.dst_set(descriptor)
+									.dst_binding(*binding)
+									.dst_array_element(0)
+									.descriptor_type(descriptor_layout.get(*binding).unwrap().into())
+									.image_info(infos)
+									.build()
+							}),
+					)
 					.collect::<Vec<_>>(),
 				&[],
 			)
@@ -489,13 +1614,14 @@ impl VulkanGraphicsContext {
 		buffer_memory_barriers: &[vk::BufferMemoryBarrier],
 		image_memory_barriers: &[vk::ImageMemoryBarrier],
 	) {
+		let mut barriers = self.barriers.borrow_mut();
 		self.queue_raster_cmd(VulkanRasterCmd::PipelineBarrier {
 			src_stage_mask,
 			dst_stage_mask,
 			dependency_flags,
-			memory_barriers: memory_barriers.to_vec(),
-			buffer_memory_barriers: buffer_memory_barriers.to_vec(),
-			image_memory_barriers: image_memory_barriers.to_vec(),
+			memory_barriers: VulkanBarrierArena::push(&mut barriers.memory_barriers, memory_barriers),
+			buffer_memory_barriers: VulkanBarrierArena::push(&mut barriers.buffer_memory_barriers, buffer_memory_barriers),
+			image_memory_barriers: VulkanBarrierArena::push(&mut barriers.image_memory_barriers, image_memory_barriers),
 		})
 	}
 
@@ -559,6 +1685,13 @@ impl TextureFormat {
 			TextureFormat::RG32Float => vk::Format::R32G32_SFLOAT,
 			TextureFormat::RGB32Float => vk::Format::R32G32B32_SFLOAT,
 			TextureFormat::RGBA32Float => vk::Format::R32G32B32A32_SFLOAT,
+			TextureFormat::BC1UNorm => vk::Format::BC1_RGBA_UNORM_BLOCK,
+			TextureFormat::BC1SRGB => vk::Format::BC1_RGBA_SRGB_BLOCK,
+			TextureFormat::BC3UNorm => vk::Format::BC3_UNORM_BLOCK,
+			TextureFormat::BC3SRGB => vk::Format::BC3_SRGB_BLOCK,
+			TextureFormat::BC5UNorm => vk::Format::BC5_UNORM_BLOCK,
+			TextureFormat::BC7UNorm => vk::Format::BC7_UNORM_BLOCK,
+			TextureFormat::BC7SRGB => vk::Format::BC7_SRGB_BLOCK,
 			TextureFormat::Depth => device.depth_format,
 		}
 	}
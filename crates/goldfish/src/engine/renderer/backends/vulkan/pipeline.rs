@@ -1,6 +1,7 @@
 use super::{
 	VulkanGraphicsContext, VulkanRasterCmd,
 	{
+		capture::CapturedRasterState,
 		descriptor::VulkanDescriptorLayout,
 		device::{VulkanDestructor, VulkanDevice},
 		render_pass::VulkanRenderPass,
@@ -8,7 +9,11 @@ use super::{
 		swapchain::VulkanSwapchain,
 	},
 };
-use crate::renderer::{DepthCompareOp, FaceCullMode, PolygonMode, Vertex, VertexAttributeDescriptionBinding, VertexAttributeFormat, VertexInputInfo, CS_MAIN, PS_MAIN, VS_MAIN};
+use crate::renderer::{
+	BlendMode, DepthBias, DepthCompareOp, DestroyGuard, FaceCullMode, PolygonMode, RenderError, SpecValue, Vertex, VertexAttributeDescriptionBinding, VertexAttributeFormat, VertexInputInfo, CS_MAIN,
+	GS_MAIN, PS_MAIN, VS_MAIN,
+};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 use std::collections::{hash_map::Entry, HashMap};
 use std::ffi::CString;
@@ -18,6 +23,7 @@ use tracy_client as tracy;
 pub struct VulkanPipeline {
 	pub pipeline: vk::Pipeline,
 	pub pipeline_layout: vk::PipelineLayout,
+	pub(crate) guard: DestroyGuard,
 }
 
 type DescriptorSetLayout = HashMap<u32, rspirv_reflect::DescriptorInfo>;
@@ -51,6 +57,10 @@ impl From<VertexAttributeFormat> for vk::Format {
 			VertexAttributeFormat::F32Vec2 => Self::R32G32_SFLOAT,
 			VertexAttributeFormat::F32Vec3 => Self::R32G32B32_SFLOAT,
 			VertexAttributeFormat::F32Vec4 => Self::R32G32B32A32_SFLOAT,
+			VertexAttributeFormat::U32 => Self::R32_UINT,
+			VertexAttributeFormat::U8x4Norm => Self::R8G8B8A8_UNORM,
+			VertexAttributeFormat::U16x4 => Self::R16G16B16A16_UINT,
+			VertexAttributeFormat::U8x4 => Self::R8G8B8A8_UINT,
 		}
 	}
 }
@@ -81,32 +91,57 @@ impl From<DepthCompareOp> for vk::CompareOp {
 	}
 }
 
+// Packs the specialization values tightly, every SpecValue is 4 bytes.
+fn specialization_data(specialization: &[(u32, SpecValue)]) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+	let map_entries = specialization
+		.iter()
+		.enumerate()
+		.map(|(i, (constant_id, _))| vk::SpecializationMapEntry {
+			constant_id: *constant_id,
+			offset: (i * 4) as u32,
+			size: 4,
+		})
+		.collect();
+
+	let data = specialization.iter().flat_map(|(_, value)| value.to_ne_bytes()).collect();
+
+	(map_entries, data)
+}
+
 impl VulkanDevice {
 	pub fn create_raster_pipeline(
 		&self,
 		vs: &VulkanShader,
 		ps: Option<&VulkanShader>,
+		gs: Option<&VulkanShader>,
 		descriptor_layouts: &[VulkanDescriptorLayout],
 		render_pass: &VulkanRenderPass,
 		depth_compare_op: Option<DepthCompareOp>,
 		depth_write: bool,
+		depth_bias: Option<DepthBias>,
+		blend_mode: BlendMode,
 		face_cull: FaceCullMode,
 		push_constant_bytes: usize,
 		vertex_input_info: VertexInputInfo,
 		polygon_mode: PolygonMode,
-	) -> VulkanPipeline {
+		specialization: &[(u32, SpecValue)],
+	) -> GoldfishResult<VulkanPipeline> {
 		self.create_raster_pipeline_impl(
 			vs,
 			ps,
+			gs,
 			descriptor_layouts,
 			render_pass.raw,
 			render_pass.color_attachments.len(),
 			depth_compare_op,
 			depth_write,
+			depth_bias,
+			blend_mode,
 			face_cull,
 			push_constant_bytes,
 			vertex_input_info,
 			polygon_mode,
+			specialization,
 		)
 	}
 
@@ -114,16 +149,32 @@ impl VulkanDevice {
 		&self,
 		vs: &VulkanShader,
 		ps: Option<&VulkanShader>,
+		gs: Option<&VulkanShader>,
 		descriptor_layouts: &[VulkanDescriptorLayout],
 		render_pass: vk::RenderPass,
 		color_attachments_count: usize,
 		depth_compare_op: Option<DepthCompareOp>,
 		depth_write: bool,
+		depth_bias: Option<DepthBias>,
+		blend_mode: BlendMode,
 		face_cull: FaceCullMode,
 		push_constant_bytes: usize,
 		vertex_input_info: VertexInputInfo,
 		polygon_mode: PolygonMode,
-	) -> VulkanPipeline {
+		specialization: &[(u32, SpecValue)],
+	) -> GoldfishResult<VulkanPipeline> {
+		if gs.is_some() && self.enabled_features.geometry_shader == 0 {
+			return Err(GoldfishError::Render(RenderError::UnsupportedFeature("geometry shaders")));
+		}
+
+		if depth_bias.map_or(false, |bias| bias.clamp != 0.0) && self.enabled_features.depth_bias_clamp == 0 {
+			return Err(GoldfishError::Render(RenderError::UnsupportedFeature("depth bias clamping")));
+		}
+
+		if polygon_mode != PolygonMode::Fill && self.enabled_features.fill_mode_non_solid == 0 {
+			return Err(GoldfishError::Render(RenderError::UnsupportedFeature("line and point polygon modes")));
+		}
+
 		let mut layout_create_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(descriptor_layouts);
 
 		let push_constant_range = vk::PushConstantRange {
@@ -136,13 +187,17 @@ impl VulkanDevice {
 			layout_create_info = layout_create_info.push_constant_ranges(std::slice::from_ref(&push_constant_range));
 		}
 
-		let pipeline_layout = unsafe { self.raw.create_pipeline_layout(&layout_create_info, None).expect("Failed to create pipeline layout!") };
+		let pipeline_layout = unsafe { self.raw.create_pipeline_layout(&layout_create_info, None).map_err(move |err| GoldfishError::Render(err.into()))? };
+
+		let (map_entries, data) = specialization_data(specialization);
+		let specialization_info = vk::SpecializationInfo::builder().map_entries(&map_entries).data(&data);
 
-		let entry_names = [CString::new(VS_MAIN).unwrap(), CString::new(PS_MAIN).unwrap()];
+		let entry_names = [CString::new(VS_MAIN).unwrap(), CString::new(PS_MAIN).unwrap(), CString::new(GS_MAIN).unwrap()];
 		let mut shader_stage_infos = vec![vk::PipelineShaderStageCreateInfo::builder()
 			.module(vs.module)
 			.stage(vk::ShaderStageFlags::VERTEX)
 			.name(&entry_names[0])
+			.specialization_info(&specialization_info)
 			.build()];
 
 		if let Some(ps) = ps {
@@ -151,6 +206,18 @@ impl VulkanDevice {
 					.module(ps.module)
 					.stage(vk::ShaderStageFlags::FRAGMENT)
 					.name(&entry_names[1])
+					.specialization_info(&specialization_info)
+					.build(),
+			);
+		}
+
+		if let Some(gs) = gs {
+			shader_stage_infos.push(
+				vk::PipelineShaderStageCreateInfo::builder()
+					.module(gs.module)
+					.stage(vk::ShaderStageFlags::GEOMETRY)
+					.name(&entry_names[2])
+					.specialization_info(&specialization_info)
 					.build(),
 			);
 		}
@@ -175,6 +242,10 @@ impl VulkanDevice {
 			line_width: 1.0,
 			polygon_mode: polygon_mode.into(),
 			cull_mode: face_cull.into(),
+			depth_bias_enable: if depth_bias.is_some() { 1 } else { 0 },
+			depth_bias_constant_factor: depth_bias.map_or(0.0, |bias| bias.constant),
+			depth_bias_slope_factor: depth_bias.map_or(0.0, |bias| bias.slope),
+			depth_bias_clamp: depth_bias.map_or(0.0, |bias| bias.clamp),
 			..Default::default()
 		};
 
@@ -202,8 +273,8 @@ impl VulkanDevice {
 			..Default::default()
 		};
 
-		let color_blend_attachment_states = vec![
-			vk::PipelineColorBlendAttachmentState {
+		let color_blend_attachment_state = match blend_mode {
+			BlendMode::Opaque => vk::PipelineColorBlendAttachmentState {
 				blend_enable: 0,
 				src_color_blend_factor: vk::BlendFactor::SRC_COLOR,
 				dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_DST_COLOR,
@@ -212,9 +283,29 @@ impl VulkanDevice {
 				dst_alpha_blend_factor: vk::BlendFactor::ZERO,
 				alpha_blend_op: vk::BlendOp::ADD,
 				color_write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B | vk::ColorComponentFlags::A,
-			};
-			color_attachments_count
-		];
+			},
+			BlendMode::Alpha => vk::PipelineColorBlendAttachmentState {
+				blend_enable: 1,
+				src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+				dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+				color_blend_op: vk::BlendOp::ADD,
+				src_alpha_blend_factor: vk::BlendFactor::ONE,
+				dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+				alpha_blend_op: vk::BlendOp::ADD,
+				color_write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B | vk::ColorComponentFlags::A,
+			},
+			BlendMode::Additive => vk::PipelineColorBlendAttachmentState {
+				blend_enable: 1,
+				src_color_blend_factor: vk::BlendFactor::ONE,
+				dst_color_blend_factor: vk::BlendFactor::ONE,
+				color_blend_op: vk::BlendOp::ADD,
+				src_alpha_blend_factor: vk::BlendFactor::ONE,
+				dst_alpha_blend_factor: vk::BlendFactor::ONE,
+				alpha_blend_op: vk::BlendOp::ADD,
+				color_write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B | vk::ColorComponentFlags::A,
+			},
+		};
+		let color_blend_attachment_states = vec![color_blend_attachment_state; color_attachments_count];
 
 		let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachment_states);
 
@@ -234,33 +325,75 @@ impl VulkanDevice {
 			.layout(pipeline_layout)
 			.render_pass(render_pass);
 
-		let pipeline = unsafe {
-			self.raw
-				.create_graphics_pipelines(vk::PipelineCache::null(), &[graphics_pipeline_info.build()], None)
-				.expect("Failed to create graphics pipeline!")
-		}[0];
+		let pipeline = match unsafe { self.raw.create_graphics_pipelines(vk::PipelineCache::null(), &[graphics_pipeline_info.build()], None) } {
+			Ok(pipelines) => pipelines[0],
+			Err((_, err)) => {
+				unsafe { self.raw.destroy_pipeline_layout(pipeline_layout, None) };
+				return Err(GoldfishError::Render(err.into()));
+			}
+		};
+
+		let pipeline = VulkanPipeline {
+			pipeline,
+			pipeline_layout,
+			guard: DestroyGuard::new("Raster pipeline", ""),
+		};
+		self.with_capture_registry(|registry| {
+			let state = CapturedRasterState {
+				color_attachments: color_attachments_count,
+				depth_compare_op,
+				depth_write,
+				depth_bias,
+				blend_mode,
+				face_cull,
+				push_constant_bytes,
+				vertex_attributes: vertex_input_info.bindings.to_vec(),
+				vertex_stride: vertex_input_info.stride,
+				polygon_mode,
+			};
+			let shaders = [Some(vs.module), ps.map(|ps| ps.module), gs.map(|gs| gs.module)];
+			registry.register_raster_pipeline(&pipeline, shaders, descriptor_layouts, render_pass, state, specialization);
+		});
 
-		VulkanPipeline { pipeline, pipeline_layout }
+		Ok(pipeline)
 	}
 
-	pub fn create_compute_pipeline(&self, cs: &VulkanShader, descriptor_layouts: &[VulkanDescriptorLayout]) -> VulkanPipeline {
+	pub fn create_compute_pipeline(&self, cs: &VulkanShader, descriptor_layouts: &[VulkanDescriptorLayout], specialization: &[(u32, SpecValue)]) -> GoldfishResult<VulkanPipeline> {
 		let layout_create_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(descriptor_layouts);
 
-		let pipeline_layout = unsafe { self.raw.create_pipeline_layout(&layout_create_info, None).expect("Failed to create pipeline layout!") };
+		let pipeline_layout = unsafe { self.raw.create_pipeline_layout(&layout_create_info, None).map_err(move |err| GoldfishError::Render(err.into()))? };
+
+		let (map_entries, data) = specialization_data(specialization);
+		let specialization_info = vk::SpecializationInfo::builder().map_entries(&map_entries).data(&data);
 
 		let name = CString::new(CS_MAIN).unwrap();
-		let stage = vk::PipelineShaderStageCreateInfo::builder().module(cs.module).stage(vk::ShaderStageFlags::COMPUTE).name(&name);
+		let stage = vk::PipelineShaderStageCreateInfo::builder()
+			.module(cs.module)
+			.stage(vk::ShaderStageFlags::COMPUTE)
+			.name(&name)
+			.specialization_info(&specialization_info);
 
 		let compute_pipeline_info = vk::ComputePipelineCreateInfo::builder().layout(pipeline_layout).stage(stage.build());
-		let pipeline = unsafe {
-			self.raw
-				.create_compute_pipelines(vk::PipelineCache::null(), &[compute_pipeline_info.build()], None)
-				.expect("Failed to create compute pipeline!")
-		}[0];
-		VulkanPipeline { pipeline, pipeline_layout }
+		let pipeline = match unsafe { self.raw.create_compute_pipelines(vk::PipelineCache::null(), &[compute_pipeline_info.build()], None) } {
+			Ok(pipelines) => pipelines[0],
+			Err((_, err)) => {
+				unsafe { self.raw.destroy_pipeline_layout(pipeline_layout, None) };
+				return Err(GoldfishError::Render(err.into()));
+			}
+		};
+
+		let pipeline = VulkanPipeline {
+			pipeline,
+			pipeline_layout,
+			guard: DestroyGuard::new("Compute pipeline", ""),
+		};
+		self.with_capture_registry(|registry| registry.register_compute_pipeline(&pipeline, cs.module, descriptor_layouts, specialization));
+
+		Ok(pipeline)
 	}
 
-	pub fn destroy_pipeline(&mut self, pipeline: VulkanPipeline) {
+	pub fn destroy_pipeline(&mut self, mut pipeline: VulkanPipeline) {
+		pipeline.guard.mark_destroyed();
 		self.queue_destruction(&mut [VulkanDestructor::PipelineLayout(pipeline.pipeline_layout), VulkanDestructor::Pipeline(pipeline.pipeline)]);
 	}
 }
@@ -281,4 +414,14 @@ impl VulkanGraphicsContext {
 			pipeline: pipeline.pipeline,
 		});
 	}
+
+	// Raster pipelines only ever have the one ALL_GRAPHICS push constant range, so every stage sees the same bytes.
+	pub fn push_constants(&self, pipeline: &VulkanPipeline, offset: u32, data: &[u8]) {
+		self.queue_raster_cmd(VulkanRasterCmd::PushConstants {
+			pipeline_layout: pipeline.pipeline_layout,
+			stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+			offset,
+			data: data.to_vec(),
+		});
+	}
 }
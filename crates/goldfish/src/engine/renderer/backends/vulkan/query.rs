@@ -0,0 +1,166 @@
+use super::device::VulkanDevice;
+use crate::renderer::{QueryKind, QueryResult, QuerySlot};
+use ash::vk;
+use std::{collections::HashMap, time::Duration};
+
+pub struct VulkanQueryPools {
+	occlusion: vk::QueryPool,
+	// Only created if the device supports pipeline statistics queries.
+	statistics: Option<vk::QueryPool>,
+	used: Vec<QuerySlot>,
+	// Two timestamps bracketing the whole frame, only created if the device supports timestamps on the graphics queue.
+	timestamps: Option<vk::QueryPool>,
+	// Set once the frame this pool belongs to has actually written its timestamps, reading them before that is undefined.
+	timestamps_written: bool,
+}
+
+impl VulkanQueryPools {
+	pub const FRAME_START_TIMESTAMP: u32 = 0;
+	pub const FRAME_END_TIMESTAMP: u32 = 1;
+}
+
+// Results come back in bit order of these flags, so keep this in sync with read_results.
+fn pipeline_statistics_flags() -> vk::QueryPipelineStatisticFlags {
+	vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS
+		| vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES
+		| vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS
+		| vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS
+}
+
+impl VulkanDevice {
+	pub fn create_query_pools(&self) -> VulkanQueryPools {
+		let occlusion = unsafe {
+			self.raw
+				.create_query_pool(&vk::QueryPoolCreateInfo::builder().query_type(vk::QueryType::OCCLUSION).query_count(QuerySlot::MAX_QUERIES), None)
+				.expect("Failed to create occlusion query pool!")
+		};
+
+		let statistics = if self.enabled_features.pipeline_statistics_query != 0 {
+			Some(unsafe {
+				self.raw
+					.create_query_pool(
+						&vk::QueryPoolCreateInfo::builder()
+							.query_type(vk::QueryType::PIPELINE_STATISTICS)
+							.query_count(QuerySlot::MAX_QUERIES)
+							.pipeline_statistics(pipeline_statistics_flags()),
+						None,
+					)
+					.expect("Failed to create pipeline statistics query pool!")
+			})
+		} else {
+			None
+		};
+
+		let timestamps = if self.physical_device_properties.limits.timestamp_compute_and_graphics != 0 {
+			Some(unsafe {
+				self.raw
+					.create_query_pool(&vk::QueryPoolCreateInfo::builder().query_type(vk::QueryType::TIMESTAMP).query_count(2), None)
+					.expect("Failed to create timestamp query pool!")
+			})
+		} else {
+			None
+		};
+
+		VulkanQueryPools {
+			occlusion,
+			statistics,
+			used: Default::default(),
+			timestamps,
+			timestamps_written: false,
+		}
+	}
+
+	pub fn destroy_query_pools(&self, query_pools: VulkanQueryPools) {
+		unsafe {
+			self.raw.destroy_query_pool(query_pools.occlusion, None);
+
+			if let Some(statistics) = query_pools.statistics {
+				self.raw.destroy_query_pool(statistics, None);
+			}
+
+			if let Some(timestamps) = query_pools.timestamps {
+				self.raw.destroy_query_pool(timestamps, None);
+			}
+		}
+	}
+}
+
+impl VulkanQueryPools {
+	pub fn pool(&self, kind: QueryKind) -> Option<vk::QueryPool> {
+		match kind {
+			QueryKind::Occlusion => Some(self.occlusion),
+			QueryKind::PipelineStatistics => self.statistics,
+		}
+	}
+
+	pub fn pools(&self) -> impl Iterator<Item = vk::QueryPool> {
+		std::iter::once(self.occlusion).chain(self.statistics)
+	}
+
+	pub fn timestamp_pool(&self) -> Option<vk::QueryPool> {
+		self.timestamps
+	}
+
+	pub fn mark_timestamps_written(&mut self) {
+		self.timestamps_written = true;
+	}
+
+	// How long the GPU spent between the two frame timestamps, None if the device can't do timestamps or the results aren't there yet.
+	pub fn read_frame_time(&self, device: &VulkanDevice) -> Option<Duration> {
+		let timestamps = self.timestamps.filter(|_| self.timestamps_written)?;
+
+		let mut data = [0u64; 2];
+		unsafe { device.raw.get_query_pool_results(timestamps, Self::FRAME_START_TIMESTAMP, 2, &mut data, vk::QueryResultFlags::TYPE_64) }.ok()?;
+
+		let ticks = data[1].saturating_sub(data[0]);
+		let nanos = ticks as f64 * device.physical_device_properties.limits.timestamp_period as f64;
+		Some(Duration::from_nanos(nanos as u64))
+	}
+
+	// The frame that used these was thrown away before it was submitted, so there won't be any results for them.
+	pub fn forget_used(&mut self) {
+		self.used.clear();
+	}
+
+	pub fn mark_used(&mut self, slot: QuerySlot) {
+		assert!(slot.index < QuerySlot::MAX_QUERIES, "Query slot {} is out of range!", slot.index);
+		assert!(!self.used.contains(&slot), "Query slot {:?} was used more than once in a frame!", slot);
+
+		self.used.push(slot);
+	}
+
+	// Doesn't wait on the GPU, any queries that aren't available yet just keep their previous results.
+	pub fn read_results(&mut self, device: &VulkanDevice, results: &mut HashMap<QuerySlot, QueryResult>) {
+		for slot in self.used.drain(..) {
+			let result = match (slot.kind, self.statistics) {
+				(QueryKind::Occlusion, _) => {
+					let mut data = [0u64; 1];
+					unsafe { device.raw.get_query_pool_results(self.occlusion, slot.index, 1, &mut data, vk::QueryResultFlags::TYPE_64) }
+						.ok()
+						.map(|_| QueryResult::Occlusion { samples_passed: data[0] })
+				}
+				(QueryKind::PipelineStatistics, Some(statistics)) => {
+					let mut data = [[0u64; 4]; 1];
+					unsafe { device.raw.get_query_pool_results(statistics, slot.index, 1, &mut data, vk::QueryResultFlags::TYPE_64) }
+						.ok()
+						.map(|_| QueryResult::PipelineStatistics {
+							vertex_shader_invocations: data[0][0],
+							clipping_primitives: data[0][1],
+							fragment_shader_invocations: data[0][2],
+							compute_shader_invocations: data[0][3],
+						})
+				}
+				(QueryKind::PipelineStatistics, None) => Some(QueryResult::PipelineStatistics {
+					vertex_shader_invocations: 0,
+					clipping_primitives: 0,
+					fragment_shader_invocations: 0,
+					compute_shader_invocations: 0,
+				}),
+			};
+
+			if let Some(result) = result {
+				results.insert(slot, result);
+			}
+		}
+	}
+}
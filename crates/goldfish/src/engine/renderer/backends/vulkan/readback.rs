@@ -0,0 +1,262 @@
+use super::{buffer::VulkanBuffer, device::VulkanDevice, VulkanGraphicsContext, VulkanRasterCmd};
+use crate::renderer::{BufferUsage, MemoryLocation, ReadbackId, ReadbackSource, RenderError, TextureUsage};
+use crate::{GoldfishError, GoldfishResult};
+use ash::vk;
+
+// Each frame in flight gets a ring this big to copy its readbacks into, anything that doesn't fit gets a dedicated buffer instead.
+pub const READBACK_RING_SIZE: usize = 256 * 1024;
+
+enum ReadbackStorage {
+	Ring { offset: usize },
+	Dedicated(VulkanBuffer),
+}
+
+struct PendingReadback {
+	id: ReadbackId,
+	// Which of the frame's submissions the copy was recorded into.
+	submission: u64,
+	size: usize,
+	storage: ReadbackStorage,
+}
+
+// The readbacks recorded into one of the frames in flight, these only get looked at again once the frame's fence has signaled.
+#[derive(Default)]
+pub struct VulkanFrameReadbacks {
+	// Created by the first request that fits in it.
+	ring: Option<VulkanBuffer>,
+	ring_offset: usize,
+	pending: Vec<PendingReadback>,
+	// The fence gets reset and reused every time the frame comes around, so a signaled fence only means a request's copy
+	// is done if the fence's last submission was the one the copy was recorded into.
+	submitted: u64,
+}
+
+impl VulkanFrameReadbacks {
+	fn read(&self, readback: &PendingReadback) -> Vec<u8> {
+		let (buffer, offset) = match &readback.storage {
+			ReadbackStorage::Ring { offset } => (self.ring.as_ref().unwrap(), *offset),
+			ReadbackStorage::Dedicated(buffer) => (buffer, 0),
+		};
+
+		buffer.allocation.mapped_slice().expect("Failed to map readback buffer!")[offset..offset + readback.size].to_vec()
+	}
+
+	pub fn destroy(self, device: &VulkanDevice) {
+		let dedicated = self.pending.into_iter().filter_map(|readback| match readback.storage {
+			ReadbackStorage::Dedicated(buffer) => Some(buffer),
+			ReadbackStorage::Ring { .. } => None,
+		});
+
+		for buffer in self.ring.into_iter().chain(dedicated) {
+			device.destroy_buffer_immediate(buffer);
+		}
+	}
+}
+
+impl VulkanGraphicsContext {
+	// Records a copy of the source into this frame's command buffer, the data can be picked up with poll_readback once the GPU is done
+	// with the frame, which is usually a couple of frames later. Nothing here waits on the GPU. Textures are expected to already be in
+	// TRANSFER_SRC_OPTIMAL (the render graph leaves exported attachments that way), so call this after the render graph has executed.
+	pub fn request_readback(&mut self, source: ReadbackSource) -> GoldfishResult<ReadbackId> {
+		let frame_slot = self.current_frame_info.as_ref().expect("request_readback must be called between begin_frame and end_frame!").frame_slot;
+
+		let (size, alignment) = match source {
+			ReadbackSource::Texture { texture, x, y, width, height } => {
+				assert!(width > 0 && height > 0, "Cannot read back an empty region!");
+
+				if x + width > texture.width || y + height > texture.height {
+					return Err(GoldfishError::Render(RenderError::OutOfBounds {
+						x: x + width - 1,
+						y: y + height - 1,
+						width: texture.width,
+						height: texture.height,
+					}));
+				}
+
+				if !texture.usage.contains(TextureUsage::TRANSFER_SRC) {
+					return Err(GoldfishError::Render(RenderError::MissingTextureUsage("TRANSFER_SRC")));
+				}
+
+				let texel_bytes = texture.format.texel_bytes().ok_or(GoldfishError::Render(RenderError::UnsupportedFormat))?;
+
+				// Image copies need the buffer offset to be a multiple of both the texel size and 4.
+				(width as usize * height as usize * texel_bytes, texel_bytes * 4)
+			}
+			ReadbackSource::Buffer { buffer, offset, size } => {
+				assert!(offset + size <= buffer.size as u64, "Readback reads past the end of the buffer!");
+				(size as usize, 4)
+			}
+		};
+
+		assert!(size > 0, "Cannot read back an empty region!");
+
+		let device = &self.swapchain.device;
+		let readbacks = &mut self.swapchain.frames[frame_slot].readbacks;
+
+		let offset = (readbacks.ring_offset + alignment - 1) / alignment * alignment;
+		let storage = if offset + size <= READBACK_RING_SIZE {
+			if readbacks.ring.is_none() {
+				readbacks.ring = Some(device.create_empty_buffer(&format!("Readback Ring {}", frame_slot), READBACK_RING_SIZE, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None)?);
+			}
+
+			readbacks.ring_offset = offset + size;
+			ReadbackStorage::Ring { offset }
+		} else {
+			ReadbackStorage::Dedicated(device.create_empty_buffer("Readback", size, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None)?)
+		};
+
+		let (dst, dst_offset) = match &storage {
+			ReadbackStorage::Ring { offset } => (readbacks.ring.as_ref().unwrap().raw, *offset as u64),
+			ReadbackStorage::Dedicated(buffer) => (buffer.raw, 0),
+		};
+
+		let id = ReadbackId(self.next_readback_id);
+		self.next_readback_id += 1;
+
+		readbacks.pending.push(PendingReadback {
+			id,
+			submission: readbacks.submitted,
+			size,
+			storage,
+		});
+
+		match source {
+			ReadbackSource::Texture { texture, x, y, width, height } => {
+				// Only one aspect can be copied at a time, and for depth stencil formats the depth is the interesting part.
+				let aspect_mask = if texture.subresource_range.aspect_mask.contains(vk::ImageAspectFlags::DEPTH) {
+					vk::ImageAspectFlags::DEPTH
+				} else {
+					texture.subresource_range.aspect_mask
+				};
+
+				self.pipeline_barrier(
+					vk::PipelineStageFlags::ALL_COMMANDS,
+					vk::PipelineStageFlags::TRANSFER,
+					vk::DependencyFlags::empty(),
+					&[],
+					&[],
+					&[vk::ImageMemoryBarrier::builder()
+						.src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+						.dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+						.old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+						.new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+						.image(texture.image)
+						.subresource_range(texture.subresource_range)
+						.build()],
+				);
+
+				self.queue_raster_cmd(VulkanRasterCmd::CopyImageToBuffer {
+					src: texture.image,
+					src_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+					dst,
+					region: vk::BufferImageCopy::builder()
+						.buffer_offset(dst_offset)
+						.image_subresource(vk::ImageSubresourceLayers::builder().aspect_mask(aspect_mask).mip_level(0).base_array_layer(0).layer_count(1).build())
+						.image_offset(vk::Offset3D { x: x as i32, y: y as i32, z: 0 })
+						.image_extent(vk::Extent3D { width, height, depth: 1 })
+						.build(),
+				});
+			}
+			ReadbackSource::Buffer { buffer, offset, size } => {
+				self.pipeline_barrier(
+					vk::PipelineStageFlags::ALL_COMMANDS,
+					vk::PipelineStageFlags::TRANSFER,
+					vk::DependencyFlags::empty(),
+					&[vk::MemoryBarrier::builder()
+						.src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+						.dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+						.build()],
+					&[],
+					&[],
+				);
+
+				self.queue_raster_cmd(VulkanRasterCmd::CopyBuffer {
+					src: buffer.raw,
+					dst,
+					region: vk::BufferCopy { src_offset: offset, dst_offset, size },
+				});
+			}
+		}
+
+		self.pipeline_barrier(
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::HOST,
+			vk::DependencyFlags::empty(),
+			&[vk::MemoryBarrier::builder()
+				.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+				.dst_access_mask(vk::AccessFlags::HOST_READ)
+				.build()],
+			&[],
+			&[],
+		);
+
+		Ok(id)
+	}
+
+	// Returns the data once the frame the copy was recorded into has finished on the GPU, None until then. Only checks the fence, so it's
+	// fine to call every frame. The data is handed out once, after that (or for an id that never existed) this always returns None.
+	pub fn poll_readback(&mut self, id: ReadbackId) -> Option<Vec<u8>> {
+		if let Some(data) = self.completed_readbacks.remove(&id) {
+			return Some(data);
+		}
+
+		let frame_slot = self.swapchain.frames.iter().position(|frame| frame.readbacks.pending.iter().any(|readback| readback.id == id))?;
+		let readbacks = &self.swapchain.frames[frame_slot].readbacks;
+		let i = readbacks.pending.iter().position(|readback| readback.id == id).unwrap();
+
+		// Still being recorded, or waiting on a submission that hasn't happened yet.
+		if readbacks.pending[i].submission >= readbacks.submitted {
+			return None;
+		}
+
+		match self.swapchain.frame_fence(frame_slot).is_signaled(&self.swapchain.device) {
+			Ok(true) => {}
+			Ok(false) => return None,
+			Err(_) => self.handle_device_lost(),
+		}
+
+		let readbacks = &mut self.swapchain.frames[frame_slot].readbacks;
+		let readback = readbacks.pending.remove(i);
+		let data = readbacks.read(&readback);
+
+		if let ReadbackStorage::Dedicated(buffer) = readback.storage {
+			self.swapchain.device.destroy_buffer(buffer);
+		}
+
+		Some(data)
+	}
+
+	// Called once the frame's fence has been waited on and before anything new is recorded, the ring is about to be reused so anything
+	// that hasn't been polled yet gets copied out.
+	pub(super) fn recycle_readbacks(&mut self, frame_slot: usize) {
+		let readbacks = &mut self.swapchain.frames[frame_slot].readbacks;
+		for readback in std::mem::take(&mut readbacks.pending) {
+			self.completed_readbacks.insert(readback.id, readbacks.read(&readback));
+
+			if let ReadbackStorage::Dedicated(buffer) = readback.storage {
+				self.swapchain.device.destroy_buffer(buffer);
+			}
+		}
+
+		readbacks.ring_offset = 0;
+	}
+
+	// Readbacks requested this frame are never going to be copied once it's discarded, so polling them just keeps returning None.
+	pub(super) fn discard_readbacks(&mut self, frame_slot: usize) {
+		let readbacks = &mut self.swapchain.frames[frame_slot].readbacks;
+		let submitted = readbacks.submitted;
+		let (discarded, pending) = std::mem::take(&mut readbacks.pending).into_iter().partition::<Vec<_>, _>(|readback| readback.submission == submitted);
+		readbacks.pending = pending;
+
+		for readback in discarded {
+			if let ReadbackStorage::Dedicated(buffer) = readback.storage {
+				self.swapchain.device.destroy_buffer(buffer);
+			}
+		}
+	}
+
+	// Called right before the frame is submitted, everything recorded into it so far is part of this submission.
+	pub(super) fn submit_readbacks(&mut self, frame_slot: usize) {
+		self.swapchain.frames[frame_slot].readbacks.submitted += 1;
+	}
+}
@@ -3,6 +3,7 @@ use super::{
 	pipeline::VulkanPipeline,
 };
 use crate::renderer::{AttachmentDescription, ImageLayout, LoadOp, StoreOp};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 
 pub struct VulkanRenderPass {
@@ -41,6 +42,7 @@ impl From<ImageLayout> for vk::ImageLayout {
 			ImageLayout::ShaderReadOnlyOptimal => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
 			ImageLayout::TransferSrcOptimal => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
 			ImageLayout::TransferDstOptimal => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			ImageLayout::PresentSrc => vk::ImageLayout::PRESENT_SRC_KHR,
 			ImageLayout::Preinitialized => vk::ImageLayout::PREINITIALIZED,
 		}
 	}
@@ -61,7 +63,7 @@ impl AttachmentDescription {
 }
 
 impl VulkanDevice {
-	pub fn create_render_pass(&self, color_attachments: &[AttachmentDescription], depth_attachment: Option<AttachmentDescription>) -> VulkanRenderPass {
+	pub fn create_render_pass(&self, color_attachments: &[AttachmentDescription], depth_attachment: Option<AttachmentDescription>) -> GoldfishResult<VulkanRenderPass> {
 		let render_pass_attachments = color_attachments
 			.iter()
 			.map(|desc| desc.to_vk(self))
@@ -98,14 +100,17 @@ impl VulkanDevice {
 		let raw = unsafe {
 			self.raw
 				.create_render_pass(&vk::RenderPassCreateInfo::builder().attachments(&render_pass_attachments).subpasses(&subpasses), None)
-				.expect("Failed to create render pass!")
+				.map_err(move |err| GoldfishError::Render(err.into()))?
 		};
 
-		VulkanRenderPass {
+		let render_pass = VulkanRenderPass {
 			raw,
 			color_attachments: color_attachments.to_vec(),
 			depth_attachment,
-		}
+		};
+		self.with_capture_registry(|registry| registry.register_render_pass(&render_pass));
+
+		Ok(render_pass)
 	}
 
 	pub fn destroy_render_pass(&mut self, render_pass: VulkanRenderPass) {
@@ -15,6 +15,20 @@ impl VulkanDevice {
 		}
 	}
 
+	// Only ever counts up from initial_value, needs timeline_semaphore_supported.
+	pub fn create_timeline_semaphore(&self, initial_value: u64) -> VulkanSemaphore {
+		tracy::span!();
+		assert!(self.timeline_semaphore_supported, "Timeline semaphores aren't supported on this device!");
+		unsafe {
+			let mut type_info = vk::SemaphoreTypeCreateInfo::builder().semaphore_type(vk::SemaphoreType::TIMELINE).initial_value(initial_value);
+			let raw = self
+				.raw
+				.create_semaphore(&vk::SemaphoreCreateInfo::builder().push_next(&mut type_info), None)
+				.expect("Failed to create timeline VulkanSemaphore");
+			VulkanSemaphore { raw }
+		}
+	}
+
 	pub fn destroy_semaphore(&self, semaphore: VulkanSemaphore) {
 		tracy::span!();
 		unsafe {
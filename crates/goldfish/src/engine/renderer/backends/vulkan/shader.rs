@@ -1,27 +1,61 @@
 use super::device::{VulkanDestructor, VulkanDevice};
+use crate::renderer::{DestroyGuard, RenderError};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 
 #[derive(Hash, PartialEq, Eq)]
 pub struct VulkanShader {
 	pub module: vk::ShaderModule,
+	pub(crate) guard: DestroyGuard,
 }
 
+impl VulkanShader {
+	// Wraps a module that's owned by some other VulkanShader, only for handing to functions that take one. Dropping it is fine.
+	pub(crate) fn borrowed(module: vk::ShaderModule) -> Self {
+		Self {
+			module,
+			guard: DestroyGuard::untracked(),
+		}
+	}
+}
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+
 impl VulkanDevice {
-	pub fn create_shader(&self, data: &[u8]) -> VulkanShader {
+	pub fn create_shader(&self, data: &[u8]) -> GoldfishResult<VulkanShader> {
+		if data.len() % 4 != 0 {
+			return Err(GoldfishError::Render(RenderError::InvalidShader {
+				message: format!("SPIR-V size {} is not a multiple of 4 bytes", data.len()),
+			}));
+		}
+
 		self.create_shader_with_code(&data.chunks_exact(4).map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect::<Vec<_>>())
 	}
 
-	pub fn create_shader_with_code(&self, code: &[u32]) -> VulkanShader {
+	pub fn create_shader_with_code(&self, code: &[u32]) -> GoldfishResult<VulkanShader> {
+		// The SPIR-V header alone is 5 words, anything shorter is definitely truncated.
+		if code.len() < 5 || code[0] != SPIRV_MAGIC {
+			return Err(GoldfishError::Render(RenderError::InvalidShader {
+				message: "Missing or truncated SPIR-V header".to_string(),
+			}));
+		}
+
 		let module = unsafe {
 			self.raw
 				.create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(code), None)
-				.expect("Failed to create shader!")
+				.map_err(move |err| GoldfishError::Render(err.into()))?
 		};
 
-		VulkanShader { module }
+		self.with_capture_registry(|registry| registry.register_shader(module, code));
+
+		Ok(VulkanShader {
+			module,
+			guard: DestroyGuard::new("Shader", ""),
+		})
 	}
 
-	pub fn destroy_shader(&mut self, shader: VulkanShader) {
+	pub fn destroy_shader(&mut self, mut shader: VulkanShader) {
+		shader.guard.mark_destroyed();
 		self.queue_destruction(&mut [VulkanDestructor::Shader(shader.module)]);
 	}
 }
@@ -1,145 +1,240 @@
 use super::{
+	buffer::VulkanBuffer,
 	command_pool::{QueueType, VulkanCommandBuffer, VulkanCommandPool},
-	device::VulkanDevice,
+	descriptor::VulkanTransientDescriptorPool,
+	device::{VulkanDestructor, VulkanDevice},
 	fence::VulkanFence,
 	pipeline::VulkanPipeline,
+	query::VulkanQueryPools,
+	readback::VulkanFrameReadbacks,
 	semaphore::VulkanSemaphore,
+	texture::VulkanTexture,
 	SwapchainError,
 };
 
+use crate::renderer::{DestroyGuard, FramePacingStats, ImageLayout, PassDiagnostics, PresentMode, SurfaceFormat, SurfacePreference, SwapchainInfo, TextureFormat, TextureUsage};
 use crate::types::Size;
 
 use ash::{extensions::khr::Swapchain, vk};
 use std::rc::Rc;
+use std::time::Instant;
 use tracy_client as tracy;
 
 pub struct VulkanSwapchain {
 	pub device: VulkanDevice,
+	pub surface: vk::SurfaceKHR,
 
 	pub image_format: vk::Format,
+	pub surface_preference: SurfacePreference,
+	pub surface_format: SurfaceFormat,
 	pub extent: vk::Extent2D,
+	pub preferred_present_mode: PresentMode,
+	pub present_mode: vk::PresentModeKHR,
 	pub render_pass: vk::RenderPass,
+	// Same as render_pass except it keeps whatever was blitted into the image, the framebuffers and pipelines are compatible with both.
+	pub load_render_pass: vk::RenderPass,
+	pub supports_blit: bool,
+	// Whether compute shaders can write straight into the images, SRGB formats almost never allow it.
+	pub supports_storage: bool,
 	pub swapchain_loader: Swapchain,
 	pub swapchain: vk::SwapchainKHR,
 
 	images: Vec<SwapchainImage>,
 
 	pub frames: Vec<VulkanFrame>,
+	// Each swapchain cycles through its own frames, the device frame only tracks deferred destruction.
+	frame_slot: usize,
+	// A frame can be submitted in several batches, each one waits on the batch before it through this. None without timeline semaphore support.
+	batch_sem: Option<VulkanSemaphore>,
+	batch_value: u64,
+	// Batches of the current frame submitted so far, and whether one has waited on the acquire semaphore.
+	batches: usize,
+	acquire_waited: bool,
 
 	pub pipelines: Vec<Option<VulkanPipeline>>,
+
+	// Filled in over the frame being recorded, and copied into last_pacing once it's presented.
+	pacing: FramePacingStats,
+	last_pacing: FramePacingStats,
+
+	pub generation: u64,
 }
 
 impl VulkanSwapchain {
-	pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+	pub const MIN_FRAMES_IN_FLIGHT: usize = 2;
+	pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
 
-	pub fn new(framebuffer_size: Size, device: VulkanDevice) -> Self {
-		let (image_format, extent, swapchain_loader, swapchain, render_pass, images) = Self::init_swapchain(framebuffer_size, &device);
-		let mut frames = Vec::with_capacity(Self::MAX_FRAMES_IN_FLIGHT);
+	pub fn new(framebuffer_size: Size, device: VulkanDevice, surface: vk::SurfaceKHR, surface_preference: SurfacePreference, preferred_present_mode: PresentMode) -> Self {
+		let (image_format, surface_format, extent, present_mode, swapchain_loader, swapchain, render_pass, load_render_pass, supports_blit, supports_storage, images) =
+			Self::init_swapchain(framebuffer_size, &device, surface, surface_preference, preferred_present_mode);
+		let mut frames = Vec::with_capacity(device.frames_in_flight);
 
-		for _ in 0..Self::MAX_FRAMES_IN_FLIGHT {
+		for _ in 0..device.frames_in_flight {
 			frames.push(VulkanFrame {
 				command_pool: device.create_command_pool(QueueType::GRAPHICS),
 				completed_fence: Rc::new(device.create_fence(true)),
 				acquired_sem: device.create_semaphore(),
 				present_sem: device.create_semaphore(),
+				queries: device.create_query_pools(),
+				transient_descriptors: device.create_transient_descriptor_pool(),
+				passes: Default::default(),
+				marker_buffer: device.create_marker_buffer(),
+				readbacks: Default::default(),
 			});
 		}
 
+		let batch_sem = device.timeline_semaphore_supported.then(|| device.create_timeline_semaphore(0));
+
 		Self {
 			device,
+			surface,
 			image_format,
+			surface_preference,
+			surface_format,
 			extent,
+			preferred_present_mode,
+			present_mode,
 			render_pass,
+			load_render_pass,
+			supports_blit,
+			supports_storage,
 			swapchain_loader,
 			swapchain,
 
 			images,
 
 			frames,
+			frame_slot: 0,
+			batch_sem,
+			batch_value: 0,
+			batches: 0,
+			acquire_waited: false,
 			pipelines: Default::default(),
+			pacing: Default::default(),
+			last_pacing: Default::default(),
+			generation: 0,
 		}
 	}
 
 	pub fn acquire(&mut self) -> Result<FrameInfo, SwapchainError> {
 		let mut guard = self.device.frame.lock().unwrap();
-		let current_frame = guard.frame as usize;
-		assert!(current_frame < Self::MAX_FRAMES_IN_FLIGHT, "Invalid swapchain current frame!");
+		let frames_in_flight = self.device.frames_in_flight;
+		let current_frame = self.frame_slot;
+		assert!(current_frame < self.frames.len(), "Invalid swapchain current frame!");
 		tracy::span!();
 
 		// Get the current frame that we are processing
 		let frame = &self.frames[current_frame];
 
 		// Wait for the frame to have fully finished rendering before acquiring.
-		frame.completed_fence.wait(&self.device);
+		let fence_wait_start = Instant::now();
+		frame.completed_fence.try_wait(&self.device).map_err(|_| SwapchainError::DeviceLost)?;
+		self.pacing = FramePacingStats {
+			fence_wait: fence_wait_start.elapsed(),
+			..Default::default()
+		};
+
+		let fences = guard.take_retired_fences(frames_in_flight);
+		if !fences.is_empty() {
+			unsafe { self.device.raw.wait_for_fences(&fences, true, u64::MAX) }.map_err(|_| SwapchainError::DeviceLost)?;
+		}
 
-		let destructors = std::mem::take(&mut guard.destructors[current_frame]);
-		for destructor in destructors.into_iter() {
+		for destructor in guard.take_retired_destructors(frames_in_flight) {
 			self.device.run_destructor(destructor);
 		}
 
-		guard.destructors[current_frame].clear();
+		let acquire_start = Instant::now();
+		let acquired = unsafe { self.swapchain_loader.acquire_next_image(self.swapchain, u64::MAX, frame.acquired_sem.raw, vk::Fence::null()) };
+		self.pacing.acquire_wait = acquire_start.elapsed();
+
+		let (image_index, suboptimal) = match acquired {
+			// A suboptimal image has still been acquired and its semaphore signaled, so the frame has to present it.
+			Ok(acquired) => acquired,
+			Err(vk::Result::ERROR_DEVICE_LOST) => return Err(SwapchainError::DeviceLost),
+			Err(_) => {
+				drop(guard);
+				self.replace_acquire_semaphore(current_frame);
+				return Err(SwapchainError::AcquireSuboptimal);
+			}
+		};
+		assert!(image_index < self.images.len() as u32, "Invalid image index received!");
+
+		// Nothing is going to wait on the semaphore once the frame is abandoned here.
+		if let Some(fence) = self.images[image_index as usize].available_fence.clone() {
+			let fence_wait_start = Instant::now();
+			if fence.try_wait(&self.device).is_err() {
+				drop(guard);
+				self.replace_acquire_semaphore(current_frame);
+				return Err(SwapchainError::DeviceLost);
+			}
+			self.pacing.fence_wait += fence_wait_start.elapsed();
+		}
+
+		self.pacing.image_index = image_index;
+		self.pacing.suboptimal = suboptimal;
 
-		match unsafe { self.swapchain_loader.acquire_next_image(self.swapchain, u64::MAX, frame.acquired_sem.raw, vk::Fence::null()) } {
-			Ok((image_index, false)) => {
-				assert!(image_index < self.images.len() as u32, "Invalid image index received!");
+		let image = &mut self.images[image_index as usize];
+		image.available_fence = Some(Rc::clone(&self.frames[current_frame].completed_fence));
 
-				let image = &mut self.images[image_index as usize];
+		self.frames[current_frame].command_pool.recycle(&self.device);
+		self.device.recycle_thread_command_pools(self.frames[current_frame].completed_fence.raw);
+		self.batches = 0;
+		self.acquire_waited = false;
 
-				if let Some(ref fence) = image.available_fence {
-					fence.wait(&self.device);
-				}
+		Ok(FrameInfo {
+			image_index,
+			frame_slot: current_frame,
+			output_framebuffer: image.framebuffer,
+			output_image: image.image,
+			suboptimal,
+		})
+	}
 
-				image.available_fence = Some(Rc::clone(&frame.completed_fence));
+	// Some drivers leave a signal pending on the semaphore after a failed acquire, and reusing it is a validation error.
+	fn replace_acquire_semaphore(&mut self, frame_slot: usize) {
+		let semaphore = std::mem::replace(&mut self.frames[frame_slot].acquired_sem, self.device.create_semaphore());
+		self.device.queue_destruction(&mut [VulkanDestructor::Semaphore(semaphore.raw)]);
+	}
 
-				self.frames[current_frame].command_pool.recycle(&self.device);
-				let command_buffer = self.frames[current_frame].command_pool.begin_command_buffer(&self.device);
+	// Every batch records into its own command buffer out of the frame's pool.
+	pub fn begin_batch(&mut self) -> VulkanCommandBuffer {
+		self.frames[self.frame_slot].command_pool.begin_command_buffer(&self.device)
+	}
 
-				Ok(FrameInfo {
-					image_index,
-					frame_index: current_frame,
-					output_framebuffer: image.framebuffer,
-					command_buffer,
-				})
-			}
-			Ok((_, true)) => Err(SwapchainError::AcquireSuboptimal),
-			Err(_) => Err(SwapchainError::AcquireSuboptimal),
+	// Only waits on the acquire semaphore if the batch touches the output image.
+	pub fn submit_batch(&mut self, command_buffer: VulkanCommandBuffer, writes_output: bool) -> Result<(), SwapchainError> {
+		tracy::span!();
+		assert!(self.batch_sem.is_some(), "Submitting a frame in batches needs timeline semaphores!");
+
+		match self.submit_command_buffer(command_buffer, writes_output, false) {
+			Ok(_) => Ok(()),
+			Err(vk::Result::ERROR_DEVICE_LOST) => Err(SwapchainError::DeviceLost),
+			Err(err) => panic!("Failed to submit frame batch! {}", err),
 		}
 	}
 
+	// Submits the last batch of the frame, which signals the frame's fence, and presents the image.
 	pub fn submit(&mut self, image_index: u32, command_buffer: VulkanCommandBuffer) -> Result<(), SwapchainError> {
 		tracy::span!();
-		let mut guard = self.device.frame.lock().unwrap();
-		let current_frame = guard.frame as usize;
+		let frame_data = self.device.frame.clone();
+		let mut guard = frame_data.lock().unwrap();
+		let current_frame = self.frame_slot;
+
+		match self.submit_command_buffer(command_buffer, true, true) {
+			Ok(_) => {}
+			Err(vk::Result::ERROR_DEVICE_LOST) => return Err(SwapchainError::DeviceLost),
+			Err(err) => panic!("Failed to submit frame! {}", err),
+		}
 
-		self.frames[current_frame].command_pool.end_command_buffer(&self.device, command_buffer);
 		let frame = &self.frames[current_frame];
-
-		let acquired_sem = &frame.acquired_sem;
 		let present_sem = &frame.present_sem;
 
-		unsafe {
-			frame.completed_fence.reset(&self.device);
-
-			let graphics_queue = self.device.graphics_queue.lock().unwrap();
-			self.device
-				.raw
-				.queue_submit(
-					*graphics_queue,
-					&[vk::SubmitInfo::builder()
-						.wait_semaphores(&[acquired_sem.raw])
-						.wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-						.command_buffers(&[command_buffer])
-						.signal_semaphores(&[present_sem.raw])
-						.build()],
-					frame.completed_fence.raw,
-				)
-				.unwrap();
-		}
-
-		guard.frame = ((current_frame + 1) % Self::MAX_FRAMES_IN_FLIGHT) as u32;
+		guard.submitted(frame.completed_fence.raw);
+		self.frame_slot = (current_frame + 1) % self.frames.len();
 
 		let present_queue = self.device.present_queue.lock().unwrap();
-		match unsafe {
+		let present_start = Instant::now();
+		let presented = unsafe {
 			self.swapchain_loader.queue_present(
 				*present_queue,
 				&vk::PresentInfoKHR::builder()
@@ -147,31 +242,112 @@ impl VulkanSwapchain {
 					.swapchains(&[self.swapchain])
 					.image_indices(&[image_index]),
 			)
-		} {
+		};
+		drop(present_queue);
+
+		self.pacing.present_wait = present_start.elapsed();
+		self.pacing.suboptimal |= matches!(presented, Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR));
+		self.last_pacing = self.pacing;
+
+		tracy::plot!("Fence Wait (ms)", self.last_pacing.fence_wait.as_secs_f64() * 1000.0);
+		tracy::plot!("Acquire Wait (ms)", self.last_pacing.acquire_wait.as_secs_f64() * 1000.0);
+		tracy::plot!("Present Wait (ms)", self.last_pacing.present_wait.as_secs_f64() * 1000.0);
+		tracy::plot!("Swapchain Image", self.last_pacing.image_index as f64);
+
+		match presented {
 			Ok(suboptimal) => {
 				return if suboptimal { Err(SwapchainError::SubmitSuboptimal) } else { Ok(()) };
 			}
 			Err(vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR) => Err(SwapchainError::SubmitSuboptimal),
+			Err(vk::Result::ERROR_DEVICE_LOST) => Err(SwapchainError::DeviceLost),
 			Err(_) => {
 				panic!("Failed to present swapchain images!");
 			}
 		}
 	}
 
+	// The timeline semaphore keeps a batch from overlapping the one it was split off from.
+	fn submit_command_buffer(&mut self, command_buffer: VulkanCommandBuffer, writes_output: bool, last: bool) -> Result<(), vk::Result> {
+		self.frames[self.frame_slot].command_pool.end_command_buffer(&self.device, command_buffer);
+		let frame = &self.frames[self.frame_slot];
+
+		let mut wait_semaphores = Vec::new();
+		let mut wait_values = Vec::new();
+		let mut wait_stages = Vec::new();
+		let mut signal_semaphores = Vec::new();
+		let mut signal_values = Vec::new();
+
+		// The acquire semaphore can only be waited on once, and the last batch always has to since it presents the image.
+		if (writes_output || last) && !self.acquire_waited {
+			wait_semaphores.push(frame.acquired_sem.raw);
+			wait_values.push(0);
+			wait_stages.push(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+			self.acquire_waited = true;
+		}
+
+		if let Some(batch_sem) = &self.batch_sem {
+			if self.batches > 0 {
+				wait_semaphores.push(batch_sem.raw);
+				wait_values.push(self.batch_value);
+				wait_stages.push(vk::PipelineStageFlags::ALL_COMMANDS);
+			}
+
+			self.batch_value += 1;
+			signal_semaphores.push(batch_sem.raw);
+			signal_values.push(self.batch_value);
+		}
+
+		if last {
+			signal_semaphores.push(frame.present_sem.raw);
+			signal_values.push(0);
+		}
+
+		let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder().wait_semaphore_values(&wait_values).signal_semaphore_values(&signal_values);
+		let mut submit_info = vk::SubmitInfo::builder()
+			.wait_semaphores(&wait_semaphores)
+			.wait_dst_stage_mask(&wait_stages)
+			.command_buffers(std::slice::from_ref(&command_buffer))
+			.signal_semaphores(&signal_semaphores);
+		if self.batch_sem.is_some() {
+			submit_info = submit_info.push_next(&mut timeline_info);
+		}
+
+		self.batches += 1;
+
+		unsafe {
+			let fence = if last {
+				frame.completed_fence.reset(&self.device);
+				frame.completed_fence.raw
+			} else {
+				vk::Fence::null()
+			};
+
+			let graphics_queue = self.device.graphics_queue.lock().unwrap();
+			self.device.raw.queue_submit(*graphics_queue, &[submit_info.build()], fence)
+		}
+	}
+
 	pub fn invalidate(&mut self, framebuffer_size: Size) {
 		tracy::span!();
 		self.device.wait_idle();
 
 		self.destroy_swapchain();
 
-		let (image_format, extent, swapchain_loader, swapchain, render_pass, images) = Self::init_swapchain(framebuffer_size, &self.device);
+		let (image_format, surface_format, extent, present_mode, swapchain_loader, swapchain, render_pass, load_render_pass, supports_blit, supports_storage, images) =
+			Self::init_swapchain(framebuffer_size, &self.device, self.surface, self.surface_preference, self.preferred_present_mode);
 
 		self.image_format = image_format;
+		self.surface_format = surface_format;
 		self.extent = extent;
+		self.present_mode = present_mode;
 		self.swapchain_loader = swapchain_loader;
 		self.swapchain = swapchain;
 		self.render_pass = render_pass;
+		self.load_render_pass = load_render_pass;
+		self.supports_blit = supports_blit;
+		self.supports_storage = supports_storage;
 		self.images = images;
+		self.generation += 1;
 	}
 
 	fn destroy_swapchain(&mut self) {
@@ -180,31 +356,79 @@ impl VulkanSwapchain {
 			for image in std::mem::take(&mut self.images).into_iter() {
 				self.device.raw.destroy_framebuffer(image.framebuffer, None);
 				self.device.raw.destroy_image_view(image.image_view, None);
+
+				if let Some(storage) = image.storage {
+					self.device.raw.destroy_image_view(storage.image_view, None);
+				}
 			}
 
 			self.device.raw.destroy_render_pass(self.render_pass, None);
+			self.device.raw.destroy_render_pass(self.load_render_pass, None);
 
 			self.swapchain_loader.destroy_swapchain(self.swapchain, None);
 		}
 	}
 
-	fn init_swapchain(framebuffer_size: Size, device: &VulkanDevice) -> (vk::Format, vk::Extent2D, Swapchain, vk::SwapchainKHR, vk::RenderPass, Vec<SwapchainImage>) {
+	fn classify_surface_format(format: &vk::SurfaceFormatKHR) -> SurfaceFormat {
+		match (format.format, format.color_space) {
+			(vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT) => SurfaceFormat::Hdr10,
+			(vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR) => SurfaceFormat::Srgb,
+			(vk::Format::R8G8B8A8_UNORM | vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR) => SurfaceFormat::Unorm,
+			_ => SurfaceFormat::Other,
+		}
+	}
+
+	// Whatever was asked for first, then 8 bit UNORM, then SRGB, then the first one. HDR only when asked for.
+	fn choose_surface_format(surface_formats: &[vk::SurfaceFormatKHR], preference: SurfacePreference) -> Option<(vk::SurfaceFormatKHR, SurfaceFormat)> {
+		let preferred = match preference {
+			SurfacePreference::Hdr10 => SurfaceFormat::Hdr10,
+			SurfacePreference::Srgb => SurfaceFormat::Srgb,
+			SurfacePreference::Unorm => SurfaceFormat::Unorm,
+		};
+
+		[preferred, SurfaceFormat::Unorm, SurfaceFormat::Srgb]
+			.into_iter()
+			.find_map(|wanted| surface_formats.iter().find(|format| Self::classify_surface_format(format) == wanted).map(|&format| (format, wanted)))
+			.or_else(|| surface_formats.first().map(|&format| (format, SurfaceFormat::Other)))
+	}
+
+	fn init_swapchain(
+		framebuffer_size: Size,
+		device: &VulkanDevice,
+		surface: vk::SurfaceKHR,
+		surface_preference: SurfacePreference,
+		preferred_present_mode: PresentMode,
+	) -> (
+		vk::Format,
+		SurfaceFormat,
+		vk::Extent2D,
+		vk::PresentModeKHR,
+		Swapchain,
+		vk::SwapchainKHR,
+		vk::RenderPass,
+		vk::RenderPass,
+		bool,
+		bool,
+		Vec<SwapchainImage>,
+	) {
 		tracy::span!();
-		let swapchain_details = device.query_swapchain_details();
+		let swapchain_details = device.query_swapchain_details(surface);
 
 		let capabilities = &swapchain_details.capabilities;
 
-		let surface_format = swapchain_details
-			.surface_formats
-			.iter()
-			.find(|&format| (format.format == vk::Format::R8G8B8A8_UNORM || format.format == vk::Format::B8G8R8A8_UNORM) && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
-			.expect("No surface formats found!");
+		let (surface_format, chosen_format) = Self::choose_surface_format(&swapchain_details.surface_formats, surface_preference).expect("No surface formats found!");
+
+		let wanted_present_mode = match preferred_present_mode {
+			PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+			PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+			PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+		};
 
 		let present_mode = swapchain_details
 			.present_modes
 			.iter()
 			.cloned()
-			.find(|&mode| mode == vk::PresentModeKHR::MAILBOX)
+			.find(|&mode| mode == wanted_present_mode)
 			.unwrap_or(vk::PresentModeKHR::FIFO);
 
 		let extent = if capabilities.current_extent.width != u32::MAX {
@@ -221,15 +445,29 @@ impl VulkanSwapchain {
 			image_count = capabilities.max_image_count;
 		}
 
+		let supports_blit = capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::TRANSFER_DST);
+		let format_properties = unsafe { device.instance.get_physical_device_format_properties(device.physical_device, surface_format.format) };
+		let supports_storage = capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::STORAGE)
+			&& format_properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+			&& device.enabled_features.shader_storage_image_write_without_format != 0;
+
+		let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+		if supports_blit {
+			image_usage |= vk::ImageUsageFlags::TRANSFER_DST;
+		}
+		if supports_storage {
+			image_usage |= vk::ImageUsageFlags::STORAGE;
+		}
+
 		let swapchain_loader = Swapchain::new(&device.instance, &device.raw);
 		let mut create_info = vk::SwapchainCreateInfoKHR::builder()
-			.surface(device.surface)
+			.surface(surface)
 			.min_image_count(image_count)
 			.image_format(surface_format.format)
 			.image_color_space(surface_format.color_space)
 			.image_extent(extent)
 			.image_array_layers(1)
-			.image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+			.image_usage(image_usage);
 
 		let queue_indices = device.get_queue_family_indices();
 		let queue_family_indices = [queue_indices.graphics_family, queue_indices.present_family];
@@ -250,7 +488,105 @@ impl VulkanSwapchain {
 
 		let image_format = surface_format.format;
 
-		let render_pass = unsafe {
+		let render_pass = Self::create_output_render_pass(device, image_format, vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED);
+		let load_render_pass = Self::create_output_render_pass(device, image_format, vk::AttachmentLoadOp::LOAD, vk::ImageLayout::PRESENT_SRC_KHR);
+
+		let vk_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).expect("Failed to get swapchain images") };
+
+		let color_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1)
+			.build();
+
+		let images: Vec<SwapchainImage> = vk_images
+			.into_iter()
+			.map(|image| unsafe {
+				let view_create_info = vk::ImageViewCreateInfo::builder()
+					.image(image)
+					.view_type(vk::ImageViewType::TYPE_2D)
+					.format(image_format)
+					.components(
+						vk::ComponentMapping::builder()
+							.r(vk::ComponentSwizzle::IDENTITY)
+							.g(vk::ComponentSwizzle::IDENTITY)
+							.b(vk::ComponentSwizzle::IDENTITY)
+							.a(vk::ComponentSwizzle::IDENTITY)
+							.build(),
+					)
+					.subresource_range(color_range);
+
+				let image_view = device.raw.create_image_view(&view_create_info, None).expect("Failed to create image view!");
+
+				// A view of its own that's only usable as a storage image, so the attachment view doesn't pick up the STORAGE usage.
+				let storage = supports_storage.then(|| {
+					let mut usage_info = vk::ImageViewUsageCreateInfo::builder().usage(vk::ImageUsageFlags::STORAGE);
+					let storage_view = device
+						.raw
+						.create_image_view(&view_create_info.push_next(&mut usage_info), None)
+						.expect("Failed to create storage image view!");
+
+					VulkanTexture {
+						width: extent.width,
+						height: extent.height,
+
+						image,
+						sampler: vk::Sampler::null(),
+						image_view: storage_view,
+						subresource_range: color_range,
+
+						allocation: Default::default(),
+						format: TextureFormat::RGBA8UNorm,
+						usage: TextureUsage::STORAGE,
+
+						owned: false,
+						sampled_layout: ImageLayout::General,
+						guard: DestroyGuard::untracked(),
+					}
+				});
+
+				let framebuffer = device
+					.raw
+					.create_framebuffer(
+						&vk::FramebufferCreateInfo::builder()
+							.render_pass(render_pass)
+							.attachments(&[image_view])
+							.width(extent.width)
+							.height(extent.height)
+							.layers(1),
+						None,
+					)
+					.expect("Failed to create framebuffer!");
+
+				SwapchainImage {
+					image,
+					image_view,
+					framebuffer,
+					storage,
+					available_fence: None,
+				}
+			})
+			.collect();
+
+		(
+			image_format,
+			chosen_format,
+			extent,
+			present_mode,
+			swapchain_loader,
+			swapchain,
+			render_pass,
+			load_render_pass,
+			supports_blit,
+			supports_storage,
+			images,
+		)
+	}
+
+	pub(super) fn create_output_render_pass(device: &VulkanDevice, image_format: vk::Format, load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout) -> vk::RenderPass {
+		unsafe {
 			device
 				.raw
 				.create_render_pass(
@@ -258,11 +594,11 @@ impl VulkanSwapchain {
 						.attachments(&[vk::AttachmentDescription::builder()
 							.format(image_format)
 							.samples(vk::SampleCountFlags::TYPE_1)
-							.load_op(vk::AttachmentLoadOp::CLEAR)
+							.load_op(load_op)
 							.store_op(vk::AttachmentStoreOp::STORE)
 							.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
 							.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-							.initial_layout(vk::ImageLayout::UNDEFINED)
+							.initial_layout(initial_layout)
 							.final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
 							.build()])
 						.subpasses(&[vk::SubpassDescription::builder()
@@ -280,63 +616,37 @@ impl VulkanSwapchain {
 					None,
 				)
 				.expect("Failed to create Render Pass!")
-		};
-
-		let vk_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).expect("Failed to get swapchain images") };
+		}
+	}
 
-		let images: Vec<SwapchainImage> = vk_images
-			.into_iter()
-			.map(|image| unsafe {
-				let image_view = device
-					.raw
-					.create_image_view(
-						&vk::ImageViewCreateInfo::builder()
-							.image(image)
-							.view_type(vk::ImageViewType::TYPE_2D)
-							.format(image_format)
-							.components(
-								vk::ComponentMapping::builder()
-									.r(vk::ComponentSwizzle::IDENTITY)
-									.g(vk::ComponentSwizzle::IDENTITY)
-									.b(vk::ComponentSwizzle::IDENTITY)
-									.a(vk::ComponentSwizzle::IDENTITY)
-									.build(),
-							)
-							.subresource_range(
-								vk::ImageSubresourceRange::builder()
-									.aspect_mask(vk::ImageAspectFlags::COLOR)
-									.base_mip_level(0)
-									.level_count(1)
-									.base_array_layer(0)
-									.layer_count(1)
-									.build(),
-							),
-						None,
-					)
-					.expect("Failed to create image view!");
+	// The acquired image viewed as a storage image, None unless supports_storage is set.
+	pub fn last_pacing(&self) -> FramePacingStats {
+		self.last_pacing
+	}
 
-				let framebuffer = device
-					.raw
-					.create_framebuffer(
-						&vk::FramebufferCreateInfo::builder()
-							.render_pass(render_pass)
-							.attachments(&[image_view])
-							.width(extent.width)
-							.height(extent.height)
-							.layers(1),
-						None,
-					)
-					.expect("Failed to create framebuffer!");
+	pub fn info(&self) -> SwapchainInfo {
+		SwapchainInfo {
+			image_count: self.images.len() as u32,
+			present_mode: match self.present_mode {
+				vk::PresentModeKHR::MAILBOX => PresentMode::Mailbox,
+				vk::PresentModeKHR::IMMEDIATE => PresentMode::Immediate,
+				_ => PresentMode::Fifo,
+			},
+			format: self.surface_format,
+			extent: Size {
+				width: self.extent.width,
+				height: self.extent.height,
+			},
+		}
+	}
 
-				SwapchainImage {
-					image_view,
-					framebuffer,
-					available_fence: None,
-				}
-			})
-			.collect();
+	pub fn storage_texture(&self, image_index: u32) -> Option<&VulkanTexture> {
+		self.images[image_index as usize].storage.as_ref()
+	}
 
-		(image_format, extent, swapchain_loader, swapchain, render_pass, images)
+	// Signaled once everything submitted for the frame has finished on the GPU, it gets reset again when the frame is next submitted.
+	pub fn frame_fence(&self, frame_slot: usize) -> &VulkanFence {
+		&self.frames[frame_slot].completed_fence
 	}
 
 	pub fn raw_device(&self) -> &ash::Device {
@@ -349,8 +659,13 @@ impl VulkanSwapchain {
 
 		self.destroy_swapchain();
 
+		// Destroyed right away instead of queued, the device is idle and there's no later frame to retire them.
+		let mut guard = self.device.frame.lock().unwrap();
 		for frame in std::mem::take(&mut self.frames).into_iter() {
+			guard.forget_fence(frame.completed_fence.raw);
+
 			self.device.destroy_command_pool(frame.command_pool);
+			self.device.destroy_thread_command_pools(frame.completed_fence.raw);
 
 			if let Ok(completed_fence) = Rc::try_unwrap(frame.completed_fence) {
 				self.device.destroy_fence(completed_fence);
@@ -358,13 +673,30 @@ impl VulkanSwapchain {
 
 			self.device.destroy_semaphore(frame.acquired_sem);
 			self.device.destroy_semaphore(frame.present_sem);
+			self.device.destroy_query_pools(frame.queries);
+			self.device.destroy_transient_descriptor_pool(frame.transient_descriptors);
+			frame.readbacks.destroy(&self.device);
+
+			if let Some(marker_buffer) = frame.marker_buffer {
+				self.device.destroy_buffer_immediate(marker_buffer);
+			}
+		}
+		std::mem::drop(guard);
+
+		if let Some(batch_sem) = self.batch_sem.take() {
+			self.device.destroy_semaphore(batch_sem);
 		}
+
+		self.device.destroy_surface(self.surface);
 	}
 }
 
 struct SwapchainImage {
+	image: vk::Image,
 	image_view: vk::ImageView,
 	framebuffer: vk::Framebuffer,
+	// Only there if the swapchain supports_storage.
+	storage: Option<VulkanTexture>,
 
 	available_fence: Option<Rc<VulkanFence>>,
 }
@@ -374,11 +706,19 @@ pub struct VulkanFrame {
 	completed_fence: Rc<VulkanFence>,
 	acquired_sem: VulkanSemaphore,
 	present_sem: VulkanSemaphore,
+	pub queries: VulkanQueryPools,
+	pub transient_descriptors: VulkanTransientDescriptorPool,
+	// Whatever the render graph executed the last time this frame was used, for figuring out what went wrong when the device gets lost.
+	pub passes: Vec<PassDiagnostics>,
+	pub marker_buffer: Option<VulkanBuffer>,
+	pub readbacks: VulkanFrameReadbacks,
 }
 
 pub struct FrameInfo {
 	pub output_framebuffer: vk::Framebuffer,
+	pub output_image: vk::Image,
 	pub image_index: u32,
-	pub frame_index: usize,
-	pub command_buffer: VulkanCommandBuffer,
+	pub frame_slot: usize,
+	// The swapchain should be recreated once this frame has been presented.
+	pub suboptimal: bool,
 }
@@ -1,5 +1,7 @@
-use super::device::{VulkanDestructor, VulkanDevice};
-use crate::renderer::{TextureFormat, TextureUsage};
+use super::device::{VulkanDestructor, VulkanDevice, VulkanUploadContext};
+use super::{VulkanGraphicsContext, VulkanRasterCmd};
+use crate::renderer::{BufferUsage, ClearValue, DestroyGuard, ImageLayout, RenderError, TextureFormat, TextureUsage};
+use crate::{GoldfishError, GoldfishResult};
 use ash::vk;
 use gpu_allocator::vulkan as vma;
 use gpu_allocator::MemoryLocation;
@@ -17,6 +19,35 @@ pub struct VulkanTexture {
 	pub allocation: vma::Allocation,
 	pub format: TextureFormat,
 	pub usage: TextureUsage,
+
+	// Textures imported with import_texture_raw don't own anything, destroying them only forgets about them.
+	pub owned: bool,
+	// The layout the image is sampled in. For imported textures it's whatever the caller promised to keep it in.
+	pub sampled_layout: ImageLayout,
+	pub(crate) guard: DestroyGuard,
+}
+
+#[cfg(test)]
+impl VulkanTexture {
+	pub(crate) fn null(format: TextureFormat) -> Self {
+		Self {
+			width: 1,
+			height: 1,
+
+			image: vk::Image::null(),
+			sampler: vk::Sampler::null(),
+			image_view: vk::ImageView::null(),
+			subresource_range: vk::ImageSubresourceRange::default(),
+
+			allocation: vma::Allocation::default(),
+			format,
+			usage: TextureUsage::SAMPLED,
+
+			owned: false,
+			sampled_layout: ImageLayout::ShaderReadOnlyOptimal,
+			guard: DestroyGuard::untracked(),
+		}
+	}
 }
 
 impl Hash for VulkanTexture {
@@ -35,8 +66,299 @@ impl PartialEq for VulkanTexture {
 
 impl Eq for VulkanTexture {}
 
+const UNNAMED_TEXTURE: &str = "Unnamed Texture";
+
+impl VulkanUploadContext {
+	// Textures created with data are left in SHADER_READ_ONLY_OPTIMAL. Cubemap data is the faces back to back, see TextureFormat::faces.
+	pub fn create_texture(&mut self, name: &str, width: u32, height: u32, format: TextureFormat, mut usage: TextureUsage, data: Option<&[u8]>) -> GoldfishResult<VulkanTexture> {
+		if data.is_some() {
+			usage |= TextureUsage::TRANSFER_DST;
+		}
+
+		// Compressed data is expected to be tightly packed rows of blocks, so the buffer row length gets rounded up to a whole block.
+		let compressed_size = format.compressed_mip_size(width, height, 0);
+		if let (Some(data), Some((_, size))) = (data, compressed_size) {
+			if data.len() < size {
+				return Err(GoldfishError::Render(RenderError::InvalidTextureData { expected: size, actual: data.len() }));
+			}
+		}
+
+		let face_size = match (compressed_size, format.texel_bytes()) {
+			(Some((_, size)), _) => size,
+			(None, Some(texel_bytes)) => width as usize * height as usize * texel_bytes,
+			(None, None) => 0,
+		};
+		if let Some(data) = data.filter(|_| format.is_cubemap()) {
+			if data.len() < face_size * 6 {
+				return Err(GoldfishError::Render(RenderError::InvalidTextureData {
+					expected: face_size * 6,
+					actual: data.len(),
+				}));
+			}
+		}
+
+		let texture = self.device.create_texture(name, width, height, format, usage)?;
+
+		if let Some(data) = data {
+			let mut copy_buffer = match self
+				.device
+				.create_empty_buffer(&format!("{} (Staging)", name), data.len(), MemoryLocation::CpuToGpu, BufferUsage::TransferSrc, None)
+			{
+				Ok(copy_buffer) => copy_buffer,
+				Err(err) => {
+					self.device.destroy_texture(texture);
+					return Err(err);
+				}
+			};
+
+			copy_buffer.allocation.mapped_slice_mut().unwrap()[0..data.len()].copy_from_slice(data);
+
+			let subresource_range = texture.subresource_range;
+			let image = texture.image;
+			let regions = (0..subresource_range.layer_count)
+				.map(|layer| {
+					vk::BufferImageCopy::builder()
+						.buffer_offset((layer as usize * face_size) as vk::DeviceSize)
+						.buffer_row_length(format.buffer_row_length(width, 0))
+						.image_subresource(
+							vk::ImageSubresourceLayers::builder()
+								.aspect_mask(subresource_range.aspect_mask)
+								.mip_level(0)
+								.base_array_layer(layer)
+								.layer_count(1)
+								.build(),
+						)
+						.image_extent(vk::Extent3D { width, height, depth: 1 })
+						.build()
+				})
+				.collect::<Vec<_>>();
+			self.wait_submit(|device, cmd| unsafe {
+				device.cmd_pipeline_barrier(
+					cmd,
+					vk::PipelineStageFlags::TOP_OF_PIPE,
+					vk::PipelineStageFlags::TRANSFER,
+					vk::DependencyFlags::empty(),
+					&[],
+					&[],
+					&[vk::ImageMemoryBarrier::builder()
+						.src_access_mask(vk::AccessFlags::empty())
+						.dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+						.old_layout(vk::ImageLayout::UNDEFINED)
+						.new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+						.image(image)
+						.subresource_range(subresource_range)
+						.build()],
+				);
+
+				device.cmd_copy_buffer_to_image(cmd, copy_buffer.raw, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+
+				device.cmd_pipeline_barrier(
+					cmd,
+					vk::PipelineStageFlags::TRANSFER,
+					vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+					vk::DependencyFlags::empty(),
+					&[],
+					&[],
+					&[vk::ImageMemoryBarrier::builder()
+						.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+						.dst_access_mask(vk::AccessFlags::SHADER_READ)
+						.old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+						.new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+						.image(image)
+						.subresource_range(subresource_range)
+						.build()],
+				);
+			});
+
+			self.device.destroy_buffer(copy_buffer);
+		}
+
+		Ok(texture)
+	}
+
+	// For textures that get read before anything has written to them, the texture needs TextureUsage::TRANSFER_DST.
+	pub fn clear_texture(&mut self, texture: &VulkanTexture, clear_value: ClearValue, layout: ImageLayout) {
+		assert!(texture.usage.contains(TextureUsage::TRANSFER_DST), "Cleared textures must have TextureUsage::TRANSFER_DST!");
+
+		let subresource_range = texture.subresource_range;
+		let image = texture.image;
+		self.wait_submit(|device, cmd| unsafe {
+			device.cmd_pipeline_barrier(
+				cmd,
+				vk::PipelineStageFlags::TOP_OF_PIPE,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[vk::ImageMemoryBarrier::builder()
+					.src_access_mask(vk::AccessFlags::empty())
+					.dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+					.old_layout(vk::ImageLayout::UNDEFINED)
+					.new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+					.image(image)
+					.subresource_range(subresource_range)
+					.build()],
+			);
+
+			match clear_value {
+				ClearValue::Color { r, g, b, a } => {
+					device.cmd_clear_color_image(cmd, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &vk::ClearColorValue { float32: [r, g, b, a] }, &[subresource_range]);
+				}
+				ClearValue::DepthStencil { depth, stencil } => {
+					device.cmd_clear_depth_stencil_image(cmd, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &vk::ClearDepthStencilValue { depth, stencil }, &[subresource_range]);
+				}
+			}
+
+			device.cmd_pipeline_barrier(
+				cmd,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::ALL_COMMANDS,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[vk::ImageMemoryBarrier::builder()
+					.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+					.dst_access_mask(vk::AccessFlags::SHADER_READ)
+					.old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+					.new_layout(layout.into())
+					.image(image)
+					.subresource_range(subresource_range)
+					.build()],
+			);
+		});
+	}
+
+	pub fn create_texture_unnamed(&mut self, width: u32, height: u32, format: TextureFormat, usage: TextureUsage, data: Option<&[u8]>) -> GoldfishResult<VulkanTexture> {
+		self.create_texture(UNNAMED_TEXTURE, width, height, format, usage, data)
+	}
+
+	pub fn destroy_texture(&mut self, texture: VulkanTexture) {
+		self.device.destroy_texture(texture);
+	}
+}
+
+impl VulkanGraphicsContext {
+	// Can't be recorded inside of a render pass, the texture has to already be in layout (TransferDstOptimal or General) and have TextureUsage::TRANSFER_DST.
+	pub fn clear_image(&self, texture: &VulkanTexture, layout: ImageLayout, clear_value: ClearValue) {
+		assert!(texture.usage.contains(TextureUsage::TRANSFER_DST), "Cleared textures must have TextureUsage::TRANSFER_DST!");
+
+		match clear_value {
+			ClearValue::Color { r, g, b, a } => self.queue_raster_cmd(VulkanRasterCmd::ClearColorImage {
+				image: texture.image,
+				layout: layout.into(),
+				value: vk::ClearColorValue { float32: [r, g, b, a] },
+				range: texture.subresource_range,
+			}),
+			ClearValue::DepthStencil { depth, stencil } => self.queue_raster_cmd(VulkanRasterCmd::ClearDepthStencilImage {
+				image: texture.image,
+				layout: layout.into(),
+				value: vk::ClearDepthStencilValue { depth, stencil },
+				range: texture.subresource_range,
+			}),
+		}
+	}
+
+	// Stalls until the GPU catches up, so it's for things like picking. Call after end_frame with the attachment in TRANSFER_SRC_OPTIMAL.
+	pub fn read_attachment_pixel(&mut self, attachment: &VulkanTexture, x: u32, y: u32) -> GoldfishResult<[u8; 16]> {
+		assert!(self.current_frame_info.is_none(), "read_attachment_pixel must be called after end_frame!");
+
+		if x >= attachment.width || y >= attachment.height {
+			return Err(GoldfishError::Render(RenderError::OutOfBounds {
+				x,
+				y,
+				width: attachment.width,
+				height: attachment.height,
+			}));
+		}
+
+		if !attachment.usage.contains(TextureUsage::TRANSFER_SRC) {
+			return Err(GoldfishError::Render(RenderError::MissingTextureUsage("TRANSFER_SRC")));
+		}
+
+		let mut readback = self
+			.swapchain
+			.device
+			.create_empty_buffer("Texel Readback", 16, MemoryLocation::GpuToCpu, BufferUsage::TransferDst, None)?;
+		self.swapchain.device.map_buffer(&mut readback).fill(0);
+
+		// Only one aspect can be copied at a time, and for depth stencil formats the depth is the interesting part.
+		let aspect_mask = if attachment.subresource_range.aspect_mask.contains(vk::ImageAspectFlags::DEPTH) {
+			vk::ImageAspectFlags::DEPTH
+		} else {
+			attachment.subresource_range.aspect_mask
+		};
+
+		let mut upload_context = self.swapchain.device.create_upload_context();
+		upload_context.wait_submit(|device, cmd| unsafe {
+			device.cmd_pipeline_barrier(
+				cmd,
+				vk::PipelineStageFlags::ALL_COMMANDS,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[vk::ImageMemoryBarrier::builder()
+					.src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+					.dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+					.old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+					.new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+					.image(attachment.image)
+					.subresource_range(attachment.subresource_range)
+					.build()],
+			);
+
+			device.cmd_copy_image_to_buffer(
+				cmd,
+				attachment.image,
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				readback.raw,
+				&[vk::BufferImageCopy::builder()
+					.image_subresource(vk::ImageSubresourceLayers::builder().aspect_mask(aspect_mask).mip_level(0).base_array_layer(0).layer_count(1).build())
+					.image_offset(vk::Offset3D { x: x as i32, y: y as i32, z: 0 })
+					.image_extent(vk::Extent3D { width: 1, height: 1, depth: 1 })
+					.build()],
+			);
+
+			device.cmd_pipeline_barrier(
+				cmd,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::HOST,
+				vk::DependencyFlags::empty(),
+				&[],
+				&[vk::BufferMemoryBarrier::builder()
+					.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+					.dst_access_mask(vk::AccessFlags::HOST_READ)
+					.buffer(readback.raw)
+					.offset(0)
+					.size(vk::WHOLE_SIZE)
+					.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+					.build()],
+				&[],
+			);
+		});
+		self.swapchain.device.destroy_upload_context(upload_context);
+
+		let mut texel = [0u8; 16];
+		texel.copy_from_slice(&readback.allocation.mapped_slice().expect("Failed to map readback buffer!")[..16]);
+		self.swapchain.device.destroy_buffer(readback);
+
+		Ok(texel)
+	}
+}
+
 impl VulkanDevice {
-	pub fn create_texture(&self, width: u32, height: u32, format: TextureFormat, usage: TextureUsage) -> VulkanTexture {
+	pub fn create_texture(&self, name: &str, width: u32, height: u32, format: TextureFormat, usage: TextureUsage) -> GoldfishResult<VulkanTexture> {
+		if format.is_compressed() {
+			if usage.intersects(TextureUsage::ATTACHMENT | TextureUsage::STORAGE) {
+				return Err(GoldfishError::Render(RenderError::CompressedAttachment(format)));
+			}
+
+			if self.enabled_features.texture_compression_bc == 0 {
+				return Err(GoldfishError::Render(RenderError::UnsupportedFeature("BC texture compression")));
+			}
+		}
+
 		let mut usage_flags = vk::ImageUsageFlags::default();
 
 		if usage.contains(TextureUsage::ATTACHMENT) {
@@ -63,20 +385,25 @@ impl VulkanDevice {
 			usage_flags |= vk::ImageUsageFlags::STORAGE;
 		}
 
-		let mut guard = self.vma.lock().unwrap();
-		let vma = guard.as_mut().unwrap();
-
 		let vk_format = format.to_vk(self);
 
+		let create_flags = if format.is_cubemap() {
+			vk::ImageCreateFlags::CUBE_COMPATIBLE
+		} else {
+			vk::ImageCreateFlags::default()
+		};
+
+		unsafe {
+			self.instance
+				.get_physical_device_image_format_properties(self.physical_device, vk_format, vk::ImageType::TYPE_2D, vk::ImageTiling::OPTIMAL, usage_flags, create_flags)
+				.map_err(move |err| GoldfishError::Render(err.into()))?;
+		}
+
 		let image = unsafe {
 			self.raw
 				.create_image(
 					&vk::ImageCreateInfo::builder()
-						.flags(if format.is_cubemap() {
-							vk::ImageCreateFlags::CUBE_COMPATIBLE
-						} else {
-							vk::ImageCreateFlags::default()
-						})
+						.flags(create_flags)
 						.image_type(vk::ImageType::TYPE_2D)
 						.format(vk_format)
 						.extent(vk::Extent3D { width, height, depth: 1 })
@@ -89,22 +416,28 @@ impl VulkanDevice {
 						.initial_layout(vk::ImageLayout::UNDEFINED),
 					None,
 				)
-				.expect("Failed to create image!")
+				.map_err(move |err| GoldfishError::Render(err.into()))?
 		};
 
 		let requirements = unsafe { self.raw.get_image_memory_requirements(image) };
 
-		let allocation = vma
-			.allocate(&vma::AllocationCreateDesc {
-				name: "Texture",
-				requirements,
-				location: MemoryLocation::GpuOnly,
-				linear: false,
-			})
-			.expect("Failed to allocate memory!");
+		let allocation = match self.allocate(&vma::AllocationCreateDesc {
+			name,
+			requirements,
+			location: MemoryLocation::GpuOnly,
+			linear: false,
+		}) {
+			Ok(allocation) => allocation,
+			Err(err) => {
+				unsafe { self.raw.destroy_image(image, None) };
+				return Err(GoldfishError::Render(err));
+			}
+		};
 
-		unsafe {
-			self.raw.bind_image_memory(image, allocation.memory(), allocation.offset()).expect("Failed to bind image memory!");
+		if let Err(err) = unsafe { self.raw.bind_image_memory(image, allocation.memory(), allocation.offset()) } {
+			unsafe { self.raw.destroy_image(image, None) };
+			self.free(allocation);
+			return Err(GoldfishError::Render(err.into()));
 		}
 
 		let sampler = unsafe {
@@ -151,7 +484,7 @@ impl VulkanDevice {
 				.expect("Failed to create image view!")
 		};
 
-		VulkanTexture {
+		let texture = VulkanTexture {
 			width,
 			height,
 
@@ -163,10 +496,66 @@ impl VulkanDevice {
 			allocation,
 			format,
 			usage,
+
+			owned: true,
+			sampled_layout: ImageLayout::ShaderReadOnlyOptimal,
+			guard: DestroyGuard::new("Texture", name),
+		};
+
+		// Imported textures aren't registered, frames that use them can't be captured.
+		self.with_capture_registry(|registry| registry.register_texture(name, &texture));
+
+		Ok(texture)
+	}
+
+	// Wraps an image created outside of the engine so it can be bound like any other texture. Nothing is owned or transitioned, the caller keeps it alive and in layout.
+	pub fn import_texture_raw(&self, image: vk::Image, image_view: vk::ImageView, sampler: vk::Sampler, width: u32, height: u32, format: TextureFormat, layout: ImageLayout) -> VulkanTexture {
+		assert!(
+			matches!(layout, ImageLayout::ShaderReadOnlyOptimal | ImageLayout::DepthStencilReadOnlyOptimal | ImageLayout::General),
+			"Imported textures must be in a layout that can be sampled from!"
+		);
+		assert!(!format.is_cubemap(), "Imported cubemaps aren't supported!");
+
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(match format {
+				TextureFormat::Depth => vk::ImageAspectFlags::DEPTH,
+				_ => vk::ImageAspectFlags::COLOR,
+			})
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1)
+			.build();
+
+		VulkanTexture {
+			width,
+			height,
+
+			image,
+			sampler,
+			image_view,
+			subresource_range,
+
+			allocation: vma::Allocation::default(),
+			format,
+			usage: TextureUsage::SAMPLED,
+
+			owned: false,
+			sampled_layout: layout,
+			guard: DestroyGuard::untracked(),
 		}
 	}
 
-	pub fn destroy_texture(&mut self, texture: VulkanTexture) {
+	pub fn create_texture_unnamed(&self, width: u32, height: u32, format: TextureFormat, usage: TextureUsage) -> GoldfishResult<VulkanTexture> {
+		self.create_texture(UNNAMED_TEXTURE, width, height, format, usage)
+	}
+
+	pub fn destroy_texture(&mut self, mut texture: VulkanTexture) {
+		texture.guard.mark_destroyed();
+		if !texture.owned {
+			return;
+		}
+
 		self.queue_destruction(&mut [
 			VulkanDestructor::Image(texture.image),
 			VulkanDestructor::ImageView(texture.image_view),
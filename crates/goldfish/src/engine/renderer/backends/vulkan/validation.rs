@@ -0,0 +1,177 @@
+use crate::renderer::{OnValidationError, ValidationDesc};
+use ash::vk;
+use log::Level;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Validation messages are logged under this instead of the module, so they can be filtered on their own.
+pub const VALIDATION_LOG_TARGET: &str = "goldfish::validation";
+
+// A message that keeps coming back is logged once, and then at most once every this often with how many times it came back in between.
+const REPEAT_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+struct SeenMessage {
+	name: String,
+	level: Level,
+	// Since it was last logged.
+	repeats: u64,
+	last_logged: Instant,
+}
+
+// Everything the debug callback needs, the messenger's user data points at this. It's owned by the device and has to outlive the messenger.
+pub struct ValidationState {
+	on_error: OnValidationError,
+	suppressed: HashSet<i32>,
+	// Keyed by message_id_number.
+	seen: Mutex<HashMap<i32, SeenMessage>>,
+	// Set whenever validation reports an error, which can happen on any thread the driver feels like.
+	error_raised: AtomicBool,
+	// Panicking in the callback would unwind into the driver, so the first error is held onto and take_error panics with it.
+	panic_message: Mutex<Option<String>>,
+}
+
+impl ValidationState {
+	pub fn new(desc: &ValidationDesc) -> Self {
+		Self {
+			on_error: desc.on_error,
+			suppressed: desc.suppressed_messages.iter().copied().collect(),
+			seen: Mutex::new(HashMap::new()),
+			error_raised: AtomicBool::new(false),
+			panic_message: Mutex::new(None),
+		}
+	}
+
+	// Whether validation has reported an error since the last call, this is where OnValidationError::Panic actually panics.
+	pub fn take_error(&self) -> bool {
+		if let Some(message) = self.panic_message.lock().unwrap().take() {
+			panic!("Validation reported an error: {}", message);
+		}
+
+		self.error_raised.swap(false, Ordering::Relaxed)
+	}
+
+	// Logs whatever repeats haven't been logged yet, so the counts aren't lost when the device goes away.
+	pub fn flush(&self) {
+		for (message_id_number, seen) in self.seen.lock().unwrap().iter_mut() {
+			if seen.repeats > 0 {
+				log::log!(target: VALIDATION_LOG_TARGET, seen.level, "[{} ({})] : seen {} more times", seen.name, message_id_number, seen.repeats);
+				seen.repeats = 0;
+			}
+		}
+	}
+
+	// Everything the debug callback does once the strings are out of the callback data, call this directly to fake a message.
+	pub fn handle_message(
+		&self,
+		message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+		message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+		message_id_number: i32,
+		message_id_name: &str,
+		message: &str,
+	) {
+		if self.suppressed.contains(&message_id_number) {
+			return;
+		}
+
+		let level = if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+			Level::Error
+		} else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+			Level::Warn
+		} else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+			// The loader alone sends dozens of these on startup, so they're only worth seeing when asked for.
+			Level::Debug
+		} else {
+			Level::Trace
+		};
+
+		let validation_error = level == Level::Error && message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION);
+		if validation_error {
+			self.error_raised.store(true, Ordering::Relaxed);
+		}
+
+		// Messages without an ID are mostly the loader's, which are all different from each other so they're always logged.
+		if message_id_number != 0 {
+			let mut seen = self.seen.lock().unwrap();
+			if let Some(seen) = seen.get_mut(&message_id_number) {
+				seen.repeats += 1;
+				if seen.last_logged.elapsed() >= REPEAT_LOG_INTERVAL {
+					log::log!(target: VALIDATION_LOG_TARGET, level, "[{} ({})] : seen {} more times", message_id_name, message_id_number, seen.repeats);
+					seen.repeats = 0;
+					seen.last_logged = Instant::now();
+				}
+				return;
+			}
+
+			seen.insert(
+				message_id_number,
+				SeenMessage {
+					name: message_id_name.to_string(),
+					level,
+					repeats: 0,
+					last_logged: Instant::now(),
+				},
+			);
+		}
+
+		log::log!(target: VALIDATION_LOG_TARGET, level, "{:?} [{} ({})] : {}", message_type, message_id_name, message_id_number, message);
+
+		// Only the first time each error comes in, otherwise an error that comes back every frame would stop every frame.
+		if validation_error {
+			match self.on_error {
+				OnValidationError::Log => {}
+				OnValidationError::Panic => {
+					self.panic_message
+						.lock()
+						.unwrap()
+						.get_or_insert_with(|| format!("[{} ({})] : {}", message_id_name, message_id_number, message));
+				}
+				OnValidationError::DebugBreak => debug_break(),
+			}
+		}
+	}
+}
+
+// Stops in the debugger on the line that called this. Without a debugger attached the process dies with a breakpoint trap.
+fn debug_break() {
+	#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+	unsafe {
+		std::arch::asm!("int3");
+	}
+
+	#[cfg(target_arch = "aarch64")]
+	unsafe {
+		std::arch::asm!("brk #0xf000");
+	}
+}
+
+// user_data has to point at the device's ValidationState.
+pub unsafe extern "system" fn vulkan_debug_callback(
+	message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+	message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+	p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+	user_data: *mut c_void,
+) -> vk::Bool32 {
+	let state = &*(user_data as *const ValidationState);
+	let callback_data = *p_callback_data;
+
+	let message_id_name = if callback_data.p_message_id_name.is_null() {
+		Cow::from("")
+	} else {
+		CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
+	};
+
+	let message = if callback_data.p_message.is_null() {
+		Cow::from("")
+	} else {
+		CStr::from_ptr(callback_data.p_message).to_string_lossy()
+	};
+
+	state.handle_message(message_severity, message_type, callback_data.message_id_number, &message_id_name, &message);
+
+	vk::FALSE
+}
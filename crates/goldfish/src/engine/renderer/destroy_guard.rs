@@ -0,0 +1,161 @@
+use log::error;
+use std::backtrace::Backtrace;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CAPTURE_BACKTRACES: AtomicBool = AtomicBool::new(false);
+
+// Whether guards created from here on remember where they were created. Capturing a backtrace for every resource is slow, so it's off
+// unless GoldfishConfig::resource_backtraces turns it on.
+pub fn set_capture_destroy_backtraces(enabled: bool) {
+	CAPTURE_BACKTRACES.store(enabled, Ordering::Relaxed);
+}
+
+// Lives in every GPU wrapper type that has to be handed back to the device (buffers, textures, shaders, pipelines, descriptor heaps and
+// meshes). In debug builds dropping one that was never marked destroyed logs an error, since whatever it was in just leaked. It's empty
+// in release builds.
+#[derive(Default)]
+pub struct DestroyGuard {
+	#[cfg(debug_assertions)]
+	state: Option<Box<GuardState>>,
+}
+
+#[cfg(debug_assertions)]
+struct GuardState {
+	kind: &'static str,
+	name: String,
+	backtrace: Option<Backtrace>,
+	destroyed: bool,
+}
+
+#[cfg(debug_assertions)]
+impl GuardState {
+	fn label(&self) -> String {
+		match self.name.as_str() {
+			"" => self.kind.to_string(),
+			name => format!("{} \"{}\"", self.kind, name),
+		}
+	}
+}
+
+impl DestroyGuard {
+	// kind is what shows up in the error, like "Buffer", and name is the resource's debug name. Leave it empty for resources without one.
+	pub fn new(kind: &'static str, name: &str) -> Self {
+		#[cfg(not(debug_assertions))]
+		let _ = (kind, name);
+
+		Self {
+			#[cfg(debug_assertions)]
+			state: Some(Box::new(GuardState {
+				kind,
+				name: name.to_string(),
+				backtrace: CAPTURE_BACKTRACES.load(Ordering::Relaxed).then(Backtrace::force_capture),
+				destroyed: false,
+			})),
+		}
+	}
+
+	// For wrappers that don't own what they wrap, like imported resources or a view of something owned elsewhere. Dropping these is fine.
+	pub fn untracked() -> Self {
+		Self::default()
+	}
+
+	// Called by the destroy functions, panics if the resource was already destroyed.
+	pub fn mark_destroyed(&mut self) {
+		#[cfg(debug_assertions)]
+		if let Some(state) = self.state.as_mut() {
+			assert!(!state.destroyed, "{} was destroyed twice!", state.label());
+			state.destroyed = true;
+		}
+	}
+}
+
+#[cfg(debug_assertions)]
+impl DestroyGuard {
+	// What gets logged when the guard is dropped, None if dropping it is fine.
+	fn leak_message(&self) -> Option<String> {
+		let state = self.state.as_ref().filter(|state| !state.destroyed)?;
+
+		Some(match &state.backtrace {
+			Some(backtrace) => format!("{} was dropped without being destroyed and leaked! It was created at:\n{}", state.label(), backtrace),
+			None => format!(
+				"{} was dropped without being destroyed and leaked! Turn on resource_backtraces in the config to see where it was created.",
+				state.label()
+			),
+		})
+	}
+}
+
+#[cfg(debug_assertions)]
+impl Drop for DestroyGuard {
+	fn drop(&mut self) {
+		// Unwinding drops everything on the way out, which would bury the panic under a pile of leaks.
+		if std::thread::panicking() {
+			return;
+		}
+
+		if let Some(message) = self.leak_message() {
+			error!("{}", message);
+		}
+	}
+}
+
+// The guard never changes what the resource it's in compares or hashes as, that's up to the handles.
+impl PartialEq for DestroyGuard {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+
+impl Eq for DestroyGuard {}
+
+impl Hash for DestroyGuard {
+	fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn leaked_guards_report_the_resource() {
+		let named = DestroyGuard::new("Buffer", "Leaky");
+		assert!(named.leak_message().unwrap().starts_with("Buffer \"Leaky\" was dropped without being destroyed"));
+
+		let unnamed = DestroyGuard::new("Texture", "");
+		assert!(unnamed.leak_message().unwrap().starts_with("Texture was dropped without being destroyed"));
+	}
+
+	#[test]
+	fn destroyed_and_untracked_guards_drop_quietly() {
+		let mut destroyed = DestroyGuard::new("Mesh", "Cube");
+		destroyed.mark_destroyed();
+		assert_eq!(destroyed.leak_message(), None);
+
+		let mut untracked = DestroyGuard::untracked();
+		assert_eq!(untracked.leak_message(), None);
+		untracked.mark_destroyed();
+		untracked.mark_destroyed();
+	}
+
+	#[test]
+	#[should_panic(expected = "Shader \"Blit\" was destroyed twice!")]
+	fn double_destroy_panics() {
+		let mut guard = DestroyGuard::new("Shader", "Blit");
+		guard.mark_destroyed();
+		guard.mark_destroyed();
+	}
+
+	#[test]
+	fn leaks_point_at_where_the_resource_was_created() {
+		set_capture_destroy_backtraces(true);
+		let guard = DestroyGuard::new("Pipeline", "Forward");
+		set_capture_destroy_backtraces(false);
+
+		let message = guard.leak_message().unwrap();
+		assert!(message.contains("It was created at:"), "{}", message);
+
+		let mut guard = guard;
+		guard.mark_destroyed();
+	}
+}
@@ -0,0 +1,298 @@
+use super::*;
+use log::warn;
+use std::sync::Arc;
+
+// Each chunk is one vertex or index buffer, meshes bigger than this still get their own dedicated buffers.
+pub const GEOMETRY_POOL_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GeometryRange {
+	pub buffer: Arc<GpuBuffer>,
+	pub offset: u64,
+	pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeometryHeapStats {
+	pub chunks: usize,
+	pub allocations: usize,
+	pub used_bytes: u64,
+	pub free_bytes: u64,
+	pub largest_free_range: u64,
+	pub free_ranges: usize,
+}
+
+impl GeometryHeapStats {
+	// 0 when all of the free space is in one range, getting closer to 1 the more it's split up into small ranges.
+	pub fn fragmentation(&self) -> f32 {
+		if self.free_bytes == 0 {
+			return 0.0;
+		}
+
+		1.0 - self.largest_free_range as f32 / self.free_bytes as f32
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeometryPoolStats {
+	pub vertices: GeometryHeapStats,
+	pub indices: GeometryHeapStats,
+	pub pending_frees: usize,
+}
+
+struct GeometryChunk {
+	buffer: Arc<GpuBuffer>,
+	// Sorted by offset as (offset, size), ranges that touch are always merged back together.
+	free_ranges: Vec<(u64, u64)>,
+}
+
+impl GeometryChunk {
+	// First fit, alignment doesn't need to be a power of two since vertex strides usually aren't.
+	fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+		for i in 0..self.free_ranges.len() {
+			let (offset, free_size) = self.free_ranges[i];
+			let aligned = (offset + alignment - 1) / alignment * alignment;
+			if aligned + size > offset + free_size {
+				continue;
+			}
+
+			let head = (offset, aligned - offset);
+			let tail = (aligned + size, offset + free_size - aligned - size);
+			self.free_ranges.splice(i..i + 1, [head, tail].into_iter().filter(|&(_, range_size)| range_size > 0));
+
+			return Some(aligned);
+		}
+
+		None
+	}
+
+	fn free(&mut self, offset: u64, size: u64) {
+		let i = self.free_ranges.partition_point(|&(free_offset, _)| free_offset < offset);
+		self.free_ranges.insert(i, (offset, size));
+
+		// Merge with the next range first so that i still points at the freed range when merging with the previous one.
+		if i + 1 < self.free_ranges.len() && offset + size == self.free_ranges[i + 1].0 {
+			self.free_ranges[i].1 += self.free_ranges.remove(i + 1).1;
+		}
+
+		if i > 0 && self.free_ranges[i - 1].0 + self.free_ranges[i - 1].1 == offset {
+			self.free_ranges[i - 1].1 += self.free_ranges.remove(i).1;
+		}
+	}
+}
+
+struct GeometryHeap {
+	name: &'static str,
+	usage: BufferUsage,
+	chunks: Vec<GeometryChunk>,
+	allocations: usize,
+}
+
+impl GeometryHeap {
+	fn new(name: &'static str, usage: BufferUsage) -> Self {
+		Self {
+			name,
+			usage,
+			chunks: Vec::new(),
+			allocations: 0,
+		}
+	}
+
+	fn allocate(&mut self, upload_context: &mut UploadContext, size: u64, alignment: u64) -> GoldfishResult<GeometryRange> {
+		assert!(size <= GEOMETRY_POOL_CHUNK_SIZE as u64, "Geometry allocation is bigger than a chunk!");
+
+		let found = self.chunks.iter_mut().find_map(|chunk| chunk.allocate(size, alignment).map(|offset| (chunk.buffer.clone(), offset)));
+
+		let (buffer, offset) = match found {
+			Some(found) => found,
+			None => {
+				let buffer = upload_context.create_buffer(
+					&format!("{} Chunk {}", self.name, self.chunks.len()),
+					GEOMETRY_POOL_CHUNK_SIZE,
+					MemoryLocation::GpuOnly,
+					self.usage | BufferUsage::TransferDst,
+					None,
+					None,
+				)?;
+
+				let mut chunk = GeometryChunk {
+					buffer: Arc::new(buffer),
+					free_ranges: vec![(0, GEOMETRY_POOL_CHUNK_SIZE as u64)],
+				};
+
+				let offset = chunk.allocate(size, alignment).unwrap();
+				let buffer = chunk.buffer.clone();
+				self.chunks.push(chunk);
+
+				(buffer, offset)
+			}
+		};
+
+		self.allocations += 1;
+
+		Ok(GeometryRange { buffer, offset, size })
+	}
+
+	fn free(&mut self, range: GeometryRange) {
+		let chunk = self
+			.chunks
+			.iter_mut()
+			.find(|chunk| Arc::ptr_eq(&chunk.buffer, &range.buffer))
+			.expect("Geometry range was not allocated from this pool!");
+
+		chunk.free(range.offset, range.size);
+		self.allocations -= 1;
+	}
+
+	fn stats(&self) -> GeometryHeapStats {
+		let free_ranges = self.chunks.iter().flat_map(|chunk| chunk.free_ranges.iter().map(|&(_, size)| size));
+		let free_bytes = free_ranges.clone().sum::<u64>();
+
+		GeometryHeapStats {
+			chunks: self.chunks.len(),
+			allocations: self.allocations,
+			used_bytes: (self.chunks.len() * GEOMETRY_POOL_CHUNK_SIZE) as u64 - free_bytes,
+			free_bytes,
+			largest_free_range: free_ranges.clone().max().unwrap_or(0),
+			free_ranges: free_ranges.count(),
+		}
+	}
+
+	fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		for chunk in self.chunks {
+			match Arc::try_unwrap(chunk.buffer) {
+				Ok(buffer) => graphics_device.destroy_buffer(buffer),
+				// There's no way to destroy the buffer while meshes still point into it, so it gets leaked and shows up in the leak report.
+				Err(_) => warn!("{} was destroyed while meshes were still using it!", self.name),
+			}
+		}
+	}
+}
+
+// Small meshes get suballocated out of a few big shared vertex and index buffers, hand them back with free_mesh.
+pub struct GeometryPool {
+	vertices: GeometryHeap,
+	indices: GeometryHeap,
+	frames_in_flight: usize,
+	frame: u64,
+	// The frame each mesh was freed on with its vertex and index ranges, they aren't reused until that frame can't be in flight anymore.
+	pending_frees: Vec<(u64, GeometryRange, GeometryRange)>,
+}
+
+impl GeometryPool {
+	pub fn new(frames_in_flight: usize) -> Self {
+		Self {
+			vertices: GeometryHeap::new("Geometry Pool Vertices", BufferUsage::VertexBuffer),
+			indices: GeometryHeap::new("Geometry Pool Indices", BufferUsage::IndexBuffer),
+			frames_in_flight,
+			frame: 0,
+			pending_frees: Vec::new(),
+		}
+	}
+
+	pub fn upload_mesh(&mut self, upload_context: &mut UploadContext, name: &str, vertices: &[Vertex], indices: &[u16]) -> GoldfishResult<Mesh> {
+		tracy::span!();
+		self.upload_mesh_from_bytes(upload_context, name, bytemuck::cast_slice(vertices), std::mem::size_of::<Vertex>(), indices)
+	}
+
+	// Draw these with a pipeline using SkinnedVertex::VERTEX_INFO.
+	pub fn upload_skinned_mesh(&mut self, upload_context: &mut UploadContext, name: &str, vertices: &[SkinnedVertex], indices: &[u16]) -> GoldfishResult<Mesh> {
+		tracy::span!();
+		self.upload_mesh_from_bytes(upload_context, name, bytemuck::cast_slice(vertices), std::mem::size_of::<SkinnedVertex>(), indices)
+	}
+
+	// Draw these with a pipeline using ExtendedVertex::VERTEX_INFO.
+	pub fn upload_extended_mesh(&mut self, upload_context: &mut UploadContext, name: &str, vertices: &[ExtendedVertex], indices: &[u16]) -> GoldfishResult<Mesh> {
+		tracy::span!();
+		self.upload_mesh_from_bytes(upload_context, name, bytemuck::cast_slice(vertices), std::mem::size_of::<ExtendedVertex>(), indices)
+	}
+
+	pub fn upload_mesh_package(&mut self, upload_context: &mut UploadContext, name: &str, package: &MeshPackage) -> GoldfishResult<Mesh> {
+		match package.extended_vertices() {
+			Some(vertices) => self.upload_extended_mesh(upload_context, name, &vertices, &package.indices),
+			None => self.upload_mesh(upload_context, name, &package.vertices, &package.indices),
+		}
+	}
+
+	fn upload_mesh_from_bytes(&mut self, upload_context: &mut UploadContext, name: &str, vertices: &[u8], stride: usize, indices: &[u16]) -> GoldfishResult<Mesh> {
+		assert!(!vertices.is_empty() && !indices.is_empty(), "Cannot upload an empty mesh!");
+
+		let index_bytes: &[u8] = bytemuck::cast_slice(indices);
+		if vertices.len() > GEOMETRY_POOL_CHUNK_SIZE || index_bytes.len() > GEOMETRY_POOL_CHUNK_SIZE {
+			return upload_context.create_mesh_from_bytes(name, vertices, indices);
+		}
+
+		// Vertex ranges are aligned to the stride so that the offset can be given to the draw as a vertex offset.
+		let vertex_range = self.vertices.allocate(upload_context, vertices.len() as u64, stride as u64)?;
+		let index_range = match self.indices.allocate(upload_context, index_bytes.len() as u64, std::mem::size_of::<u16>() as u64) {
+			Ok(index_range) => index_range,
+			Err(err) => {
+				self.vertices.free(vertex_range);
+				return Err(err);
+			}
+		};
+
+		let written = upload_context
+			.write_buffer(&format!("{} (Vertices)", name), &vertex_range.buffer, vertex_range.offset, vertices)
+			.and_then(|_| upload_context.write_buffer(&format!("{} (Indices)", name), &index_range.buffer, index_range.offset, index_bytes));
+
+		if let Err(err) = written {
+			self.vertices.free(vertex_range);
+			self.indices.free(index_range);
+			return Err(err);
+		}
+
+		Ok(Mesh {
+			index_count: indices.len() as u32,
+			first_index: (index_range.offset / std::mem::size_of::<u16>() as u64) as u32,
+			vertex_offset: (vertex_range.offset / stride as u64) as i32,
+			vertex_buffer: MeshBuffer::Pooled(vertex_range),
+			index_buffer: MeshBuffer::Pooled(index_range),
+			guard: DestroyGuard::new("Mesh", name),
+		})
+	}
+
+	// Meshes that were too big for the pool are destroyed right away, everything else waits for end_frame.
+	pub fn free_mesh(&mut self, graphics_device: &mut GraphicsDevice, mut mesh: Mesh) {
+		tracy::span!();
+		match (mesh.vertex_buffer, mesh.index_buffer) {
+			(MeshBuffer::Pooled(vertex_range), MeshBuffer::Pooled(index_range)) => {
+				mesh.guard.mark_destroyed();
+				self.pending_frees.push((self.frame, vertex_range, index_range));
+			}
+			(vertex_buffer, index_buffer) => graphics_device.destroy_mesh(Mesh { vertex_buffer, index_buffer, ..mesh }),
+		}
+	}
+
+	// Called by the engine once per frame, hands back the ranges of meshes freed long enough ago that no frame in flight can be drawing them.
+	pub fn end_frame(&mut self) {
+		for (freed_frame, vertex_range, index_range) in std::mem::take(&mut self.pending_frees) {
+			if self.frame - freed_frame >= self.frames_in_flight as u64 {
+				self.vertices.free(vertex_range);
+				self.indices.free(index_range);
+			} else {
+				self.pending_frees.push((freed_frame, vertex_range, index_range));
+			}
+		}
+
+		self.frame += 1;
+	}
+
+	pub fn stats(&self) -> GeometryPoolStats {
+		GeometryPoolStats {
+			vertices: self.vertices.stats(),
+			indices: self.indices.stats(),
+			pending_frees: self.pending_frees.len(),
+		}
+	}
+
+	pub fn destroy(mut self, graphics_device: &mut GraphicsDevice) {
+		for (_, vertex_range, index_range) in std::mem::take(&mut self.pending_frees) {
+			self.vertices.free(vertex_range);
+			self.indices.free(index_range);
+		}
+
+		self.vertices.destroy(graphics_device);
+		self.indices.destroy(graphics_device);
+	}
+}
@@ -0,0 +1,123 @@
+use super::*;
+use glam::Mat4;
+use log::warn;
+
+// Has to match PointLight in the game's utils.hlsli, except the position and radius are in view space by the time the
+// shaders see them.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GpuPointLight {
+	color: [f32; 4],
+	position: [f32; 3],
+	radius: f32,
+}
+
+unsafe impl bytemuck::Pod for GpuPointLight {}
+unsafe impl bytemuck::Zeroable for GpuPointLight {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+	// In world space.
+	pub position: Vec3,
+	// Nothing past this is lit, the light cull uses it to pick which tiles the light goes into.
+	pub radius: f32,
+	pub color: Color,
+}
+
+// Handed out by LightManager::add_point_light, never reused for another light.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct LightId(u64);
+
+// Every point light in the scene, for forward+ lighting. Lights are added and moved around in world space, and prepare writes them into
+// a StructuredBuffer<PointLight> in view space once a frame, so the light cull and the lighting don't each transform every light again.
+// Bind buffer wherever a shader wants the lights, light_count is how many of them are in it.
+pub struct LightManager {
+	lights: Vec<(LightId, PointLight)>,
+	next_id: u64,
+	max_lights: usize,
+	buffer: PerFrameBuffer,
+	// How many lights the last prepare wrote.
+	count: usize,
+}
+
+impl LightManager {
+	pub fn new(upload_context: &mut UploadContext, max_lights: usize) -> GoldfishResult<Self> {
+		tracy::span!();
+		assert!(max_lights > 0, "Cannot create a light manager without any lights!");
+
+		let buffer = PerFrameBuffer::new(upload_context, "Point Lights", std::mem::size_of::<GpuPointLight>() * max_lights, BufferUsage::StorageBuffer)?;
+
+		Ok(Self {
+			lights: Vec::new(),
+			next_id: 0,
+			max_lights,
+			buffer,
+			count: 0,
+		})
+	}
+
+	// Lights past max_lights are kept around, but only the first max_lights (in the order they were added) make it into the buffer.
+	pub fn add_point_light(&mut self, light: PointLight) -> LightId {
+		let id = LightId(self.next_id);
+		self.next_id += 1;
+
+		if self.lights.len() == self.max_lights {
+			warn!("More than {} point lights were added, the rest won't light anything until some are removed!", self.max_lights);
+		}
+
+		self.lights.push((id, light));
+		id
+	}
+
+	// Returns false if the light was already removed.
+	pub fn update(&mut self, id: LightId, light: PointLight) -> bool {
+		match self.lights.iter_mut().find(|(light_id, _)| *light_id == id) {
+			Some((_, existing)) => {
+				*existing = light;
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub fn remove(&mut self, id: LightId) -> Option<PointLight> {
+		let index = self.lights.iter().position(|(light_id, _)| *light_id == id)?;
+		Some(self.lights.remove(index).1)
+	}
+
+	pub fn get(&self, id: LightId) -> Option<&PointLight> {
+		self.lights.iter().find(|(light_id, _)| *light_id == id).map(|(_, light)| light)
+	}
+
+	// Writes this frame's lights in view space, call it once a frame before recording with the same GraphicsContext::frame_slot.
+	pub fn prepare(&mut self, frame_slot: usize, view: Mat4) {
+		// Views don't usually scale, but if this one does the radius has to shrink or grow along with everything else.
+		let radius_scale = view.transform_vector3(Vec3::X).length();
+		let lights = self
+			.lights
+			.iter()
+			.take(self.max_lights)
+			.map(|(_, light)| GpuPointLight {
+				color: [light.color.r, light.color.g, light.color.b, light.color.a],
+				position: view.transform_point3(light.position).to_array(),
+				radius: light.radius * radius_scale,
+			})
+			.collect::<Vec<_>>();
+
+		self.count = lights.len();
+		self.buffer.write(frame_slot, bytemuck::cast_slice(&lights));
+	}
+
+	pub fn buffer(&self) -> &PerFrameBuffer {
+		&self.buffer
+	}
+
+	// How many lights are in the buffer as of the last prepare.
+	pub fn light_count(&self) -> usize {
+		self.count
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		self.buffer.destroy(graphics_device);
+	}
+}
@@ -3,26 +3,58 @@ use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use uuid::uuid;
 
-use super::package::{AssetType, Package};
-use super::GoldfishEngine;
-use crate::types::Color;
+use super::package::{AssetType, MeshPackage, Package};
+use super::{GoldfishEngine, GoldfishResult};
+use crate::types::{Color, Size};
 use backends::vulkan::*;
-use glam::{Vec2, Vec3};
+use glam::{DVec2, UVec2, Vec2, Vec3, Vec4};
+pub use goldfish_derive::VertexInput;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
 use tracy_client as tracy;
 pub mod backends;
+pub mod destroy_guard;
+pub mod geometry_pool;
+pub mod lights;
+pub mod overlay;
+mod overlay_font;
+pub mod particles;
+pub mod passes;
+pub mod per_frame_buffer;
 pub mod render_graph;
+pub mod renderdoc;
+pub mod resolution_scaler;
+pub mod shader_reload;
+pub mod shadow_atlas;
 
+pub use destroy_guard::*;
+pub use geometry_pool::*;
+pub use lights::*;
+pub use overlay::*;
+pub use particles::*;
+pub use passes::*;
+pub use per_frame_buffer::*;
 pub use render_graph::*;
+pub use resolution_scaler::*;
+pub use shader_reload::*;
+pub use shadow_atlas::*;
 
 pub const VS_MAIN: &'static str = "vs_main";
 pub const PS_MAIN: &'static str = "ps_main";
+pub const GS_MAIN: &'static str = "gs_main";
 pub const CS_MAIN: &'static str = "cs_main";
 
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
 pub type GraphicsDevice = VulkanDevice;
 pub type GraphicsContext = VulkanGraphicsContext;
+pub type FrameGuard<'a> = VulkanFrameGuard<'a>;
+pub type Frame<'a> = VulkanFrameGuard<'a>;
 pub type UploadContext = VulkanUploadContext;
 pub type GpuBuffer = VulkanBuffer;
+pub type MappedBuffer<'a> = VulkanMappedBuffer<'a>;
 pub type Pipeline = VulkanPipeline;
 pub type RenderPass = VulkanRenderPass;
 pub type Shader = VulkanShader;
@@ -32,14 +64,52 @@ pub type DescriptorHeap = VulkanDescriptorHeap;
 pub type DescriptorLayoutCache = VulkanDescriptorLayoutCache;
 pub type DescriptorHandle = VulkanDescriptorHandle;
 pub type DescriptorLayout = VulkanDescriptorLayout;
+pub type TransientDescriptor = VulkanTransientDescriptor;
+pub type FrameCapture = VulkanFrameCapture;
+pub type ReplayOutput = VulkanReplayOutput;
 
-pub struct FrameId(u32);
+#[derive(Error, Debug)]
+pub enum RenderError {
+	#[error("The graphics device was lost")]
+	DeviceLost,
+	#[error("Out of memory")]
+	OutOfMemory,
+	#[error("Invalid shader: {message}")]
+	InvalidShader { message: String },
+	#[error("Unsupported format")]
+	UnsupportedFormat,
+	#[error("The surface cannot be presented to from this graphics device")]
+	UnsupportedSurface,
+	#[error("The graphics device does not support {0}")]
+	UnsupportedFeature(&'static str),
+	#[error("Pixel ({x}, {y}) is outside of the {width}x{height} texture")]
+	OutOfBounds { x: u32, y: u32, width: u32, height: u32 },
+	#[error("The texture was not created with {0} usage")]
+	MissingTextureUsage(&'static str),
+	#[error("Block compressed format {0:?} can't be used as an attachment or storage image")]
+	CompressedAttachment(TextureFormat),
+	#[error("Expected {expected} bytes of texture data but got {actual}")]
+	InvalidTextureData { expected: usize, actual: usize },
+	#[error("Frame capture failed: {0}")]
+	Capture(String),
+	#[error("Unknown graphics API error: {0}")]
+	Unknown(String),
+}
 
-impl FrameId {
-	const FRAME_ID_MAX: u32 = 10000;
-	pub fn incr(&mut self) {
-		self.0 = (self.0 + 1) % Self::FRAME_ID_MAX;
-	}
+#[derive(Debug, Clone)]
+pub struct PassDiagnostics {
+	pub name: &'static str,
+	pub resources: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct ReadbackId(pub(crate) u64);
+
+// Texture regions are in texels of the first mip and come back as tightly packed rows, only the depth of depth stencil textures.
+#[derive(Clone, Copy)]
+pub enum ReadbackSource<'a> {
+	Texture { texture: &'a Texture, x: u32, y: u32, width: u32, height: u32 },
+	Buffer { buffer: &'a GpuBuffer, offset: u64, size: u64 },
 }
 
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -47,18 +117,28 @@ pub enum TextureFormat {
 	// Fixed point [0.0, 1.0]
 	// All 0s == 0.0
 	// All 1s == 1.0
+	// The aliases are the names these had before the signed and integer formats were added,
+	// older .meta files still reference them so they need to keep deserializing.
+	#[serde(alias = "R8")]
 	R8UNorm,
+	#[serde(alias = "R16")]
 	R16UNorm,
 
+	#[serde(alias = "RG8")]
 	RG8UNorm,
+	#[serde(alias = "RG16")]
 	RG16UNorm,
 
+	#[serde(alias = "RGB8")]
 	RGB8UNorm,
+	#[serde(alias = "RGB16")]
 	RGB16UNorm,
 	CubemapRGB8UNorm,
 	CubemapRGB16UNorm,
 
+	#[serde(alias = "RGBA8")]
 	RGBA8UNorm,
+	#[serde(alias = "RGBA16")]
 	RGBA16UNorm,
 	CubemapRGBA8UNorm,
 	CubemapRGBA16UNorm,
@@ -127,17 +207,209 @@ pub enum TextureFormat {
 	RGB32Float,
 	RGBA32Float,
 
+	// Block compressed, the width and height get rounded up to a whole 4x4 block.
+	BC1UNorm,
+	BC1SRGB,
+	BC3UNorm,
+	BC3SRGB,
+	BC5UNorm,
+	BC7UNorm,
+	BC7SRGB,
+
 	// Depth formats
 	// TODO(Brandon): Add depth stencil format here.
 	Depth,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum SurfacePreference {
+	Hdr10,
+	Srgb,
+	#[default]
+	Unorm,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum PresentMode {
+	// Vsync, presents wait for the vertical blank.
+	Fifo,
+	// Vsync without blocking, newer frames replace ones still waiting in the queue.
+	#[default]
+	Mailbox,
+	// No vsync at all, expect tearing.
+	Immediate,
+}
+
+// Which GPU the device gets created on, a GPU that can't present to the window or is missing the queues we need is never picked.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum DeviceSelection {
+	// Discrete GPUs first, then whichever supports the biggest textures.
+	#[default]
+	Auto,
+	// An AdapterInfo::index from GraphicsDevice::enumerate_adapters, device creation fails if that GPU can't be used.
+	Index(usize),
+	PreferIntegrated,
+	PreferDiscrete,
+}
+
+impl std::str::FromStr for DeviceSelection {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.trim().to_lowercase().as_str() {
+			"auto" => Ok(Self::Auto),
+			"integrated" => Ok(Self::PreferIntegrated),
+			"discrete" => Ok(Self::PreferDiscrete),
+			index => index
+				.parse::<usize>()
+				.map(Self::Index)
+				.map_err(|_| format!("Unknown GPU selection \"{}\", expected an adapter index, auto, integrated or discrete", s)),
+		}
+	}
+}
+
+// How the final output pass fits the scene into the swapchain when the window isn't the shape the game renders at.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum OutputPolicy {
+	// Covers the whole output, whatever shape it is.
+	#[default]
+	Stretch,
+	// The largest centered rect with this aspect (width over height), the rest of the output is cleared to bar_color.
+	Letterbox {
+		aspect: f32,
+		bar_color: Color,
+	},
+	// The largest whole multiple of width by height that fits, so pixel art stays crisp.
+	IntegerScale {
+		width: u32,
+		height: u32,
+		bar_color: Color,
+	},
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OutputViewport {
+	pub offset: UVec2,
+	pub extent: UVec2,
+}
+
+impl OutputViewport {
+	pub fn size(&self) -> Size {
+		Size {
+			width: self.extent.x,
+			height: self.extent.y,
+		}
+	}
+}
+
+impl OutputPolicy {
+	pub fn viewport(&self, output_size: Size) -> OutputViewport {
+		let output = UVec2::new(output_size.width, output_size.height);
+		let extent = match *self {
+			OutputPolicy::Stretch => output,
+			OutputPolicy::Letterbox { aspect, .. } => fit_aspect(output, aspect),
+			OutputPolicy::IntegerScale { width, height, .. } => match (output.x / width.max(1)).min(output.y / height.max(1)) {
+				0 => fit_aspect(output, width as f32 / height.max(1) as f32),
+				scale => UVec2::new(width, height) * scale,
+			},
+		};
+
+		OutputViewport {
+			offset: UVec2::new(output.x.saturating_sub(extent.x) / 2, output.y.saturating_sub(extent.y) / 2),
+			extent,
+		}
+	}
+
+	pub fn bar_color(&self) -> Option<Color> {
+		match *self {
+			OutputPolicy::Stretch => None,
+			OutputPolicy::Letterbox { bar_color, .. } | OutputPolicy::IntegerScale { bar_color, .. } => Some(bar_color),
+		}
+	}
+
+	pub fn output_to_viewport(&self, output_size: Size, position: DVec2) -> Option<DVec2> {
+		let viewport = self.viewport(output_size);
+		let position = position - viewport.offset.as_dvec2();
+		let extent = viewport.extent.as_dvec2();
+		(position.x >= 0.0 && position.y >= 0.0 && position.x < extent.x && position.y < extent.y).then_some(position)
+	}
+}
+
+fn fit_aspect(output: UVec2, aspect: f32) -> UVec2 {
+	assert!(aspect > 0.0, "Output aspect has to be positive!");
+	let aspect = aspect as f64;
+	if output.x as f64 > output.y as f64 * aspect {
+		UVec2::new(((output.y as f64 * aspect).round() as u32).min(output.x).max(1), output.y.max(1))
+	} else {
+		UVec2::new(output.x.max(1), ((output.x as f64 / aspect).round() as u32).min(output.y).max(1))
+	}
+}
+
+// What happens when validation reports an error, on top of it being logged.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum OnValidationError {
+	#[default]
+	Log,
+	// Panics at the start of the frame after the error was reported, so a CI run fails on any validation error.
+	Panic,
+	DebugBreak,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ValidationDesc {
+	pub on_error: OnValidationError,
+	pub suppressed_messages: Vec<i32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AdapterType {
+	Discrete,
+	Integrated,
+	Virtual,
+	Cpu,
+	Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdapterInfo {
+	pub index: usize,
+	pub name: String,
+	pub device_type: AdapterType,
+	pub vram_bytes: u64,
+	pub driver_version: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SurfaceFormat {
+	// 10 bit color with the ST2084 (PQ) transfer function, shaders need to encode to PQ themselves.
+	Hdr10,
+	// 8 bit SRGB typed, the hardware applies the gamma curve on write so shaders should skip the manual gamma correction.
+	Srgb,
+	// 8 bit UNORM in the SRGB color space, shaders do the gamma correction.
+	Unorm,
+	// Nothing we know about was available so we took the first format the surface reported and treat it like UNORM.
+	Other,
+}
+
+impl SurfaceFormat {
+	pub fn needs_manual_gamma(&self) -> bool {
+		matches!(self, SurfaceFormat::Unorm | SurfaceFormat::Other)
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum ClearValue {
 	Color { r: f32, g: f32, b: f32, a: f32 },
 	DepthStencil { depth: f32, stencil: u32 },
 }
 
+impl ClearValue {
+	pub fn from_color(color: Color, target_is_srgb: bool) -> Self {
+		let Color { r, g, b, a } = if target_is_srgb { color.to_linear() } else { color };
+		ClearValue::Color { r, g, b, a }
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum ImageLayout {
 	Undefined,
@@ -149,6 +421,19 @@ pub enum ImageLayout {
 	ShaderReadOnlyOptimal,
 	TransferSrcOptimal,
 	TransferDstOptimal,
+	PresentSrc,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Filter {
+	Nearest,
+	Linear,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum IndexType {
+	U16,
+	U32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -184,9 +469,16 @@ pub enum VertexAttributeFormat {
 	F32Vec2,
 	F32Vec3,
 	F32Vec4,
+	U32,
+	// Four bytes packed into a u32, normalized to [0.0, 1.0] when read in the shader.
+	U8x4Norm,
+	// Read as a uint4 in the shader, used for bone indices.
+	U16x4,
+	// Same as U8x4Norm but not normalized, VertexInput can't tell the two apart so this one has to be described by hand.
+	U8x4,
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct VertexAttributeDescriptionBinding {
 	pub location: u32,
 	pub format: VertexAttributeFormat,
@@ -199,39 +491,6 @@ pub struct VertexInputInfo {
 	pub stride: u32,
 }
 
-impl Vertex {
-	pub const VERTEX_INFO: VertexInputInfo = VertexInputInfo {
-		bindings: &[
-			VertexAttributeDescriptionBinding {
-				location: 0,
-				format: VertexAttributeFormat::F32Vec3,
-				offset: memoffset::offset_of!(Self, position) as u32,
-			},
-			VertexAttributeDescriptionBinding {
-				location: 1,
-				format: VertexAttributeFormat::F32Vec3,
-				offset: memoffset::offset_of!(Self, normal) as u32,
-			},
-			VertexAttributeDescriptionBinding {
-				location: 2,
-				format: VertexAttributeFormat::F32Vec2,
-				offset: memoffset::offset_of!(Self, uv) as u32,
-			},
-			VertexAttributeDescriptionBinding {
-				location: 3,
-				format: VertexAttributeFormat::F32Vec3,
-				offset: memoffset::offset_of!(Self, tangent) as u32,
-			},
-			VertexAttributeDescriptionBinding {
-				location: 4,
-				format: VertexAttributeFormat::F32Vec3,
-				offset: memoffset::offset_of!(Self, bitangent) as u32,
-			},
-		],
-		stride: std::mem::size_of::<Self>() as u32,
-	};
-}
-
 pub const EMPTY_VERTEX_INFO: VertexInputInfo = VertexInputInfo { bindings: &[], stride: 0 };
 
 impl TextureFormat {
@@ -243,6 +502,68 @@ impl TextureFormat {
 			| (*self == TextureFormat::CubemapSRGB8)
 			| (*self == TextureFormat::CubemapSRGBA8);
 	}
+
+	// Array layers of the image, cubemaps have one for each face in +X, -X, +Y, -Y, +Z, -Z order.
+	pub fn faces(&self) -> u32 {
+		if self.is_cubemap() {
+			6
+		} else {
+			1
+		}
+	}
+
+	pub fn block_bytes(&self) -> Option<usize> {
+		match self {
+			TextureFormat::BC1UNorm | TextureFormat::BC1SRGB => Some(8),
+			TextureFormat::BC3UNorm | TextureFormat::BC3SRGB | TextureFormat::BC5UNorm | TextureFormat::BC7UNorm | TextureFormat::BC7SRGB => Some(16),
+			_ => None,
+		}
+	}
+
+	pub fn texel_bytes(&self) -> Option<usize> {
+		match self {
+			TextureFormat::R8UNorm | TextureFormat::R8SNorm | TextureFormat::R8UInt | TextureFormat::R8SInt => Some(1),
+			TextureFormat::R16UNorm | TextureFormat::R16SNorm | TextureFormat::R16UInt | TextureFormat::R16SInt => Some(2),
+			TextureFormat::RG8UNorm | TextureFormat::RG8SNorm | TextureFormat::RG8UInt | TextureFormat::RG8SInt => Some(2),
+			TextureFormat::RGB8UNorm | TextureFormat::CubemapRGB8UNorm | TextureFormat::SRGB8 | TextureFormat::CubemapSRGB8 => Some(3),
+			TextureFormat::RGB8SNorm | TextureFormat::RGB8UInt | TextureFormat::RGB8SInt => Some(3),
+			TextureFormat::RG16UNorm | TextureFormat::RG16SNorm | TextureFormat::RG16UInt | TextureFormat::RG16SInt => Some(4),
+			TextureFormat::RGBA8UNorm | TextureFormat::CubemapRGBA8UNorm | TextureFormat::SRGBA8 | TextureFormat::CubemapSRGBA8 => Some(4),
+			TextureFormat::RGBA8SNorm | TextureFormat::RGBA8UInt | TextureFormat::RGBA8SInt => Some(4),
+			TextureFormat::R32UInt | TextureFormat::R32SInt | TextureFormat::R32Float => Some(4),
+			TextureFormat::Depth => Some(4),
+			TextureFormat::RGB16UNorm | TextureFormat::CubemapRGB16UNorm | TextureFormat::RGB16SNorm | TextureFormat::RGB16UInt | TextureFormat::RGB16SInt => Some(6),
+			TextureFormat::RGBA16UNorm | TextureFormat::CubemapRGBA16UNorm | TextureFormat::RGBA16SNorm | TextureFormat::RGBA16UInt | TextureFormat::RGBA16SInt => Some(8),
+			TextureFormat::RG32UInt | TextureFormat::RG32SInt | TextureFormat::RG32Float => Some(8),
+			TextureFormat::RGB32UInt | TextureFormat::RGB32SInt | TextureFormat::RGB32Float => Some(12),
+			TextureFormat::RGBA32UInt | TextureFormat::RGBA32SInt | TextureFormat::RGBA32Float => Some(16),
+			TextureFormat::BC1UNorm | TextureFormat::BC1SRGB | TextureFormat::BC3UNorm | TextureFormat::BC3SRGB | TextureFormat::BC5UNorm | TextureFormat::BC7UNorm | TextureFormat::BC7SRGB => None,
+		}
+	}
+
+	pub fn is_compressed(&self) -> bool {
+		self.block_bytes().is_some()
+	}
+
+	// Row pitch and size in bytes of a mip of a block compressed texture, partial blocks take a whole block.
+	pub fn compressed_mip_size(&self, width: u32, height: u32, mip_level: u32) -> Option<(usize, usize)> {
+		let block_bytes = self.block_bytes()?;
+
+		let blocks_wide = ((width >> mip_level).max(1) as usize + 3) / 4;
+		let blocks_high = ((height >> mip_level).max(1) as usize + 3) / 4;
+
+		let row_pitch = blocks_wide * block_bytes;
+		Some((row_pitch, row_pitch * blocks_high))
+	}
+
+	// The row length of a buffer to image copy in texels, which for compressed data is the mip's width rounded up to a whole block. Zero
+	// (tightly packed) for everything else.
+	pub fn buffer_row_length(&self, width: u32, mip_level: u32) -> u32 {
+		match (self.compressed_mip_size(width, 1, mip_level), self.block_bytes()) {
+			(Some((row_pitch, _)), Some(block_bytes)) => (row_pitch / block_bytes * 4) as u32,
+			_ => 0,
+		}
+	}
 }
 
 bitflags! {
@@ -269,19 +590,33 @@ bitflags! {
 		const StorageBuffer      = 0x20;
 		const IndexBuffer        = 0x40;
 		const VertexBuffer       = 0x80;
+		const IndirectBuffer     = 0x100;
 	}
 }
 
 pub use gpu_allocator::MemoryLocation;
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum MemoryLocationPreference {
+	Exact(MemoryLocation),
+	// CpuToGpu with resizable BAR so writes go straight into VRAM, otherwise GpuOnly and filled from a staging buffer.
+	Auto,
+}
+
+impl From<MemoryLocation> for MemoryLocationPreference {
+	fn from(location: MemoryLocation) -> Self {
+		Self::Exact(location)
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum LoadOp {
 	Load,
 	Clear,
 	DontCare,
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum StoreOp {
 	Store,
 	DontCare,
@@ -297,7 +632,7 @@ pub struct AttachmentDescription {
 	pub final_layout: ImageLayout,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub enum DescriptorBindingType {
 	Texture2D,
 	RWTexture2D,
@@ -307,16 +642,261 @@ pub enum DescriptorBindingType {
 	CBuffer,
 	StructuredBuffer,
 	RWStructuredBuffer,
+	// A fixed size Texture2D array, every element has to be written.
+	Texture2DArray { count: u32 },
+	// Needs descriptor indexing, elements that never get written are left unbound.
+	Texture2DRuntimeArray,
+	// Sampled with a direction, the texture has to have one of the Cubemap formats.
+	TextureCube,
+}
+
+impl DescriptorBindingType {
+	pub fn descriptor_count(&self) -> u32 {
+		match *self {
+			DescriptorBindingType::Texture2DArray { count } => count,
+			DescriptorBindingType::Texture2DRuntimeArray => MAX_BINDLESS_TEXTURES,
+			_ => 1,
+		}
+	}
+
+	pub fn is_bindless(&self) -> bool {
+		matches!(self, DescriptorBindingType::Texture2DRuntimeArray)
+	}
+
+	pub fn required_buffer_usage(&self) -> Option<BufferUsage> {
+		match *self {
+			DescriptorBindingType::CBuffer => Some(BufferUsage::UniformBuffer),
+			DescriptorBindingType::StructuredBuffer | DescriptorBindingType::RWStructuredBuffer => Some(BufferUsage::StorageBuffer),
+			DescriptorBindingType::Buffer => Some(BufferUsage::UniformTexelBuffer),
+			DescriptorBindingType::RWBuffer => Some(BufferUsage::StorageTexelBuffer),
+			_ => None,
+		}
+	}
+
+	pub fn required_texture_usage(&self) -> Option<TextureUsage> {
+		match *self {
+			DescriptorBindingType::Texture2D | DescriptorBindingType::Texture2DArray { .. } | DescriptorBindingType::Texture2DRuntimeArray | DescriptorBindingType::TextureCube => {
+				Some(TextureUsage::SAMPLED)
+			}
+			DescriptorBindingType::RWTexture2D => Some(TextureUsage::STORAGE),
+			_ => None,
+		}
+	}
 }
 
+pub const MAX_BINDLESS_TEXTURES: u32 = 1024;
+
 #[derive(Debug)]
 pub struct DescriptorSetInfo {
 	pub bindings: phf::Map<u32, DescriptorBindingType>,
 }
 
-use crate::types::{Vec2Serde, Vec3Serde};
+impl DescriptorSetInfo {
+	pub fn is_bindless(&self) -> bool {
+		self.bindings.values().any(|ty| ty.is_bindless())
+	}
+
+	pub fn to_bindings(&self) -> DescriptorSetBindings {
+		DescriptorSetBindings::from(self)
+	}
+
+	fn sorted_entries(&self) -> Vec<(u32, DescriptorBindingType)> {
+		let mut entries = self.bindings.entries().map(|(binding, ty)| (*binding, *ty)).collect::<Vec<_>>();
+		entries.sort_unstable_by_key(|(binding, _)| *binding);
+		entries
+	}
+}
+
+// Infos are compared by their bindings rather than by address so that identical sets declared in different places share layouts and heaps.
+impl std::hash::Hash for DescriptorSetInfo {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.sorted_entries().hash(state);
+	}
+}
+
+impl PartialEq for DescriptorSetInfo {
+	fn eq(&self, other: &Self) -> bool {
+		self.bindings.len() == other.bindings.len() && self.bindings.entries().all(|(binding, ty)| other.bindings.get(binding) == Some(ty))
+	}
+}
+
+impl Eq for DescriptorSetInfo {}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct DescriptorSetBindings(Arc<[(u32, DescriptorBindingType)]>);
+
+impl DescriptorSetBindings {
+	pub fn get(&self, binding: u32) -> Option<DescriptorBindingType> {
+		self.0.binary_search_by_key(&binding, |(i, _)| *i).ok().map(|i| self.0[i].1)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (u32, DescriptorBindingType)> + '_ {
+		self.0.iter().copied()
+	}
+
+	pub fn as_slice(&self) -> &[(u32, DescriptorBindingType)] {
+		&self.0
+	}
+
+	pub fn is_bindless(&self) -> bool {
+		self.0.iter().any(|(_, ty)| ty.is_bindless())
+	}
+}
+
+impl From<&DescriptorSetInfo> for DescriptorSetBindings {
+	fn from(info: &DescriptorSetInfo) -> Self {
+		Self(info.sorted_entries().into())
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum SpecValue {
+	U32(u32),
+	I32(i32),
+	F32(f32),
+	Bool(bool),
+}
+
+impl SpecValue {
+	pub fn to_ne_bytes(&self) -> [u8; 4] {
+		match *self {
+			SpecValue::U32(v) => v.to_ne_bytes(),
+			SpecValue::I32(v) => v.to_ne_bytes(),
+			SpecValue::F32(v) => v.to_ne_bytes(),
+			SpecValue::Bool(v) => (v as u32).to_ne_bytes(),
+		}
+	}
+}
+
+// Floats don't implement Hash or Eq, so we compare the raw bits instead so that SpecValues can be used in pipeline cache keys.
+impl std::hash::Hash for SpecValue {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		self.to_ne_bytes().hash(state);
+	}
+}
+
+impl PartialEq for SpecValue {
+	fn eq(&self, other: &Self) -> bool {
+		std::mem::discriminant(self) == std::mem::discriminant(other) && self.to_ne_bytes() == other.to_ne_bytes()
+	}
+}
+
+impl Eq for SpecValue {}
+
+// Mostly for shadow passes, slope is scaled by the polygon's depth slope and the total bias is clamped to clamp (0.0 for no clamping).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DepthBias {
+	pub constant: f32,
+	pub slope: f32,
+	pub clamp: f32,
+}
+
+impl DepthBias {
+	fn to_bits(&self) -> [u32; 3] {
+		[self.constant.to_bits(), self.slope.to_bits(), self.clamp.to_bits()]
+	}
+}
+
+impl std::hash::Hash for DepthBias {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.to_bits().hash(state);
+	}
+}
+
+impl PartialEq for DepthBias {
+	fn eq(&self, other: &Self) -> bool {
+		self.to_bits() == other.to_bits()
+	}
+}
+
+impl Eq for DepthBias {}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum BlendMode {
+	Opaque,
+	// Straight (non-premultiplied) alpha, src * a + dst * (1 - a).
+	Alpha,
+	// src + dst, for accumulating into an attachment that's loaded rather than cleared.
+	Additive,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum QueryKind {
+	Occlusion,
+	PipelineStatistics,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct QuerySlot {
+	pub index: u32,
+	pub kind: QueryKind,
+}
+
+impl QuerySlot {
+	pub const MAX_QUERIES: u32 = 64;
+
+	pub const fn occlusion(index: u32) -> Self {
+		Self { index, kind: QueryKind::Occlusion }
+	}
+
+	pub const fn pipeline_statistics(index: u32) -> Self {
+		Self {
+			index,
+			kind: QueryKind::PipelineStatistics,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryResult {
+	Occlusion {
+		samples_passed: u64,
+	},
+	PipelineStatistics {
+		vertex_shader_invocations: u64,
+		clipping_primitives: u64,
+		fragment_shader_invocations: u64,
+		compute_shader_invocations: u64,
+	},
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryStats {
+	pub device_local_used: u64,
+	pub device_local_budget: u64,
+	pub host_visible_used: u64,
+	pub host_visible_budget: u64,
+	pub allocation_count: u64,
+}
+
+// Where the CPU was blocked over the last frame that was presented.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramePacingStats {
+	pub fence_wait: Duration,
+	pub acquire_wait: Duration,
+	pub present_wait: Duration,
+	pub image_index: u32,
+	pub suboptimal: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapchainInfo {
+	pub image_count: u32,
+	pub present_mode: PresentMode,
+	pub format: SurfaceFormat,
+	pub extent: Size,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpecializationConstant {
+	pub constant_id: u32,
+	pub default: SpecValue,
+}
+
+use crate::types::{Vec2Serde, Vec3Serde, Vec4Serde};
 #[repr(C)]
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Serialize, Deserialize, VertexInput)]
 pub struct Vertex {
 	#[serde(with = "Vec3Serde")]
 	pub position: Vec3,
@@ -333,53 +913,327 @@ pub struct Vertex {
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
 
+// The first five locations match Vertex, so shaders can share everything but the bone inputs.
+// Weights are expected to add up to 1, unused influences should have a weight of 0.
+#[repr(C)]
+#[derive(Copy, Clone, Serialize, Deserialize, VertexInput)]
+pub struct SkinnedVertex {
+	#[serde(with = "Vec3Serde")]
+	pub position: Vec3,
+	#[serde(with = "Vec3Serde")]
+	pub normal: Vec3,
+	#[serde(with = "Vec2Serde")]
+	pub uv: Vec2,
+	#[serde(with = "Vec3Serde")]
+	pub tangent: Vec3,
+	#[serde(with = "Vec3Serde")]
+	pub bitangent: Vec3,
+	pub bone_indices: [u16; 4],
+	#[serde(with = "Vec4Serde")]
+	pub bone_weights: Vec4,
+}
+
+unsafe impl bytemuck::Pod for SkinnedVertex {}
+unsafe impl bytemuck::Zeroable for SkinnedVertex {}
+
+// Vertex with the optional channels tacked on the end, see MeshPackage::extended_vertices.
+#[repr(C)]
+#[derive(Copy, Clone, Serialize, Deserialize, VertexInput)]
+pub struct ExtendedVertex {
+	#[serde(with = "Vec3Serde")]
+	pub position: Vec3,
+	#[serde(with = "Vec3Serde")]
+	pub normal: Vec3,
+	#[serde(with = "Vec2Serde")]
+	pub uv: Vec2,
+	#[serde(with = "Vec3Serde")]
+	pub tangent: Vec3,
+	#[serde(with = "Vec3Serde")]
+	pub bitangent: Vec3,
+	pub color: [u8; 4],
+	#[serde(with = "Vec2Serde")]
+	pub uv1: Vec2,
+}
+
+unsafe impl bytemuck::Pod for ExtendedVertex {}
+unsafe impl bytemuck::Zeroable for ExtendedVertex {}
+
+bitflags! {
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+	pub struct VertexChannels: u8
+	{
+		const COLOR = 0x1;
+		const UV1   = 0x2;
+	}
+}
+
+#[derive(Hash, PartialEq, Eq)]
+pub enum MeshBuffer {
+	Dedicated(GpuBuffer),
+	Pooled(GeometryRange),
+}
+
+impl std::ops::Deref for MeshBuffer {
+	type Target = GpuBuffer;
+
+	fn deref(&self) -> &GpuBuffer {
+		match self {
+			Self::Dedicated(buffer) => buffer,
+			Self::Pooled(range) => &range.buffer,
+		}
+	}
+}
+
 #[derive(Hash, PartialEq, Eq)]
 pub struct Mesh {
-	pub vertex_buffer: GpuBuffer,
-	pub index_buffer: GpuBuffer,
+	pub vertex_buffer: MeshBuffer,
+	pub index_buffer: MeshBuffer,
 	pub index_count: u32,
+	pub first_index: u32,
+	pub vertex_offset: i32,
+	pub(crate) guard: DestroyGuard,
+}
+
+impl Mesh {
+	pub fn is_pooled(&self) -> bool {
+		matches!(self.vertex_buffer, MeshBuffer::Pooled(_))
+	}
 }
 
 impl UploadContext {
-	pub fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u16]) -> Mesh {
+	pub fn create_mesh(&mut self, name: &str, vertices: &[Vertex], indices: &[u16]) -> GoldfishResult<Mesh> {
+		tracy::span!();
+		self.create_mesh_from_bytes(name, bytemuck::cast_slice(vertices), indices)
+	}
+
+	pub fn create_skinned_mesh(&mut self, name: &str, vertices: &[SkinnedVertex], indices: &[u16]) -> GoldfishResult<Mesh> {
 		tracy::span!();
+		self.create_mesh_from_bytes(name, bytemuck::cast_slice(vertices), indices)
+	}
+
+	pub fn create_extended_mesh(&mut self, name: &str, vertices: &[ExtendedVertex], indices: &[u16]) -> GoldfishResult<Mesh> {
+		tracy::span!();
+		self.create_mesh_from_bytes(name, bytemuck::cast_slice(vertices), indices)
+	}
+
+	// ExtendedVertex if the package has any of the optional channels, Vertex otherwise. Skins are left out, see create_skinned_mesh.
+	pub fn create_mesh_from_package(&mut self, name: &str, package: &MeshPackage) -> GoldfishResult<Mesh> {
+		match package.extended_vertices() {
+			Some(vertices) => self.create_extended_mesh(name, &vertices, &package.indices),
+			None => self.create_mesh(name, &package.vertices, &package.indices),
+		}
+	}
+
+	fn create_mesh_from_bytes(&mut self, name: &str, vertices: &[u8], indices: &[u16]) -> GoldfishResult<Mesh> {
 		let vertex_buffer = self.create_buffer(
-			std::mem::size_of::<Vertex>() * vertices.len(),
+			&format!("{} (Vertices)", name),
+			vertices.len(),
 			MemoryLocation::GpuOnly,
 			BufferUsage::VertexBuffer,
 			None,
-			Some(bytemuck::cast_slice(vertices)),
-		);
+			Some(vertices),
+		)?;
 
 		let index_count = indices.len() as u32;
-		let index_buffer = self.create_buffer(
+		let index_buffer = match self.create_buffer(
+			&format!("{} (Indices)", name),
 			std::mem::size_of::<u16>() * indices.len(),
 			MemoryLocation::GpuOnly,
 			BufferUsage::IndexBuffer,
 			None,
 			Some(bytemuck::cast_slice(indices)),
-		);
+		) {
+			Ok(index_buffer) => index_buffer,
+			Err(err) => {
+				self.destroy_buffer(vertex_buffer);
+				return Err(err);
+			}
+		};
 
-		Mesh {
-			vertex_buffer,
-			index_buffer,
+		Ok(Mesh {
+			vertex_buffer: MeshBuffer::Dedicated(vertex_buffer),
+			index_buffer: MeshBuffer::Dedicated(index_buffer),
 			index_count,
+			first_index: 0,
+			vertex_offset: 0,
+			guard: DestroyGuard::new("Mesh", name),
+		})
+	}
+}
+
+impl GraphicsDevice {
+	pub fn destroy_mesh(&mut self, mut mesh: Mesh) {
+		tracy::span!();
+		mesh.guard.mark_destroyed();
+		for buffer in [mesh.vertex_buffer, mesh.index_buffer] {
+			match buffer {
+				MeshBuffer::Dedicated(buffer) => self.destroy_buffer(buffer),
+				MeshBuffer::Pooled(_) => panic!("Cannot destroy a pooled mesh, free it through its geometry pool!"),
+			}
+		}
+	}
+}
+
+// Fallbacks for descriptor bindings that a pass doesn't provide.
+pub struct DefaultResources {
+	pub white_texture: Texture,
+	pub black_texture: Texture,
+	pub normal_texture: Texture,
+	pub white_cubemap: Texture,
+	pub uniform_buffer: GpuBuffer,
+	// Unlit magenta over the whole screen, what the render graph draws with when a pipeline can't be created. See ReloadableShader.
+	pub error_vs: Shader,
+	pub error_ps: Shader,
+}
+
+impl DefaultResources {
+	pub const UNIFORM_BUFFER_SIZE: usize = 16;
+
+	pub fn texture_for(&self, binding_type: DescriptorBindingType) -> Option<&Texture> {
+		match binding_type {
+			DescriptorBindingType::Texture2D | DescriptorBindingType::Texture2DArray { .. } | DescriptorBindingType::SamplerState => Some(&self.white_texture),
+			DescriptorBindingType::TextureCube => Some(&self.white_cubemap),
+			_ => None,
+		}
+	}
+
+	pub fn buffer_for(&self, binding_type: DescriptorBindingType) -> Option<&GpuBuffer> {
+		match binding_type {
+			DescriptorBindingType::CBuffer | DescriptorBindingType::StructuredBuffer | DescriptorBindingType::RWStructuredBuffer => Some(&self.uniform_buffer),
+			_ => None,
+		}
+	}
+}
+
+impl UploadContext {
+	pub fn create_default_resources(&mut self) -> GoldfishResult<DefaultResources> {
+		tracy::span!();
+		let (error_vs, error_ps) = self.device.create_error_shaders()?;
+
+		let mut textures = Vec::new();
+		for (name, format, pixels) in [
+			("Default White", TextureFormat::RGBA8UNorm, &[255u8, 255, 255, 255][..]),
+			("Default Black", TextureFormat::RGBA8UNorm, &[0, 0, 0, 255]),
+			("Default Normal", TextureFormat::RGBA8UNorm, &[128, 128, 255, 255]),
+			("Default White Cubemap", TextureFormat::CubemapRGBA8UNorm, &[255; 6 * 4]),
+		] {
+			match self.create_texture(name, 1, 1, format, TextureUsage::SAMPLED, Some(pixels)) {
+				Ok(texture) => textures.push(texture),
+				Err(err) => {
+					for texture in textures {
+						self.destroy_texture(texture);
+					}
+					self.device.destroy_shader(error_vs);
+					self.device.destroy_shader(error_ps);
+					return Err(err);
+				}
+			}
+		}
+
+		let uniform_buffer = match self.create_buffer(
+			"Default Uniform",
+			DefaultResources::UNIFORM_BUFFER_SIZE,
+			MemoryLocation::GpuOnly,
+			BufferUsage::UniformBuffer | BufferUsage::StorageBuffer,
+			None,
+			Some(&[0u8; DefaultResources::UNIFORM_BUFFER_SIZE]),
+		) {
+			Ok(uniform_buffer) => uniform_buffer,
+			Err(err) => {
+				for texture in textures {
+					self.destroy_texture(texture);
+				}
+				self.device.destroy_shader(error_vs);
+				self.device.destroy_shader(error_ps);
+				return Err(err);
+			}
+		};
+
+		let mut textures = textures.into_iter();
+		Ok(DefaultResources {
+			white_texture: textures.next().unwrap(),
+			black_texture: textures.next().unwrap(),
+			normal_texture: textures.next().unwrap(),
+			white_cubemap: textures.next().unwrap(),
+			uniform_buffer,
+			error_vs,
+			error_ps,
+		})
+	}
+}
+
+#[cfg(test)]
+impl DefaultResources {
+	pub(crate) fn null() -> Self {
+		Self {
+			white_texture: Texture::null(TextureFormat::RGBA8UNorm),
+			black_texture: Texture::null(TextureFormat::RGBA8UNorm),
+			normal_texture: Texture::null(TextureFormat::RGBA8UNorm),
+			white_cubemap: Texture::null(TextureFormat::CubemapRGBA8UNorm),
+			uniform_buffer: GpuBuffer::null(Self::UNIFORM_BUFFER_SIZE, BufferUsage::UniformBuffer | BufferUsage::StorageBuffer),
+			error_vs: Shader::borrowed(ash::vk::ShaderModule::null()),
+			error_ps: Shader::borrowed(ash::vk::ShaderModule::null()),
 		}
 	}
 }
 
 impl GraphicsDevice {
-	pub fn destroy_mesh(&mut self, mesh: Mesh) {
+	pub fn destroy_default_resources(&mut self, default_resources: DefaultResources) {
 		tracy::span!();
-		self.destroy_buffer(mesh.vertex_buffer);
-		self.destroy_buffer(mesh.index_buffer);
+		self.destroy_texture(default_resources.white_texture);
+		self.destroy_texture(default_resources.black_texture);
+		self.destroy_texture(default_resources.normal_texture);
+		self.destroy_texture(default_resources.white_cubemap);
+		self.destroy_buffer(default_resources.uniform_buffer);
+		self.destroy_shader(default_resources.error_vs);
+		self.destroy_shader(default_resources.error_ps);
 	}
 }
 
 impl GraphicsContext {
 	pub fn draw_mesh(&self, mesh: &Mesh) {
 		self.bind_vertex_buffer(&mesh.vertex_buffer);
-		self.bind_index_buffer(&mesh.index_buffer);
-		self.draw_indexed(mesh.index_count);
+		self.bind_index_buffer(&mesh.index_buffer, 0, IndexType::U16);
+		self.draw_indexed(mesh.index_count, 1, mesh.first_index, mesh.vertex_offset, 0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compressed_formats_round_up_to_whole_blocks() {
+		assert_eq!(TextureFormat::BC1UNorm.block_bytes(), Some(8));
+		assert_eq!(TextureFormat::BC7SRGB.block_bytes(), Some(16));
+		assert_eq!(TextureFormat::RGBA8UNorm.block_bytes(), None);
+		assert!(TextureFormat::BC1SRGB.texel_bytes().is_none());
+
+		assert_eq!(TextureFormat::BC1UNorm.compressed_mip_size(4, 4, 0), Some((8, 8)));
+		assert_eq!(TextureFormat::BC1UNorm.compressed_mip_size(5, 5, 0), Some((16, 32)));
+		assert_eq!(TextureFormat::BC3UNorm.compressed_mip_size(256, 128, 0), Some((1024, 32 * 1024)));
+		assert_eq!(TextureFormat::BC5UNorm.compressed_mip_size(1, 1, 0), Some((16, 16)));
+		assert_eq!(TextureFormat::RGBA8UNorm.compressed_mip_size(4, 4, 0), None);
+	}
+
+	#[test]
+	fn compressed_mips_never_go_below_one_block() {
+		let mip_size = |mip_level| TextureFormat::BC7UNorm.compressed_mip_size(256, 128, mip_level).unwrap();
+
+		assert_eq!(mip_size(1), (512, 512 * 16));
+		assert_eq!(mip_size(3), (128, 512));
+		assert_eq!(mip_size(5), (32, 32));
+		assert_eq!(mip_size(7), (16, 16));
+		assert_eq!(mip_size(8), (16, 16));
+		assert_eq!(mip_size(9), (16, 16));
+	}
+
+	#[test]
+	fn buffer_row_length_is_whole_blocks_of_texels() {
+		assert_eq!(TextureFormat::BC1UNorm.buffer_row_length(4, 0), 4);
+		assert_eq!(TextureFormat::BC1UNorm.buffer_row_length(5, 0), 8);
+		assert_eq!(TextureFormat::BC7UNorm.buffer_row_length(1, 0), 4);
+		assert_eq!(TextureFormat::BC3SRGB.buffer_row_length(100, 2), 28);
+		assert_eq!(TextureFormat::RGBA8UNorm.buffer_row_length(5, 0), 0);
 	}
 }
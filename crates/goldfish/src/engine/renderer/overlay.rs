@@ -0,0 +1,220 @@
+use super::overlay_font::{FIRST_GLYPH, FONT_BITMAP, GLYPH_COUNT, GLYPH_HEIGHT, GLYPH_WIDTH};
+use super::*;
+use crate::camera::pixel_space_projection;
+use crate::types::PhysicalSize;
+use glam::Mat4;
+
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = (GLYPH_COUNT as u32 + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+const ATLAS_WIDTH: u32 = ATLAS_COLUMNS * GLYPH_WIDTH;
+const ATLAS_HEIGHT: u32 = ATLAS_ROWS * GLYPH_HEIGHT;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, VertexInput)]
+pub struct OverlayVertex {
+	// Already in clip space, the overlay converts from pixels when the text is added.
+	pub position: Vec2,
+	pub uv: Vec2,
+	pub color: [u8; 4],
+}
+
+unsafe impl bytemuck::Pod for OverlayVertex {}
+unsafe impl bytemuck::Zeroable for OverlayVertex {}
+
+// Immediate mode text for debug stats, call text as many times as needed each frame and then record it into the output render pass.
+pub struct DebugOverlay {
+	vs: Shader,
+	ps: Shader,
+	descriptor_layout: &'static DescriptorSetInfo,
+	font: Texture,
+	// One per frame in flight, since the CPU writes into these while the previous frames might still be reading from theirs.
+	// This assumes record is called exactly once for every frame that actually gets submitted.
+	vertex_buffers: Vec<GpuBuffer>,
+	frame_slot: usize,
+	vertices: Vec<OverlayVertex>,
+	projection: Mat4,
+	scale_factor: f32,
+}
+
+impl DebugOverlay {
+	pub const MAX_GLYPHS: usize = 4096;
+	const VERTICES_PER_GLYPH: usize = 6;
+
+	// The shaders are compiled by the game (the engine has no shader pipeline of its own) and are owned by the overlay from here on out.
+	// They're expected to take the font texture and its sampler at bindings 0 and 1 of set 0, and OverlayVertex as their vertex input.
+	pub fn new(upload_context: &mut UploadContext, vs: Shader, ps: Shader, descriptor_layout: &'static DescriptorSetInfo) -> GoldfishResult<Self> {
+		tracy::span!();
+		let font = upload_context.create_texture(
+			"Debug Overlay Font",
+			ATLAS_WIDTH,
+			ATLAS_HEIGHT,
+			TextureFormat::R8UNorm,
+			TextureUsage::SAMPLED,
+			Some(&Self::bake_atlas()),
+		)?;
+
+		let vertex_buffers = (0..upload_context.device.frames_in_flight)
+			.map(|_| {
+				upload_context.create_buffer(
+					"Debug Overlay Vertices",
+					std::mem::size_of::<OverlayVertex>() * Self::VERTICES_PER_GLYPH * Self::MAX_GLYPHS,
+					MemoryLocation::CpuToGpu,
+					BufferUsage::VertexBuffer,
+					None,
+					None,
+				)
+			})
+			.collect::<GoldfishResult<Vec<_>>>()?;
+
+		Ok(Self {
+			vs,
+			ps,
+			descriptor_layout,
+			font,
+			vertex_buffers,
+			frame_slot: 0,
+			vertices: Vec::new(),
+			projection: pixel_space_projection(1, 1),
+			scale_factor: 1.0,
+		})
+	}
+
+	fn bake_atlas() -> Vec<u8> {
+		let mut pixels = vec![0u8; (ATLAS_WIDTH * ATLAS_HEIGHT) as usize];
+
+		for (glyph, rows) in FONT_BITMAP.iter().enumerate() {
+			let x0 = (glyph as u32 % ATLAS_COLUMNS) * GLYPH_WIDTH;
+			let y0 = (glyph as u32 / ATLAS_COLUMNS) * GLYPH_HEIGHT;
+
+			for (y, row) in rows.iter().enumerate() {
+				for x in 0..GLYPH_WIDTH {
+					if row & (0x80 >> x) != 0 {
+						pixels[((y0 + y as u32) * ATLAS_WIDTH + x0 + x) as usize] = 255;
+					}
+				}
+			}
+		}
+
+		pixels
+	}
+
+	// Throws away whatever was queued up last frame, text positions are relative to the top left of a screen this size. Pass the window's
+	// scale factor so the text comes out the same size on every monitor.
+	pub fn begin(&mut self, screen_size: PhysicalSize, scale_factor: f64) {
+		self.vertices.clear();
+		self.projection = pixel_space_projection(screen_size.width, screen_size.height);
+		self.scale_factor = scale_factor as f32;
+	}
+
+	// In logical pixels, like everything else the overlay takes.
+	pub fn line_height(&self) -> u32 {
+		GLYPH_HEIGHT
+	}
+
+	// x and y are in logical pixels from the top left of the screen, newlines start over at x one line down.
+	// Anything past MAX_GLYPHS for the frame is silently dropped.
+	pub fn text(&mut self, x: i32, y: i32, color: Color, text: &str) {
+		let color = [color.r, color.g, color.b, color.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+		let (mut cursor_x, mut cursor_y) = (x, y);
+		for c in text.chars() {
+			if c == '\n' {
+				cursor_x = x;
+				cursor_y += GLYPH_HEIGHT as i32;
+				continue;
+			}
+
+			if self.vertices.len() + Self::VERTICES_PER_GLYPH > Self::VERTICES_PER_GLYPH * Self::MAX_GLYPHS {
+				return;
+			}
+
+			let glyph = match c {
+				' ' => None,
+				c if c.is_ascii() && (c as u8) >= FIRST_GLYPH && ((c as u8 - FIRST_GLYPH) as usize) < GLYPH_COUNT => Some(c as u8 - FIRST_GLYPH),
+				_ => Some(b'?' - FIRST_GLYPH),
+			};
+
+			if let Some(glyph) = glyph {
+				self.push_glyph(cursor_x, cursor_y, glyph as u32, color);
+			}
+
+			cursor_x += GLYPH_WIDTH as i32;
+		}
+	}
+
+	fn push_glyph(&mut self, x: i32, y: i32, glyph: u32, color: [u8; 4]) {
+		let to_clip = |px: i32, py: i32| self.projection.project_point3(Vec3::new(px as f32 * self.scale_factor, py as f32 * self.scale_factor, 0.0)).truncate();
+
+		let u0 = ((glyph % ATLAS_COLUMNS) * GLYPH_WIDTH) as f32 / ATLAS_WIDTH as f32;
+		let v0 = ((glyph / ATLAS_COLUMNS) * GLYPH_HEIGHT) as f32 / ATLAS_HEIGHT as f32;
+		let u1 = u0 + GLYPH_WIDTH as f32 / ATLAS_WIDTH as f32;
+		let v1 = v0 + GLYPH_HEIGHT as f32 / ATLAS_HEIGHT as f32;
+
+		let (x1, y1) = (x + GLYPH_WIDTH as i32, y + GLYPH_HEIGHT as i32);
+		let corner = |px, py, u, v| OverlayVertex {
+			position: to_clip(px, py),
+			uv: Vec2::new(u, v),
+			color,
+		};
+
+		let top_left = corner(x, y, u0, v0);
+		let top_right = corner(x1, y, u1, v0);
+		let bottom_left = corner(x, y1, u0, v1);
+		let bottom_right = corner(x1, y1, u1, v1);
+
+		self.vertices.extend_from_slice(&[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+	}
+
+	// Must be recorded while the output render pass is begun, render_pass is the pass builder's output render pass handle.
+	pub fn record<'a>(&'a mut self, pass: &mut PassBuilder<'a, '_>, render_pass: GraphRenderPassHandle) {
+		let frame_slot = self.frame_slot;
+		self.frame_slot = (self.frame_slot + 1) % self.vertex_buffers.len();
+
+		if self.vertices.is_empty() {
+			return;
+		}
+
+		let bytes: &[u8] = bytemuck::cast_slice(&self.vertices);
+		self.vertex_buffers[frame_slot].allocation.mapped_slice_mut().expect("Failed to map overlay vertex buffer!")[..bytes.len()].copy_from_slice(bytes);
+
+		let this: &'a Self = self;
+		let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+			name: "Debug Overlay Pipeline",
+			vs: &this.vs,
+			ps: Some(&this.ps),
+			gs: None,
+			descriptor_layouts: &[this.descriptor_layout],
+			render_pass,
+			depth_compare_op: None,
+			depth_write: false,
+			depth_bias: None,
+			blend_mode: BlendMode::Alpha,
+			face_cull: FaceCullMode::NoCull,
+			push_constant_bytes: 0,
+			vertex_input_info: OverlayVertex::VERTEX_INFO,
+			polygon_mode: PolygonMode::Fill,
+			specialization: &[],
+		});
+
+		let descriptor = pass.add_graphics_descriptor_set(DescriptorDesc {
+			name: "Debug Overlay Descriptor",
+			descriptor_layout: this.descriptor_layout,
+			bindings: &mut [(0, DescriptorBindingDesc::ImportedTexture(&this.font)), (1, DescriptorBindingDesc::ImportedTexture(&this.font))],
+		});
+
+		pass.cmd_bind_raster_pipeline(pipeline);
+		pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+		pass.cmd_bind_vertex_buffers(&[(BufferBindingDesc::ImportedBuffer(&this.vertex_buffers[frame_slot]), 0)]);
+		pass.cmd_draw(this.vertices.len() as u32, 1, 0, 0);
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		for buffer in self.vertex_buffers {
+			graphics_device.destroy_buffer(buffer);
+		}
+
+		graphics_device.destroy_texture(self.font);
+		graphics_device.destroy_shader(self.vs);
+		graphics_device.destroy_shader(self.ps);
+	}
+}
@@ -0,0 +1,310 @@
+use super::*;
+use glam::{Mat4, UVec2};
+use log::warn;
+
+// Everything emitted in a frame goes into one buffer, emit calls past this are dropped until the next frame.
+pub const MAX_EMITTERS_PER_FRAME: usize = 64;
+// The counter buffer is an emit cursor followed by (after some padding) a vk::DrawIndirectCommand.
+const COUNTERS_SIZE: usize = 8 * std::mem::size_of::<u32>();
+const DRAW_ARGS_OFFSET: u64 = 4 * std::mem::size_of::<u32>() as u64;
+const VERTICES_PER_PARTICLE: u32 = 6;
+// Has to match sizeof(Particle) in the game's particles.hlsli, the CPU never touches the particles after zeroing them.
+const PARTICLE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EmitParams {
+	pub position: Vec3,
+	pub velocity: Vec3,
+	// Each particle's velocity gets pushed up to this far off in every direction.
+	pub spread: f32,
+	pub count: u32,
+	// In seconds, each particle lives somewhere between half of this and all of it.
+	pub lifetime: f32,
+	pub size: f32,
+	pub color: Color,
+}
+
+// Compiled by the game and owned by the particle system, see the game's particles.hlsli for the layouts and bindings.
+pub struct ParticleShaders {
+	pub cs_emit: Shader,
+	pub emit_layout: &'static DescriptorSetInfo,
+	pub emit_local_size: [u32; 3],
+	pub cs_update: Shader,
+	pub update_layout: &'static DescriptorSetInfo,
+	pub update_local_size: [u32; 3],
+	pub vs: Shader,
+	pub ps: Shader,
+	pub draw_layout: &'static DescriptorSetInfo,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ParticleFrame {
+	view: [f32; 16],
+	proj: [f32; 16],
+	gravity_dt: [f32; 4],
+	// Emitter count, particles to emit, max particles and a random seed.
+	counts: [u32; 4],
+}
+
+unsafe impl bytemuck::Pod for ParticleFrame {}
+unsafe impl bytemuck::Zeroable for ParticleFrame {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GpuEmitter {
+	position_lifetime: [f32; 4],
+	velocity_spread: [f32; 4],
+	color: [f32; 4],
+	size: f32,
+	count: u32,
+	first: u32,
+	padding: u32,
+}
+
+unsafe impl bytemuck::Pod for GpuEmitter {}
+unsafe impl bytemuck::Zeroable for GpuEmitter {}
+
+// GPU simulated particles: compute passes emit into a fixed size buffer and age it into an indirect draw of camera facing quads.
+pub struct ParticleSystem {
+	shaders: ParticleShaders,
+	max_particles: u32,
+	particles: GpuBuffer,
+	// Indices of the particles that survived this frame's update, which the draw's instances read.
+	draw_list: GpuBuffer,
+	counters: GpuBuffer,
+	frame: PerFrameBuffer,
+	emitters: PerFrameBuffer,
+	pending: Vec<EmitParams>,
+	emit_count: u32,
+	seed: u32,
+	pub gravity: Vec3,
+}
+
+impl ParticleSystem {
+	pub fn new(upload_context: &mut UploadContext, max_particles: u32, shaders: ParticleShaders) -> GoldfishResult<Self> {
+		tracy::span!();
+		assert!(max_particles > 0, "Cannot create a particle system without any particles!");
+
+		let particles = upload_context.create_buffer(
+			"Particles",
+			PARTICLE_SIZE * max_particles as usize,
+			MemoryLocation::GpuOnly,
+			BufferUsage::StorageBuffer,
+			None,
+			Some(&vec![0; PARTICLE_SIZE * max_particles as usize]),
+		)?;
+
+		let draw_list = upload_context.create_buffer(
+			"Particle Draw List",
+			std::mem::size_of::<u32>() * max_particles as usize,
+			MemoryLocation::GpuOnly,
+			BufferUsage::StorageBuffer,
+			None,
+			None,
+		)?;
+
+		let counters: [u32; 8] = [0, 0, 0, 0, VERTICES_PER_PARTICLE, 0, 0, 0];
+		let counters = upload_context.create_buffer(
+			"Particle Counters",
+			COUNTERS_SIZE,
+			MemoryLocation::GpuOnly,
+			BufferUsage::StorageBuffer | BufferUsage::IndirectBuffer,
+			None,
+			Some(bytemuck::cast_slice(&counters)),
+		)?;
+
+		let frame = PerFrameBuffer::new(upload_context, "Particle Frame", std::mem::size_of::<ParticleFrame>(), BufferUsage::UniformBuffer)?;
+		let emitters = PerFrameBuffer::new(
+			upload_context,
+			"Particle Emitters",
+			std::mem::size_of::<GpuEmitter>() * MAX_EMITTERS_PER_FRAME,
+			BufferUsage::StorageBuffer,
+		)?;
+
+		Ok(Self {
+			shaders,
+			max_particles,
+			particles,
+			draw_list,
+			counters,
+			frame,
+			emitters,
+			pending: Vec::new(),
+			emit_count: 0,
+			seed: 0,
+			gravity: Vec3::new(0.0, -9.8, 0.0),
+		})
+	}
+
+	// Queued until the next prepare, the particles show up in the frame that's recorded after it.
+	pub fn emit(&mut self, params: EmitParams) {
+		if params.count == 0 {
+			return;
+		}
+
+		if self.pending.len() >= MAX_EMITTERS_PER_FRAME {
+			warn!("Dropped a particle emit, only {} can be queued per frame!", MAX_EMITTERS_PER_FRAME);
+			return;
+		}
+
+		self.pending.push(params);
+	}
+
+	// Writes this frame's emitters and camera, call it once a frame before recording with the same GraphicsContext::frame_slot.
+	pub fn prepare(&mut self, frame_slot: usize, dt: f32, view: Mat4, proj: Mat4) {
+		let mut first = 0;
+		let emitters = self
+			.pending
+			.drain(..)
+			.map(|params| {
+				// Emitting more than the buffer holds in one frame would just overwrite the same particles again.
+				let count = params.count.min(self.max_particles - first);
+				let emitter = GpuEmitter {
+					position_lifetime: params.position.extend(params.lifetime).to_array(),
+					velocity_spread: params.velocity.extend(params.spread).to_array(),
+					color: [params.color.r, params.color.g, params.color.b, params.color.a],
+					size: params.size,
+					count,
+					first,
+					padding: 0,
+				};
+				first += count;
+				emitter
+			})
+			.collect::<Vec<_>>();
+
+		self.emit_count = first;
+		self.seed = self.seed.wrapping_add(0x9e37_79b9);
+
+		self.emitters.write(frame_slot, bytemuck::cast_slice(&emitters));
+		self.frame.write(
+			frame_slot,
+			bytemuck::bytes_of(&ParticleFrame {
+				view: view.to_cols_array(),
+				proj: proj.to_cols_array(),
+				gravity_dt: self.gravity.extend(dt).to_array(),
+				counts: [emitters.len() as u32, self.emit_count, self.max_particles, self.seed],
+			}),
+		);
+	}
+
+	// Adds the emit and update passes. They only run if something that ends up in the output reads the particles, which record_draw does.
+	pub fn record_simulation<'a>(&'a self, render_graph: &mut RenderGraph<'a>) {
+		{
+			let mut emit_pass = render_graph.add_pass("particle emit");
+
+			let descriptor = emit_pass.add_compute_descriptor_set(DescriptorDesc {
+				name: "Particle Emit Descriptor",
+				descriptor_layout: self.shaders.emit_layout,
+				bindings: &mut [
+					(0, DescriptorBindingDesc::PerFrameBuffer(&self.frame)),
+					(1, DescriptorBindingDesc::PerFrameBuffer(&self.emitters)),
+					(2, DescriptorBindingDesc::ImportedBuffer(&self.particles)),
+					(3, DescriptorBindingDesc::ImportedBuffer(&self.counters)),
+				],
+			});
+
+			let pipeline = emit_pass.add_compute_pipeline(ComputePipelineDesc {
+				name: "Particle Emit Pipeline",
+				cs: &self.shaders.cs_emit,
+				descriptor_layouts: &[self.shaders.emit_layout],
+				specialization: &[],
+				local_size: self.shaders.emit_local_size,
+			});
+
+			emit_pass.write_imported_buffer(&self.particles);
+			emit_pass.write_imported_buffer(&self.counters);
+
+			// Always at least one group, the first thread resets the draw's instance count even when nothing is emitted.
+			emit_pass.cmd_bind_compute_pipeline(pipeline);
+			emit_pass.cmd_bind_compute_descriptor(descriptor, 0, pipeline);
+			emit_pass.cmd_dispatch_for_size_auto(UVec2::new(self.emit_count.max(1), 1));
+		}
+
+		let mut update_pass = render_graph.add_pass("particle update");
+
+		let descriptor = update_pass.add_compute_descriptor_set(DescriptorDesc {
+			name: "Particle Update Descriptor",
+			descriptor_layout: self.shaders.update_layout,
+			bindings: &mut [
+				(0, DescriptorBindingDesc::PerFrameBuffer(&self.frame)),
+				(1, DescriptorBindingDesc::ImportedBuffer(&self.particles)),
+				(2, DescriptorBindingDesc::ImportedBuffer(&self.draw_list)),
+				(3, DescriptorBindingDesc::ImportedBuffer(&self.counters)),
+			],
+		});
+
+		let pipeline = update_pass.add_compute_pipeline(ComputePipelineDesc {
+			name: "Particle Update Pipeline",
+			cs: &self.shaders.cs_update,
+			descriptor_layouts: &[self.shaders.update_layout],
+			specialization: &[],
+			local_size: self.shaders.update_local_size,
+		});
+
+		update_pass.write_imported_buffer(&self.particles);
+		update_pass.write_imported_buffer(&self.draw_list);
+		update_pass.write_imported_buffer(&self.counters);
+
+		update_pass.cmd_bind_compute_pipeline(pipeline);
+		update_pass.cmd_bind_compute_descriptor(descriptor, 0, pipeline);
+		update_pass.cmd_dispatch_for_size_auto(UVec2::new(self.max_particles, 1));
+	}
+
+	// Must be recorded inside render_pass after record_simulation. Alpha blended and depth tested against reversed depth, without writing it.
+	pub fn record_draw<'a>(&'a self, pass: &mut PassBuilder<'a, '_>, render_pass: GraphRenderPassHandle) {
+		let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+			name: "Particle Pipeline",
+			vs: &self.shaders.vs,
+			ps: Some(&self.shaders.ps),
+			gs: None,
+			descriptor_layouts: &[self.shaders.draw_layout],
+			render_pass,
+			depth_compare_op: Some(DepthCompareOp::Greater),
+			depth_write: false,
+			depth_bias: None,
+			blend_mode: BlendMode::Alpha,
+			face_cull: FaceCullMode::NoCull,
+			push_constant_bytes: 0,
+			vertex_input_info: EMPTY_VERTEX_INFO,
+			polygon_mode: PolygonMode::Fill,
+			specialization: &[],
+		});
+
+		let descriptor = pass.add_graphics_descriptor_set(DescriptorDesc {
+			name: "Particle Descriptor",
+			descriptor_layout: self.shaders.draw_layout,
+			bindings: &mut [
+				(0, DescriptorBindingDesc::PerFrameBuffer(&self.frame)),
+				(1, DescriptorBindingDesc::ImportedBuffer(&self.particles)),
+				(2, DescriptorBindingDesc::ImportedBuffer(&self.draw_list)),
+			],
+		});
+
+		pass.cmd_bind_raster_pipeline(pipeline);
+		pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+		pass.cmd_draw_indirect(
+			BufferBindingDesc::ImportedBuffer(&self.counters),
+			DRAW_ARGS_OFFSET,
+			1,
+			std::mem::size_of::<ash::vk::DrawIndirectCommand>() as u32,
+		);
+	}
+
+	pub fn max_particles(&self) -> u32 {
+		self.max_particles
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		graphics_device.destroy_buffer(self.particles);
+		graphics_device.destroy_buffer(self.draw_list);
+		graphics_device.destroy_buffer(self.counters);
+		self.frame.destroy(graphics_device);
+		self.emitters.destroy(graphics_device);
+		graphics_device.destroy_shader(self.shaders.cs_emit);
+		graphics_device.destroy_shader(self.shaders.cs_update);
+		graphics_device.destroy_shader(self.shaders.vs);
+		graphics_device.destroy_shader(self.shaders.ps);
+	}
+}
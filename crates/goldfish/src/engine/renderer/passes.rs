@@ -0,0 +1,182 @@
+use super::*;
+
+// Passes that nearly every frame ends up needing. The shaders are compiled by the game like the debug overlay's, and are owned by the
+// passes from here on out.
+
+pub struct DrawItem<'a> {
+	pub mesh: &'a Mesh,
+	// Bound as the model constant buffer, next to the camera.
+	pub model: &'a PerFrameBuffer,
+	// Pushed at offset 0 right before the draw.
+	pub push_constants: Option<&'a [u8]>,
+}
+
+pub struct DepthPrepassParams<'a, 'b> {
+	pub camera_buffer: &'a PerFrameBuffer,
+	pub draws: &'b [DrawItem<'a>],
+	pub size: AttachmentSize,
+	// Begun before the first draw and ended after the last one, in reverse order.
+	pub queries: &'b [QuerySlot],
+}
+
+pub struct DepthPrepass {
+	vs: Shader,
+	descriptor_layout: &'static DescriptorSetInfo,
+	vertex_input_info: VertexInputInfo,
+}
+
+impl DepthPrepass {
+	// The vertex shader is expected to take the camera and model constant buffers at bindings 0 and 1 of set 0, and vertices laid out like
+	// vertex_input_info.
+	pub fn new(vs: Shader, descriptor_layout: &'static DescriptorSetInfo, vertex_input_info: VertexInputInfo) -> Self {
+		Self {
+			vs,
+			descriptor_layout,
+			vertex_input_info,
+		}
+	}
+
+	// Adds a pass that draws every item into a new depth attachment, cleared to 0 since depth is reversed like the rest of the engine uses.
+	// Whatever reads the attachment afterwards gets ordered after the prepass.
+	pub fn record<'a>(&'a self, render_graph: &mut RenderGraph<'a>, params: &DepthPrepassParams<'a, '_>) -> GraphAttachmentHandle {
+		let mut pass = render_graph.add_pass("depth prepass");
+
+		let mut depth = pass.add_attachment(AttachmentDesc {
+			name: "Prepass Depth",
+			format: TextureFormat::Depth,
+			size: params.size,
+			load_op: LoadOp::Clear,
+			store_op: StoreOp::Store,
+			usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT,
+		});
+
+		let render_pass = pass.add_render_pass(RenderPassDesc {
+			name: "Depth Prepass Render Pass",
+			color_attachments: &mut [],
+			depth_attachment: Some(&mut depth),
+		});
+
+		let push_constant_bytes = params.draws.iter().filter_map(|draw| draw.push_constants).map(|constants| constants.len()).max().unwrap_or(0);
+		let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+			name: "Depth Prepass Pipeline",
+			vs: &self.vs,
+			ps: None,
+			gs: None,
+			descriptor_layouts: &[self.descriptor_layout],
+			render_pass,
+			depth_compare_op: Some(DepthCompareOp::Greater),
+			depth_write: true,
+			depth_bias: None,
+			blend_mode: BlendMode::Opaque,
+			face_cull: FaceCullMode::Back,
+			push_constant_bytes,
+			vertex_input_info: self.vertex_input_info,
+			polygon_mode: PolygonMode::Fill,
+			specialization: &[],
+		});
+
+		pass.cmd_begin_render_pass(render_pass, &[ClearValue::DepthStencil { depth: 0.0, stencil: 0 }]);
+		pass.cmd_bind_raster_pipeline(pipeline);
+
+		for &slot in params.queries {
+			pass.cmd_begin_query(slot);
+		}
+
+		for draw in params.draws {
+			let descriptor = pass.add_graphics_descriptor_set(DescriptorDesc {
+				name: "Depth Prepass Descriptor",
+				descriptor_layout: self.descriptor_layout,
+				bindings: &mut [(0, DescriptorBindingDesc::PerFrameBuffer(params.camera_buffer)), (1, DescriptorBindingDesc::PerFrameBuffer(draw.model))],
+			});
+
+			pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+			if let Some(constants) = draw.push_constants {
+				pass.cmd_push_constants(pipeline, 0, constants);
+			}
+			pass.cmd_draw_mesh(draw.mesh);
+		}
+
+		for &slot in params.queries.iter().rev() {
+			pass.cmd_end_query(slot);
+		}
+
+		pass.cmd_end_render_pass();
+		// The GPU can get going on the prepass while the rest of the frame is recorded.
+		pass.submit_hint();
+
+		depth.read()
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		graphics_device.destroy_shader(self.vs);
+	}
+}
+
+pub enum OutputOrAttachment<'c> {
+	Output,
+	Attachment(&'c mut MutableGraphAttachmentHandle),
+}
+
+pub struct FullscreenBlit {
+	vs: Shader,
+	ps: Shader,
+	descriptor_layout: &'static DescriptorSetInfo,
+}
+
+impl FullscreenBlit {
+	// The vertex shader is expected to draw a fullscreen triangle out of 3 vertices without any vertex input. The pixel shader takes the
+	// source texture and its sampler at bindings 0 and 1 of set 0, and a bool specialization constant with id 0 that's true for linear filtering.
+	pub fn new(vs: Shader, ps: Shader, descriptor_layout: &'static DescriptorSetInfo) -> Self {
+		Self { vs, ps, descriptor_layout }
+	}
+
+	// Begins dest's render pass and stretches source over all of it. The render pass is left begun so more can be drawn on top, end it with
+	// cmd_end_render_pass. For the output that's the pass' output render pass, so everything else drawn to the output has to be in this pass.
+	pub fn record<'a>(&'a self, pass: &mut PassBuilder<'a, '_>, source: GraphAttachmentHandle, dest: OutputOrAttachment, filter: Filter) -> GraphRenderPassHandle {
+		let render_pass = match dest {
+			OutputOrAttachment::Output => pass.add_output_render_pass(),
+			OutputOrAttachment::Attachment(attachment) => pass.add_render_pass(RenderPassDesc {
+				name: "Fullscreen Blit Render Pass",
+				color_attachments: &mut [attachment],
+				depth_attachment: None,
+			}),
+		};
+
+		let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+			name: "Fullscreen Blit Pipeline",
+			vs: &self.vs,
+			ps: Some(&self.ps),
+			gs: None,
+			descriptor_layouts: &[self.descriptor_layout],
+			render_pass,
+			depth_compare_op: None,
+			depth_write: false,
+			depth_bias: None,
+			blend_mode: BlendMode::Opaque,
+			face_cull: FaceCullMode::Front,
+			push_constant_bytes: 0,
+			vertex_input_info: EMPTY_VERTEX_INFO,
+			polygon_mode: PolygonMode::Fill,
+			specialization: &[(0, SpecValue::Bool(filter == Filter::Linear))],
+		});
+
+		let descriptor = pass.add_graphics_descriptor_set(DescriptorDesc {
+			name: "Fullscreen Blit Descriptor",
+			descriptor_layout: self.descriptor_layout,
+			bindings: &mut [(0, DescriptorBindingDesc::Attachment(source)), (1, DescriptorBindingDesc::Attachment(source))],
+		});
+
+		// The triangle covers every pixel, the clear value is only there because the render pass expects one.
+		pass.cmd_begin_render_pass(render_pass, &[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+		pass.cmd_bind_raster_pipeline(pipeline);
+		pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+		pass.cmd_draw(3, 1, 0, 0);
+
+		render_pass
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		graphics_device.destroy_shader(self.vs);
+		graphics_device.destroy_shader(self.ps);
+	}
+}
@@ -0,0 +1,120 @@
+use super::*;
+use std::hash::{Hash, Hasher};
+
+// A buffer that gets rewritten every frame, with one copy per frame in flight so the CPU never writes into a copy that a previous
+// frame's commands might still be reading. Bind it with DescriptorBindingDesc::PerFrameBuffer and the render graph picks the copy for
+// the frame being recorded, write should be given the same GraphicsContext::frame_slot.
+// The copies are created with MemoryLocationPreference::Auto. Without resizable BAR they're GpuOnly, and everything is written into a
+// CpuToGpu staging buffer instead that the render graph copies over at the start of every graph the buffer is bound in.
+// The copies are indexed by a single context's frame slot, so don't share one between graphics contexts.
+pub struct PerFrameBuffer {
+	buffers: Vec<GpuBuffer>,
+	// One for each of buffers when they aren't host visible, empty otherwise.
+	staging: Vec<GpuBuffer>,
+	// How many times write went through each path, mostly so it can be checked which one a machine takes.
+	direct_writes: u64,
+	staged_writes: u64,
+}
+
+impl Hash for PerFrameBuffer {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.buffers.hash(state);
+	}
+}
+
+impl PartialEq for PerFrameBuffer {
+	fn eq(&self, other: &Self) -> bool {
+		self.buffers == other.buffers
+	}
+}
+
+impl Eq for PerFrameBuffer {}
+
+impl PerFrameBuffer {
+	pub fn new(upload_context: &mut UploadContext, name: &str, size: usize, usage: BufferUsage) -> GoldfishResult<Self> {
+		let staged = upload_context.device.resolve_memory_location(MemoryLocationPreference::Auto) != MemoryLocation::CpuToGpu;
+
+		let mut per_frame_buffer = Self {
+			buffers: Vec::with_capacity(upload_context.device.frames_in_flight),
+			staging: Vec::new(),
+			direct_writes: 0,
+			staged_writes: 0,
+		};
+
+		let device_usage = if staged { usage | BufferUsage::TransferDst } else { usage };
+		for _ in 0..upload_context.device.frames_in_flight {
+			match upload_context.create_buffer(name, size, MemoryLocationPreference::Auto, device_usage, None, None) {
+				Ok(buffer) => per_frame_buffer.buffers.push(buffer),
+				Err(err) => {
+					per_frame_buffer.destroy(&mut upload_context.device);
+					return Err(err);
+				}
+			}
+
+			if staged {
+				match upload_context.create_buffer(&format!("{} (Staging)", name), size, MemoryLocation::CpuToGpu, BufferUsage::TransferSrc, None, None) {
+					Ok(staging) => per_frame_buffer.staging.push(staging),
+					Err(err) => {
+						per_frame_buffer.destroy(&mut upload_context.device);
+						return Err(err);
+					}
+				}
+			}
+		}
+
+		Ok(per_frame_buffer)
+	}
+
+	pub fn write(&mut self, frame_slot: usize, data: &[u8]) {
+		if self.is_staged() {
+			self.staged_writes += 1;
+		} else {
+			self.direct_writes += 1;
+		}
+
+		let buffer = self.current_mut(frame_slot);
+		assert!(data.len() <= buffer.size, "Cannot write per frame buffer with data that is too long!");
+
+		buffer.allocation.mapped_slice_mut().expect("Failed to map per frame buffer!")[..data.len()].copy_from_slice(data);
+		buffer.forget_written(0..data.len());
+	}
+
+	// Whether writes go through staging buffers, see MemoryLocationPreference::Auto.
+	pub fn is_staged(&self) -> bool {
+		!self.staging.is_empty()
+	}
+
+	pub fn direct_writes(&self) -> u64 {
+		self.direct_writes
+	}
+
+	pub fn staged_writes(&self) -> u64 {
+		self.staged_writes
+	}
+
+	// The copy the CPU writes for this frame slot, which is always host visible. This is the staging buffer when there is one.
+	pub fn current(&self, frame_slot: usize) -> &GpuBuffer {
+		match self.staging.get(frame_slot) {
+			Some(staging) => staging,
+			None => &self.buffers[frame_slot],
+		}
+	}
+
+	pub fn current_mut(&mut self, frame_slot: usize) -> &mut GpuBuffer {
+		match self.staging.get_mut(frame_slot) {
+			Some(staging) => staging,
+			None => &mut self.buffers[frame_slot],
+		}
+	}
+
+	// The copy the GPU reads for this frame slot, which is what gets bound.
+	pub fn gpu_buffer(&self, frame_slot: usize) -> &GpuBuffer {
+		&self.buffers[frame_slot]
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		for buffer in self.buffers.into_iter().chain(self.staging) {
+			graphics_device.destroy_buffer(buffer);
+		}
+	}
+}
@@ -1,5 +1,42 @@
 use super::*;
+use crate::build::PushConstant;
+use crate::jobs::{self, JobHandle};
+use crate::types::Size;
+use crate::GoldfishError;
+use glam::UVec2;
+use log::{debug, error};
+use std::collections::hash_map::Entry;
 use std::collections::HashSet;
+use std::fmt::Write;
+
+// Where a shader can write an imported buffer from, see PassBuilder::write_imported_buffer.
+fn shader_write_stages() -> ash::vk::PipelineStageFlags {
+	ash::vk::PipelineStageFlags::VERTEX_SHADER | ash::vk::PipelineStageFlags::FRAGMENT_SHADER | ash::vk::PipelineStageFlags::COMPUTE_SHADER
+}
+
+fn imported_buffer_access() -> ash::vk::AccessFlags {
+	ash::vk::AccessFlags::UNIFORM_READ
+		| ash::vk::AccessFlags::SHADER_READ
+		| ash::vk::AccessFlags::SHADER_WRITE
+		| ash::vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+		| ash::vk::AccessFlags::INDEX_READ
+		| ash::vk::AccessFlags::INDIRECT_COMMAND_READ
+		| ash::vk::AccessFlags::TRANSFER_READ
+		| ash::vk::AccessFlags::TRANSFER_WRITE
+}
+
+pub fn dispatch_group_count(size: UVec2, local_size: UVec2) -> UVec2 {
+	assert!(local_size.x > 0 && local_size.y > 0, "Local size cannot be zero!");
+	UVec2::new(size.x.div_ceil(local_size.x), size.y.div_ceil(local_size.y))
+}
+
+// What an OutputRelative attachment ends up being sized to, never smaller than a pixel so a minimized window still gets valid attachments.
+pub fn relative_output_size(output_size: Size, scale_x: f32, scale_y: f32) -> Size {
+	Size {
+		width: ((output_size.width as f32 * scale_x).round() as u32).max(1),
+		height: ((output_size.height as f32 * scale_y).round() as u32).max(1),
+	}
+}
 
 #[derive(Debug, Clone)]
 enum PassCmd {
@@ -27,17 +64,141 @@ enum PassCmd {
 	DrawMesh {
 		mesh: GraphImportedMeshHandle,
 	},
+	BindVertexBuffers {
+		buffers: Vec<(GraphBufferBinding, u64)>,
+	},
+	BindIndexBuffer {
+		buffer: GraphBufferBinding,
+		offset: u64,
+		index_type: IndexType,
+	},
+	DrawIndexed {
+		index_count: u32,
+		instance_count: u32,
+		first_index: u32,
+		vertex_offset: i32,
+		first_instance: u32,
+	},
 	Draw {
 		vertex_count: u32,
 		instance_count: u32,
 		first_vertex: u32,
 		first_instance: u32,
 	},
+	DrawIndirect {
+		buffer: GraphBufferBinding,
+		offset: u64,
+		draw_count: u32,
+		stride: u32,
+		indexed: bool,
+	},
 	Dispatch {
 		group_count_x: u32,
 		group_count_y: u32,
 		group_count_z: u32,
 	},
+	BeginQuery {
+		slot: QuerySlot,
+	},
+	EndQuery {
+		slot: QuerySlot,
+	},
+	UpdateImportedBuffer {
+		buffer: GraphImportedBufferHandle,
+		offset: u64,
+		data: Vec<u8>,
+	},
+	FillBuffer {
+		buffer: MutableGraphBufferHandle,
+		value: u32,
+	},
+	ClearAttachment {
+		attachment: MutableGraphAttachmentHandle,
+		value: ClearValue,
+	},
+	PushConstants {
+		pipeline: GraphRasterPipelineHandle,
+		offset: u32,
+		data: Vec<u8>,
+	},
+	BlitToOutput {
+		attachment: GraphAttachmentHandle,
+		filter: Filter,
+	},
+	SetViewport {
+		offset: UVec2,
+		extent: UVec2,
+	},
+}
+
+const UNUSED_RESOURCE_FRAMES: u64 = 8;
+
+// Slots get reused, so anything cached by index also has to remember the slot's generation.
+struct ResourceSlots<T> {
+	slots: Vec<Option<T>>,
+	generations: Vec<u64>,
+	last_used: Vec<u64>,
+	free: Vec<usize>,
+}
+
+impl<T> Default for ResourceSlots<T> {
+	fn default() -> Self {
+		Self {
+			slots: Vec::new(),
+			generations: Vec::new(),
+			last_used: Vec::new(),
+			free: Vec::new(),
+		}
+	}
+}
+
+impl<T> ResourceSlots<T> {
+	fn insert(&mut self, resource: T, frame: u64) -> usize {
+		match self.free.pop() {
+			Some(index) => {
+				self.slots[index] = Some(resource);
+				self.last_used[index] = frame;
+				index
+			}
+			None => {
+				self.slots.push(Some(resource));
+				self.generations.push(0);
+				self.last_used.push(frame);
+				self.slots.len() - 1
+			}
+		}
+	}
+
+	fn remove(&mut self, index: usize) -> T {
+		let resource = self.slots[index].take().expect("Resource slot was already empty!");
+		self.generations[index] += 1;
+		self.free.push(index);
+		resource
+	}
+
+	fn get(&self, index: usize) -> &T {
+		self.slots[index].as_ref().expect("Resource slot is empty!")
+	}
+
+	fn generation(&self, index: usize) -> u64 {
+		self.generations[index]
+	}
+
+	fn is_current(&self, index: usize, generation: u64) -> bool {
+		self.slots[index].is_some() && self.generations[index] == generation
+	}
+
+	fn mark_used(&mut self, index: usize, frame: u64) {
+		self.last_used[index] = frame;
+	}
+
+	fn is_unused(&self, index: usize, frame: u64) -> bool {
+		frame.saturating_sub(self.last_used[index]) > UNUSED_RESOURCE_FRAMES
+	}
+
+	fn into_resources(self) -> impl Iterator<Item = T> {
+		self.slots.into_iter().flatten()
+	}
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
@@ -49,7 +210,7 @@ struct BufferCacheKey {
 
 #[derive(Default)]
 struct BufferCache {
-	buffers: Vec<GpuBuffer>,
+	buffers: ResourceSlots<GpuBuffer>,
 	cache: HashMap<BufferCacheKey, Vec<usize>>,
 }
 
@@ -61,23 +222,51 @@ struct AttachmentCacheKey {
 	usage: TextureUsage,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AttachmentState {
+	layout: ImageLayout,
+	stage: ash::vk::PipelineStageFlags,
+	access: ash::vk::AccessFlags,
+}
+
+impl AttachmentState {
+	const CLEARED: Self = Self {
+		layout: ImageLayout::ShaderReadOnlyOptimal,
+		stage: ash::vk::PipelineStageFlags::TRANSFER,
+		access: ash::vk::AccessFlags::TRANSFER_WRITE,
+	};
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct HistoryAttachmentCacheKey {
+	name: &'static str,
+	attachment: AttachmentCacheKey,
+}
+
+struct HistoryAttachment {
+	attachments: [usize; 2],
+	previous: usize,
+	states: [AttachmentState; 2],
+}
+
 #[derive(Default)]
 struct AttachmentCache {
-	attachments: Vec<Texture>,
+	attachments: ResourceSlots<Texture>,
 	cache: HashMap<AttachmentCacheKey, Vec<usize>>,
+	history: HashMap<HistoryAttachmentCacheKey, HistoryAttachment>,
 }
 
 #[derive(Clone, Hash, PartialEq, Eq)]
 struct FramebufferCacheKey {
 	width: u32,
 	height: u32,
-	attachments: Vec<usize>,
+	attachments: Vec<(usize, u64)>,
 	render_pass: usize,
 }
 
 #[derive(Default)]
 struct FramebufferCache {
-	framebuffers: Vec<Framebuffer>,
+	framebuffers: ResourceSlots<Framebuffer>,
 	cache: HashMap<FramebufferCacheKey, usize>,
 }
 
@@ -93,30 +282,74 @@ struct RenderPassCache {
 	cache: HashMap<RenderPassCacheKey, usize>,
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum PhysicalRenderPass {
+	Cached(usize),
+	// The swapchain owns the output render pass, so we identify it by the generation of the swapchain it belongs to.
+	Output { generation: u64 },
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct RasterPipelineCacheKey {
 	vs: ash::vk::ShaderModule,         // TODO(Brandon): Make this platform agnostic or find some better way to do this.
 	ps: Option<ash::vk::ShaderModule>, // This applies to all borrowed resources where we need some hashable way of identifying them.
+	gs: Option<ash::vk::ShaderModule>,
 	descriptor_layouts: Vec<DescriptorLayout>,
-	render_pass: usize,
+	render_pass: PhysicalRenderPass,
 	depth_compare_op: Option<DepthCompareOp>,
 	depth_write: bool,
+	depth_bias: Option<DepthBias>,
+	blend_mode: BlendMode,
 	face_cull: FaceCullMode,
 	push_constant_bytes: usize,
 	vertex_input_info: VertexInputInfo,
 	polygon_mode: PolygonMode,
+	specialization: Vec<(u32, SpecValue)>,
+}
+
+enum PipelineStatus {
+	Ready(usize),
+	Compiling(JobHandle<GoldfishResult<Pipeline>>),
+	Failed(usize),
 }
 
 #[derive(Default)]
 struct RasterPipelineCache {
-	pipelines: Vec<Pipeline>,
-	cache: HashMap<RasterPipelineCacheKey, usize>,
+	pipelines: Vec<Option<Pipeline>>,
+	free_pipelines: Vec<usize>,
+	cache: HashMap<RasterPipelineCacheKey, PipelineStatus>,
+	async_compile: bool,
+}
+
+impl RasterPipelineCache {
+	#[cfg(debug_assertions)]
+	fn assert_slots_reachable(&self) {
+		let mut referenced = vec![false; self.pipelines.len()];
+		for status in self.cache.values() {
+			if let &PipelineStatus::Ready(index) | &PipelineStatus::Failed(index) = status {
+				referenced[index] = true;
+			}
+		}
+
+		let mut free = vec![false; self.pipelines.len()];
+		for &index in &self.free_pipelines {
+			assert!(!free[index], "Raster pipeline slot {} is on the free list twice!", index);
+			assert!(self.pipelines[index].is_none(), "Raster pipeline slot {} is on the free list but still holds a pipeline!", index);
+			free[index] = true;
+		}
+
+		for (index, pipeline) in self.pipelines.iter().enumerate() {
+			assert!(pipeline.is_none() || referenced[index], "Raster pipeline slot {} leaked, nothing in the cache uses it!", index);
+			assert!(pipeline.is_some() || free[index], "Raster pipeline slot {} is empty but was never freed!", index);
+		}
+	}
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct ComputePipelineCacheKey {
 	cs: ash::vk::ShaderModule, // TODO(Brandon): Same thing as raster pipeline cache key
 	descriptor_layouts: Vec<DescriptorLayout>,
+	specialization: Vec<(u32, SpecValue)>,
 }
 
 #[derive(Default)]
@@ -125,7 +358,7 @@ struct ComputePipelineCache {
 	cache: HashMap<ComputePipelineCacheKey, usize>,
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 enum DescriptorHeapCacheKeyBinding {
 	ImportedBuffer {
 		buffer: ash::vk::Buffer,
@@ -135,11 +368,20 @@ enum DescriptorHeapCacheKeyBinding {
 		sampler: ash::vk::Sampler,
 		image_view: ash::vk::ImageView,
 	}, // We need a better way of identifying them.
+	ImportedTextureArray {
+		image_views: Vec<ash::vk::ImageView>,
+	},
 	Buffer {
 		buffer: usize,
+		generation: u64,
 	},
 	Attachment {
 		attachment: usize,
+		generation: u64,
+		layout: ImageLayout,
+	},
+	OutputStorage {
+		image_view: ash::vk::ImageView,
 	},
 }
 
@@ -148,9 +390,38 @@ struct DescriptorHeapCacheKey {
 	bindings: Vec<(u32, DescriptorHeapCacheKeyBinding)>,
 }
 
+// Each frame slot's copy of a set is written the first time the slot uses it, once the GPU is done with that slot.
+#[derive(Clone, Copy)]
+struct CachedDescriptor {
+	handle: DescriptorHandle,
+	written_slots: u32,
+}
+
+impl CachedDescriptor {
+	fn take_write(&mut self, frame_slot: usize) -> bool {
+		let slot = 1 << frame_slot;
+		let needs_write = self.written_slots & slot == 0;
+		self.written_slots |= slot;
+
+		needs_write
+	}
+}
+
 struct DescriptorHeapCache {
 	heap: DescriptorHeap,
-	cache: HashMap<DescriptorHeapCacheKey, DescriptorHandle>,
+	cache: HashMap<DescriptorHeapCacheKey, CachedDescriptor>,
+}
+
+fn register_descriptor_heap(heap_caches: &mut HashMap<DescriptorSetBindings, DescriptorHeapCache>, bindings: &DescriptorSetBindings, create: impl FnOnce() -> DescriptorHeap) {
+	if !heap_caches.contains_key(bindings) {
+		heap_caches.insert(
+			bindings.clone(),
+			DescriptorHeapCache {
+				heap: create(),
+				cache: Default::default(),
+			},
+		);
+	}
 }
 
 #[derive(Default)]
@@ -162,20 +433,208 @@ pub struct RenderGraphCache {
 	raster_pipeline_cache: RasterPipelineCache,
 	compute_pipeline_cache: ComputePipelineCache,
 	descriptor_layout_cache: DescriptorLayoutCache,
-	graphics_descriptor_heap_caches: HashMap<*const DescriptorSetInfo, DescriptorHeapCache>,
-	compute_descriptor_heap_caches: HashMap<*const DescriptorSetInfo, DescriptorHeapCache>,
+	graphics_descriptor_heap_caches: HashMap<DescriptorSetBindings, DescriptorHeapCache>,
+	compute_descriptor_heap_caches: HashMap<DescriptorSetBindings, DescriptorHeapCache>,
+	pass_timings: Vec<PassTiming>,
+	submit_batching: SubmitBatching,
+	exported_attachments: HashMap<&'static str, usize>,
+	frame: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PassTiming {
+	pub name: &'static str,
+	pub cpu_time: std::time::Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitBatching {
+	Single,
+	Hints,
+	// Submits after every this many passes (out of the ones that actually run), as well as after the hinted ones.
+	EveryPasses(usize),
+}
+
+impl Default for SubmitBatching {
+	fn default() -> Self {
+		Self::Single
+	}
+}
+
+fn validation_failure(pass: &str, cmd: Option<usize>, resource: &str, message: &str) -> ! {
+	let location = match cmd {
+		Some(cmd) => format!("pass \"{}\" at command {}", pass, cmd),
+		None => format!("pass \"{}\"", pass),
+	};
+
+	if resource.is_empty() {
+		panic!("Render graph validation failed in {}: {}!", location, message);
+	} else {
+		panic!("Render graph validation failed in {} ({}): {}!", location, resource, message);
+	}
+}
+
+fn validate_buffer_binding(descriptor: &str, binding: u32, binding_type: Option<DescriptorBindingType>, usage: BufferUsage) {
+	match binding_type {
+		Some(binding_type) => match binding_type.required_buffer_usage() {
+			Some(required) => assert!(
+				usage.contains(required),
+				"Binding {} of {} is a {:?} which needs a buffer with {:?} usage, but the buffer bound to it only has {:?}!",
+				binding,
+				descriptor,
+				binding_type,
+				required,
+				usage
+			),
+			None => panic!("Binding {} of {} is a {:?}, a buffer (with {:?} usage) can't be bound to it!", binding, descriptor, binding_type, usage),
+		},
+		None => panic!("Binding {} of {} isn't in its descriptor layout!", binding, descriptor),
+	}
+}
+
+fn validate_texture_binding(descriptor: &str, binding: u32, binding_type: Option<DescriptorBindingType>, usage: TextureUsage) {
+	match binding_type {
+		Some(binding_type) => match binding_type.required_texture_usage() {
+			Some(required) => assert!(
+				usage.contains(required),
+				"Binding {} of {} is a {:?} which needs a texture with {:?} usage, but the texture bound to it only has {:?}!",
+				binding,
+				descriptor,
+				binding_type,
+				required,
+				usage
+			),
+			None if binding_type == DescriptorBindingType::SamplerState => {}
+			None => panic!(
+				"Binding {} of {} is a {:?}, a texture (with {:?} usage) can't be bound to it!",
+				binding, descriptor, binding_type, usage
+			),
+		},
+		None => panic!("Binding {} of {} isn't in its descriptor layout!", binding, descriptor),
+	}
+}
+
+fn create_cached_raster_pipeline(graphics_device: &GraphicsDevice, render_pass: &RenderPass, key: &RasterPipelineCacheKey) -> GoldfishResult<Pipeline> {
+	let ps = key.ps.map(Shader::borrowed);
+	let gs = key.gs.map(Shader::borrowed);
+
+	graphics_device.create_raster_pipeline(
+		&Shader::borrowed(key.vs),
+		ps.as_ref(),
+		gs.as_ref(),
+		&key.descriptor_layouts,
+		render_pass,
+		key.depth_compare_op,
+		key.depth_write,
+		key.depth_bias,
+		key.blend_mode,
+		key.face_cull,
+		key.push_constant_bytes,
+		key.vertex_input_info,
+		key.polygon_mode,
+		&key.specialization,
+	)
 }
 
 impl RenderGraphCache {
-	fn alloc_render_pass(&mut self, graphics_device: &GraphicsDevice, key: &RenderPassCacheKey) -> usize {
-		*self.render_pass_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated render pass! {:?}", key);
-			self.render_pass_cache
-				.render_passes
-				.push(graphics_device.create_render_pass(&key.color_attachment_descs, key.depth_attachment_desc));
-
-			self.render_pass_cache.render_passes.len() - 1
-		})
+	pub fn pass_timings(&self) -> &[PassTiming] {
+		&self.pass_timings
+	}
+
+	// Left in ImageLayout::TransferSrcOptimal, and reused by the next graph that's executed so read from it before then.
+	pub fn exported_attachment(&self, name: &str) -> Option<&Texture> {
+		self.exported_attachments.get(name).map(|&index| self.attachment_cache.attachments.get(index))
+	}
+
+	// Offscreen pipelines get compiled on a worker thread and their draws are skipped until they're ready. Output pipelines never are.
+	pub fn set_async_pipeline_compilation(&mut self, enabled: bool) {
+		self.raster_pipeline_cache.async_compile = enabled;
+	}
+
+	pub fn set_submit_batching(&mut self, batching: SubmitBatching) {
+		assert!(batching != SubmitBatching::EveryPasses(0), "Can't submit after every 0 passes!");
+		self.submit_batching = batching;
+	}
+
+	// Meant to be called while loading so pipelines that only show up later don't cause a hitch. Always waits for all of them.
+	pub fn precompile(&mut self, graphics_context: &mut GraphicsContext, graphics_device: &GraphicsDevice, descs: &[RasterPipelinePrecompileDesc]) -> GoldfishResult<()> {
+		tracy::span!();
+		let mut keys = Vec::with_capacity(descs.len());
+		for desc in descs {
+			let descriptor_bindings = desc.descriptor_layouts.iter().map(|info| info.to_bindings()).collect::<Vec<_>>();
+			for bindings in descriptor_bindings.iter() {
+				graphics_device.check_descriptor_set_support(bindings)?;
+			}
+
+			let render_pass = match desc.render_pass {
+				PrecompileRenderPass::Output => PhysicalRenderPass::Output {
+					generation: graphics_context.get_output_generation(),
+				},
+				PrecompileRenderPass::Attachments { color_attachments, depth_attachment } => {
+					let description = |attachment: &AttachmentDesc, final_layout| AttachmentDescription {
+						format: attachment.format,
+						usage: attachment.usage,
+						load_op: attachment.load_op,
+						store_op: attachment.store_op,
+						initial_layout: ImageLayout::Undefined,
+						final_layout,
+					};
+
+					let key = RenderPassCacheKey {
+						color_attachment_descs: color_attachments.iter().map(|a| description(a, ImageLayout::ColorAttachmentOptimal)).collect(),
+						depth_attachment_desc: depth_attachment.as_ref().map(|a| description(a, ImageLayout::DepthStencilAttachmentOptimal)),
+					};
+
+					PhysicalRenderPass::Cached(self.alloc_render_pass(graphics_device, &key)?)
+				}
+			};
+
+			let descriptor_layouts = descriptor_bindings
+				.iter()
+				.map(|bindings| self.register_graphics_descriptor_layout(graphics_device, bindings))
+				.collect::<Vec<_>>();
+
+			keys.push(RasterPipelineCacheKey {
+				vs: desc.vs.module,
+				ps: desc.ps.map(|ps| ps.module),
+				gs: desc.gs.map(|gs| gs.module),
+				descriptor_layouts,
+				render_pass,
+				depth_compare_op: desc.depth_compare_op,
+				depth_write: desc.depth_write,
+				depth_bias: desc.depth_bias,
+				blend_mode: desc.blend_mode,
+				face_cull: desc.face_cull,
+				push_constant_bytes: desc.push_constant_bytes,
+				vertex_input_info: desc.vertex_input_info,
+				polygon_mode: desc.polygon_mode,
+				specialization: desc.specialization.to_vec(),
+			});
+		}
+
+		for key in keys.iter() {
+			self.alloc_raster_pipeline(graphics_context, graphics_device, key)?;
+		}
+
+		for key in keys.iter() {
+			self.finish_raster_pipeline(key)?;
+		}
+
+		Ok(())
+	}
+
+	fn alloc_render_pass(&mut self, graphics_device: &GraphicsDevice, key: &RenderPassCacheKey) -> GoldfishResult<usize> {
+		match self.render_pass_cache.cache.entry(key.clone()) {
+			Entry::Occupied(entry) => Ok(*entry.get()),
+			Entry::Vacant(entry) => {
+				debug!("Allocated render pass {:?}", key);
+				self.render_pass_cache
+					.render_passes
+					.push(graphics_device.create_render_pass(&key.color_attachment_descs, key.depth_attachment_desc)?);
+
+				Ok(*entry.insert(self.render_pass_cache.render_passes.len() - 1))
+			}
+		}
 	}
 
 	fn get_render_pass_index(&self, key: &RenderPassCacheKey) -> usize {
@@ -188,16 +647,14 @@ impl RenderGraphCache {
 
 	fn alloc_framebuffer(&mut self, graphics_device: &GraphicsDevice, key: &FramebufferCacheKey) -> usize {
 		*self.framebuffer_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated framebuffer!");
+			debug!("Allocated framebuffer");
 
 			let render_pass = &self.render_pass_cache.render_passes[key.render_pass];
-			let attachments = key.attachments.iter().map(|a| &self.attachment_cache.attachments[*a]).collect::<Vec<_>>();
+			let attachments = key.attachments.iter().map(|&(a, _)| self.attachment_cache.attachments.get(a)).collect::<Vec<_>>();
 
 			self.framebuffer_cache
 				.framebuffers
-				.push(graphics_device.create_framebuffer(key.width, key.height, render_pass, &attachments));
-
-			self.framebuffer_cache.framebuffers.len() - 1
+				.insert(graphics_device.create_framebuffer(key.width, key.height, render_pass, &attachments), self.frame)
 		})
 	}
 
@@ -206,67 +663,218 @@ impl RenderGraphCache {
 	}
 
 	fn get_framebuffer(&self, key: &FramebufferCacheKey) -> &Framebuffer {
-		&self.framebuffer_cache.framebuffers[self.get_framebuffer_index(key)]
+		self.framebuffer_cache.framebuffers.get(self.get_framebuffer_index(key))
 	}
 
-	fn alloc_raster_pipeline(&mut self, graphics_context: &mut GraphicsContext, graphics_device: &GraphicsDevice, key: &RasterPipelineCacheKey) -> usize {
-		*self.raster_pipeline_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated pipeline!");
-			// TODO(Brandon): Kinda a messy hack to get around rust, not sure if there is a better way to do this...
-			let ps = Shader {
-				module: key.ps.unwrap_or(ash::vk::ShaderModule::null()),
-			};
-			let ps = key.ps.map_or(None, |_| Some(&ps));
+	fn alloc_raster_pipeline(&mut self, graphics_context: &mut GraphicsContext, graphics_device: &GraphicsDevice, key: &RasterPipelineCacheKey) -> GoldfishResult<Option<usize>> {
+		match self.raster_pipeline_cache.cache.get(key) {
+			Some(&PipelineStatus::Ready(index)) | Some(&PipelineStatus::Failed(index)) => return Ok(Some(index)),
+			Some(PipelineStatus::Compiling(handle)) if !handle.is_finished() => return Ok(None),
+			Some(PipelineStatus::Compiling(_)) => return self.finish_raster_pipeline(key).map(Some),
+			None => {}
+		}
+
+		// The output render pass gets destroyed whenever the swapchain is recreated, which could be while a worker is still using it.
+		if let PhysicalRenderPass::Cached(render_pass) = key.render_pass {
+			if self.raster_pipeline_cache.async_compile {
+				debug!("Allocated pipeline");
+				let render_pass = &self.render_pass_cache.render_passes[render_pass];
+				let render_pass = RenderPass {
+					raw: render_pass.raw,
+					color_attachments: render_pass.color_attachments.clone(),
+					depth_attachment: render_pass.depth_attachment,
+				};
+				let graphics_device = graphics_device.clone();
+				let worker_key = key.clone();
+
+				let handle = jobs::spawn(move || create_cached_raster_pipeline(&graphics_device, &render_pass, &worker_key));
+				self.raster_pipeline_cache.cache.insert(key.clone(), PipelineStatus::Compiling(handle));
+
+				return Ok(None);
+			}
+		}
+
+		let pipeline = self.create_raster_pipeline(graphics_context, graphics_device, key)?;
+		Ok(Some(self.insert_raster_pipeline(key, pipeline)))
+	}
+
+	fn create_raster_pipeline(&self, graphics_context: &mut GraphicsContext, graphics_device: &GraphicsDevice, key: &RasterPipelineCacheKey) -> GoldfishResult<Pipeline> {
+		debug!("Allocated pipeline");
+		match key.render_pass {
+			PhysicalRenderPass::Output { generation } => {
+				assert_eq!(generation, graphics_context.get_output_generation(), "Attempted to create a pipeline for a stale output render pass!");
+				let ps = key.ps.map(Shader::borrowed);
+				let gs = key.gs.map(Shader::borrowed);
 
-			// TODO(Brandon): Really good example of how we should allow for fetching of the render pass from the swapchain.
-			self.raster_pipeline_cache.pipelines.push(if key.render_pass == usize::MAX {
 				graphics_context.create_raster_pipeline(
-					&Shader { module: key.vs },
-					ps,
-					&key.descriptor_layouts,
-					key.depth_compare_op,
-					key.depth_write,
-					key.face_cull,
-					key.push_constant_bytes,
-					key.vertex_input_info,
-					key.polygon_mode,
-				)
-			} else {
-				graphics_device.create_raster_pipeline(
-					&Shader { module: key.vs },
-					ps,
+					&Shader::borrowed(key.vs),
+					ps.as_ref(),
+					gs.as_ref(),
 					&key.descriptor_layouts,
-					&mut self.render_pass_cache.render_passes[key.render_pass],
 					key.depth_compare_op,
 					key.depth_write,
+					key.depth_bias,
+					key.blend_mode,
 					key.face_cull,
 					key.push_constant_bytes,
 					key.vertex_input_info,
 					key.polygon_mode,
+					&key.specialization,
 				)
-			});
+			}
+			PhysicalRenderPass::Cached(render_pass) => create_cached_raster_pipeline(graphics_device, &self.render_pass_cache.render_passes[render_pass], key),
+		}
+	}
+
+	fn fail_raster_pipeline(
+		&mut self,
+		graphics_context: &mut GraphicsContext,
+		graphics_device: &GraphicsDevice,
+		default_resources: &DefaultResources,
+		key: &RasterPipelineCacheKey,
+		err: GoldfishError,
+	) -> GoldfishResult<usize> {
+		error!("Failed to create a raster pipeline, drawing with the error pipeline instead! {}", err);
+
+		let error_key = RasterPipelineCacheKey {
+			vs: default_resources.error_vs.module,
+			ps: Some(default_resources.error_ps.module),
+			gs: None,
+			depth_compare_op: None,
+			depth_write: false,
+			depth_bias: None,
+			blend_mode: BlendMode::Opaque,
+			face_cull: FaceCullMode::NoCull,
+			polygon_mode: PolygonMode::Fill,
+			specialization: Vec::new(),
+			..key.clone()
+		};
+
+		let index = match self.raster_pipeline_cache.cache.get(&error_key) {
+			Some(&PipelineStatus::Ready(index)) => index,
+			_ => {
+				let pipeline = self.create_raster_pipeline(graphics_context, graphics_device, &error_key)?;
+				self.insert_raster_pipeline(&error_key, pipeline)
+			}
+		};
 
+		self.raster_pipeline_cache.cache.insert(key.clone(), PipelineStatus::Failed(index));
+		Ok(index)
+	}
+
+	fn insert_raster_pipeline(&mut self, key: &RasterPipelineCacheKey, pipeline: Pipeline) -> usize {
+		let index = if let Some(index) = self.raster_pipeline_cache.free_pipelines.pop() {
+			self.raster_pipeline_cache.pipelines[index] = Some(pipeline);
+			index
+		} else {
+			self.raster_pipeline_cache.pipelines.push(Some(pipeline));
 			self.raster_pipeline_cache.pipelines.len() - 1
-		})
+		};
+
+		self.raster_pipeline_cache.cache.insert(key.clone(), PipelineStatus::Ready(index));
+		index
+	}
+
+	fn finish_raster_pipeline(&mut self, key: &RasterPipelineCacheKey) -> GoldfishResult<usize> {
+		match self.raster_pipeline_cache.cache.remove(key) {
+			Some(PipelineStatus::Compiling(handle)) => {
+				let pipeline = handle.join().expect("Pipeline compilation job panicked!")?;
+				Ok(self.insert_raster_pipeline(key, pipeline))
+			}
+			Some(PipelineStatus::Ready(index)) => {
+				self.raster_pipeline_cache.cache.insert(key.clone(), PipelineStatus::Ready(index));
+				Ok(index)
+			}
+			Some(PipelineStatus::Failed(index)) => {
+				self.raster_pipeline_cache.cache.insert(key.clone(), PipelineStatus::Failed(index));
+				Ok(index)
+			}
+			None => unreachable!("Raster pipeline was never allocated!"),
+		}
+	}
+
+	// Pipelines built against an old swapchain render pass are incompatible once it's recreated.
+	fn evict_stale_output_pipelines(&mut self, graphics_device: &mut GraphicsDevice, generation: u64) {
+		let stale = self
+			.raster_pipeline_cache
+			.cache
+			.keys()
+			.filter(|key| matches!(key.render_pass, PhysicalRenderPass::Output { generation: g } if g != generation))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		for key in stale {
+			let index = match self.raster_pipeline_cache.cache.remove(&key).unwrap() {
+				PipelineStatus::Ready(index) => index,
+				PipelineStatus::Compiling(_) => unreachable!("Output pipelines are never compiled asynchronously!"),
+				PipelineStatus::Failed(_) => continue,
+			};
+			debug!("Evicted stale output pipeline");
+
+			graphics_device.destroy_pipeline(self.raster_pipeline_cache.pipelines[index].take().unwrap());
+			self.raster_pipeline_cache.free_pipelines.push(index);
+		}
+
+		#[cfg(debug_assertions)]
+		self.raster_pipeline_cache.assert_slots_reachable();
+	}
+
+	// Has to be called before destroying shaders that were drawn with, otherwise the driver could hand one of their handles out to the
+	// next shader module that's created, which would then pick up the old pipelines.
+	pub fn evict_shader_pipelines(&mut self, graphics_device: &mut GraphicsDevice, shaders: &[&Shader]) {
+		let uses_shader = |module: ash::vk::ShaderModule| shaders.iter().any(|shader| shader.module == module);
+		let stale = self
+			.raster_pipeline_cache
+			.cache
+			.keys()
+			.filter(|key| uses_shader(key.vs) || key.ps.map_or(false, uses_shader) || key.gs.map_or(false, uses_shader))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		for key in stale {
+			match self.raster_pipeline_cache.cache.remove(&key).unwrap() {
+				PipelineStatus::Ready(index) => {
+					graphics_device.destroy_pipeline(self.raster_pipeline_cache.pipelines[index].take().unwrap());
+					self.raster_pipeline_cache.free_pipelines.push(index);
+				}
+				PipelineStatus::Compiling(handle) => {
+					if let Ok(Ok(pipeline)) = handle.join() {
+						graphics_device.destroy_pipeline(pipeline);
+					}
+				}
+				// The error pipeline is shared by everything that failed, so it stays.
+				PipelineStatus::Failed(_) => {}
+			}
+			debug!("Evicted pipeline for a destroyed shader");
+		}
+
+		#[cfg(debug_assertions)]
+		self.raster_pipeline_cache.assert_slots_reachable();
 	}
 
 	fn get_raster_pipeline_index(&self, key: &RasterPipelineCacheKey) -> usize {
-		*self.raster_pipeline_cache.cache.get(key).unwrap()
+		match self.raster_pipeline_cache.cache.get(key).unwrap() {
+			&PipelineStatus::Ready(index) | &PipelineStatus::Failed(index) => index,
+			PipelineStatus::Compiling(_) => panic!("Raster pipeline is still compiling!"),
+		}
 	}
 
 	fn get_raster_pipeline(&self, key: &RasterPipelineCacheKey) -> &Pipeline {
-		&self.raster_pipeline_cache.pipelines[self.get_raster_pipeline_index(key)]
+		self.raster_pipeline_cache.pipelines[self.get_raster_pipeline_index(key)].as_ref().unwrap()
 	}
 
-	fn alloc_compute_pipeline(&mut self, graphics_device: &GraphicsDevice, key: &ComputePipelineCacheKey) -> usize {
-		*self.compute_pipeline_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated compute pipeline");
-			self.compute_pipeline_cache
-				.pipelines
-				.push(graphics_device.create_compute_pipeline(&Shader { module: key.cs }, &key.descriptor_layouts));
+	fn alloc_compute_pipeline(&mut self, graphics_device: &GraphicsDevice, key: &ComputePipelineCacheKey) -> GoldfishResult<usize> {
+		match self.compute_pipeline_cache.cache.entry(key.clone()) {
+			Entry::Occupied(entry) => Ok(*entry.get()),
+			Entry::Vacant(entry) => {
+				debug!("Allocated compute pipeline");
+				self.compute_pipeline_cache
+					.pipelines
+					.push(graphics_device.create_compute_pipeline(&Shader::borrowed(key.cs), &key.descriptor_layouts, &key.specialization)?);
 
-			self.compute_pipeline_cache.pipelines.len() - 1
-		})
+				Ok(*entry.insert(self.compute_pipeline_cache.pipelines.len() - 1))
+			}
+		}
 	}
 
 	fn get_compute_pipeline_index(&self, key: &ComputePipelineCacheKey) -> usize {
@@ -277,101 +885,248 @@ impl RenderGraphCache {
 		&self.compute_pipeline_cache.pipelines[self.get_compute_pipeline_index(key)]
 	}
 
-	fn alloc_attachments(&mut self, graphics_device: &GraphicsDevice, key: &AttachmentCacheKey, count: usize) {
+	fn alloc_attachments(&mut self, graphics_device: &GraphicsDevice, key: &AttachmentCacheKey, names: &[&'static str]) -> GoldfishResult<()> {
 		let attachments = self.attachment_cache.cache.entry(key.clone()).or_default();
-		while attachments.len() < count {
-			println!("Allocated attachment!");
-			attachments.push(self.attachment_cache.attachments.len());
-			self.attachment_cache
-				.attachments
-				.push(graphics_device.create_texture(key.width, key.height, key.format, key.usage | TextureUsage::ATTACHMENT));
+		while attachments.len() < names.len() {
+			let attachment = graphics_device.create_texture(names[attachments.len()], key.width, key.height, key.format, key.usage | TextureUsage::ATTACHMENT)?;
+			debug!("Allocated attachment {}", names[attachments.len()]);
+			attachments.push(self.attachment_cache.attachments.insert(attachment, self.frame));
 		}
-	}
 
-	fn alloc_buffers(&mut self, graphics_device: &GraphicsDevice, key: &BufferCacheKey, count: usize) {
-		let buffers = self.buffer_cache.cache.entry(*key).or_default();
-		while buffers.len() < count {
-			println!("Allocated buffer!");
-			buffers.push(self.buffer_cache.buffers.len());
-			self.buffer_cache.buffers.push(graphics_device.create_empty_buffer(key.size, key.location, key.usage, None));
-		}
+		Ok(())
 	}
 
-	fn alloc_graphics_descriptor(&mut self, graphics_device: &GraphicsDevice, descriptor_info: &'static DescriptorSetInfo, key: &DescriptorHeapCacheKey) -> DescriptorHandle {
-		self.register_graphics_descriptor_layout(graphics_device, descriptor_info);
+	fn alloc_history_attachment(&mut self, graphics_device: &mut GraphicsDevice, key: &HistoryAttachmentCacheKey) -> GoldfishResult<()> {
+		if self.attachment_cache.history.contains_key(key) {
+			return Ok(());
+		}
 
-		let descriptor_cache = self.graphics_descriptor_heap_caches.get_mut(&(descriptor_info as *const DescriptorSetInfo)).unwrap();
+		let attachment = &key.attachment;
+		let usage = attachment.usage | TextureUsage::ATTACHMENT | TextureUsage::SAMPLED | TextureUsage::TRANSFER_DST;
 
-		*descriptor_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated descriptor!");
-			descriptor_cache.heap.alloc().unwrap()
-		})
-	}
+		let first = graphics_device.create_texture(&format!("{} (History 0)", key.name), attachment.width, attachment.height, attachment.format, usage)?;
+		let second = match graphics_device.create_texture(&format!("{} (History 1)", key.name), attachment.width, attachment.height, attachment.format, usage) {
+			Ok(second) => second,
+			Err(err) => {
+				graphics_device.destroy_texture(first);
+				return Err(err);
+			}
+		};
 
-	fn alloc_compute_descriptor(&mut self, graphics_device: &GraphicsDevice, descriptor_info: &'static DescriptorSetInfo, key: &DescriptorHeapCacheKey) -> DescriptorHandle {
-		self.register_compute_descriptor_layout(graphics_device, descriptor_info);
+		// The first frame reads last frame's version before anything has ever written it, so start both off cleared to zero.
+		let clear_value = if attachment.format == TextureFormat::Depth {
+			ClearValue::DepthStencil { depth: 0.0, stencil: 0 }
+		} else {
+			ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 }
+		};
 
-		let descriptor_cache = self.compute_descriptor_heap_caches.get_mut(&(descriptor_info as *const DescriptorSetInfo)).unwrap();
+		let mut upload_context = graphics_device.create_upload_context();
+		upload_context.clear_texture(&first, clear_value, AttachmentState::CLEARED.layout);
+		upload_context.clear_texture(&second, clear_value, AttachmentState::CLEARED.layout);
+		graphics_device.destroy_upload_context(upload_context);
+
+		debug!("Allocated history attachment");
+		let first = self.attachment_cache.attachments.insert(first, self.frame);
+		let second = self.attachment_cache.attachments.insert(second, self.frame);
+
+		self.attachment_cache.history.insert(
+			*key,
+			HistoryAttachment {
+				attachments: [first, second],
+				previous: 0,
+				states: [AttachmentState::CLEARED; 2],
+			},
+		);
 
-		*descriptor_cache.cache.entry(key.clone()).or_insert_with(|| {
-			println!("Allocated descriptor!");
-			descriptor_cache.heap.alloc().unwrap()
-		})
+		Ok(())
 	}
 
-	fn get_graphics_descriptor_heap(&self, descriptor_info: &'static DescriptorSetInfo) -> &DescriptorHeap {
-		&self.graphics_descriptor_heap_caches.get(&(descriptor_info as *const DescriptorSetInfo)).unwrap().heap
-	}
+	fn alloc_buffers(&mut self, graphics_device: &GraphicsDevice, key: &BufferCacheKey, names: &[&'static str]) -> GoldfishResult<()> {
+		let buffers = self.buffer_cache.cache.entry(*key).or_default();
+		while buffers.len() < names.len() {
+			let buffer = graphics_device.create_empty_buffer(names[buffers.len()], key.size, key.location, key.usage, None)?;
+			debug!("Allocated buffer {}", names[buffers.len()]);
+			buffers.push(self.buffer_cache.buffers.insert(buffer, self.frame));
+		}
 
-	fn get_compute_descriptor_heap(&self, descriptor_info: &'static DescriptorSetInfo) -> &DescriptorHeap {
-		&self.compute_descriptor_heap_caches.get(&(descriptor_info as *const DescriptorSetInfo)).unwrap().heap
+		Ok(())
 	}
 
-	fn register_graphics_descriptor_layout(&mut self, graphics_device: &GraphicsDevice, descriptor_info: &'static DescriptorSetInfo) -> DescriptorLayout {
-		let layout = graphics_device.get_graphics_layout(&mut self.descriptor_layout_cache, descriptor_info);
+	fn evict_unused_resources(&mut self, graphics_device: &mut GraphicsDevice) {
+		let frame = self.frame;
+		let mut evicted = false;
 
-		self.graphics_descriptor_heap_caches.entry(descriptor_info).or_insert_with(|| DescriptorHeapCache {
-			heap: graphics_device.create_descriptor_heap(layout),
-			cache: Default::default(),
-		});
+		let attachments = &mut self.attachment_cache.attachments;
+		for indices in self.attachment_cache.cache.values_mut() {
+			indices.retain(|&index| {
+				if !attachments.is_unused(index, frame) {
+					return true;
+				}
 
-		layout
-	}
+				debug!("Evicted unused attachment");
+				graphics_device.destroy_texture(attachments.remove(index));
+				evicted = true;
+				false
+			});
+		}
+		self.attachment_cache.cache.retain(|_, indices| !indices.is_empty());
 
-	fn register_compute_descriptor_layout(&mut self, graphics_device: &GraphicsDevice, descriptor_info: &'static DescriptorSetInfo) -> DescriptorLayout {
-		let layout = graphics_device.get_compute_layout(&mut self.descriptor_layout_cache, descriptor_info);
+		self.attachment_cache.history.retain(|_, history| {
+			if history.attachments.iter().any(|&index| !attachments.is_unused(index, frame)) {
+				return true;
+			}
 
-		self.compute_descriptor_heap_caches.entry(descriptor_info).or_insert_with(|| DescriptorHeapCache {
-			heap: graphics_device.create_descriptor_heap(layout),
-			cache: Default::default(),
+			debug!("Evicted unused history attachment");
+			for index in history.attachments {
+				graphics_device.destroy_texture(attachments.remove(index));
+			}
+			evicted = true;
+			false
 		});
 
-		layout
-	}
+		let buffers = &mut self.buffer_cache.buffers;
+		for indices in self.buffer_cache.cache.values_mut() {
+			indices.retain(|&index| {
+				if !buffers.is_unused(index, frame) {
+					return true;
+				}
 
-	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
-		for buffer in self.buffer_cache.buffers {
-			graphics_device.destroy_buffer(buffer);
+				debug!("Evicted unused buffer");
+				graphics_device.destroy_buffer(buffers.remove(index));
+				evicted = true;
+				false
+			});
 		}
+		self.buffer_cache.cache.retain(|_, indices| !indices.is_empty());
 
-		for attachment in self.attachment_cache.attachments {
-			graphics_device.destroy_texture(attachment);
+		if !evicted {
+			return;
 		}
 
-		for pipeline in self.raster_pipeline_cache.pipelines {
-			graphics_device.destroy_pipeline(pipeline);
-		}
+		let attachments = &self.attachment_cache.attachments;
+		let buffers = &self.buffer_cache.buffers;
 
-		for render_pass in self.render_pass_cache.render_passes {
-			graphics_device.destroy_render_pass(render_pass);
-		}
+		let framebuffers = &mut self.framebuffer_cache.framebuffers;
+		self.framebuffer_cache.cache.retain(|key, &mut index| {
+			if key.attachments.iter().all(|&(attachment, generation)| attachments.is_current(attachment, generation)) {
+				return true;
+			}
 
-		for framebuffer in self.framebuffer_cache.framebuffers {
-			graphics_device.destroy_framebuffer(framebuffer);
-		}
+			debug!("Evicted stale framebuffer");
+			graphics_device.destroy_framebuffer(framebuffers.remove(index));
+			false
+		});
 
-		for (_, cache) in self.graphics_descriptor_heap_caches {
-			graphics_device.destroy_descriptor_heap(cache.heap);
+		for descriptor_cache in self.graphics_descriptor_heap_caches.values_mut().chain(self.compute_descriptor_heap_caches.values_mut()) {
+			let heap = &mut descriptor_cache.heap;
+			descriptor_cache.cache.retain(|key, descriptor| {
+				let current = key.bindings.iter().all(|(_, binding)| match *binding {
+					DescriptorHeapCacheKeyBinding::Attachment { attachment, generation, .. } => attachments.is_current(attachment, generation),
+					DescriptorHeapCacheKeyBinding::Buffer { buffer, generation } => buffers.is_current(buffer, generation),
+					_ => true,
+				});
+
+				if !current {
+					debug!("Freed stale descriptor");
+					heap.free(descriptor.handle);
+				}
+
+				current
+			});
+		}
+
+		self.exported_attachments.retain(|_, &mut index| attachments.slots[index].is_some());
+	}
+
+	fn alloc_graphics_descriptor(&mut self, graphics_device: &GraphicsDevice, bindings: &DescriptorSetBindings, key: &DescriptorHeapCacheKey, frame_slot: usize) -> (DescriptorHandle, bool) {
+		self.register_graphics_descriptor_layout(graphics_device, bindings);
+
+		let descriptor_cache = self.graphics_descriptor_heap_caches.get_mut(bindings).unwrap();
+
+		let descriptor = descriptor_cache.cache.entry(key.clone()).or_insert_with(|| {
+			debug!("Allocated descriptor");
+			CachedDescriptor {
+				handle: descriptor_cache.heap.alloc().unwrap(),
+				written_slots: 0,
+			}
+		});
+
+		(descriptor.handle, descriptor.take_write(frame_slot))
+	}
+
+	fn alloc_compute_descriptor(&mut self, graphics_device: &GraphicsDevice, bindings: &DescriptorSetBindings, key: &DescriptorHeapCacheKey, frame_slot: usize) -> (DescriptorHandle, bool) {
+		self.register_compute_descriptor_layout(graphics_device, bindings);
+
+		let descriptor_cache = self.compute_descriptor_heap_caches.get_mut(bindings).unwrap();
+
+		let descriptor = descriptor_cache.cache.entry(key.clone()).or_insert_with(|| {
+			debug!("Allocated descriptor");
+			CachedDescriptor {
+				handle: descriptor_cache.heap.alloc().unwrap(),
+				written_slots: 0,
+			}
+		});
+
+		(descriptor.handle, descriptor.take_write(frame_slot))
+	}
+
+	fn get_graphics_descriptor_heap(&self, bindings: &DescriptorSetBindings) -> &DescriptorHeap {
+		&self.graphics_descriptor_heap_caches.get(bindings).unwrap().heap
+	}
+
+	fn get_compute_descriptor_heap(&self, bindings: &DescriptorSetBindings) -> &DescriptorHeap {
+		&self.compute_descriptor_heap_caches.get(bindings).unwrap().heap
+	}
+
+	fn register_graphics_descriptor_layout(&mut self, graphics_device: &GraphicsDevice, bindings: &DescriptorSetBindings) -> DescriptorLayout {
+		let layout = graphics_device.get_graphics_layout(&mut self.descriptor_layout_cache, bindings);
+		register_descriptor_heap(&mut self.graphics_descriptor_heap_caches, bindings, || graphics_device.create_descriptor_heap(layout, bindings));
+
+		layout
+	}
+
+	fn register_compute_descriptor_layout(&mut self, graphics_device: &GraphicsDevice, bindings: &DescriptorSetBindings) -> DescriptorLayout {
+		let layout = graphics_device.get_compute_layout(&mut self.descriptor_layout_cache, bindings);
+		register_descriptor_heap(&mut self.compute_descriptor_heap_caches, bindings, || graphics_device.create_descriptor_heap(layout, bindings));
+
+		layout
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		for (_, status) in self.raster_pipeline_cache.cache {
+			if let PipelineStatus::Compiling(handle) = status {
+				if let Ok(Ok(pipeline)) = handle.join() {
+					graphics_device.destroy_pipeline(pipeline);
+				}
+			}
+		}
+
+		for buffer in self.buffer_cache.buffers.into_resources() {
+			graphics_device.destroy_buffer(buffer);
+		}
+
+		for attachment in self.attachment_cache.attachments.into_resources() {
+			graphics_device.destroy_texture(attachment);
+		}
+
+		for pipeline in self.raster_pipeline_cache.pipelines.into_iter().flatten() {
+			graphics_device.destroy_pipeline(pipeline);
+		}
+
+		for pipeline in self.compute_pipeline_cache.pipelines {
+			graphics_device.destroy_pipeline(pipeline);
+		}
+
+		for render_pass in self.render_pass_cache.render_passes {
+			graphics_device.destroy_render_pass(render_pass);
+		}
+
+		for framebuffer in self.framebuffer_cache.framebuffers.into_resources() {
+			graphics_device.destroy_framebuffer(framebuffer);
+		}
+
+		for (_, cache) in self.graphics_descriptor_heap_caches {
+			graphics_device.destroy_descriptor_heap(cache.heap);
 		}
 
 		for (_, cache) in self.compute_descriptor_heap_caches {
@@ -385,14 +1140,62 @@ impl RenderGraphCache {
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub struct AttachmentDesc {
 	pub name: &'static str,
-	pub width: u32,
-	pub height: u32,
+	pub size: AttachmentSize,
 	pub format: TextureFormat,
 	pub load_op: LoadOp,
 	pub store_op: StoreOp,
 	pub usage: TextureUsage,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum AttachmentSize {
+	Absolute(u32, u32),
+	// Sized from the output's extent times the scale times the graph's resolution scale, see relative_output_size.
+	OutputRelative { scale_x: f32, scale_y: f32 },
+}
+
+impl PartialEq for AttachmentSize {
+	fn eq(&self, other: &Self) -> bool {
+		match (*self, *other) {
+			(Self::Absolute(width, height), Self::Absolute(other_width, other_height)) => width == other_width && height == other_height,
+			(Self::OutputRelative { scale_x, scale_y }, Self::OutputRelative { scale_x: other_x, scale_y: other_y }) => {
+				scale_x.to_bits() == other_x.to_bits() && scale_y.to_bits() == other_y.to_bits()
+			}
+			_ => false,
+		}
+	}
+}
+
+impl Eq for AttachmentSize {}
+
+impl AttachmentSize {
+	pub const OUTPUT: Self = Self::OutputRelative { scale_x: 1.0, scale_y: 1.0 };
+
+	pub fn absolute(&self) -> Option<(u32, u32)> {
+		match *self {
+			Self::Absolute(width, height) => Some((width, height)),
+			Self::OutputRelative { .. } => None,
+		}
+	}
+}
+
+impl std::hash::Hash for AttachmentSize {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		match *self {
+			Self::Absolute(width, height) => {
+				state.write_u8(0);
+				state.write_u32(width);
+				state.write_u32(height);
+			}
+			Self::OutputRelative { scale_x, scale_y } => {
+				state.write_u8(1);
+				state.write_u32(scale_x.to_bits());
+				state.write_u32(scale_y.to_bits());
+			}
+		}
+	}
+}
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub struct BufferDesc {
 	pub name: &'static str,
@@ -412,35 +1215,78 @@ pub struct RasterPipelineDesc<'a, 'b> {
 	pub name: &'static str,
 	pub vs: &'a Shader,
 	pub ps: Option<&'a Shader>,
-	pub descriptor_layouts: &'b [&'static DescriptorSetInfo],
+	pub gs: Option<&'a Shader>,
+	pub descriptor_layouts: &'b [&'b DescriptorSetInfo],
 	pub render_pass: GraphRenderPassHandle,
 	pub depth_compare_op: Option<DepthCompareOp>,
 	pub depth_write: bool,
+	pub depth_bias: Option<DepthBias>,
+	pub blend_mode: BlendMode,
+	pub face_cull: FaceCullMode,
+	pub push_constant_bytes: usize,
+	pub vertex_input_info: VertexInputInfo,
+	pub polygon_mode: PolygonMode,
+	pub specialization: &'b [(u32, SpecValue)],
+}
+
+// Has to describe the attachments exactly like the pass using the pipeline does, otherwise that pass gets a render pass of its own.
+pub enum PrecompileRenderPass<'b> {
+	Output,
+	Attachments {
+		color_attachments: &'b [AttachmentDesc],
+		depth_attachment: Option<AttachmentDesc>,
+	},
+}
+
+// Everything RasterPipelineDesc has, but without needing a render graph to get a render pass handle from.
+pub struct RasterPipelinePrecompileDesc<'a, 'b> {
+	pub vs: &'a Shader,
+	pub ps: Option<&'a Shader>,
+	pub gs: Option<&'a Shader>,
+	pub descriptor_layouts: &'b [&'b DescriptorSetInfo],
+	pub render_pass: PrecompileRenderPass<'b>,
+	pub depth_compare_op: Option<DepthCompareOp>,
+	pub depth_write: bool,
+	pub depth_bias: Option<DepthBias>,
+	pub blend_mode: BlendMode,
 	pub face_cull: FaceCullMode,
 	pub push_constant_bytes: usize,
 	pub vertex_input_info: VertexInputInfo,
 	pub polygon_mode: PolygonMode,
+	pub specialization: &'b [(u32, SpecValue)],
 }
 
 #[derive(Clone)]
 pub struct ComputePipelineDesc<'a, 'b> {
 	pub name: &'static str,
 	pub cs: &'a Shader,
-	pub descriptor_layouts: &'b [&'static DescriptorSetInfo],
+	pub descriptor_layouts: &'b [&'b DescriptorSetInfo],
+	pub specialization: &'b [(u32, SpecValue)],
+	pub local_size: [u32; 3],
 }
 
 pub enum DescriptorBindingDesc<'a, 'b> {
 	ImportedBuffer(&'a GpuBuffer),
+	PerFrameBuffer(&'a PerFrameBuffer),
 	ImportedTexture(&'a Texture),
+	TextureArray(&'a [&'a Texture]),
 	Buffer(GraphBufferHandle),
 	MutableBuffer(&'b mut MutableGraphBufferHandle),
 	Attachment(GraphAttachmentHandle),
 	MutableAttachment(&'b mut MutableGraphAttachmentHandle),
+	// This frame's swapchain image as a RWTexture2D, see PassBuilder::add_output_storage_binding.
+	OutputStorage(GraphOutputStorageHandle),
+}
+
+#[derive(Clone, Copy)]
+pub enum BufferBindingDesc<'a> {
+	ImportedBuffer(&'a GpuBuffer),
+	Buffer(GraphBufferHandle),
 }
 
 pub struct DescriptorDesc<'a, 'b> {
 	pub name: &'static str,
-	pub descriptor_layout: &'static DescriptorSetInfo,
+	pub descriptor_layout: &'b DescriptorSetInfo,
 	pub bindings: &'b mut [(u32, DescriptorBindingDesc<'a, 'b>)],
 }
 
@@ -449,7 +1295,9 @@ pub enum GraphImportedResource<'a> {
 	Shader(&'a Shader),
 	Mesh(&'a Mesh),
 	Buffer(&'a GpuBuffer),
+	PerFrameBuffer(&'a PerFrameBuffer),
 	Texture(&'a Texture),
+	TextureArray(&'a [&'a Texture]),
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -472,14 +1320,22 @@ pub struct GraphImportedMeshHandle {
 	id: usize,
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum GraphBufferBinding {
+	ImportedBuffer(GraphImportedBufferHandle),
+	Buffer(GraphBufferHandle),
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 enum GraphOwnedResourceDescriptorBinding {
 	ImportedBuffer(GraphImportedBufferHandle),
 	ImportedTexture(GraphImportedTextureHandle),
+	ImportedTextureArray(GraphImportedTextureHandle),
 	Buffer(GraphBufferHandle),
 	MutableBuffer(MutableGraphBufferHandle),
 	Attachment(GraphAttachmentHandle),
 	MutableAttachment(MutableGraphAttachmentHandle),
+	OutputStorage(GraphOutputStorageHandle),
 }
 
 #[derive(Debug, Clone)]
@@ -488,19 +1344,25 @@ enum GraphOwnedResource {
 		name: &'static str,
 		vs: GraphImportedShaderHandle,
 		ps: Option<GraphImportedShaderHandle>,
-		descriptor_layouts: Vec<&'static DescriptorSetInfo>,
+		gs: Option<GraphImportedShaderHandle>,
+		descriptor_layouts: Vec<DescriptorSetBindings>,
 		render_pass: GraphRenderPassHandle,
 		depth_compare_op: Option<DepthCompareOp>,
 		depth_write: bool,
+		depth_bias: Option<DepthBias>,
+		blend_mode: BlendMode,
 		face_cull: FaceCullMode,
 		push_constant_bytes: usize,
 		vertex_input_info: VertexInputInfo,
 		polygon_mode: PolygonMode,
+		specialization: Vec<(u32, SpecValue)>,
 	},
 	ComputePipeline {
 		name: &'static str,
 		cs: GraphImportedShaderHandle,
-		descriptor_layouts: Vec<&'static DescriptorSetInfo>,
+		descriptor_layouts: Vec<DescriptorSetBindings>,
+		specialization: Vec<(u32, SpecValue)>,
+		local_size: [u32; 3],
 	},
 	RenderPass {
 		name: &'static str,
@@ -508,14 +1370,17 @@ enum GraphOwnedResource {
 		depth_attachment: Option<MutableGraphAttachmentHandle>,
 	},
 	OutputRenderPass {},
+	OutputStorage {},
 	Attachment {
 		name: &'static str,
 		width: u32,
 		height: u32,
+		size: AttachmentSize,
 		format: TextureFormat,
 		usage: TextureUsage,
 		load_op: LoadOp,
 		store_op: StoreOp,
+		history: Option<HistoryFrame>,
 	},
 	Buffer {
 		name: &'static str,
@@ -525,16 +1390,60 @@ enum GraphOwnedResource {
 	},
 	GraphicsDescriptorSet {
 		name: &'static str,
-		descriptor_layout: &'static DescriptorSetInfo,
+		descriptor_layout: DescriptorSetBindings,
 		bindings: Vec<(u32, GraphOwnedResourceDescriptorBinding)>,
+		transient: bool,
 	},
 	ComputeDescriptorSet {
 		name: &'static str,
-		descriptor_layout: &'static DescriptorSetInfo,
+		descriptor_layout: DescriptorSetBindings,
 		bindings: Vec<(u32, GraphOwnedResourceDescriptorBinding)>,
+		transient: bool,
 	},
 }
 
+impl GraphOwnedResource {
+	fn name(&self) -> &'static str {
+		match self {
+			GraphOwnedResource::RasterPipeline { name, .. }
+			| GraphOwnedResource::ComputePipeline { name, .. }
+			| GraphOwnedResource::RenderPass { name, .. }
+			| GraphOwnedResource::Attachment { name, .. }
+			| GraphOwnedResource::Buffer { name, .. }
+			| GraphOwnedResource::GraphicsDescriptorSet { name, .. }
+			| GraphOwnedResource::ComputeDescriptorSet { name, .. } => name,
+			GraphOwnedResource::OutputRenderPass {} | GraphOwnedResource::OutputStorage {} => "output",
+		}
+	}
+
+	fn history_key(&self) -> Option<(HistoryAttachmentCacheKey, HistoryFrame)> {
+		match self {
+			&GraphOwnedResource::Attachment {
+				name,
+				width,
+				height,
+				format,
+				usage,
+				history: Some(history),
+				..
+			} => Some((
+				HistoryAttachmentCacheKey {
+					name,
+					attachment: AttachmentCacheKey { width, height, format, usage },
+				},
+				history,
+			)),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HistoryFrame {
+	Previous,
+	Current,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct GraphRasterPipelineHandle {
 	id: usize,
@@ -562,6 +1471,7 @@ pub struct MutableGraphAttachmentHandle {
 	layout: ImageLayout,
 	stage: ash::vk::PipelineStageFlags,
 	access: ash::vk::AccessFlags,
+	usage: TextureUsage,
 }
 
 impl MutableGraphAttachmentHandle {
@@ -577,6 +1487,36 @@ impl MutableGraphAttachmentHandle {
 			final_layout: ImageLayout::ShaderReadOnlyOptimal,
 		}
 	}
+
+	// Same as read, except the attachment ends up in whatever layout the reader asked for.
+	pub fn read_as(self, layout: ImageLayout) -> GraphAttachmentHandle {
+		let shader_stages = ash::vk::PipelineStageFlags::VERTEX_SHADER | ash::vk::PipelineStageFlags::FRAGMENT_SHADER | ash::vk::PipelineStageFlags::COMPUTE_SHADER;
+		let (required_usage, dst_stage, dst_access) = match layout {
+			ImageLayout::ShaderReadOnlyOptimal => (TextureUsage::SAMPLED, shader_stages, ash::vk::AccessFlags::SHADER_READ),
+			ImageLayout::General => (TextureUsage::STORAGE, shader_stages, ash::vk::AccessFlags::SHADER_READ),
+			ImageLayout::TransferSrcOptimal => (TextureUsage::TRANSFER_SRC, ash::vk::PipelineStageFlags::TRANSFER, ash::vk::AccessFlags::TRANSFER_READ),
+			ImageLayout::PresentSrc => (TextureUsage::empty(), ash::vk::PipelineStageFlags::BOTTOM_OF_PIPE, ash::vk::AccessFlags::empty()),
+			_ => panic!("Attachments can't be read as {:?}!", layout),
+		};
+
+		assert!(
+			self.usage.contains(required_usage),
+			"Reading an attachment as {:?} requires {:?} usage, but it only has {:?}!",
+			layout,
+			required_usage,
+			self.usage
+		);
+
+		GraphAttachmentHandle {
+			id: self.id,
+			src_stage: self.stage,
+			src_access: self.access,
+			initial_layout: self.layout,
+			dst_stage,
+			dst_access,
+			final_layout: layout,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -612,7 +1552,12 @@ pub struct GraphRenderPassHandle {
 	id: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct GraphOutputStorageHandle {
+	id: usize,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct GraphGraphicsDescriptorHandle {
 	id: usize,
 }
@@ -640,9 +1585,16 @@ pub struct RecordedPass {
 	cmds: Vec<PassCmd>,
 	read_attachments: HashSet<GraphAttachmentHandle>,
 	write_attachments: HashSet<MutableGraphAttachmentHandle>,
+	written_attachments: HashSet<usize>,
 
 	read_buffers: HashSet<GraphBufferHandle>,
 	write_buffers: HashSet<MutableGraphBufferHandle>,
+
+	// Hazards on imported buffers are resolved against whichever passes touched them before this one.
+	read_imported_buffers: HashSet<GraphImportedBufferHandle>,
+	write_imported_buffers: HashSet<GraphImportedBufferHandle>,
+	shader_write_imported_buffers: HashSet<GraphImportedBufferHandle>,
+	submit_hint: bool,
 }
 
 pub struct RenderGraph<'a> {
@@ -650,7 +1602,12 @@ pub struct RenderGraph<'a> {
 	owned_resources: Vec<GraphOwnedResource>,
 	resource_to_owning_pass: HashMap<usize, PassHandle>,
 	imported_resources: Vec<GraphImportedResource<'a>>,
+	exported_attachments: Vec<MutableGraphAttachmentHandle>,
 	cache: &'a mut RenderGraphCache,
+	default_resources: &'a DefaultResources,
+	resolution_scale: f32,
+	output_policy: OutputPolicy,
+	validation: bool,
 }
 
 struct VirtualToPhysicalResourceMap<T: Copy> {
@@ -670,26 +1627,72 @@ impl<T: Copy> VirtualToPhysicalResourceMap<T> {
 	}
 }
 
+#[derive(Clone, Copy)]
+enum PhysicalDescriptor {
+	Cached(DescriptorHandle),
+	Transient(TransientDescriptor),
+}
+
+enum PhysicalDescriptorRef<'a> {
+	Cached(DescriptorHandle, &'a DescriptorHeap),
+	Transient(TransientDescriptor),
+}
+
 struct GraphPhysicalResourceMap {
 	attachment_map: VirtualToPhysicalResourceMap<usize>,
 	buffer_map: VirtualToPhysicalResourceMap<usize>,
-	descriptor_map: VirtualToPhysicalResourceMap<(DescriptorHandle, &'static DescriptorSetInfo)>,
-	render_pass_map: VirtualToPhysicalResourceMap<usize>,
+	descriptor_map: VirtualToPhysicalResourceMap<PhysicalDescriptor>,
+	render_pass_map: VirtualToPhysicalResourceMap<PhysicalRenderPass>,
 	framebuffer_map: VirtualToPhysicalResourceMap<usize>,
-	raster_pipeline_map: VirtualToPhysicalResourceMap<usize>,
+	raster_pipeline_map: VirtualToPhysicalResourceMap<Option<usize>>,
 	compute_pipeline_map: VirtualToPhysicalResourceMap<usize>,
 }
 
 impl GraphPhysicalResourceMap {
-	fn new(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice, graphics_context: &mut GraphicsContext) -> Self {
-		let attachment_map = Self::alloc_attachments(graph, graphics_device);
-		let buffer_map = Self::alloc_buffers(graph, graphics_device);
+	fn new(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice, graphics_context: &mut GraphicsContext) -> GoldfishResult<Self> {
+		for resource in graph.owned_resources.iter() {
+			let descriptor_layouts = match resource {
+				GraphOwnedResource::RasterPipeline { descriptor_layouts, .. } | GraphOwnedResource::ComputePipeline { descriptor_layouts, .. } => &descriptor_layouts[..],
+				GraphOwnedResource::GraphicsDescriptorSet { descriptor_layout, .. } | GraphOwnedResource::ComputeDescriptorSet { descriptor_layout, .. } => std::slice::from_ref(descriptor_layout),
+				_ => continue,
+			};
+
+			for bindings in descriptor_layouts {
+				graphics_device.check_descriptor_set_support(bindings)?;
+			}
+		}
+
+		// Whoever builds the graph is expected to check GraphicsContext::supports_output_storage and draw the output instead.
+		if !graphics_context.supports_output_storage() && graph.owned_resources.iter().any(|r| matches!(r, GraphOwnedResource::OutputStorage {})) {
+			return Err(GoldfishError::Render(RenderError::UnsupportedFeature("writing to the swapchain from a shader")));
+		}
+
+		graph.cache.frame = graphics_context.frame_index();
+		graph.cache.evict_unused_resources(graphics_device);
+
+		let output_size = graph.output_policy.viewport(graphics_context.output_size()).size();
+		for resource in graph.owned_resources.iter_mut() {
+			if let GraphOwnedResource::Attachment {
+				width,
+				height,
+				size: AttachmentSize::OutputRelative { scale_x, scale_y },
+				..
+			} = resource
+			{
+				let size = relative_output_size(output_size, *scale_x * graph.resolution_scale, *scale_y * graph.resolution_scale);
+				*width = size.width;
+				*height = size.height;
+			}
+		}
+
+		let attachment_map = Self::alloc_attachments(graph, graphics_device)?;
+		let buffer_map = Self::alloc_buffers(graph, graphics_device)?;
 		let descriptor_map = Self::alloc_descriptors(graph, graphics_device, graphics_context, &attachment_map, &buffer_map);
-		let (render_pass_map, framebuffer_map) = Self::alloc_render_passes(graph, graphics_device, &attachment_map);
-		let raster_pipeline_map = Self::alloc_raster_pipelines(graph, graphics_device, graphics_context, &render_pass_map);
-		let compute_pipeline_map = Self::alloc_compute_pipelines(graph, graphics_device);
+		let (render_pass_map, framebuffer_map) = Self::alloc_render_passes(graph, graphics_device, graphics_context, &attachment_map)?;
+		let raster_pipeline_map = Self::alloc_raster_pipelines(graph, graphics_device, graphics_context, &render_pass_map)?;
+		let compute_pipeline_map = Self::alloc_compute_pipelines(graph, graphics_device)?;
 
-		Self {
+		Ok(Self {
 			attachment_map,
 			buffer_map,
 			descriptor_map,
@@ -697,26 +1700,29 @@ impl GraphPhysicalResourceMap {
 			framebuffer_map,
 			raster_pipeline_map,
 			compute_pipeline_map,
-		}
+		})
 	}
 
 	fn get_render_pass<'a>(&self, graph: &'a RenderGraph, render_pass: GraphRenderPassHandle) -> Option<(&'a RenderPass, &'a Framebuffer)> {
-		let physical_render_pass = self.render_pass_map.get_physical(render_pass.id);
-		if physical_render_pass == usize::MAX {
+		let PhysicalRenderPass::Cached(physical_render_pass) = self.render_pass_map.get_physical(render_pass.id) else {
 			return None;
-		}
+		};
 
 		let physical_framebuffer = self.framebuffer_map.get_physical(render_pass.id);
 		Some((
 			&graph.cache.render_pass_cache.render_passes[physical_render_pass],
-			&graph.cache.framebuffer_cache.framebuffers[physical_framebuffer],
+			graph.cache.framebuffer_cache.framebuffers.get(physical_framebuffer),
 		))
 	}
 
 	fn get_raster_pipeline<'a>(&self, graph: &'a RenderGraph, pipeline: GraphRasterPipelineHandle) -> &'a Pipeline {
-		let physical_pipeline = self.raster_pipeline_map.get_physical(pipeline.id);
+		let physical_pipeline = self.raster_pipeline_map.get_physical(pipeline.id).expect("Raster pipeline is still compiling!");
+
+		graph.cache.raster_pipeline_cache.pipelines[physical_pipeline].as_ref().unwrap()
+	}
 
-		&graph.cache.raster_pipeline_cache.pipelines[physical_pipeline]
+	fn is_raster_pipeline_ready(&self, pipeline: GraphRasterPipelineHandle) -> bool {
+		self.raster_pipeline_map.get_physical(pipeline.id).is_some()
 	}
 
 	fn get_compute_pipeline<'a>(&self, graph: &'a RenderGraph, pipeline: GraphComputePipelineHandle) -> &'a Pipeline {
@@ -725,94 +1731,149 @@ impl GraphPhysicalResourceMap {
 		&graph.cache.compute_pipeline_cache.pipelines[physical_pipeline]
 	}
 
-	fn get_graphics_descriptor<'a>(&self, graph: &'a RenderGraph, descriptor: GraphGraphicsDescriptorHandle) -> (DescriptorHandle, &'a DescriptorHeap) {
-		let (descriptor, info) = self.descriptor_map.get_physical(descriptor.id);
-		(descriptor, graph.cache.get_graphics_descriptor_heap(info))
+	fn get_graphics_descriptor<'a>(&self, graph: &'a RenderGraph, descriptor: GraphGraphicsDescriptorHandle) -> PhysicalDescriptorRef<'a> {
+		match self.descriptor_map.get_physical(descriptor.id) {
+			PhysicalDescriptor::Cached(handle) => match &graph.owned_resources[descriptor.id] {
+				GraphOwnedResource::GraphicsDescriptorSet { descriptor_layout, .. } => PhysicalDescriptorRef::Cached(handle, graph.cache.get_graphics_descriptor_heap(descriptor_layout)),
+				_ => unreachable!("Invalid graphics descriptor handle!"),
+			},
+			PhysicalDescriptor::Transient(descriptor) => PhysicalDescriptorRef::Transient(descriptor),
+		}
 	}
 
-	fn get_compute_descriptor<'a>(&self, graph: &'a RenderGraph, descriptor: GraphComputeDescriptorHandle) -> (DescriptorHandle, &'a DescriptorHeap) {
-		let (descriptor, info) = self.descriptor_map.get_physical(descriptor.id);
-		(descriptor, graph.cache.get_compute_descriptor_heap(info))
+	fn get_compute_descriptor<'a>(&self, graph: &'a RenderGraph, descriptor: GraphComputeDescriptorHandle) -> PhysicalDescriptorRef<'a> {
+		match self.descriptor_map.get_physical(descriptor.id) {
+			PhysicalDescriptor::Cached(handle) => match &graph.owned_resources[descriptor.id] {
+				GraphOwnedResource::ComputeDescriptorSet { descriptor_layout, .. } => PhysicalDescriptorRef::Cached(handle, graph.cache.get_compute_descriptor_heap(descriptor_layout)),
+				_ => unreachable!("Invalid compute descriptor handle!"),
+			},
+			PhysicalDescriptor::Transient(descriptor) => PhysicalDescriptorRef::Transient(descriptor),
+		}
 	}
 
 	fn get_attachment<'a>(&self, graph: &'a RenderGraph, attachment: GraphAttachmentHandle) -> &'a Texture {
 		let physical_attachment = self.attachment_map.get_physical(attachment.id);
 
-		&graph.cache.attachment_cache.attachments[physical_attachment]
+		graph.cache.attachment_cache.attachments.get(physical_attachment)
 	}
 
 	fn get_mutable_attachment<'a>(&self, graph: &'a RenderGraph, attachment: MutableGraphAttachmentHandle) -> &'a Texture {
 		let physical_attachment = self.attachment_map.get_physical(attachment.id);
 
-		&graph.cache.attachment_cache.attachments[physical_attachment]
+		graph.cache.attachment_cache.attachments.get(physical_attachment)
 	}
 
 	fn get_buffer<'a>(&self, graph: &'a RenderGraph, buffer: GraphBufferHandle) -> &'a GpuBuffer {
 		let physical_buffer = self.buffer_map.get_physical(buffer.id);
 
-		&graph.cache.buffer_cache.buffers[physical_buffer]
+		graph.cache.buffer_cache.buffers.get(physical_buffer)
+	}
+
+	fn get_mutable_buffer<'a>(&self, graph: &'a RenderGraph, buffer: MutableGraphBufferHandle) -> &'a GpuBuffer {
+		let physical_buffer = self.buffer_map.get_physical(buffer.id);
+
+		graph.cache.buffer_cache.buffers.get(physical_buffer)
 	}
 
-	fn alloc_attachments(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> VirtualToPhysicalResourceMap<usize> {
-		let mut attachment_type_to_virtual = HashMap::<AttachmentCacheKey, Vec<usize>>::new();
+	fn get_bound_buffer<'a>(&self, graph: &'a RenderGraph, buffer: GraphBufferBinding) -> &'a GpuBuffer {
+		match buffer {
+			GraphBufferBinding::ImportedBuffer(buffer) => match graph.imported_resources[buffer.id] {
+				GraphImportedResource::Buffer(buffer) => buffer,
+				_ => unreachable!("Invalid imported buffer!"),
+			},
+			GraphBufferBinding::Buffer(buffer) => self.get_buffer(graph, buffer),
+		}
+	}
+
+	fn alloc_attachments(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> GoldfishResult<VirtualToPhysicalResourceMap<usize>> {
+		let mut attachment_type_to_virtual = HashMap::<AttachmentCacheKey, Vec<(usize, &'static str)>>::new();
 
 		for (i, resource) in graph.owned_resources.iter().enumerate() {
 			match resource {
-				&GraphOwnedResource::Attachment { width, height, format, usage, .. } => {
+				&GraphOwnedResource::Attachment {
+					name,
+					width,
+					height,
+					format,
+					usage,
+					history: None,
+					..
+				} => {
 					let key = AttachmentCacheKey { width, height, format, usage };
 
-					attachment_type_to_virtual.entry(key).or_default().push(i);
+					attachment_type_to_virtual.entry(key).or_default().push((i, name));
 				}
 				_ => {}
 			}
 		}
 
 		for (key, virtual_resources) in attachment_type_to_virtual.iter() {
-			graph.cache.alloc_attachments(graphics_device, key, virtual_resources.len());
+			let names = virtual_resources.iter().map(|&(_, name)| name).collect::<Vec<_>>();
+			graph.cache.alloc_attachments(graphics_device, key, &names)?;
 		}
 
 		let mut attachment_map = VirtualToPhysicalResourceMap::new();
 
 		// TODO(Brandon): Optimize this by mapping virtual to physical attachments based on existing framebuffers and descriptors to reduce allocations.
 		for (key, virtual_resources) in attachment_type_to_virtual {
-			for (i, virtual_resource) in virtual_resources.into_iter().enumerate() {
+			for (i, (virtual_resource, _)) in virtual_resources.into_iter().enumerate() {
 				let index = graph.cache.attachment_cache.cache[&key][i];
+				graph.cache.attachment_cache.attachments.mark_used(index, graph.cache.frame);
 				attachment_map.map_physical(virtual_resource, index);
 			}
 		}
 
-		attachment_map
+		for (i, resource) in graph.owned_resources.iter().enumerate() {
+			if let Some((key, history)) = resource.history_key() {
+				graph.cache.alloc_history_attachment(graphics_device, &key)?;
+
+				let history_attachment = &graph.cache.attachment_cache.history[&key];
+				let index = match history {
+					HistoryFrame::Previous => history_attachment.attachments[history_attachment.previous],
+					HistoryFrame::Current => history_attachment.attachments[1 - history_attachment.previous],
+				};
+
+				for index in history_attachment.attachments {
+					graph.cache.attachment_cache.attachments.mark_used(index, graph.cache.frame);
+				}
+				attachment_map.map_physical(i, index);
+			}
+		}
+
+		Ok(attachment_map)
 	}
 
-	fn alloc_buffers(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> VirtualToPhysicalResourceMap<usize> {
-		let mut buffer_type_to_virtual = HashMap::<BufferCacheKey, Vec<usize>>::new();
+	fn alloc_buffers(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> GoldfishResult<VirtualToPhysicalResourceMap<usize>> {
+		let mut buffer_type_to_virtual = HashMap::<BufferCacheKey, Vec<(usize, &'static str)>>::new();
 
 		for (i, resource) in graph.owned_resources.iter().enumerate() {
 			match resource {
-				&GraphOwnedResource::Buffer { size, usage, location, .. } => {
+				&GraphOwnedResource::Buffer { name, size, usage, location } => {
 					let key = BufferCacheKey { size, usage, location };
 
-					buffer_type_to_virtual.entry(key).or_default().push(i);
+					buffer_type_to_virtual.entry(key).or_default().push((i, name));
 				}
 				_ => {}
 			}
 		}
 
 		for (key, virtual_resources) in buffer_type_to_virtual.iter() {
-			graph.cache.alloc_buffers(graphics_device, key, virtual_resources.len());
+			let names = virtual_resources.iter().map(|&(_, name)| name).collect::<Vec<_>>();
+			graph.cache.alloc_buffers(graphics_device, key, &names)?;
 		}
 
 		let mut buffer_map = VirtualToPhysicalResourceMap::new();
 
 		// TODO(Brandon): Optimize this by mapping virtual to physical attachments based on existing framebuffers and descriptors to reduce allocations.
 		for (key, virtual_resources) in buffer_type_to_virtual {
-			for (i, virtual_resource) in virtual_resources.into_iter().enumerate() {
+			for (i, (virtual_resource, _)) in virtual_resources.into_iter().enumerate() {
 				let index = graph.cache.buffer_cache.cache[&key][i];
+				graph.cache.buffer_cache.buffers.mark_used(index, graph.cache.frame);
 				buffer_map.map_physical(virtual_resource, index);
 			}
 		}
 
-		buffer_map
+		Ok(buffer_map)
 	}
 
 	fn alloc_descriptors(
@@ -821,8 +1882,9 @@ impl GraphPhysicalResourceMap {
 		graphics_context: &mut GraphicsContext,
 		attachment_map: &VirtualToPhysicalResourceMap<usize>,
 		buffer_map: &VirtualToPhysicalResourceMap<usize>,
-	) -> VirtualToPhysicalResourceMap<(DescriptorHandle, &'static DescriptorSetInfo)> {
+	) -> VirtualToPhysicalResourceMap<PhysicalDescriptor> {
 		let mut descriptor_map = VirtualToPhysicalResourceMap::new();
+		let output_storage_view = graphics_context.supports_output_storage().then(|| graphics_context.output_storage_texture().image_view);
 		for (id, resource) in graph.owned_resources.iter().enumerate() {
 			let get_key_bindings = |bindings: &Vec<(u32, GraphOwnedResourceDescriptorBinding)>| {
 				bindings
@@ -833,6 +1895,7 @@ impl GraphPhysicalResourceMap {
 							match binding {
 								GraphOwnedResourceDescriptorBinding::ImportedBuffer(buffer) => match &graph.imported_resources[buffer.id] {
 									GraphImportedResource::Buffer(buffer) => DescriptorHeapCacheKeyBinding::ImportedBuffer { buffer: buffer.raw },
+									GraphImportedResource::PerFrameBuffer(buffer) => DescriptorHeapCacheKeyBinding::ImportedBuffer { buffer: buffer.gpu_buffer(0).raw },
 									_ => unreachable!("Invalid buffer handle!"),
 								},
 								GraphOwnedResourceDescriptorBinding::ImportedTexture(texture) => match &graph.imported_resources[texture.id] {
@@ -843,17 +1906,44 @@ impl GraphPhysicalResourceMap {
 									},
 									_ => unreachable!("Invalid texture handle!"),
 								},
-								GraphOwnedResourceDescriptorBinding::Buffer(buffer) => DescriptorHeapCacheKeyBinding::Buffer {
-									buffer: buffer_map.get_physical(buffer.id),
-								},
-								GraphOwnedResourceDescriptorBinding::MutableBuffer(buffer) => DescriptorHeapCacheKeyBinding::Buffer {
-									buffer: buffer_map.get_physical(buffer.id),
-								},
-								GraphOwnedResourceDescriptorBinding::Attachment(attachment) => DescriptorHeapCacheKeyBinding::Attachment {
-									attachment: attachment_map.get_physical(attachment.id),
+								GraphOwnedResourceDescriptorBinding::ImportedTextureArray(textures) => match &graph.imported_resources[textures.id] {
+									GraphImportedResource::TextureArray(textures) => DescriptorHeapCacheKeyBinding::ImportedTextureArray {
+										image_views: textures.iter().map(|texture| texture.image_view).collect(),
+									},
+									_ => unreachable!("Invalid texture array handle!"),
 								},
-								GraphOwnedResourceDescriptorBinding::MutableAttachment(attachment) => DescriptorHeapCacheKeyBinding::Attachment {
-									attachment: attachment_map.get_physical(attachment.id),
+								GraphOwnedResourceDescriptorBinding::Buffer(buffer) => {
+									let buffer = buffer_map.get_physical(buffer.id);
+									DescriptorHeapCacheKeyBinding::Buffer {
+										buffer,
+										generation: graph.cache.buffer_cache.buffers.generation(buffer),
+									}
+								}
+								GraphOwnedResourceDescriptorBinding::MutableBuffer(buffer) => {
+									let buffer = buffer_map.get_physical(buffer.id);
+									DescriptorHeapCacheKeyBinding::Buffer {
+										buffer,
+										generation: graph.cache.buffer_cache.buffers.generation(buffer),
+									}
+								}
+								GraphOwnedResourceDescriptorBinding::Attachment(handle) => {
+									let attachment = attachment_map.get_physical(handle.id);
+									DescriptorHeapCacheKeyBinding::Attachment {
+										attachment,
+										generation: graph.cache.attachment_cache.attachments.generation(attachment),
+										layout: handle.final_layout,
+									}
+								}
+								GraphOwnedResourceDescriptorBinding::MutableAttachment(handle) => {
+									let attachment = attachment_map.get_physical(handle.id);
+									DescriptorHeapCacheKeyBinding::Attachment {
+										attachment,
+										generation: graph.cache.attachment_cache.attachments.generation(attachment),
+										layout: handle.layout,
+									}
+								}
+								GraphOwnedResourceDescriptorBinding::OutputStorage(_) => DescriptorHeapCacheKeyBinding::OutputStorage {
+									image_view: output_storage_view.unwrap(),
 								},
 							},
 						)
@@ -866,15 +1956,15 @@ impl GraphPhysicalResourceMap {
 				graphics_context: &mut GraphicsContext,
 				attachment_map: &VirtualToPhysicalResourceMap<usize>,
 				buffer_map: &VirtualToPhysicalResourceMap<usize>,
+				name: &str,
 				bindings: &Vec<(u32, GraphOwnedResourceDescriptorBinding)>,
-				descriptor_heap: &DescriptorHeap,
-				descriptor: &DescriptorHandle,
-				descriptor_layout: &'static DescriptorSetInfo,
+				descriptor: PhysicalDescriptorRef,
+				descriptor_layout: &DescriptorSetBindings,
 			) {
 				// Update the descriptor set with the appropriate data.
-				// TODO(Brandon): We should first check to make sure that we actually need to do this before we do so to prevent unnecessary vkUpdateDescriptorSet calls.
+				let frame_slot = graphics_context.frame_slot();
 
-				let buffers = bindings
+				let mut buffers = bindings
 					.iter()
 					.filter(|(_, ty)| match ty {
 						GraphOwnedResourceDescriptorBinding::ImportedBuffer(..) => true,
@@ -887,59 +1977,198 @@ impl GraphPhysicalResourceMap {
 							match buffer {
 								GraphOwnedResourceDescriptorBinding::ImportedBuffer(buffer) => match graph.imported_resources[buffer.id] {
 									GraphImportedResource::Buffer(buffer) => buffer,
+									GraphImportedResource::PerFrameBuffer(buffer) => buffer.gpu_buffer(frame_slot),
 									_ => unreachable!("Invalid imported buffer!"),
 								},
-								GraphOwnedResourceDescriptorBinding::Buffer(buffer) => &graph.cache.buffer_cache.buffers[buffer_map.get_physical(buffer.id)],
+								GraphOwnedResourceDescriptorBinding::Buffer(buffer) => graph.cache.buffer_cache.buffers.get(buffer_map.get_physical(buffer.id)),
 								_ => unreachable!(),
 							},
 						)
 					})
 					.collect::<Vec<_>>();
 
-				let images = bindings
+				let mut images = bindings
 					.iter()
 					.filter(|(_, ty)| match ty {
+						GraphOwnedResourceDescriptorBinding::ImportedTexture(..) => true,
 						GraphOwnedResourceDescriptorBinding::Attachment(..) => true,
 						GraphOwnedResourceDescriptorBinding::MutableAttachment(..) => true,
+						GraphOwnedResourceDescriptorBinding::OutputStorage(..) => true,
 						_ => false,
 					})
 					.map(|(binding, image)| match image {
+						GraphOwnedResourceDescriptorBinding::ImportedTexture(texture) => match graph.imported_resources[texture.id] {
+							GraphImportedResource::Texture(texture) => (*binding, texture, texture.sampled_layout),
+							_ => unreachable!("Invalid imported texture!"),
+						},
 						GraphOwnedResourceDescriptorBinding::Attachment(attachment) => {
-							let physical_attachment = &graph.cache.attachment_cache.attachments[attachment_map.get_physical(attachment.id)];
+							let physical_attachment = graph.cache.attachment_cache.attachments.get(attachment_map.get_physical(attachment.id));
 
 							(*binding, physical_attachment, attachment.final_layout)
 						}
 						GraphOwnedResourceDescriptorBinding::MutableAttachment(attachment) => {
-							let physical_attachment = &graph.cache.attachment_cache.attachments[attachment_map.get_physical(attachment.id)];
+							let physical_attachment = graph.cache.attachment_cache.attachments.get(attachment_map.get_physical(attachment.id));
 
 							(*binding, physical_attachment, attachment.layout)
 						}
+						GraphOwnedResourceDescriptorBinding::OutputStorage(_) => (*binding, graphics_context.output_storage_texture(), ImageLayout::General),
 						_ => unreachable!(),
 					})
 					.collect::<Vec<_>>();
 
-				graphics_context.update_descriptor(&buffers, &images, descriptor_layout, descriptor_heap, &descriptor);
+				let mut image_arrays = bindings
+					.iter()
+					.filter_map(|(binding, ty)| match ty {
+						GraphOwnedResourceDescriptorBinding::ImportedTextureArray(textures) => match graph.imported_resources[textures.id] {
+							GraphImportedResource::TextureArray(textures) => {
+								assert!(
+									textures.iter().all(|texture| texture.sampled_layout == ImageLayout::ShaderReadOnlyOptimal),
+									"Textures in an array must be sampled in ImageLayout::ShaderReadOnlyOptimal!"
+								);
+								Some((*binding, textures.to_vec()))
+							}
+							_ => unreachable!("Invalid imported texture array!"),
+						},
+						_ => None,
+					})
+					.collect::<Vec<_>>();
+
+				if graph.validation {
+					for (binding, buffer) in buffers.iter() {
+						validate_buffer_binding(name, *binding, descriptor_layout.get(*binding), buffer.usage);
+					}
+					for (binding, texture, _) in images.iter() {
+						validate_texture_binding(name, *binding, descriptor_layout.get(*binding), texture.usage);
+					}
+				}
+
+				for (binding, textures) in image_arrays.iter_mut() {
+					let binding_type = descriptor_layout.get(*binding).expect("Texture array bound to a binding that isn't in the layout!");
+					if let DescriptorBindingType::Texture2DArray { count } = binding_type {
+						let default = graph.default_resources.texture_for(binding_type).unwrap();
+						textures.resize(textures.len().max(count as usize), default);
+					}
+				}
+
+				// Anything the pass didn't bind falls back to the engine's default resources so that we never leave a binding with garbage in it.
+				for (binding, binding_type) in descriptor_layout.iter() {
+					if bindings.iter().any(|(i, _)| *i == binding) {
+						continue;
+					}
+
+					if binding_type.is_bindless() {
+						continue;
+					}
+
+					if let DescriptorBindingType::Texture2DArray { count } = binding_type {
+						image_arrays.push((binding, vec![graph.default_resources.texture_for(binding_type).unwrap(); count as usize]));
+					} else if let Some(texture) = graph.default_resources.texture_for(binding_type) {
+						images.push((binding, texture, ImageLayout::ShaderReadOnlyOptimal));
+					} else if let Some(buffer) = graph.default_resources.buffer_for(binding_type) {
+						buffers.push((binding, buffer));
+					} else {
+						panic!("Binding {} of type {:?} was not provided and has no default resource!", binding, binding_type);
+					}
+				}
+
+				let image_arrays = image_arrays
+					.iter()
+					.map(|(binding, textures)| (*binding, &textures[..], ImageLayout::ShaderReadOnlyOptimal))
+					.collect::<Vec<_>>();
+
+				match descriptor {
+					PhysicalDescriptorRef::Cached(descriptor, descriptor_heap) => graphics_context.update_descriptor(&buffers, &images, &image_arrays, descriptor_layout, descriptor_heap, &descriptor),
+					PhysicalDescriptorRef::Transient(descriptor) => graphics_context.update_transient_descriptor(&buffers, &images, &image_arrays, descriptor_layout, &descriptor),
+				}
 			}
 			match resource {
-				GraphOwnedResource::GraphicsDescriptorSet { descriptor_layout, bindings, .. } => {
+				GraphOwnedResource::GraphicsDescriptorSet {
+					name,
+					descriptor_layout,
+					bindings,
+					transient: true,
+				} => {
+					let layout = graphics_device.get_graphics_layout(&mut graph.cache.descriptor_layout_cache, descriptor_layout);
+					let descriptor = graphics_context.alloc_transient_descriptor(layout, descriptor_layout);
+					update_descriptor(
+						graph,
+						graphics_context,
+						attachment_map,
+						buffer_map,
+						name,
+						bindings,
+						PhysicalDescriptorRef::Transient(descriptor),
+						descriptor_layout,
+					);
+
+					descriptor_map.map_physical(id, PhysicalDescriptor::Transient(descriptor));
+				}
+				GraphOwnedResource::GraphicsDescriptorSet {
+					name, descriptor_layout, bindings, ..
+				} => {
 					let key_bindings = get_key_bindings(bindings);
 					let key = DescriptorHeapCacheKey { bindings: key_bindings };
 
-					let descriptor = graph.cache.alloc_graphics_descriptor(graphics_device, descriptor_layout, &key);
-					let descriptor_heap = &graph.cache.get_graphics_descriptor_heap(descriptor_layout);
-					update_descriptor(graph, graphics_context, attachment_map, buffer_map, bindings, descriptor_heap, &descriptor, descriptor_layout);
+					let (descriptor, needs_write) = graph.cache.alloc_graphics_descriptor(graphics_device, descriptor_layout, &key, graphics_context.frame_slot());
+					if needs_write {
+						let descriptor_heap = &graph.cache.get_graphics_descriptor_heap(descriptor_layout);
+						update_descriptor(
+							graph,
+							graphics_context,
+							attachment_map,
+							buffer_map,
+							name,
+							bindings,
+							PhysicalDescriptorRef::Cached(descriptor, descriptor_heap),
+							descriptor_layout,
+						);
+					}
 
-					descriptor_map.map_physical(id, (descriptor, *descriptor_layout));
+					descriptor_map.map_physical(id, PhysicalDescriptor::Cached(descriptor));
+				}
+				GraphOwnedResource::ComputeDescriptorSet {
+					name,
+					descriptor_layout,
+					bindings,
+					transient: true,
+				} => {
+					let layout = graphics_device.get_compute_layout(&mut graph.cache.descriptor_layout_cache, descriptor_layout);
+					let descriptor = graphics_context.alloc_transient_descriptor(layout, descriptor_layout);
+					update_descriptor(
+						graph,
+						graphics_context,
+						attachment_map,
+						buffer_map,
+						name,
+						bindings,
+						PhysicalDescriptorRef::Transient(descriptor),
+						descriptor_layout,
+					);
+
+					descriptor_map.map_physical(id, PhysicalDescriptor::Transient(descriptor));
 				}
-				GraphOwnedResource::ComputeDescriptorSet { descriptor_layout, bindings, .. } => {
+				GraphOwnedResource::ComputeDescriptorSet {
+					name, descriptor_layout, bindings, ..
+				} => {
 					let key_bindings = get_key_bindings(bindings);
 					let key = DescriptorHeapCacheKey { bindings: key_bindings };
 
-					let descriptor = graph.cache.alloc_compute_descriptor(graphics_device, descriptor_layout, &key);
-					let descriptor_heap = &graph.cache.get_compute_descriptor_heap(descriptor_layout);
-					update_descriptor(graph, graphics_context, attachment_map, buffer_map, bindings, descriptor_heap, &descriptor, descriptor_layout);
+					let (descriptor, needs_write) = graph.cache.alloc_compute_descriptor(graphics_device, descriptor_layout, &key, graphics_context.frame_slot());
+					if needs_write {
+						let descriptor_heap = &graph.cache.get_compute_descriptor_heap(descriptor_layout);
+						update_descriptor(
+							graph,
+							graphics_context,
+							attachment_map,
+							buffer_map,
+							name,
+							bindings,
+							PhysicalDescriptorRef::Cached(descriptor, descriptor_heap),
+							descriptor_layout,
+						);
+					}
 
-					descriptor_map.map_physical(id, (descriptor, *descriptor_layout));
+					descriptor_map.map_physical(id, PhysicalDescriptor::Cached(descriptor));
 				}
 				_ => {}
 			}
@@ -951,8 +2180,9 @@ impl GraphPhysicalResourceMap {
 	fn alloc_render_passes(
 		graph: &mut RenderGraph,
 		graphics_device: &mut GraphicsDevice,
+		graphics_context: &GraphicsContext,
 		attachment_map: &VirtualToPhysicalResourceMap<usize>,
-	) -> (VirtualToPhysicalResourceMap<usize>, VirtualToPhysicalResourceMap<usize>) {
+	) -> GoldfishResult<(VirtualToPhysicalResourceMap<PhysicalRenderPass>, VirtualToPhysicalResourceMap<usize>)> {
 		let mut render_pass_map = VirtualToPhysicalResourceMap::new();
 		let mut framebuffer_map = VirtualToPhysicalResourceMap::new();
 
@@ -969,8 +2199,7 @@ impl GraphPhysicalResourceMap {
 								usage,
 								load_op,
 								store_op,
-								// TODO(Brandon): In the future we might need to support other layout transitions in case we want to write to an attachment that was previously read.
-								initial_layout: ImageLayout::Undefined,
+								initial_layout: if load_op == LoadOp::Load { handle.layout } else { ImageLayout::Undefined },
 								final_layout: handle.layout,
 							},
 							_ => unreachable!(),
@@ -983,7 +2212,7 @@ impl GraphPhysicalResourceMap {
 							usage,
 							load_op,
 							store_op,
-							initial_layout: ImageLayout::Undefined,
+							initial_layout: if load_op == LoadOp::Load { handle.layout } else { ImageLayout::Undefined },
 							final_layout: handle.layout,
 						}),
 						_ => unreachable!(),
@@ -994,38 +2223,30 @@ impl GraphPhysicalResourceMap {
 						depth_attachment_desc,
 					};
 
-					let render_pass = graph.cache.alloc_render_pass(graphics_device, &render_pass_key);
+					let render_pass = graph.cache.alloc_render_pass(graphics_device, &render_pass_key)?;
 
-					let width = color_attachments
+					let sizes = color_attachments
 						.iter()
+						.chain(depth_attachment.iter())
 						.map(|a| match &graph.owned_resources[a.id] {
-							&GraphOwnedResource::Attachment { width, .. } => width,
+							&GraphOwnedResource::Attachment { name, width, height, .. } => (name, width, height),
 							_ => unreachable!(),
 						})
-						.chain(depth_attachment.into_iter().map(|d| match &graph.owned_resources[d.id] {
-							&GraphOwnedResource::Attachment { width, .. } => width,
-							_ => unreachable!(),
-						}))
-						.min()
-						.unwrap_or(0);
+						.collect::<Vec<_>>();
 
-					let height = color_attachments
-						.iter()
-						.map(|a| match &graph.owned_resources[a.id] {
-							&GraphOwnedResource::Attachment { height, .. } => height,
-							_ => unreachable!(),
-						})
-						.chain(depth_attachment.into_iter().map(|d| match &graph.owned_resources[d.id] {
-							&GraphOwnedResource::Attachment { height, .. } => height,
-							_ => unreachable!(),
-						}))
-						.min()
-						.unwrap_or(0);
+					let (_, width, height) = sizes.first().copied().unwrap_or(("", 0, 0));
+					assert!(
+						sizes.iter().all(|&(_, w, h)| w == width && h == height),
+						"Render pass {} mixes attachments of different sizes! {:?}",
+						resource.name(),
+						sizes
+					);
 
 					let mut attachments = color_attachments.iter().map(|a| attachment_map.get_physical(a.id)).collect::<Vec<_>>();
 					if let Some(a) = depth_attachment {
 						attachments.push(attachment_map.get_physical(a.id));
 					}
+					let attachments = attachments.into_iter().map(|a| (a, graph.cache.attachment_cache.attachments.generation(a))).collect();
 
 					let framebuffer_key = FramebufferCacheKey {
 						width,
@@ -1037,40 +2258,51 @@ impl GraphPhysicalResourceMap {
 					let framebuffer = graph.cache.alloc_framebuffer(graphics_device, &framebuffer_key);
 
 					// NOTE(Brandon): Framebuffer and render pass resources are internally bound on the same virtual index.
-					render_pass_map.map_physical(id, render_pass);
+					render_pass_map.map_physical(id, PhysicalRenderPass::Cached(render_pass));
 					framebuffer_map.map_physical(id, framebuffer);
 				}
 				GraphOwnedResource::OutputRenderPass {} => {
-					render_pass_map.map_physical(id, usize::MAX);
+					render_pass_map.map_physical(
+						id,
+						PhysicalRenderPass::Output {
+							generation: graphics_context.get_output_generation(),
+						},
+					);
 				}
 				_ => {}
 			}
 		}
 
-		(render_pass_map, framebuffer_map)
+		Ok((render_pass_map, framebuffer_map))
 	}
 
 	fn alloc_raster_pipelines(
 		graph: &mut RenderGraph,
 		graphics_device: &mut GraphicsDevice,
 		graphics_context: &mut GraphicsContext,
-		render_pass_map: &VirtualToPhysicalResourceMap<usize>,
-	) -> VirtualToPhysicalResourceMap<usize> {
+		render_pass_map: &VirtualToPhysicalResourceMap<PhysicalRenderPass>,
+	) -> GoldfishResult<VirtualToPhysicalResourceMap<Option<usize>>> {
 		let mut pipeline_map = VirtualToPhysicalResourceMap::new();
 
+		graph.cache.evict_stale_output_pipelines(graphics_device, graphics_context.get_output_generation());
+
 		for (id, resource) in graph.owned_resources.iter().enumerate() {
 			match resource {
 				GraphOwnedResource::RasterPipeline {
 					vs,
 					ps,
+					gs,
 					descriptor_layouts,
 					render_pass,
 					depth_compare_op,
 					depth_write,
+					depth_bias,
+					blend_mode,
 					face_cull,
 					push_constant_bytes,
 					vertex_input_info,
 					polygon_mode,
+					specialization,
 					..
 				} => {
 					// TODO(Brandon): Definitely don't do it like this, this is a hack to get the raw pointer
@@ -1088,6 +2320,15 @@ impl GraphPhysicalResourceMap {
 						None
 					};
 
+					let gs = if let Some(gs) = gs {
+						match &graph.imported_resources[gs.id] {
+							GraphImportedResource::Shader(shader) => Some(shader.module),
+							_ => panic!("Invalid geometry shader handle!"),
+						}
+					} else {
+						None
+					};
+
 					let descriptor_layouts = descriptor_layouts
 						.into_iter()
 						.map(|info| graph.cache.register_graphics_descriptor_layout(graphics_device, info))
@@ -1098,32 +2339,44 @@ impl GraphPhysicalResourceMap {
 					let key = RasterPipelineCacheKey {
 						vs,
 						ps,
+						gs,
 						render_pass,
 						descriptor_layouts,
 						depth_compare_op: *depth_compare_op,
 						depth_write: *depth_write,
+						depth_bias: *depth_bias,
+						blend_mode: *blend_mode,
 						face_cull: *face_cull,
 						push_constant_bytes: *push_constant_bytes,
 						vertex_input_info: *vertex_input_info,
 						polygon_mode: *polygon_mode,
+						specialization: specialization.clone(),
 					};
 
-					let pipeline = graph.cache.alloc_raster_pipeline(graphics_context, graphics_device, &key);
+					let pipeline = match graph.cache.alloc_raster_pipeline(graphics_context, graphics_device, &key) {
+						Ok(pipeline) => pipeline,
+						Err(err) => Some(graph.cache.fail_raster_pipeline(graphics_context, graphics_device, graph.default_resources, &key, err)?),
+					};
 					pipeline_map.map_physical(id, pipeline);
 				}
 				_ => {}
 			}
 		}
 
-		pipeline_map
+		Ok(pipeline_map)
 	}
 
-	fn alloc_compute_pipelines(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> VirtualToPhysicalResourceMap<usize> {
+	fn alloc_compute_pipelines(graph: &mut RenderGraph, graphics_device: &mut GraphicsDevice) -> GoldfishResult<VirtualToPhysicalResourceMap<usize>> {
 		let mut pipeline_map = VirtualToPhysicalResourceMap::new();
 
 		for (id, resource) in graph.owned_resources.iter().enumerate() {
 			match resource {
-				GraphOwnedResource::ComputePipeline { cs, descriptor_layouts, .. } => {
+				GraphOwnedResource::ComputePipeline {
+					cs,
+					descriptor_layouts,
+					specialization,
+					..
+				} => {
 					// TODO(Brandon): Definitely don't do it like this, this is a hack to get the raw pointer
 					let cs = match &graph.imported_resources[cs.id] {
 						GraphImportedResource::Shader(shader) => shader.module,
@@ -1135,30 +2388,86 @@ impl GraphPhysicalResourceMap {
 						.map(|info| graph.cache.register_compute_descriptor_layout(graphics_device, info))
 						.collect::<Vec<_>>();
 
-					let key = ComputePipelineCacheKey { cs, descriptor_layouts };
+					let key = ComputePipelineCacheKey {
+						cs,
+						descriptor_layouts,
+						specialization: specialization.clone(),
+					};
 
-					let pipeline = graph.cache.alloc_compute_pipeline(graphics_device, &key);
+					let pipeline = graph.cache.alloc_compute_pipeline(graphics_device, &key)?;
 					pipeline_map.map_physical(id, pipeline);
 				}
 				_ => {}
 			}
 		}
 
-		pipeline_map
+		Ok(pipeline_map)
+	}
+}
+
+impl Frame<'_> {
+	pub fn execute_graph(&mut self, graph: RenderGraph, graphics_device: &mut GraphicsDevice) -> GoldfishResult<()> {
+		let result = graph.execute(self, graphics_device);
+		if result.is_err() {
+			self.discard();
+		}
+
+		result
 	}
 }
 
 impl<'a> RenderGraph<'a> {
-	pub fn new(cache: &'a mut RenderGraphCache) -> Self {
+	pub fn new(cache: &'a mut RenderGraphCache, default_resources: &'a DefaultResources) -> Self {
 		Self {
 			passes: Default::default(),
 			owned_resources: Default::default(),
 			resource_to_owning_pass: Default::default(),
 			imported_resources: Default::default(),
+			exported_attachments: Default::default(),
 			cache,
+			default_resources,
+			resolution_scale: 1.0,
+			output_policy: OutputPolicy::Stretch,
+			validation: cfg!(debug_assertions),
 		}
 	}
 
+	// Scales every attachment sized OutputRelative on top of its own scale, usually ResolutionScaler::current_scale.
+	pub fn set_resolution_scale(&mut self, resolution_scale: f32) {
+		assert!(resolution_scale > 0.0, "Resolution scale has to be positive!");
+		self.resolution_scale = resolution_scale;
+	}
+
+	pub fn resolution_scale(&self) -> f32 {
+		self.resolution_scale
+	}
+
+	// The output render pass is drawn inside of the policy's viewport with the bars cleared to its bar color.
+	pub fn set_output_policy(&mut self, output_policy: OutputPolicy) {
+		self.output_policy = output_policy;
+	}
+
+	pub fn output_policy(&self) -> OutputPolicy {
+		self.output_policy
+	}
+
+	pub fn set_validation(&mut self, enabled: bool) {
+		self.validation = enabled;
+	}
+
+	// Keeps the pass that wrote the attachment alive and makes it available through RenderGraphCache::exported_attachment.
+	// Must be called after the attachment was last written.
+	pub fn export_attachment(&mut self, attachment: MutableGraphAttachmentHandle) {
+		let usage = match &self.owned_resources[attachment.id] {
+			GraphOwnedResource::Attachment { usage, .. } => *usage,
+			_ => unreachable!("Invalid attachment!"),
+		};
+
+		assert!(usage.contains(TextureUsage::TRANSFER_SRC), "Exported attachments must have TextureUsage::TRANSFER_SRC!");
+
+		self.exported_attachments.push(attachment);
+	}
+
 	pub fn add_pass<'b>(&'b mut self, name: &'static str) -> PassBuilder<'a, 'b> {
 		let pass = PassHandle { id: self.passes.len() };
 		let recorded = Some(RecordedPass {
@@ -1167,21 +2476,34 @@ impl<'a> RenderGraph<'a> {
 			cmds: Default::default(),
 			read_attachments: Default::default(),
 			write_attachments: Default::default(),
+			written_attachments: Default::default(),
 			read_buffers: Default::default(),
 			write_buffers: Default::default(),
+			read_imported_buffers: Default::default(),
+			write_imported_buffers: Default::default(),
+			shader_write_imported_buffers: Default::default(),
+			submit_hint: false,
 		});
 
-		PassBuilder { graph: self, pass, recorded }
+		PassBuilder {
+			graph: self,
+			pass,
+			recorded,
+			sorted: Default::default(),
+		}
 	}
 
-	fn resolve_pass_dependencies(&mut self, pass: PassHandle, pass_order: &mut Vec<PassHandle>) -> PassDependencyNode {
+	fn resolve_pass_dependencies(&self, pass: PassHandle, pass_order: &mut Vec<PassHandle>) -> PassDependencyNode {
 		let recorded_pass = &self.passes[pass.id];
 		pass_order.push(pass);
 		let dependencies = recorded_pass
 			.read_attachments
 			.iter()
-			.map(|a| self.resource_to_owning_pass[&a.id])
+			.filter_map(|a| self.resource_to_owning_pass.get(&a.id).copied())
 			.chain(recorded_pass.read_buffers.iter().map(|b| self.resource_to_owning_pass[&b.id]))
+			.chain(self.imported_buffer_dependencies(recorded_pass))
+			.chain(self.attachment_dependencies(recorded_pass))
+			.chain(self.output_dependencies(recorded_pass))
 			// .chain(recorded_pass.write_attachments.iter().map(|a| self.resource_to_owning_pass[&a.id]))
 			.collect::<HashSet<_>>()
 			.into_iter()
@@ -1191,48 +2513,365 @@ impl<'a> RenderGraph<'a> {
 		PassDependencyNode { pass, dependencies }
 	}
 
-	pub fn execute(mut self, graphics_context: &mut GraphicsContext, graphics_device: &mut GraphicsDevice) {
-		let output = self
-			.owned_resources
+	fn imported_buffer_dependencies(&self, recorded_pass: &RecordedPass) -> Vec<PassHandle> {
+		let earlier_passes = &self.passes[..recorded_pass.pass.id];
+
+		let read_dependencies = recorded_pass
+			.read_imported_buffers
 			.iter()
-			.enumerate()
-			.filter(|(_, r)| matches!(r, GraphOwnedResource::OutputRenderPass {}))
-			.collect::<Vec<_>>();
+			.filter_map(|buffer| earlier_passes.iter().rev().find(|p| p.write_imported_buffers.contains(buffer)).map(|p| p.pass));
 
-		if output.len() != 1 {
-			panic!("Multiple output render pass resources were found!");
-		}
+		let write_dependencies = recorded_pass.write_imported_buffers.iter().flat_map(|buffer| {
+			earlier_passes
+				.iter()
+				.filter(move |p| p.read_imported_buffers.contains(buffer) || p.write_imported_buffers.contains(buffer))
+				.map(|p| p.pass)
+		});
 
-		let (id, _) = output[0];
+		read_dependencies.chain(write_dependencies).collect()
+	}
+
+	fn attachment_dependencies(&self, recorded_pass: &RecordedPass) -> Vec<PassHandle> {
+		let earlier_passes = &self.passes[..recorded_pass.pass.id];
+
+		let read_dependencies = recorded_pass
+			.read_attachments
+			.iter()
+			.filter_map(|attachment| earlier_passes.iter().rev().find(|p| p.written_attachments.contains(&attachment.id)).map(|p| p.pass));
+
+		let write_dependencies = recorded_pass.written_attachments.iter().flat_map(|&id| {
+			earlier_passes
+				.iter()
+				.filter(move |p| p.written_attachments.contains(&id) || p.read_attachments.iter().any(|attachment| attachment.id == id))
+				.map(|p| p.pass)
+		});
+
+		read_dependencies.chain(write_dependencies).collect()
+	}
+
+	// Writing the output is loading whatever an earlier pass left in it, so each pass that writes it depends on the one recorded before it.
+	fn output_dependencies(&self, recorded_pass: &RecordedPass) -> Vec<PassHandle> {
+		let output_passes = self.output_passes();
+		match output_passes.iter().position(|&p| p == recorded_pass.pass) {
+			Some(i) if i > 0 => vec![output_passes[i - 1]],
+			_ => Vec::new(),
+		}
+	}
+
+	fn attachment_load_op(&self, id: usize) -> LoadOp {
+		match &self.owned_resources[id] {
+			&GraphOwnedResource::Attachment { load_op, .. } => load_op,
+			_ => unreachable!("Invalid attachment!"),
+		}
+	}
+
+	fn output_passes(&self) -> Vec<PassHandle> {
+		let output_render_passes = self.owned_resources.iter().filter(|r| matches!(r, GraphOwnedResource::OutputRenderPass {})).count();
+		if output_render_passes > 1 {
+			panic!("Multiple output render pass resources were found!");
+		}
+
+		let mut passes = self
+			.owned_resources
+			.iter()
+			.enumerate()
+			.filter(|(_, r)| matches!(r, GraphOwnedResource::OutputRenderPass {} | GraphOwnedResource::OutputStorage {}))
+			.map(|(id, _)| self.resource_to_owning_pass[&id])
+			.collect::<Vec<_>>();
+
+		passes.sort_unstable_by_key(|p| p.id);
+		passes.dedup();
+		passes
+	}
+
+	fn writes_output_storage(&self, pass: PassHandle) -> bool {
+		self.owned_resources
+			.iter()
+			.enumerate()
+			.any(|(id, r)| matches!(r, GraphOwnedResource::OutputStorage {}) && self.resource_to_owning_pass[&id] == pass)
+	}
+
+	fn resolve_pass_order(&self) -> Option<Vec<PassHandle>> {
+		let output_passes = self.output_passes();
+		let &root_pass = output_passes.last()?;
 
-		let root_pass = self.resource_to_owning_pass[&id];
 		let mut passes = vec![root_pass];
 		// let root =
 		self.resolve_pass_dependencies(root_pass, &mut passes);
 		// dbg!(root);
 
+		for attachment in self.exported_attachments.iter() {
+			let pass = self.resource_to_owning_pass[&attachment.id];
+			passes.push(pass);
+			self.resolve_pass_dependencies(pass, &mut passes);
+		}
+
 		let mut found = HashSet::<PassHandle>::new();
 		passes.reverse();
 		passes.retain(|p| found.insert(*p));
 
-		let resource_map = GraphPhysicalResourceMap::new(&mut self, graphics_device, graphics_context);
-		for pass in passes {
+		Some(passes)
+	}
+
+	fn pass_resource_names(&self, pass: PassHandle) -> Vec<&'static str> {
+		let pass = &self.passes[pass.id];
+
+		let mut ids = Vec::new();
+		ids.extend(pass.read_attachments.iter().map(|a| a.id));
+		ids.extend(pass.write_attachments.iter().map(|a| a.id));
+		ids.extend(pass.read_buffers.iter().map(|b| b.id));
+		ids.extend(pass.write_buffers.iter().map(|b| b.id));
+		ids.extend(pass.cmds.iter().filter_map(|cmd| match cmd {
+			PassCmd::BindGraphicsDescriptor { descriptor, .. } => Some(descriptor.id),
+			PassCmd::BindComputeDescriptor { descriptor, .. } => Some(descriptor.id),
+			_ => None,
+		}));
+
+		ids.sort_unstable();
+		ids.dedup();
+		ids.into_iter().map(|id| self.owned_resources[id].name()).collect()
+	}
+
+	// Catches the mistakes the automatic barriers can't do anything about. Every recorded pass is checked, even the ones that get culled.
+	fn validate(&self) {
+		tracy::span!();
+		for pass in self.passes.iter() {
+			for attachment in pass.read_attachments.iter() {
+				if pass.written_attachments.contains(&attachment.id) {
+					validation_failure(pass.name, None, self.owned_resource_name(attachment.id), "attachment is both read and written by the pass");
+				}
+			}
+
+			for buffer in pass.read_buffers.iter() {
+				if pass.write_buffers.iter().any(|b| b.id == buffer.id) {
+					validation_failure(pass.name, None, self.owned_resource_name(buffer.id), "buffer is both read and written by the pass");
+				}
+			}
+
+			let mut render_pass = None;
+			let mut raster_pipeline = None;
+			let mut compute_pipeline = None;
+			for (index, cmd) in pass.cmds.iter().enumerate() {
+				let fail = |resource: &str, message: &str| validation_failure(pass.name, Some(index), resource, message);
+				let check_owned = |id: usize, expected: fn(&GraphOwnedResource) -> bool, message: &str| {
+					if !self.owned_resources.get(id).map_or(false, expected) {
+						fail(self.owned_resource_name(id), message);
+					}
+				};
+				let check_imported = |id: usize, expected: fn(&GraphImportedResource) -> bool, message: &str| {
+					if !self.imported_resources.get(id).map_or(false, expected) {
+						fail(&format!("imported resource {}", id), message);
+					}
+				};
+				let check_buffer = |binding: &GraphBufferBinding| match *binding {
+					GraphBufferBinding::ImportedBuffer(buffer) => check_imported(buffer.id, |r| matches!(r, GraphImportedResource::Buffer(_)), "not a buffer imported into this graph"),
+					GraphBufferBinding::Buffer(buffer) => check_owned(buffer.id, |r| matches!(r, GraphOwnedResource::Buffer { .. }), "not a buffer created in this graph"),
+				};
+				let is_raster_pipeline = |r: &GraphOwnedResource| matches!(r, GraphOwnedResource::RasterPipeline { .. });
+				let is_compute_pipeline = |r: &GraphOwnedResource| matches!(r, GraphOwnedResource::ComputePipeline { .. });
+
+				let inside_render_pass = match cmd {
+					PassCmd::DrawMesh { .. } | PassCmd::Draw { .. } | PassCmd::DrawIndexed { .. } | PassCmd::DrawIndirect { .. } => Some(true),
+					PassCmd::Dispatch { .. } | PassCmd::UpdateImportedBuffer { .. } | PassCmd::FillBuffer { .. } | PassCmd::ClearAttachment { .. } | PassCmd::BlitToOutput { .. } => Some(false),
+					_ => None,
+				};
+				match (inside_render_pass, render_pass) {
+					(Some(true), None) => fail("", "draws have to be recorded inside of a render pass"),
+					(Some(false), Some(id)) => fail(self.owned_resource_name(id), "only draws can be recorded inside of a render pass"),
+					_ => {}
+				}
+
+				match cmd {
+					PassCmd::BeginRenderPass { render_pass: handle, .. } => {
+						check_owned(
+							handle.id,
+							|r| matches!(r, GraphOwnedResource::RenderPass { .. } | GraphOwnedResource::OutputRenderPass {}),
+							"not a render pass created in this graph",
+						);
+						if let Some(id) = render_pass {
+							fail(self.owned_resource_name(id), "render pass was never ended before beginning another one");
+						}
+						render_pass = Some(handle.id);
+					}
+					PassCmd::EndRenderPass {} => {
+						if render_pass.take().is_none() {
+							fail("", "ended a render pass that was never begun");
+						}
+					}
+					&PassCmd::BindRasterPipeline { pipeline } => {
+						check_owned(pipeline.id, is_raster_pipeline, "not a raster pipeline created in this graph");
+						raster_pipeline = Some(pipeline.id);
+					}
+					&PassCmd::BindComputePipeline { pipeline } => {
+						check_owned(pipeline.id, is_compute_pipeline, "not a compute pipeline created in this graph");
+						compute_pipeline = Some(pipeline.id);
+					}
+					&PassCmd::BindGraphicsDescriptor { descriptor, pipeline, .. } => {
+						check_owned(
+							descriptor.id,
+							|r| matches!(r, GraphOwnedResource::GraphicsDescriptorSet { .. }),
+							"not a graphics descriptor set created in this graph",
+						);
+						check_owned(pipeline.id, is_raster_pipeline, "not a raster pipeline created in this graph");
+					}
+					&PassCmd::BindComputeDescriptor { descriptor, pipeline, .. } => {
+						check_owned(
+							descriptor.id,
+							|r| matches!(r, GraphOwnedResource::ComputeDescriptorSet { .. }),
+							"not a compute descriptor set created in this graph",
+						);
+						check_owned(pipeline.id, is_compute_pipeline, "not a compute pipeline created in this graph");
+					}
+					&PassCmd::PushConstants { pipeline, .. } => check_owned(pipeline.id, is_raster_pipeline, "not a raster pipeline created in this graph"),
+					PassCmd::BindVertexBuffers { buffers } => buffers.iter().for_each(|(buffer, _)| check_buffer(buffer)),
+					PassCmd::BindIndexBuffer { buffer, .. } => check_buffer(buffer),
+					&PassCmd::UpdateImportedBuffer { buffer, .. } => check_imported(buffer.id, |r| matches!(r, GraphImportedResource::Buffer(_)), "not a buffer imported into this graph"),
+					&PassCmd::FillBuffer { buffer, .. } => check_owned(buffer.id, |r| matches!(r, GraphOwnedResource::Buffer { .. }), "not a buffer created in this graph"),
+					&PassCmd::ClearAttachment { attachment, .. } => check_owned(attachment.id, |r| matches!(r, GraphOwnedResource::Attachment { .. }), "not an attachment created in this graph"),
+					&PassCmd::BlitToOutput { attachment, .. } => check_owned(attachment.id, |r| matches!(r, GraphOwnedResource::Attachment { .. }), "not an attachment created in this graph"),
+					_ => {}
+				}
+
+				match cmd {
+					&PassCmd::DrawMesh { mesh } => {
+						check_imported(mesh.id, |r| matches!(r, GraphImportedResource::Mesh(_)), "not a mesh imported into this graph");
+						if raster_pipeline.is_none() {
+							fail("", "drawing without a raster pipeline bound");
+						}
+					}
+					PassCmd::DrawIndirect { buffer, .. } => {
+						check_buffer(buffer);
+						if raster_pipeline.is_none() {
+							fail("", "drawing without a raster pipeline bound");
+						}
+					}
+					PassCmd::Draw { .. } | PassCmd::DrawIndexed { .. } if raster_pipeline.is_none() => fail("", "drawing without a raster pipeline bound"),
+					PassCmd::Dispatch { .. } if compute_pipeline.is_none() => fail("", "dispatching without a compute pipeline bound"),
+					_ => {}
+				}
+			}
+
+			if let Some(id) = render_pass {
+				validation_failure(pass.name, Some(pass.cmds.len()), self.owned_resource_name(id), "render pass was never ended");
+			}
+		}
+	}
+
+	fn owned_resource_name(&self, id: usize) -> &'static str {
+		self.owned_resources.get(id).map_or("<not in this graph>", |r| r.name())
+	}
+
+	// Per frame buffers without resizable BAR are copied over from their staging buffers before the first pass that could read them.
+	fn copy_staged_per_frame_buffers(&self, graphics_context: &mut GraphicsContext) {
+		let frame_slot = graphics_context.frame_slot();
+
+		let mut copied = HashSet::new();
+		let mut barriers = Vec::new();
+		for resource in self.imported_resources.iter() {
+			let buffer = match resource {
+				GraphImportedResource::PerFrameBuffer(buffer) if buffer.is_staged() => buffer,
+				_ => continue,
+			};
+
+			let (staging, dst) = (buffer.current(frame_slot), buffer.gpu_buffer(frame_slot));
+			if !copied.insert(dst.raw) {
+				continue;
+			}
+
+			graphics_context.copy_buffer(staging, dst, 0, 0, staging.size as u64);
+			barriers.push(
+				ash::vk::BufferMemoryBarrier::builder()
+					.buffer(dst.raw)
+					.offset(0)
+					.size(staging.size as u64)
+					.src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+					.dst_access_mask(ash::vk::AccessFlags::UNIFORM_READ | ash::vk::AccessFlags::SHADER_READ)
+					.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+					.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+					.build(),
+			);
+		}
+
+		if !barriers.is_empty() {
+			graphics_context.pipeline_barrier(
+				ash::vk::PipelineStageFlags::TRANSFER,
+				ash::vk::PipelineStageFlags::ALL_COMMANDS,
+				ash::vk::DependencyFlags::empty(),
+				&[],
+				&barriers,
+				&[],
+			);
+		}
+	}
+
+	pub fn execute(mut self, graphics_context: &mut GraphicsContext, graphics_device: &mut GraphicsDevice) -> GoldfishResult<()> {
+		if self.validation {
+			self.validate();
+		}
+
+		let passes = self.resolve_pass_order().expect("Nothing in the graph writes to the output!");
+
+		let resource_map = GraphPhysicalResourceMap::new(&mut self, graphics_device, graphics_context)?;
+
+		// Attachments written by more than one pass transition from wherever the last one left them, history swaps only if its pass runs.
+		let history_keys = self
+			.owned_resources
+			.iter()
+			.enumerate()
+			.filter_map(|(id, r)| r.history_key().map(|history_key| (id, history_key)))
+			.filter(|&(id, (_, history))| history == HistoryFrame::Current && passes.contains(&self.resource_to_owning_pass[&id]))
+			.map(|(_, (key, _))| key)
+			.collect::<Vec<_>>();
+
+		let mut attachment_states = HashMap::<usize, AttachmentState>::new();
+		for key in history_keys.iter() {
+			let history_attachment = &self.cache.attachment_cache.history[key];
+			for (&index, &state) in history_attachment.attachments.iter().zip(history_attachment.states.iter()) {
+				attachment_states.insert(index, state);
+			}
+		}
+
+		self.cache.pass_timings.clear();
+		let pass_count = passes.len();
+		for (i, pass) in passes.into_iter().enumerate() {
+			let pass_start = std::time::Instant::now();
+			graphics_context.begin_cmd_list();
+			graphics_context.begin_pass_diagnostics(self.passes[pass.id].name, self.pass_resource_names(pass));
+
+			if i == 0 {
+				self.copy_staged_per_frame_buffers(graphics_context);
+			}
+
 			for &attachment in self.passes[pass.id].read_attachments.iter() {
 				let physical_attachment = resource_map.get_attachment(&self, attachment);
 				// dbg!("Adding read pipeline barrier for pass {}, {:?}", self.passes[pass.id].name, attachment);
 
+				let state = attachment_states.insert(
+					resource_map.attachment_map.get_physical(attachment.id),
+					AttachmentState {
+						layout: attachment.final_layout,
+						stage: attachment.dst_stage,
+						access: attachment.dst_access,
+					},
+				);
+				let state = state.unwrap_or(AttachmentState {
+					layout: attachment.initial_layout,
+					stage: attachment.src_stage,
+					access: attachment.src_access,
+				});
+
 				graphics_context.pipeline_barrier(
-					attachment.src_stage,
+					state.stage,
 					attachment.dst_stage,
 					ash::vk::DependencyFlags::empty(),
 					&[],
 					&[],
 					&[ash::vk::ImageMemoryBarrier::builder()
-						.old_layout(attachment.initial_layout.into())
+						.old_layout(state.layout.into())
 						.new_layout(attachment.final_layout.into())
 						.image(physical_attachment.image)
 						.subresource_range(physical_attachment.subresource_range)
-						.src_access_mask(attachment.src_access)
+						.src_access_mask(state.access)
 						.dst_access_mask(attachment.dst_access)
 						.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
 						.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
@@ -1244,18 +2883,33 @@ impl<'a> RenderGraph<'a> {
 				let physical_attachment = resource_map.get_mutable_attachment(&self, attachment);
 				// dbg!("Adding write pipeline barrier for pass {}, {:?}", self.passes[pass.id].name, attachment);
 
+				// Unless the attachment is loaded the contents get thrown away, but an earlier pass might still be reading it.
+				let state = attachment_states.insert(
+					resource_map.attachment_map.get_physical(attachment.id),
+					AttachmentState {
+						layout: attachment.layout,
+						stage: attachment.stage,
+						access: attachment.access,
+					},
+				);
+				let (old_layout, src_stage, src_access) = match state {
+					Some(state) if self.attachment_load_op(attachment.id) == LoadOp::Load => (state.layout, state.stage, state.access),
+					Some(state) => (ImageLayout::Undefined, state.stage, state.access),
+					None => (ImageLayout::Undefined, ash::vk::PipelineStageFlags::TOP_OF_PIPE, ash::vk::AccessFlags::empty()),
+				};
+
 				graphics_context.pipeline_barrier(
-					ash::vk::PipelineStageFlags::TOP_OF_PIPE,
+					src_stage,
 					attachment.stage,
 					ash::vk::DependencyFlags::empty(),
 					&[],
 					&[],
 					&[ash::vk::ImageMemoryBarrier::builder()
-						.old_layout(ImageLayout::Undefined.into())
+						.old_layout(old_layout.into())
 						.new_layout(attachment.layout.into())
 						.image(physical_attachment.image)
 						.subresource_range(physical_attachment.subresource_range)
-						.src_access_mask(ash::vk::AccessFlags::empty())
+						.src_access_mask(src_access)
 						.dst_access_mask(attachment.access)
 						.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
 						.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
@@ -1284,19 +2938,132 @@ impl<'a> RenderGraph<'a> {
 				)
 			}
 
+			for &buffer in self.passes[pass.id].write_buffers.iter() {
+				let physical_buffer = resource_map.get_mutable_buffer(&self, buffer);
+
+				graphics_context.pipeline_barrier(
+					ash::vk::PipelineStageFlags::ALL_COMMANDS,
+					buffer.stage,
+					ash::vk::DependencyFlags::empty(),
+					&[],
+					&[ash::vk::BufferMemoryBarrier::builder()
+						.buffer(physical_buffer.raw)
+						.size(physical_buffer.size as u64)
+						.offset(0)
+						.src_access_mask(ash::vk::AccessFlags::SHADER_WRITE | ash::vk::AccessFlags::TRANSFER_WRITE)
+						.dst_access_mask(buffer.access)
+						.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.build()],
+					&[],
+				)
+			}
+
+			for buffer in self.passes[pass.id].shader_write_imported_buffers.iter() {
+				let buffer = match &self.imported_resources[buffer.id] {
+					GraphImportedResource::Buffer(buffer) => *buffer,
+					_ => unreachable!("Invalid buffer!"),
+				};
+
+				graphics_context.pipeline_barrier(
+					ash::vk::PipelineStageFlags::ALL_COMMANDS,
+					shader_write_stages(),
+					ash::vk::DependencyFlags::empty(),
+					&[],
+					&[ash::vk::BufferMemoryBarrier::builder()
+						.buffer(buffer.raw)
+						.size(buffer.size as u64)
+						.offset(0)
+						.src_access_mask(imported_buffer_access())
+						.dst_access_mask(ash::vk::AccessFlags::SHADER_READ | ash::vk::AccessFlags::SHADER_WRITE)
+						.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.build()],
+					&[],
+				);
+			}
+
+			if self.writes_output_storage(pass) {
+				graphics_context.begin_output_storage();
+			}
+
+			let mut skip_draws = false;
 			for cmd in self.passes[pass.id].cmds.iter() {
 				match cmd {
+					PassCmd::DrawMesh { .. } | PassCmd::Draw { .. } | PassCmd::DrawIndexed { .. } | PassCmd::DrawIndirect { .. } if skip_draws => {}
+					&PassCmd::BindGraphicsDescriptor { pipeline, .. } | &PassCmd::PushConstants { pipeline, .. } if !resource_map.is_raster_pipeline_ready(pipeline) => {}
 					PassCmd::BeginRenderPass { render_pass, clear_values } => {
-						if let Some((render_pass, framebuffer)) = resource_map.get_render_pass(&self, *render_pass) {
-							graphics_context.begin_render_pass(render_pass, framebuffer, &clear_values);
+						if let Some((physical_render_pass, framebuffer)) = resource_map.get_render_pass(&self, *render_pass) {
+							let (color_attachments, depth_attachment) = match &self.owned_resources[render_pass.id] {
+								GraphOwnedResource::RenderPass {
+									color_attachments, depth_attachment, ..
+								} => (color_attachments, depth_attachment),
+								_ => unreachable!("Invalid render pass!"),
+							};
+
+							for &attachment in color_attachments.iter().chain(depth_attachment.iter()) {
+								let state = attachment_states.insert(
+									resource_map.attachment_map.get_physical(attachment.id),
+									AttachmentState {
+										layout: attachment.layout,
+										stage: attachment.stage,
+										access: attachment.access,
+									},
+								);
+
+								if self.attachment_load_op(attachment.id) != LoadOp::Load {
+									continue;
+								}
+
+								let state = state.expect("Loaded attachment was not written earlier in the frame!");
+								let (dst_stage, dst_access) = if attachment.layout == ImageLayout::DepthStencilAttachmentOptimal {
+									(
+										ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | attachment.stage,
+										ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | attachment.access,
+									)
+								} else {
+									(attachment.stage, ash::vk::AccessFlags::COLOR_ATTACHMENT_READ | attachment.access)
+								};
+
+								let physical_attachment = resource_map.get_mutable_attachment(&self, attachment);
+								graphics_context.pipeline_barrier(
+									state.stage,
+									dst_stage,
+									ash::vk::DependencyFlags::empty(),
+									&[],
+									&[],
+									&[ash::vk::ImageMemoryBarrier::builder()
+										.old_layout(state.layout.into())
+										.new_layout(attachment.layout.into())
+										.image(physical_attachment.image)
+										.subresource_range(physical_attachment.subresource_range)
+										.src_access_mask(state.access)
+										.dst_access_mask(dst_access)
+										.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+										.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+										.build()],
+								);
+							}
+
+							graphics_context.begin_render_pass(physical_render_pass, framebuffer, &clear_values);
 						} else {
-							graphics_context.begin_output_render_pass(&clear_values);
+							match self.output_policy.bar_color() {
+								Some(bar_color) => graphics_context.begin_output_render_pass(&[ClearValue::from_color(bar_color, graphics_context.output_is_srgb())]),
+								None => graphics_context.begin_output_render_pass(&clear_values),
+							}
+
+							if self.output_policy != OutputPolicy::Stretch {
+								let viewport = self.output_policy.viewport(graphics_context.output_size());
+								graphics_context.set_viewport(viewport.offset.x, viewport.offset.y, viewport.extent.x, viewport.extent.y);
+							}
 						}
 					}
 					PassCmd::EndRenderPass {} => graphics_context.end_render_pass(),
 					&PassCmd::BindRasterPipeline { pipeline } => {
-						let pipeline = resource_map.get_raster_pipeline(&self, pipeline);
-						graphics_context.bind_raster_pipeline(pipeline);
+						skip_draws = !resource_map.is_raster_pipeline_ready(pipeline);
+						if !skip_draws {
+							graphics_context.bind_raster_pipeline(resource_map.get_raster_pipeline(&self, pipeline));
+						}
 					}
 					&PassCmd::BindComputePipeline { pipeline } => {
 						let pipeline = resource_map.get_compute_pipeline(&self, pipeline);
@@ -1305,35 +3072,286 @@ impl<'a> RenderGraph<'a> {
 					&PassCmd::BindGraphicsDescriptor { set, descriptor, pipeline } => {
 						let pipeline = resource_map.get_raster_pipeline(&self, pipeline);
 
-						let (descriptor, descriptor_heap) = resource_map.get_graphics_descriptor(&self, descriptor);
-
-						graphics_context.bind_graphics_descriptor(descriptor_heap, &descriptor, set, pipeline);
+						match resource_map.get_graphics_descriptor(&self, descriptor) {
+							PhysicalDescriptorRef::Cached(descriptor, descriptor_heap) => graphics_context.bind_graphics_descriptor(descriptor_heap, &descriptor, set, pipeline),
+							PhysicalDescriptorRef::Transient(descriptor) => graphics_context.bind_graphics_transient_descriptor(&descriptor, set, pipeline),
+						}
 					}
 					&PassCmd::BindComputeDescriptor { set, descriptor, pipeline } => {
 						let pipeline = resource_map.get_compute_pipeline(&self, pipeline);
 
-						let (descriptor, descriptor_heap) = resource_map.get_compute_descriptor(&self, descriptor);
-
-						graphics_context.bind_compute_descriptor(descriptor_heap, &descriptor, set, pipeline);
+						match resource_map.get_compute_descriptor(&self, descriptor) {
+							PhysicalDescriptorRef::Cached(descriptor, descriptor_heap) => graphics_context.bind_compute_descriptor(descriptor_heap, &descriptor, set, pipeline),
+							PhysicalDescriptorRef::Transient(descriptor) => graphics_context.bind_compute_transient_descriptor(&descriptor, set, pipeline),
+						}
 					}
 					PassCmd::DrawMesh { mesh } => match &self.imported_resources[mesh.id] {
 						GraphImportedResource::Mesh(mesh) => graphics_context.draw_mesh(mesh),
 						_ => unreachable!("Invalid mesh!"),
 					},
+					PassCmd::BindVertexBuffers { buffers } => {
+						let buffers = buffers.iter().map(|&(buffer, offset)| (resource_map.get_bound_buffer(&self, buffer), offset)).collect::<Vec<_>>();
+
+						graphics_context.bind_vertex_buffers(0, &buffers);
+					}
+					&PassCmd::BindIndexBuffer { buffer, offset, index_type } => {
+						graphics_context.bind_index_buffer(resource_map.get_bound_buffer(&self, buffer), offset, index_type);
+					}
+					&PassCmd::DrawIndexed {
+						index_count,
+						instance_count,
+						first_index,
+						vertex_offset,
+						first_instance,
+					} => graphics_context.draw_indexed(index_count, instance_count, first_index, vertex_offset, first_instance),
 					&PassCmd::Draw {
 						vertex_count,
 						instance_count,
 						first_vertex,
 						first_instance,
 					} => graphics_context.draw(vertex_count, instance_count, first_vertex, first_instance),
+					&PassCmd::DrawIndirect {
+						buffer,
+						offset,
+						draw_count,
+						stride,
+						indexed,
+					} => {
+						let buffer = resource_map.get_bound_buffer(&self, buffer);
+						if indexed {
+							graphics_context.draw_indexed_indirect(buffer, offset, draw_count, stride);
+						} else {
+							graphics_context.draw_indirect(buffer, offset, draw_count, stride);
+						}
+					}
 					&PassCmd::Dispatch {
 						group_count_x,
 						group_count_y,
 						group_count_z,
-					} => graphics_context.dispatch(group_count_x, group_count_y, group_count_z),
+					} => {
+						let max_group_count = graphics_device.max_compute_work_group_count();
+						assert!(
+							group_count_x <= max_group_count[0] && group_count_y <= max_group_count[1] && group_count_z <= max_group_count[2],
+							"Pass {} dispatched {}x{}x{} groups, the device supports at most {}x{}x{}!",
+							self.passes[pass.id].name,
+							group_count_x,
+							group_count_y,
+							group_count_z,
+							max_group_count[0],
+							max_group_count[1],
+							max_group_count[2]
+						);
+						graphics_context.dispatch(group_count_x, group_count_y, group_count_z)
+					}
+					&PassCmd::BlitToOutput { attachment, filter } => {
+						let viewport = self.output_policy.viewport(graphics_context.output_size());
+						let bar_clear = self.output_policy.bar_color().map(|c| ClearValue::from_color(c, graphics_context.output_is_srgb()));
+						graphics_context.blit_to_output(resource_map.get_attachment(&self, attachment), attachment.final_layout, filter, viewport, bar_clear)
+					}
+					&PassCmd::BeginQuery { slot } => graphics_context.begin_query(slot),
+					&PassCmd::EndQuery { slot } => graphics_context.end_query(slot),
+					PassCmd::UpdateImportedBuffer { buffer, offset, data } => {
+						let buffer = match &self.imported_resources[buffer.id] {
+							GraphImportedResource::Buffer(buffer) => *buffer,
+							_ => unreachable!("Invalid buffer!"),
+						};
+
+						let mut staging = graphics_device.create_empty_buffer("Imported Buffer Update (Staging)", data.len(), MemoryLocation::CpuToGpu, BufferUsage::TransferSrc, None)?;
+						graphics_device.map_buffer(&mut staging)[..data.len()].copy_from_slice(data);
+
+						let read_access = ash::vk::AccessFlags::UNIFORM_READ
+							| ash::vk::AccessFlags::SHADER_READ
+							| ash::vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+							| ash::vk::AccessFlags::INDEX_READ
+							| ash::vk::AccessFlags::TRANSFER_READ;
+						let barrier = ash::vk::BufferMemoryBarrier::builder()
+							.buffer(buffer.raw)
+							.offset(*offset)
+							.size(data.len() as u64)
+							.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+							.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+							.build();
+
+						graphics_context.pipeline_barrier(
+							ash::vk::PipelineStageFlags::ALL_COMMANDS,
+							ash::vk::PipelineStageFlags::TRANSFER,
+							ash::vk::DependencyFlags::empty(),
+							&[],
+							&[ash::vk::BufferMemoryBarrier {
+								src_access_mask: read_access,
+								dst_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE,
+								..barrier
+							}],
+							&[],
+						);
+
+						graphics_context.copy_buffer(&staging, buffer, 0, *offset, data.len() as u64);
+
+						graphics_context.pipeline_barrier(
+							ash::vk::PipelineStageFlags::TRANSFER,
+							ash::vk::PipelineStageFlags::ALL_COMMANDS,
+							ash::vk::DependencyFlags::empty(),
+							&[],
+							&[ash::vk::BufferMemoryBarrier {
+								src_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE,
+								dst_access_mask: read_access,
+								..barrier
+							}],
+							&[],
+						);
+
+						graphics_device.destroy_buffer(staging);
+					}
+					&PassCmd::FillBuffer { buffer, value } => {
+						let physical_buffer = resource_map.get_mutable_buffer(&self, buffer);
+						graphics_context.fill_buffer(physical_buffer, 0, physical_buffer.size as u64, value);
+
+						graphics_context.pipeline_barrier(
+							ash::vk::PipelineStageFlags::TRANSFER,
+							ash::vk::PipelineStageFlags::VERTEX_SHADER | ash::vk::PipelineStageFlags::FRAGMENT_SHADER | ash::vk::PipelineStageFlags::COMPUTE_SHADER,
+							ash::vk::DependencyFlags::empty(),
+							&[],
+							&[ash::vk::BufferMemoryBarrier::builder()
+								.buffer(physical_buffer.raw)
+								.size(physical_buffer.size as u64)
+								.offset(0)
+								.src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+								.dst_access_mask(ash::vk::AccessFlags::SHADER_READ | ash::vk::AccessFlags::SHADER_WRITE)
+								.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+								.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+								.build()],
+							&[],
+						);
+					}
+					&PassCmd::ClearAttachment { attachment, value } => {
+						let physical_attachment = resource_map.get_mutable_attachment(&self, attachment);
+						let state = attachment_states.insert(
+							resource_map.attachment_map.get_physical(attachment.id),
+							AttachmentState {
+								layout: attachment.layout,
+								stage: attachment.stage,
+								access: attachment.access,
+							},
+						);
+						let (src_stage, src_access) = state.map_or((ash::vk::PipelineStageFlags::TOP_OF_PIPE, ash::vk::AccessFlags::empty()), |state| (state.stage, state.access));
+
+						graphics_context.pipeline_barrier(
+							src_stage,
+							ash::vk::PipelineStageFlags::TRANSFER,
+							ash::vk::DependencyFlags::empty(),
+							&[],
+							&[],
+							&[ash::vk::ImageMemoryBarrier::builder()
+								.old_layout(ImageLayout::Undefined.into())
+								.new_layout(attachment.layout.into())
+								.image(physical_attachment.image)
+								.subresource_range(physical_attachment.subresource_range)
+								.src_access_mask(src_access)
+								.dst_access_mask(attachment.access)
+								.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+								.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+								.build()],
+						);
+
+						graphics_context.clear_image(physical_attachment, attachment.layout, value);
+					}
+					&PassCmd::PushConstants { pipeline, offset, ref data } => {
+						let pipeline = resource_map.get_raster_pipeline(&self, pipeline);
+						graphics_context.push_constants(pipeline, offset, data);
+					}
+					&PassCmd::SetViewport { offset, extent } => graphics_context.set_viewport(offset.x, offset.y, extent.x, extent.y),
 				}
 			}
+
+			for buffer in self.passes[pass.id].shader_write_imported_buffers.iter() {
+				let buffer = match &self.imported_resources[buffer.id] {
+					GraphImportedResource::Buffer(buffer) => *buffer,
+					_ => unreachable!("Invalid buffer!"),
+				};
+
+				graphics_context.pipeline_barrier(
+					shader_write_stages(),
+					ash::vk::PipelineStageFlags::ALL_COMMANDS,
+					ash::vk::DependencyFlags::empty(),
+					&[],
+					&[ash::vk::BufferMemoryBarrier::builder()
+						.buffer(buffer.raw)
+						.size(buffer.size as u64)
+						.offset(0)
+						.src_access_mask(ash::vk::AccessFlags::SHADER_WRITE)
+						.dst_access_mask(imported_buffer_access())
+						.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+						.build()],
+					&[],
+				);
+			}
+
+			graphics_context.end_pass_diagnostics();
+			self.cache.pass_timings.push(PassTiming {
+				name: self.passes[pass.id].name,
+				cpu_time: pass_start.elapsed(),
+			});
+
+			let submit = match self.cache.submit_batching {
+				SubmitBatching::Single => false,
+				SubmitBatching::Hints => self.passes[pass.id].submit_hint,
+				SubmitBatching::EveryPasses(count) => self.passes[pass.id].submit_hint || (i + 1) % count == 0,
+			};
+			if submit && i + 1 < pass_count {
+				graphics_context.submit_batch();
+			}
+		}
+
+		self.cache.exported_attachments.clear();
+		for &attachment in self.exported_attachments.iter() {
+			let physical_attachment = resource_map.get_mutable_attachment(&self, attachment);
+
+			let state = attachment_states.insert(
+				resource_map.attachment_map.get_physical(attachment.id),
+				AttachmentState {
+					layout: ImageLayout::TransferSrcOptimal,
+					stage: ash::vk::PipelineStageFlags::TRANSFER,
+					access: ash::vk::AccessFlags::TRANSFER_READ,
+				},
+			);
+			let state = state.unwrap_or(AttachmentState {
+				layout: attachment.layout,
+				stage: attachment.stage,
+				access: attachment.access,
+			});
+
+			graphics_context.pipeline_barrier(
+				state.stage,
+				ash::vk::PipelineStageFlags::TRANSFER,
+				ash::vk::DependencyFlags::empty(),
+				&[],
+				&[],
+				&[ash::vk::ImageMemoryBarrier::builder()
+					.old_layout(state.layout.into())
+					.new_layout(ImageLayout::TransferSrcOptimal.into())
+					.image(physical_attachment.image)
+					.subresource_range(physical_attachment.subresource_range)
+					.src_access_mask(state.access)
+					.dst_access_mask(ash::vk::AccessFlags::TRANSFER_READ)
+					.src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+					.dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+					.build()],
+			);
+
+			let name = self.owned_resources[attachment.id].name();
+			self.cache.exported_attachments.insert(name, resource_map.attachment_map.get_physical(attachment.id));
+		}
+
+		for key in history_keys.iter() {
+			let history_attachment = self.cache.attachment_cache.history.get_mut(key).unwrap();
+			for (index, state) in history_attachment.attachments.iter().zip(history_attachment.states.iter_mut()) {
+				*state = attachment_states[index];
+			}
+			history_attachment.previous = 1 - history_attachment.previous;
 		}
+
+		Ok(())
 	}
 
 	fn import_resource(&mut self, resource: GraphImportedResource<'a>) -> usize {
@@ -1354,29 +3372,236 @@ impl<'a> RenderGraph<'a> {
 		id
 	}
 
+	// Render it with `dot -Tsvg`, passes only get ranked by execution order once something writes to the output.
+	pub fn dump_dot(&self) -> String {
+		const ATTACHMENT_COLOR: &str = "dodgerblue";
+		const BUFFER_COLOR: &str = "forestgreen";
+		const DESCRIPTOR_COLOR: &str = "darkorange";
+		const IMPORTED_COLOR: &str = "gray40";
+
+		let mut dot = String::new();
+		writeln!(dot, "digraph RenderGraph {{").unwrap();
+		writeln!(dot, "\trankdir=LR;").unwrap();
+		writeln!(dot, "\tnode [fontname=\"monospace\"];").unwrap();
+
+		let pass_order = self.resolve_pass_order();
+		for pass in self.passes.iter() {
+			let order = pass_order.as_ref().and_then(|order| order.iter().position(|p| *p == pass.pass));
+			let label = match order {
+				Some(order) => format!("#{} {}\\n{} cmds", order, pass.name, pass.cmds.len()),
+				None => format!("{}\\n{} cmds", pass.name, pass.cmds.len()),
+			};
+			let style = if order.is_some() || pass_order.is_none() { "solid" } else { "dashed" };
+			writeln!(dot, "\tpass_{} [shape=box, style={}, label=\"{}\"];", pass.pass.id, style, label).unwrap();
+		}
+
+		for (id, resource) in self.owned_resources.iter().enumerate() {
+			let (label, color) = match resource {
+				GraphOwnedResource::Attachment {
+					name,
+					width,
+					height,
+					size,
+					format,
+					history,
+					..
+				} => {
+					let history = match history {
+						Some(HistoryFrame::Previous) => "\\nlast frame",
+						Some(HistoryFrame::Current) => "\\nhistory",
+						None => "",
+					};
+
+					let size = match size {
+						AttachmentSize::Absolute(..) => format!("{}x{}", width, height),
+						AttachmentSize::OutputRelative { scale_x, scale_y } => {
+							format!("{}x{} output", scale_x * self.resolution_scale, scale_y * self.resolution_scale)
+						}
+					};
+
+					(format!("{}\\n{} {:?}{}", name, size, format, history), ATTACHMENT_COLOR)
+				}
+				GraphOwnedResource::Buffer { name, size, location, .. } => (format!("{}\\n{} bytes {:?}", name, size, location), BUFFER_COLOR),
+				GraphOwnedResource::GraphicsDescriptorSet { name, transient, .. } | GraphOwnedResource::ComputeDescriptorSet { name, transient, .. } => {
+					(format!("{}\\n{}", name, if *transient { "transient descriptor" } else { "descriptor" }), DESCRIPTOR_COLOR)
+				}
+				_ => continue,
+			};
+			writeln!(dot, "\tres_{} [shape=ellipse, color={}, label=\"{}\"];", id, color, label).unwrap();
+		}
+
+		for (id, resource) in self.imported_resources.iter().enumerate() {
+			let label = match resource {
+				GraphImportedResource::Shader(_) => continue,
+				GraphImportedResource::Mesh(mesh) => format!("imported mesh\\n{} indices", mesh.index_count),
+				GraphImportedResource::Buffer(buffer) => format!("imported buffer\\n{} bytes {:?}", buffer.size, buffer.location),
+				GraphImportedResource::PerFrameBuffer(buffer) => format!("imported per frame buffer\\n{} bytes{}", buffer.gpu_buffer(0).size, if buffer.is_staged() { " (staged)" } else { "" }),
+				GraphImportedResource::Texture(texture) => format!("imported texture\\n{}x{} {:?}", texture.width, texture.height, texture.format),
+				GraphImportedResource::TextureArray(textures) => format!("imported texture array\\n{} textures", textures.len()),
+			};
+			writeln!(dot, "\timported_{} [shape=cylinder, color={}, label=\"{}\"];", id, IMPORTED_COLOR, label).unwrap();
+		}
+
+		for pass in self.passes.iter() {
+			let p = pass.pass.id;
+			for attachment in pass.read_attachments.iter() {
+				writeln!(dot, "\tres_{} -> pass_{} [color={}];", attachment.id, p, ATTACHMENT_COLOR).unwrap();
+			}
+			for id in pass.written_attachments.iter() {
+				writeln!(dot, "\tpass_{} -> res_{} [color={}, style=bold];", p, id, ATTACHMENT_COLOR).unwrap();
+			}
+			for buffer in pass.read_buffers.iter() {
+				writeln!(dot, "\tres_{} -> pass_{} [color={}];", buffer.id, p, BUFFER_COLOR).unwrap();
+			}
+			for buffer in pass.write_buffers.iter() {
+				writeln!(dot, "\tpass_{} -> res_{} [color={}, style=bold];", p, buffer.id, BUFFER_COLOR).unwrap();
+			}
+
+			let mut imported = HashSet::new();
+			let mut descriptors = HashSet::new();
+			for cmd in pass.cmds.iter() {
+				match cmd {
+					PassCmd::BindGraphicsDescriptor { descriptor, .. } => {
+						descriptors.insert(descriptor.id);
+					}
+					PassCmd::BindComputeDescriptor { descriptor, .. } => {
+						descriptors.insert(descriptor.id);
+					}
+					PassCmd::DrawMesh { mesh } => {
+						imported.insert(mesh.id);
+					}
+					PassCmd::BindVertexBuffers { buffers } => {
+						imported.extend(buffers.iter().filter_map(|(b, _)| match b {
+							GraphBufferBinding::ImportedBuffer(b) => Some(b.id),
+							GraphBufferBinding::Buffer(_) => None,
+						}));
+					}
+					PassCmd::BindIndexBuffer {
+						buffer: GraphBufferBinding::ImportedBuffer(b),
+						..
+					}
+					| PassCmd::DrawIndirect {
+						buffer: GraphBufferBinding::ImportedBuffer(b),
+						..
+					} => {
+						imported.insert(b.id);
+					}
+					_ => {}
+				}
+			}
+
+			for id in descriptors {
+				writeln!(dot, "\tres_{} -> pass_{} [color={}];", id, p, DESCRIPTOR_COLOR).unwrap();
+			}
+			for id in imported {
+				writeln!(dot, "\timported_{} -> pass_{} [color={}];", id, p, IMPORTED_COLOR).unwrap();
+			}
+			for buffer in pass.write_imported_buffers.iter() {
+				writeln!(dot, "\tpass_{} -> imported_{} [color={}, style=bold];", p, buffer.id, IMPORTED_COLOR).unwrap();
+			}
+		}
+
+		for (id, resource) in self.owned_resources.iter().enumerate() {
+			let bindings = match resource {
+				GraphOwnedResource::GraphicsDescriptorSet { bindings, .. } | GraphOwnedResource::ComputeDescriptorSet { bindings, .. } => bindings,
+				_ => continue,
+			};
+
+			for (_, binding) in bindings.iter() {
+				let (src, color) = match binding {
+					GraphOwnedResourceDescriptorBinding::ImportedBuffer(b) => (format!("imported_{}", b.id), IMPORTED_COLOR),
+					GraphOwnedResourceDescriptorBinding::ImportedTexture(t) => (format!("imported_{}", t.id), IMPORTED_COLOR),
+					GraphOwnedResourceDescriptorBinding::ImportedTextureArray(t) => (format!("imported_{}", t.id), IMPORTED_COLOR),
+					GraphOwnedResourceDescriptorBinding::Buffer(b) => (format!("res_{}", b.id), BUFFER_COLOR),
+					GraphOwnedResourceDescriptorBinding::MutableBuffer(b) => (format!("res_{}", b.id), BUFFER_COLOR),
+					GraphOwnedResourceDescriptorBinding::Attachment(a) => (format!("res_{}", a.id), ATTACHMENT_COLOR),
+					GraphOwnedResourceDescriptorBinding::MutableAttachment(a) => (format!("res_{}", a.id), ATTACHMENT_COLOR),
+					GraphOwnedResourceDescriptorBinding::OutputStorage(_) => continue,
+				};
+				writeln!(dot, "\t{} -> res_{} [color={}, style=dashed];", src, id, color).unwrap();
+			}
+		}
+
+		if let Some(pass_order) = pass_order {
+			for pair in pass_order.windows(2) {
+				writeln!(dot, "\tpass_{} -> pass_{} [style=invis, weight=10];", pair[0].id, pair[1].id).unwrap();
+			}
+		}
+
+		writeln!(dot, "}}").unwrap();
+		dot
+	}
+
 	fn record_pass(&mut self, pass: RecordedPass) {
 		self.passes.push(pass);
 	}
 }
 
+// What was bound when a sorted draw was recorded, so that the draw can be moved around and still draw with it.
+#[derive(Clone, Default, PartialEq)]
+struct SortedDrawState {
+	pipeline: Option<GraphRasterPipelineHandle>,
+	descriptors: Vec<(u32, GraphGraphicsDescriptorHandle, GraphRasterPipelineHandle)>,
+	push_constants: Vec<(u32, Vec<u8>, GraphRasterPipelineHandle)>,
+}
+
+struct SortedDraw {
+	key: u64,
+	mesh: GraphImportedMeshHandle,
+	state: usize,
+}
+
+#[derive(Default)]
+struct SortedDraws {
+	current: SortedDrawState,
+	states: Vec<SortedDrawState>,
+	draws: Vec<SortedDraw>,
+}
+
+impl SortedDraws {
+	fn emit_state_changes(cmds: &mut Vec<PassCmd>, from: &SortedDrawState, to: &SortedDrawState) {
+		let pipeline_changed = from.pipeline != to.pipeline;
+		if let Some(pipeline) = to.pipeline.filter(|_| pipeline_changed) {
+			cmds.push(PassCmd::BindRasterPipeline { pipeline });
+		}
+
+		for &(set, descriptor, pipeline) in to.descriptors.iter() {
+			if pipeline_changed || !from.descriptors.contains(&(set, descriptor, pipeline)) {
+				cmds.push(PassCmd::BindGraphicsDescriptor { set, descriptor, pipeline });
+			}
+		}
+
+		for push in to.push_constants.iter() {
+			if pipeline_changed || !from.push_constants.contains(push) {
+				let (offset, data, pipeline) = push.clone();
+				cmds.push(PassCmd::PushConstants { pipeline, offset, data });
+			}
+		}
+	}
+}
+
 pub struct PassBuilder<'a, 'b> {
 	graph: &'b mut RenderGraph<'a>,
 	pass: PassHandle,
 	recorded: Option<RecordedPass>,
+	sorted: SortedDraws,
 }
 
 impl<'a, 'b> PassBuilder<'a, 'b> {
 	pub fn add_attachment(&mut self, desc: AttachmentDesc) -> MutableGraphAttachmentHandle {
+		let (width, height) = desc.size.absolute().unwrap_or_default();
 		let id = self.graph.create_resource(
 			self.pass,
 			GraphOwnedResource::Attachment {
 				name: desc.name,
-				width: desc.width,
-				height: desc.height,
+				width,
+				height,
+				size: desc.size,
 				format: desc.format,
 				load_op: desc.load_op,
 				store_op: desc.store_op,
 				usage: desc.usage,
+				history: None,
 			},
 		);
 
@@ -1385,16 +3610,73 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 			layout: ImageLayout::Undefined,
 			stage: ash::vk::PipelineStageFlags::empty(),
 			access: ash::vk::AccessFlags::empty(),
+			usage: desc.usage,
 		}
 	}
 
-	pub fn add_buffer(&mut self, desc: BufferDesc) -> MutableGraphBufferHandle {
-		let id = self.graph.create_resource(
-			self.pass,
-			GraphOwnedResource::Buffer {
-				name: desc.name,
-				size: desc.size,
-				usage: desc.usage,
+	// For temporal effects, returns last frame's version of the attachment (cleared to zero at first) and this frame's one to write.
+	pub fn add_history_attachment(&mut self, desc: AttachmentDesc) -> (GraphAttachmentHandle, MutableGraphAttachmentHandle) {
+		assert!(
+			self.graph.owned_resources.iter().all(|r| r.history_key().map_or(true, |(key, _)| key.name != desc.name)),
+			"History attachment {} was added more than once!",
+			desc.name
+		);
+		let Some((width, height)) = desc.size.absolute() else {
+			panic!("History attachment {} has to have an absolute size!", desc.name);
+		};
+
+		let resource = |history| GraphOwnedResource::Attachment {
+			name: desc.name,
+			width,
+			height,
+			size: desc.size,
+			format: desc.format,
+			load_op: desc.load_op,
+			store_op: desc.store_op,
+			usage: desc.usage,
+			history: Some(history),
+		};
+
+		let current = self.graph.create_resource(self.pass, resource(HistoryFrame::Current));
+
+		let previous = self.graph.owned_resources.len();
+		self.graph.owned_resources.push(resource(HistoryFrame::Previous));
+
+		let (key, _) = self.graph.owned_resources[previous].history_key().unwrap();
+		let state = self
+			.graph
+			.cache
+			.attachment_cache
+			.history
+			.get(&key)
+			.map_or(AttachmentState::CLEARED, |history_attachment| history_attachment.states[history_attachment.previous]);
+
+		let previous = MutableGraphAttachmentHandle {
+			id: previous,
+			layout: state.layout,
+			stage: state.stage,
+			access: state.access,
+			usage: desc.usage,
+		};
+
+		let current = MutableGraphAttachmentHandle {
+			id: current,
+			layout: ImageLayout::Undefined,
+			stage: ash::vk::PipelineStageFlags::empty(),
+			access: ash::vk::AccessFlags::empty(),
+			usage: desc.usage,
+		};
+
+		(previous.read(), current)
+	}
+
+	pub fn add_buffer(&mut self, desc: BufferDesc) -> MutableGraphBufferHandle {
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::Buffer {
+				name: desc.name,
+				size: desc.size,
+				usage: desc.usage,
 				location: desc.location,
 			},
 		);
@@ -1406,6 +3688,16 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 		}
 	}
 
+	// For imported buffers that shaders in this pass write to. Without this the graph assumes bound imported buffers are only read.
+	pub fn write_imported_buffer(&mut self, buffer: &'a GpuBuffer) {
+		let id = self.graph.import_resource(GraphImportedResource::Buffer(buffer));
+		let buffer = GraphImportedBufferHandle { id };
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.write_imported_buffers.insert(buffer);
+		recorded.shader_write_imported_buffers.insert(buffer);
+	}
+
 	fn decl_read_attachment(&mut self, attachment: GraphAttachmentHandle) {
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.read_attachments.insert(attachment);
@@ -1414,6 +3706,7 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 	fn decl_write_attachment(&mut self, attachment: MutableGraphAttachmentHandle) {
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.write_attachments.insert(attachment);
+		recorded.written_attachments.insert(attachment.id);
 	}
 
 	fn decl_read_buffer(&mut self, buffer: GraphBufferHandle) {
@@ -1433,9 +3726,20 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 			id: self.graph.import_resource(GraphImportedResource::Shader(desc.cs)),
 		};
 
-		let descriptor_layouts = desc.descriptor_layouts.to_vec();
+		let descriptor_layouts = desc.descriptor_layouts.iter().map(|info| info.to_bindings()).collect();
+		let specialization = desc.specialization.to_vec();
+		let local_size = desc.local_size;
 
-		let id = self.graph.create_resource(self.pass, GraphOwnedResource::ComputePipeline { name, cs, descriptor_layouts });
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::ComputePipeline {
+				name,
+				cs,
+				descriptor_layouts,
+				specialization,
+				local_size,
+			},
+		);
 
 		GraphComputePipelineHandle { id }
 	}
@@ -1455,14 +3759,42 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 			None
 		};
 
-		let descriptor_layouts = desc.descriptor_layouts.to_vec();
+		let gs = if let Some(gs) = desc.gs {
+			Some(GraphImportedShaderHandle {
+				id: self.graph.import_resource(GraphImportedResource::Shader(gs)),
+			})
+		} else {
+			None
+		};
+
+		let descriptor_layouts = desc.descriptor_layouts.iter().map(|info| info.to_bindings()).collect();
 		let render_pass = desc.render_pass;
 		let depth_compare_op = desc.depth_compare_op;
 		let depth_write = desc.depth_write;
+		let depth_bias = desc.depth_bias;
+		let blend_mode = desc.blend_mode;
 		let face_cull = desc.face_cull;
 		let push_constant_bytes = desc.push_constant_bytes;
 		let vertex_input_info = desc.vertex_input_info;
 		let polygon_mode = desc.polygon_mode;
+		let specialization = desc.specialization.to_vec();
+
+		if ps.is_none() {
+			let color_render_pass = match &self.graph.owned_resources[render_pass.id] {
+				GraphOwnedResource::RenderPass { name, color_attachments, .. } if !color_attachments.is_empty() => Some(*name),
+				GraphOwnedResource::OutputRenderPass {} => Some("output"),
+				_ => None,
+			};
+
+			if let Some(render_pass_name) = color_render_pass {
+				panic!(
+					"Pipeline \"{}\" in pass \"{}\" has no pixel shader but render pass \"{}\" has color attachments!",
+					name,
+					self.recorded.as_ref().unwrap().name,
+					render_pass_name
+				);
+			}
+		}
 
 		let id = self.graph.create_resource(
 			self.pass,
@@ -1470,22 +3802,67 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 				name,
 				vs,
 				ps,
+				gs,
 				descriptor_layouts,
 				render_pass,
 				depth_compare_op,
 				depth_write,
+				depth_bias,
+				blend_mode,
 				face_cull,
 				push_constant_bytes,
 				vertex_input_info,
 				polygon_mode,
+				specialization,
 			},
 		);
 
 		GraphRasterPipelineHandle { id }
 	}
 
+	// For depth prepasses and shadow maps, the render pass can't have any color attachments.
+	pub fn add_depth_only_pipeline(
+		&mut self,
+		name: &'static str,
+		vs: &'a Shader,
+		descriptor_layouts: &[&DescriptorSetInfo],
+		vertex_input_info: VertexInputInfo,
+		render_pass: GraphRenderPassHandle,
+		face_cull: FaceCullMode,
+		depth_bias: Option<DepthBias>,
+	) -> GraphRasterPipelineHandle {
+		self.add_raster_pipeline(RasterPipelineDesc {
+			name,
+			vs,
+			ps: None,
+			gs: None,
+			descriptor_layouts,
+			render_pass,
+			depth_compare_op: Some(DepthCompareOp::Greater),
+			depth_write: true,
+			depth_bias,
+			blend_mode: BlendMode::Opaque,
+			face_cull,
+			push_constant_bytes: 0,
+			vertex_input_info,
+			polygon_mode: PolygonMode::Fill,
+			specialization: &[],
+		})
+	}
+
 	pub fn add_render_pass(&mut self, desc: RenderPassDesc) -> GraphRenderPassHandle {
 		let name = desc.name;
+
+		for attachment in desc.color_attachments.iter().map(|a| &**a).chain(desc.depth_attachment.as_deref()) {
+			if self.graph.attachment_load_op(attachment.id) == LoadOp::Load && attachment.layout == ImageLayout::Undefined {
+				panic!(
+					"Pass {} loads attachment {}, but nothing wrote to it earlier in the frame!",
+					self.recorded.as_ref().unwrap().name,
+					self.graph.owned_resources[attachment.id].name()
+				);
+			}
+		}
+
 		let color_attachments = desc
 			.color_attachments
 			.into_iter()
@@ -1504,6 +3881,9 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 			Some(*a)
 		});
 
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.written_attachments.extend(color_attachments.iter().chain(depth_attachment.iter()).map(|a| a.id));
+
 		let id = self.graph.create_resource(
 			self.pass,
 			GraphOwnedResource::RenderPass {
@@ -1517,12 +3897,39 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 	}
 
 	pub fn add_output_render_pass(&mut self) -> GraphRenderPassHandle {
-		let id = self.graph.create_resource(self.pass, GraphOwnedResource::OutputRenderPass {});
+		let pass = self.pass;
+		let existing = self
+			.graph
+			.owned_resources
+			.iter()
+			.enumerate()
+			.find(|&(id, r)| matches!(r, GraphOwnedResource::OutputRenderPass {}) && self.graph.resource_to_owning_pass[&id] == pass)
+			.map(|(id, _)| id);
+
+		let id = existing.unwrap_or_else(|| self.graph.create_resource(pass, GraphOwnedResource::OutputRenderPass {}));
 
 		GraphRenderPassHandle { id }
 	}
 
+	// Lets a compute shader write the swapchain image as a RWTexture2D, see GraphicsContext::supports_output_storage.
+	pub fn add_output_storage_binding(&mut self) -> GraphOutputStorageHandle {
+		let pass = self.pass;
+		let existing = self
+			.graph
+			.owned_resources
+			.iter()
+			.enumerate()
+			.find(|&(id, r)| matches!(r, GraphOwnedResource::OutputStorage {}) && self.graph.resource_to_owning_pass[&id] == pass)
+			.map(|(id, _)| id);
+
+		let id = existing.unwrap_or_else(|| self.graph.create_resource(pass, GraphOwnedResource::OutputStorage {}));
+
+		GraphOutputStorageHandle { id }
+	}
+
 	fn add_descriptor_set<'c>(&mut self, desc: DescriptorDesc<'a, 'c>) -> Vec<(u32, GraphOwnedResourceDescriptorBinding)> {
+		let descriptor_layout = desc.descriptor_layout;
+		let validation = self.graph.validation;
 		desc.bindings
 			.into_iter()
 			.map(|(i, binding)| {
@@ -1530,13 +3937,45 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 					*i,
 					match binding {
 						DescriptorBindingDesc::ImportedBuffer(buffer) => {
+							if validation {
+								validate_buffer_binding(desc.name, *i, descriptor_layout.bindings.get(&*i).copied(), buffer.usage);
+							}
+
 							let id = self.graph.import_resource(GraphImportedResource::Buffer(buffer));
 							GraphOwnedResourceDescriptorBinding::ImportedBuffer(GraphImportedBufferHandle { id })
 						}
+						DescriptorBindingDesc::PerFrameBuffer(buffer) => {
+							if validation {
+								validate_buffer_binding(desc.name, *i, descriptor_layout.bindings.get(&*i).copied(), buffer.gpu_buffer(0).usage);
+							}
+
+							let id = self.graph.import_resource(GraphImportedResource::PerFrameBuffer(buffer));
+							GraphOwnedResourceDescriptorBinding::ImportedBuffer(GraphImportedBufferHandle { id })
+						}
 						DescriptorBindingDesc::ImportedTexture(texture) => {
+							match descriptor_layout.bindings.get(&*i) {
+								Some(DescriptorBindingType::TextureCube) => assert!(texture.format.is_cubemap(), "{} expects a cubemap at binding {}!", desc.name, i),
+								Some(DescriptorBindingType::Texture2D) => assert!(!texture.format.is_cubemap(), "{} expects a 2D texture at binding {}!", desc.name, i),
+								_ => {}
+							}
+
+							if validation {
+								validate_texture_binding(desc.name, *i, descriptor_layout.bindings.get(&*i).copied(), texture.usage);
+							}
+
 							let id = self.graph.import_resource(GraphImportedResource::Texture(texture));
 							GraphOwnedResourceDescriptorBinding::ImportedTexture(GraphImportedTextureHandle { id })
 						}
+						DescriptorBindingDesc::TextureArray(textures) => {
+							if validation {
+								for texture in textures.iter() {
+									validate_texture_binding(desc.name, *i, descriptor_layout.bindings.get(&*i).copied(), texture.usage);
+								}
+							}
+
+							let id = self.graph.import_resource(GraphImportedResource::TextureArray(textures));
+							GraphOwnedResourceDescriptorBinding::ImportedTextureArray(GraphImportedTextureHandle { id })
+						}
 						DescriptorBindingDesc::Buffer(buffer) => {
 							self.decl_read_buffer(*buffer);
 							GraphOwnedResourceDescriptorBinding::Buffer(*buffer)
@@ -1558,6 +3997,13 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 							self.decl_write_attachment(**attachment);
 							GraphOwnedResourceDescriptorBinding::MutableAttachment(**attachment)
 						}
+						DescriptorBindingDesc::OutputStorage(output) => {
+							assert_eq!(
+								self.graph.resource_to_owning_pass[&output.id], self.pass,
+								"The output storage binding has to be bound in the pass that added it!"
+							);
+							GraphOwnedResourceDescriptorBinding::OutputStorage(*output)
+						}
 					},
 				)
 			})
@@ -1566,22 +4012,74 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 
 	pub fn add_graphics_descriptor_set<'c>(&mut self, desc: DescriptorDesc<'a, 'c>) -> GraphGraphicsDescriptorHandle {
 		let name = desc.name;
-		let descriptor_layout = desc.descriptor_layout;
+		let descriptor_layout = desc.descriptor_layout.to_bindings();
 
 		// TODO(Brandon): Validate bindings with descriptor set info.
 		let bindings = self.add_descriptor_set(desc);
-		let id = self.graph.create_resource(self.pass, GraphOwnedResource::GraphicsDescriptorSet { name, bindings, descriptor_layout });
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::GraphicsDescriptorSet {
+				name,
+				bindings,
+				descriptor_layout,
+				transient: false,
+			},
+		);
+
+		GraphGraphicsDescriptorHandle { id }
+	}
+
+	pub fn add_transient_graphics_descriptor_set<'c>(&mut self, desc: DescriptorDesc<'a, 'c>) -> GraphGraphicsDescriptorHandle {
+		let name = desc.name;
+		let descriptor_layout = desc.descriptor_layout.to_bindings();
+
+		let bindings = self.add_descriptor_set(desc);
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::GraphicsDescriptorSet {
+				name,
+				bindings,
+				descriptor_layout,
+				transient: true,
+			},
+		);
 
 		GraphGraphicsDescriptorHandle { id }
 	}
 
 	pub fn add_compute_descriptor_set<'c>(&mut self, desc: DescriptorDesc<'a, 'c>) -> GraphComputeDescriptorHandle {
 		let name = desc.name;
-		let descriptor_layout = desc.descriptor_layout;
+		let descriptor_layout = desc.descriptor_layout.to_bindings();
 
 		// TODO(Brandon): Validate bindings with descriptor set info.
 		let bindings = self.add_descriptor_set(desc);
-		let id = self.graph.create_resource(self.pass, GraphOwnedResource::ComputeDescriptorSet { name, bindings, descriptor_layout });
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::ComputeDescriptorSet {
+				name,
+				bindings,
+				descriptor_layout,
+				transient: false,
+			},
+		);
+
+		GraphComputeDescriptorHandle { id }
+	}
+
+	pub fn add_transient_compute_descriptor_set<'c>(&mut self, desc: DescriptorDesc<'a, 'c>) -> GraphComputeDescriptorHandle {
+		let name = desc.name;
+		let descriptor_layout = desc.descriptor_layout.to_bindings();
+
+		let bindings = self.add_descriptor_set(desc);
+		let id = self.graph.create_resource(
+			self.pass,
+			GraphOwnedResource::ComputeDescriptorSet {
+				name,
+				bindings,
+				descriptor_layout,
+				transient: true,
+			},
+		);
 
 		GraphComputeDescriptorHandle { id }
 	}
@@ -1594,11 +4092,34 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 	}
 
 	pub fn cmd_bind_raster_pipeline(&mut self, pipeline: GraphRasterPipelineHandle) {
+		self.sorted.current.pipeline = Some(pipeline);
+
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::BindRasterPipeline { pipeline });
 	}
 
+	fn decl_read_imported_buffers(&mut self, descriptor_id: usize) {
+		let bindings = match &self.graph.owned_resources[descriptor_id] {
+			GraphOwnedResource::GraphicsDescriptorSet { bindings, .. } | GraphOwnedResource::ComputeDescriptorSet { bindings, .. } => bindings,
+			_ => unreachable!("Invalid descriptor!"),
+		};
+
+		let recorded = self.recorded.as_mut().unwrap();
+		for (_, binding) in bindings.iter() {
+			if let &GraphOwnedResourceDescriptorBinding::ImportedBuffer(buffer) = binding {
+				recorded.read_imported_buffers.insert(buffer);
+			}
+		}
+	}
+
 	pub fn cmd_bind_graphics_descriptor(&mut self, descriptor: GraphGraphicsDescriptorHandle, set: u32, pipeline: GraphRasterPipelineHandle) {
+		self.decl_read_imported_buffers(descriptor.id);
+
+		let descriptors = &mut self.sorted.current.descriptors;
+		descriptors.retain(|&(bound_set, _, _)| bound_set != set);
+		descriptors.push((set, descriptor, pipeline));
+		descriptors.sort_by_key(|&(set, _, _)| set);
+
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::BindGraphicsDescriptor { set, descriptor, pipeline });
 	}
@@ -1611,6 +4132,99 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 		recorded.cmds.push(PassCmd::DrawMesh { mesh });
 	}
 
+	// Like cmd_draw_mesh, but recorded by cmd_flush_sorted in order of the key, usually quantized view space depth.
+	pub fn cmd_draw_mesh_sorted(&mut self, mesh: &'a Mesh, sort_key: u64) {
+		assert!(self.sorted.current.pipeline.is_some(), "A raster pipeline has to be bound before drawing sorted!");
+
+		let id = self.graph.import_resource(GraphImportedResource::Mesh(mesh));
+		let mesh = GraphImportedMeshHandle { id };
+
+		if self.sorted.states.last() != Some(&self.sorted.current) {
+			self.sorted.states.push(self.sorted.current.clone());
+		}
+
+		self.sorted.draws.push(SortedDraw {
+			key: sort_key,
+			mesh,
+			state: self.sorted.states.len() - 1,
+		});
+	}
+
+	// Lowest key first when ascending, with ties kept in draw order. Whatever was bound before the flush is bound again afterwards.
+	pub fn cmd_flush_sorted(&mut self, ascending: bool) {
+		let mut draws = std::mem::take(&mut self.sorted.draws);
+		let states = std::mem::take(&mut self.sorted.states);
+		if draws.is_empty() {
+			return;
+		}
+
+		if ascending {
+			draws.sort_by_key(|draw| (draw.key, draw.state));
+		} else {
+			draws.sort_by_key(|draw| (std::cmp::Reverse(draw.key), draw.state));
+		}
+
+		let recorded = self.recorded.as_mut().unwrap();
+		let mut bound = &self.sorted.current;
+		for draw in draws.iter() {
+			SortedDraws::emit_state_changes(&mut recorded.cmds, bound, &states[draw.state]);
+			bound = &states[draw.state];
+
+			recorded.cmds.push(PassCmd::DrawMesh { mesh: draw.mesh });
+		}
+
+		SortedDraws::emit_state_changes(&mut recorded.cmds, bound, &self.sorted.current);
+	}
+
+	fn add_buffer_binding(&mut self, buffer: BufferBindingDesc<'a>, dst_stage: ash::vk::PipelineStageFlags, dst_access: ash::vk::AccessFlags) -> GraphBufferBinding {
+		match buffer {
+			BufferBindingDesc::ImportedBuffer(buffer) => {
+				let id = self.graph.import_resource(GraphImportedResource::Buffer(buffer));
+				let buffer = GraphImportedBufferHandle { id };
+				self.recorded.as_mut().unwrap().read_imported_buffers.insert(buffer);
+				GraphBufferBinding::ImportedBuffer(buffer)
+			}
+			BufferBindingDesc::Buffer(buffer) => {
+				let buffer = GraphBufferHandle { dst_stage, dst_access, ..buffer };
+				self.decl_read_buffer(buffer);
+				GraphBufferBinding::Buffer(buffer)
+			}
+		}
+	}
+
+	pub fn cmd_bind_vertex_buffers(&mut self, buffers: &[(BufferBindingDesc<'a>, u64)]) {
+		let buffers = buffers
+			.iter()
+			.map(|&(buffer, offset)| {
+				(
+					self.add_buffer_binding(buffer, ash::vk::PipelineStageFlags::VERTEX_INPUT, ash::vk::AccessFlags::VERTEX_ATTRIBUTE_READ),
+					offset,
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::BindVertexBuffers { buffers });
+	}
+
+	pub fn cmd_bind_index_buffer(&mut self, buffer: BufferBindingDesc<'a>, offset: u64, index_type: IndexType) {
+		let buffer = self.add_buffer_binding(buffer, ash::vk::PipelineStageFlags::VERTEX_INPUT, ash::vk::AccessFlags::INDEX_READ);
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::BindIndexBuffer { buffer, offset, index_type });
+	}
+
+	pub fn cmd_draw_indexed(&mut self, index_count: u32, instance_count: u32, first_index: u32, vertex_offset: i32, first_instance: u32) {
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::DrawIndexed {
+			index_count,
+			instance_count,
+			first_index,
+			vertex_offset,
+			first_instance,
+		});
+	}
+
 	pub fn cmd_draw(&mut self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::Draw {
@@ -1621,16 +4235,64 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 		});
 	}
 
+	// The buffer needs BufferUsage::IndirectBuffer, and a pass whose shader writes it has to call write_imported_buffer.
+	pub fn cmd_draw_indirect(&mut self, buffer: BufferBindingDesc<'a>, offset: u64, draw_count: u32, stride: u32) {
+		self.push_draw_indirect(buffer, offset, draw_count, stride, false);
+	}
+
+	pub fn cmd_draw_indexed_indirect(&mut self, buffer: BufferBindingDesc<'a>, offset: u64, draw_count: u32, stride: u32) {
+		self.push_draw_indirect(buffer, offset, draw_count, stride, true);
+	}
+
+	fn push_draw_indirect(&mut self, buffer: BufferBindingDesc<'a>, offset: u64, draw_count: u32, stride: u32, indexed: bool) {
+		let usage = match buffer {
+			BufferBindingDesc::ImportedBuffer(buffer) => buffer.usage,
+			BufferBindingDesc::Buffer(buffer) => match &self.graph.owned_resources[buffer.id] {
+				GraphOwnedResource::Buffer { usage, .. } => *usage,
+				_ => unreachable!("Invalid buffer!"),
+			},
+		};
+		assert!(usage.contains(BufferUsage::IndirectBuffer), "Buffer must have BufferUsage::IndirectBuffer to draw indirect!");
+
+		let buffer = self.add_buffer_binding(buffer, ash::vk::PipelineStageFlags::DRAW_INDIRECT, ash::vk::AccessFlags::INDIRECT_COMMAND_READ);
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::DrawIndirect {
+			buffer,
+			offset,
+			draw_count,
+			stride,
+			indexed,
+		});
+	}
+
 	pub fn cmd_bind_compute_pipeline(&mut self, pipeline: GraphComputePipelineHandle) {
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::BindComputePipeline { pipeline });
 	}
 
 	pub fn cmd_bind_compute_descriptor(&mut self, descriptor: GraphComputeDescriptorHandle, set: u32, pipeline: GraphComputePipelineHandle) {
+		self.decl_read_imported_buffers(descriptor.id);
+
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::BindComputeDescriptor { set, descriptor, pipeline });
 	}
 
+	// Stretches the attachment over the output policy's viewport, outside of any render pass.
+	pub fn cmd_blit_attachment_to_output(&mut self, attachment: GraphAttachmentHandle, filter: Filter) {
+		assert!(
+			matches!(attachment.final_layout, ImageLayout::TransferSrcOptimal | ImageLayout::General),
+			"Attachments have to be read as TransferSrcOptimal (or General) to be blitted, not {:?}!",
+			attachment.final_layout
+		);
+
+		self.add_output_render_pass();
+		self.decl_read_attachment(attachment);
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::BlitToOutput { attachment, filter });
+	}
+
 	pub fn cmd_dispatch(&mut self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::Dispatch {
@@ -1640,14 +4302,535 @@ impl<'a, 'b> PassBuilder<'a, 'b> {
 		});
 	}
 
+	pub fn cmd_dispatch_for_size(&mut self, pixel_size: UVec2, local_size: UVec2) {
+		let group_count = dispatch_group_count(pixel_size, local_size);
+		self.cmd_dispatch(group_count.x, group_count.y, 1);
+	}
+
+	pub fn cmd_dispatch_for_size_auto(&mut self, pixel_size: UVec2) {
+		let recorded = self.recorded.as_ref().unwrap();
+		let pipeline = recorded
+			.cmds
+			.iter()
+			.rev()
+			.find_map(|cmd| match cmd {
+				&PassCmd::BindComputePipeline { pipeline } => Some(pipeline),
+				_ => None,
+			})
+			.unwrap_or_else(|| panic!("Pass {} dispatched for a size without a bound compute pipeline!", recorded.name));
+
+		let local_size = match &self.graph.owned_resources[pipeline.id] {
+			GraphOwnedResource::ComputePipeline { local_size, .. } => *local_size,
+			_ => unreachable!("Invalid compute pipeline!"),
+		};
+
+		self.cmd_dispatch_for_size(pixel_size, UVec2::new(local_size[0], local_size[1]));
+	}
+
+	// Flushes anything drawn with cmd_draw_mesh_sorted front to back (ascending) first, call cmd_flush_sorted beforehand for any other order.
 	pub fn cmd_end_render_pass(&mut self) {
+		self.cmd_flush_sorted(true);
+
 		let recorded = self.recorded.as_mut().unwrap();
 		recorded.cmds.push(PassCmd::EndRenderPass {});
 	}
+
+	// Draws only land within this part of the attachments until the next render pass begins, mostly for ShadowAtlas regions.
+	pub fn cmd_set_viewport(&mut self, offset: UVec2, extent: UVec2) {
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::SetViewport { offset, extent });
+	}
+
+	pub fn cmd_push_constants(&mut self, pipeline: GraphRasterPipelineHandle, offset: u32, data: &[u8]) {
+		let push_constant_bytes = match &self.graph.owned_resources[pipeline.id] {
+			GraphOwnedResource::RasterPipeline { push_constant_bytes, .. } => *push_constant_bytes,
+			_ => unreachable!("Invalid raster pipeline!"),
+		};
+
+		assert!(offset as usize + data.len() <= push_constant_bytes, "Push constants don't fit in the pipeline's push constant range!");
+
+		let push_constants = &mut self.sorted.current.push_constants;
+		push_constants.retain(|&(pushed_offset, _, _)| pushed_offset != offset);
+		push_constants.push((offset, data.to_vec(), pipeline));
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::PushConstants {
+			pipeline,
+			offset,
+			data: data.to_vec(),
+		});
+	}
+
+	pub fn cmd_push_constant_struct<const S: usize>(&mut self, pipeline: GraphRasterPipelineHandle, constants: &impl PushConstant<S>) {
+		self.cmd_push_constants(pipeline, 0, &constants.as_buffer());
+	}
+
+	// Everything up to and including this pass gets submitted as soon as it's recorded, unless the cache's SubmitBatching is Single.
+	pub fn submit_hint(&mut self) {
+		self.recorded.as_mut().unwrap().submit_hint = true;
+	}
+
+	// Results show up in GraphicsContext::query_results a few frames later. Occlusion queries have to begin and end within the same render pass.
+	pub fn cmd_begin_query(&mut self, slot: QuerySlot) {
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::BeginQuery { slot });
+	}
+
+	pub fn cmd_end_query(&mut self, slot: QuerySlot) {
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::EndQuery { slot });
+	}
+
+	// Copies data in on the GPU timeline, so passes recorded before this still read the old contents. Needs BufferUsage::TransferDst.
+	pub fn cmd_update_imported_buffer(&mut self, buffer: &'a GpuBuffer, offset: u64, data: &[u8]) {
+		assert!(
+			buffer.usage.contains(BufferUsage::TransferDst),
+			"Imported buffer must have BufferUsage::TransferDst to be updated in a pass!"
+		);
+		assert!(offset as usize + data.len() <= buffer.size, "Cannot update imported buffer with data that is too long!");
+
+		let id = self.graph.import_resource(GraphImportedResource::Buffer(buffer));
+		let buffer = GraphImportedBufferHandle { id };
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.write_imported_buffers.insert(buffer);
+		recorded.cmds.push(PassCmd::UpdateImportedBuffer { buffer, offset, data: data.to_vec() });
+	}
+
+	pub fn cmd_fill_buffer(&mut self, buffer: &mut MutableGraphBufferHandle, value: u32) {
+		match &self.graph.owned_resources[buffer.id] {
+			GraphOwnedResource::Buffer { size, usage, .. } => {
+				assert!(usage.contains(BufferUsage::TransferDst), "Buffer must have BufferUsage::TransferDst to be filled in a pass!");
+				assert!(size % 4 == 0, "Cannot fill a buffer whose size is not a multiple of 4!");
+			}
+			_ => unreachable!("Invalid buffer!"),
+		}
+
+		buffer.stage = ash::vk::PipelineStageFlags::TRANSFER;
+		buffer.access = ash::vk::AccessFlags::TRANSFER_WRITE;
+		self.decl_write_buffer(*buffer);
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.cmds.push(PassCmd::FillBuffer { buffer: *buffer, value });
+	}
+
+	pub fn cmd_clear_attachment(&mut self, attachment: &mut MutableGraphAttachmentHandle, value: ClearValue) {
+		assert!(
+			attachment.usage.contains(TextureUsage::TRANSFER_DST),
+			"Attachment must have TextureUsage::TRANSFER_DST to be cleared in a pass!"
+		);
+
+		attachment.layout = ImageLayout::TransferDstOptimal;
+		attachment.stage = ash::vk::PipelineStageFlags::TRANSFER;
+		attachment.access = ash::vk::AccessFlags::TRANSFER_WRITE;
+
+		let recorded = self.recorded.as_mut().unwrap();
+		recorded.written_attachments.insert(attachment.id);
+		recorded.cmds.push(PassCmd::ClearAttachment { attachment: *attachment, value });
+	}
 }
 
 impl<'a, 'b> Drop for PassBuilder<'a, 'b> {
 	fn drop(&mut self) {
+		assert!(self.sorted.draws.is_empty(), "Sorted draws have to be flushed before the pass ends!");
 		self.graph.record_pass(self.recorded.take().unwrap());
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn dispatch_group_count_rounds_up() {
+		let groups = |size: u32| dispatch_group_count(UVec2::new(size, size), UVec2::new(16, 8)).to_array();
+
+		assert_eq!(groups(1), [1, 1]);
+		assert_eq!(groups(8), [1, 1]);
+		assert_eq!(groups(16), [1, 2]);
+		assert_eq!(groups(17), [2, 3]);
+		assert_eq!(groups(33), [3, 5]);
+		assert_eq!(groups(0), [0, 0]);
+
+		assert_eq!(dispatch_group_count(UVec2::new(1920, 1080), UVec2::ONE).to_array(), [1920, 1080]);
+		assert_eq!(dispatch_group_count(UVec2::splat(u32::MAX), UVec2::new(16, 1)).to_array(), [u32::MAX / 16 + 1, u32::MAX]);
+	}
+
+	#[test]
+	#[should_panic(expected = "Local size cannot be zero")]
+	fn dispatch_group_count_rejects_empty_groups() {
+		dispatch_group_count(UVec2::new(16, 16), UVec2::new(8, 0));
+	}
+
+	#[test]
+	fn reused_resource_slots_reject_stale_generations() {
+		let mut slots = ResourceSlots::default();
+		let first = slots.insert("first", 0);
+		let stale = slots.generation(first);
+		assert!(slots.is_current(first, stale));
+
+		assert!(slots.is_unused(first, UNUSED_RESOURCE_FRAMES + 1));
+		assert_eq!(slots.remove(first), "first");
+		assert!(!slots.is_current(first, stale));
+
+		let second = slots.insert("second", UNUSED_RESOURCE_FRAMES + 1);
+		assert_eq!(second, first);
+		assert_eq!(*slots.get(second), "second");
+		assert!(!slots.is_current(second, stale));
+		assert!(slots.is_current(second, slots.generation(second)));
+		assert!(!slots.is_unused(second, UNUSED_RESOURCE_FRAMES + 1));
+	}
+
+	fn scene_desc() -> AttachmentDesc {
+		AttachmentDesc {
+			name: "Scene",
+			size: AttachmentSize::Absolute(4, 4),
+			format: TextureFormat::RGBA8UNorm,
+			load_op: LoadOp::Load,
+			store_op: StoreOp::Store,
+			usage: TextureUsage::TRANSFER_DST | TextureUsage::TRANSFER_SRC | TextureUsage::SAMPLED,
+		}
+	}
+
+	// Records a single pass called "broken", with whatever record returns pushed straight into it, and validates the graph.
+	fn validate_pass(record: impl FnOnce(&mut PassBuilder) -> Vec<PassCmd>) {
+		let mut cache = RenderGraphCache::default();
+		let default_resources = DefaultResources::null();
+		let mut graph = RenderGraph::new(&mut cache, &default_resources);
+
+		{
+			let mut pass = graph.add_pass("broken");
+			let cmds = record(&mut pass);
+			pass.recorded.as_mut().unwrap().cmds.extend(cmds);
+		}
+
+		graph.validate();
+	}
+
+	fn begin(render_pass: GraphRenderPassHandle) -> PassCmd {
+		PassCmd::BeginRenderPass {
+			render_pass,
+			clear_values: Vec::new(),
+		}
+	}
+
+	fn draw() -> PassCmd {
+		PassCmd::Draw {
+			vertex_count: 3,
+			instance_count: 1,
+			first_vertex: 0,
+			first_instance: 0,
+		}
+	}
+
+	fn dispatch() -> PassCmd {
+		PassCmd::Dispatch {
+			group_count_x: 1,
+			group_count_y: 1,
+			group_count_z: 1,
+		}
+	}
+
+	#[test]
+	fn validation_accepts_a_valid_pass() {
+		validate_pass(|pass| {
+			let mut scene = pass.add_attachment(scene_desc());
+			pass.cmd_clear_attachment(&mut scene, ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 });
+			let render_pass = pass.add_output_render_pass();
+			vec![begin(render_pass), PassCmd::EndRenderPass {}]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "pass \"broken\" (Scene): attachment is both read and written by the pass")]
+	fn validation_catches_reading_a_written_attachment() {
+		validate_pass(|pass| {
+			let mut scene = pass.add_attachment(scene_desc());
+			pass.cmd_clear_attachment(&mut scene, ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 });
+			pass.decl_read_attachment(scene.read_as(ImageLayout::ShaderReadOnlyOptimal));
+			Vec::new()
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 0 (<not in this graph>): not a buffer created in this graph")]
+	fn validation_catches_resources_from_another_graph() {
+		validate_pass(|_| {
+			vec![PassCmd::FillBuffer {
+				buffer: MutableGraphBufferHandle {
+					id: 99,
+					stage: ash::vk::PipelineStageFlags::TRANSFER,
+					access: ash::vk::AccessFlags::TRANSFER_WRITE,
+				},
+				value: 0,
+			}]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 1 (Scene): not a raster pipeline created in this graph")]
+	fn validation_catches_the_wrong_kind_of_resource() {
+		validate_pass(|pass| {
+			let mut scene = pass.add_attachment(scene_desc());
+			pass.cmd_clear_attachment(&mut scene, ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 });
+			vec![PassCmd::BindRasterPipeline {
+				pipeline: GraphRasterPipelineHandle { id: scene.id },
+			}]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "render pass was never ended")]
+	fn validation_catches_unended_render_passes() {
+		validate_pass(|pass| {
+			let render_pass = pass.add_output_render_pass();
+			vec![begin(render_pass)]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 0: ended a render pass that was never begun")]
+	fn validation_catches_ending_a_render_pass_that_was_never_begun() {
+		validate_pass(|_| vec![PassCmd::EndRenderPass {}]);
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 0: draws have to be recorded inside of a render pass")]
+	fn validation_catches_draws_outside_of_a_render_pass() {
+		validate_pass(|_| vec![draw()]);
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 1 (output): only draws can be recorded inside of a render pass")]
+	fn validation_catches_dispatches_inside_of_a_render_pass() {
+		validate_pass(|pass| {
+			let render_pass = pass.add_output_render_pass();
+			vec![begin(render_pass), dispatch(), PassCmd::EndRenderPass {}]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 1: drawing without a raster pipeline bound")]
+	fn validation_catches_draws_without_a_pipeline() {
+		validate_pass(|pass| {
+			let render_pass = pass.add_output_render_pass();
+			vec![begin(render_pass), draw(), PassCmd::EndRenderPass {}]
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "at command 0: dispatching without a compute pipeline bound")]
+	fn validation_catches_dispatches_without_a_pipeline() {
+		validate_pass(|_| vec![dispatch()]);
+	}
+
+	#[test]
+	fn dump_dot_has_edges_for_two_passes() {
+		let mut cache = RenderGraphCache::default();
+		let default_resources = DefaultResources::null();
+		let mut graph = RenderGraph::new(&mut cache, &default_resources);
+
+		let scene = {
+			let mut pass = graph.add_pass("clear");
+			let mut scene = pass.add_attachment(AttachmentDesc {
+				name: "Scene",
+				size: AttachmentSize::Absolute(4, 4),
+				format: TextureFormat::RGBA8UNorm,
+				load_op: LoadOp::Load,
+				store_op: StoreOp::Store,
+				usage: TextureUsage::TRANSFER_DST | TextureUsage::TRANSFER_SRC,
+			});
+			pass.cmd_clear_attachment(&mut scene, ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 });
+			scene
+		};
+
+		{
+			let mut pass = graph.add_pass("blit");
+			pass.cmd_blit_attachment_to_output(scene.read_as(ImageLayout::TransferSrcOptimal), Filter::Linear);
+		}
+
+		let dot = graph.dump_dot();
+		assert!(dot.starts_with("digraph RenderGraph {"));
+		assert!(dot.contains("\tpass_0 [shape=box, style=solid, label=\"#0 clear\\n1 cmds\"];"), "{}", dot);
+		assert!(dot.contains("\tpass_1 [shape=box, style=solid, label=\"#1 blit\\n1 cmds\"];"), "{}", dot);
+		assert!(dot.contains("\tres_0 [shape=ellipse, color=dodgerblue, label=\"Scene\\n4x4 RGBA8UNorm\"];"), "{}", dot);
+		assert!(dot.contains("\tpass_0 -> res_0 [color=dodgerblue, style=bold];"), "{}", dot);
+		assert!(dot.contains("\tres_0 -> pass_1 [color=dodgerblue];"), "{}", dot);
+		assert!(dot.contains("\tpass_0 -> pass_1 [style=invis, weight=10];"), "{}", dot);
+	}
+
+	// Laid out like the build script's generated modules, except with statics so the identical sets can't be merged into one address.
+	mod forward {
+		use crate::renderer::{DescriptorBindingType, DescriptorSetInfo};
+
+		pub static DESCRIPTOR_SET_0_INFO: DescriptorSetInfo = DescriptorSetInfo {
+			bindings: phf::phf_map! {
+				0u32 => DescriptorBindingType::CBuffer,
+				1u32 => DescriptorBindingType::Texture2D,
+			},
+		};
+	}
+
+	mod shadow {
+		use crate::renderer::{DescriptorBindingType, DescriptorSetInfo};
+
+		pub static DESCRIPTOR_SET_0_INFO: DescriptorSetInfo = DescriptorSetInfo {
+			bindings: phf::phf_map! {
+				1u32 => DescriptorBindingType::Texture2D,
+				0u32 => DescriptorBindingType::CBuffer,
+			},
+		};
+
+		pub static DESCRIPTOR_SET_1_INFO: DescriptorSetInfo = DescriptorSetInfo {
+			bindings: phf::phf_map! {
+				0u32 => DescriptorBindingType::CBuffer,
+				1u32 => DescriptorBindingType::TextureCube,
+			},
+		};
+	}
+
+	#[test]
+	fn identical_descriptor_set_infos_share_a_layout_and_heap() {
+		use ash::vk::Handle;
+
+		let mut layout_cache = DescriptorLayoutCache::default();
+		let mut heap_caches = HashMap::new();
+		let mut layouts_created = 0;
+		let mut heaps_created = 0;
+
+		let mut register = |info: &DescriptorSetInfo| {
+			let bindings = info.to_bindings();
+			let layout = DescriptorLayoutCache::get_or_create(&mut layout_cache.graphics_layouts, &bindings, |_| {
+				layouts_created += 1;
+				ash::vk::DescriptorSetLayout::from_raw(layouts_created)
+			});
+			register_descriptor_heap(&mut heap_caches, &bindings, || {
+				heaps_created += 1;
+				DescriptorHeap::null()
+			});
+			layout
+		};
+
+		assert!(!std::ptr::eq(&forward::DESCRIPTOR_SET_0_INFO, &shadow::DESCRIPTOR_SET_0_INFO));
+		let forward_layout = register(&forward::DESCRIPTOR_SET_0_INFO);
+		let shadow_layout = register(&shadow::DESCRIPTOR_SET_0_INFO);
+		assert_eq!(forward_layout, shadow_layout);
+
+		// A set with different bindings still gets its own.
+		let cube_layout = register(&shadow::DESCRIPTOR_SET_1_INFO);
+		assert_ne!(cube_layout, forward_layout);
+
+		assert_eq!(layouts_created, 2);
+		assert_eq!(heaps_created, 2);
+		assert_eq!(layout_cache.graphics_layouts.len(), 2);
+		assert_eq!(heap_caches.len(), 2);
+	}
+
+	static USAGE_TEST_INFO: DescriptorSetInfo = DescriptorSetInfo {
+		bindings: phf::phf_map! {
+			0u32 => DescriptorBindingType::CBuffer,
+			1u32 => DescriptorBindingType::StructuredBuffer,
+			2u32 => DescriptorBindingType::RWStructuredBuffer,
+			3u32 => DescriptorBindingType::Buffer,
+			4u32 => DescriptorBindingType::RWBuffer,
+			5u32 => DescriptorBindingType::Texture2D,
+			6u32 => DescriptorBindingType::RWTexture2D,
+			7u32 => DescriptorBindingType::SamplerState,
+		},
+	};
+
+	fn bind(validation: bool, binding: u32, resource: DescriptorBindingDesc) {
+		let mut cache = RenderGraphCache::default();
+		let default_resources = DefaultResources::null();
+		let mut graph = RenderGraph::new(&mut cache, &default_resources);
+		graph.set_validation(validation);
+
+		let mut pass = graph.add_pass("bind");
+		pass.add_graphics_descriptor_set(DescriptorDesc {
+			name: "Usage test",
+			descriptor_layout: &USAGE_TEST_INFO,
+			bindings: &mut [(binding, resource)],
+		});
+	}
+
+	fn bind_buffer(binding: u32, usage: BufferUsage) {
+		let buffer = GpuBuffer::null(64, usage);
+		bind(true, binding, DescriptorBindingDesc::ImportedBuffer(&buffer));
+	}
+
+	fn bind_texture(binding: u32, usage: TextureUsage) {
+		let mut texture = Texture::null(TextureFormat::RGBA8UNorm);
+		texture.usage = usage;
+		bind(true, binding, DescriptorBindingDesc::ImportedTexture(&texture));
+	}
+
+	// The message has to say which binding of which set it was, what was bound and what it needed.
+	fn refused(bind: impl FnOnce() + std::panic::UnwindSafe, expected: &[&str]) {
+		let payload = std::panic::catch_unwind(bind).expect_err("The binding should have been refused!");
+		let message = payload.downcast_ref::<String>().expect("Expected a formatted panic message!");
+		for part in expected {
+			assert!(message.contains(part), "{:?} doesn't mention {:?}", message, part);
+		}
+	}
+
+	#[test]
+	fn usage_matching_the_binding_is_accepted() {
+		bind_buffer(0, BufferUsage::UniformBuffer | BufferUsage::TransferDst);
+		bind_buffer(1, BufferUsage::StorageBuffer);
+		bind_buffer(2, BufferUsage::StorageBuffer);
+		bind_buffer(3, BufferUsage::UniformTexelBuffer);
+		bind_buffer(4, BufferUsage::StorageTexelBuffer);
+		bind_texture(5, TextureUsage::SAMPLED);
+		bind_texture(6, TextureUsage::STORAGE | TextureUsage::SAMPLED);
+		// Samplers only use the texture's sampler.
+		bind_texture(7, TextureUsage::ATTACHMENT);
+	}
+
+	#[test]
+	fn cbuffers_need_uniform_usage() {
+		refused(|| bind_buffer(0, BufferUsage::VertexBuffer), &["Binding 0 of Usage test", "CBuffer", "UniformBuffer", "VertexBuffer"]);
+	}
+
+	#[test]
+	fn structured_buffers_need_storage_usage() {
+		refused(
+			|| bind_buffer(1, BufferUsage::UniformBuffer),
+			&["Binding 1 of Usage test", "StructuredBuffer", "StorageBuffer", "UniformBuffer"],
+		);
+		refused(
+			|| bind_buffer(2, BufferUsage::IndexBuffer),
+			&["Binding 2 of Usage test", "RWStructuredBuffer", "StorageBuffer", "IndexBuffer"],
+		);
+	}
+
+	#[test]
+	fn texel_buffers_need_texel_usage() {
+		refused(
+			|| bind_buffer(3, BufferUsage::StorageTexelBuffer),
+			&["Binding 3 of Usage test", "Buffer", "UniformTexelBuffer", "StorageTexelBuffer"],
+		);
+		refused(
+			|| bind_buffer(4, BufferUsage::UniformTexelBuffer),
+			&["Binding 4 of Usage test", "RWBuffer", "StorageTexelBuffer", "UniformTexelBuffer"],
+		);
+	}
+
+	#[test]
+	fn textures_need_sampled_or_storage_usage() {
+		refused(|| bind_texture(5, TextureUsage::STORAGE), &["Binding 5 of Usage test", "Texture2D", "SAMPLED", "STORAGE"]);
+		refused(|| bind_texture(6, TextureUsage::SAMPLED), &["Binding 6 of Usage test", "RWTexture2D", "STORAGE", "SAMPLED"]);
+	}
+
+	#[test]
+	fn resources_of_the_wrong_kind_are_refused() {
+		refused(|| bind_buffer(5, BufferUsage::UniformBuffer), &["Binding 5 of Usage test", "Texture2D", "a buffer", "UniformBuffer"]);
+		refused(|| bind_texture(0, TextureUsage::SAMPLED), &["Binding 0 of Usage test", "CBuffer", "a texture", "SAMPLED"]);
+		refused(|| bind_buffer(8, BufferUsage::UniformBuffer), &["Binding 8 of Usage test", "isn't in its descriptor layout"]);
+	}
+
+	#[test]
+	fn usage_is_not_checked_without_validation() {
+		let buffer = GpuBuffer::null(64, BufferUsage::VertexBuffer);
+		bind(false, 0, DescriptorBindingDesc::ImportedBuffer(&buffer));
+	}
+}
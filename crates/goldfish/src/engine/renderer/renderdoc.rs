@@ -0,0 +1,168 @@
+use libloading::Library;
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// eRENDERDOC_API_Version_1_4_1, anything since RenderDoc 1.4 hands this out.
+const API_VERSION: c_int = 10401;
+
+#[cfg(target_os = "windows")]
+const LIBRARY_NAME: &str = "renderdoc.dll";
+#[cfg(all(unix, not(target_os = "macos")))]
+const LIBRARY_NAME: &str = "librenderdoc.so";
+
+type GetApiFn = unsafe extern "C" fn(version: c_int, out_api: *mut *mut c_void) -> c_int;
+
+// Only the front of RENDERDOC_API_1_4_1 up to the last function we call, the layout has to match renderdoc_app.h exactly.
+// Everything we don't call is left as an opaque pointer, underscored so it doesn't count as dead code.
+#[repr(C)]
+struct RenderDocApi {
+	get_api_version: unsafe extern "C" fn(major: *mut c_int, minor: *mut c_int, patch: *mut c_int),
+	_set_capture_option_u32: *const c_void,
+	_set_capture_option_f32: *const c_void,
+	_get_capture_option_u32: *const c_void,
+	_get_capture_option_f32: *const c_void,
+	_set_focus_toggle_keys: *const c_void,
+	_set_capture_keys: *const c_void,
+	_get_overlay_bits: *const c_void,
+	_mask_overlay_bits: *const c_void,
+	_remove_hooks: *const c_void,
+	_unload_crash_handler: *const c_void,
+	_set_capture_file_path_template: *const c_void,
+	_get_capture_file_path_template: *const c_void,
+	get_num_captures: unsafe extern "C" fn() -> u32,
+	get_capture: unsafe extern "C" fn(index: u32, filename: *mut c_char, path_length: *mut u32, timestamp: *mut u64) -> u32,
+	trigger_capture: unsafe extern "C" fn(),
+	_is_target_control_connected: *const c_void,
+	_launch_replay_ui: *const c_void,
+	_set_active_window: *const c_void,
+	start_frame_capture: unsafe extern "C" fn(device: *const c_void, window: *const c_void),
+	is_frame_capturing: unsafe extern "C" fn() -> u32,
+	end_frame_capture: unsafe extern "C" fn(device: *const c_void, window: *const c_void) -> u32,
+	trigger_multi_frame_capture: unsafe extern "C" fn(frames: u32),
+}
+
+static RENDERDOC: OnceLock<Option<RenderDoc>> = OnceLock::new();
+
+// Looked up the first time this is called and cached from then on, None when the process isn't running under RenderDoc. This is global
+// rather than living on the engine so the validation callback and the device lost handler can get at it too.
+pub fn renderdoc() -> Option<&'static RenderDoc> {
+	RENDERDOC.get_or_init(RenderDoc::load).as_ref()
+}
+
+// RenderDoc's in-application API, see https://renderdoc.org/docs/in_application_api.html. This only ever picks up a RenderDoc that's
+// already injected into the process (launched from the RenderDoc UI or renderdoccmd), it never loads one itself since the library
+// has to be in before the Vulkan instance is created for the capture layer to hook anything.
+pub struct RenderDoc {
+	api: *const RenderDocApi,
+	// Never called into directly, it just has to outlive the api table which points into it.
+	_library: Library,
+}
+
+// RenderDoc's API is documented as safe to call from any thread.
+unsafe impl Send for RenderDoc {}
+unsafe impl Sync for RenderDoc {}
+
+impl RenderDoc {
+	// None when the process isn't running under RenderDoc.
+	pub fn load() -> Option<Self> {
+		let library = Self::open_injected()?;
+
+		unsafe {
+			let get_api = library.get::<GetApiFn>(b"RENDERDOC_GetAPI\0").ok()?;
+
+			let mut api: *mut c_void = std::ptr::null_mut();
+			if get_api(API_VERSION, &mut api) != 1 || api.is_null() {
+				return None;
+			}
+
+			Some(Self {
+				api: api as *const RenderDocApi,
+				_library: library,
+			})
+		}
+	}
+
+	#[cfg(target_os = "windows")]
+	fn open_injected() -> Option<Library> {
+		libloading::os::windows::Library::open_already_loaded(LIBRARY_NAME).ok().map(Library::from)
+	}
+
+	#[cfg(all(unix, not(target_os = "macos")))]
+	fn open_injected() -> Option<Library> {
+		// RTLD_NOLOAD isn't in libloading, it only hands back a library that's already loaded rather than loading it.
+		const RTLD_NOLOAD: c_int = 0x4;
+
+		unsafe {
+			libloading::os::unix::Library::open(Some(LIBRARY_NAME), libloading::os::unix::RTLD_NOW | RTLD_NOLOAD)
+				.ok()
+				.map(Library::from)
+		}
+	}
+
+	// RenderDoc doesn't support macOS.
+	#[cfg(target_os = "macos")]
+	fn open_injected() -> Option<Library> {
+		None
+	}
+
+	fn api(&self) -> &RenderDocApi {
+		unsafe { &*self.api }
+	}
+
+	pub fn version(&self) -> (i32, i32, i32) {
+		let (mut major, mut minor, mut patch) = (0, 0, 0);
+		unsafe { (self.api().get_api_version)(&mut major, &mut minor, &mut patch) };
+
+		(major, minor, patch)
+	}
+
+	// Captures the next frames presented, starting with the next one to begin. Each frame ends up in its own .rdc.
+	pub fn trigger_capture(&self, frames: u32) {
+		unsafe {
+			match frames {
+				0 => {}
+				1 => (self.api().trigger_capture)(),
+				frames => (self.api().trigger_multi_frame_capture)(frames),
+			}
+		}
+	}
+
+	pub fn is_frame_capturing(&self) -> bool {
+		unsafe { (self.api().is_frame_capturing)() == 1 }
+	}
+
+	// Null for either the device or the window matches whatever's being captured, which is fine since there's only ever one device.
+	pub fn start_frame_capture(&self) {
+		unsafe { (self.api().start_frame_capture)(std::ptr::null(), std::ptr::null()) };
+	}
+
+	// Returns whether a capture was actually written.
+	pub fn end_frame_capture(&self) -> bool {
+		unsafe { (self.api().end_frame_capture)(std::ptr::null(), std::ptr::null()) == 1 }
+	}
+
+	// Every capture made since the process started, including ones made from the RenderDoc UI.
+	pub fn capture_count(&self) -> u32 {
+		unsafe { (self.api().get_num_captures)() }
+	}
+
+	pub fn capture_path(&self, index: u32) -> Option<PathBuf> {
+		unsafe {
+			let mut length = 0;
+			if (self.api().get_capture)(index, std::ptr::null_mut(), &mut length, std::ptr::null_mut()) == 0 {
+				return None;
+			}
+
+			// The length includes the null terminator.
+			let mut path = vec![0u8; length as usize];
+			if (self.api().get_capture)(index, path.as_mut_ptr() as *mut c_char, &mut length, std::ptr::null_mut()) == 0 {
+				return None;
+			}
+
+			path.truncate(path.iter().position(|&c| c == 0).unwrap_or(path.len()));
+			Some(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+		}
+	}
+}
@@ -0,0 +1,92 @@
+use log::debug;
+use std::time::Duration;
+
+// Picks how much to scale down the resolution of the scene passes (see AttachmentSize::OutputRelative) to keep the GPU under a frame budget.
+// Drops a step as soon as the GPU has been over budget for a few frames in a row, but only goes back up once the next step up looks like it
+// would fit for a good while, so it doesn't flip back and forth right at the edge of the budget.
+pub struct ResolutionScaler {
+	// None keeps everything at full resolution.
+	budget: Option<Duration>,
+	step: usize,
+	over_budget_frames: u32,
+	under_budget_frames: u32,
+}
+
+impl ResolutionScaler {
+	pub const STEPS: [f32; 4] = [1.0, 0.85, 0.7, 0.5];
+
+	const DOWNSCALE_FRAMES: u32 = 10;
+	const UPSCALE_FRAMES: u32 = 60;
+	// The next step up has to come in this far under budget, otherwise the noise in the GPU timings would push it right back down.
+	const UPSCALE_HEADROOM: f64 = 0.85;
+
+	pub fn new(budget: Option<Duration>) -> Self {
+		Self {
+			budget,
+			step: 0,
+			over_budget_frames: 0,
+			under_budget_frames: 0,
+		}
+	}
+
+	pub fn budget(&self) -> Option<Duration> {
+		self.budget
+	}
+
+	// Clearing the budget goes straight back to full resolution.
+	pub fn set_budget(&mut self, budget: Option<Duration>) {
+		self.budget = budget;
+		if budget.is_none() {
+			self.step = 0;
+		}
+		self.over_budget_frames = 0;
+		self.under_budget_frames = 0;
+	}
+
+	// Should be fed once per frame with GraphicsContext::gpu_frame_time.
+	pub fn record(&mut self, gpu_frame_time: Duration) {
+		let Some(budget) = self.budget else {
+			return;
+		};
+
+		if gpu_frame_time > budget {
+			self.under_budget_frames = 0;
+			self.over_budget_frames += 1;
+
+			if self.over_budget_frames >= Self::DOWNSCALE_FRAMES && self.step + 1 < Self::STEPS.len() {
+				self.change_step(self.step + 1);
+			}
+			return;
+		}
+
+		self.over_budget_frames = 0;
+		if self.step == 0 {
+			return;
+		}
+
+		// Assumes the GPU time scales with the number of pixels, which overestimates it since some of the frame doesn't
+		// depend on the resolution at all. That only makes it more careful about going back up.
+		let ratio = (Self::STEPS[self.step - 1] / Self::STEPS[self.step]) as f64;
+		let estimated = gpu_frame_time.as_secs_f64() * ratio * ratio;
+		if estimated < budget.as_secs_f64() * Self::UPSCALE_HEADROOM {
+			self.under_budget_frames += 1;
+
+			if self.under_budget_frames >= Self::UPSCALE_FRAMES {
+				self.change_step(self.step - 1);
+			}
+		} else {
+			self.under_budget_frames = 0;
+		}
+	}
+
+	pub fn current_scale(&self) -> f32 {
+		Self::STEPS[self.step]
+	}
+
+	fn change_step(&mut self, step: usize) {
+		debug!("Resolution scale changed from {} to {}", Self::STEPS[self.step], Self::STEPS[step]);
+		self.step = step;
+		self.over_budget_frames = 0;
+		self.under_budget_frames = 0;
+	}
+}
@@ -0,0 +1,181 @@
+use super::*;
+use crate::package::AssetEvent;
+use crate::GoldfishError;
+use log::{error, info};
+use naga::{
+	back::spv,
+	front::wgsl,
+	valid::{Capabilities, ValidationFlags, Validator},
+	ShaderStage,
+};
+
+// Drawn in place of anything whose pipeline couldn't be built, see DefaultResources::error_vs. It doesn't know anything about the vertices
+// it's drawn with, so the first triangle of every draw covers the whole screen in unlit magenta.
+const ERROR_SHADER_WGSL: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn ps_main() -> @location(0) vec4<f32> {
+	return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+// The engine doesn't have a build script of its own to compile HLSL with, so the shaders it ships with itself (like the error
+// shader) are WGSL that goes through naga when they're needed. They're tiny, so that's quick.
+pub(crate) fn compile_wgsl(source: &str, stage: ShaderStage, entry_point: &str) -> GoldfishResult<Vec<u32>> {
+	let invalid_shader = |message: String| GoldfishError::Render(RenderError::InvalidShader { message });
+
+	let module = wgsl::parse_str(source).map_err(|err| invalid_shader(err.emit_to_string(source)))?;
+	let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+		.validate(&module)
+		.map_err(|err| invalid_shader(err.to_string()))?;
+
+	let options = spv::Options {
+		flags: spv::WriterFlags::empty(),
+		..Default::default()
+	};
+	let pipeline_options = spv::PipelineOptions {
+		shader_stage: stage,
+		entry_point: entry_point.to_owned(),
+	};
+
+	spv::write_vec(&module, &info, &options, Some(&pipeline_options)).map_err(|err| invalid_shader(err.to_string()))
+}
+
+impl GraphicsDevice {
+	// The vertex and pixel shader of the error pipeline.
+	pub fn create_error_shaders(&mut self) -> GoldfishResult<(Shader, Shader)> {
+		let vs = self.create_shader_with_code(&compile_wgsl(ERROR_SHADER_WGSL, ShaderStage::Vertex, VS_MAIN)?)?;
+		let ps = match compile_wgsl(ERROR_SHADER_WGSL, ShaderStage::Fragment, PS_MAIN).and_then(|code| self.create_shader_with_code(&code)) {
+			Ok(ps) => ps,
+			Err(err) => {
+				self.destroy_shader(vs);
+				return Err(err);
+			}
+		};
+
+		Ok((vs, ps))
+	}
+}
+
+// A shader that starts out as whatever the game was built with, and gets swapped out whenever the editor reimports its source from the asset
+// directory. While the source doesn't compile it's drawn with the error shaders, so a broken save shows up right away. If a package that did
+// build can't be turned into shader modules, the last shaders that worked are kept instead.
+pub struct ReloadableShader {
+	// Relative to the asset directory, like "shaders/fullscreen.hlsl".
+	path: &'static str,
+	vs: Shader,
+	ps: Option<Shader>,
+	// Set while the last import of the source failed.
+	error: Option<String>,
+}
+
+impl ReloadableShader {
+	pub fn new(path: &'static str, vs: Shader, ps: Option<Shader>) -> Self {
+		Self { path, vs, ps, error: None }
+	}
+
+	pub fn path(&self) -> &'static str {
+		self.path
+	}
+
+	// The error from the last time the source failed to import, None once it imports again.
+	pub fn error(&self) -> Option<&str> {
+		self.error.as_deref()
+	}
+
+	// The shaders to build pipelines with, which are the error shaders while the source is broken.
+	pub fn vs<'a>(&'a self, default_resources: &'a DefaultResources) -> &'a Shader {
+		match self.error {
+			Some(_) => &default_resources.error_vs,
+			None => &self.vs,
+		}
+	}
+
+	pub fn ps<'a>(&'a self, default_resources: &'a DefaultResources) -> Option<&'a Shader> {
+		match self.error {
+			Some(_) => Some(&default_resources.error_ps),
+			None => self.ps.as_ref(),
+		}
+	}
+
+	// Call with everything from GoldfishEngine::drain_asset_events, returns whether the event was about this shader. The cache is the one
+	// that's drawn with these shaders, their pipelines are evicted from it when they're replaced.
+	pub fn handle_asset_event(&mut self, engine: &mut GoldfishEngine, render_graph_cache: &mut RenderGraphCache, event: &AssetEvent) -> bool {
+		let uuid = match engine.uuid_for_path(self.path, AssetType::Shader) {
+			Ok(uuid) => uuid,
+			Err(_) => return false,
+		};
+
+		match event {
+			AssetEvent::AssetChanged { uuid: changed, .. } if *changed == uuid => {
+				match self.reload(engine, render_graph_cache) {
+					Ok(()) => {
+						info!("Reloaded {}", self.path);
+						self.error = None;
+					}
+					Err(err) => error!("Failed to reload {}, keeping the last shaders that worked! {}", self.path, err),
+				}
+				true
+			}
+			AssetEvent::ImportFailed { uuid: failed, message } if *failed == uuid => {
+				// The watcher retries a few times, which doesn't need to be logged again.
+				if self.error.as_deref() != Some(message.as_str()) {
+					error!("{} failed to compile, drawing with the error shaders until it's fixed! {}", self.path, message);
+					self.error = Some(message.clone());
+				}
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn reload(&mut self, engine: &mut GoldfishEngine, render_graph_cache: &mut RenderGraphCache) -> GoldfishResult<()> {
+		let package = match engine.read_package_by_path(self.path, AssetType::Shader)? {
+			Package::Shader(package) => package,
+			_ => return Err(GoldfishError::Unknown(format!("{} isn't a shader package", self.path))),
+		};
+
+		let invalid_shader = |message: &str| GoldfishError::Render(RenderError::InvalidShader { message: message.to_string() });
+		let permutation = package.permutation(0).ok_or_else(|| invalid_shader("the package doesn't have any permutations"))?;
+		let vs_ir = permutation.vs_ir.as_ref().ok_or_else(|| invalid_shader("the package doesn't have a vertex shader"))?;
+		if self.ps.is_some() && permutation.ps_ir.is_none() {
+			return Err(invalid_shader("the package doesn't have a pixel shader"));
+		}
+
+		let graphics_device = &mut engine.graphics_device;
+		let vs = graphics_device.create_shader_with_code(vs_ir)?;
+		let ps = match permutation.ps_ir.as_ref().map(|ps_ir| graphics_device.create_shader_with_code(ps_ir)).transpose() {
+			Ok(ps) => ps,
+			Err(err) => {
+				graphics_device.destroy_shader(vs);
+				return Err(err);
+			}
+		};
+
+		let old_vs = std::mem::replace(&mut self.vs, vs);
+		let old_ps = std::mem::replace(&mut self.ps, ps);
+		let mut replaced = vec![&old_vs];
+		replaced.extend(old_ps.as_ref());
+		render_graph_cache.evict_shader_pipelines(graphics_device, &replaced);
+
+		// Whatever's still in flight with them is done before the destruction actually happens.
+		graphics_device.destroy_shader(old_vs);
+		if let Some(old_ps) = old_ps {
+			graphics_device.destroy_shader(old_ps);
+		}
+
+		Ok(())
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		graphics_device.destroy_shader(self.vs);
+		if let Some(ps) = self.ps {
+			graphics_device.destroy_shader(ps);
+		}
+	}
+}
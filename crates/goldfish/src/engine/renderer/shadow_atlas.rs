@@ -0,0 +1,324 @@
+use super::*;
+use glam::{UVec2, Vec4};
+use log::warn;
+
+// Regions never get smaller than this, requests that don't fit are shrunk down to at most this size before giving up.
+pub const MIN_SHADOW_REGION_SIZE: u32 = 64;
+
+// A square piece of the atlas, the extent can be smaller than what was asked for if the atlas was too full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasRegion {
+	pub offset: UVec2,
+	pub extent: UVec2,
+}
+
+impl AtlasRegion {
+	// What shaders multiply a shadow map UV by (xy) and add to it (zw) to land in this region of the atlas.
+	pub fn uv_scale_offset(&self, atlas_size: u32) -> Vec4 {
+		let atlas_size = atlas_size as f32;
+		Vec4::new(
+			self.extent.x as f32 / atlas_size,
+			self.extent.y as f32 / atlas_size,
+			self.offset.x as f32 / atlas_size,
+			self.offset.y as f32 / atlas_size,
+		)
+	}
+}
+
+enum Node {
+	Free,
+	Allocated { transient: bool },
+	// Top left, top right, bottom left, bottom right.
+	Split(Box<[Node; 4]>),
+}
+
+impl Node {
+	fn split() -> Self {
+		Self::Split(Box::new([Self::Free, Self::Free, Self::Free, Self::Free]))
+	}
+
+	fn child_offset(offset: UVec2, size: u32, child: usize) -> UVec2 {
+		let half = size / 2;
+		offset + UVec2::new(child as u32 % 2 * half, child as u32 / 2 * half)
+	}
+
+	// The smallest free node that's at least the size, as its offset and size.
+	fn best_fit(&self, offset: UVec2, size: u32, requested: u32) -> Option<(UVec2, u32)> {
+		match self {
+			Self::Free if size >= requested => Some((offset, size)),
+			Self::Free | Self::Allocated { .. } => None,
+			Self::Split(_) if size / 2 < requested => None,
+			Self::Split(children) => children
+				.iter()
+				.enumerate()
+				.filter_map(|(i, child)| child.best_fit(Self::child_offset(offset, size, i), size / 2, requested))
+				.min_by_key(|&(_, fit)| fit),
+		}
+	}
+
+	// Walks down to the free node at the target offset and size, then splits it until there's a node of the requested size to allocate.
+	fn allocate(&mut self, offset: UVec2, size: u32, target: (UVec2, u32), requested: u32, transient: bool) {
+		if (offset, size) == target {
+			assert!(matches!(self, Self::Free), "Shadow atlas node is already in use!");
+
+			if size == requested {
+				*self = Self::Allocated { transient };
+			} else {
+				// The top left child has the same offset, so carrying on into it keeps splitting until the size is right.
+				*self = Self::split();
+				self.allocate(offset, size, (offset, size / 2), requested, transient);
+			}
+
+			return;
+		}
+
+		let half = size / 2;
+		let child = (target.0.x >= offset.x + half) as usize + (target.0.y >= offset.y + half) as usize * 2;
+		match self {
+			Self::Split(children) => children[child].allocate(Self::child_offset(offset, size, child), half, target, requested, transient),
+			_ => unreachable!("Shadow atlas allocation went through a node that isn't split!"),
+		}
+	}
+
+	// Children that all end up free get merged back into their parent, so bigger regions can be handed out again.
+	fn free(&mut self, offset: UVec2, size: u32, region: &AtlasRegion, only_transient: bool) {
+		match self {
+			Self::Free => (),
+			&mut Self::Allocated { transient } => {
+				let matches = if only_transient { transient } else { offset == region.offset && size == region.extent.x };
+				if matches {
+					*self = Self::Free;
+				}
+			}
+			Self::Split(children) => {
+				let half = size / 2;
+				for (i, child) in children.iter_mut().enumerate() {
+					let child_offset = Self::child_offset(offset, size, i);
+					if only_transient || (region.offset.cmpge(child_offset).all() && region.offset.cmplt(child_offset + half).all()) {
+						child.free(child_offset, half, region, only_transient);
+					}
+				}
+
+				if children.iter().all(|child| matches!(child, Self::Free)) {
+					*self = Self::Free;
+				}
+			}
+		}
+	}
+
+	fn allocated_area(&self, size: u32) -> u64 {
+		match self {
+			Self::Free => 0,
+			Self::Allocated { .. } => size as u64 * size as u64,
+			Self::Split(children) => children.iter().map(|child| child.allocated_area(size / 2)).sum(),
+		}
+	}
+}
+
+// Packs every light's shadow map into one big depth attachment, so the lighting shader only ever has to bind a single texture.
+// Regions are handed out by a quad tree, so every region is a power of two and regions of the same size pack perfectly.
+// The shadow pass renders into a region by calling PassBuilder::cmd_set_viewport with it after beginning the render pass.
+pub struct ShadowAtlas {
+	size: u32,
+	root: Node,
+}
+
+impl ShadowAtlas {
+	pub fn new(size: u32) -> Self {
+		assert!(size.is_power_of_two() && size >= MIN_SHADOW_REGION_SIZE, "Shadow atlas size must be a power of two!");
+
+		Self { size, root: Node::Free }
+	}
+
+	pub fn size(&self) -> u32 {
+		self.size
+	}
+
+	// Add this to the shadow pass every frame, the atlas only keeps track of which regions are in use, not their contents.
+	pub fn attachment_desc(&self, name: &'static str) -> AttachmentDesc {
+		AttachmentDesc {
+			name,
+			format: TextureFormat::Depth,
+			size: AttachmentSize::Absolute(self.size, self.size),
+			load_op: LoadOp::Clear,
+			store_op: StoreOp::Store,
+			usage: TextureUsage::SAMPLED | TextureUsage::ATTACHMENT,
+		}
+	}
+
+	// Stays allocated until it's freed. Sizes get rounded up to a power of two, and if there's no room left the size keeps getting halved
+	// until it fits, so a light ends up with a blurrier shadow rather than none at all. None only once not even the smallest region fits.
+	pub fn allocate(&mut self, size: u32) -> Option<AtlasRegion> {
+		self.allocate_with(size, false)
+	}
+
+	// Freed by the next call to clear_frame, for lights that are only around for a frame or get a new resolution every frame.
+	pub fn allocate_transient(&mut self, size: u32) -> Option<AtlasRegion> {
+		self.allocate_with(size, true)
+	}
+
+	fn allocate_with(&mut self, size: u32, transient: bool) -> Option<AtlasRegion> {
+		// Anything bigger than the whole atlas gets the whole atlas, that isn't the atlas being full.
+		let fitted = size.clamp(MIN_SHADOW_REGION_SIZE, self.size).next_power_of_two();
+		let mut requested = fitted;
+
+		loop {
+			if let Some(target) = self.root.best_fit(UVec2::ZERO, self.size, requested) {
+				self.root.allocate(UVec2::ZERO, self.size, target, requested, transient);
+
+				if requested < fitted {
+					warn!("Shadow atlas is full, shrunk a {} shadow map down to {}!", size, requested);
+				}
+
+				return Some(AtlasRegion {
+					offset: target.0,
+					extent: UVec2::splat(requested),
+				});
+			}
+
+			if requested == MIN_SHADOW_REGION_SIZE {
+				warn!("Shadow atlas is full, couldn't fit a {} shadow map!", size);
+				return None;
+			}
+
+			requested /= 2;
+		}
+	}
+
+	pub fn free(&mut self, region: AtlasRegion) {
+		self.root.free(UVec2::ZERO, self.size, &region, false);
+	}
+
+	// Frees every transient region, call once at the start of each frame before allocating that frame's regions.
+	pub fn clear_frame(&mut self) {
+		let region = AtlasRegion {
+			offset: UVec2::ZERO,
+			extent: UVec2::ZERO,
+		};
+		self.root.free(UVec2::ZERO, self.size, &region, true);
+	}
+
+	// How much of the atlas is in use, between 0.0 and 1.0.
+	pub fn occupancy(&self) -> f32 {
+		(self.root.allocated_area(self.size) as f64 / (self.size as f64 * self.size as f64)) as f32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+
+	fn overlaps(a: &AtlasRegion, b: &AtlasRegion) -> bool {
+		(a.offset.cmplt(b.offset + b.extent) & b.offset.cmplt(a.offset + a.extent)).all()
+	}
+
+	#[test]
+	fn same_size_regions_pack_perfectly() {
+		let mut atlas = ShadowAtlas::new(1024);
+		let regions = (0..16).map(|_| atlas.allocate(256).unwrap()).collect::<Vec<_>>();
+
+		assert!(regions
+			.iter()
+			.all(|region| region.extent == UVec2::splat(256) && (region.offset + region.extent).cmple(UVec2::splat(1024)).all()));
+		assert_eq!(regions.iter().map(|region| region.offset).collect::<HashSet<_>>().len(), 16);
+		assert_eq!(atlas.occupancy(), 1.0);
+		assert_eq!(atlas.allocate(64), None);
+	}
+
+	#[test]
+	fn sizes_round_up_and_clamp_to_the_atlas() {
+		let mut atlas = ShadowAtlas::new(1024);
+		assert_eq!(atlas.allocate(100).unwrap().extent, UVec2::splat(128));
+		assert_eq!(atlas.allocate(1).unwrap().extent, UVec2::splat(MIN_SHADOW_REGION_SIZE));
+
+		let mut atlas = ShadowAtlas::new(1024);
+		assert_eq!(
+			atlas.allocate(4096),
+			Some(AtlasRegion {
+				offset: UVec2::ZERO,
+				extent: UVec2::splat(1024)
+			})
+		);
+	}
+
+	#[test]
+	fn full_atlases_halve_the_request_until_it_fits() {
+		let mut atlas = ShadowAtlas::new(1024);
+		let quadrants = (0..3).map(|_| atlas.allocate(512).unwrap()).collect::<Vec<_>>();
+
+		let shrunk = atlas.allocate(1024).unwrap();
+		assert_eq!(shrunk.extent, UVec2::splat(512));
+		assert!(quadrants.iter().all(|quadrant| !overlaps(quadrant, &shrunk)));
+
+		assert_eq!(atlas.allocate(64), None);
+	}
+
+	#[test]
+	fn small_regions_leave_the_rest_of_the_atlas_usable() {
+		let mut atlas = ShadowAtlas::new(1024);
+		let small = atlas.allocate(64).unwrap();
+
+		// The 64 only splits up one quadrant, the other three still fit a 512 each.
+		let large = (0..3).map(|_| atlas.allocate(512).unwrap()).collect::<Vec<_>>();
+		assert!(large.iter().all(|region| region.extent == UVec2::splat(512) && !overlaps(region, &small)));
+		assert_eq!(atlas.allocate(512).unwrap().extent, UVec2::splat(256));
+	}
+
+	#[test]
+	fn freeing_all_four_children_merges_them_back() {
+		let mut atlas = ShadowAtlas::new(1024);
+		let quadrants = (0..4).map(|_| atlas.allocate(512).unwrap()).collect::<Vec<_>>();
+
+		for quadrant in &quadrants[1..] {
+			atlas.free(*quadrant);
+		}
+		// One child is still allocated, so the parent can't be handed out whole.
+		assert_eq!(atlas.allocate(1024).unwrap().extent, UVec2::splat(512));
+
+		let mut atlas = ShadowAtlas::new(1024);
+		for quadrant in (0..4).map(|_| atlas.allocate(512).unwrap()).collect::<Vec<_>>() {
+			atlas.free(quadrant);
+		}
+		assert_eq!(atlas.occupancy(), 0.0);
+		assert_eq!(
+			atlas.allocate(1024),
+			Some(AtlasRegion {
+				offset: UVec2::ZERO,
+				extent: UVec2::splat(1024)
+			})
+		);
+	}
+
+	#[test]
+	fn clear_frame_only_frees_transient_regions() {
+		let mut atlas = ShadowAtlas::new(1024);
+		let persistent = atlas.allocate(512).unwrap();
+		for _ in 0..3 {
+			atlas.allocate_transient(512).unwrap();
+		}
+		assert_eq!(atlas.occupancy(), 1.0);
+
+		atlas.clear_frame();
+		assert_eq!(atlas.occupancy(), 0.25);
+
+		let reused = atlas.allocate(1024).unwrap();
+		assert_eq!(reused.extent, UVec2::splat(512));
+		assert!(!overlaps(&reused, &persistent));
+	}
+
+	#[test]
+	fn occupancy_counts_allocated_area() {
+		let mut atlas = ShadowAtlas::new(1024);
+		assert_eq!(atlas.occupancy(), 0.0);
+
+		let region = atlas.allocate(256).unwrap();
+		assert_eq!(atlas.occupancy(), 1.0 / 16.0);
+
+		atlas.allocate(512).unwrap();
+		assert_eq!(atlas.occupancy(), 1.0 / 16.0 + 0.25);
+
+		atlas.free(region);
+		assert_eq!(atlas.occupancy(), 0.25);
+	}
+}
@@ -0,0 +1,100 @@
+use crate::build::StructuredBuffer;
+use crate::package::SkinPackage;
+use crate::renderer::{BufferUsage, GraphicsDevice, PerFrameBuffer, UploadContext};
+use crate::GoldfishResult;
+use glam::Mat4;
+
+// Turns a pose into the bone matrices that skinned vertices get blended with, and keeps them in a storage buffer for the vertex shader.
+// Bind the buffer with DescriptorBindingDesc::PerFrameBuffer, the shader indexes it with SkinnedVertex::bone_indices.
+pub struct SkinPalette {
+	joint_names: Vec<String>,
+	parents: Vec<Option<u16>>,
+	inverse_bind_matrices: Vec<Mat4>,
+	rest_pose: Vec<Mat4>,
+	// Each joint's transform in mesh space, only kept around so children can be resolved against their parents.
+	joint_transforms: Vec<Mat4>,
+	matrices: Vec<Mat4>,
+	buffer: PerFrameBuffer,
+}
+
+impl SkinPalette {
+	// The bone size is the size of the shader's bone struct, which is whatever the generated StructuredBuffer::size() says.
+	pub fn new(upload_context: &mut UploadContext, name: &str, skin: &SkinPackage, bone_size: usize) -> GoldfishResult<Self> {
+		for (i, joint) in skin.joints.iter().enumerate() {
+			if let Some(parent) = joint.parent {
+				assert!((parent as usize) < i, "Joint {} comes before its parent!", joint.name);
+			}
+		}
+
+		let joint_count = skin.joints.len();
+		let buffer = PerFrameBuffer::new(upload_context, name, bone_size * joint_count.max(1), BufferUsage::StorageBuffer)?;
+
+		let rest_pose = skin.joints.iter().map(|joint| Mat4::from_cols_array(&joint.rest_transform)).collect();
+		let mut palette = Self {
+			joint_names: skin.joints.iter().map(|joint| joint.name.clone()).collect(),
+			parents: skin.joints.iter().map(|joint| joint.parent).collect(),
+			inverse_bind_matrices: skin.joints.iter().map(|joint| Mat4::from_cols_array(&joint.inverse_bind_matrix)).collect(),
+			rest_pose,
+			joint_transforms: vec![Mat4::IDENTITY; joint_count],
+			matrices: vec![Mat4::IDENTITY; joint_count],
+			buffer,
+		};
+
+		let rest_pose = palette.rest_pose.clone();
+		palette.set_pose(&rest_pose);
+
+		Ok(palette)
+	}
+
+	pub fn joint_count(&self) -> usize {
+		self.parents.len()
+	}
+
+	// Animation tracks are matched up with joints by name.
+	pub fn joint_names(&self) -> &[String] {
+		&self.joint_names
+	}
+
+	// Every joint's transform relative to its parent in the bind pose, a good starting point for building a pose.
+	pub fn rest_pose(&self) -> &[Mat4] {
+		&self.rest_pose
+	}
+
+	// The pose has one transform per joint relative to its parent, in the same order as the skin's joints.
+	pub fn set_pose(&mut self, pose: &[Mat4]) {
+		assert_eq!(pose.len(), self.joint_count(), "Pose doesn't have a transform for every joint!");
+
+		// Parents always come before their children, so a single pass is enough to resolve everything.
+		for (i, local) in pose.iter().enumerate() {
+			let joint_transform = match self.parents[i] {
+				Some(parent) => self.joint_transforms[parent as usize] * *local,
+				None => *local,
+			};
+
+			self.joint_transforms[i] = joint_transform;
+			self.matrices[i] = joint_transform * self.inverse_bind_matrices[i];
+		}
+	}
+
+	pub fn matrices(&self) -> &[Mat4] {
+		&self.matrices
+	}
+
+	// Converts the matrices into the shader's bone struct and writes them into this frame's copy of the buffer.
+	pub fn write<T: StructuredBuffer<S>, const S: usize>(&mut self, frame_slot: usize, to_bone: impl Fn(Mat4) -> T) {
+		let bones = self.matrices.iter().map(|&matrix| to_bone(matrix)).collect::<Vec<_>>();
+
+		let mut data = vec![0u8; T::size() * bones.len()];
+		T::copy_to_raw(&bones, &mut data);
+
+		self.buffer.write(frame_slot, &data);
+	}
+
+	pub fn buffer(&self) -> &PerFrameBuffer {
+		&self.buffer
+	}
+
+	pub fn destroy(self, graphics_device: &mut GraphicsDevice) {
+		self.buffer.destroy(graphics_device);
+	}
+}
@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+pub struct FrameTimer {
+	samples: [Duration; Self::SAMPLE_COUNT],
+	sample_count: usize,
+	next_sample: usize,
+	target_fps: Option<u32>,
+	frame_start: Instant,
+}
+
+impl FrameTimer {
+	pub const SAMPLE_COUNT: usize = 256;
+
+	// Sleeping is only accurate to a millisecond or so (worse on Windows), so we spin for the remainder.
+	const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+	pub fn new() -> Self {
+		Self {
+			samples: [Duration::ZERO; Self::SAMPLE_COUNT],
+			sample_count: 0,
+			next_sample: 0,
+			target_fps: None,
+			frame_start: Instant::now(),
+		}
+	}
+
+	pub fn record(&mut self, dt: Duration) {
+		self.samples[self.next_sample] = dt;
+		self.next_sample = (self.next_sample + 1) % Self::SAMPLE_COUNT;
+		self.sample_count = (self.sample_count + 1).min(Self::SAMPLE_COUNT);
+	}
+
+	pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+		self.target_fps = target_fps.filter(|&fps| fps > 0);
+	}
+
+	pub fn get_target_fps(&self) -> Option<u32> {
+		self.target_fps
+	}
+
+	pub fn avg_ms(&self) -> f64 {
+		if self.sample_count == 0 {
+			return 0.0;
+		}
+
+		self.samples[0..self.sample_count].iter().map(|s| s.as_secs_f64()).sum::<f64>() * 1000.0 / self.sample_count as f64
+	}
+
+	// Nearest-rank percentile of the recorded frame times in milliseconds, p is in the range [0.0, 100.0].
+	pub fn percentile(&self, p: f64) -> f64 {
+		if self.sample_count == 0 {
+			return 0.0;
+		}
+
+		let mut sorted = self.samples[0..self.sample_count].to_vec();
+		sorted.sort();
+
+		let rank = ((p.clamp(0.0, 100.0) / 100.0) * self.sample_count as f64).ceil() as usize;
+		sorted[rank.max(1) - 1].as_secs_f64() * 1000.0
+	}
+
+	pub fn fps(&self) -> f64 {
+		let avg_ms = self.avg_ms();
+		if avg_ms <= 0.0 {
+			return 0.0;
+		}
+
+		1000.0 / avg_ms
+	}
+
+	// Blocks until the target frame time has elapsed since the previous call. Does nothing when vsync is already pacing frames.
+	pub fn limit(&mut self, vsync: bool) {
+		if let (Some(target_fps), false) = (self.target_fps, vsync) {
+			let deadline = self.frame_start + Duration::from_secs_f64(1.0 / target_fps as f64);
+
+			let now = Instant::now();
+			if deadline > now + Self::SPIN_THRESHOLD {
+				std::thread::sleep(deadline - now - Self::SPIN_THRESHOLD);
+			}
+
+			while Instant::now() < deadline {
+				std::hint::spin_loop();
+			}
+		}
+
+		self.frame_start = Instant::now();
+	}
+}
+
+impl Default for FrameTimer {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// Where the game is at in time, updated by GoldfishEngine::run right before the game's update. Everything but delta is game time, which
+// is slowed down by time_scale and stops while paused. Input driven things like the camera should stick with delta so they stay
+// responsive either way.
+pub struct EngineTime {
+	// Game time since the engine started running.
+	pub elapsed: Duration,
+	// How long the last frame really took.
+	pub delta: Duration,
+	// delta scaled by time_scale, zero while paused.
+	pub scaled_delta: Duration,
+	time_scale: f32,
+	pub paused: bool,
+	// How many frames have been run, counts up even while paused.
+	pub frame_count: u64,
+}
+
+impl EngineTime {
+	pub fn new() -> Self {
+		Self {
+			elapsed: Duration::ZERO,
+			delta: Duration::ZERO,
+			scaled_delta: Duration::ZERO,
+			time_scale: 1.0,
+			paused: false,
+			frame_count: 0,
+		}
+	}
+
+	pub fn advance(&mut self, delta: Duration) {
+		self.delta = delta;
+		self.scaled_delta = if self.paused { Duration::ZERO } else { delta.mul_f32(self.time_scale) };
+		self.elapsed += self.scaled_delta;
+		self.frame_count += 1;
+	}
+
+	pub fn time_scale(&self) -> f32 {
+		self.time_scale
+	}
+
+	// 1.0 is real time, anything below 0 (or NaN) is clamped to 0 which stops game time without pausing.
+	pub fn set_time_scale(&mut self, time_scale: f32) {
+		self.time_scale = time_scale.max(0.0);
+	}
+
+	// elapsed wrapped around to [0, period) seconds. Past a few hours an f32 of the full elapsed time is too coarse to animate anything
+	// smoothly, so shaders should be fed this with whatever period their animation repeats at.
+	pub fn elapsed_seconds_f32_wrapped(&self, period: f32) -> f32 {
+		assert!(period > 0.0, "Cannot wrap elapsed time to a period of {}!", period);
+		(self.elapsed.as_secs_f64() % period as f64) as f32
+	}
+}
+
+impl Default for EngineTime {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct Size {
 	pub width: u32,
 	pub height: u32,
@@ -10,7 +10,60 @@ impl Size {
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+// Window sizes come in two flavors that are easy to mix up on high DPI displays. Physical pixels are what the swapchain,
+// the cursor position and anything rendered use, logical pixels are physical pixels divided by the window's scale factor and are what UI
+// should be laid out in so that it comes out the same size on every monitor.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub struct PhysicalSize {
+	pub width: u32,
+	pub height: u32,
+}
+
+impl PhysicalSize {
+	pub fn aspect(&self) -> f64 {
+		self.width as f64 / self.height as f64
+	}
+
+	pub fn to_logical(self, scale_factor: f64) -> LogicalSize {
+		LogicalSize {
+			width: self.width as f64 / scale_factor,
+			height: self.height as f64 / scale_factor,
+		}
+	}
+}
+
+impl From<PhysicalSize> for Size {
+	fn from(size: PhysicalSize) -> Self {
+		Size {
+			width: size.width,
+			height: size.height,
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LogicalSize {
+	pub width: f64,
+	pub height: f64,
+}
+
+impl LogicalSize {
+	pub fn aspect(&self) -> f64 {
+		self.width / self.height
+	}
+
+	// Rounded to the nearest pixel.
+	pub fn to_physical(self, scale_factor: f64) -> PhysicalSize {
+		PhysicalSize {
+			width: (self.width * scale_factor).round() as u32,
+			height: (self.height * scale_factor).round() as u32,
+		}
+	}
+}
+
+// A color is just four floats, nothing here knows which space they're in. Colors picked by hand (color pickers, hex codes,
+// from_srgb_u8) are sRGB encoded, anything lighting math is done on has to be linear. Alpha is always linear and never converted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub struct Color {
 	pub r: f32,
 	pub g: f32,
@@ -18,7 +71,93 @@ pub struct Color {
 	pub a: f32,
 }
 
-use glam::{Vec2, Vec3};
+impl Color {
+	pub const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+	pub const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+	pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+
+	pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+		Self { r, g, b, a }
+	}
+
+	// Stays sRGB encoded, this only maps 0-255 to 0-1.
+	pub fn from_srgb_u8(r: u8, g: u8, b: u8, a: u8) -> Self {
+		Self::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+	}
+
+	// The other way around from from_srgb_u8, anything outside of 0-1 gets clamped.
+	pub fn to_srgb_u8(self) -> [u8; 4] {
+		[self.r, self.g, self.b, self.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+	}
+
+	// #rrggbb or #rrggbbaa, the # is optional. Like from_srgb_u8 the result is still sRGB encoded.
+	pub fn from_hex(hex: &str) -> Option<Self> {
+		let hex = hex.strip_prefix('#').unwrap_or(hex);
+		if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+			return None;
+		}
+
+		let channel = |i: usize| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok();
+		let a = if hex.len() == 8 { channel(3)? } else { 255 };
+		Some(Self::from_srgb_u8(channel(0)?, channel(1)?, channel(2)?, a))
+	}
+
+	// Leaves alpha off when it's opaque, so it round trips through from_hex either way.
+	pub fn to_hex(self) -> String {
+		match self.to_srgb_u8() {
+			[r, g, b, 255] => format!("#{:02x}{:02x}{:02x}", r, g, b),
+			[r, g, b, a] => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+		}
+	}
+
+	// Hue is in degrees and wraps, saturation and value are 0-1. HSV is defined on whatever space the result gets used in, which is
+	// almost always sRGB.
+	pub fn from_hsv(hue: f32, saturation: f32, value: f32, a: f32) -> Self {
+		let hue = hue.rem_euclid(360.0) / 60.0;
+		let chroma = value * saturation;
+		let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+		let (r, g, b) = match hue as u32 {
+			0 => (chroma, x, 0.0),
+			1 => (x, chroma, 0.0),
+			2 => (0.0, chroma, x),
+			3 => (0.0, x, chroma),
+			4 => (x, 0.0, chroma),
+			_ => (chroma, 0.0, x),
+		};
+
+		let m = value - chroma;
+		Self::new(r + m, g + m, b + m, a)
+	}
+
+	// Treats this as sRGB encoded and decodes it.
+	pub fn to_linear(self) -> Self {
+		Self::new(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b), self.a)
+	}
+
+	// Treats this as linear and encodes it.
+	pub fn to_srgb(self) -> Self {
+		Self::new(linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b), self.a)
+	}
+}
+
+// The exact piecewise sRGB transfer function, the 2.2 power curve is off by enough near black to be visible.
+fn srgb_to_linear(c: f32) -> f32 {
+	if c <= 0.04045 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+	if c <= 0.0031308 {
+		c * 12.92
+	} else {
+		1.055 * c.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+use glam::{Vec2, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -35,3 +174,82 @@ pub struct Vec3Serde {
 	y: f32,
 	z: f32,
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Vec4")]
+pub struct Vec4Serde {
+	x: f32,
+	y: f32,
+	z: f32,
+	w: f32,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn transfer_functions_meet_at_the_breakpoints() {
+		let next = |c: f32| f32::from_bits(c.to_bits() + 1);
+		assert_eq!(srgb_to_linear(0.04045), 0.04045 / 12.92);
+		assert!((srgb_to_linear(next(0.04045)) - 0.04045 / 12.92).abs() < 1e-6);
+		assert_eq!(linear_to_srgb(0.0031308), 0.0031308 * 12.92);
+		assert!((linear_to_srgb(next(0.0031308)) - 0.0031308 * 12.92).abs() < 1e-6);
+
+		// Either side of a breakpoint maps to the same side of the other one.
+		assert!((linear_to_srgb(srgb_to_linear(0.04045)) - 0.04045).abs() < 1e-6);
+		assert!((srgb_to_linear(linear_to_srgb(0.0031308)) - 0.0031308).abs() < 1e-6);
+	}
+
+	#[test]
+	fn transfer_functions_hit_known_values() {
+		assert_eq!(srgb_to_linear(0.0), 0.0);
+		assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+		assert!((srgb_to_linear(0.5) - 0.214_041_14).abs() < 1e-6);
+		assert!((linear_to_srgb(0.18) - 0.461_356_1).abs() < 1e-6);
+	}
+
+	#[test]
+	fn every_u8_round_trips_through_linear() {
+		for v in 0..=255 {
+			let color = Color::from_srgb_u8(v, v, v, v);
+			assert_eq!(color.to_linear().to_srgb().to_srgb_u8(), [v; 4]);
+		}
+	}
+
+	#[test]
+	fn alpha_is_never_converted() {
+		let color = Color::new(0.5, 0.5, 0.5, 0.5);
+		assert_eq!(color.to_linear().a, 0.5);
+		assert_eq!(color.to_srgb().a, 0.5);
+	}
+
+	#[test]
+	fn hsv_primaries() {
+		assert_eq!(Color::from_hsv(0.0, 1.0, 1.0, 1.0), Color::new(1.0, 0.0, 0.0, 1.0));
+		assert_eq!(Color::from_hsv(120.0, 1.0, 1.0, 1.0), Color::new(0.0, 1.0, 0.0, 1.0));
+		assert_eq!(Color::from_hsv(240.0, 1.0, 1.0, 1.0), Color::new(0.0, 0.0, 1.0, 1.0));
+		assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0, 1.0), Color::from_hsv(240.0, 1.0, 1.0, 1.0));
+		assert_eq!(Color::from_hsv(42.0, 0.0, 1.0, 1.0), Color::WHITE);
+	}
+
+	#[test]
+	fn hex_round_trips() {
+		assert_eq!(Color::from_hex("#ff8000"), Some(Color::from_srgb_u8(255, 128, 0, 255)));
+		assert_eq!(Color::from_hex("FF800080"), Some(Color::from_srgb_u8(255, 128, 0, 128)));
+		assert_eq!(Color::from_srgb_u8(255, 128, 0, 255).to_hex(), "#ff8000");
+		assert_eq!(Color::from_srgb_u8(255, 128, 0, 128).to_hex(), "#ff800080");
+		assert_eq!(Color::TRANSPARENT.to_hex(), "#00000000");
+
+		for hex in ["#123456", "#abcdef01"] {
+			assert_eq!(Color::from_hex(hex).unwrap().to_hex(), hex);
+		}
+	}
+
+	#[test]
+	fn bad_hex_is_refused() {
+		for hex in ["", "#", "#fff", "#12345", "#1234567", "#gg0000", "#ff00ff0", "#ff00ff000", "#f\u{e9}000"] {
+			assert_eq!(Color::from_hex(hex), None, "{}", hex);
+		}
+	}
+}
@@ -1,11 +1,17 @@
-use crate::types::Size;
+use crate::input::AtomicInputState;
+use crate::types::{LogicalSize, PhysicalSize};
 use glam::DVec2;
 use raw_window_handle::HasRawDisplayHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use winit::{
-	event::{Event, WindowEvent},
+	event::{Event, Ime, MouseButton, VirtualKeyCode, WindowEvent},
 	event_loop::{ControlFlow, EventLoop},
 	platform::run_return::EventLoopExtRunReturn,
+	window::{CursorGrabMode, WindowId},
 };
 
 pub struct Window {
@@ -16,14 +22,108 @@ pub struct Window {
 
 pub type WindowRunContext = EventLoop<()>;
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WindowDesc {
+	// The platform picks a size when this isn't set.
+	pub size: Option<PhysicalSize>,
+	pub resizable: bool,
+	pub maximized: bool,
+}
+
+// How the cursor ended up being held in place, not every platform supports every kind of grab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorLock {
+	Unlocked,
+	// The platform keeps the cursor in place and only reports deltas (macOS, Wayland).
+	Locked,
+	// The cursor is only kept inside the window (Windows, X11), it still gets raw deltas but is recentered so it never sits on an edge.
+	Confined,
+	// No grab worked at all, the cursor is warped back to the center every frame and the deltas come from how far it got.
+	Emulated,
+}
+
+// Everything about the windows that isn't input state, in the order it happened. See GoldfishEngine::drain_events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+	// Text as typed, after the keyboard layout and IME. Control characters are left out, use the keys for those.
+	TextInput(char),
+	FileDropped(PathBuf),
+	FocusChanged(bool),
+	// True once nothing of the window can be seen anymore (minimized, or covered by other windows). Only reported on some platforms.
+	OcclusionChanged(bool),
+	CursorEntered,
+	CursorLeft,
+	// The engine exits at the end of the frame unless GoldfishEngine::cancel_exit is called.
+	CloseRequested,
+	// For windows made with Window::new_secondary, whoever owns the window's GraphicsContext has to pass the size on to its on_resize.
+	SecondaryResized { window: WindowId, size: PhysicalSize },
+	// Nothing happens on its own, destroy the window's GraphicsContext and drop the window to close it.
+	SecondaryCloseRequested(WindowId),
+}
+
+// What the engine does while the main window doesn't have focus or can't be seen.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum BackgroundBehavior {
+	RenderNormally,
+	// Updates (and renders) at most this many times a second, sleeping in between.
+	ThrottleTo(u32),
+	// Keeps updating at a low rate but nothing is rendered, see GoldfishEngine::should_render.
+	SkipRendering,
+}
+
+impl Default for BackgroundBehavior {
+	fn default() -> Self {
+		Self::ThrottleTo(10)
+	}
+}
+
+// What the event loop should do once an update is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateControl {
+	Continue,
+	// Keeps pumping events without updating until then, unless the main window comes back to the foreground first.
+	WaitUntil(Instant),
+	Exit,
+}
+
+impl Default for WindowDesc {
+	fn default() -> Self {
+		Self {
+			size: None,
+			resizable: true,
+			maximized: false,
+		}
+	}
+}
+
 impl Window {
-	pub fn new(name: &'static str) -> Result<Self, winit::error::OsError> {
-		let window_builder = winit::window::WindowBuilder::new().with_title(name);
+	pub fn new(name: &'static str, desc: &WindowDesc) -> Result<Self, winit::error::OsError> {
+		let mut window_builder = winit::window::WindowBuilder::new().with_title(name).with_resizable(desc.resizable).with_maximized(desc.maximized);
+
+		if let Some(size) = desc.size {
+			window_builder = window_builder.with_inner_size(winit::dpi::PhysicalSize::new(size.width, size.height));
+		}
 
 		let event_loop = EventLoop::new();
 		let winit_window = window_builder.build(&event_loop)?;
 		winit_window.raw_display_handle();
 
+		// Without this committed IME text never shows up, and some layouts (dead keys, CJK) can't be typed at all.
+		winit_window.set_ime_allowed(true);
+
+		Ok(Self {
+			name,
+			winit_window,
+			event_loop: Some(event_loop),
+		})
+	}
+
+	// Never shown, for running the renderer without anything on screen. The device still needs a window to pick a GPU that can present.
+	pub fn new_hidden(name: &'static str) -> Result<Self, winit::error::OsError> {
+		let event_loop = EventLoop::new();
+		let winit_window = winit::window::WindowBuilder::new().with_title(name).with_visible(false).build(&event_loop)?;
+
 		Ok(Self {
 			name,
 			winit_window,
@@ -31,47 +131,195 @@ impl Window {
 		})
 	}
 
-	pub fn get_dpi(&self) -> f64 {
+	// winit only allows a single event loop, so any other window has to be built from the main window's loop before it starts running.
+	pub fn new_secondary(&self, name: &'static str) -> Result<Self, winit::error::OsError> {
+		let event_loop = self.event_loop.as_ref().expect("Cannot create a secondary window once the event loop is running!");
+		let winit_window = winit::window::WindowBuilder::new().with_title(name).build(event_loop)?;
+
+		Ok(Self { name, winit_window, event_loop: None })
+	}
+
+	// The window that the Secondary events refer to.
+	pub fn id(&self) -> WindowId {
+		self.winit_window.id()
+	}
+
+	// How many physical pixels make up a logical one, changes when the window moves to a monitor with a different scale.
+	pub fn scale_factor(&self) -> f64 {
 		self.winit_window.scale_factor()
 	}
 
-	pub fn get_size(&self) -> Size {
+	// The size of the swapchain, use logical_size to lay out UI.
+	pub fn get_size(&self) -> PhysicalSize {
 		let size = self.winit_window.inner_size();
 
-		Size {
+		PhysicalSize {
 			width: size.width,
 			height: size.height,
 		}
 	}
 
+	// Only a request, the platform can pick another size or apply it later.
+	pub fn set_size(&self, size: PhysicalSize) {
+		self.winit_window.set_inner_size(winit::dpi::PhysicalSize::new(size.width, size.height));
+	}
+
+	pub fn logical_size(&self) -> LogicalSize {
+		self.get_size().to_logical(self.scale_factor())
+	}
+
+	// Physical pixels from the top left of the window, same as the cursor position.
+	pub fn get_center(&self) -> DVec2 {
+		let size = self.winit_window.inner_size();
+
+		DVec2 {
+			x: size.width as f64 / 2.0,
+			y: size.height as f64 / 2.0,
+		}
+	}
+
+	// Tries the grabs from best to worst and returns whichever one the platform accepted.
+	pub fn grab_cursor(&self) -> CursorLock {
+		if self.winit_window.set_cursor_grab(CursorGrabMode::Locked).is_ok() {
+			CursorLock::Locked
+		} else if self.winit_window.set_cursor_grab(CursorGrabMode::Confined).is_ok() {
+			CursorLock::Confined
+		} else {
+			CursorLock::Emulated
+		}
+	}
+
+	pub fn release_cursor(&self) -> Result<(), winit::error::ExternalError> {
+		self.winit_window.set_cursor_grab(CursorGrabMode::None)
+	}
+
+	pub fn center_cursor(&self) -> Result<(), winit::error::ExternalError> {
+		let center = self.get_center();
+		self.winit_window.set_cursor_position(winit::dpi::PhysicalPosition::new(center.x, center.y))
+	}
+
 	pub fn get_run_context(&mut self) -> WindowRunContext {
 		self.event_loop.take().expect("Cannot get call get_run_context more than once!")
 	}
 
-	pub fn run<F>(mut context: WindowRunContext, mut update_fn: F)
+	// The event loop keeps running until the update returns UpdateControl::Exit.
+	pub fn run<F>(mut context: WindowRunContext, main_window: winit::window::WindowId, input_state: Arc<AtomicInputState>, mut update_fn: F)
 	where
-		F: FnMut(Duration, &[bool; 255], DVec2, Option<Size>) -> (),
+		F: FnMut(Duration, &HashSet<VirtualKeyCode>, &HashSet<MouseButton>, DVec2, DVec2, Option<PhysicalSize>, Vec<EngineEvent>) -> UpdateControl,
 	{
 		let mut last_time = Instant::now();
-		let mut new_size: Option<Size> = None;
-		let mut keys = [false; 255];
+		let mut wait_until: Option<Instant> = None;
+		let mut new_size: Option<PhysicalSize> = None;
+		let mut events = Vec::new();
+		// Some platforms send committed IME text as ReceivedCharacter too, skip it so it isn't typed twice.
+		let mut committed = VecDeque::new();
+		let mut keys = HashSet::new();
+		let mut mouse_buttons = HashSet::new();
 		let mut mouse_delta = Default::default();
+		let mut cursor_position = Default::default();
 
 		context.run_return(|event, _, control_flow| {
-			*control_flow = ControlFlow::Poll;
+			// Every event comes through here, so whatever the last update asked for has to be set again each time.
+			*control_flow = match wait_until {
+				Some(deadline) => ControlFlow::WaitUntil(deadline),
+				None => ControlFlow::Poll,
+			};
 
 			match event {
 				Event::WindowEvent {
-					event: WindowEvent::CloseRequested, ..
-				} => *control_flow = ControlFlow::Exit,
+					event: WindowEvent::CloseRequested,
+					window_id,
+				} if window_id == main_window => events.push(EngineEvent::CloseRequested),
 				Event::WindowEvent {
-					event: WindowEvent::Resized(size), ..
-				} => {
-					new_size = Some(Size {
+					event: WindowEvent::CloseRequested,
+					window_id,
+				} => events.push(EngineEvent::SecondaryCloseRequested(window_id)),
+				Event::WindowEvent {
+					event: WindowEvent::Resized(size),
+					window_id,
+				} if window_id == main_window => {
+					new_size = Some(PhysicalSize {
 						width: size.width,
 						height: size.height,
 					})
 				}
+				// A scale change resizes without a Resized event, so the swapchain has to be recreated here too.
+				Event::WindowEvent {
+					event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
+					window_id,
+				} if window_id == main_window => {
+					new_size = Some(PhysicalSize {
+						width: new_inner_size.width,
+						height: new_inner_size.height,
+					})
+				}
+				Event::WindowEvent {
+					event: WindowEvent::Resized(size),
+					window_id,
+				} => events.push(EngineEvent::SecondaryResized {
+					window: window_id,
+					size: PhysicalSize {
+						width: size.width,
+						height: size.height,
+					},
+				}),
+				Event::WindowEvent {
+					event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
+					window_id,
+				} => events.push(EngineEvent::SecondaryResized {
+					window: window_id,
+					size: PhysicalSize {
+						width: new_inner_size.width,
+						height: new_inner_size.height,
+					},
+				}),
+				Event::WindowEvent {
+					event: WindowEvent::Focused(window_focused),
+					window_id,
+				} if window_id == main_window => {
+					if window_focused {
+						wait_until = None;
+					}
+					events.push(EngineEvent::FocusChanged(window_focused));
+				}
+				Event::WindowEvent {
+					event: WindowEvent::Occluded(occluded),
+					window_id,
+				} if window_id == main_window => {
+					if !occluded {
+						wait_until = None;
+					}
+					events.push(EngineEvent::OcclusionChanged(occluded));
+				}
+				Event::WindowEvent {
+					event: WindowEvent::ReceivedCharacter(c),
+					window_id,
+				} if window_id == main_window => {
+					if committed.front() == Some(&c) {
+						committed.pop_front();
+					} else if !c.is_control() {
+						events.push(EngineEvent::TextInput(c));
+					}
+				}
+				Event::WindowEvent {
+					event: WindowEvent::Ime(Ime::Commit(text)),
+					window_id,
+				} if window_id == main_window => {
+					committed.extend(text.chars());
+					events.extend(text.chars().filter(|c| !c.is_control()).map(EngineEvent::TextInput));
+				}
+				Event::WindowEvent {
+					event: WindowEvent::DroppedFile(path),
+					window_id,
+				} if window_id == main_window => events.push(EngineEvent::FileDropped(path)),
+				Event::WindowEvent {
+					event: WindowEvent::CursorEntered { .. },
+					window_id,
+				} if window_id == main_window => events.push(EngineEvent::CursorEntered),
+				Event::WindowEvent {
+					event: WindowEvent::CursorLeft { .. },
+					window_id,
+				} if window_id == main_window => events.push(EngineEvent::CursorLeft),
 				Event::WindowEvent {
 					event: WindowEvent::KeyboardInput {
 						input: winit::event::KeyboardInput {
@@ -83,22 +331,51 @@ impl Window {
 					},
 					..
 				} => {
-					keys[keycode as usize] = match state {
-						winit::event::ElementState::Pressed => true,
-						winit::event::ElementState::Released => false,
-					}
+					match state {
+						winit::event::ElementState::Pressed => keys.insert(keycode),
+						winit::event::ElementState::Released => keys.remove(&keycode),
+					};
 				}
+				Event::WindowEvent {
+					event: WindowEvent::MouseInput { button, state, .. },
+					window_id,
+				} if window_id == main_window => {
+					match state {
+						winit::event::ElementState::Pressed => mouse_buttons.insert(button),
+						winit::event::ElementState::Released => mouse_buttons.remove(&button),
+					};
+				}
+				Event::WindowEvent {
+					event: WindowEvent::CursorMoved { position, .. },
+					window_id,
+				} if window_id == main_window => cursor_position = DVec2 { x: position.x, y: position.y },
 				Event::DeviceEvent {
 					event: winit::event::DeviceEvent::MouseMotion { delta: (dx, dy) },
 					..
-				} => mouse_delta = DVec2 { x: dx, y: dy },
+				} => {
+					mouse_delta += DVec2 { x: dx, y: dy };
+					input_state.add_mouse_delta(DVec2 { x: dx, y: dy });
+				}
 				Event::MainEventsCleared => {
 					let now = Instant::now();
+					if wait_until.map_or(false, |deadline| deadline > now) {
+						return;
+					}
+
 					let dt = now - last_time;
 					last_time = now;
 
-					update_fn(dt, &keys, mouse_delta, new_size);
+					wait_until = None;
+					match update_fn(dt, &keys, &mouse_buttons, mouse_delta, cursor_position, new_size, std::mem::take(&mut events)) {
+						UpdateControl::Continue => {}
+						UpdateControl::WaitUntil(deadline) => {
+							wait_until = Some(deadline);
+							*control_flow = ControlFlow::WaitUntil(deadline);
+						}
+						UpdateControl::Exit => *control_flow = ControlFlow::Exit,
+					}
 					new_size = None;
+					committed.clear();
 					mouse_delta = Default::default();
 				}
 				_ => (),
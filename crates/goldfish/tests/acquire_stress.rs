@@ -0,0 +1,66 @@
+use goldfish::config::GoldfishConfig;
+use goldfish::renderer::{ClearValue, GraphicsDevice};
+use goldfish::types::PhysicalSize;
+use goldfish::window::{Window, WindowDesc};
+
+const FRAMES: u32 = 300;
+
+fn main() {
+	if GraphicsDevice::enumerate_adapters().is_empty() {
+		println!("No GPU, skipping the acquire stress test");
+		return;
+	}
+
+	let Ok(window) = Window::new("Goldfish Acquire Stress Test", &WindowDesc::default()) else {
+		println!("No display, skipping the acquire stress test");
+		return;
+	};
+
+	let config = GoldfishConfig::default();
+	let validation = config.validation_desc();
+	assert!(validation.is_some(), "The acquire stress test needs validation enabled");
+
+	let (mut device, mut context) = GraphicsDevice::new_with_context(
+		&window,
+		config.frames_in_flight,
+		validation.as_ref(),
+		config.device_selection(),
+		false,
+		config.surface_preference,
+		config.present_mode,
+	);
+
+	let mut failed_acquires = 0;
+	let mut discarded = 0;
+	for i in 0..FRAMES {
+		// The context never hears about these, so the next acquire finds the swapchain out of date (or suboptimal).
+		if i % 3 == 0 {
+			window.set_size(PhysicalSize {
+				width: 320 + (i % 7) * 16,
+				height: 240 + (i % 5) * 16,
+			});
+		}
+
+		match context.begin_frame(&window) {
+			Ok(mut frame) => {
+				frame.begin_output_render_pass(&[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+				frame.end_render_pass();
+
+				// An acquired frame that gets abandoned still has to leave the acquire semaphore reusable.
+				if i % 10 == 0 {
+					frame.discard();
+					discarded += 1;
+				}
+			}
+			Err(_) => failed_acquires += 1,
+		}
+
+		assert!(!device.take_validation_error(), "Frame {} raised a validation error", i);
+	}
+
+	device.wait_idle();
+	context.destroy();
+	device.destroy();
+
+	println!("{} frames, {} failed acquires and {} discarded frames without a validation error", FRAMES, failed_acquires, discarded);
+}
@@ -0,0 +1,215 @@
+use goldfish::config::GoldfishConfig;
+use goldfish::renderer::{
+	AttachmentDesc, AttachmentSize, BlendMode, ClearValue, DescriptorBindingDesc, DescriptorBindingType, DescriptorDesc, DescriptorSetInfo, FaceCullMode, GraphicsDevice, LoadOp, PolygonMode,
+	RasterPipelineDesc, ReadbackSource, RenderGraph, RenderGraphCache, RenderPassDesc, StoreOp, TextureFormat, TextureUsage, EMPTY_VERTEX_INFO, PS_MAIN, VS_MAIN,
+};
+use goldfish::window::Window;
+use naga::{
+	back::spv,
+	front::wgsl,
+	valid::{Capabilities, ValidationFlags, Validator},
+	ShaderStage,
+};
+
+const SAMPLED_ATTACHMENT: &str = "BC1 Sampled";
+
+// Same as the editor's replay --golden, drivers don't all round the same way.
+const TOLERANCE: u8 = 2;
+
+// A single 4x4 BC1 block. The endpoints are pure red and pure blue in RGB565, and the indices pick red for the even rows and blue for the
+// odd ones.
+const BC1_BLOCK: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0x00, 0x55, 0x00, 0x55];
+const RED: [u8; 4] = [255, 0, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+// Draws the texture over a 4x4 attachment, sampling at each pixel's center so every pixel lands on exactly one texel.
+const SAMPLE_SHADER_WGSL: &str = r#"
+@group(0) @binding(0) var t_source: texture_2d<f32>;
+@group(0) @binding(1) var s_source: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn ps_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+	return textureSample(t_source, s_source, position.xy / 4.0);
+}
+"#;
+
+static SAMPLE_DESCRIPTOR_SET_INFO: DescriptorSetInfo = DescriptorSetInfo {
+	bindings: phf::phf_map! {
+		0u32 => DescriptorBindingType::Texture2D,
+		1u32 => DescriptorBindingType::SamplerState,
+	},
+};
+
+fn compile_wgsl(stage: ShaderStage, entry_point: &str) -> Vec<u32> {
+	let module = wgsl::parse_str(SAMPLE_SHADER_WGSL).unwrap_or_else(|err| panic!("{}", err.emit_to_string(SAMPLE_SHADER_WGSL)));
+	let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+		.validate(&module)
+		.expect("The sample shader doesn't validate!");
+
+	let options = spv::Options {
+		flags: spv::WriterFlags::empty(),
+		..Default::default()
+	};
+	let pipeline_options = spv::PipelineOptions {
+		shader_stage: stage,
+		entry_point: entry_point.to_owned(),
+	};
+
+	spv::write_vec(&module, &info, &options, Some(&pipeline_options)).expect("Failed to write the sample shader!")
+}
+
+fn main() {
+	if GraphicsDevice::enumerate_adapters().is_empty() {
+		println!("No GPU, skipping the BC upload test");
+		return;
+	}
+
+	let window = Window::new_hidden("Goldfish BC Upload Test").expect("Failed to create a window to render in!");
+	let config = GoldfishConfig::default();
+	let (mut device, mut context) = GraphicsDevice::new_with_context(
+		&window,
+		config.frames_in_flight,
+		config.validation_desc().as_ref(),
+		config.device_selection(),
+		false,
+		config.surface_preference,
+		config.present_mode,
+	);
+
+	let mut upload_context = device.create_upload_context();
+	let default_resources = upload_context.create_default_resources().expect("Failed to create default resources!");
+	let texture = upload_context.create_texture("BC1 Source", 4, 4, TextureFormat::BC1UNorm, TextureUsage::SAMPLED, Some(&BC1_BLOCK));
+	device.destroy_upload_context(upload_context);
+
+	let texture = match texture {
+		Ok(texture) => texture,
+		Err(err) => {
+			println!("Couldn't create a BC1 texture, skipping the BC upload test: {}", err);
+			device.destroy_default_resources(default_resources);
+			context.destroy();
+			device.destroy();
+			return;
+		}
+	};
+
+	let vs = device
+		.create_shader_with_code(&compile_wgsl(ShaderStage::Vertex, VS_MAIN))
+		.expect("Failed to create the vertex shader!");
+	let ps = device
+		.create_shader_with_code(&compile_wgsl(ShaderStage::Fragment, PS_MAIN))
+		.expect("Failed to create the pixel shader!");
+
+	let mut cache = RenderGraphCache::default();
+	let readback = {
+		let mut frame = context.begin_frame(&window).expect("Failed to begin a frame!");
+		let mut graph = RenderGraph::new(&mut cache, &default_resources);
+
+		{
+			let mut pass = graph.add_pass("sample");
+
+			let mut sampled = pass.add_attachment(AttachmentDesc {
+				name: SAMPLED_ATTACHMENT,
+				format: TextureFormat::RGBA8UNorm,
+				size: AttachmentSize::Absolute(4, 4),
+				load_op: LoadOp::Clear,
+				store_op: StoreOp::Store,
+				usage: TextureUsage::ATTACHMENT | TextureUsage::TRANSFER_SRC,
+			});
+
+			let render_pass = pass.add_render_pass(RenderPassDesc {
+				name: "Sample render pass",
+				color_attachments: &mut [&mut sampled],
+				depth_attachment: None,
+			});
+
+			let pipeline = pass.add_raster_pipeline(RasterPipelineDesc {
+				name: "Sample Pipeline",
+				vs: &vs,
+				ps: Some(&ps),
+				gs: None,
+				descriptor_layouts: &[&SAMPLE_DESCRIPTOR_SET_INFO],
+				render_pass,
+				depth_compare_op: None,
+				depth_write: false,
+				depth_bias: None,
+				blend_mode: BlendMode::Opaque,
+				face_cull: FaceCullMode::NoCull,
+				push_constant_bytes: 0,
+				vertex_input_info: EMPTY_VERTEX_INFO,
+				polygon_mode: PolygonMode::Fill,
+				specialization: &[],
+			});
+
+			let descriptor = pass.add_graphics_descriptor_set(DescriptorDesc {
+				name: "Sample Descriptor",
+				descriptor_layout: &SAMPLE_DESCRIPTOR_SET_INFO,
+				bindings: &mut [(0, DescriptorBindingDesc::ImportedTexture(&texture)), (1, DescriptorBindingDesc::ImportedTexture(&texture))],
+			});
+
+			pass.cmd_begin_render_pass(render_pass, &[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 }]);
+			pass.cmd_bind_raster_pipeline(pipeline);
+			pass.cmd_bind_graphics_descriptor(descriptor, 0, pipeline);
+			pass.cmd_draw(3, 1, 0, 0);
+			pass.cmd_end_render_pass();
+
+			drop(pass);
+			graph.export_attachment(sampled);
+		}
+
+		// The graph has to write to the output for anything in it to run.
+		{
+			let mut pass = graph.add_pass("output");
+			let render_pass = pass.add_output_render_pass();
+			pass.cmd_begin_render_pass(render_pass, &[ClearValue::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }]);
+			pass.cmd_end_render_pass();
+		}
+
+		frame.execute_graph(graph, &mut device).expect("Failed to execute the render graph!");
+
+		let sampled = cache.exported_attachment(SAMPLED_ATTACHMENT).expect("The sampled attachment wasn't exported!");
+		frame
+			.request_readback(ReadbackSource::Texture {
+				texture: sampled,
+				x: 0,
+				y: 0,
+				width: 4,
+				height: 4,
+			})
+			.expect("Failed to read back the sampled attachment!")
+	};
+
+	device.wait_idle();
+	let pixels = context.poll_readback(readback).expect("The readback didn't finish after waiting for the GPU!");
+	let validation_error = device.take_validation_error();
+
+	cache.destroy(&mut device);
+	device.destroy_shader(vs);
+	device.destroy_shader(ps);
+	device.destroy_texture(texture);
+	device.destroy_default_resources(default_resources);
+	context.destroy();
+	device.destroy();
+
+	assert!(!validation_error, "The BC upload raised a validation error");
+	assert_eq!(pixels.len(), 4 * 4 * 4);
+	for (y, row) in pixels.chunks_exact(4 * 4).enumerate() {
+		let expected = if y % 2 == 0 { RED } else { BLUE };
+		for (x, pixel) in row.chunks_exact(4).enumerate() {
+			assert!(
+				pixel.iter().zip(expected.iter()).all(|(a, e)| a.abs_diff(*e) <= TOLERANCE),
+				"Pixel ({}, {}) sampled as {:?} instead of {:?}",
+				x,
+				y,
+				pixel,
+				expected
+			);
+		}
+	}
+	println!("The BC1 texture samples back as uploaded");
+}
@@ -0,0 +1,6 @@
+#include "b.hlsli"
+
+float4 ps_main() : SV_TARGET
+{
+    return shade();
+}
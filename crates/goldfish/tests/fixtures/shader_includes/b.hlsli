@@ -0,0 +1,10 @@
+#ifndef B
+#define B
+#include "c.hlsli"
+
+float4 shade()
+{
+    return base_color() * 0.5;
+}
+
+#endif
@@ -0,0 +1,9 @@
+#ifndef C
+#define C
+
+float4 base_color()
+{
+    return float4(1.0, 0.0, 1.0, 1.0);
+}
+
+#endif
@@ -0,0 +1,33 @@
+use goldfish::config::GoldfishConfig;
+use goldfish::renderer::{FrameCapture, GraphicsDevice};
+use goldfish::window::Window;
+use std::path::Path;
+
+// Same as the editor's replay --golden, drivers don't all round the same way.
+const TOLERANCE: u8 = 2;
+
+fn main() {
+	if GraphicsDevice::enumerate_adapters().is_empty() {
+		println!("No GPU, skipping the replay test");
+		return;
+	}
+
+	let captures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/captures");
+	let capture = FrameCapture::load(&captures.join("blit.capture")).unwrap_or_else(|err| panic!("Failed to load the capture: {}", err));
+	let golden = image::open(captures.join("blit.png")).expect("Failed to read the golden image!").into_rgba8();
+
+	let window = Window::new_hidden("Goldfish Replay Test").expect("Failed to create a window to replay in!");
+	let config = GoldfishConfig::default();
+	let mut device = GraphicsDevice::new(&window, config.frames_in_flight, config.validation_desc().as_ref(), config.device_selection(), false);
+	let result = device.replay_capture(&capture);
+	let validation_error = device.take_validation_error();
+	device.destroy();
+
+	let output = result.unwrap_or_else(|err| panic!("Failed to replay the capture: {}", err));
+	assert!(!validation_error, "The replay raised a validation error");
+	assert_eq!((output.width, output.height), golden.dimensions());
+
+	let mismatched = output.mismatched_pixels(golden.as_raw(), TOLERANCE);
+	assert_eq!(mismatched, 0, "{} pixels differ from the golden image", mismatched);
+	println!("The replay matches the golden image");
+}